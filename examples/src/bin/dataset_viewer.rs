@@ -1,10 +1,10 @@
 use align3d::{io::dataset::SubsetDataset, viz::rgbd_dataset_viewer::RgbdDatasetViewer};
 use clap::Parser;
-use examples::load_dataset;
+use examples::{load_dataset, CalibrationArgs};
 
 #[derive(Parser)]
 struct CommandLine {
-    // Dataset format: slamtb or ilrgbd
+    // Dataset format: ilrgbd, tum, slamtb, or any registered one
     format: String,
     // Dataset path
     dataset: String,
@@ -13,13 +13,17 @@ struct CommandLine {
     // Future versions will come with a better UI.
     #[clap(short, long, default_value = "15")]
     samples: usize,
+    #[command(flatten)]
+    calibration: CalibrationArgs,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // TODO: finish this example
     let args = CommandLine::parse();
 
-    let dataset = load_dataset(args.format, args.dataset).unwrap();
+    let dataset = args
+        .calibration
+        .apply(load_dataset(args.format, args.dataset).unwrap())?;
     let dataset = Box::new(SubsetDataset::new(
         dataset,
         [0, 15, 30, 45, 60, 75, 90, 120, 160, 250].into(),
@@ -0,0 +1,56 @@
+use std::{cell::RefCell, rc::Rc};
+
+use align3d::{
+    io::{read_ply, write_ply},
+    viz::{
+        controllers::GeometryEditControl,
+        geometry::{VkPointCloud, VkPointCloudNode},
+        GeoViewer,
+    },
+};
+use clap::Parser;
+
+/// Cleans up a map in the viewer, e.g., removing people or far away points.
+///
+/// Arrows and PageUp/PageDown move the brush, - and = change its size, and [ and ] its
+/// step. Space selects the points inside the brush. R at two cursor positions selects a
+/// rectangle, and P at each lasso vertex then Enter selects a lasso. Backspace clears the
+/// selection. Delete removes the selected points, C crops the map to them, and Z and Y undo and redo.
+/// Closing the window writes the edited map.
+#[derive(Parser)]
+struct Args {
+    /// The map to edit, a PLY file
+    input: String,
+    /// Output PLY file
+    output: String,
+    /// Initial brush radius, in meters
+    #[clap(long, default_value = "0.1")]
+    radius: f32,
+}
+
+fn main() {
+    let args = Args::parse();
+    let control = Rc::new(RefCell::new(GeometryEditControl::new(
+        read_ply(&args.input).unwrap(),
+        args.radius,
+    )));
+
+    let mut viewer = GeoViewer::new();
+    let node = viewer.add(&control.borrow().preview());
+    node.borrow_mut().properties_mut().transformation = control.borrow().node_transformation;
+
+    let edit = control.clone();
+    viewer.run_with_scene_callback(move |key, window_state, scene, memory_allocator| {
+        let mut edit = edit.borrow_mut();
+        if edit.key_event(key, window_state) {
+            let new_node = VkPointCloudNode::new(VkPointCloud::from_pointcloud(
+                memory_allocator,
+                &edit.preview(),
+            ));
+            new_node.borrow_mut().properties.transformation = edit.node_transformation;
+            scene.nodes[0] = new_node;
+        }
+    });
+
+    write_ply(&args.output, control.borrow().editor.geometry()).unwrap();
+}
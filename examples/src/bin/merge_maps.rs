@@ -0,0 +1,82 @@
+use std::{cell::RefCell, rc::Rc};
+
+use align3d::{
+    edit::merge_maps,
+    icp::{align_maps, MapAlignmentParams},
+    io::{read_ply, write_ply},
+    pointcloud::PointCloud,
+    transform::Transform,
+    viz::{controllers::PoseNudgeControl, node::IntoVulkanWorldSpace, GeoViewer},
+};
+use clap::Parser;
+
+/// Aligns and merges two maps of the same place, e.g., captured on different days.
+///
+/// First, roughly align the source map in the viewer: arrows and PageUp/PageDown move it,
+/// I/J/K/L/U/O rotate it, and [ and ] change the step size. Closing the window refines the
+/// alignment and writes the merged map.
+#[derive(Parser)]
+struct Args {
+    /// The reference map, a PLY file
+    target: String,
+    /// The map to align, a PLY file
+    source: String,
+    /// Output PLY file
+    output: String,
+    /// Translation step of the keyboard nudges, in meters
+    #[clap(long, default_value = "0.1")]
+    step: f32,
+    /// Skips the viewer and refines from the identity
+    #[clap(long, action)]
+    no_viewer: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+    let target = read_ply(&args.target).unwrap();
+    let source = read_ply(&args.source).unwrap();
+
+    let control = Rc::new(RefCell::new(PoseNudgeControl::new(
+        Transform::eye(),
+        args.step,
+    )));
+    if !args.no_viewer {
+        let mut viewer = GeoViewer::new();
+        let target_node = viewer.add(&PointCloud::from_geometry(target.clone()));
+        let source_node = viewer.add(&PointCloud::from_geometry(source.clone()));
+        // Both in the same convention, so the nudges move the source as in the map frame.
+        target_node.borrow_mut().properties_mut().transformation =
+            Transform::eye().into_vulkan_coordinate_system();
+        source_node.borrow_mut().properties_mut().transformation =
+            Transform::eye().into_vulkan_coordinate_system();
+
+        let nudge = control.clone();
+        viewer.run_with_key_callback(move |key, _| {
+            let mut nudge = nudge.borrow_mut();
+            if nudge.key_event(key) {
+                source_node.borrow_mut().properties_mut().transformation =
+                    nudge.transform.clone().into_vulkan_coordinate_system();
+            }
+        });
+    }
+    let initial = control.borrow().transform.clone();
+
+    println!("Refining the alignment");
+    let source_to_target = align_maps(
+        &PointCloud::from_geometry(target.clone()),
+        &PointCloud::from_geometry(source.clone()),
+        &initial,
+        &MapAlignmentParams::default(),
+    );
+    println!(
+        "Source to target: translation {:?}, angle {:.2} degrees",
+        source_to_target.translation(),
+        source_to_target.angle().to_degrees()
+    );
+
+    write_ply(
+        &args.output,
+        &merge_maps(&target, &source, &source_to_target),
+    )
+    .unwrap();
+}
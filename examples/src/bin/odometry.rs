@@ -1,20 +1,27 @@
 use align3d::{
     bilateral::BilateralFilter,
-    icp::{multiscale::MultiscaleAlign, MsIcpParams},
-    io::dataset::SubsetDataset,
+    error::A3dError,
+    io::dataset::{DatasetIter, FrameErrorPolicy, SubsetDataset},
+    live_config::{ConfigWatcher, LiveParams},
     metrics::TransformMetrics,
-    range_image::{RangeImage, RangeImageBuilder},
-    trajectory::TrajectoryBuilder,
-    viz::rgbd_dataset_viewer::RgbdDatasetViewer, transform::Transform,
+    odometry::OdometryPipeline,
+    range_image::RangeImageBuilder,
+    session::Session,
+    telemetry::{
+        resident_memory, CsvTelemetry, FrameTelemetry, PrometheusTelemetry, TelemetrySink,
+    },
+    viz::rgbd_dataset_viewer::RgbdDatasetViewer,
 };
 
+use std::time::Instant;
+
 use clap::Parser;
-use examples::load_dataset;
+use examples::{load_dataset, CalibrationArgs};
 use kdam::tqdm;
 
 #[derive(Parser)]
 struct Args {
-    /// Format of the dataset: ilrgbd, or tum
+    /// Format of the dataset: ilrgbd, tum, slamtb, or any registered one
     format: String,
     /// Path to the dataset directory
     dataset: String,
@@ -23,39 +30,110 @@ struct Args {
     /// Shows the point clouds with the predicted odometry
     #[clap(long, short, action)]
     show: bool,
+    /// JSON file with parameters to apply while running, reloaded when it changes
+    #[clap(long)]
+    live_config: Option<String>,
+    /// Exports per-frame metrics, as Prometheus text if the file ends with .prom, CSV otherwise
+    #[clap(long)]
+    telemetry: Option<String>,
+    /// Directory where the run is checkpointed every 100 frames, resuming from it if it has a checkpoint
+    #[clap(long)]
+    checkpoint: Option<String>,
+    #[command(flatten)]
+    calibration: CalibrationArgs,
 }
 
 fn main() {
     let args = Args::parse();
     let dataset = {
-        let mut dataset = load_dataset(args.format, args.dataset).unwrap();
+        let mut dataset = args
+            .calibration
+            .apply(load_dataset(args.format, args.dataset).unwrap())
+            .unwrap();
         if let Some(max_frames) = args.max_frames {
             dataset = Box::new(SubsetDataset::new(dataset, (0..max_frames).collect()));
         }
         dataset
     };
 
-    let range_processing =
-        RangeImageBuilder::default().with_bilateral_filter(Some(BilateralFilter::default()));
-
-    let icp_params = MsIcpParams::default();
+    let mut live_params = LiveParams::default();
+    let mut odometry = OdometryPipeline::builder()
+        .range_image(
+            RangeImageBuilder::default().with_bilateral_filter(Some(BilateralFilter::default())),
+        )
+        .icp(live_params.icp.clone())
+        .build()
+        .unwrap();
+    let mut config_watcher = args.live_config.map(ConfigWatcher::new);
+    let mut telemetry: Option<Box<dyn TelemetrySink>> = args.telemetry.map(|filepath| {
+        if filepath.ends_with(".prom") {
+            Box::new(PrometheusTelemetry::new(Some(filepath))) as Box<dyn TelemetrySink>
+        } else {
+            Box::new(CsvTelemetry::new(std::fs::File::create(filepath).unwrap()))
+        }
+    });
 
-    let mut trajectory_build = TrajectoryBuilder::with_start(Transform::eye(), 0.0);
-    let mut last_frame: Vec<RangeImage> = range_processing.build(dataset.get(0).unwrap());
+    let mut session = Session::new(0);
+    if let Some(dirpath) = args.checkpoint.as_ref() {
+        match Session::load(dirpath) {
+            Ok(checkpoint) => {
+                checkpoint.resume(&mut odometry).unwrap();
+                eprintln!("Resuming at frame {}", checkpoint.next_frame);
+                session = checkpoint;
+            }
+            Err(A3dError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => panic!("Can't load the checkpoint: {err}"),
+        }
+    }
 
-    for i in tqdm!(
-        1..dataset.len(),
-        total = dataset.len() - 1,
+    let mut frames =
+        DatasetIter::new(dataset.as_ref(), FrameErrorPolicy::Skip).start_at(session.next_frame);
+    for item in tqdm!(
+        frames.by_ref(),
+        total = dataset.len(),
         desc = "Processing frames"
     ) {
-        let current_frame = range_processing.build(dataset.get(i).unwrap());
-        let icp = MultiscaleAlign::new(icp_params.clone(), &last_frame).unwrap();
-        let transform = icp.align(&current_frame);
-        trajectory_build.accumulate(&transform, Some(i as f32));
-        last_frame = current_frame;
+        let (i, frame) = item.unwrap();
+        if let Some(watcher) = config_watcher.as_mut() {
+            match watcher.poll(&mut live_params) {
+                Ok(true) => {
+                    eprintln!("Reloaded the live config at frame {i}");
+                    odometry.set_icp(live_params.icp.clone()).unwrap();
+                }
+                Ok(false) => {}
+                Err(err) => eprintln!("Ignoring the live config: {err}"),
+            }
+        }
+        let timestamp = frame.metadata.timestamp;
+        let start = Instant::now();
+        odometry.process(frame).unwrap();
+        session.next_frame = i + 1;
+        if let Some(dirpath) = args.checkpoint.as_ref() {
+            if session.next_frame % 100 == 0 {
+                session.capture(&odometry);
+                session.save(dirpath).unwrap();
+            }
+        }
+        if let Some(telemetry) = telemetry.as_mut() {
+            let record = FrameTelemetry {
+                frame: i,
+                timestamp,
+                timings: [("odometry".to_string(), start.elapsed())]
+                    .into_iter()
+                    .collect(),
+                memory_bytes: resident_memory(),
+                ..Default::default()
+            };
+            if let Err(err) = telemetry.record(&record) {
+                eprintln!("Telemetry error: {err}");
+            }
+        }
+    }
+    for err in frames.skipped() {
+        eprintln!("Skipped {err}");
     }
 
-    let pred_trajectory = trajectory_build.build();
+    let (pred_trajectory, _) = odometry.into_model();
     let gt_trajectory = &dataset.trajectory().unwrap().first_frame_at_origin();
 
     let metrics = TransformMetrics::mean_trajectory_error(&pred_trajectory, gt_trajectory).unwrap();
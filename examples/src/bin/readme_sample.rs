@@ -1,11 +1,11 @@
 use align3d::{
     bilateral::BilateralFilter,
-    icp::{multiscale::MultiscaleAlign, MsIcpParams},
+    icp::MsIcpParams,
     io::dataset::{IndoorLidarDataset, RgbdDataset, SubsetDataset},
     metrics::TransformMetrics,
+    odometry::OdometryPipeline,
     range_image::RangeImageBuilder,
-    trajectory::TrajectoryBuilder,
-    viz::rgbd_dataset_viewer::RgbdDatasetViewer, transform::Transform,
+    viz::rgbd_dataset_viewer::RgbdDatasetViewer,
 };
 
 fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
@@ -14,36 +14,27 @@ fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
         Box::new(IndoorLidarDataset::load("../datasets/apartment")?),
         (0..20).collect(),
     ));
-    // Prepares range image processing
-    let range_image_build = RangeImageBuilder::default()
-        .with_bilateral_filter(Some(BilateralFilter::default()))
-        .pyramid_levels(3);
+    // Prepares range image processing and the multiscale ICP of the frame-to-frame odometry
+    let mut odometry = OdometryPipeline::builder()
+        .range_image(
+            RangeImageBuilder::default().with_bilateral_filter(Some(BilateralFilter::default())),
+        )
+        .icp(MsIcpParams::default())
+        .build()?;
 
-    // Initialize ICP params for MultiScale alignment.
-    let icp_params = MsIcpParams::default();
-    let mut trajectory_build = TrajectoryBuilder::with_start(Transform::eye(), 0.0);
-
-    // Frame-to-frame odometry
-    let mut prev_frame = range_image_build.build(dataset.get(0).unwrap());
-    for i in 1..dataset.len() {
-        let current_frame = range_image_build.build(dataset.get(i).unwrap());
-
-        let icp = MultiscaleAlign::new(icp_params.clone(), &prev_frame).unwrap();
-        let transform = icp.align(&current_frame);
-        trajectory_build.accumulate(&transform, Some(i as f32));
-
-        prev_frame = current_frame;
+    for i in 0..dataset.len() {
+        odometry.process(dataset.get(i)?)?;
     }
 
     // Computes the mean trajectory errors
-    let pred_trajectory = trajectory_build.build();
+    let (pred_trajectory, _) = odometry.into_model();
     let gt_trajectory = &dataset
         .trajectory()
         .expect("Dataset has no trajectory")
         .first_frame_at_origin();
     let metrics = TransformMetrics::mean_trajectory_error(&pred_trajectory, gt_trajectory)?;
     println!("Mean trajectory error: {metrics}");
-    
+
     // Shows the point clouds
     RgbdDatasetViewer::new(dataset)
         .with_trajectory(pred_trajectory)
@@ -0,0 +1,83 @@
+use std::{cell::RefCell, rc::Rc};
+
+use align3d::{
+    io::{read_ply, write_ply, Geometry},
+    pointcloud::PointCloud,
+    transform::Transform,
+    viz::{
+        controllers::ClippingPlaneControl,
+        geometry::{VkPointCloud, VkPointCloudNode},
+        node::IntoVulkanWorldSpace,
+        GeoViewer,
+    },
+};
+use clap::Parser;
+use nalgebra::Vector3;
+use ndarray::Array1;
+
+/// Inspects the interior of a reconstruction with a movable clipping plane.
+///
+/// PageUp/PageDown move the plane, I/J/K/L tilt it, and X, Y and Z align it with an axis.
+/// [ and ] change the step size, and T switches between clipping and showing a slab
+/// around the plane. Closing the window writes the shown part, if an output is given.
+#[derive(Parser)]
+struct Args {
+    /// The reconstruction, a PLY file
+    input: String,
+    /// Output PLY file for the final slice
+    output: Option<String>,
+    /// Translation step and slab thickness, in meters
+    #[clap(long, default_value = "0.05")]
+    step: f32,
+}
+
+/// Creates a point cloud for the viewer, which needs colors and normals.
+fn preview(geometry: Geometry) -> PointCloud {
+    let mut pcl = PointCloud::from_geometry(geometry);
+    if pcl.colors.is_none() {
+        pcl.colors = Some(Array1::from_elem(pcl.len(), Vector3::repeat(160)));
+    }
+    if pcl.normals.is_none() {
+        pcl.normals = Some(Array1::zeros(pcl.len()));
+    }
+    pcl
+}
+
+fn main() {
+    let args = Args::parse();
+    let geometry = read_ply(&args.input).unwrap();
+    let centroid = geometry.points.iter().sum::<Vector3<f32>>() / geometry.len_vertices() as f32;
+    let control = Rc::new(RefCell::new(ClippingPlaneControl::new(
+        centroid,
+        Vector3::z(),
+        args.step,
+    )));
+
+    let mut viewer = GeoViewer::new();
+    let node = viewer.add(&preview(control.borrow().apply(&geometry)));
+    node.borrow_mut().properties_mut().transformation =
+        Transform::eye().into_vulkan_coordinate_system();
+
+    let clipping = control.clone();
+    let shown = geometry.clone();
+    viewer.run_with_scene_callback(move |key, _, scene, memory_allocator| {
+        let mut clipping = clipping.borrow_mut();
+        if !clipping.key_event(key) {
+            return;
+        }
+        let slice = clipping.apply(&shown);
+        if slice.len_vertices() == 0 {
+            scene.nodes[0].borrow_mut().properties_mut().set_visible(false);
+            return;
+        }
+        let new_node =
+            VkPointCloudNode::new(VkPointCloud::from_pointcloud(memory_allocator, &preview(slice)));
+        new_node.borrow_mut().properties.transformation =
+            Transform::eye().into_vulkan_coordinate_system();
+        scene.nodes[0] = new_node;
+    });
+
+    if let Some(output) = args.output {
+        write_ply(output, &control.borrow().apply(&geometry)).unwrap();
+    }
+}
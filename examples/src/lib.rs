@@ -1,14 +1,61 @@
 use align3d::{
+    camera::{CameraIntrinsics, Distortion},
     error::A3dError,
-    io::dataset::{IndoorLidarDataset, RgbdDataset, TumRgbdDataset},
+    io::dataset::{DatasetRegistry, RectifiedDataset, RgbdDataset},
 };
+use clap::Args;
 
+/// Opens a dataset by the name of its format, from the formats in
+/// [`DatasetRegistry::global`]. Register other formats into it before calling the tools.
 pub fn load_dataset(format: String, path: String) -> Result<Box<dyn RgbdDataset + Send>, A3dError> {
-    match format.as_str() {
-        "ilrgbd" => Ok(Box::new(IndoorLidarDataset::load(&path).unwrap())),
-        "tum" => Ok(Box::new(TumRgbdDataset::load(&path).unwrap())),
-        _ => Err(A3dError::invalid_parameter(format!(
-            "Invalid dataset format: {format}"
-        ))),
+    DatasetRegistry::global()
+        .read()
+        .unwrap()
+        .load(&format, &path)
+        .map_err(|err| A3dError::invalid_parameter(err.to_string()))
+}
+
+/// Command line options to fix the calibration of a dataset.
+#[derive(Args, Debug, Default)]
+pub struct CalibrationArgs {
+    /// Overrides the dataset intrinsics, as fx,fy,cx,cy
+    #[clap(long, value_delimiter = ',', num_args = 4)]
+    pub intrinsics: Option<Vec<f64>>,
+    /// Rectifies the images with distortion coefficients, as k1,k2,p1,p2,k3
+    #[clap(long, value_delimiter = ',', num_args = 5)]
+    pub distortion: Option<Vec<f64>>,
+}
+
+impl CalibrationArgs {
+    /// Wraps the dataset to apply the calibration options, if any is given.
+    pub fn apply(
+        &self,
+        dataset: Box<dyn RgbdDataset + Send>,
+    ) -> Result<Box<dyn RgbdDataset + Send>, A3dError> {
+        if self.intrinsics.is_none() && self.distortion.is_none() {
+            return Ok(dataset);
+        }
+        if dataset.is_empty() {
+            return Err(A3dError::invalid_parameter("The dataset is empty"));
+        }
+
+        let intrinsics = self.intrinsics.as_ref().map(|values| {
+            let (camera, _) = dataset.camera(0);
+            CameraIntrinsics::from_simple_intrinsic(
+                values[0],
+                values[1],
+                values[2],
+                values[3],
+                camera.width,
+                camera.height,
+            )
+        });
+        let distortion = self
+            .distortion
+            .as_ref()
+            .map(|values| Distortion::new([values[0], values[1], values[2], values[3], values[4]]));
+        Ok(Box::new(RectifiedDataset::new(
+            dataset, intrinsics, distortion,
+        )))
     }
 }
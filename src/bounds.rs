@@ -0,0 +1,523 @@
+//! Bounding volumes used to crop and cull geometry.
+
+use nalgebra::{Matrix3, Vector3};
+use ndarray::ArrayView1;
+
+use crate::{
+    camera::PinholeCamera,
+    slicing::Plane,
+    transform::{Transform, Transformable},
+};
+
+/// An axis-aligned bounding box, made of the points between `min` and `max`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb3Df {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb3Df {
+    /// Creates the box between two corners.
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// A box without any point, the identity of [`Aabb3Df::union`].
+    pub fn empty() -> Self {
+        Self {
+            min: Vector3::repeat(f32::INFINITY),
+            max: Vector3::repeat(f32::NEG_INFINITY),
+        }
+    }
+
+    /// The smallest box containing the points, empty if there are none.
+    pub fn from_points(points: &ArrayView1<Vector3<f32>>) -> Self {
+        Self::from_point_iter(points.iter().copied())
+    }
+
+    /// The smallest box containing the points, empty if there are none.
+    pub fn from_point_iter<I>(point_iter: I) -> Self
+    where
+        I: IntoIterator<Item = Vector3<f32>>,
+    {
+        point_iter
+            .into_iter()
+            .fold(Self::empty(), |aabb, point| Self {
+                min: aabb.min.inf(&point),
+                max: aabb.max.sup(&point),
+            })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|axis| self.min[axis] > self.max[axis])
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Size of the box along each axis.
+    pub fn extents(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    /// The 8 corners of the box.
+    pub fn corners(&self) -> [Vector3<f32>; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+            Vector3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        })
+    }
+
+    /// Whether the point is inside the box or on its boundary.
+    pub fn contains(&self, point: &Vector3<f32>) -> bool {
+        (0..3).all(|axis| self.min[axis] <= point[axis] && point[axis] <= self.max[axis])
+    }
+
+    /// The smallest box containing both boxes.
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    /// Whether both boxes share at least a point.
+    pub fn intersects(&self, other: &Self) -> bool {
+        (0..3).all(|axis| self.min[axis] <= other.max[axis] && other.min[axis] <= self.max[axis])
+    }
+
+    /// The points shared by both boxes, empty if they don't intersect.
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.sup(&other.min),
+            max: self.max.inf(&other.max),
+        }
+    }
+}
+
+impl Transformable<Aabb3Df> for Transform {
+    /// The axis-aligned box containing the transformed box, which is larger than the
+    /// box itself when rotated.
+    fn transform(&self, aabb: &Aabb3Df) -> Aabb3Df {
+        if aabb.is_empty() {
+            return *aabb;
+        }
+        let rotation = self.0.rotation.to_rotation_matrix();
+        let half_extents = rotation.matrix().abs() * aabb.extents() * 0.5;
+        let center = self.transform_vector(&aabb.center());
+        Aabb3Df::new(center - half_extents, center + half_extents)
+    }
+}
+
+/// An oriented bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Obb3Df {
+    pub center: Vector3<f32>,
+    /// Rotation from the box frame to the world, its columns are the box axes.
+    pub rotation: Matrix3<f32>,
+    /// Half of the box size along each of its axes.
+    pub half_extents: Vector3<f32>,
+}
+
+impl Obb3Df {
+    /// Creates the box from its center, the rotation of its axes and half of its size.
+    pub fn new(center: Vector3<f32>, rotation: Matrix3<f32>, half_extents: Vector3<f32>) -> Self {
+        Self {
+            center,
+            rotation,
+            half_extents,
+        }
+    }
+
+    /// A box aligned with the principal axes of the points, tighter than [`Aabb3Df`] for
+    /// elongated objects that aren't aligned with the world axes.
+    ///
+    /// # Returns
+    ///
+    /// The box, with its axes by decreasing variance of the points, or `None` if there are
+    /// no points.
+    pub fn from_points(points: &ArrayView1<Vector3<f32>>) -> Option<Self> {
+        Self::from_point_iter(points.iter().copied())
+    }
+
+    /// Same as [`Obb3Df::from_points`].
+    pub fn from_point_iter<I>(point_iter: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = Vector3<f32>>,
+    {
+        let points = point_iter.into_iter().collect::<Vec<_>>();
+        if points.is_empty() {
+            return None;
+        }
+        let centroid = points.iter().sum::<Vector3<f32>>() / points.len() as f32;
+        let covariance = points.iter().fold(Matrix3::zeros(), |sum, point| {
+            let centered = point - centroid;
+            sum + centered * centered.transpose()
+        });
+
+        let eigen = covariance.symmetric_eigen();
+        let mut order_by_variance = [0, 1, 2];
+        order_by_variance.sort_by(|a, b| eigen.eigenvalues[*b].total_cmp(&eigen.eigenvalues[*a]));
+        let mut rotation =
+            Matrix3::from_columns(&order_by_variance.map(|i| eigen.eigenvectors.column(i)));
+        if rotation.determinant() < 0.0 {
+            rotation.set_column(2, &-rotation.column(2));
+        }
+
+        let local = Aabb3Df::from_point_iter(
+            points
+                .iter()
+                .map(|point| rotation.transpose() * (point - centroid)),
+        );
+        Some(Self {
+            center: centroid + rotation * local.center(),
+            rotation,
+            half_extents: local.extents() * 0.5,
+        })
+    }
+
+    pub fn volume(&self) -> f32 {
+        8.0 * self.half_extents.product()
+    }
+
+    /// The 8 corners of the box.
+    pub fn corners(&self) -> [Vector3<f32>; 8] {
+        Aabb3Df::new(-self.half_extents, self.half_extents)
+            .corners()
+            .map(|corner| self.center + self.rotation * corner)
+    }
+
+    /// The axis-aligned box containing this box.
+    pub fn aabb(&self) -> Aabb3Df {
+        let half_extents = self.rotation.abs() * self.half_extents;
+        Aabb3Df::new(self.center - half_extents, self.center + half_extents)
+    }
+
+    /// Whether the point is inside the box or on its boundary.
+    pub fn contains(&self, point: &Vector3<f32>) -> bool {
+        let local = self.rotation.transpose() * (point - self.center);
+        (0..3).all(|axis| local[axis].abs() <= self.half_extents[axis])
+    }
+
+    /// A box containing both boxes, fitted to their corners.
+    pub fn union(&self, other: &Self) -> Self {
+        Self::from_point_iter(self.corners().into_iter().chain(other.corners()))
+            .expect("The corners aren't empty")
+    }
+
+    /// Whether both boxes share at least a point, by the separating axis test.
+    pub fn intersects(&self, other: &Self) -> bool {
+        // Epsilon against parallel edges, whose cross product is nearly null.
+        const EPSILON: f32 = 1e-6;
+        let (a, b) = (&self.half_extents, &other.half_extents);
+        // The other box's axes and center in this box's frame.
+        let rotation = self.rotation.transpose() * other.rotation;
+        let abs_rotation = rotation.abs().add_scalar(EPSILON);
+        let t = self.rotation.transpose() * (other.center - self.center);
+
+        for i in 0..3 {
+            let rb = (0..3).map(|j| b[j] * abs_rotation[(i, j)]).sum::<f32>();
+            if t[i].abs() > a[i] + rb {
+                return false;
+            }
+        }
+        for j in 0..3 {
+            let ra = (0..3).map(|i| a[i] * abs_rotation[(i, j)]).sum::<f32>();
+            let distance = (0..3).map(|i| t[i] * rotation[(i, j)]).sum::<f32>();
+            if distance.abs() > ra + b[j] {
+                return false;
+            }
+        }
+        for i in 0..3 {
+            let (i1, i2) = ((i + 1) % 3, (i + 2) % 3);
+            for j in 0..3 {
+                let (j1, j2) = ((j + 1) % 3, (j + 2) % 3);
+                let ra = a[i1] * abs_rotation[(i2, j)] + a[i2] * abs_rotation[(i1, j)];
+                let rb = b[j1] * abs_rotation[(i, j2)] + b[j2] * abs_rotation[(i, j1)];
+                let distance = t[i2] * rotation[(i1, j)] - t[i1] * rotation[(i2, j)];
+                if distance.abs() > ra + rb {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Whether the box shares at least a point with an axis-aligned box.
+    pub fn intersects_aabb(&self, aabb: &Aabb3Df) -> bool {
+        !aabb.is_empty() && self.intersects(&Obb3Df::from(*aabb))
+    }
+}
+
+impl From<Aabb3Df> for Obb3Df {
+    fn from(aabb: Aabb3Df) -> Self {
+        Self {
+            center: aabb.center(),
+            rotation: Matrix3::identity(),
+            half_extents: aabb.extents() * 0.5,
+        }
+    }
+}
+
+impl Transformable<Obb3Df> for Transform {
+    fn transform(&self, obb: &Obb3Df) -> Obb3Df {
+        Obb3Df {
+            center: self.transform_vector(&obb.center),
+            rotation: self.0.rotation.to_rotation_matrix().matrix() * obb.rotation,
+            half_extents: obb.half_extents,
+        }
+    }
+}
+
+/// The volume seen by a camera between a near and a far depth, used to cull the geometry
+/// outside of the view.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    /// The left, right, top, bottom, near and far planes, with their normals pointing
+    /// inside.
+    pub planes: [Plane; 6],
+    /// The corners on the near plane then on the far plane, in the image corners order:
+    /// top-left, top-right, bottom-right and bottom-left.
+    pub corners: [Vector3<f32>; 8],
+}
+
+impl Frustum {
+    /// Creates the frustum of a camera.
+    ///
+    /// # Arguments
+    ///
+    /// * camera - The camera, its intrinsics give the image size.
+    /// * near - Minimum depth, along the camera Z axis.
+    /// * far - Maximum depth.
+    pub fn new(camera: &PinholeCamera, near: f32, far: f32) -> Self {
+        let intrinsics = &camera.intrinsics;
+        // Image borders on the plane at depth 1.
+        let left = (-intrinsics.cx / intrinsics.fx) as f32;
+        let right = ((intrinsics.width as f64 - intrinsics.cx) / intrinsics.fx) as f32;
+        let top = (-intrinsics.cy / intrinsics.fy) as f32;
+        let bottom = ((intrinsics.height as f64 - intrinsics.cy) / intrinsics.fy) as f32;
+
+        let to_world = &camera.camera_to_world;
+        let plane = |point: Vector3<f32>, normal: Vector3<f32>| {
+            Plane::from_point_normal(
+                &to_world.transform_vector(&point),
+                &to_world.transform_normal(&normal),
+            )
+        };
+        let planes = [
+            plane(Vector3::zeros(), Vector3::new(1.0, 0.0, -left)),
+            plane(Vector3::zeros(), Vector3::new(-1.0, 0.0, right)),
+            plane(Vector3::zeros(), Vector3::new(0.0, 1.0, -top)),
+            plane(Vector3::zeros(), Vector3::new(0.0, -1.0, bottom)),
+            plane(Vector3::new(0.0, 0.0, near), Vector3::z()),
+            plane(Vector3::new(0.0, 0.0, far), -Vector3::z()),
+        ];
+        let image_corners = [(left, top), (right, top), (right, bottom), (left, bottom)];
+        let corners = [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+            let (x, y) = image_corners[i % 4];
+            let depth = if i < 4 { near } else { far };
+            to_world.transform_vector(&(Vector3::new(x, y, 1.0) * depth))
+        });
+        Self { planes, corners }
+    }
+
+    /// Whether the point is inside the frustum or on its boundary.
+    pub fn contains(&self, point: &Vector3<f32>) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    /// Whether a sphere may intersect the frustum. Spheres near its edges, outside of it but
+    /// not fully outside of one of its planes, are also reported.
+    pub fn intersects_sphere(&self, center: &Vector3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(center) >= -radius)
+    }
+
+    /// Whether a box may intersect the frustum. Like [`Frustum::intersects_sphere`], the
+    /// test is conservative: only the boxes fully outside of one of its planes are rejected.
+    pub fn intersects_aabb(&self, aabb: &Aabb3Df) -> bool {
+        !aabb.is_empty()
+            && self.planes.iter().all(|plane| {
+                // The corner furthest along the normal.
+                let corner = Vector3::from_fn(|axis, _| {
+                    if plane.normal[axis] >= 0.0 {
+                        aabb.max[axis]
+                    } else {
+                        aabb.min[axis]
+                    }
+                });
+                plane.signed_distance(&corner) >= 0.0
+            })
+    }
+
+    /// The axis-aligned box containing the frustum.
+    pub fn aabb(&self) -> Aabb3Df {
+        Aabb3Df::from_point_iter(self.corners)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix3, Unit, Vector3};
+    use ndarray::Array1;
+
+    use super::{Aabb3Df, Frustum, Obb3Df};
+    use crate::{
+        camera::{CameraIntrinsics, PinholeCamera},
+        transform::{TransformBuilder, Transformable},
+    };
+
+    #[test]
+    fn test_aabb() {
+        let points = Array1::from_vec(vec![
+            Vector3::new(1.0, -1.0, 0.0),
+            Vector3::new(2.0, 0.5, 3.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        ]);
+        let aabb = Aabb3Df::from_points(&points.view());
+        assert_eq!(aabb.min, Vector3::new(0.0, -1.0, 0.0));
+        assert_eq!(aabb.max, Vector3::new(2.0, 0.5, 3.0));
+        assert!(Aabb3Df::from_point_iter(std::iter::empty()).is_empty());
+        assert_eq!(Aabb3Df::empty().union(&aabb), aabb);
+
+        let other = Aabb3Df::new(Vector3::new(1.5, 0.0, -1.0), Vector3::new(4.0, 1.0, 1.0));
+        assert!(aabb.intersects(&other));
+        assert_eq!(
+            aabb.intersection(&other),
+            Aabb3Df::new(Vector3::new(1.5, 0.0, 0.0), Vector3::new(2.0, 0.5, 1.0))
+        );
+        assert_eq!(
+            aabb.union(&other),
+            Aabb3Df::new(Vector3::new(0.0, -1.0, -1.0), Vector3::new(4.0, 1.0, 3.0))
+        );
+        let far = Aabb3Df::new(Vector3::repeat(5.0), Vector3::repeat(6.0));
+        assert!(!aabb.intersects(&far));
+        assert!(aabb.intersection(&far).is_empty());
+
+        let transform = TransformBuilder::default()
+            .axis_angle(Vector3::z_axis(), std::f32::consts::FRAC_PI_4)
+            .translation(Vector3::new(1.0, 2.0, 3.0))
+            .build();
+        let transformed = transform.transform(&aabb);
+        for corner in aabb.corners() {
+            let corner = transform.transform_vector(&corner);
+            assert!(transformed.contains(&(corner * 0.9999 + transformed.center() * 0.0001)));
+        }
+    }
+
+    #[test]
+    fn test_obb() {
+        // A 4 x 1 x 0.5 box rotated around Z and Y.
+        let transform = TransformBuilder::default()
+            .axis_angle(Unit::new_normalize(Vector3::new(1.0, 2.0, 0.5)), 0.7)
+            .translation(Vector3::new(-1.0, 2.0, 0.5))
+            .build();
+        let points = (0..=40)
+            .flat_map(|i| (0..=10).flat_map(move |j| (0..=5).map(move |k| (i, j, k))))
+            .map(|(i, j, k)| {
+                transform.transform_vector(&Vector3::new(
+                    i as f32 * 0.1 - 2.0,
+                    j as f32 * 0.1 - 0.5,
+                    k as f32 * 0.1 - 0.25,
+                ))
+            })
+            .collect::<Array1<_>>();
+
+        let obb = Obb3Df::from_points(&points.view()).unwrap();
+        assert!((obb.half_extents - Vector3::new(2.0, 0.5, 0.25)).norm() < 1e-3);
+        assert!((obb.center - Vector3::new(-1.0, 2.0, 0.5)).norm() < 1e-3);
+        assert!(obb.rotation.determinant() > 0.0);
+        assert!((obb.volume() - 2.0).abs() < 1e-2);
+        assert!(obb.volume() < obb.aabb().extents().product());
+        assert!(Obb3Df::from_point_iter(std::iter::empty()).is_none());
+
+        let inflated = Obb3Df {
+            half_extents: obb.half_extents.add_scalar(1e-4),
+            ..obb
+        };
+        assert!(points.iter().all(|point| inflated.contains(point)));
+        let aabb = obb.aabb();
+        assert!(obb
+            .corners()
+            .iter()
+            .all(|corner| aabb.contains(&(corner * 0.9999 + aabb.center() * 0.0001))));
+
+        // A cube rotated by 45 degrees around X, moved next to the unit cube.
+        let rotation = TransformBuilder::default()
+            .axis_angle(Vector3::x_axis(), std::f32::consts::FRAC_PI_4)
+            .build();
+        let unit = Obb3Df::new(Vector3::zeros(), Matrix3::identity(), Vector3::repeat(0.5));
+        let rotated = rotation.transform(&unit);
+        let moved = |offset: Vector3<f32>| Obb3Df {
+            center: offset,
+            ..rotated
+        };
+        assert!(unit.intersects(&moved(Vector3::new(0.0, 1.1, 0.0))));
+        assert!(!unit.intersects(&moved(Vector3::new(0.0, 1.3, 0.0))));
+        assert!(!unit.intersects(&moved(Vector3::new(0.0, 0.95, 0.95))));
+        assert!(unit.intersects(&moved(Vector3::new(0.0, 0.8, 0.8))));
+        assert!(unit.intersects_aabb(&Aabb3Df::new(Vector3::repeat(0.4), Vector3::repeat(2.0))));
+        assert!(!unit.intersects_aabb(&Aabb3Df::empty()));
+
+        let union = unit.union(&Obb3Df {
+            center: Vector3::new(3.0, 0.0, 0.0),
+            ..unit
+        });
+        assert!(union.contains(&Vector3::new(3.4, 0.0, 0.0)));
+        assert!(union.contains(&Vector3::new(-0.4, 0.4, 0.4)));
+    }
+
+    #[test]
+    fn test_frustum() {
+        // Looking along the world X axis from the origin, with a 90 degrees field of view.
+        let camera = PinholeCamera::new(
+            CameraIntrinsics::from_simple_intrinsic(50.0, 50.0, 50.0, 50.0, 100, 100),
+            TransformBuilder::default()
+                .axis_angle(Vector3::y_axis(), std::f32::consts::FRAC_PI_2)
+                .build(),
+        );
+        let frustum = Frustum::new(&camera, 0.5, 10.0);
+        assert!(frustum.contains(&Vector3::new(5.0, 0.0, 0.0)));
+        assert!(frustum.contains(&Vector3::new(5.0, 4.9, -4.9)));
+        assert!(!frustum.contains(&Vector3::new(5.0, 5.1, 0.0)));
+        assert!(!frustum.contains(&Vector3::new(0.4, 0.0, 0.0)));
+        assert!(!frustum.contains(&Vector3::new(10.1, 0.0, 0.0)));
+        assert!(!frustum.contains(&Vector3::new(-5.0, 0.0, 0.0)));
+        for point in [
+            Vector3::new(1.0, 0.3, 0.2),
+            Vector3::new(9.0, -8.0, 3.0),
+            Vector3::new(2.0, 3.0, 0.0),
+        ] {
+            let (x, y, z) = camera.project(&point);
+            let visible = (0.5..=10.0).contains(&z)
+                && (0.0..=100.0).contains(&x)
+                && (0.0..=100.0).contains(&y);
+            assert_eq!(frustum.contains(&point), visible);
+        }
+
+        assert!(frustum.intersects_sphere(&Vector3::new(5.0, 5.5, 0.0), 1.0));
+        assert!(!frustum.intersects_sphere(&Vector3::new(5.0, 7.0, 0.0), 1.0));
+        assert!(!frustum.intersects_sphere(&Vector3::new(-1.5, 0.0, 0.0), 1.0));
+
+        assert!(frustum.intersects_aabb(&Aabb3Df::new(
+            Vector3::new(-1.0, -1.0, -1.0),
+            Vector3::new(1.0, 1.0, 1.0)
+        )));
+        assert!(!frustum.intersects_aabb(&Aabb3Df::new(
+            Vector3::new(11.0, -1.0, -1.0),
+            Vector3::new(12.0, 1.0, 1.0)
+        )));
+        assert!(!frustum.intersects_aabb(&Aabb3Df::empty()));
+
+        let aabb = frustum.aabb();
+        assert!((aabb.max - Vector3::new(10.0, 10.0, 10.0)).norm() < 1e-4);
+        assert!((aabb.min - Vector3::new(0.5, -10.0, -10.0)).norm() < 1e-4);
+    }
+}
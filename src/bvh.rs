@@ -0,0 +1,484 @@
+//! Bounding volume hierarchy over triangle meshes.
+
+use nalgebra::Vector3;
+use ndarray::{Array2, Zip};
+
+use crate::{bounds::Aabb3Df, camera::PinholeCamera, error::A3dError, io::Geometry};
+
+/// Maximum number of triangles in a leaf.
+const MAX_LEAF_SIZE: usize = 4;
+
+/// A half-line from an origin along a direction.
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    /// Direction of the ray, distances along it are in units of its norm.
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Vector3<f32>, direction: Vector3<f32>) -> Self {
+        Self { origin, direction }
+    }
+
+    /// The point at a distance along the ray.
+    pub fn at(&self, distance: f32) -> Vector3<f32> {
+        self.origin + self.direction * distance
+    }
+}
+
+/// The first intersection of a ray with a mesh, see [`MeshBvh::ray_cast`].
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    /// Distance along the ray, in units of its direction norm.
+    pub distance: f32,
+    /// Index of the hit face.
+    pub triangle: usize,
+    /// The intersection point.
+    pub point: Vector3<f32>,
+}
+
+/// The closest point of a mesh surface to a query point, see [`MeshBvh::closest_point`].
+#[derive(Debug, Clone, Copy)]
+pub struct ClosestPoint {
+    /// Index of the face of the point.
+    pub triangle: usize,
+    /// The point on the face.
+    pub point: Vector3<f32>,
+    /// Distance between the query and the point.
+    pub distance: f32,
+}
+
+enum NodeKind {
+    /// Range of `MeshBvh::order`.
+    Leaf {
+        start: usize,
+        end: usize,
+    },
+    Inner {
+        left: usize,
+        right: usize,
+    },
+}
+
+struct Node {
+    bounds: Aabb3Df,
+    kind: NodeKind,
+}
+
+/// A bounding volume hierarchy over the triangles of a mesh. Nodes split their triangles
+/// at the median centroid along the longest axis, so ray casts and closest point queries
+/// visit a logarithmic number of boxes, e.g., to render synthetic depth images or to
+/// measure the distance of a reconstruction to a ground truth model.
+pub struct MeshBvh {
+    triangles: Vec<[Vector3<f32>; 3]>,
+    /// Triangle indices, ordered such that each leaf holds a contiguous range.
+    order: Vec<usize>,
+    nodes: Vec<Node>,
+}
+
+impl MeshBvh {
+    /// Builds the hierarchy of a mesh.
+    ///
+    /// # Arguments
+    ///
+    /// * mesh - The triangle mesh.
+    ///
+    /// # Returns
+    ///
+    /// The hierarchy, or error if the mesh has no faces.
+    pub fn new(mesh: &Geometry) -> Result<Self, A3dError> {
+        let faces = mesh
+            .faces
+            .as_ref()
+            .filter(|faces| faces.nrows() > 0)
+            .ok_or_else(|| A3dError::invalid_parameter("The mesh has no faces."))?;
+        let triangles = faces
+            .rows()
+            .into_iter()
+            .map(|face| [0, 1, 2].map(|corner| mesh.points[face[corner]]))
+            .collect::<Vec<_>>();
+        let centroids = triangles
+            .iter()
+            .map(|[a, b, c]| (a + b + c) / 3.0)
+            .collect::<Vec<_>>();
+
+        let mut bvh = Self {
+            order: (0..triangles.len()).collect(),
+            triangles,
+            nodes: Vec::new(),
+        };
+        bvh.build(&centroids, 0, bvh.triangles.len());
+        Ok(bvh)
+    }
+
+    /// Builds the node of the triangles in `order[start..end]`, returning its index.
+    fn build(&mut self, centroids: &[Vector3<f32>], start: usize, end: usize) -> usize {
+        let bounds = Aabb3Df::from_point_iter(
+            self.order[start..end]
+                .iter()
+                .flat_map(|index| self.triangles[*index]),
+        );
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            bounds,
+            kind: NodeKind::Leaf { start, end },
+        });
+        if end - start <= MAX_LEAF_SIZE {
+            return node;
+        }
+
+        let centroid_bounds =
+            Aabb3Df::from_point_iter(self.order[start..end].iter().map(|index| centroids[*index]));
+        let axis = centroid_bounds.extents().imax();
+        let middle = (start + end) / 2;
+        self.order[start..end].select_nth_unstable_by(middle - start, |lhs, rhs| {
+            centroids[*lhs][axis].total_cmp(&centroids[*rhs][axis])
+        });
+
+        let left = self.build(centroids, start, middle);
+        let right = self.build(centroids, middle, end);
+        self.nodes[node].kind = NodeKind::Inner { left, right };
+        node
+    }
+
+    /// Number of triangles.
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    /// The box containing the mesh.
+    pub fn bounds(&self) -> Aabb3Df {
+        self.nodes[0].bounds
+    }
+
+    /// The vertices of a triangle.
+    pub fn triangle(&self, index: usize) -> [Vector3<f32>; 3] {
+        self.triangles[index]
+    }
+
+    /// Finds the first intersection of a ray with the mesh. Both sides of the triangles
+    /// are hit.
+    ///
+    /// # Arguments
+    ///
+    /// * ray - The ray.
+    /// * max_distance - Distance along the ray after which intersections are ignored.
+    ///
+    /// # Returns
+    ///
+    /// The closest intersection, `None` if the ray misses the mesh.
+    pub fn ray_cast(&self, ray: &Ray, max_distance: f32) -> Option<RayHit> {
+        let inv_direction = ray.direction.map(f32::recip);
+        let mut closest: Option<(f32, usize)> = None;
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            let limit = closest.map_or(max_distance, |(distance, _)| distance);
+            match ray_box_distance(ray, &inv_direction, &node.bounds) {
+                Some(distance) if distance <= limit => (),
+                _ => continue,
+            }
+            match node.kind {
+                NodeKind::Leaf { start, end } => {
+                    for index in &self.order[start..end] {
+                        if let Some(distance) = ray_triangle_distance(ray, self.triangles[*index]) {
+                            let limit = closest.map_or(max_distance, |(distance, _)| distance);
+                            if distance <= limit {
+                                closest = Some((distance, *index));
+                            }
+                        }
+                    }
+                }
+                NodeKind::Inner { left, right } => {
+                    stack.extend([left, right]);
+                }
+            }
+        }
+
+        closest.map(|(distance, triangle)| RayHit {
+            distance,
+            triangle,
+            point: ray.at(distance),
+        })
+    }
+
+    /// Finds the closest point of the mesh surface to a query point, not only among the
+    /// vertices.
+    pub fn closest_point(&self, point: &Vector3<f32>) -> ClosestPoint {
+        let mut best = (f32::INFINITY, 0, *point);
+        let mut stack = vec![(0, 0.0)];
+        while let Some((node, sqr_box_distance)) = stack.pop() {
+            if sqr_box_distance >= best.0 {
+                continue;
+            }
+            match self.nodes[node].kind {
+                NodeKind::Leaf { start, end } => {
+                    for index in &self.order[start..end] {
+                        let closest = closest_point_on_triangle(point, self.triangles[*index]);
+                        let sqr_distance = (closest - point).norm_squared();
+                        if sqr_distance < best.0 {
+                            best = (sqr_distance, *index, closest);
+                        }
+                    }
+                }
+                NodeKind::Inner { left, right } => {
+                    let left = (left, sqr_distance_to_box(point, &self.nodes[left].bounds));
+                    let right = (right, sqr_distance_to_box(point, &self.nodes[right].bounds));
+                    // Visits the closest child first, it is on top of the stack.
+                    if left.1 < right.1 {
+                        stack.extend([right, left]);
+                    } else {
+                        stack.extend([left, right]);
+                    }
+                }
+            }
+        }
+
+        let (sqr_distance, triangle, closest) = best;
+        ClosestPoint {
+            triangle,
+            point: closest,
+            distance: sqr_distance.sqrt(),
+        }
+    }
+
+    /// Renders the depth image of the mesh seen by a camera, casting a ray through the
+    /// center of each pixel.
+    ///
+    /// # Arguments
+    ///
+    /// * camera - The camera, its intrinsics give the image size.
+    ///
+    /// # Returns
+    ///
+    /// The depth along the camera's Z axis with shape (height, width), 0 where the mesh
+    /// isn't seen.
+    pub fn render_depth(&self, camera: &PinholeCamera) -> Array2<f32> {
+        let intrinsics = &camera.intrinsics;
+        let origin = camera.camera_to_world.translation();
+        let mut depth = Array2::zeros((intrinsics.height, intrinsics.width));
+        Zip::indexed(&mut depth).par_for_each(|(y, x), depth| {
+            // The direction has a unit Z in the camera, so distances along it are depths.
+            let direction = camera
+                .camera_to_world
+                .transform_normal(&intrinsics.backproject(x as f32, y as f32, 1.0));
+            if let Some(hit) = self.ray_cast(&Ray::new(origin, direction), f32::INFINITY) {
+                *depth = hit.distance;
+            }
+        });
+        depth
+    }
+}
+
+/// Distance along a ray to the entrance of a box, 0 if it starts inside, `None` if it
+/// misses the box.
+fn ray_box_distance(ray: &Ray, inv_direction: &Vector3<f32>, aabb: &Aabb3Df) -> Option<f32> {
+    let (mut near, mut far) = (0.0f32, f32::INFINITY);
+    for axis in 0..3 {
+        if ray.direction[axis] == 0.0 {
+            // Parallel to the slab, the products below would be NaN on its faces.
+            if ray.origin[axis] < aabb.min[axis] || ray.origin[axis] > aabb.max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let t0 = (aabb.min[axis] - ray.origin[axis]) * inv_direction[axis];
+        let t1 = (aabb.max[axis] - ray.origin[axis]) * inv_direction[axis];
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+    }
+    (near <= far).then_some(near)
+}
+
+/// Distance along a ray to a triangle, `None` if it misses it. See Möller and Trumbore,
+/// Fast, Minimum Storage Ray/Triangle Intersection.
+fn ray_triangle_distance(ray: &Ray, [a, b, c]: [Vector3<f32>; 3]) -> Option<f32> {
+    let (ab, ac) = (b - a, c - a);
+    let p = ray.direction.cross(&ac);
+    let determinant = ab.dot(&p);
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+    let inv_determinant = determinant.recip();
+    let ao = ray.origin - a;
+    let u = ao.dot(&p) * inv_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = ao.cross(&ab);
+    let v = ray.direction.dot(&q) * inv_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = ac.dot(&q) * inv_determinant;
+    (distance >= 0.0).then_some(distance)
+}
+
+/// Squared distance between a point and a box, 0 if it is inside.
+fn sqr_distance_to_box(point: &Vector3<f32>, aabb: &Aabb3Df) -> f32 {
+    (aabb.min - point)
+        .sup(&(point - aabb.max))
+        .sup(&Vector3::zeros())
+        .norm_squared()
+}
+
+/// Closest point to `point` on the triangle `abc`, see Ericson, Real-Time Collision
+/// Detection, 5.1.5.
+pub(crate) fn closest_point_on_triangle(
+    point: &Vector3<f32>,
+    [a, b, c]: [Vector3<f32>; 3],
+) -> Vector3<f32> {
+    let (ab, ac) = (b - a, c - a);
+    let ap = point - a;
+    let (d1, d2) = (ab.dot(&ap), ac.dot(&ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+    let bp = point - b;
+    let (d3, d4) = (ab.dot(&bp), ac.dot(&bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = point - c;
+    let (d5, d6) = (ab.dot(&cp), ac.dot(&cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 >= d3 && d5 >= d6 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denominator = va + vb + vc;
+    a + ab * (vb / denominator) + ac * (vc / denominator)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Quaternion, Vector3};
+    use ndarray::{Array1, Array2};
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::{closest_point_on_triangle, MeshBvh, Ray};
+    use crate::{
+        camera::{CameraIntrinsics, PinholeCamera},
+        io::{Geometry, GeometryBuilder},
+        transform::Transform,
+    };
+
+    /// A grid of `size` x `size` unit squares on the plane z = `z`, split in triangles.
+    fn grid_mesh(size: usize, z: f32) -> Geometry {
+        let points = Array1::from_shape_fn((size + 1) * (size + 1), |i| {
+            Vector3::new((i % (size + 1)) as f32, (i / (size + 1)) as f32, z)
+        });
+        let faces = Array2::from_shape_fn((size * size * 2, 3), |(face, corner)| {
+            let square = face / 2;
+            let (x, y) = (square % size, square / size);
+            let vertex = |dx: usize, dy: usize| (y + dy) * (size + 1) + x + dx;
+            let triangle = if face % 2 == 0 {
+                [vertex(0, 0), vertex(1, 0), vertex(1, 1)]
+            } else {
+                [vertex(0, 0), vertex(1, 1), vertex(0, 1)]
+            };
+            triangle[corner]
+        });
+        GeometryBuilder::new(points).with_faces(faces).build()
+    }
+
+    #[test]
+    fn should_cast_rays() {
+        let bvh = MeshBvh::new(&grid_mesh(10, 2.0)).unwrap();
+        assert_eq!(bvh.len(), 200);
+
+        let hit = bvh
+            .ray_cast(
+                &Ray::new(Vector3::new(3.2, 4.7, 0.0), Vector3::new(0.0, 0.0, 1.0)),
+                10.0,
+            )
+            .unwrap();
+        assert!((hit.distance - 2.0).abs() < 1e-5);
+        assert!((hit.point - Vector3::new(3.2, 4.7, 2.0)).norm() < 1e-5);
+        let [a, b, c] = bvh.triangle(hit.triangle);
+        assert!((hit.point - closest_point_on_triangle(&hit.point, [a, b, c])).norm() < 1e-5);
+
+        // Oblique ray from below, hitting the back face.
+        let hit = bvh
+            .ray_cast(
+                &Ray::new(Vector3::new(1.0, 1.0, 4.0), Vector3::new(1.0, 2.0, -1.0)),
+                10.0,
+            )
+            .unwrap();
+        assert!((hit.distance - 2.0).abs() < 1e-5);
+        assert!((hit.point - Vector3::new(3.0, 5.0, 2.0)).norm() < 1e-5);
+
+        let up = Vector3::new(0.0, 0.0, 1.0);
+        assert!(bvh
+            .ray_cast(&Ray::new(Vector3::new(3.2, 4.7, 0.0), up), 1.5)
+            .is_none());
+        assert!(bvh
+            .ray_cast(&Ray::new(Vector3::new(3.2, 4.7, 3.0), up), 10.0)
+            .is_none());
+        assert!(bvh
+            .ray_cast(&Ray::new(Vector3::new(11.0, 4.7, 0.0), up), 10.0)
+            .is_none());
+    }
+
+    #[test]
+    fn should_find_closest_points() {
+        let mesh = grid_mesh(8, 0.0);
+        let bvh = MeshBvh::new(&mesh).unwrap();
+        let triangles = (0..bvh.len())
+            .map(|index| bvh.triangle(index))
+            .collect::<Vec<_>>();
+
+        let mut rng = SmallRng::seed_from_u64(4);
+        for _ in 0..100 {
+            let query = Vector3::new(
+                rng.gen_range(-3.0..11.0),
+                rng.gen_range(-3.0..11.0),
+                rng.gen_range(-2.0..2.0),
+            );
+            let expected = triangles
+                .iter()
+                .map(|triangle| (query - closest_point_on_triangle(&query, *triangle)).norm())
+                .fold(f32::INFINITY, f32::min);
+            let closest = bvh.closest_point(&query);
+            assert!((closest.distance - expected).abs() < 1e-5);
+            assert!((closest.point - query).norm() - closest.distance < 1e-5);
+            assert!(closest.point.z.abs() < 1e-6);
+        }
+
+        let no_faces = GeometryBuilder::new(mesh.points.clone()).build();
+        assert!(MeshBvh::new(&no_faces).is_err());
+    }
+
+    #[test]
+    fn should_render_depth() {
+        let bvh = MeshBvh::new(&grid_mesh(10, 2.0)).unwrap();
+        let camera = PinholeCamera::new(
+            CameraIntrinsics::from_simple_intrinsic(20.0, 20.0, 10.0, 10.0, 20, 20),
+            Transform::new(&Vector3::new(5.0, 5.0, 0.0), &Quaternion::identity()),
+        );
+        let depth = bvh.render_depth(&camera);
+        assert_eq!(depth.dim(), (20, 20));
+        assert!(depth.iter().all(|depth| (depth - 2.0).abs() < 1e-5));
+
+        let far = PinholeCamera::new(
+            camera.intrinsics.clone(),
+            Transform::new(&Vector3::new(50.0, 5.0, 0.0), &Quaternion::identity()),
+        );
+        assert!(bvh.render_depth(&far).iter().all(|depth| *depth == 0.0));
+    }
+}
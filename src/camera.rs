@@ -4,6 +4,10 @@ use super::transform::Transform;
 
 /// Camera intrinsic parameters.
 #[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
 pub struct CameraIntrinsics {
     /// Focal length and pixel scale in the X-axis.
     pub fx: f64,
@@ -132,6 +136,47 @@ impl CameraIntrinsics {
         self.height = height;
     }
 }
+/// Lens distortion coefficients of the Brown-Conrady model, in the OpenCV order.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+pub struct Distortion {
+    pub k1: f64,
+    pub k2: f64,
+    pub p1: f64,
+    pub p2: f64,
+    pub k3: f64,
+}
+
+impl Distortion {
+    /// Creates the distortion from the `[k1, k2, p1, p2, k3]` coefficients.
+    pub fn new(coefficients: [f64; 5]) -> Self {
+        let [k1, k2, p1, p2, k3] = coefficients;
+        Self { k1, k2, p1, p2, k3 }
+    }
+
+    /// Applies the distortion to normalized image coordinates, i.e., `(x / z, y / z)`.
+    ///
+    /// # Arguments
+    ///
+    /// * x: The undistorted normalized x coordinate.
+    /// * y: The undistorted normalized y coordinate.
+    ///
+    /// # Returns
+    ///
+    /// * The distorted normalized coordinates.
+    pub fn distort(&self, x: f64, y: f64) -> (f64, f64) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        (
+            x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+            y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y,
+        )
+    }
+}
+
 /// A pinhole camera. It is defined by its intrinsic parameters and its pose in the world.
 #[derive(Clone, Debug)]
 pub struct PinholeCamera {
@@ -241,4 +286,13 @@ mod tests {
         assert_eq!(x, 50.0);
         assert_eq!(y, 75.0);
     }
+
+    #[test]
+    pub fn test_distort() {
+        let distortion = super::Distortion::new([0.1, 0.01, 0.001, -0.002, 0.0]);
+        assert_eq!(distortion.distort(0.0, 0.0), (0.0, 0.0));
+        let (x, y) = distortion.distort(0.5, 0.0);
+        assert!((x - (0.5 * (1.0 + 0.025 + 0.000625) - 0.002 * 0.75)).abs() < 1e-12);
+        assert!((y - 0.001 * 0.25).abs() < 1e-12);
+    }
 }
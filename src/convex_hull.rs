@@ -0,0 +1,392 @@
+//! Convex hulls of point sets.
+
+use std::collections::HashMap;
+
+use nalgebra::Vector3;
+use ndarray::{Array1, Array2};
+
+use crate::{
+    error::A3dError,
+    io::{Geometry, GeometryBuilder},
+    pointcloud::PointCloud,
+};
+
+/// A triangle of the hull being built.
+struct Face {
+    vertices: [usize; 3],
+    normal: Vector3<f64>,
+    offset: f64,
+    /// Points above the face not yet on the hull.
+    outside: Vec<usize>,
+    alive: bool,
+}
+
+impl Face {
+    fn new(points: &[Vector3<f64>], vertices: [usize; 3]) -> Self {
+        let [a, b, c] = vertices.map(|index| points[index]);
+        let normal = (b - a)
+            .cross(&(c - a))
+            .try_normalize(0.0)
+            .unwrap_or_else(Vector3::zeros);
+        Self {
+            vertices,
+            normal,
+            offset: normal.dot(&a),
+            outside: Vec::new(),
+            alive: true,
+        }
+    }
+
+    fn distance(&self, point: &Vector3<f64>) -> f64 {
+        self.normal.dot(point) - self.offset
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.vertices;
+        [(a, b), (b, c), (c, a)]
+    }
+}
+
+/// Index of the candidate with the largest score.
+fn argmax(candidates: impl Iterator<Item = usize>, score: impl Fn(usize) -> f64) -> usize {
+    candidates
+        .max_by(|a, b| score(*a).total_cmp(&score(*b)))
+        .unwrap()
+}
+
+/// The initial tetrahedron, `None` if the points are coplanar.
+fn initial_simplex(points: &[Vector3<f64>], epsilon: f64) -> Option<[usize; 4]> {
+    let extremes = (0..3)
+        .flat_map(|axis| {
+            [
+                argmax(0..points.len(), |i| -points[i][axis]),
+                argmax(0..points.len(), |i| points[i][axis]),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let (i0, i1) = extremes
+        .iter()
+        .flat_map(|a| extremes.iter().map(move |b| (*a, *b)))
+        .max_by(|(a0, b0), (a1, b1)| {
+            (points[*a0] - points[*b0])
+                .norm_squared()
+                .total_cmp(&(points[*a1] - points[*b1]).norm_squared())
+        })?;
+    if (points[i0] - points[i1]).norm() <= epsilon {
+        return None;
+    }
+
+    let direction = (points[i1] - points[i0]).normalize();
+    let line_distance = |i: usize| (points[i] - points[i0]).cross(&direction).norm();
+    let i2 = argmax(0..points.len(), line_distance);
+    if line_distance(i2) <= epsilon {
+        return None;
+    }
+
+    let plane = Face::new(points, [i0, i1, i2]);
+    let i3 = argmax(0..points.len(), |i| plane.distance(&points[i]).abs());
+    if plane.distance(&points[i3]).abs() <= epsilon {
+        return None;
+    }
+    Some([i0, i1, i2, i3])
+}
+
+/// Computes the 3D convex hull of points with the quickhull algorithm.
+///
+/// # Arguments
+///
+/// * points - The points.
+///
+/// # Returns
+///
+/// The triangles of the hull, as indices of the points, counter-clockwise when seen from
+/// outside. `None` if there are fewer than 4 points or they are coplanar.
+pub(crate) fn convex_hull(points: &[Vector3<f64>]) -> Option<Vec<[usize; 3]>> {
+    if points.len() < 4 {
+        return None;
+    }
+    let scale = points
+        .iter()
+        .fold(0.0_f64, |scale, point| scale.max(point.amax()));
+    let epsilon = scale.max(f64::MIN_POSITIVE) * 1e-12;
+    let simplex = initial_simplex(points, epsilon)?;
+
+    let interior = simplex
+        .iter()
+        .fold(Vector3::zeros(), |sum, index| sum + points[*index])
+        / 4.0;
+    let [i0, i1, i2, i3] = simplex;
+    let mut faces = [[i0, i1, i2], [i0, i3, i1], [i1, i3, i2], [i2, i3, i0]]
+        .into_iter()
+        .map(|[a, b, c]| {
+            let face = Face::new(points, [a, b, c]);
+            if face.distance(&interior) > 0.0 {
+                Face::new(points, [a, c, b])
+            } else {
+                face
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // The face on the left of each directed edge.
+    let mut edge_faces = HashMap::new();
+    for (index, face) in faces.iter().enumerate() {
+        for edge in face.edges() {
+            edge_faces.insert(edge, index);
+        }
+    }
+
+    // Each point goes to the face it is the farthest above, if any.
+    let assign = |faces: &mut [Face], candidates: &[usize], point: usize| {
+        let (best, distance) = candidates
+            .iter()
+            .map(|face| (*face, faces[*face].distance(&points[point])))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        if distance > epsilon {
+            faces[best].outside.push(point);
+        }
+    };
+    for point in 0..points.len() {
+        if !simplex.contains(&point) {
+            assign(&mut faces, &[0, 1, 2, 3], point);
+        }
+    }
+
+    let mut current = 0;
+    while current < faces.len() {
+        if !faces[current].alive || faces[current].outside.is_empty() {
+            current += 1;
+            continue;
+        }
+        let face = &faces[current];
+        let apex = *face
+            .outside
+            .iter()
+            .max_by(|a, b| {
+                face.distance(&points[**a])
+                    .total_cmp(&face.distance(&points[**b]))
+            })
+            .unwrap();
+
+        // Faces seen from the apex, connected to the current one, and their boundary.
+        let mut visible = vec![current];
+        let mut is_visible = HashMap::from([(current, true)]);
+        let mut horizon = Vec::new();
+        let mut next = 0;
+        while next < visible.len() {
+            for (a, b) in faces[visible[next]].edges() {
+                let neighbor = edge_faces[&(b, a)];
+                let neighbor_visible = *is_visible
+                    .entry(neighbor)
+                    .or_insert_with(|| faces[neighbor].distance(&points[apex]) > epsilon);
+                if !neighbor_visible {
+                    horizon.push((a, b));
+                } else if !visible.contains(&neighbor) {
+                    visible.push(neighbor);
+                }
+            }
+            next += 1;
+        }
+
+        let mut orphans = Vec::new();
+        for index in &visible {
+            let face = &mut faces[*index];
+            face.alive = false;
+            orphans.append(&mut face.outside);
+            for edge in face.edges() {
+                edge_faces.remove(&edge);
+            }
+        }
+
+        let new_faces = (faces.len()..faces.len() + horizon.len()).collect::<Vec<_>>();
+        for (a, b) in horizon {
+            let face = Face::new(points, [a, b, apex]);
+            for edge in face.edges() {
+                edge_faces.insert(edge, faces.len());
+            }
+            faces.push(face);
+        }
+        for point in orphans {
+            if point != apex {
+                assign(&mut faces, &new_faces, point);
+            }
+        }
+    }
+
+    Some(
+        faces
+            .into_iter()
+            .filter(|face| face.alive)
+            .map(|face| face.vertices)
+            .collect(),
+    )
+}
+
+/// The convex hull of a point cloud.
+pub struct ConvexHull {
+    /// Triangle mesh of the hull, counter-clockwise when seen from outside.
+    pub mesh: Geometry,
+    /// Index in the point cloud of each vertex of the mesh.
+    pub vertex_indices: Vec<usize>,
+}
+
+impl ConvexHull {
+    /// Volume enclosed by the hull.
+    pub fn volume(&self) -> f32 {
+        self.triangles()
+            .map(|[a, b, c]| a.dot(&b.cross(&c)))
+            .sum::<f64>() as f32
+            / 6.0
+    }
+
+    /// Surface area of the hull.
+    pub fn area(&self) -> f32 {
+        self.triangles()
+            .map(|[a, b, c]| (b - a).cross(&(c - a)).norm())
+            .sum::<f64>() as f32
+            / 2.0
+    }
+
+    /// The vertices of each triangle, relative to the first vertex for precision.
+    fn triangles(&self) -> impl Iterator<Item = [Vector3<f64>; 3]> + '_ {
+        let points = &self.mesh.points;
+        let origin = points[0].cast::<f64>();
+        self.mesh
+            .faces
+            .as_ref()
+            .unwrap()
+            .rows()
+            .into_iter()
+            .map(move |face| [0, 1, 2].map(|k| points[face[k]].cast::<f64>() - origin))
+    }
+}
+
+impl PointCloud {
+    /// Computes the convex hull of the points with quickhull, e.g., as coarse collision
+    /// bounds or to estimate the volume of a segmented object.
+    ///
+    /// # Returns
+    ///
+    /// The hull, or error if the cloud has fewer than 4 points or they are coplanar.
+    pub fn convex_hull(&self) -> Result<ConvexHull, A3dError> {
+        let points = self
+            .points
+            .iter()
+            .map(|point| point.cast::<f64>())
+            .collect::<Vec<_>>();
+        let triangles = convex_hull(&points).ok_or_else(|| {
+            A3dError::invalid_parameter("The convex hull requires 4 non-coplanar points.")
+        })?;
+
+        let mut vertex_indices = triangles.iter().flatten().copied().collect::<Vec<_>>();
+        vertex_indices.sort_unstable();
+        vertex_indices.dedup();
+        let mut vertex_map = HashMap::new();
+        for (vertex, index) in vertex_indices.iter().enumerate() {
+            vertex_map.insert(*index, vertex);
+        }
+        let faces = Array2::from_shape_fn((triangles.len(), 3), |(face, k)| {
+            vertex_map[&triangles[face][k]]
+        });
+        let mesh = GeometryBuilder::new(
+            vertex_indices
+                .iter()
+                .map(|index| self.points[*index])
+                .collect::<Array1<_>>(),
+        )
+        .with_faces(faces)
+        .build();
+        Ok(ConvexHull {
+            mesh,
+            vertex_indices,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use nalgebra::Vector3;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::convex_hull;
+    use crate::pointcloud::PointCloud;
+
+    #[test]
+    fn test_convex_hull() {
+        let mut rng = StdRng::seed_from_u64(8);
+        // The corners of a cube and random points inside it.
+        let mut points = (0..8)
+            .map(|i| Vector3::new((i & 1) as f64, (i >> 1 & 1) as f64, (i >> 2) as f64))
+            .collect::<Vec<_>>();
+        points.extend((0..500).map(|_| {
+            Vector3::new(
+                rng.gen_range(0.01..0.99),
+                rng.gen_range(0.01..0.99),
+                rng.gen_range(0.01..0.99),
+            )
+        }));
+
+        let triangles = convex_hull(&points).unwrap();
+        assert_eq!(triangles.len(), 12);
+        let vertices = triangles.iter().flatten().copied().collect::<HashSet<_>>();
+        assert_eq!(vertices, (0..8).collect());
+
+        // Every edge is shared by two triangles in opposite directions.
+        let edges = triangles
+            .iter()
+            .flat_map(|[a, b, c]| [(*a, *b), (*b, *c), (*c, *a)])
+            .collect::<HashSet<_>>();
+        assert_eq!(edges.len(), 36);
+        assert!(edges.iter().all(|(a, b)| edges.contains(&(*b, *a))));
+
+        // Outward orientation.
+        let center = Vector3::new(0.5, 0.5, 0.5);
+        assert!(triangles.iter().all(|[a, b, c]| {
+            let normal = (points[*b] - points[*a]).cross(&(points[*c] - points[*a]));
+            normal.dot(&(points[*a] - center)) > 0.0
+        }));
+
+        let plane = (0..10)
+            .map(|i| Vector3::new(i as f64, (i * i) as f64, 0.0))
+            .collect::<Vec<_>>();
+        assert!(convex_hull(&plane).is_none());
+    }
+
+    #[test]
+    fn test_pointcloud_convex_hull() {
+        // An octahedron of radius 2 around a point at its center.
+        let pcl = PointCloud {
+            points: [
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(2.0, 0.0, 0.0),
+                Vector3::new(-2.0, 0.0, 0.0),
+                Vector3::new(0.0, 2.0, 0.0),
+                Vector3::new(0.0, -2.0, 0.0),
+                Vector3::new(0.0, 0.0, 2.0),
+                Vector3::new(0.0, 0.0, -2.0),
+            ]
+            .into_iter()
+            .collect(),
+            normals: None,
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+        let hull = pcl.convex_hull().unwrap();
+        assert_eq!(hull.vertex_indices, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(hull.mesh.len_vertices(), 6);
+        assert_eq!(hull.mesh.len_faces(), 8);
+        for (vertex, index) in hull.vertex_indices.iter().enumerate() {
+            assert_eq!(hull.mesh.points[vertex], pcl.points[*index]);
+        }
+        // 4/3 r^3 and 4 sqrt(3) r^2.
+        assert!((hull.volume() - 32.0 / 3.0).abs() < 1e-5);
+        assert!((hull.area() - 16.0 * 3f32.sqrt()).abs() < 1e-4);
+
+        assert!(pcl.select(&[0, 1, 2]).convex_hull().is_err());
+    }
+}
@@ -0,0 +1,538 @@
+use std::path::Path;
+
+use nalgebra::{Vector2, Vector3};
+use ndarray::{Array1, Array2, Axis};
+
+use crate::{
+    camera::PinholeCamera,
+    io::{write_ply, Geometry},
+    transform::Transform,
+};
+
+/// An edit over a geometry.
+#[derive(Clone, Debug)]
+pub enum EditCommand {
+    /// Keeps only the vertices inside the axis aligned box.
+    Crop {
+        min: Vector3<f32>,
+        max: Vector3<f32>,
+    },
+    /// Deletes the selected vertices.
+    DeleteSelection(Vec<usize>),
+    /// Transforms the vertices and normals.
+    Transform(Transform),
+}
+
+/// What is needed to revert an edit.
+enum UndoRecord {
+    Snapshot(Box<Geometry>),
+    /// The vertices and normals before a transform. Restoring them instead of applying the
+    /// inverse keeps repeated undo and redo from drifting.
+    Points {
+        points: Array1<Vector3<f32>>,
+        normals: Option<Array1<Vector3<f32>>>,
+    },
+}
+
+/// Removes the vertices whose mask is false, together with the faces that use them.
+fn retain_vertices(geometry: &Geometry, keep: &[bool]) -> Geometry {
+    let mut new_index = vec![usize::MAX; keep.len()];
+    for (count, (index, _)) in keep
+        .iter()
+        .enumerate()
+        .filter(|(_, keep)| **keep)
+        .enumerate()
+    {
+        new_index[index] = count;
+    }
+
+    fn select<T: Clone>(values: &Array1<T>, keep: &[bool]) -> Array1<T> {
+        values
+            .iter()
+            .zip(keep)
+            .filter(|(_, keep)| **keep)
+            .map(|(value, _)| value.clone())
+            .collect()
+    }
+
+    let faces = geometry.faces.as_ref().map(|faces| {
+        let faces = faces
+            .axis_iter(Axis(0))
+            .filter(|face| face.iter().all(|index| keep[*index]))
+            .flat_map(|face| {
+                face.iter()
+                    .map(|index| new_index[*index])
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        Array2::from_shape_vec((faces.len() / 3, 3), faces).unwrap()
+    });
+
+    Geometry {
+        points: select(&geometry.points, keep),
+        colors: geometry.colors.as_ref().map(|colors| select(colors, keep)),
+        normals: geometry
+            .normals
+            .as_ref()
+            .map(|normals| select(normals, keep)),
+        faces,
+        texcoords: geometry
+            .texcoords
+            .as_ref()
+            .map(|texcoords| select(texcoords, keep)),
+        metadata: geometry.metadata.clone(),
+    }
+}
+
+/// Projects the points in front of the camera into image coordinates.
+fn project_points<'a>(
+    points: &'a Array1<Vector3<f32>>,
+    camera: &'a PinholeCamera,
+) -> impl Iterator<Item = (usize, Vector2<f32>)> + 'a {
+    points.iter().enumerate().filter_map(|(index, point)| {
+        let (u, v, z) = camera.project(point);
+        (z > 0.0).then_some((index, Vector2::new(u, v)))
+    })
+}
+
+/// Selects the points whose projection falls inside a rectangle, e.g., dragged over the
+/// viewer.
+///
+/// # Arguments
+///
+/// * `points` - The points.
+/// * `camera` - The camera used to view the points.
+/// * `corner0` - One corner of the rectangle, in pixels.
+/// * `corner1` - The opposite corner of the rectangle, in pixels.
+///
+/// # Returns
+///
+/// * The indices of the selected points.
+pub fn select_rectangle(
+    points: &Array1<Vector3<f32>>,
+    camera: &PinholeCamera,
+    corner0: Vector2<f32>,
+    corner1: Vector2<f32>,
+) -> Vec<usize> {
+    project_points(points, camera)
+        .filter(|(_, uv)| inside_rectangle(uv, &corner0, &corner1))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Whether a point is inside the rectangle with the given opposite corners.
+pub(crate) fn inside_rectangle(
+    point: &Vector2<f32>,
+    corner0: &Vector2<f32>,
+    corner1: &Vector2<f32>,
+) -> bool {
+    let (min, max) = (corner0.inf(corner1), corner0.sup(corner1));
+    point[0] >= min[0] && point[0] <= max[0] && point[1] >= min[1] && point[1] <= max[1]
+}
+
+/// Whether a point is inside a polygon, using the even-odd rule.
+pub(crate) fn inside_polygon(point: &Vector2<f32>, polygon: &[Vector2<f32>]) -> bool {
+    let mut inside = false;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        if (a[1] > point[1]) != (b[1] > point[1])
+            && point[0] < a[0] + (point[1] - a[1]) * (b[0] - a[0]) / (b[1] - a[1])
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Selects the points whose projection falls inside a lasso polygon, using the even-odd
+/// rule.
+///
+/// # Arguments
+///
+/// * `points` - The points.
+/// * `camera` - The camera used to view the points.
+/// * `polygon` - The lasso vertices, in pixels. It is implicitly closed.
+///
+/// # Returns
+///
+/// * The indices of the selected points.
+pub fn select_lasso(
+    points: &Array1<Vector3<f32>>,
+    camera: &PinholeCamera,
+    polygon: &[Vector2<f32>],
+) -> Vec<usize> {
+    if polygon.len() < 3 {
+        return Vec::new();
+    }
+    project_points(points, camera)
+        .filter(|(_, uv)| inside_polygon(uv, polygon))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Selects the points inside a sphere, e.g., a 3D brush.
+///
+/// # Returns
+///
+/// * The indices of the selected points.
+pub fn select_sphere(
+    points: &Array1<Vector3<f32>>,
+    center: &Vector3<f32>,
+    radius: f32,
+) -> Vec<usize> {
+    let radius_sqr = radius * radius;
+    points
+        .iter()
+        .enumerate()
+        .filter(|(_, point)| (*point - center).norm_squared() <= radius_sqr)
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Creates a geometry with only the selected vertices and the faces between them.
+pub fn extract_selection(geometry: &Geometry, selection: &[usize]) -> Geometry {
+    let mut keep = vec![false; geometry.len_vertices()];
+    for index in selection.iter() {
+        if let Some(keep) = keep.get_mut(*index) {
+            *keep = true;
+        }
+    }
+    retain_vertices(geometry, &keep)
+}
+
+/// Merges two geometries into one, e.g., maps of the same place captured in different
+/// sessions. Attributes are only kept when both geometries have them.
+///
+/// # Arguments
+///
+/// * `target` - The reference geometry, its vertices come first.
+/// * `source` - The geometry to append.
+/// * `source_to_target` - Transformation of the source into the target frame.
+pub fn merge_maps(target: &Geometry, source: &Geometry, source_to_target: &Transform) -> Geometry {
+    fn concatenate<T: Clone>(
+        first: &Option<Array1<T>>,
+        second: Option<Array1<T>>,
+    ) -> Option<Array1<T>> {
+        let (first, second) = (first.as_ref()?, second?);
+        Some(first.iter().chain(second.iter()).cloned().collect())
+    }
+
+    let num_target_vertices = target.len_vertices();
+    let faces = match (&target.faces, &source.faces) {
+        (Some(target_faces), Some(source_faces)) => Some(
+            ndarray::concatenate(
+                Axis(0),
+                &[
+                    target_faces.view(),
+                    source_faces
+                        .mapv(|index| index + num_target_vertices)
+                        .view(),
+                ],
+            )
+            .unwrap(),
+        ),
+        _ => None,
+    };
+
+    Geometry {
+        points: concatenate(
+            &Some(target.points.clone()),
+            Some(source_to_target.transform_vectors(source.points.clone())),
+        )
+        .unwrap(),
+        colors: concatenate(&target.colors, source.colors.clone()),
+        normals: concatenate(
+            &target.normals,
+            source
+                .normals
+                .as_ref()
+                .map(|normals| source_to_target.transform_normals(normals.clone())),
+        ),
+        faces,
+        texcoords: concatenate(&target.texcoords, source.texcoords.clone()),
+        metadata: target.metadata.clone(),
+    }
+}
+
+/// Edit layer over a geometry with undo and redo, e.g., for cleaning up point clouds in a
+/// viewer.
+pub struct GeometryEditor {
+    geometry: Geometry,
+    undo_stack: Vec<(EditCommand, UndoRecord)>,
+    redo_stack: Vec<EditCommand>,
+}
+
+impl GeometryEditor {
+    /// Starts editing a geometry.
+    pub fn new(geometry: Geometry) -> Self {
+        Self {
+            geometry,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+        }
+    }
+
+    /// The current geometry.
+    pub fn geometry(&self) -> &Geometry {
+        &self.geometry
+    }
+
+    /// Finishes editing, returning the current geometry.
+    pub fn into_geometry(self) -> Geometry {
+        self.geometry
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    fn execute(&mut self, command: &EditCommand) -> UndoRecord {
+        match command {
+            EditCommand::Crop { min, max } => {
+                let keep = self
+                    .geometry
+                    .points
+                    .iter()
+                    .map(|point| {
+                        (0..3).all(|axis| point[axis] >= min[axis] && point[axis] <= max[axis])
+                    })
+                    .collect::<Vec<_>>();
+                let cropped = retain_vertices(&self.geometry, &keep);
+                UndoRecord::Snapshot(Box::new(std::mem::replace(&mut self.geometry, cropped)))
+            }
+            EditCommand::DeleteSelection(selection) => {
+                let mut keep = vec![true; self.geometry.len_vertices()];
+                for index in selection.iter() {
+                    if let Some(keep) = keep.get_mut(*index) {
+                        *keep = false;
+                    }
+                }
+                let remaining = retain_vertices(&self.geometry, &keep);
+                UndoRecord::Snapshot(Box::new(std::mem::replace(&mut self.geometry, remaining)))
+            }
+            EditCommand::Transform(transform) => {
+                let record = UndoRecord::Points {
+                    points: self.geometry.points.clone(),
+                    normals: self.geometry.normals.clone(),
+                };
+                self.geometry
+                    .points
+                    .iter_mut()
+                    .for_each(|point| *point = transform.transform_vector(point));
+                if let Some(normals) = self.geometry.normals.as_mut() {
+                    normals
+                        .iter_mut()
+                        .for_each(|normal| *normal = transform.transform_normal(normal));
+                }
+                record
+            }
+        }
+    }
+
+    /// Applies an edit and clears the redo history.
+    pub fn apply(&mut self, command: EditCommand) {
+        let record = self.execute(&command);
+        self.undo_stack.push((command, record));
+        self.redo_stack.clear();
+    }
+
+    /// Reverts the last edit.
+    ///
+    /// # Returns
+    ///
+    /// * False if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some((command, record)) = self.undo_stack.pop() else {
+            return false;
+        };
+        match record {
+            UndoRecord::Snapshot(geometry) => self.geometry = *geometry,
+            UndoRecord::Points { points, normals } => {
+                self.geometry.points = points;
+                self.geometry.normals = normals;
+            }
+        }
+        self.redo_stack.push(command);
+        true
+    }
+
+    /// Applies again the last undone edit.
+    ///
+    /// # Returns
+    ///
+    /// * False if there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(command) = self.redo_stack.pop() else {
+            return false;
+        };
+        let record = self.execute(&command);
+        self.undo_stack.push((command, record));
+        true
+    }
+
+    /// Saves the selected part of the current geometry into a PLY file.
+    pub fn export_selection<P: AsRef<Path>>(
+        &self,
+        selection: &[usize],
+        filepath: P,
+    ) -> std::io::Result<()> {
+        write_ply(filepath, &extract_selection(&self.geometry, selection))
+    }
+
+    /// Saves the current geometry into a PLY file.
+    pub fn save<P: AsRef<Path>>(&self, filepath: P) -> std::io::Result<()> {
+        write_ply(filepath, &self.geometry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use nalgebra::Vector2;
+    use ndarray::array;
+
+    use super::{
+        extract_selection, merge_maps, select_lasso, select_rectangle, select_sphere, EditCommand,
+        GeometryEditor,
+    };
+    use crate::{
+        camera::{CameraIntrinsics, PinholeCamera},
+        io::{read_off, GeometryBuilder},
+        transform::Transform,
+    };
+
+    #[test]
+    fn test_undo_redo() {
+        let teapot = read_off("tests/data/teapot.off").unwrap();
+        let num_vertices = teapot.len_vertices();
+        let num_faces = teapot.len_faces();
+        let mut editor = GeometryEditor::new(teapot.clone());
+        assert!(!editor.undo());
+
+        editor.apply(EditCommand::DeleteSelection(vec![0, 1, 2]));
+        assert_eq!(editor.geometry().len_vertices(), num_vertices - 3);
+        assert!(editor.geometry().len_faces() < num_faces);
+        let faces = editor.geometry().faces.as_ref().unwrap();
+        assert!(faces.iter().all(|index| *index < num_vertices - 3));
+
+        let min = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+        let max = Vector3::new(f32::MAX, 0.0, f32::MAX);
+        editor.apply(EditCommand::Crop { min, max });
+        assert!(editor.geometry().points.iter().all(|point| point[1] <= 0.0));
+
+        editor.apply(EditCommand::Transform(Transform::new(
+            &Vector3::new(1.0, 2.0, 3.0),
+            &nalgebra::Quaternion::identity(),
+        )));
+        assert!(editor.geometry().points.iter().all(|point| point[1] <= 2.0));
+
+        assert!(editor.undo());
+        assert!(editor.undo());
+        assert!(editor.undo());
+        assert!(!editor.can_undo());
+        assert_eq!(editor.geometry().points, teapot.points);
+        assert_eq!(editor.geometry().faces, teapot.faces);
+
+        assert!(editor.redo());
+        assert_eq!(editor.geometry().len_vertices(), num_vertices - 3);
+        editor.apply(EditCommand::DeleteSelection(vec![0]));
+        assert!(!editor.can_redo());
+
+        editor.save("tests/outputs/edited-teapot.ply").unwrap();
+    }
+
+    #[test]
+    fn test_undo_transform() {
+        let teapot = read_off("tests/data/teapot.off").unwrap();
+        let mut editor = GeometryEditor::new(teapot.clone());
+        editor.apply(EditCommand::Transform(Transform::new(
+            &Vector3::new(0.1, -0.2, 0.3),
+            &nalgebra::UnitQuaternion::from_euler_angles(0.3, 0.2, 0.1).into_inner(),
+        )));
+
+        for _ in 0..20 {
+            assert!(editor.undo());
+            assert!(editor.redo());
+        }
+        assert!(editor.undo());
+        assert_eq!(editor.geometry().points, teapot.points);
+        assert_eq!(editor.geometry().normals, teapot.normals);
+    }
+
+    #[test]
+    fn test_selection() {
+        let geometry = GeometryBuilder::new(array![
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.5, 0.0, 1.0),
+            Vector3::new(0.0, 0.5, 1.0),
+            Vector3::new(-0.5, -0.5, 1.0),
+            Vector3::new(0.0, 0.0, -1.0)
+        ])
+        .with_faces(array![[0, 1, 2], [0, 2, 3]])
+        .build();
+        let camera = PinholeCamera::new(
+            CameraIntrinsics::from_simple_intrinsic(100.0, 100.0, 50.0, 50.0, 100, 100),
+            Transform::eye(),
+        );
+
+        // Points project to (50, 50), (100, 50), (50, 100) and (0, 0), the last is behind.
+        let selection = select_rectangle(
+            &geometry.points,
+            &camera,
+            Vector2::new(110.0, 40.0),
+            Vector2::new(40.0, 60.0),
+        );
+        assert_eq!(selection, vec![0, 1]);
+
+        let triangle = [
+            Vector2::new(40.0, 40.0),
+            Vector2::new(120.0, 40.0),
+            Vector2::new(40.0, 120.0),
+        ];
+        assert_eq!(
+            select_lasso(&geometry.points, &camera, &triangle),
+            vec![0, 1, 2]
+        );
+
+        assert_eq!(
+            select_sphere(&geometry.points, &Vector3::new(0.0, 0.0, 1.0), 0.6),
+            vec![0, 1, 2]
+        );
+
+        let extracted = extract_selection(&geometry, &[0, 1, 2]);
+        assert_eq!(extracted.len_vertices(), 3);
+        assert_eq!(extracted.faces, Some(array![[0, 1, 2]]));
+    }
+
+    #[test]
+    fn test_merge_maps() {
+        let teapot = read_off("tests/data/teapot.off").unwrap();
+        let points = GeometryBuilder::new(array![Vector3::new(1.0, 2.0, 3.0)]).build();
+        let translation = Transform::new(
+            &Vector3::new(0.0, 0.0, 1.0),
+            &nalgebra::Quaternion::identity(),
+        );
+
+        let merged = merge_maps(&teapot, &teapot, &translation);
+        assert_eq!(merged.len_vertices(), 2 * teapot.len_vertices());
+        assert_eq!(merged.len_faces(), 2 * teapot.len_faces());
+        let faces = merged.faces.as_ref().unwrap();
+        assert_eq!(
+            faces[(teapot.len_faces(), 0)],
+            teapot.faces.as_ref().unwrap()[(0, 0)] + teapot.len_vertices()
+        );
+
+        let merged = merge_maps(&teapot, &points, &translation);
+        assert_eq!(merged.len_vertices(), teapot.len_vertices() + 1);
+        assert_eq!(
+            merged.points[teapot.len_vertices()],
+            Vector3::new(1.0, 2.0, 4.0)
+        );
+        assert!(merged.faces.is_none());
+    }
+}
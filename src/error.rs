@@ -6,6 +6,8 @@ pub enum A3dError {
     Io(std::io::Error),
     Parser(String),
     Assertion(String),
+    /// Used when a GPU operation fails or no device is available.
+    Gpu(String),
 }
 
 impl std::fmt::Display for A3dError {
@@ -15,6 +17,7 @@ impl std::fmt::Display for A3dError {
             A3dError::Parser(err) => write!(f, "Parser error: {err}"),
             A3dError::InvalidParameter(err) => write!(f, "Parameter error: {err}"),
             A3dError::Assertion(err) => write!(f, "Assertion err,or: {err}"),
+            A3dError::Gpu(err) => write!(f, "GPU error: {err}"),
         }
     }
 }
@@ -28,6 +31,12 @@ impl A3dError {
     }
 }
 
+impl From<std::io::Error> for A3dError {
+    fn from(err: std::io::Error) -> Self {
+        A3dError::Io(err)
+    }
+}
+
 impl std::error::Error for A3dError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
@@ -35,6 +44,7 @@ impl std::error::Error for A3dError {
             A3dError::Parser(_) => None,
             A3dError::InvalidParameter(_) => None,
             A3dError::Assertion(_) => None,
+            A3dError::Gpu(_) => None,
         }
     }
 }
@@ -0,0 +1,361 @@
+use nalgebra::Vector3;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    error::A3dError,
+    kdtree::R3dTree,
+    pointcloud::{neighborhood_covariance, PointCloud},
+};
+
+/// Number of bins of each of the three angular features of FPFH.
+pub const FPFH_BINS: usize = 11;
+
+/// Fast Point Feature Histogram, the three angular histograms concatenated. Each histogram
+/// sums to about 200: 100 of the point itself plus 100 of its weighted neighbors.
+pub type FpfhFeature = [f32; 3 * FPFH_BINS];
+
+/// Parameters of the FPFH computation.
+#[derive(Debug, Clone, Copy)]
+pub struct FpfhParams {
+    /// Radius of the neighborhood, usually about 5 times the point spacing.
+    pub radius: f32,
+    /// Maximum number of neighbors inside the radius.
+    pub max_neighbors: usize,
+}
+
+impl Default for FpfhParams {
+    fn default() -> Self {
+        Self {
+            radius: 0.1,
+            max_neighbors: 100,
+        }
+    }
+}
+
+/// Angular features of a pair of oriented points, Rusu et al., Fast Point Feature
+/// Histograms (FPFH) for 3D Registration, 2009.
+///
+/// # Returns
+///
+/// The `(theta, alpha, phi)` features, `theta` in `[-pi, pi]` and the others in `[-1, 1]`,
+/// or `None` for coincident points.
+fn pair_features(
+    point: &Vector3<f32>,
+    normal: &Vector3<f32>,
+    other_point: &Vector3<f32>,
+    other_normal: &Vector3<f32>,
+) -> Option<(f32, f32, f32)> {
+    let direction = (other_point - point).try_normalize(1e-8)?;
+
+    // The point whose normal is closer to the direction is the source of the frame.
+    let (u, target_normal, direction, phi) = {
+        let angle = normal.dot(&direction);
+        let other_angle = other_normal.dot(&direction);
+        if angle.abs().acos() > other_angle.abs().acos() {
+            (other_normal, normal, -direction, -other_angle)
+        } else {
+            (normal, other_normal, direction, angle)
+        }
+    };
+    let v = direction.cross(u).try_normalize(1e-8).unwrap_or_default();
+    let w = u.cross(&v);
+
+    let alpha = v.dot(target_normal);
+    let theta = w.dot(target_normal).atan2(u.dot(target_normal));
+    Some((theta, alpha, phi))
+}
+
+fn bin(value: f32, min: f32, max: f32) -> usize {
+    let bin = (FPFH_BINS as f32 * (value - min) / (max - min)).floor() as isize;
+    bin.clamp(0, FPFH_BINS as isize - 1) as usize
+}
+
+/// Simplified Point Feature Histogram, the histograms of the pair features between a point
+/// and its neighbors, each one summing to 100.
+fn compute_spfh(
+    pcl: &PointCloud,
+    normals: &ndarray::Array1<Vector3<f32>>,
+    index: usize,
+    neighbors: &[(usize, f32)],
+) -> FpfhFeature {
+    let mut histogram = [0.0; 3 * FPFH_BINS];
+    if neighbors.is_empty() {
+        return histogram;
+    }
+
+    let increment = 100.0 / neighbors.len() as f32;
+    for &(neighbor, _) in neighbors {
+        if let Some((theta, alpha, phi)) = pair_features(
+            &pcl.points[index],
+            &normals[index],
+            &pcl.points[neighbor],
+            &normals[neighbor],
+        ) {
+            histogram[bin(theta, -std::f32::consts::PI, std::f32::consts::PI)] += increment;
+            histogram[FPFH_BINS + bin(alpha, -1.0, 1.0)] += increment;
+            histogram[2 * FPFH_BINS + bin(phi, -1.0, 1.0)] += increment;
+        }
+    }
+    histogram
+}
+
+/// Parameters of the Intrinsic Shape Signatures keypoint detector.
+#[derive(Debug, Clone, Copy)]
+pub struct IssParams {
+    /// Radius of the neighborhood whose scatter matrix describes a point.
+    pub salient_radius: f32,
+    /// Radius of the non-maximum suppression, keypoints are at least this far apart.
+    pub non_max_radius: f32,
+    /// Maximum ratio between the second and the first largest eigenvalues. Lower values
+    /// reject neighborhoods with an ambiguous principal direction.
+    pub gamma_21: f32,
+    /// Maximum ratio between the third and the second largest eigenvalues.
+    pub gamma_32: f32,
+    /// Minimum number of neighbors inside the salient radius, including the point.
+    pub min_neighbors: usize,
+    /// Maximum number of neighbors searched inside the salient radius.
+    pub max_neighbors: usize,
+}
+
+impl Default for IssParams {
+    fn default() -> Self {
+        Self {
+            salient_radius: 0.05,
+            non_max_radius: 0.05,
+            gamma_21: 0.975,
+            gamma_32: 0.975,
+            min_neighbors: 5,
+            max_neighbors: 100,
+        }
+    }
+}
+
+/// Detects Intrinsic Shape Signatures keypoints, Zhong, Intrinsic Shape Signatures: A
+/// Shape Descriptor for 3D Object Recognition, 2009. A point is salient when the
+/// eigenvalues of its neighborhood scatter are well separated, and its saliency is the
+/// smallest eigenvalue: large at corners and edges, zero on planes. Keypoints are picked
+/// from the most salient point, skipping the ones close to a previous keypoint, so
+/// descriptors and global registration can run on a few hundred points instead of the
+/// whole cloud, e.g., with [`PointCloud::select`].
+///
+/// # Arguments
+///
+/// * pcl - The point cloud.
+/// * params - Parameters of the detection.
+///
+/// # Returns
+///
+/// The indices of the keypoints in increasing order, or error if a radius isn't positive.
+pub fn detect_iss_keypoints(pcl: &PointCloud, params: &IssParams) -> Result<Vec<usize>, A3dError> {
+    if params.salient_radius <= 0.0 || params.non_max_radius <= 0.0 {
+        return Err(A3dError::invalid_parameter(
+            "The ISS radii must be positive.",
+        ));
+    }
+
+    let kdtree = R3dTree::new(&pcl.points.view());
+    let sqr_salient_radius = params.salient_radius * params.salient_radius;
+    let saliencies = (0..pcl.len())
+        .into_par_iter()
+        .map(|index| {
+            let neighbors = kdtree
+                .knn(&pcl.points[index], params.max_neighbors)
+                .into_iter()
+                .filter(|(_, sqr_distance)| *sqr_distance <= sqr_salient_radius)
+                .map(|(neighbor, _)| neighbor)
+                .collect::<Vec<_>>();
+            if neighbors.len() < params.min_neighbors {
+                return None;
+            }
+            let mut eigenvalues = neighborhood_covariance(&pcl.points, &neighbors)?
+                .symmetric_eigenvalues()
+                .as_slice()
+                .to_vec();
+            eigenvalues.sort_by(|a, b| b.total_cmp(a));
+            let [first, second, third] = [eigenvalues[0], eigenvalues[1], eigenvalues[2]];
+            (second < params.gamma_21 * first && third < params.gamma_32 * second)
+                .then_some(third)
+                .filter(|third| *third > 0.0)
+        })
+        .collect::<Vec<_>>();
+
+    // Greedy non-maximum suppression, from the most salient point.
+    let mut candidates = saliencies
+        .iter()
+        .enumerate()
+        .filter_map(|(index, saliency)| saliency.map(|saliency| (index, saliency)))
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    let sqr_non_max_radius = params.non_max_radius * params.non_max_radius;
+    let mut keypoints: Vec<usize> = Vec::new();
+    for (index, _) in candidates {
+        let point = pcl.points[index];
+        if keypoints
+            .iter()
+            .all(|keypoint| (pcl.points[*keypoint] - point).norm_squared() > sqr_non_max_radius)
+        {
+            keypoints.push(index);
+        }
+    }
+    keypoints.sort_unstable();
+    Ok(keypoints)
+}
+
+/// Computes the FPFH descriptor of each point, a rotation invariant description of the
+/// local geometry used to match points between scans without an initial alignment.
+///
+/// # Arguments
+///
+/// * pcl - The point cloud, must have normals.
+/// * params - The neighborhood parameters.
+///
+/// # Returns
+///
+/// One feature per point, all zeros for points without neighbors. Error if the point
+/// cloud has no normals.
+pub fn compute_fpfh(pcl: &PointCloud, params: &FpfhParams) -> Result<Vec<FpfhFeature>, A3dError> {
+    let normals = pcl
+        .normals
+        .as_ref()
+        .ok_or_else(|| A3dError::invalid_parameter("FPFH requires the point normals."))?;
+
+    let kdtree = R3dTree::new(&pcl.points.view());
+    let sqr_radius = params.radius * params.radius;
+    let neighborhoods = (0..pcl.len())
+        .into_par_iter()
+        .map(|index| {
+            kdtree
+                .knn(&pcl.points[index], params.max_neighbors + 1)
+                .into_iter()
+                .filter(|&(neighbor, sqr_distance)| neighbor != index && sqr_distance <= sqr_radius)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let spfhs = (0..pcl.len())
+        .into_par_iter()
+        .map(|index| compute_spfh(pcl, normals, index, &neighborhoods[index]))
+        .collect::<Vec<_>>();
+
+    let features = (0..pcl.len())
+        .into_par_iter()
+        .map(|index| {
+            let mut feature = spfhs[index];
+            let mut neighbors_sum = [0.0; 3 * FPFH_BINS];
+            for &(neighbor, sqr_distance) in &neighborhoods[index] {
+                let weight = 1.0 / sqr_distance.sqrt().max(1e-8);
+                for (sum, value) in neighbors_sum.iter_mut().zip(spfhs[neighbor].iter()) {
+                    *sum += weight * value;
+                }
+            }
+
+            for (histogram, sums) in feature
+                .chunks_mut(FPFH_BINS)
+                .zip(neighbors_sum.chunks(FPFH_BINS))
+            {
+                let total = sums.iter().sum::<f32>();
+                if total > 0.0 {
+                    for (value, sum) in histogram.iter_mut().zip(sums) {
+                        *value += 100.0 * sum / total;
+                    }
+                }
+            }
+            feature
+        })
+        .collect();
+    Ok(features)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rstest::rstest;
+
+    use super::{compute_fpfh, detect_iss_keypoints, FpfhParams, IssParams, FPFH_BINS};
+    use crate::{
+        pointcloud::PointCloud,
+        transform::{TransformBuilder, Transformable},
+        unit_test::sample_teapot_surface,
+    };
+
+    #[rstest]
+    fn test_fpfh_is_rigid_invariant(sample_teapot_surface: PointCloud) {
+        let params = FpfhParams::default();
+        let features = compute_fpfh(&sample_teapot_surface, &params).unwrap();
+        assert_eq!(features.len(), sample_teapot_surface.len());
+
+        let sums = features[0]
+            .chunks(FPFH_BINS)
+            .map(|histogram| histogram.iter().sum::<f32>())
+            .collect::<Vec<_>>();
+        sums.iter()
+            .for_each(|sum| assert!((sum - 200.0).abs() < 1e-2));
+
+        let transform = TransformBuilder::default()
+            .translation(Vector3::new(1.0, -2.0, 0.5))
+            .axis_angle(Vector3::x_axis(), 1.2)
+            .build();
+        let moved = compute_fpfh(&transform.transform(&sample_teapot_surface), &params).unwrap();
+        let max_difference = features
+            .iter()
+            .zip(moved.iter())
+            .flat_map(|(a, b)| a.iter().zip(b.iter()).map(|(a, b)| (a - b).abs()))
+            .fold(0.0f32, f32::max);
+        // Only neighbors on the bin edges may change bins by rounding.
+        assert!(max_difference < 5.0, "{max_difference}");
+
+        let mut without_normals = transform.transform(&sample_teapot_surface);
+        without_normals.normals = None;
+        assert!(compute_fpfh(&without_normals, &params).is_err());
+    }
+
+    #[rstest]
+    fn test_detect_iss_keypoints(sample_teapot_surface: PointCloud) {
+        let params = IssParams::default();
+        let keypoints = detect_iss_keypoints(&sample_teapot_surface, &params).unwrap();
+        assert!(!keypoints.is_empty());
+        assert!(keypoints.len() < sample_teapot_surface.len() / 10);
+        assert!(keypoints.windows(2).all(|pair| pair[0] < pair[1]));
+        for (i, a) in keypoints.iter().enumerate() {
+            for b in &keypoints[i + 1..] {
+                let distance =
+                    (sample_teapot_surface.points[*a] - sample_teapot_surface.points[*b]).norm();
+                assert!(distance > params.non_max_radius);
+            }
+        }
+
+        // The saliency only depends on the shape.
+        let transform = TransformBuilder::default()
+            .translation(Vector3::new(1.0, -2.0, 0.5))
+            .axis_angle(Vector3::x_axis(), 1.2)
+            .build();
+        let moved =
+            detect_iss_keypoints(&transform.transform(&sample_teapot_surface), &params).unwrap();
+        let common = keypoints
+            .iter()
+            .filter(|index| moved.contains(index))
+            .count();
+        assert!(
+            common * 10 > keypoints.len() * 9,
+            "{common}/{}",
+            keypoints.len()
+        );
+
+        let sparser = detect_iss_keypoints(
+            &sample_teapot_surface,
+            &IssParams {
+                non_max_radius: 0.15,
+                ..params
+            },
+        )
+        .unwrap();
+        assert!(sparser.len() < keypoints.len());
+        assert!(detect_iss_keypoints(
+            &sample_teapot_surface,
+            &IssParams {
+                salient_radius: 0.0,
+                ..params
+            }
+        )
+        .is_err());
+    }
+}
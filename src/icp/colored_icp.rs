@@ -0,0 +1,306 @@
+use std::ops::ControlFlow;
+
+use nalgebra::Vector3;
+use ndarray::Array2;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use super::{
+    cost_function::ColorDistance,
+    icp_params::IcpParams,
+    icp_result::{IcpResult, IterationCallback, IterationInfo, IterationTracker},
+};
+use crate::{
+    extra_math,
+    optim::{GaussNewton, RobustLoss},
+    range_image::RangeImage,
+    transform::{LieGroup, Transform},
+};
+
+/// Colored ICP from Park et al., Colored Point Cloud Registration Revisited, ICCV 2017.
+///
+/// The color of each target point is modeled as a linear function on its tangent plane,
+/// whose gradient is the intensity map gradient lifted into 3D. Source points are compared
+/// against that function instead of the color at their projection, which locks the
+/// alignment on surfaces where geometry alone slides, e.g., flat textured walls.
+/// The geometric and color terms are weighted by [`IcpParams::weight`] and
+/// [`IcpParams::color_weight`], the paper uses 0.968 and 0.032.
+pub struct ColoredIcp<'target_lt> {
+    pub params: IcpParams,
+    pub initial_transform: Transform,
+    target: &'target_lt RangeImage,
+    /// Color gradient of each target pixel on its tangent plane.
+    color_gradients: Array2<Vector3<f32>>,
+}
+
+impl<'target_lt> ColoredIcp<'target_lt> {
+    /// Creates the colored ICP, computing the target color gradients.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The ICP parameters.
+    /// * `target` - The target image. It must have normals and an intensity map.
+    pub fn new(params: IcpParams, target: &'target_lt RangeImage) -> Self {
+        let intensity_map = target
+            .intensity_map
+            .as_ref()
+            .expect("Please, the target image should have a intensity map.");
+        let normals = target
+            .normals
+            .as_ref()
+            .expect("Please, the target image should have normals.");
+        let (fx, fy) = (target.intrinsics.fx as f32, target.intrinsics.fy as f32);
+
+        let color_gradients = Array2::from_shape_fn(target.points.dim(), |(row, col)| {
+            if target.mask[(row, col)] == 0 {
+                return Vector3::zeros();
+            }
+            let point = target.points[(row, col)];
+            let normal = normals[(row, col)];
+            let (_, du, dv) = intensity_map.bilinear_grad(col as f32, row as f32);
+
+            // Chain rule through the projection, then onto the tangent plane.
+            let z = point[2];
+            let gradient = Vector3::new(
+                du * fx / z,
+                dv * fy / z,
+                -(du * fx * point[0] + dv * fy * point[1]) / (z * z),
+            );
+            gradient - normal * gradient.dot(&normal)
+        });
+
+        Self {
+            params,
+            initial_transform: Transform::eye(),
+            target,
+            color_gradients,
+        }
+    }
+
+    /// Aligns the source image to the target image.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source image. It must have intensities.
+    ///
+    /// # Returns
+    ///
+    /// * The transformation that aligns the source image to the target image, the inliers
+    ///   are the geometric residual terms.
+    pub fn align(&self, source: &RangeImage) -> IcpResult {
+        self.align_impl(source, None)
+    }
+
+    /// Same as `align`, reporting the progress of every iteration to a callback that can
+    /// abort the run.
+    ///
+    /// # Arguments
+    ///
+    /// * source - Same as `align`.
+    /// * callback - Called after every iteration, see [`IterationCallback`].
+    pub fn align_with_callback(
+        &self,
+        source: &RangeImage,
+        mut callback: impl FnMut(IterationInfo) -> ControlFlow<()>,
+    ) -> IcpResult {
+        self.align_impl(source, Some(&mut callback))
+    }
+
+    fn align_impl<'a>(
+        &self,
+        source: &RangeImage,
+        callback: Option<&'a mut IterationCallback<'a>>,
+    ) -> IcpResult {
+        let intensity_map = self.target.intensity_map.as_ref().unwrap();
+        let target_normals = self.target.normals.as_ref().unwrap();
+        let source_intensities = source
+            .intensities
+            .as_ref()
+            .expect("Please, the source image should have intensity colors.");
+
+        let max_distance_sqr = self.params.max_distance * self.params.max_distance;
+        let max_color_distance_sqr =
+            self.params.max_color_distance * self.params.max_color_distance;
+        let (target_width, target_height) =
+            (self.target.width() as i32, self.target.height() as i32);
+        let color_distance = ColorDistance {};
+
+        let mut optim_transform = self.initial_transform.clone();
+        let mut tracker =
+            IterationTracker::new(&self.params, &optim_transform).with_callback(callback);
+        for _ in 0..self.params.max_iterations {
+            let (mut geom_optim, color_optim) = (0..source.len())
+                .into_par_iter()
+                .fold(
+                    || (GaussNewton::<6>::new(), GaussNewton::<6>::new()),
+                    |(mut geom_optim, mut color_optim), index| {
+                        let (row, col) = (index / source.width(), index % source.width());
+                        if source.mask[(row, col)] == 0 {
+                            return (geom_optim, color_optim);
+                        }
+
+                        let p = optim_transform.transform_vector(&source.points[(row, col)]);
+                        let (u, v) = self.target.intrinsics.project(&p);
+                        let (u, v) = ((u + 0.5).floor() as i32, (v + 0.5).floor() as i32);
+                        if u < 0 || v < 0 || u >= target_width || v >= target_height {
+                            return (geom_optim, color_optim);
+                        }
+                        let (u, v) = (u as usize, v as usize);
+                        let Some(target_point) = self.target.get_point(v, u) else {
+                            return (geom_optim, color_optim);
+                        };
+                        if (target_point - p).norm_squared() > max_distance_sqr {
+                            return (geom_optim, color_optim);
+                        }
+
+                        let target_normal = target_normals[(v, u)];
+                        if let Some(source_normals) = &source.normals {
+                            let source_normal =
+                                optim_transform.transform_normal(&source_normals[(row, col)]);
+                            if extra_math::angle_between_normals(&source_normal, &target_normal)
+                                >= self.params.max_normal_angle
+                            {
+                                return (geom_optim, color_optim);
+                            }
+                        }
+
+                        self.params.geometric_cost.step(
+                            &mut geom_optim,
+                            &p,
+                            &target_point,
+                            &target_normal,
+                            self.params.weight,
+                            self.params.robust_loss.as_ref(),
+                        );
+
+                        // The color that the target tangent plane predicts at the source point.
+                        let color_gradient = self.color_gradients[(v, u)];
+                        let target_color = intensity_map.bilinear(u as f32, v as f32)
+                            + color_gradient.dot(&(p - target_point));
+                        let source_color = source_intensities[index] as f32 * 0.003_921_569; // / 255.0;
+                        let (color_residual, color_jacobian) = color_distance.jacobian(
+                            &p,
+                            &color_gradient,
+                            source_color,
+                            target_color,
+                        );
+                        if color_residual * color_residual <= max_color_distance_sqr {
+                            let robust_weight = self
+                                .params
+                                .color_robust_loss
+                                .map_or(1.0, |loss| loss.weight(color_residual));
+                            color_optim.weighted_step(
+                                color_residual,
+                                &color_jacobian,
+                                self.params.color_weight * robust_weight,
+                            );
+                        }
+                        (geom_optim, color_optim)
+                    },
+                )
+                .reduce(
+                    || (GaussNewton::<6>::new(), GaussNewton::<6>::new()),
+                    |(mut geom_optim, mut color_optim), (geom_other, color_other)| {
+                        geom_optim.add(&geom_other);
+                        color_optim.add(&color_other);
+                        (geom_optim, color_optim)
+                    },
+                );
+
+            let num_residuals = geom_optim.count();
+            // Weighted per residual, as in the paper's objective.
+            geom_optim.add(&color_optim);
+            let residual = geom_optim.mean_squared_residual();
+            let Some(update) = geom_optim.solve() else {
+                break;
+            };
+            optim_transform = &Transform::exp(&LieGroup::Se3(update)) * &optim_transform;
+            tracker.record(&optim_transform, &geom_optim, num_residuals);
+            if tracker.should_stop(&update, residual) {
+                break;
+            }
+        }
+
+        tracker.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rstest::rstest;
+
+    use super::ColoredIcp;
+    use crate::{
+        camera::CameraIntrinsics,
+        icp::icp_params::IcpParams,
+        metrics::TransformMetrics,
+        range_image::RangeImage,
+        transform::Transform,
+        unit_test::{sample_range_img_ds2, TestRangeImageDataset},
+    };
+
+    /// A textured wall at 1 meter seen from a camera shifted by `offset`.
+    fn textured_wall(offset: &Vector3<f32>) -> RangeImage {
+        let camera = CameraIntrinsics::from_simple_intrinsic(100.0, 100.0, 40.0, 30.0, 80, 60);
+        let mut image = RangeImage::from_intrinsics_fn(
+            &camera,
+            |row, col| Some(camera.backproject(col as f32, row as f32, 1.0)),
+            |_, _| Some(Vector3::new(0.0, 0.0, -1.0)),
+            |row, col| {
+                let world = camera.backproject(col as f32, row as f32, 1.0) + offset;
+                let value = 127.0 + 60.0 * (world[0] * 25.0).sin() + 60.0 * (world[1] * 20.0).cos();
+                Some(Vector3::repeat(value as u8))
+            },
+        );
+        image.compute_intensity().compute_intensity_map();
+        image
+    }
+
+    #[test]
+    fn test_flat_wall() {
+        let target = textured_wall(&Vector3::zeros());
+        let offset = Vector3::new(0.02, 0.01, 0.0);
+        let source = textured_wall(&offset);
+        let expected = Transform::new(&offset, &nalgebra::Quaternion::identity());
+
+        let params = IcpParams {
+            max_iterations: 30,
+            weight: 0.968,
+            color_weight: 0.032,
+            max_color_distance: 1.0,
+            ..Default::default()
+        };
+        let colored = ColoredIcp::new(params, &target).align(&source).transform;
+        assert!(TransformMetrics::new(&colored, &expected).translation < 2e-3);
+
+        // Geometry alone can't tell where the camera is along the wall.
+        let geometric = ColoredIcp::new(
+            IcpParams {
+                color_weight: 0.0,
+                ..params
+            },
+            &target,
+        )
+        .align(&source)
+        .transform;
+        assert!(TransformMetrics::new(&geometric, &expected).translation > 1e-2);
+    }
+
+    #[rstest]
+    fn test_align(sample_range_img_ds2: TestRangeImageDataset) {
+        let rimage0 = sample_range_img_ds2.get(0).unwrap();
+        let rimage1 = sample_range_img_ds2.get(1).unwrap();
+        let gt_transform = sample_range_img_ds2.get_ground_truth(1, 0);
+
+        let actual = ColoredIcp::new(
+            IcpParams {
+                max_iterations: 10,
+                ..Default::default()
+            },
+            &rimage0,
+        )
+        .align(&rimage1)
+        .transform;
+        assert!(TransformMetrics::new(&actual, &gt_transform).angle.abs() < 0.01);
+    }
+}
@@ -1,4 +1,7 @@
-use nalgebra::Vector3;
+use nalgebra::{Matrix3, Vector3};
+
+use super::icp_params::GeometricCost;
+use crate::optim::{GaussNewton, RobustLoss};
 
 pub struct PointPlaneDistance {}
 
@@ -39,6 +42,115 @@ impl PointPlaneDistance {
     }
 }
 
+pub struct PointPointDistance {}
+
+impl PointPointDistance {
+    /// Computes the residuals and the Jacobians of the point-point distance, one per axis.
+    ///
+    /// # Arguments
+    ///
+    /// * source_point - 3D point in the source frame.
+    /// * target_point - 3D point in the target frame.
+    pub fn jacobian(
+        &self,
+        source_point: &Vector3<f32>,
+        target_point: &Vector3<f32>,
+    ) -> [(f32, [f32; 6]); 3] {
+        let difference = target_point - source_point;
+        [0, 1, 2].map(|axis| {
+            let direction = Vector3::ith(axis, 1.0);
+            (difference[axis], se3_jacobian(source_point, &direction))
+        })
+    }
+}
+
+/// Distribution-to-distribution distance of Generalized ICP, which models each point as a
+/// Gaussian of its local surface.
+pub struct DistributionDistance {}
+
+impl DistributionDistance {
+    /// Computes the residuals and the Jacobians of the point distance whitened by the
+    /// combined covariance, one per axis.
+    ///
+    /// # Arguments
+    ///
+    /// * source_point - 3D point in the source frame.
+    /// * target_point - 3D point in the target frame.
+    /// * combined_covariance - Target covariance plus the rotated source covariance.
+    ///
+    /// # Returns
+    ///
+    /// The residuals and Jacobians, or `None` if the covariance is singular.
+    pub fn jacobian(
+        &self,
+        source_point: &Vector3<f32>,
+        target_point: &Vector3<f32>,
+        combined_covariance: &Matrix3<f32>,
+    ) -> Option<[(f32, [f32; 6]); 3]> {
+        // Information = L * L^T, so the residual L^T * d has unit covariance.
+        let whitening = combined_covariance.try_inverse()?.cholesky()?.l();
+        let difference = target_point - source_point;
+        Some([0, 1, 2].map(|axis| {
+            let direction = whitening.column(axis).into_owned();
+            (
+                direction.dot(&difference),
+                se3_jacobian(source_point, &direction),
+            )
+        }))
+    }
+}
+
+impl GeometricCost {
+    /// Adds the residuals of a correspondence into the optimizer.
+    ///
+    /// # Arguments
+    ///
+    /// * optimizer - The Gauss-Newton optimizer.
+    /// * source_point - 3D point in the source frame.
+    /// * target_point - 3D point in the target frame.
+    /// * target_normal - Normal of the target point, unused by point-to-point.
+    /// * weight - Weight of the correspondence.
+    /// * robust_loss - Robust loss of the residuals, `None` for least squares.
+    pub(super) fn step(
+        &self,
+        optimizer: &mut GaussNewton<6>,
+        source_point: &Vector3<f32>,
+        target_point: &Vector3<f32>,
+        target_normal: &Vector3<f32>,
+        weight: f32,
+        robust_loss: Option<&impl RobustLoss>,
+    ) {
+        let weight = robust_loss.map_or(weight, |loss| {
+            let difference = target_point - source_point;
+            let distance = match self {
+                GeometricCost::PointToPlane => difference.dot(target_normal),
+                _ => difference.norm(),
+            };
+            weight * loss.weight(distance)
+        });
+        let (plane_weight, point_weight) = match self {
+            GeometricCost::PointToPoint => (0.0, 1.0),
+            GeometricCost::PointToPlane => (1.0, 0.0),
+            GeometricCost::Hybrid {
+                point_to_point_weight,
+            } => (1.0, *point_to_point_weight),
+        };
+
+        if plane_weight > 0.0 {
+            let point_plane = PointPlaneDistance {};
+            let (residual, jacobian) =
+                point_plane.jacobian(source_point, target_point, target_normal);
+            optimizer.weighted_step(residual, &jacobian, weight * plane_weight);
+        }
+        if point_weight > 0.0 {
+            let point_point = PointPointDistance {};
+            for (residual, jacobian) in point_point.jacobian(source_point, target_point) {
+                optimizer.weighted_step(residual, &jacobian, weight * point_weight);
+            }
+        }
+    }
+}
+
 pub struct ColorDistance {}
 
 impl ColorDistance {
@@ -55,3 +167,29 @@ impl ColorDistance {
         )
     }
 }
+
+pub struct DepthDistance {}
+
+impl DepthDistance {
+    /// Computes the residual and the Jacobian of the difference between the target depth at
+    /// the projection of a point and the point depth, as in Kerl et al., Dense Visual SLAM
+    /// for RGB-D Cameras, 2013.
+    ///
+    /// # Arguments
+    ///
+    /// * source_point - 3D point in the target frame.
+    /// * depth_gradient - Gradient of the target depth at the projection, chained with the
+    ///   projection Jacobian.
+    /// * target_depth - Target depth at the projection.
+    pub fn jacobian(
+        &self,
+        source_point: &Vector3<f32>,
+        depth_gradient: &Vector3<f32>,
+        target_depth: f32,
+    ) -> (f32, [f32; 6]) {
+        (
+            target_depth - source_point[2],
+            se3_jacobian(source_point, &(Vector3::z() - depth_gradient)),
+        )
+    }
+}
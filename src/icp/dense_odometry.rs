@@ -0,0 +1,467 @@
+use itertools::izip;
+use nalgebra::Vector3;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use super::{
+    cost_function::{ColorDistance, DepthDistance},
+    icp_params::MsIcpParams,
+    icp_result::{IcpResult, IterationTracker},
+};
+use crate::{
+    error::A3dError,
+    optim::GaussNewton,
+    range_image::RangeImage,
+    transform::{LieGroup, Transform},
+};
+
+/// How the photometric term of [`DenseOdometry`] is linearized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PhotometricFormulation {
+    /// Warps the source pixels into the target and differentiates the target image at the
+    /// warped points, so the Jacobians are recomputed every iteration.
+    #[default]
+    Forward,
+    /// Warps the target pixels into the source and differentiates the target image at its
+    /// own pixels (Baker and Matthews, Lucas-Kanade 20 Years On, 2004). The Jacobians are
+    /// computed once per pyramid level, which makes iterations cheaper. The source levels
+    /// must then have intensity maps.
+    InverseCompositional,
+}
+
+/// Parameters of the dense RGB-D odometry.
+#[derive(Debug, Clone)]
+pub struct DenseOdometryParams {
+    /// Parameters of each pyramid level, index 0 is the finest. Only the maximum iterations,
+    /// the termination and the information estimate are used.
+    pub levels: MsIcpParams,
+    /// Weight of the depth residuals relative to the intensity ones. Both are normalized
+    /// by their scales, so 1 weights them equally, and 0 is purely photometric.
+    pub depth_weight: f32,
+    /// Degrees of freedom of the Student-t distribution of the residuals, which weights
+    /// down outliers. 5 as in DVO.
+    pub dof: f32,
+    /// Maximum difference between the depth of a point and the target depth at its
+    /// projection, in meters, and maximum depth variation of the interpolated pixels.
+    pub max_depth_difference: f32,
+    /// Minimum magnitude of the target intensity gradient, per pixel, of the pixels used
+    /// by the photometric term. Flat regions add noise and no constraint.
+    pub min_gradient: f32,
+    /// Linearization of the photometric term. The depth term is always forward.
+    pub formulation: PhotometricFormulation,
+}
+
+impl Default for DenseOdometryParams {
+    fn default() -> Self {
+        Self {
+            levels: MsIcpParams::default(),
+            depth_weight: 1.0,
+            dof: 5.0,
+            max_depth_difference: 0.1,
+            min_gradient: 0.01,
+            formulation: PhotometricFormulation::Forward,
+        }
+    }
+}
+
+type Residual = (f32, [f32; 6]);
+
+/// A target pixel of the inverse-compositional photometric term: its point, intensity and
+/// Jacobian.
+type ReferencePixel = (Vector3<f32>, f32, [f32; 6]);
+
+/// Target depth and its gradient with bilinear interpolation, `None` if a neighbor pixel
+/// is invalid or the neighborhood crosses a depth discontinuity.
+fn depth_bilinear_grad(
+    image: &RangeImage,
+    u: f32,
+    v: f32,
+    max_depth_difference: f32,
+) -> Option<(f32, f32, f32)> {
+    let (col, row) = (u as usize, v as usize);
+    let mut depths = [0.0; 4];
+    for (depth, (row, col)) in depths.iter_mut().zip([
+        (row, col),
+        (row, col + 1),
+        (row + 1, col),
+        (row + 1, col + 1),
+    ]) {
+        *depth = image.get_point(row, col)?[2];
+    }
+    let [depth00, depth10, depth01, depth11] = depths;
+    let (min, max) = depths
+        .iter()
+        .fold((f32::INFINITY, 0.0f32), |(min, max), depth| {
+            (min.min(*depth), max.max(*depth))
+        });
+    if max - min > max_depth_difference {
+        return None;
+    }
+
+    let (u_frac, v_frac) = (u - col as f32, v - row as f32);
+    let top = depth00 + (depth10 - depth00) * u_frac;
+    let bottom = depth01 + (depth11 - depth01) * u_frac;
+    let grad_u = (depth10 - depth00) * (1.0 - v_frac) + (depth11 - depth01) * v_frac;
+    Some((top + (bottom - top) * v_frac, grad_u, bottom - top))
+}
+
+/// Squared scale of the Student-t distribution fitted to the residuals, by the iteratively
+/// reweighted estimate of Kerl et al., Robust Odometry Estimation for RGB-D Cameras, 2013.
+fn t_distribution_sqr_scale(residuals: &[Residual], dof: f32) -> f32 {
+    if residuals.is_empty() {
+        return 1.0;
+    }
+    let count = residuals.len() as f32;
+    let mut sqr_scale = residuals.iter().map(|(r, _)| r * r).sum::<f32>() / count;
+    for _ in 0..10 {
+        if sqr_scale <= f32::EPSILON {
+            return f32::EPSILON;
+        }
+        let next = residuals
+            .iter()
+            .map(|(r, _)| r * r * (dof + 1.0) / (dof + r * r / sqr_scale))
+            .sum::<f32>()
+            / count;
+        let converged = (next - sqr_scale).abs() < 1e-3 * sqr_scale;
+        sqr_scale = next;
+        if converged {
+            break;
+        }
+    }
+    sqr_scale.max(f32::EPSILON)
+}
+
+/// Dense RGB-D odometry in the style of DVO (Kerl et al., 2013): instead of matching
+/// points, it warps the source pixels into the target and minimizes, jointly, the
+/// differences of their intensities and of their depths, coarse to fine over image
+/// pyramids. The residuals are weighted by a Student-t distribution whose scale is
+/// re-estimated every iteration.
+///
+/// It complements ICP on scenes with little geometry but texture, e.g., a poster on a
+/// wall, and needs small motions between frames.
+pub struct DenseOdometry<'pyramid_lt> {
+    params: DenseOdometryParams,
+    target_pyramid: &'pyramid_lt [RangeImage],
+}
+
+impl<'pyramid_lt> DenseOdometry<'pyramid_lt> {
+    /// Creates a new dense odometry instance.
+    ///
+    /// # Arguments
+    ///
+    /// * params - The odometry parameters.
+    /// * target_pyramid - The target pyramid, each level must have an intensity map.
+    ///
+    /// # Returns
+    ///
+    /// * Ok(DenseOdometry)
+    /// * Err(Error(InvalidParameter)) if the number of pyramid levels and of level
+    ///   parameters differ, or a target level has no intensity map.
+    pub fn new(
+        params: DenseOdometryParams,
+        target_pyramid: &'pyramid_lt [RangeImage],
+    ) -> Result<Self, A3dError> {
+        if params.levels.len() != target_pyramid.len() {
+            return Err(A3dError::invalid_parameter(
+                "The number of range images pyramid levels and level parameters must be equal.",
+            ));
+        }
+        if target_pyramid
+            .iter()
+            .any(|image| image.intensity_map.is_none())
+        {
+            return Err(A3dError::invalid_parameter(
+                "The target pyramid levels must have intensity maps.",
+            ));
+        }
+
+        Ok(Self {
+            params,
+            target_pyramid,
+        })
+    }
+
+    /// Target pixels of the inverse-compositional photometric term, with their Jacobians
+    /// at the identity.
+    fn reference_pixels(&self, target: &RangeImage) -> Vec<ReferencePixel> {
+        let intensity_map = target.intensity_map.as_ref().unwrap();
+        (1..target.height() - 1)
+            .into_par_iter()
+            .flat_map_iter(|row| {
+                (1..target.width() - 1).filter_map(move |col| {
+                    let point = target.get_point(row, col)?;
+                    // Central differences, the pixels are sampled at their centers.
+                    let (u, v) = (col as f32, row as f32);
+                    let intensity = intensity_map.bilinear(u, v);
+                    let du = 0.5
+                        * (intensity_map.bilinear(u + 1.0, v) - intensity_map.bilinear(u - 1.0, v));
+                    let dv = 0.5
+                        * (intensity_map.bilinear(u, v + 1.0) - intensity_map.bilinear(u, v - 1.0));
+                    if du.hypot(dv) < self.params.min_gradient {
+                        return None;
+                    }
+                    let ((dfx, dcx), (dfy, dcy)) = target.intrinsics.project_grad(&point);
+                    let gradient = Vector3::new(du * dfx, dv * dfy, du * dcx + dv * dcy);
+                    let (_, jacobian) =
+                        ColorDistance {}.jacobian(&point, &gradient, 0.0, intensity);
+                    Some((point, intensity, jacobian))
+                })
+            })
+            .collect()
+    }
+
+    /// Inverse-compositional photometric residuals of the target pixels warped into the
+    /// source. Pixels occluded in the source are skipped.
+    fn inverse_photometric_residuals(
+        &self,
+        reference: &[ReferencePixel],
+        source: &RangeImage,
+        transform: &Transform,
+    ) -> Vec<Residual> {
+        let intensity_map = source.intensity_map.as_ref().expect(
+            "Please, the source images should have intensity maps for the inverse-compositional formulation.",
+        );
+        let inverse = transform.inverse();
+        let (max_u, max_v) = ((source.width() - 1) as f32, (source.height() - 1) as f32);
+        reference
+            .into_par_iter()
+            .filter_map(|(target_point, target_intensity, jacobian)| {
+                let point = inverse.transform_vector(target_point);
+                if point[2] <= 0.0 {
+                    return None;
+                }
+                let (u, v) = source.intrinsics.project(&point);
+                if !(0.0..max_u).contains(&u) || !(0.0..max_v).contains(&v) {
+                    return None;
+                }
+                let source_point = source.get_point((v + 0.5) as usize, (u + 0.5) as usize)?;
+                if (source_point[2] - point[2]).abs() > self.params.max_depth_difference {
+                    return None;
+                }
+                Some((intensity_map.bilinear(u, v) - target_intensity, *jacobian))
+            })
+            .collect()
+    }
+
+    /// Photometric, if `forward_photometric`, and depth residuals of the source pixels
+    /// warped by a transformation.
+    fn residuals(
+        &self,
+        target: &RangeImage,
+        source: &RangeImage,
+        transform: &Transform,
+        forward_photometric: bool,
+    ) -> (Vec<Residual>, Vec<Residual>) {
+        let intensity_map = target.intensity_map.as_ref().unwrap();
+        let source_intensities = source
+            .intensities
+            .as_ref()
+            .expect("Please, the source images should have intensities.");
+        let (max_u, max_v) = ((target.width() - 1) as f32, (target.height() - 1) as f32);
+        let color_distance = ColorDistance {};
+        let depth_distance = DepthDistance {};
+
+        (0..source.height())
+            .into_par_iter()
+            .fold(
+                || (Vec::new(), Vec::new()),
+                |(mut photometric, mut depth), row| {
+                    for col in 0..source.width() {
+                        let Some(source_point) = source.get_point(row, col) else {
+                            continue;
+                        };
+                        let point = transform.transform_vector(&source_point);
+                        if point[2] <= 0.0 {
+                            continue;
+                        }
+                        let (u, v) = target.intrinsics.project(&point);
+                        if !(0.0..max_u).contains(&u) || !(0.0..max_v).contains(&v) {
+                            continue;
+                        }
+                        let ((dfx, dcx), (dfy, dcy)) = target.intrinsics.project_grad(&point);
+
+                        if forward_photometric {
+                            let (target_intensity, du, dv) = intensity_map.bilinear_grad(u, v);
+                            if du.hypot(dv) >= self.params.min_gradient {
+                                let source_intensity =
+                                    source_intensities[row * source.width() + col] as f32
+                                        * 0.003_921_569; // / 255.0;
+                                let gradient =
+                                    Vector3::new(du * dfx, dv * dfy, du * dcx + dv * dcy);
+                                photometric.push(color_distance.jacobian(
+                                    &point,
+                                    &gradient,
+                                    source_intensity,
+                                    target_intensity,
+                                ));
+                            }
+                        }
+
+                        if self.params.depth_weight > 0.0 {
+                            if let Some((target_depth, du, dv)) =
+                                depth_bilinear_grad(target, u, v, self.params.max_depth_difference)
+                            {
+                                let gradient =
+                                    Vector3::new(du * dfx, dv * dfy, du * dcx + dv * dcy);
+                                let residual =
+                                    depth_distance.jacobian(&point, &gradient, target_depth);
+                                if residual.0.abs() <= self.params.max_depth_difference {
+                                    depth.push(residual);
+                                }
+                            }
+                        }
+                    }
+                    (photometric, depth)
+                },
+            )
+            .reduce(
+                || (Vec::new(), Vec::new()),
+                |(mut photometric, mut depth), (other_photometric, other_depth)| {
+                    photometric.extend(other_photometric);
+                    depth.extend(other_depth);
+                    (photometric, depth)
+                },
+            )
+    }
+
+    /// Aligns the source pyramid to the target pyramid.
+    ///
+    /// # Arguments
+    ///
+    /// * source_pyramid - The source pyramid, each level must have intensities.
+    ///
+    /// # Returns
+    ///
+    /// * The result of the finest level, with the iterations of all levels. The inliers
+    ///   are the photometric plus the depth residuals.
+    pub fn align(&self, source_pyramid: &[RangeImage]) -> IcpResult {
+        let mut optim_transform = Transform::eye();
+        let mut iterations = 0;
+        let mut result = None;
+
+        for (params, target, source) in izip!(
+            self.params.levels.iter(),
+            self.target_pyramid.iter(),
+            source_pyramid.iter()
+        )
+        .rev()
+        {
+            let reference = match self.params.formulation {
+                PhotometricFormulation::Forward => None,
+                PhotometricFormulation::InverseCompositional => Some(self.reference_pixels(target)),
+            };
+
+            let mut tracker = IterationTracker::new(params, &optim_transform);
+            let mut optimizer = GaussNewton::<6>::new();
+            for _ in 0..params.max_iterations {
+                let (photometric, depth) = match &reference {
+                    None => self.residuals(target, source, &optim_transform, true),
+                    Some(reference) => {
+                        // Only the depth term needs the source pixels to be warped.
+                        let depth = if self.params.depth_weight > 0.0 {
+                            self.residuals(target, source, &optim_transform, false).1
+                        } else {
+                            Vec::new()
+                        };
+                        let photometric =
+                            self.inverse_photometric_residuals(reference, source, &optim_transform);
+                        (photometric, depth)
+                    }
+                };
+                for (residuals, term_weight) in
+                    [(&photometric, 1.0), (&depth, self.params.depth_weight)]
+                {
+                    let sqr_scale = t_distribution_sqr_scale(residuals, self.params.dof);
+                    for (residual, jacobian) in residuals {
+                        let weight = (self.params.dof + 1.0)
+                            / (self.params.dof + residual * residual / sqr_scale)
+                            / sqr_scale;
+                        optimizer.weighted_step(*residual, jacobian, term_weight * weight);
+                    }
+                }
+
+                let residual = optimizer.mean_squared_residual();
+                let Some(update) = optimizer.solve() else {
+                    break;
+                };
+                optim_transform = &Transform::exp(&LieGroup::Se3(update)) * &optim_transform;
+                tracker.record(
+                    &optim_transform,
+                    &optimizer,
+                    photometric.len() + depth.len(),
+                );
+                optimizer.reset();
+                if tracker.should_stop(&update, residual) {
+                    break;
+                }
+            }
+
+            let level_result = tracker.finish();
+            iterations += level_result.iterations;
+            optim_transform = level_result.transform.clone();
+            result = Some(level_result);
+        }
+
+        let mut result = result.expect("The pyramid should have at least one level.");
+        result.iterations = iterations;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{DenseOdometry, DenseOdometryParams, PhotometricFormulation};
+    use crate::{
+        icp::{IcpParams, MsIcpParams},
+        metrics::TransformMetrics,
+        range_image::RangeImage,
+        unit_test::{sample_range_img_ds2, TestRangeImageDataset},
+    };
+
+    fn pyramid(image: RangeImage) -> Vec<RangeImage> {
+        let mut pyramid = image.pyramid(3, 1.0);
+        for level in pyramid.iter_mut() {
+            level.compute_intensity_map();
+        }
+        pyramid
+    }
+
+    #[rstest]
+    #[case(1.0, PhotometricFormulation::Forward)]
+    #[case(0.0, PhotometricFormulation::Forward)]
+    #[case(1.0, PhotometricFormulation::InverseCompositional)]
+    #[case(0.0, PhotometricFormulation::InverseCompositional)]
+    fn test_align(
+        sample_range_img_ds2: TestRangeImageDataset,
+        #[case] depth_weight: f32,
+        #[case] formulation: PhotometricFormulation,
+    ) {
+        let target = pyramid(sample_range_img_ds2.get(0).unwrap());
+        let source = pyramid(sample_range_img_ds2.get(1).unwrap());
+        let gt_transform = sample_range_img_ds2.get_ground_truth(1, 0);
+
+        let params = DenseOdometryParams {
+            depth_weight,
+            formulation,
+            ..Default::default()
+        };
+        let result = DenseOdometry::new(params, &target).unwrap().align(&source);
+        let metrics = TransformMetrics::new(&result.transform, &gt_transform);
+        assert!(metrics.angle < 0.01, "{metrics}");
+        assert!(metrics.translation < 0.01, "{metrics}");
+    }
+
+    #[rstest]
+    fn test_invalid_pyramid(sample_range_img_ds2: TestRangeImageDataset) {
+        let without_intensity = sample_range_img_ds2.get(0).unwrap().pyramid(3, 1.0);
+        assert!(DenseOdometry::new(DenseOdometryParams::default(), &without_intensity).is_err());
+
+        let target = pyramid(sample_range_img_ds2.get(0).unwrap());
+        let params = DenseOdometryParams {
+            levels: MsIcpParams::repeat(2, &IcpParams::default()),
+            ..Default::default()
+        };
+        assert!(DenseOdometry::new(params, &target).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use nalgebra::Vector3;
+use rand::Rng;
+
+use super::{
+    cost_function::PointPointDistance,
+    global_registration::{match_features, GlobalRegistrationResult},
+};
+use crate::{
+    error::A3dError,
+    features::{compute_fpfh, FpfhParams},
+    optim::{GaussNewton, RobustKernel, RobustLoss},
+    pointcloud::PointCloud,
+    random::RandomState,
+    transform::{LieGroup, Transform},
+};
+
+/// Parameters of the Fast Global Registration.
+#[derive(Debug, Clone, Copy)]
+pub struct FgrParams {
+    /// Parameters of the FPFH features.
+    pub feature: FpfhParams,
+    /// Final scale of the Geman-McClure kernel, residuals beyond it are mostly ignored
+    /// at the end of the optimization.
+    pub max_correspondence_distance: f32,
+    /// Number of Gauss-Newton iterations.
+    pub max_iterations: usize,
+    /// The squared kernel scale is divided by this every 4 iterations (graduated
+    /// non-convexity), starting from the squared diameter of the target.
+    pub division_factor: f32,
+    /// Keeps only the correspondences of triplets whose edge lengths agree within this
+    /// ratio. `None` skips the tuple test.
+    pub tuple_scale: Option<f32>,
+    /// Maximum number of triplets kept by the tuple test.
+    pub max_tuples: usize,
+    /// Random state of the tuple sampling.
+    pub random_state: RandomState,
+}
+
+impl Default for FgrParams {
+    fn default() -> Self {
+        Self {
+            feature: FpfhParams::default(),
+            max_correspondence_distance: 0.025,
+            max_iterations: 64,
+            division_factor: 1.4,
+            tuple_scale: Some(0.95),
+            max_tuples: 1000,
+            random_state: RandomState::default(),
+        }
+    }
+}
+
+/// Keeps the correspondences of random triplets with consistent edge lengths.
+fn tuple_test(
+    target: &PointCloud,
+    source: &PointCloud,
+    correspondences: &[(usize, usize)],
+    params: &FgrParams,
+    tuple_scale: f32,
+) -> Vec<(usize, usize)> {
+    let mut rng = params.random_state.rng();
+    let mut kept = Vec::new();
+    let mut tuple_count = 0;
+    for _ in 0..correspondences.len() * 100 {
+        if tuple_count >= params.max_tuples {
+            break;
+        }
+        let tuple = [(); 3].map(|_| correspondences[rng.gen_range(0..correspondences.len())]);
+        let consistent = (0..3).all(|i| {
+            let j = (i + 1) % 3;
+            let source_length = (source.points[tuple[i].0] - source.points[tuple[j].0]).norm();
+            let target_length = (target.points[tuple[i].1] - target.points[tuple[j].1]).norm();
+            source_length * tuple_scale < target_length
+                && target_length < source_length / tuple_scale
+        });
+        if consistent {
+            kept.extend(tuple);
+            tuple_count += 1;
+        }
+    }
+    kept
+}
+
+/// Globally aligns two point clouds with the Fast Global Registration of Zhou et al., 2016.
+/// Instead of sampling hypotheses like [`super::global_registration`], it optimizes the
+/// alignment of all the feature correspondences with a Geman-McClure kernel whose scale
+/// shrinks over the iterations, so outliers lose influence gradually. It is deterministic
+/// for a seed and usually faster than RANSAC. Refine the result with ICP.
+///
+/// # Arguments
+///
+/// * target - The reference point cloud, must have normals.
+/// * source - The point cloud to align, must have normals.
+/// * params - The registration parameters.
+///
+/// # Returns
+///
+/// The registration, or error if a point cloud has no normals or there are fewer than 3
+/// correspondences.
+pub fn fast_global_registration(
+    target: &PointCloud,
+    source: &PointCloud,
+    params: &FgrParams,
+) -> Result<GlobalRegistrationResult, A3dError> {
+    let target_features = compute_fpfh(target, &params.feature)?;
+    let source_features = compute_fpfh(source, &params.feature)?;
+
+    // Mutual nearest features.
+    let target_matches = match_features(&source_features, &target_features);
+    let mut correspondences = match_features(&target_features, &source_features)
+        .into_iter()
+        .filter(|&(source_index, target_index)| target_matches[target_index].1 == source_index)
+        .collect::<Vec<_>>();
+    if let Some(tuple_scale) = params.tuple_scale {
+        correspondences = tuple_test(target, source, &correspondences, params, tuple_scale);
+    }
+    if correspondences.len() < 3 {
+        return Err(A3dError::invalid_parameter(
+            "Fast global registration found fewer than 3 correspondences.",
+        ));
+    }
+
+    let centroid = target.points.iter().sum::<Vector3<f32>>() / target.len() as f32;
+    let diameter = 2.0
+        * target
+            .points
+            .iter()
+            .map(|point| (point - centroid).norm())
+            .fold(0.0, f32::max);
+    let min_sqr_scale = params.max_correspondence_distance.powi(2);
+    let mut sqr_scale = diameter * diameter;
+
+    let mut transform = Transform::eye();
+    let mut optimizer = GaussNewton::<6>::new();
+    for iteration in 0..params.max_iterations {
+        let kernel = RobustKernel::GemanMcClure {
+            scale: sqr_scale.sqrt(),
+        };
+        for &(source_index, target_index) in &correspondences {
+            let source_point = transform.transform_vector(&source.points[source_index]);
+            let target_point = target.points[target_index];
+            let weight = kernel.weight((target_point - source_point).norm());
+            let point_point = PointPointDistance {};
+            for (residual, jacobian) in point_point.jacobian(&source_point, &target_point) {
+                optimizer.weighted_step(residual, &jacobian, weight);
+            }
+        }
+
+        let Some(update) = optimizer.solve() else {
+            break;
+        };
+        transform = &Transform::exp(&LieGroup::Se3(update)) * &transform;
+        optimizer.reset();
+
+        if iteration % 4 == 3 && sqr_scale >= min_sqr_scale {
+            sqr_scale /= params.division_factor;
+        }
+    }
+
+    let inlier_count = correspondences
+        .iter()
+        .filter(|(source_index, target_index)| {
+            (transform.transform_vector(&source.points[*source_index])
+                - target.points[*target_index])
+                .norm_squared()
+                <= min_sqr_scale
+        })
+        .count();
+    Ok(GlobalRegistrationResult {
+        transform,
+        inlier_count,
+        correspondence_count: correspondences.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Axis;
+    use rstest::rstest;
+
+    use super::{fast_global_registration, FgrParams};
+    use crate::{
+        features::FpfhParams, metrics::TransformMetrics, pointcloud::PointCloud,
+        transform::TransformBuilder, unit_test::sample_teapot_surface,
+    };
+
+    #[rstest]
+    fn test_fast_global_registration(sample_teapot_surface: PointCloud) {
+        let indices = (0..sample_teapot_surface.len())
+            .step_by(4)
+            .collect::<Vec<_>>();
+        let target = PointCloud {
+            points: sample_teapot_surface.points.select(Axis(0), &indices),
+            normals: sample_teapot_surface
+                .normals
+                .map(|normals| normals.select(Axis(0), &indices)),
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.5, -0.3, 0.2))
+            .axis_angle(Vector3::z_axis(), 2.0)
+            .build();
+        let source = &displacement * &target;
+
+        let params = FgrParams {
+            feature: FpfhParams {
+                radius: 0.15,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = fast_global_registration(&target, &source, &params).unwrap();
+        let metrics = TransformMetrics::new(&result.transform, &displacement.inverse());
+        assert!(metrics.angle < 0.05, "{metrics}");
+        assert!(metrics.translation < 0.05, "{metrics}");
+        assert!(result.inlier_count > result.correspondence_count / 2);
+    }
+}
@@ -0,0 +1,364 @@
+use nalgebra::Vector3;
+use ndarray::{Array1, Axis};
+use rand::{rngs::StdRng, seq::index::sample, Rng};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use super::global_registration::fit_rigid_transform;
+use crate::{
+    error::A3dError, kdtree::R3dTree, pointcloud::PointCloud, random::RandomState,
+    transform::Transform, voxel_hash::VoxelHashGrid,
+};
+
+/// Parameters of the 4-Points Congruent Sets alignment.
+#[derive(Debug, Clone, Copy)]
+pub struct FourPcsParams {
+    /// Expected fraction of overlap between the clouds, sets the width of the bases.
+    pub overlap: f32,
+    /// Distance tolerance, used to match pair lengths, intersection points and to count
+    /// the points of the Largest Common Pointset (LCP).
+    pub delta: f32,
+    /// Number of bases tried.
+    pub num_bases: usize,
+    /// Number of points randomly sampled from each cloud.
+    pub sample_size: usize,
+    /// Number of candidates returned.
+    pub num_candidates: usize,
+    /// Random state of the sampling.
+    pub random_state: RandomState,
+}
+
+impl Default for FourPcsParams {
+    fn default() -> Self {
+        Self {
+            overlap: 0.5,
+            delta: 0.02,
+            num_bases: 100,
+            sample_size: 500,
+            num_candidates: 5,
+            random_state: RandomState::default(),
+        }
+    }
+}
+
+/// A candidate alignment of [`four_pcs`].
+#[derive(Debug, Clone)]
+pub struct FourPcsCandidate {
+    /// The transformation that aligns the source to the target.
+    pub transform: Transform,
+    /// Fraction of the sampled source points within `delta` of a target point after
+    /// alignment.
+    pub lcp: f32,
+}
+
+/// Four coplanar points split in two segments that intersect.
+struct Base {
+    points: [Vector3<f32>; 4],
+    /// Length of the segments 0-1 and 2-3.
+    lengths: [f32; 2],
+    /// Position of the intersection along each segment, as a fraction of its length.
+    ratios: [f32; 2],
+}
+
+impl Base {
+    /// Orders four points so that the segments 0-1 and 2-3 intersect, if possible.
+    fn new(points: [Vector3<f32>; 4], delta: f32) -> Option<Self> {
+        [[0, 1, 2, 3], [0, 2, 1, 3], [0, 3, 1, 2]]
+            .into_iter()
+            .find_map(|order| {
+                let [a, b, c, d] = order.map(|i| points[i]);
+                let (u, v, w) = (b - a, d - c, a - c);
+                let (uu, uv, vv, uw, vw) = (u.dot(&u), u.dot(&v), v.dot(&v), u.dot(&w), v.dot(&w));
+                let denominator = uu * vv - uv * uv;
+                if denominator.abs() < 1e-12 {
+                    return None;
+                }
+                let s = (uv * vw - vv * uw) / denominator;
+                let t = (uu * vw - uv * uw) / denominator;
+                let gap = (a + u * s - (c + v * t)).norm();
+                ((0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&t) && gap <= delta).then(|| {
+                    Base {
+                        points: [a, b, c, d],
+                        lengths: [u.norm(), v.norm()],
+                        ratios: [s, t],
+                    }
+                })
+            })
+    }
+}
+
+/// Samples a wide and nearly coplanar base.
+fn sample_base(
+    points: &Array1<Vector3<f32>>,
+    width: f32,
+    delta: f32,
+    rng: &mut StdRng,
+) -> Option<Base> {
+    const ATTEMPTS: usize = 50;
+    let random_point = |rng: &mut StdRng| points[rng.gen_range(0..points.len())];
+
+    let first = random_point(rng);
+    let second = (0..ATTEMPTS).map(|_| random_point(rng)).find(|point| {
+        let distance = (point - first).norm();
+        distance >= 0.5 * width && distance <= width
+    })?;
+    let third = (0..ATTEMPTS)
+        .map(|_| random_point(rng))
+        .filter(|point| (point - first).norm() <= width && (point - second).norm() <= width)
+        .max_by(|a, b| {
+            let area_a = (second - first).cross(&(a - first)).norm();
+            let area_b = (second - first).cross(&(b - first)).norm();
+            area_a.total_cmp(&area_b)
+        })?;
+
+    let normal = (second - first)
+        .cross(&(third - first))
+        .try_normalize(1e-8)?;
+    // The fourth point is the closest to the plane among the ones far from the others.
+    let fourth = points
+        .iter()
+        .filter(|point| {
+            [first, second, third].iter().all(|other| {
+                (*point - other).norm() >= 0.3 * width && (*point - other).norm() <= width
+            })
+        })
+        .min_by(|a, b| {
+            (*a - first)
+                .dot(&normal)
+                .abs()
+                .total_cmp(&(*b - first).dot(&normal).abs())
+        })?;
+
+    Base::new([first, second, third, *fourth], delta)
+}
+
+/// Pairs of source points with the given distance, in both orders.
+fn pairs_with_length(
+    points: &Array1<Vector3<f32>>,
+    length: f32,
+    delta: f32,
+) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for i in 0..points.len() {
+        for j in i + 1..points.len() {
+            if ((points[i] - points[j]).norm() - length).abs() <= delta {
+                pairs.push((i, j));
+                pairs.push((j, i));
+            }
+        }
+    }
+    pairs
+}
+
+/// Coarsely aligns two point clouds without an initial guess with 4-Points Congruent Sets,
+/// Aiger et al., 2008. It samples coplanar 4-point bases from the target, finds the 4-point
+/// sets of the source with the same affine invariants, and scores each resulting alignment
+/// by its Largest Common Pointset. It works with very low overlap and doesn't need normals.
+/// The source pairs are searched exhaustively on a random sample, not with the indexing of
+/// Super4PCS, so keep `sample_size` in the hundreds.
+///
+/// # Arguments
+///
+/// * target - The reference point cloud.
+/// * source - The point cloud to align.
+/// * params - The alignment parameters.
+///
+/// # Returns
+///
+/// Up to `num_candidates` distinct alignments, from the best LCP score to the worst, for
+/// verification with ICP. Error if the clouds have fewer than 4 points.
+pub fn four_pcs(
+    target: &PointCloud,
+    source: &PointCloud,
+    params: &FourPcsParams,
+) -> Result<Vec<FourPcsCandidate>, A3dError> {
+    if target.len() < 4 || source.len() < 4 {
+        return Err(A3dError::invalid_parameter(
+            "4PCS requires at least 4 points in each cloud.",
+        ));
+    }
+
+    let mut rng = params.random_state.rng();
+    let mut subsample = |pcl: &PointCloud| {
+        let mut indices = sample(&mut rng, pcl.len(), params.sample_size.min(pcl.len())).into_vec();
+        indices.sort_unstable();
+        pcl.points.select(Axis(0), &indices)
+    };
+    let target_points = subsample(target);
+    let source_points = subsample(source);
+
+    let target_kdtree = R3dTree::new(&target_points.view());
+    let centroid = target_points.iter().sum::<Vector3<f32>>() / target_points.len() as f32;
+    let diameter = 2.0
+        * target_points
+            .iter()
+            .map(|point| (point - centroid).norm())
+            .fold(0.0, f32::max);
+    let width = params.overlap * diameter;
+    let sqr_delta = params.delta * params.delta;
+
+    // Scores with every `step`-th point, a coarse step ranks the many congruent sets
+    // before scoring the best ones with all the points.
+    let lcp = |transform: &Transform, step: usize| {
+        let (count, total) =
+            source_points
+                .iter()
+                .step_by(step)
+                .fold((0, 0), |(count, total), point| {
+                    let inlier =
+                        target_kdtree.nearest(&transform.transform_vector(point)).1 <= sqr_delta;
+                    (count + inlier as usize, total + 1)
+                });
+        count as f32 / total as f32
+    };
+    let coarse_step = (source_points.len() / 100).max(1);
+
+    let mut candidates: Vec<FourPcsCandidate> = Vec::new();
+    for _ in 0..params.num_bases {
+        let Some(base) = sample_base(&target_points, width, params.delta, &mut rng) else {
+            continue;
+        };
+
+        let first_pairs = pairs_with_length(&source_points, base.lengths[0], params.delta);
+        let second_pairs = pairs_with_length(&source_points, base.lengths[1], params.delta);
+        if first_pairs.is_empty() || second_pairs.is_empty() {
+            continue;
+        }
+        let intersection = |(i, j): (usize, usize), ratio: f32| {
+            source_points[i] + (source_points[j] - source_points[i]) * ratio
+        };
+        // The intersection points of the source pairs, hashed in cells of size `delta`.
+        let second_intersections = VoxelHashGrid::from_point_iter(
+            second_pairs
+                .iter()
+                .map(|pair| intersection(*pair, base.ratios[1])),
+            params.delta,
+        );
+
+        let transforms = first_pairs
+            .into_par_iter()
+            .flat_map_iter(|first_pair| {
+                second_intersections
+                    .neighbors_within(&intersection(first_pair, base.ratios[0]), params.delta)
+                    .into_iter()
+                    .filter_map(|(second, _)| {
+                        let (k, l) = second_pairs[second];
+                        let congruent =
+                            [first_pair.0, first_pair.1, k, l].map(|i| source_points[i]);
+                        // The cross distances of a rigid motion of the base also agree.
+                        let rigid = [(0, 2), (0, 3), (1, 2), (1, 3)].iter().all(|&(i, j)| {
+                            ((congruent[i] - congruent[j]).norm()
+                                - (base.points[i] - base.points[j]).norm())
+                            .abs()
+                                <= 2.0 * params.delta
+                        });
+                        if !rigid {
+                            return None;
+                        }
+                        let transform = fit_rigid_transform(&congruent, &base.points)?;
+                        congruent
+                            .iter()
+                            .zip(base.points.iter())
+                            .all(|(source, target)| {
+                                (transform.transform_vector(source) - target).norm() <= params.delta
+                            })
+                            .then_some(transform)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut ranked = transforms
+            .into_par_iter()
+            .map(|transform| (lcp(&transform, coarse_step), transform))
+            .collect::<Vec<_>>();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        for (_, transform) in ranked.into_iter().take(params.num_candidates) {
+            let candidate = FourPcsCandidate {
+                lcp: lcp(&transform, 1),
+                transform,
+            };
+            insert_candidate(&mut candidates, candidate, params);
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// Inserts a candidate keeping the list sorted, without near duplicates and within the
+/// maximum number of candidates.
+fn insert_candidate(
+    candidates: &mut Vec<FourPcsCandidate>,
+    candidate: FourPcsCandidate,
+    params: &FourPcsParams,
+) {
+    let is_duplicate = |other: &FourPcsCandidate| {
+        let difference = &other.transform.inverse() * &candidate.transform;
+        difference.angle() < 0.05 && difference.translation().norm() < 2.0 * params.delta
+    };
+    if let Some(position) = candidates.iter().position(is_duplicate) {
+        if candidates[position].lcp >= candidate.lcp {
+            return;
+        }
+        candidates.remove(position);
+    }
+
+    let position = candidates.partition_point(|other| other.lcp >= candidate.lcp);
+    candidates.insert(position, candidate);
+    candidates.truncate(params.num_candidates);
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Axis;
+    use rstest::rstest;
+
+    use super::{four_pcs, FourPcsParams};
+    use crate::{
+        metrics::TransformMetrics, pointcloud::PointCloud, transform::TransformBuilder,
+        unit_test::sample_teapot_surface,
+    };
+
+    fn crop(pcl: &PointCloud, keep: impl Fn(&Vector3<f32>) -> bool) -> PointCloud {
+        let indices = (0..pcl.len())
+            .step_by(12)
+            .filter(|index| keep(&pcl.points[*index]))
+            .collect::<Vec<_>>();
+        PointCloud {
+            points: pcl.points.select(Axis(0), &indices),
+            normals: None,
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        }
+    }
+
+    #[rstest]
+    fn test_four_pcs(sample_teapot_surface: PointCloud) {
+        // The clouds overlap in about 60% of their points.
+        let target = crop(&sample_teapot_surface, |point| point[0] < 0.9);
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.5, -0.3, 0.2))
+            .axis_angle(Vector3::z_axis(), 2.0)
+            .build();
+        let source = &displacement * &crop(&sample_teapot_surface, |point| point[0] > 0.1);
+
+        let params = FourPcsParams {
+            overlap: 0.6,
+            sample_size: 1000,
+            num_bases: 20,
+            ..Default::default()
+        };
+        let candidates = four_pcs(&target, &source, &params).unwrap();
+        assert!(!candidates.is_empty());
+        assert!(candidates.len() <= params.num_candidates);
+        assert!(candidates.windows(2).all(|pair| pair[0].lcp >= pair[1].lcp));
+
+        let expected = displacement.inverse();
+        assert!(candidates.iter().any(|candidate| {
+            let metrics = TransformMetrics::new(&candidate.transform, &expected);
+            metrics.angle < 0.1 && metrics.translation < 0.1
+        }));
+    }
+}
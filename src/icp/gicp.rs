@@ -0,0 +1,293 @@
+use std::ops::ControlFlow;
+
+use nalgebra::{Matrix3, Vector3};
+use ndarray::Array1;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use super::{
+    cost_function::DistributionDistance,
+    icp_params::{IcpTermination, InformationEstimate},
+    icp_result::{IcpResult, IterationCallback, IterationInfo, IterationTracker},
+    point_repr::PointRepr,
+};
+use crate::{
+    kdtree::R3dTree,
+    optim::GaussNewton,
+    pointcloud::PointCloud,
+    transform::{LieGroup, Transform},
+};
+
+/// Generalized ICP parameters.
+#[derive(Debug, Clone, Copy)]
+pub struct GicpParams {
+    /// Maximum number of iterations.
+    pub max_iterations: usize,
+    /// Maximum distance between two points to be considered as the same.
+    pub max_distance: f32,
+    /// Number of neighbors used to estimate the point covariances.
+    pub num_neighbors: usize,
+    /// Variance along the surface normal relative to the one along the surface, models
+    /// the points as small discs.
+    pub covariance_epsilon: f32,
+    /// Criteria to stop before the maximum number of iterations.
+    pub termination: IcpTermination,
+    /// How the information matrix of the result is estimated.
+    pub information: InformationEstimate,
+}
+
+impl Default for GicpParams {
+    fn default() -> Self {
+        Self {
+            max_iterations: 30,
+            max_distance: 0.5,
+            num_neighbors: 20,
+            covariance_epsilon: 1e-3,
+            termination: IcpTermination::default(),
+            information: InformationEstimate::ResidualVariance,
+        }
+    }
+}
+
+/// Estimates the covariance of each point from its neighborhood, regularized into a disc
+/// with unit variance along the surface and `epsilon` along the normal, as in the
+/// plane-to-plane model of Generalized ICP.
+///
+/// # Arguments
+///
+/// * points - The points.
+/// * kdtree - Tree built from the same points.
+/// * num_neighbors - Neighborhood size.
+/// * epsilon - Variance along the normal.
+///
+/// # Returns
+///
+/// One covariance matrix per point.
+pub fn compute_covariances(
+    points: &Array1<Vector3<f32>>,
+    kdtree: &R3dTree,
+    num_neighbors: usize,
+    epsilon: f32,
+) -> Vec<Matrix3<f32>> {
+    (0..points.len())
+        .into_par_iter()
+        .map(|index| {
+            let neighbors = kdtree.knn(&points[index], num_neighbors);
+            if neighbors.len() < 3 {
+                return Matrix3::identity();
+            }
+            let mean = neighbors
+                .iter()
+                .fold(Vector3::zeros(), |sum, (neighbor, _)| {
+                    sum + points[*neighbor]
+                })
+                / neighbors.len() as f32;
+            let covariance = neighbors
+                .iter()
+                .fold(Matrix3::zeros(), |sum, (neighbor, _)| {
+                    let centered = points[*neighbor] - mean;
+                    sum + centered * centered.transpose()
+                })
+                / neighbors.len() as f32;
+
+            let eigen = covariance.symmetric_eigen();
+            let normal_axis = eigen.eigenvalues.imin();
+            let scales =
+                Vector3::from_fn(|axis, _| if axis == normal_axis { epsilon } else { 1.0 });
+            eigen.eigenvectors * Matrix3::from_diagonal(&scales) * eigen.eigenvectors.transpose()
+        })
+        .collect()
+}
+
+/// Generalized ICP (GICP) for aligning two point clouds. It minimizes the distance between
+/// the local surface distributions of matched points, which is more robust than
+/// point-to-plane ICP on noisy data, e.g., LiDAR scans, and doesn't need normals.
+///
+/// The clouds are [`PointCloud`] by default, or any other layout implementing
+/// [`PointRepr`].
+pub struct Gicp<'target, T: PointRepr + ?Sized = PointCloud> {
+    /// Parameters of the algorithm.
+    pub params: GicpParams,
+    /// Initial transformation to start the algorithm. Default is the identity.
+    pub initial_transform: Transform,
+    target: &'target T,
+    kdtree: R3dTree,
+    target_covariances: Vec<Matrix3<f32>>,
+}
+
+impl<'target, T: PointRepr + ?Sized> Gicp<'target, T> {
+    /// Create a new GICP instance, computing the target covariances.
+    ///
+    /// # Arguments
+    ///
+    /// * params - Parameters of the algorithm.
+    /// * target - Target point cloud.
+    pub fn new(params: GicpParams, target: &'target T) -> Self {
+        let target_points = target.positions();
+        let kdtree = R3dTree::new(&target_points.view());
+        let target_covariances = compute_covariances(
+            &target_points,
+            &kdtree,
+            params.num_neighbors,
+            params.covariance_epsilon,
+        );
+        Self {
+            params,
+            initial_transform: Transform::eye(),
+            target,
+            kdtree,
+            target_covariances,
+        }
+    }
+
+    /// Aligns the source point cloud to the target point cloud.
+    ///
+    /// # Arguments
+    ///
+    /// * source - Source point cloud.
+    ///
+    /// # Returns
+    ///
+    /// The transformation that aligns the source point cloud to the target point cloud.
+    /// The residuals of the result are whitened by the point covariances.
+    pub fn align<S: PointRepr + ?Sized>(&self, source: &S) -> IcpResult {
+        self.align_impl(source, None)
+    }
+
+    /// Same as `align`, reporting the progress of every iteration to a callback that can
+    /// abort the run.
+    ///
+    /// # Arguments
+    ///
+    /// * source - Same as `align`.
+    /// * callback - Called after every iteration, see [`IterationCallback`].
+    pub fn align_with_callback<S: PointRepr + ?Sized>(
+        &self,
+        source: &S,
+        mut callback: impl FnMut(IterationInfo) -> ControlFlow<()>,
+    ) -> IcpResult {
+        self.align_impl(source, Some(&mut callback))
+    }
+
+    fn align_impl<'a, S: PointRepr + ?Sized>(
+        &self,
+        source: &S,
+        callback: Option<&'a mut IterationCallback<'a>>,
+    ) -> IcpResult {
+        let source_points = source.positions();
+        let source_covariances = compute_covariances(
+            &source_points,
+            &R3dTree::new(&source_points.view()),
+            self.params.num_neighbors,
+            self.params.covariance_epsilon,
+        );
+        let max_distance_sqr = self.params.max_distance * self.params.max_distance;
+        let cost = DistributionDistance {};
+
+        let mut optim_transform = self.initial_transform.clone();
+        let mut tracker = IterationTracker::with_criteria(
+            self.params.termination,
+            self.params.information,
+            &optim_transform,
+        )
+        .with_callback(callback);
+        for _ in 0..self.params.max_iterations {
+            let rotation = optim_transform.0.rotation.to_rotation_matrix();
+            let rotation = rotation.matrix();
+
+            let optimizer = (0..source_points.len())
+                .into_par_iter()
+                .fold(GaussNewton::<6>::new, |mut optimizer, index| {
+                    let source_point = optim_transform.transform_vector(&source_points[index]);
+                    let (found_index, found_sqr_distance) = self.kdtree.nearest(&source_point);
+                    if found_sqr_distance > max_distance_sqr {
+                        return optimizer;
+                    }
+
+                    let combined_covariance = self.target_covariances[found_index]
+                        + rotation * source_covariances[index] * rotation.transpose();
+                    if let Some(residuals) = cost.jacobian(
+                        &source_point,
+                        &self.target.position(found_index),
+                        &combined_covariance,
+                    ) {
+                        for (residual, jacobian) in residuals {
+                            optimizer.weighted_step(residual, &jacobian, 1.0);
+                        }
+                    }
+                    optimizer
+                })
+                .reduce(GaussNewton::<6>::new, |mut optimizer, other| {
+                    optimizer.add(&other);
+                    optimizer
+                });
+
+            let residual = optimizer.mean_squared_residual();
+            let Some(update) = optimizer.solve() else {
+                break;
+            };
+            // Each correspondence has one residual per axis.
+            optim_transform = &Transform::exp(&LieGroup::Se3(update)) * &optim_transform;
+            tracker.record(&optim_transform, &optimizer, optimizer.count() / 3);
+            if tracker.should_stop(&update, residual) {
+                break;
+            }
+        }
+
+        tracker.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+    use rstest::rstest;
+
+    use super::{Gicp, GicpParams};
+    use crate::{
+        metrics::TransformMetrics,
+        pointcloud::PointCloud,
+        transform::TransformBuilder,
+        unit_test::{sample_pcl_ds1, sample_teapot_surface, TestPclDataset},
+    };
+
+    #[rstest]
+    fn test_gicp_noisy_teapot(sample_teapot_surface: PointCloud) {
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.04, -0.03, 0.02))
+            .axis_angle(Vector3::y_axis(), 0.05)
+            .build();
+        let mut source = &displacement * &sample_teapot_surface;
+        let mut rng = SmallRng::seed_from_u64(7);
+        source.points.iter_mut().for_each(|point| {
+            *point += Vector3::from_fn(|_, _| rng.gen_range(-0.005..0.005));
+        });
+        source.normals = None;
+
+        let result = Gicp::new(GicpParams::default(), &sample_teapot_surface).align(&source);
+        let metrics = TransformMetrics::new(&result.transform, &displacement.inverse());
+        assert!(metrics.translation < 2e-3);
+        assert!(metrics.angle < 2e-3);
+        assert!(result.converged);
+        assert!(result.iterations < GicpParams::default().max_iterations);
+        assert!(result.covariance().is_some());
+    }
+
+    #[rstest]
+    fn test_gicp(sample_pcl_ds1: TestPclDataset) {
+        let target_pcl = sample_pcl_ds1.get(0);
+        let source_pcl = sample_pcl_ds1.get(1);
+
+        let actual = Gicp::new(
+            GicpParams {
+                max_iterations: 10,
+                ..Default::default()
+            },
+            &target_pcl,
+        )
+        .align(&source_pcl)
+        .transform;
+        let gt_transform = sample_pcl_ds1.get_ground_truth(1, 0);
+        assert!(TransformMetrics::new(&actual, &gt_transform).angle.abs() < 0.1);
+    }
+}
@@ -0,0 +1,280 @@
+use nalgebra::{Isometry3, Matrix3, Rotation3, Translation3, UnitQuaternion, Vector3};
+use rand::Rng;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    error::A3dError,
+    features::{compute_fpfh, FpfhFeature, FpfhParams},
+    pointcloud::PointCloud,
+    random::RandomState,
+    transform::Transform,
+};
+
+/// Parameters of the feature based global registration.
+#[derive(Debug, Clone, Copy)]
+pub struct GlobalRegistrationParams {
+    /// Parameters of the FPFH features.
+    pub feature: FpfhParams,
+    /// Maximum distance between the aligned points of an inlier correspondence.
+    pub max_correspondence_distance: f32,
+    /// Maximum number of RANSAC hypotheses.
+    pub max_iterations: usize,
+    /// Probability of sampling an outlier free hypothesis, used to stop before
+    /// `max_iterations` once the inlier ratio is known.
+    pub confidence: f32,
+    /// Minimum ratio between the lengths of the matching edges of a sample, samples of
+    /// dissimilar triangles are discarded before scoring.
+    pub edge_length_ratio: f32,
+    /// Random state of the sampling, the registration is deterministic for a state.
+    pub random_state: RandomState,
+}
+
+impl Default for GlobalRegistrationParams {
+    fn default() -> Self {
+        Self {
+            feature: FpfhParams::default(),
+            max_correspondence_distance: 0.05,
+            max_iterations: 100000,
+            confidence: 0.999,
+            edge_length_ratio: 0.9,
+            random_state: RandomState::default(),
+        }
+    }
+}
+
+/// Outcome of a global registration.
+#[derive(Debug, Clone)]
+pub struct GlobalRegistrationResult {
+    /// The transformation that aligns the source to the target.
+    pub transform: Transform,
+    /// Number of feature correspondences within the maximum distance after alignment.
+    pub inlier_count: usize,
+    /// Number of feature correspondences, one per source point.
+    pub correspondence_count: usize,
+}
+
+/// Rigid transformation minimizing the squared distances between matched points, Kabsch's
+/// method. `None` if the points are degenerate.
+pub(super) fn fit_rigid_transform(
+    source: &[Vector3<f32>],
+    target: &[Vector3<f32>],
+) -> Option<Transform> {
+    let count = source.len() as f32;
+    let source_centroid = source.iter().sum::<Vector3<f32>>() / count;
+    let target_centroid = target.iter().sum::<Vector3<f32>>() / count;
+    let covariance = source
+        .iter()
+        .zip(target)
+        .fold(Matrix3::zeros(), |acc, (source, target)| {
+            acc + (source - source_centroid) * (target - target_centroid).transpose()
+        });
+
+    let svd = covariance.try_svd(true, true, f32::EPSILON, 100)?;
+    let (u, v_t) = (svd.u?, svd.v_t?);
+    let mut correction = Matrix3::identity();
+    if (v_t.transpose() * u.transpose()).determinant() < 0.0 {
+        correction[(2, 2)] = -1.0;
+    }
+    let rotation = UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(
+        v_t.transpose() * correction * u.transpose(),
+    ));
+    let translation = target_centroid - rotation * source_centroid;
+    Some(Transform(Isometry3::from_parts(
+        Translation3::from(translation),
+        rotation,
+    )))
+}
+
+fn sqr_feature_distance(a: &FpfhFeature, b: &FpfhFeature) -> f32 {
+    a.iter().zip(b.iter()).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// Matches each source feature to its closest target feature.
+pub(super) fn match_features(
+    target: &[FpfhFeature],
+    source: &[FpfhFeature],
+) -> Vec<(usize, usize)> {
+    (0..source.len())
+        .into_par_iter()
+        .filter_map(|source_index| {
+            (0..target.len())
+                .min_by(|&a, &b| {
+                    sqr_feature_distance(&target[a], &source[source_index])
+                        .total_cmp(&sqr_feature_distance(&target[b], &source[source_index]))
+                })
+                .map(|target_index| (source_index, target_index))
+        })
+        .collect()
+}
+
+/// Coarsely aligns two point clouds without an initial guess: matches their FPFH features
+/// and finds the transformation agreeing with most matches by RANSAC. Refine the result
+/// with ICP. Feature matching is quadratic on the number of points, so downsample large
+/// clouds to a few thousand points first.
+///
+/// # Arguments
+///
+/// * target - The reference point cloud, must have normals.
+/// * source - The point cloud to align, must have normals.
+/// * params - The registration parameters.
+///
+/// # Returns
+///
+/// The registration, or error if a point cloud has no normals or no hypothesis could be
+/// sampled, e.g., with fewer than 3 points.
+pub fn global_registration(
+    target: &PointCloud,
+    source: &PointCloud,
+    params: &GlobalRegistrationParams,
+) -> Result<GlobalRegistrationResult, A3dError> {
+    let target_features = compute_fpfh(target, &params.feature)?;
+    let source_features = compute_fpfh(source, &params.feature)?;
+    let correspondences = match_features(&target_features, &source_features);
+    if correspondences.len() < 3 {
+        return Err(A3dError::invalid_parameter(
+            "Global registration requires at least 3 points.",
+        ));
+    }
+
+    let sqr_max_distance = params.max_correspondence_distance.powi(2);
+    let inliers = |transform: &Transform| {
+        correspondences
+            .iter()
+            .filter(|(source_index, target_index)| {
+                (transform.transform_vector(&source.points[*source_index])
+                    - target.points[*target_index])
+                    .norm_squared()
+                    <= sqr_max_distance
+            })
+            .copied()
+            .collect::<Vec<_>>()
+    };
+
+    let mut rng = params.random_state.rng();
+    let mut best: Option<(Transform, usize)> = None;
+    let mut required_iterations = params.max_iterations;
+    let mut iteration = 0;
+    while iteration < required_iterations {
+        iteration += 1;
+        let sample = [(); 3].map(|_| correspondences[rng.gen_range(0..correspondences.len())]);
+        let source_points = sample.map(|(source_index, _)| source.points[source_index]);
+        let target_points = sample.map(|(_, target_index)| target.points[target_index]);
+
+        let similar_edges = (0..3).all(|i| {
+            let j = (i + 1) % 3;
+            let source_length = (source_points[i] - source_points[j]).norm();
+            let target_length = (target_points[i] - target_points[j]).norm();
+            source_length.min(target_length)
+                >= params.edge_length_ratio * source_length.max(target_length)
+        });
+        if !similar_edges {
+            continue;
+        }
+        let Some(transform) = fit_rigid_transform(&source_points, &target_points) else {
+            continue;
+        };
+
+        let inlier_count = inliers(&transform).len();
+        if best
+            .as_ref()
+            .is_none_or(|(_, best_count)| inlier_count > *best_count)
+        {
+            best = Some((transform, inlier_count));
+            let inlier_ratio = inlier_count as f32 / correspondences.len() as f32;
+            let outlier_free = 1.0 - inlier_ratio.powi(3);
+            if outlier_free < 1.0 {
+                let needed = if outlier_free <= 0.0 {
+                    0.0
+                } else {
+                    (1.0 - params.confidence).ln() / outlier_free.ln()
+                };
+                required_iterations = required_iterations.min(needed.ceil() as usize);
+            }
+        }
+    }
+
+    let (transform, _) = best.ok_or_else(|| {
+        A3dError::invalid_parameter("No valid registration hypothesis was sampled.")
+    })?;
+
+    // Refits to all the inliers of the best hypothesis.
+    let best_inliers = inliers(&transform);
+    let transform = if best_inliers.len() >= 3 {
+        let source_points = best_inliers
+            .iter()
+            .map(|(source_index, _)| source.points[*source_index])
+            .collect::<Vec<_>>();
+        let target_points = best_inliers
+            .iter()
+            .map(|(_, target_index)| target.points[*target_index])
+            .collect::<Vec<_>>();
+        fit_rigid_transform(&source_points, &target_points).unwrap_or(transform)
+    } else {
+        transform
+    };
+
+    Ok(GlobalRegistrationResult {
+        inlier_count: inliers(&transform).len(),
+        correspondence_count: correspondences.len(),
+        transform,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Axis;
+    use rstest::rstest;
+
+    use super::{global_registration, GlobalRegistrationParams};
+    use crate::{
+        features::FpfhParams,
+        icp::{Gicp, GicpParams},
+        metrics::TransformMetrics,
+        pointcloud::PointCloud,
+        transform::TransformBuilder,
+        unit_test::sample_teapot_surface,
+    };
+
+    #[rstest]
+    fn test_global_registration(sample_teapot_surface: PointCloud) {
+        let indices = (0..sample_teapot_surface.len())
+            .step_by(4)
+            .collect::<Vec<_>>();
+        let target = PointCloud {
+            points: sample_teapot_surface.points.select(Axis(0), &indices),
+            normals: sample_teapot_surface
+                .normals
+                .map(|normals| normals.select(Axis(0), &indices)),
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.5, -0.3, 0.2))
+            .axis_angle(Vector3::z_axis(), 2.0)
+            .build();
+        let source = &displacement * &target;
+
+        let params = GlobalRegistrationParams {
+            feature: FpfhParams {
+                radius: 0.15,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = global_registration(&target, &source, &params).unwrap();
+        assert_eq!(result.correspondence_count, target.len());
+        let metrics = TransformMetrics::new(&result.transform, &displacement.inverse());
+        assert!(metrics.angle < 0.1, "{metrics}");
+        assert!(metrics.translation < 0.1, "{metrics}");
+
+        // Close enough for ICP to converge.
+        let mut gicp = Gicp::new(GicpParams::default(), &target);
+        gicp.initial_transform = result.transform;
+        let refined = gicp.align(&source).transform;
+        let metrics = TransformMetrics::new(&refined, &displacement.inverse());
+        assert!(metrics.angle < 1e-2, "{metrics}");
+    }
+}
@@ -0,0 +1,348 @@
+use std::sync::Arc;
+
+use nalgebra::{Matrix4, Matrix6, Vector6};
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::{Device, Queue},
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+    sync,
+    sync::GpuFuture,
+};
+
+use super::icp_params::IcpParams;
+use crate::{
+    camera::CameraIntrinsics,
+    error::A3dError,
+    optim::{GaussNewton, RobustKernel},
+    range_image::RangeImage,
+    transform::Transform,
+    viz::Manager,
+};
+
+/// Invocations per workgroup, `GROUP_SIZE` of the shader.
+const GROUP_SIZE: usize = 128;
+/// Values reduced per workgroup, `NUM_VALUES` of the shader.
+const NUM_VALUES: usize = 29;
+
+mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "resources/shaders/icp/projective_icp.comp",
+    }
+}
+
+pub(super) fn gpu_error(err: impl std::fmt::Display) -> A3dError {
+    A3dError::Gpu(err.to_string())
+}
+
+/// Kind, scale and shape of a robust kernel, as encoded by the shader.
+fn robust_kernel_code(kernel: Option<RobustKernel>) -> (u32, f32, f32) {
+    match kernel {
+        None => (0, 1.0, 0.0),
+        Some(RobustKernel::Huber { scale }) => (1, scale, 0.0),
+        Some(RobustKernel::Tukey { scale }) => (2, scale, 0.0),
+        Some(RobustKernel::Cauchy { scale }) => (3, scale, 0.0),
+        Some(RobustKernel::GemanMcClure { scale }) => (4, scale, 0.0),
+        Some(RobustKernel::Barron { alpha, scale }) => (5, scale, alpha),
+    }
+}
+
+/// Range image uploaded to the GPU, its points and normals with one element per pixel.
+pub(super) struct GpuRangeImage {
+    points: Subbuffer<[[f32; 4]]>,
+    normals: Subbuffer<[[f32; 4]]>,
+    intrinsics: CameraIntrinsics,
+    width: usize,
+    height: usize,
+}
+
+impl GpuRangeImage {
+    /// Number of pixels.
+    pub(super) fn len(&self) -> usize {
+        self.width * self.height
+    }
+}
+
+/// Compute shader of projective point-to-plane ICP, used by
+/// [`super::ProjectiveIcp::with_gpu`]. Each invocation associates a source pixel with the
+/// target pixel it projects to, and each workgroup reduces its `J^T J` and `J^T r` in
+/// shared memory. The partial sums of the workgroups are added on the CPU.
+pub struct GpuIcpKernel {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    pipeline: Arc<ComputePipeline>,
+    memory_allocator: StandardMemoryAllocator,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+}
+
+impl GpuIcpKernel {
+    /// Creates the compute pipeline on a queue of the manager.
+    ///
+    /// # Arguments
+    ///
+    /// * `manager` - The Vulkan manager.
+    ///
+    /// # Returns
+    ///
+    /// The kernel, or error if the manager has no queue left or the shader can't be loaded.
+    pub fn new(manager: &mut Manager) -> Result<Self, A3dError> {
+        let queue = manager
+            .queues
+            .next()
+            .ok_or_else(|| A3dError::Gpu("No Vulkan queue available.".to_string()))?;
+        let device = manager.device.clone();
+        let shader = cs::load(device.clone()).map_err(gpu_error)?;
+        let pipeline = ComputePipeline::new(
+            device.clone(),
+            shader.entry_point("main").unwrap(),
+            &(),
+            None,
+            |_| {},
+        )
+        .map_err(gpu_error)?;
+
+        Ok(Self {
+            memory_allocator: StandardMemoryAllocator::new_default(device.clone()),
+            descriptor_set_allocator: StandardDescriptorSetAllocator::new(device.clone()),
+            command_buffer_allocator: StandardCommandBufferAllocator::new(
+                device.clone(),
+                Default::default(),
+            ),
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    /// Uploads the points and normals of a range image.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The range image, must have normals.
+    ///
+    /// # Returns
+    ///
+    /// The uploaded image, or error if the image has no normals or the allocation fails.
+    pub(super) fn upload(&self, image: &RangeImage) -> Result<GpuRangeImage, A3dError> {
+        let normals = image.normals.as_ref().ok_or_else(|| {
+            A3dError::invalid_parameter("GPU ICP requires the range image normals.")
+        })?;
+        let create_info = BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        };
+        let alloc_info = AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        };
+
+        let points = Buffer::from_iter(
+            &self.memory_allocator,
+            create_info.clone(),
+            alloc_info.clone(),
+            image
+                .points
+                .iter()
+                .zip(image.mask.iter())
+                .map(|(point, mask)| [point[0], point[1], point[2], *mask as f32]),
+        )
+        .map_err(gpu_error)?;
+        let normals = Buffer::from_iter(
+            &self.memory_allocator,
+            create_info,
+            alloc_info,
+            normals
+                .iter()
+                .map(|normal| [normal[0], normal[1], normal[2], 0.0]),
+        )
+        .map_err(gpu_error)?;
+
+        Ok(GpuRangeImage {
+            points,
+            normals,
+            intrinsics: image.intrinsics.clone(),
+            width: image.width(),
+            height: image.height(),
+        })
+    }
+
+    /// Accumulates the point-to-plane normal equations of the projective correspondences,
+    /// the same as the CPU implementation up to the summation order.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The uploaded target image.
+    /// * `source` - The uploaded source image.
+    /// * `transform` - The current source to target transformation.
+    /// * `params` - The ICP parameters.
+    ///
+    /// # Returns
+    ///
+    /// The optimizer with the normal equations, or error if the dispatch fails.
+    pub(super) fn normal_equations(
+        &self,
+        target: &GpuRangeImage,
+        source: &GpuRangeImage,
+        transform: &Transform,
+        params: &IcpParams,
+    ) -> Result<GaussNewton<6>, A3dError> {
+        let num_groups = source.len().div_ceil(GROUP_SIZE);
+        let partials = Buffer::new_slice::<f32>(
+            &self.memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Download,
+                ..Default::default()
+            },
+            (num_groups * NUM_VALUES) as u64,
+        )
+        .map_err(gpu_error)?;
+
+        let layout = self.pipeline.layout().set_layouts().get(0).unwrap();
+        let descriptor_set = PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            layout.clone(),
+            [
+                WriteDescriptorSet::buffer(0, source.points.clone()),
+                WriteDescriptorSet::buffer(1, source.normals.clone()),
+                WriteDescriptorSet::buffer(2, target.points.clone()),
+                WriteDescriptorSet::buffer(3, target.normals.clone()),
+                WriteDescriptorSet::buffer(4, partials.clone()),
+            ],
+        )
+        .map_err(gpu_error)?;
+
+        let (robust_kind, robust_scale, robust_alpha) = robust_kernel_code(params.robust_loss);
+        let intrinsics = &target.intrinsics;
+        let push_constants = cs::PushConstants {
+            transform: Matrix4::from(transform).into(),
+            intrinsics: [
+                intrinsics.fx as f32,
+                intrinsics.fy as f32,
+                intrinsics.cx as f32,
+                intrinsics.cy as f32,
+            ],
+            source_len: source.len() as u32,
+            target_width: target.width as u32,
+            target_height: target.height as u32,
+            robust_kind,
+            max_distance_sqr: params.max_distance * params.max_distance,
+            min_normal_cos: params.max_normal_angle.cos(),
+            robust_scale,
+            robust_alpha,
+        };
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(gpu_error)?;
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                descriptor_set,
+            )
+            .push_constants(self.pipeline.layout().clone(), 0, push_constants)
+            .dispatch([num_groups as u32, 1, 1])
+            .map_err(gpu_error)?;
+        let command_buffer = builder.build().map_err(gpu_error)?;
+
+        sync::now(self.device.clone())
+            .then_execute(self.queue.clone(), command_buffer)
+            .map_err(gpu_error)?
+            .then_signal_fence_and_flush()
+            .map_err(gpu_error)?
+            .wait(None)
+            .map_err(gpu_error)?;
+
+        let partials = partials.read().map_err(gpu_error)?;
+        let mut sums = [0.0f64; NUM_VALUES];
+        for group in partials.chunks(NUM_VALUES) {
+            for (sum, value) in sums.iter_mut().zip(group) {
+                *sum += *value as f64;
+            }
+        }
+
+        let mut hessian = Matrix6::zeros();
+        let mut k = 0;
+        for i in 0..6 {
+            for j in i..6 {
+                hessian[(i, j)] = sums[k] as f32;
+                hessian[(j, i)] = sums[k] as f32;
+                k += 1;
+            }
+        }
+        let gradient = Vector6::from_fn(|i, _| sums[21 + i] as f32);
+        Ok(GaussNewton::from_normal_equations(
+            hessian,
+            gradient,
+            sums[27] as f32,
+            sums[28] as usize,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::GpuIcpKernel;
+    use crate::{
+        icp::{projective_icp::normal_equations, IcpParams, ProjectiveIcp, RobustKernel},
+        metrics::TransformMetrics,
+        unit_test::{sample_range_img_ds2, TestRangeImageDataset},
+        viz::Manager,
+    };
+
+    #[ignore]
+    #[rstest]
+    fn test_gpu_matches_cpu(sample_range_img_ds2: TestRangeImageDataset) {
+        let target = sample_range_img_ds2.get(0).unwrap();
+        let source = sample_range_img_ds2.get(1).unwrap();
+        let mut manager = Manager::default();
+        let kernel = GpuIcpKernel::new(&mut manager).unwrap();
+
+        let params = IcpParams {
+            max_iterations: 10,
+            robust_loss: Some(RobustKernel::Huber { scale: 0.02 }),
+            ..Default::default()
+        };
+        let transform = sample_range_img_ds2.get_ground_truth(1, 0);
+        let cpu = normal_equations(&target, &source, &transform, &params);
+        let gpu = kernel
+            .normal_equations(
+                &kernel.upload(&target).unwrap(),
+                &kernel.upload(&source).unwrap(),
+                &transform,
+                &params,
+            )
+            .unwrap();
+        // Rounding may move a few projections to the neighbor pixel.
+        assert!(cpu.count().abs_diff(gpu.count()) <= cpu.count() / 1000);
+        let difference = (cpu.hessian() - gpu.hessian()).norm() / cpu.hessian().norm();
+        assert!(difference < 1e-3, "{difference}");
+
+        let cpu_result = ProjectiveIcp::new(params, &target).align(&source);
+        let gpu_icp = ProjectiveIcp::new(params, &target).with_gpu(&kernel);
+        assert!(gpu_icp.uses_gpu());
+        let gpu_result = gpu_icp.align(&source);
+        let metrics = TransformMetrics::new(&gpu_result.transform, &cpu_result.transform);
+        assert!(metrics.angle < 1e-4, "{metrics}");
+        assert!(metrics.translation < 1e-4, "{metrics}");
+    }
+}
@@ -0,0 +1,312 @@
+use std::sync::Arc;
+
+use nalgebra::Vector3;
+use ndarray::ArrayView1;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::{Device, Queue},
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+    sync,
+    sync::GpuFuture,
+};
+
+use super::gpu_icp::gpu_error;
+use crate::{error::A3dError, viz::Manager};
+
+/// Invocations per workgroup, `GROUP_SIZE` of the shader.
+const GROUP_SIZE: usize = 128;
+
+mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "resources/shaders/icp/nearest_neighbor.comp",
+    }
+}
+
+/// Points uploaded to the GPU to be searched by [`GpuNearestNeighbor::nearest`].
+pub struct GpuPoints {
+    points: Subbuffer<[[f32; 4]]>,
+    len: usize,
+}
+
+impl GpuPoints {
+    /// Number of points.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Compute shader of brute force nearest neighbor search, used by [`super::Icp::with_gpu`]
+/// to associate dense clouds. Each invocation scans all the target points for a query,
+/// so it is quadratic, but with enough GPU cores it beats the k-d tree on clouds of tens
+/// of thousands of points, and it needs no tree to be built for each target.
+pub struct GpuNearestNeighbor {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    pipeline: Arc<ComputePipeline>,
+    memory_allocator: StandardMemoryAllocator,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+}
+
+impl GpuNearestNeighbor {
+    /// Creates the compute pipeline on a queue of the manager.
+    ///
+    /// # Arguments
+    ///
+    /// * `manager` - The Vulkan manager.
+    ///
+    /// # Returns
+    ///
+    /// The kernel, or error if the manager has no queue left or the shader can't be loaded.
+    pub fn new(manager: &mut Manager) -> Result<Self, A3dError> {
+        let queue = manager
+            .queues
+            .next()
+            .ok_or_else(|| A3dError::Gpu("No Vulkan queue available.".to_string()))?;
+        let device = manager.device.clone();
+        let shader = cs::load(device.clone()).map_err(gpu_error)?;
+        let pipeline = ComputePipeline::new(
+            device.clone(),
+            shader.entry_point("main").unwrap(),
+            &(),
+            None,
+            |_| {},
+        )
+        .map_err(gpu_error)?;
+
+        Ok(Self {
+            memory_allocator: StandardMemoryAllocator::new_default(device.clone()),
+            descriptor_set_allocator: StandardDescriptorSetAllocator::new(device.clone()),
+            command_buffer_allocator: StandardCommandBufferAllocator::new(
+                device.clone(),
+                Default::default(),
+            ),
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    fn storage_buffer<I>(&self, points: I) -> Result<Subbuffer<[[f32; 4]]>, A3dError>
+    where
+        I: IntoIterator<Item = Vector3<f32>>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        Buffer::from_iter(
+            &self.memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+            points
+                .into_iter()
+                .map(|point| [point[0], point[1], point[2], 0.0]),
+        )
+        .map_err(gpu_error)
+    }
+
+    fn download_buffer<T>(&self, len: usize) -> Result<Subbuffer<[T]>, A3dError>
+    where
+        T: vulkano::buffer::BufferContents,
+    {
+        Buffer::new_slice::<T>(
+            &self.memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Download,
+                ..Default::default()
+            },
+            len as u64,
+        )
+        .map_err(gpu_error)
+    }
+
+    /// Uploads the points to search, e.g., the target cloud once per registration.
+    ///
+    /// # Arguments
+    ///
+    /// * `points` - The points.
+    ///
+    /// # Returns
+    ///
+    /// The uploaded points, or error if there are none or the allocation fails.
+    pub fn upload(&self, points: &ArrayView1<Vector3<f32>>) -> Result<GpuPoints, A3dError> {
+        if points.is_empty() {
+            return Err(A3dError::invalid_parameter(
+                "The nearest neighbor search requires points.",
+            ));
+        }
+        Ok(GpuPoints {
+            points: self.storage_buffer(points.iter().copied())?,
+            len: points.len(),
+        })
+    }
+
+    /// Finds the nearest neighbor of each query point, the same as
+    /// [`crate::kdtree::R3dTree::batch_nearest`] up to ties.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The uploaded points to search.
+    /// * `queries` - The query points.
+    ///
+    /// # Returns
+    ///
+    /// The index of the nearest neighbor of each query and the squared distance to it, or
+    /// error if the dispatch fails.
+    pub fn nearest(
+        &self,
+        target: &GpuPoints,
+        queries: &[Vector3<f32>],
+    ) -> Result<(Vec<usize>, Vec<f32>), A3dError> {
+        if queries.is_empty() {
+            return Ok((Vec::new(), Vec::new()));
+        }
+        let query_buffer = self.storage_buffer(queries.iter().copied())?;
+        let indices = self.download_buffer::<u32>(queries.len())?;
+        let sqr_distances = self.download_buffer::<f32>(queries.len())?;
+
+        let layout = self.pipeline.layout().set_layouts().get(0).unwrap();
+        let descriptor_set = PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            layout.clone(),
+            [
+                WriteDescriptorSet::buffer(0, target.points.clone()),
+                WriteDescriptorSet::buffer(1, query_buffer),
+                WriteDescriptorSet::buffer(2, indices.clone()),
+                WriteDescriptorSet::buffer(3, sqr_distances.clone()),
+            ],
+        )
+        .map_err(gpu_error)?;
+        let push_constants = cs::PushConstants {
+            target_len: target.len as u32,
+            query_len: queries.len() as u32,
+        };
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(gpu_error)?;
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                descriptor_set,
+            )
+            .push_constants(self.pipeline.layout().clone(), 0, push_constants)
+            .dispatch([queries.len().div_ceil(GROUP_SIZE) as u32, 1, 1])
+            .map_err(gpu_error)?;
+        let command_buffer = builder.build().map_err(gpu_error)?;
+
+        sync::now(self.device.clone())
+            .then_execute(self.queue.clone(), command_buffer)
+            .map_err(gpu_error)?
+            .then_signal_fence_and_flush()
+            .map_err(gpu_error)?
+            .wait(None)
+            .map_err(gpu_error)?;
+
+        let indices = indices
+            .read()
+            .map_err(gpu_error)?
+            .iter()
+            .map(|index| *index as usize)
+            .collect();
+        let sqr_distances = sqr_distances.read().map_err(gpu_error)?.to_vec();
+        Ok((indices, sqr_distances))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::{Array1, Array2};
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+    use rstest::rstest;
+
+    use super::GpuNearestNeighbor;
+    use crate::{
+        icp::{Icp, IcpParams},
+        kdtree::R3dTree,
+        metrics::TransformMetrics,
+        unit_test::{sample_pcl_ds1, TestPclDataset},
+        viz::Manager,
+    };
+
+    #[ignore]
+    #[test]
+    fn test_gpu_matches_kdtree() {
+        let mut rng = SmallRng::seed_from_u64(3);
+        let mut random_points = |len| {
+            Array1::from_shape_fn(len, |_| {
+                Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>())
+            })
+        };
+        let target = random_points(5000);
+        let queries = random_points(1000);
+
+        let mut manager = Manager::default();
+        let kernel = GpuNearestNeighbor::new(&mut manager).unwrap();
+        let gpu_target = kernel.upload(&target.view()).unwrap();
+        assert_eq!(gpu_target.len(), 5000);
+        let (indices, sqr_distances) = kernel
+            .nearest(&gpu_target, queries.as_slice().unwrap())
+            .unwrap();
+
+        let (expected_indices, expected_sqr_distances) = R3dTree::new(&target.view())
+            .batch_nearest(&Array2::from_shape_fn((1000, 3), |(row, axis)| {
+                queries[row][axis]
+            }));
+        assert_eq!(indices, expected_indices.to_vec());
+        for (distance, expected) in sqr_distances.iter().zip(&expected_sqr_distances) {
+            assert!((distance - expected).abs() < 1e-6);
+        }
+
+        assert!(kernel.upload(&Array1::zeros(0).view()).is_err());
+        assert_eq!(kernel.nearest(&gpu_target, &[]).unwrap().0.len(), 0);
+    }
+
+    #[ignore]
+    #[rstest]
+    fn test_gpu_icp_matches_cpu(sample_pcl_ds1: TestPclDataset) {
+        let target = sample_pcl_ds1.get(0);
+        let source = sample_pcl_ds1.get(1);
+        let mut manager = Manager::default();
+        let kernel = GpuNearestNeighbor::new(&mut manager).unwrap();
+
+        let params = IcpParams {
+            max_iterations: 5,
+            ..Default::default()
+        };
+        let cpu_result = Icp::new(params, &target).align(&source);
+        let gpu_icp = Icp::new(params, &target).with_gpu(&kernel);
+        assert!(gpu_icp.uses_gpu());
+        let gpu_result = gpu_icp.align(&source);
+        let metrics = TransformMetrics::new(&gpu_result.transform, &cpu_result.transform);
+        assert!(metrics.angle < 1e-4, "{metrics}");
+        assert!(metrics.translation < 1e-4, "{metrics}");
+    }
+}
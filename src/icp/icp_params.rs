@@ -1,8 +1,69 @@
 use std::{
     f32::consts::PI,
     ops::{Index, IndexMut},
+    time::Duration,
 };
 
+use super::rejection::CorrespondenceRejection;
+use crate::optim::RobustKernel;
+
+/// Geometric residual minimized by ICP.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeometricCost {
+    /// Distance between matched points. Doesn't use normals, but converges slowly on
+    /// smooth surfaces where points slide along the surface.
+    PointToPoint,
+    /// Distance between the source point and the target point's tangent plane.
+    PointToPlane,
+    /// Point-to-plane plus the point-to-point distance scaled by a weight, which keeps the
+    /// fast convergence of point-to-plane while constraining degenerate directions, e.g.,
+    /// sliding along a planar scene.
+    Hybrid {
+        /// Weight of the point-to-point term relative to the point-to-plane one.
+        point_to_point_weight: f32,
+    },
+}
+
+/// Criteria to stop ICP before its maximum number of iterations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IcpTermination {
+    /// Converged when an update translates less than this, in meters, and rotates less
+    /// than `min_rotation`.
+    pub min_translation: f32,
+    /// Converged when an update rotates less than this, in radians, and translates less
+    /// than `min_translation`.
+    pub min_rotation: f32,
+    /// Converged when the mean squared residual changes less than this fraction between
+    /// two iterations.
+    pub min_relative_residual_change: f32,
+    /// Stops, without converging, when the run takes longer than this.
+    pub max_time: Option<Duration>,
+}
+
+impl Default for IcpTermination {
+    fn default() -> Self {
+        Self {
+            min_translation: 1e-5,
+            min_rotation: 1e-5,
+            min_relative_residual_change: 1e-5,
+            max_time: None,
+        }
+    }
+}
+
+/// How [`super::IcpResult::information`] is estimated from the Gauss-Newton Hessian `H`
+/// of the final iteration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InformationEstimate {
+    /// `H / σ²`, with the residual variance `σ²` estimated from the final residuals.
+    ResidualVariance,
+    /// Closed form of Censi, An accurate closed-form estimate of ICP's covariance, 2007,
+    /// for geometric residuals between points with isotropic noise of standard deviation
+    /// `point_sigma`, in meters, in both clouds. Each residual then has variance `2σ²`
+    /// and the information is `H / (2σ²)`.
+    Censi { point_sigma: f32 },
+}
+
 /// ICP parameters
 #[derive(Debug, Clone, Copy)]
 pub struct IcpParams {
@@ -20,6 +81,18 @@ pub struct IcpParams {
     pub max_normal_angle: f32,
 
     pub max_color_distance: f32,
+    /// Geometric residual to minimize.
+    pub geometric_cost: GeometricCost,
+    /// Robust loss applied to the geometric residuals, `None` for plain least squares.
+    pub robust_loss: Option<RobustKernel>,
+    /// Robust loss applied to the color residuals, `None` for plain least squares.
+    pub color_robust_loss: Option<RobustKernel>,
+    /// Further rejection of correspondences, used by point cloud ICP.
+    pub rejection: CorrespondenceRejection,
+    /// When to stop before `max_iterations`.
+    pub termination: IcpTermination,
+    /// How the information matrix of the result is estimated.
+    pub information: InformationEstimate,
 }
 
 impl Default for IcpParams {
@@ -39,6 +112,12 @@ impl Default for IcpParams {
             max_distance: 0.5,
             max_normal_angle: 18.0_f32.to_radians(),
             max_color_distance: 0.25,
+            geometric_cost: GeometricCost::PointToPlane,
+            robust_loss: None,
+            color_robust_loss: None,
+            rejection: CorrespondenceRejection::default(),
+            termination: IcpTermination::default(),
+            information: InformationEstimate::ResidualVariance,
         }
     }
 }
@@ -53,6 +132,11 @@ impl IcpParams {
         self.weight = value;
         self
     }
+
+    pub fn geometric_cost(&'_ mut self, value: GeometricCost) -> &'_ mut IcpParams {
+        self.geometric_cost = value;
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
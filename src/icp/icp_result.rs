@@ -0,0 +1,322 @@
+use std::{
+    ops::ControlFlow,
+    time::{Duration, Instant},
+};
+
+use nalgebra::{Matrix6, Vector6};
+
+use super::{
+    icp_params::{IcpParams, IcpTermination, InformationEstimate},
+    rejection::RejectionStats,
+};
+use crate::{optim::GaussNewton, transform::Transform};
+
+/// Outcome of an ICP run.
+#[derive(Debug, Clone)]
+pub struct IcpResult {
+    /// The transformation that aligns the source to the target.
+    pub transform: Transform,
+    /// Number of iterations run.
+    pub iterations: usize,
+    /// Root mean squared residual of the iteration that solved `transform`, i.e., at the
+    /// transform before its update.
+    pub final_rmse: f32,
+    /// Number of correspondences of the iteration that solved `transform`.
+    pub inlier_count: usize,
+    /// Whether a convergence criterion was met, false if the run stopped by the maximum
+    /// number of iterations or time.
+    pub converged: bool,
+    /// Counts of rejected correspondences, only filled by point cloud ICP.
+    pub rejection: RejectionStats,
+    /// Information matrix (inverse covariance) of `transform`, for weighting the edges of
+    /// pose graphs, from the Gauss-Newton Hessian of the iteration that solved it. Its
+    /// order is the one of the Gauss-Newton updates, translation then rotation, as a
+    /// perturbation applied on the left. `None` if no iteration ran.
+    pub information: Option<Matrix6<f32>>,
+}
+
+impl IcpResult {
+    /// Covariance of `transform`, the inverse of the information matrix, `None` if it isn't
+    /// invertible, e.g., in degenerate scenes.
+    pub fn covariance(&self) -> Option<Matrix6<f32>> {
+        self.information?.try_inverse()
+    }
+}
+
+/// Progress of an ICP run, passed to the callback of `align_with_callback` after every
+/// iteration.
+#[derive(Debug, Clone, Copy)]
+pub struct IterationInfo {
+    /// Number of iterations run, starting at 1.
+    pub iteration: usize,
+    /// Mean squared residual of the iteration.
+    pub mean_squared_residual: f32,
+    /// Norm of the translation part of the Gauss-Newton update.
+    pub update_translation: f32,
+    /// Norm of the rotation part of the Gauss-Newton update, in radians.
+    pub update_rotation: f32,
+    /// Number of correspondences of the iteration.
+    pub inlier_count: usize,
+    /// Time since the start of the run.
+    pub elapsed: Duration,
+}
+
+/// Callback of the ICP iterations. Returning [`ControlFlow::Break`] aborts the run, which
+/// then returns its best iterate so far without being marked as converged.
+pub type IterationCallback<'a> = dyn FnMut(IterationInfo) -> ControlFlow<()> + 'a;
+
+/// Keeps the best iterate of an ICP run and checks its termination criteria.
+pub(super) struct IterationTracker<'a> {
+    termination: IcpTermination,
+    information_estimate: InformationEstimate,
+    start: Instant,
+    previous_residual: Option<f32>,
+    best_cost: f32,
+    inlier_count: usize,
+    callback: Option<&'a mut IterationCallback<'a>>,
+    result: IcpResult,
+}
+
+impl<'a> IterationTracker<'a> {
+    /// Starts tracking a run.
+    ///
+    /// # Arguments
+    ///
+    /// * params - The parameters of the run.
+    /// * initial_transform - Returned if no iteration is recorded.
+    pub(super) fn new(params: &IcpParams, initial_transform: &Transform) -> Self {
+        Self::with_criteria(params.termination, params.information, initial_transform)
+    }
+
+    /// Same as [`IterationTracker::new`], for backends with their own parameter types.
+    ///
+    /// # Arguments
+    ///
+    /// * termination - The termination criteria.
+    /// * information_estimate - How the information matrix is estimated.
+    /// * initial_transform - Returned if no iteration is recorded.
+    pub(super) fn with_criteria(
+        termination: IcpTermination,
+        information_estimate: InformationEstimate,
+        initial_transform: &Transform,
+    ) -> Self {
+        Self {
+            termination,
+            information_estimate,
+            start: Instant::now(),
+            previous_residual: None,
+            best_cost: f32::INFINITY,
+            inlier_count: 0,
+            callback: None,
+            result: IcpResult {
+                transform: initial_transform.clone(),
+                iterations: 0,
+                final_rmse: f32::INFINITY,
+                inlier_count: 0,
+                converged: false,
+                rejection: RejectionStats::default(),
+                information: None,
+            },
+        }
+    }
+
+    /// Reports the iterations to a callback, which may abort the run.
+    pub(super) fn with_callback(mut self, callback: Option<&'a mut IterationCallback<'a>>) -> Self {
+        self.callback = callback;
+        self
+    }
+
+    /// Keeps an iterate if it has the smallest mean squared residual so far.
+    ///
+    /// # Arguments
+    ///
+    /// * transform - The transform of the iterate, i.e., after applying its update.
+    /// * optimizer - The optimizer with the residuals of the iterate.
+    /// * inlier_count - The number of correspondences.
+    ///
+    /// # Returns
+    ///
+    /// Whether it was kept.
+    pub(super) fn record(
+        &mut self,
+        transform: &Transform,
+        optimizer: &GaussNewton<6>,
+        inlier_count: usize,
+    ) -> bool {
+        self.record_with_cost(
+            transform,
+            optimizer.mean_squared_residual(),
+            optimizer,
+            inlier_count,
+        )
+    }
+
+    /// Same as [`IterationTracker::record`], with the iterates ranked by another cost.
+    pub(super) fn record_with_cost(
+        &mut self,
+        transform: &Transform,
+        cost: f32,
+        optimizer: &GaussNewton<6>,
+        inlier_count: usize,
+    ) -> bool {
+        self.inlier_count = inlier_count;
+        // A NaN cost never ranks better, so it must be rejected explicitly.
+        if cost.is_nan() || cost >= self.best_cost {
+            return false;
+        }
+        let mean_squared_residual = optimizer.mean_squared_residual();
+        let variance = match self.information_estimate {
+            InformationEstimate::ResidualVariance => {
+                // Unbiased by the 6 degrees of freedom of the fit.
+                let count = optimizer.count() as f32;
+                mean_squared_residual * count / (count - 6.0).max(1.0)
+            }
+            InformationEstimate::Censi { point_sigma } => 2.0 * point_sigma * point_sigma,
+        };
+
+        self.best_cost = cost;
+        self.result.transform = transform.clone();
+        self.result.final_rmse = mean_squared_residual.sqrt();
+        self.result.inlier_count = inlier_count;
+        self.result.information = Some(optimizer.hessian() / variance);
+        true
+    }
+
+    /// Sets the rejection counts of the best iterate.
+    pub(super) fn set_rejection(&mut self, rejection: RejectionStats) {
+        self.result.rejection = rejection;
+    }
+
+    /// Counts an iteration and checks the termination criteria.
+    ///
+    /// # Arguments
+    ///
+    /// * update - The Gauss-Newton update of the iteration, translation first.
+    /// * mean_squared_residual - The residual of the iteration.
+    ///
+    /// # Returns
+    ///
+    /// Whether to stop, also when the callback aborts the run.
+    pub(super) fn should_stop(
+        &mut self,
+        update: &Vector6<f32>,
+        mean_squared_residual: f32,
+    ) -> bool {
+        self.result.iterations += 1;
+        let update_translation = update.fixed_rows::<3>(0).norm();
+        let update_rotation = update.fixed_rows::<3>(3).norm();
+
+        if let Some(callback) = self.callback.as_mut() {
+            let info = IterationInfo {
+                iteration: self.result.iterations,
+                mean_squared_residual,
+                update_translation,
+                update_rotation,
+                inlier_count: self.inlier_count,
+                elapsed: self.start.elapsed(),
+            };
+            if callback(info).is_break() {
+                return true;
+            }
+        }
+
+        let small_update = update_translation < self.termination.min_translation
+            && update_rotation < self.termination.min_rotation;
+        let small_residual_change = self.previous_residual.is_some_and(|previous| {
+            (previous - mean_squared_residual).abs()
+                <= self.termination.min_relative_residual_change * previous
+        });
+        self.previous_residual = Some(mean_squared_residual);
+        if small_update || small_residual_change {
+            self.result.converged = true;
+            return true;
+        }
+
+        self.termination
+            .max_time
+            .is_some_and(|max_time| self.start.elapsed() >= max_time)
+    }
+
+    /// Ends the run.
+    pub(super) fn finish(self) -> IcpResult {
+        self.result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use nalgebra::{Matrix6, Vector6};
+
+    use super::IterationTracker;
+    use crate::{
+        icp::{icp_params::InformationEstimate, IcpParams, IcpTermination},
+        optim::GaussNewton,
+        transform::Transform,
+    };
+
+    /// Optimizer with 12 residuals of the same value, two per parameter.
+    fn optimizer(residual: f32) -> GaussNewton<6> {
+        let mut optimizer = GaussNewton::new();
+        for index in 0..12 {
+            let mut jacobian = [0.0; 6];
+            jacobian[index % 6] = 1.0;
+            optimizer.weighted_step(residual, &jacobian, 1.0);
+        }
+        optimizer
+    }
+
+    #[test]
+    fn test_termination() {
+        let params = IcpParams::default();
+        let mut tracker = IterationTracker::new(&params, &Transform::eye());
+        let update = Vector6::new(0.1, 0.0, 0.0, 0.0, 0.0, 0.0);
+        assert!(tracker.record(&Transform::eye(), &optimizer(2.0), 10));
+        assert!(!tracker.should_stop(&update, 4.0));
+        assert!(!tracker.record(&Transform::eye(), &optimizer(3.0), 10));
+        assert!(!tracker.should_stop(&update, 9.0));
+        assert!(tracker.should_stop(&Vector6::zeros(), 1.0));
+
+        let result = tracker.finish();
+        assert_eq!(result.iterations, 3);
+        assert_eq!(result.final_rmse, 2.0);
+        assert!(result.converged);
+
+        let mut tracker = IterationTracker::new(
+            &IcpParams {
+                termination: IcpTermination {
+                    max_time: Some(Duration::ZERO),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            &Transform::eye(),
+        );
+        assert!(tracker.should_stop(&update, 4.0));
+        assert!(!tracker.finish().converged);
+    }
+
+    #[test]
+    fn test_information() {
+        // Residual variance of 4 * 12 / 6.
+        let mut tracker = IterationTracker::new(&IcpParams::default(), &Transform::eye());
+        tracker.record(&Transform::eye(), &optimizer(2.0), 12);
+        let result = tracker.finish();
+        assert_eq!(result.information, Some(Matrix6::identity() * 0.25));
+        assert_eq!(result.covariance(), Some(Matrix6::identity() * 4.0));
+
+        let mut tracker = IterationTracker::new(
+            &IcpParams {
+                information: InformationEstimate::Censi { point_sigma: 0.5 },
+                ..Default::default()
+            },
+            &Transform::eye(),
+        );
+        tracker.record(&Transform::eye(), &optimizer(2.0), 12);
+        assert_eq!(
+            tracker.finish().information,
+            Some(Matrix6::identity() * 4.0)
+        );
+    }
+}
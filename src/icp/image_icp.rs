@@ -1,25 +1,50 @@
+use std::ops::ControlFlow;
+
 use itertools::izip;
 use nalgebra::Vector3;
 use ndarray::Axis;
-use num::Float;
 use rayon::prelude::{ParallelBridge, ParallelIterator};
 
 use crate::{
     extra_math,
-    optim::GaussNewton,
+    optim::{GaussNewton, RobustLoss},
     range_image::RangeImage,
     transform::{LieGroup, Transform},
 };
 
 use super::{
-    cost_function::{ColorDistance, PointPlaneDistance},
+    cost_function::ColorDistance,
     icp_params::IcpParams,
+    icp_result::{IcpResult, IterationCallback, IterationInfo, IterationTracker},
+    motion_prior::MotionPrior,
 };
 
+/// Mean variance of the valid points of an image, a third of the covariance trace, or zero
+/// if the image has no covariances.
+fn mean_variance(image: &RangeImage) -> f32 {
+    let Some(covariances) = image.covariances.as_ref() else {
+        return 0.0;
+    };
+    let (sum, count) = covariances
+        .iter()
+        .zip(image.mask.iter())
+        .filter(|(_, mask)| **mask == 1)
+        .fold((0.0, 0), |(sum, count), (covariance, _)| {
+            (sum + covariance.trace() / 3.0, count + 1)
+        });
+    if count > 0 {
+        sum / count as f32
+    } else {
+        0.0
+    }
+}
+
 pub struct ImageIcp<'target_lt> {
     pub params: IcpParams,
     target: &'target_lt RangeImage,
     pub initial_transform: Transform,
+    /// Prior whose residual is added to every iteration, see [`ImageIcp::with_motion_prior`].
+    pub motion_prior: Option<MotionPrior>,
 }
 
 impl<'target_lt> ImageIcp<'target_lt> {
@@ -28,10 +53,27 @@ impl<'target_lt> ImageIcp<'target_lt> {
             params,
             target,
             initial_transform: Transform::eye(),
+            motion_prior: None,
         }
     }
 
+    /// Starts the alignment from a motion prior and adds its residual to every iteration.
+    ///
+    /// # Arguments
+    ///
+    /// * `prior` - The motion prior.
+    pub fn with_motion_prior(mut self, prior: MotionPrior) -> Self {
+        self.initial_transform = prior.transform.clone();
+        self.motion_prior = Some(prior);
+        self
+    }
+
     /// Aligns the source point cloud to the target point cloud.
+    /// When the images have confidences, the residuals are weighted by the product of the
+    /// source and target point confidences. When they have covariances, the geometric
+    /// residuals are also weighted by the inverse of their variance along the target normal,
+    /// `n^T (R Σs R^T + Σt) n`, normalized by the mean point variance so the weights stay
+    /// balanced with the color term. This way, noisy far away points don't dominate.
     ///
     /// # Arguments
     ///
@@ -39,8 +81,32 @@ impl<'target_lt> ImageIcp<'target_lt> {
     ///
     /// # Returns
     ///
-    /// * The transformation that aligns the source point cloud to the target point cloud.
-    pub fn align(&self, source: &RangeImage) -> Transform {
+    /// * The transformation that aligns the source point cloud to the target point cloud,
+    ///   the inliers are the geometric residual terms.
+    pub fn align(&self, source: &RangeImage) -> IcpResult {
+        self.align_impl(source, None)
+    }
+
+    /// Same as `align`, reporting the progress of every iteration to a callback that can
+    /// abort the run.
+    ///
+    /// # Arguments
+    ///
+    /// * source - Same as `align`.
+    /// * callback - Called after every iteration, see [`IterationCallback`].
+    pub fn align_with_callback(
+        &self,
+        source: &RangeImage,
+        mut callback: impl FnMut(IterationInfo) -> ControlFlow<()>,
+    ) -> IcpResult {
+        self.align_impl(source, Some(&mut callback))
+    }
+
+    fn align_impl<'a>(
+        &self,
+        source: &RangeImage,
+        callback: Option<&'a mut IterationCallback<'a>>,
+    ) -> IcpResult {
         let intensity_map = self
             .target
             .intensity_map
@@ -56,9 +122,20 @@ impl<'target_lt> ImageIcp<'target_lt> {
             .as_ref()
             .expect("Please, the source image should have intensity colors.");
 
+        let source_confidences = source
+            .confidences
+            .as_ref()
+            .map(|confidences| confidences.to_shape(source.len()).unwrap());
+        let target_confidences = self.target.confidences.as_ref();
+        let source_covariances = source
+            .covariances
+            .as_ref()
+            .map(|covariances| covariances.to_shape(source.len()).unwrap());
+        let target_covariances = self.target.covariances.as_ref();
+        let variance_scale = mean_variance(source) + mean_variance(self.target);
+
         let mut optim_transform = self.initial_transform.clone();
 
-        let geometric_distance = PointPlaneDistance {};
         let color_distance = ColorDistance {};
 
         let max_color_distance_sqr =
@@ -68,12 +145,13 @@ impl<'target_lt> ImageIcp<'target_lt> {
         let mut geom_optim = GaussNewton::<6>::new();
         let mut color_optim = GaussNewton::<6>::new();
 
-        let mut best_residual = Float::infinity();
-        let mut best_transform = optim_transform.clone();
+        let mut tracker =
+            IterationTracker::new(&self.params, &optim_transform).with_callback(callback);
 
         const BATCH_SIZE: usize = 4096;
 
         for _ in 0..self.params.max_iterations {
+            let inverse_transform = optim_transform.inverse();
             let sub_gn_opts = izip!(
                 source
                     .mask
@@ -93,12 +171,15 @@ impl<'target_lt> ImageIcp<'target_lt> {
                     .unwrap()
                     .axis_chunks_iter(Axis(0), BATCH_SIZE)
             )
+            .enumerate()
             .par_bridge()
-            .map(|(mask_chunk, point_chunk, color_chunk)| {
+            .map(|(chunk_index, (mask_chunk, point_chunk, color_chunk))| {
                 let mut color_sub_opt = GaussNewton::<6>::new();
                 let mut geom_sub_opt = GaussNewton::<6>::new();
 
-                for (mask, point, color) in izip!(mask_chunk, point_chunk, color_chunk) {
+                for (k, (mask, point, color)) in
+                    izip!(mask_chunk, point_chunk, color_chunk).enumerate()
+                {
                     if *mask == 0 {
                         continue;
                     }
@@ -122,10 +203,37 @@ impl<'target_lt> ImageIcp<'target_lt> {
                         continue;
                     }
 
-                    let (residual, jacobian) =
-                        geometric_distance.jacobian(&p, &target_point, &target_normal);
+                    let weight = source_confidences
+                        .as_ref()
+                        .map_or(1.0, |confidences| confidences[chunk_index * BATCH_SIZE + k])
+                        * target_confidences.map_or(1.0, |confidences| {
+                            confidences[(v_int as usize, u_int as usize)]
+                        });
 
-                    geom_sub_opt.step(residual, &jacobian);
+                    let source_normal = inverse_transform.transform_normal(&target_normal);
+                    let variance = source_covariances.as_ref().map_or(0.0, |covariances| {
+                        (source_normal.transpose()
+                            * covariances[chunk_index * BATCH_SIZE + k]
+                            * source_normal)[0]
+                    }) + target_covariances.map_or(0.0, |covariances| {
+                        (target_normal.transpose()
+                            * covariances[(v_int as usize, u_int as usize)]
+                            * target_normal)[0]
+                    });
+                    let geometric_weight = if variance > 0.0 {
+                        weight * variance_scale / variance
+                    } else {
+                        weight
+                    };
+
+                    self.params.geometric_cost.step(
+                        &mut geom_sub_opt,
+                        &p,
+                        &target_point,
+                        &target_normal,
+                        geometric_weight,
+                        self.params.robust_loss.as_ref(),
+                    );
                     // Color part.
                     let (target_color, du, dv) = intensity_map.bilinear_grad(u, v);
                     let source_color = *color as f32 * 0.003_921_569; // / 255.0;
@@ -134,7 +242,15 @@ impl<'target_lt> ImageIcp<'target_lt> {
                     let (color_residual, color_jacobian) =
                         color_distance.jacobian(&p, &color_gradient, source_color, target_color);
                     if color_residual * color_residual <= max_color_distance_sqr {
-                        color_sub_opt.step(color_residual, &color_jacobian);
+                        let robust_weight = self
+                            .params
+                            .color_robust_loss
+                            .map_or(1.0, |loss| loss.weight(color_residual));
+                        color_sub_opt.weighted_step(
+                            color_residual,
+                            &color_jacobian,
+                            weight * robust_weight,
+                        );
                     }
                 }
 
@@ -147,20 +263,23 @@ impl<'target_lt> ImageIcp<'target_lt> {
                 geom_optim.add(&sub_gn.1);
             }
 
+            let num_residuals = geom_optim.count();
             geom_optim.add_weighted(&color_optim, self.params.weight, self.params.color_weight);
+            if let Some(prior) = &self.motion_prior {
+                prior.add_residuals(&mut geom_optim, &optim_transform);
+            }
             let residual = geom_optim.mean_squared_residual();
             let update = geom_optim.solve().unwrap();
             optim_transform = &Transform::exp(&LieGroup::Se3(update)) * &optim_transform;
-
+            tracker.record(&optim_transform, &geom_optim, num_residuals);
             geom_optim.reset();
             color_optim.reset();
 
-            if residual < best_residual {
-                best_residual = residual;
-                best_transform = optim_transform.clone();
+            if tracker.should_stop(&update, residual) {
+                break;
             }
         }
-        best_transform
+        tracker.finish()
     }
 }
 
@@ -168,12 +287,15 @@ impl<'target_lt> ImageIcp<'target_lt> {
 mod tests {
     use std::time::Instant;
 
+    use nalgebra::Matrix6;
     use rstest::rstest;
 
     use super::ImageIcp;
     use crate::{
-        icp::icp_params::IcpParams,
+        icp::{icp_params::IcpParams, MotionPrior},
+        io::dataset::DepthNoiseParams,
         metrics::TransformMetrics,
+        transform::Transform,
         unit_test::{sample_range_img_ds2, TestRangeImageDataset},
     };
 
@@ -192,10 +314,55 @@ mod tests {
             },
             &rimage0,
         )
-        .align(&rimage1);
+        .align(&rimage1)
+        .transform;
         println!("Align computed in {:?}", now.elapsed());
         let angle_diff = TransformMetrics::new(&actual, &gt_transform).angle.abs();
         println!("Result metric: {}", angle_diff);
         assert!(angle_diff < 0.01);
     }
+
+    #[rstest]
+    fn test_motion_prior(sample_range_img_ds2: TestRangeImageDataset) {
+        let rimage0 = sample_range_img_ds2.get(0).unwrap();
+        let rimage1 = sample_range_img_ds2.get(1).unwrap();
+        let gt_transform = sample_range_img_ds2.get_ground_truth(1, 0);
+        let params = IcpParams {
+            max_iterations: 5,
+            ..Default::default()
+        };
+
+        // A weak prior at the ground truth doesn't harm the alignment.
+        let weak = MotionPrior::new(gt_transform.clone(), &(Matrix6::identity() * 1e-2)).unwrap();
+        let icp = ImageIcp::new(params, &rimage0).with_motion_prior(weak);
+        let metrics = TransformMetrics::new(&icp.align(&rimage1).transform, &gt_transform);
+        assert!(metrics.angle < 0.01, "{metrics}");
+
+        // A strong prior dominates the residuals.
+        let strong = MotionPrior::new(Transform::eye(), &(Matrix6::identity() * 1e-12)).unwrap();
+        let icp = ImageIcp::new(params, &rimage0).with_motion_prior(strong);
+        let metrics = TransformMetrics::new(&icp.align(&rimage1).transform, &Transform::eye());
+        assert!(metrics.angle < 1e-3, "{metrics}");
+        assert!(metrics.translation < 1e-3, "{metrics}");
+    }
+
+    #[rstest]
+    fn test_covariance_weighting(sample_range_img_ds2: TestRangeImageDataset) {
+        let mut rimage0 = sample_range_img_ds2.get(0).unwrap();
+        let mut rimage1 = sample_range_img_ds2.get(1).unwrap();
+        let gt_transform = sample_range_img_ds2.get_ground_truth(1, 0);
+        rimage0.compute_covariances(&DepthNoiseParams::default());
+        rimage1.compute_covariances(&DepthNoiseParams::default());
+
+        let result = ImageIcp::new(
+            IcpParams {
+                max_iterations: 5,
+                ..Default::default()
+            },
+            &rimage0,
+        )
+        .align(&rimage1);
+        let metrics = TransformMetrics::new(&result.transform, &gt_transform);
+        assert!(metrics.angle < 0.01, "{metrics}");
+    }
 }
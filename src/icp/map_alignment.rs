@@ -0,0 +1,123 @@
+use ndarray::Array1;
+
+use super::gicp::{Gicp, GicpParams};
+use crate::{pointcloud::PointCloud, transform::Transform};
+
+/// Parameters of [`align_maps`].
+#[derive(Debug, Clone)]
+pub struct MapAlignmentParams {
+    /// Maximum correspondence distance of each refinement stage, from coarse to fine. The
+    /// first one should cover the error of the initial alignment.
+    pub max_distances: Vec<f32>,
+    /// Maximum number of iterations per stage.
+    pub max_iterations: usize,
+    /// Maximum number of points of each map used for the alignment, larger maps are
+    /// uniformly subsampled.
+    pub max_points: usize,
+}
+
+impl Default for MapAlignmentParams {
+    fn default() -> Self {
+        Self {
+            max_distances: vec![0.5, 0.2, 0.05],
+            max_iterations: 20,
+            max_points: 50_000,
+        }
+    }
+}
+
+fn subsample(pcl: &PointCloud, max_points: usize) -> PointCloud {
+    let stride = pcl.len().div_ceil(max_points.max(1)).max(1);
+    PointCloud {
+        points: pcl
+            .points
+            .iter()
+            .step_by(stride)
+            .cloned()
+            .collect::<Array1<_>>(),
+        normals: None,
+        colors: None,
+        intensities: None,
+        curvatures: None,
+        attributes: Default::default(),
+    }
+}
+
+/// Aligns two reconstructions of the same place, e.g., scans taken on different days.
+/// Starting from a rough initial alignment, the source map is refined by Generalized ICP
+/// with decreasing correspondence distances.
+///
+/// # Arguments
+///
+/// * target - The reference map.
+/// * source - The map to align.
+/// * initial - Rough source to target transformation, e.g., set by hand in the viewer.
+/// * params - Parameters of the alignment.
+///
+/// # Returns
+///
+/// The refined transformation from the source map into the target map.
+pub fn align_maps(
+    target: &PointCloud,
+    source: &PointCloud,
+    initial: &Transform,
+    params: &MapAlignmentParams,
+) -> Transform {
+    let target = subsample(target, params.max_points);
+    let source = subsample(source, params.max_points);
+
+    let mut gicp = Gicp::new(
+        GicpParams {
+            max_iterations: params.max_iterations,
+            ..Default::default()
+        },
+        &target,
+    );
+    let mut transform = initial.clone();
+    for max_distance in params.max_distances.iter() {
+        gicp.params.max_distance = *max_distance;
+        gicp.initial_transform = transform;
+        transform = gicp.align(&source).transform;
+    }
+    transform
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rstest::rstest;
+
+    use super::{align_maps, MapAlignmentParams};
+    use crate::{
+        metrics::TransformMetrics, pointcloud::PointCloud, transform::TransformBuilder,
+        unit_test::sample_teapot_surface,
+    };
+
+    #[rstest]
+    fn test_align_maps(sample_teapot_surface: PointCloud) {
+        let source_to_target = TransformBuilder::default()
+            .translation(Vector3::new(0.8, -0.4, 0.3))
+            .axis_angle(Vector3::z_axis(), 0.6)
+            .build();
+        let source = &source_to_target.inverse() * &sample_teapot_surface;
+
+        // A seed a few centimeters and degrees off, as placed by hand.
+        let initial = TransformBuilder::default()
+            .translation(Vector3::new(0.75, -0.38, 0.33))
+            .axis_angle(Vector3::z_axis(), 0.55)
+            .build();
+
+        let actual = align_maps(
+            &sample_teapot_surface,
+            &source,
+            &initial,
+            &MapAlignmentParams {
+                max_points: 2000,
+                ..Default::default()
+            },
+        );
+        let metrics = TransformMetrics::new(&actual, &source_to_target);
+        assert!(metrics.translation < 1e-2);
+        assert!(metrics.angle < 1e-2);
+    }
+}
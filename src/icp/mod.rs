@@ -1,8 +1,51 @@
 mod icp_params;
-pub use icp_params::{IcpParams, MsIcpParams};
+pub use crate::optim::{RobustKernel, RobustLoss};
+pub use icp_params::{GeometricCost, IcpParams, IcpTermination, InformationEstimate, MsIcpParams};
+mod icp_result;
+pub use icp_result::{IcpResult, IterationCallback, IterationInfo};
+mod colored_icp;
+pub use colored_icp::ColoredIcp;
 mod cost_function;
+mod rejection;
+pub use rejection::{CorrespondenceRejection, RejectionStats};
 mod pcl_icp;
 pub use pcl_icp::Icp;
+mod point_repr;
+pub use point_repr::PointRepr;
+mod point_line_icp;
+pub use point_line_icp::PointLineIcp;
+mod gicp;
+pub use gicp::{compute_covariances, Gicp, GicpParams};
+mod ndt;
+pub use ndt::Ndt;
+mod global_registration;
+pub use global_registration::{
+    global_registration, GlobalRegistrationParams, GlobalRegistrationResult,
+};
+mod fgr;
+pub use fgr::{fast_global_registration, FgrParams};
+mod teaser;
+pub use teaser::{teaser_registration, teaser_solve, TeaserParams, TeaserSolution};
+mod four_pcs;
+pub use four_pcs::{four_pcs, FourPcsCandidate, FourPcsParams};
+mod map_alignment;
+pub use map_alignment::{align_maps, MapAlignmentParams};
+mod multiview;
+pub use multiview::{multiview_registration, MultiviewParams, MultiviewResult};
+mod motion_prior;
+pub use motion_prior::MotionPrior;
 mod image_icp;
 pub use image_icp::ImageIcp;
+mod dense_odometry;
+pub use dense_odometry::{DenseOdometry, DenseOdometryParams, PhotometricFormulation};
+mod projective_icp;
+pub use projective_icp::ProjectiveIcp;
+#[cfg(feature = "viz")]
+mod gpu_icp;
+#[cfg(feature = "viz")]
+pub use gpu_icp::GpuIcpKernel;
+#[cfg(feature = "viz")]
+mod gpu_nearest;
+#[cfg(feature = "viz")]
+pub use gpu_nearest::{GpuNearestNeighbor, GpuPoints};
 pub mod multiscale;
@@ -0,0 +1,115 @@
+use nalgebra::Matrix6;
+
+use crate::{error::A3dError, optim::GaussNewton, transform::Transform};
+
+/// Prior knowledge of the source to target transformation, e.g., integrated from a
+/// gyroscope or predicted by a constant velocity model. It initializes ICP and adds a weak
+/// residual pulling the estimate towards it, which keeps the alignment stable where the
+/// geometry doesn't constrain every direction, e.g., corridors or flat walls.
+#[derive(Debug, Clone)]
+pub struct MotionPrior {
+    /// The predicted transformation.
+    pub transform: Transform,
+    /// Transposed Cholesky factor of the information, `L^T` with `L L^T = Σ⁻¹`.
+    sqrt_information: Matrix6<f32>,
+}
+
+impl MotionPrior {
+    /// Creates a prior.
+    ///
+    /// # Arguments
+    ///
+    /// * transform - The predicted transformation.
+    /// * covariance - Covariance of the prediction, in the order of the Gauss-Newton
+    ///   updates, translation then rotation, as a perturbation applied on the left. The
+    ///   ICP residuals have unit weights, so the prior weighs as much as a correspondence
+    ///   when its variance is 1 squared meter, smaller variances make it stronger.
+    ///
+    /// # Returns
+    ///
+    /// The prior, or error if the covariance isn't symmetric positive definite.
+    pub fn new(transform: Transform, covariance: &Matrix6<f32>) -> Result<Self, A3dError> {
+        let information = covariance
+            .cholesky()
+            .map(|cholesky| cholesky.inverse())
+            .ok_or_else(|| {
+                A3dError::invalid_parameter("The prior covariance must be positive definite.")
+            })?;
+        let sqrt_information = information
+            .cholesky()
+            .ok_or_else(|| {
+                A3dError::invalid_parameter("The prior covariance must be positive definite.")
+            })?
+            .l()
+            .transpose();
+        Ok(Self {
+            transform,
+            sqrt_information,
+        })
+    }
+
+    /// Adds the prior residuals of the current estimate, the whitened difference between
+    /// the prior and the estimate, with the Jacobian of a small difference.
+    ///
+    /// # Arguments
+    ///
+    /// * optimizer - The optimizer of the iteration.
+    /// * transform - The current estimate.
+    pub(super) fn add_residuals(&self, optimizer: &mut GaussNewton<6>, transform: &Transform) {
+        let difference = (&self.transform * &transform.inverse()).log();
+        let residuals = self.sqrt_information * difference;
+        for (row, residual) in residuals.iter().enumerate() {
+            let jacobian: [f32; 6] = self.sqrt_information.row(row).transpose().into();
+            optimizer.weighted_step(*residual, &jacobian, 1.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix6, Vector3};
+
+    use super::MotionPrior;
+    use crate::{
+        optim::GaussNewton,
+        transform::{Transform, TransformBuilder},
+    };
+
+    #[test]
+    fn test_prior_alone_recovers_transform() {
+        let transform = TransformBuilder::default()
+            .translation(Vector3::new(0.1, -0.2, 0.05))
+            .axis_angle(Vector3::y_axis(), 0.1)
+            .build();
+        let prior = MotionPrior::new(transform.clone(), &(Matrix6::identity() * 1e-4)).unwrap();
+
+        let mut optimizer = GaussNewton::new();
+        prior.add_residuals(&mut optimizer, &Transform::eye());
+        assert!((optimizer.hessian() - Matrix6::identity() * 1e4).norm() < 1.0);
+        let update = optimizer.solve().unwrap();
+        assert!((update - transform.log()).norm() < 1e-4);
+
+        assert!(MotionPrior::new(transform, &Matrix6::zeros()).is_err());
+    }
+
+    #[test]
+    fn test_prior_near_estimate() {
+        let transform = TransformBuilder::default()
+            .translation(Vector3::new(0.1, -0.2, 0.05))
+            .axis_angle(Vector3::y_axis(), 0.1)
+            .build();
+        let estimate = &TransformBuilder::default()
+            .axis_angle(Vector3::x_axis(), 1e-3)
+            .build()
+            * &transform;
+        let prior = MotionPrior::new(transform.clone(), &(Matrix6::identity() * 1e-4)).unwrap();
+
+        let mut optimizer = GaussNewton::new();
+        prior.add_residuals(&mut optimizer, &estimate);
+        let update = optimizer.solve().unwrap();
+        assert!(update.iter().all(|value| value.is_finite()));
+        let expected = (&transform * &estimate.inverse()).log();
+        assert!((update - expected).norm() < 1e-6, "{update} != {expected}");
+        assert!((expected[3] + 1e-3).abs() < 1e-6);
+    }
+}
@@ -1,4 +1,11 @@
-use super::{ImageIcp, MsIcpParams};
+use std::{
+    ops::ControlFlow,
+    time::{Duration, Instant},
+};
+
+use super::{
+    IcpParams, IcpResult, ImageIcp, IterationInfo, MotionPrior, MsIcpParams, RobustKernel,
+};
 use crate::{error::A3dError, range_image::RangeImage, transform::Transform};
 use itertools::izip;
 
@@ -6,7 +13,9 @@ use itertools::izip;
 /// TODO: Make it generic for point cloud ICP.
 pub struct MultiscaleAlign<'pyramid_lt> {
     params: MsIcpParams,
-    target_pyramid: &'pyramid_lt Vec<RangeImage>,
+    target_pyramid: &'pyramid_lt [RangeImage],
+    initial_transform: Option<Transform>,
+    motion_prior: Option<MotionPrior>,
 }
 
 impl<'pyramid_lt> MultiscaleAlign<'pyramid_lt> {
@@ -25,7 +34,7 @@ impl<'pyramid_lt> MultiscaleAlign<'pyramid_lt> {
     ///   of ICP parameters are equal.
     pub fn new(
         params: MsIcpParams,
-        target_pyramid: &'pyramid_lt Vec<RangeImage>,
+        target_pyramid: &'pyramid_lt [RangeImage],
     ) -> Result<Self, A3dError> {
         if params.len() != target_pyramid.len() {
             return Err(A3dError::invalid_parameter(
@@ -36,9 +45,33 @@ impl<'pyramid_lt> MultiscaleAlign<'pyramid_lt> {
         Ok(Self {
             target_pyramid,
             params,
+            initial_transform: None,
+            motion_prior: None,
         })
     }
 
+    /// Starts the alignment from a transform instead of the identity, or of the motion
+    /// prior if there is one.
+    ///
+    /// # Arguments
+    ///
+    /// * transform: Initial guess of the alignment.
+    pub fn with_initial_transform(mut self, transform: Transform) -> Self {
+        self.initial_transform = Some(transform);
+        self
+    }
+
+    /// Starts the alignment from a motion prior and adds its residual to the iterations of
+    /// every level, see [`ImageIcp::with_motion_prior`].
+    ///
+    /// # Arguments
+    ///
+    /// * prior: The motion prior.
+    pub fn with_motion_prior(mut self, prior: MotionPrior) -> Self {
+        self.motion_prior = Some(prior);
+        self
+    }
+
     /// Aligns the source point cloud to the target point cloud.
     ///
     /// # Arguments
@@ -47,35 +80,232 @@ impl<'pyramid_lt> MultiscaleAlign<'pyramid_lt> {
     ///
     /// # Returns
     ///
-    /// * The optimized transform.
-    pub fn align(&self, source_pyramid: &[RangeImage]) -> Transform {
-        let mut optim_transform = Transform::eye();
+    /// * The result of the finest level, with the iterations of all levels.
+    pub fn align(&self, source_pyramid: &[RangeImage]) -> IcpResult {
+        self.align_with_callback(source_pyramid, |_| ControlFlow::Continue(()))
+    }
+
+    /// Same as `align`, reporting the progress of every iteration to a callback that can
+    /// abort the run. The levels are reported from the coarsest one, with the iterations
+    /// counted across levels.
+    ///
+    /// # Arguments
+    ///
+    /// * source_pyramid: The source point cloud pyramid.
+    /// * callback: Called after every iteration, see [`super::IterationCallback`].
+    pub fn align_with_callback(
+        &self,
+        source_pyramid: &[RangeImage],
+        callback: impl FnMut(IterationInfo) -> ControlFlow<()>,
+    ) -> IcpResult {
+        self.align_with_reports(source_pyramid, callback).0
+    }
+
+    /// Same as `align_with_callback`, also returning the report of every level run.
+    ///
+    /// # Arguments
+    ///
+    /// * source_pyramid: The source point cloud pyramid.
+    /// * callback: Called after every iteration, see [`super::IterationCallback`].
+    ///
+    /// # Returns
+    ///
+    /// * The result of the finest level run, with the iterations of all levels.
+    /// * The report of each level, from the coarsest. The levels after an abort are
+    ///   missing.
+    pub fn align_with_reports(
+        &self,
+        source_pyramid: &[RangeImage],
+        mut callback: impl FnMut(IterationInfo) -> ControlFlow<()>,
+    ) -> (IcpResult, Vec<IcpLevelReport>) {
+        let mut optim_transform = self.initial_transform.clone().unwrap_or_else(|| {
+            self.motion_prior
+                .as_ref()
+                .map_or_else(Transform::eye, |prior| prior.transform.clone())
+        });
+        let mut iterations = 0;
+        let mut reports: Vec<IcpLevelReport> = Vec::with_capacity(self.params.len());
 
-        for (params, target, source) in izip!(
+        for (level, params, target, source) in izip!(
+            0..self.params.len(),
             self.params.iter(),
             self.target_pyramid.iter(),
             source_pyramid.iter()
         )
         .rev()
         {
+            let start = Instant::now();
             let mut icp = ImageIcp::new(*params, target);
             icp.initial_transform = optim_transform;
-            optim_transform = icp.align(source);
+            icp.motion_prior = self.motion_prior.clone();
+            let mut aborted = false;
+            let level_result = icp.align_with_callback(source, |mut info| {
+                info.iteration += iterations;
+                let flow = callback(info);
+                aborted = flow.is_break();
+                flow
+            });
+            iterations += level_result.iterations;
+            optim_transform = level_result.transform.clone();
+            reports.push(IcpLevelReport {
+                level,
+                result: level_result,
+                elapsed: start.elapsed(),
+            });
+            if aborted {
+                break;
+            }
+        }
+
+        let mut result = reports
+            .last()
+            .expect("The pyramid should have at least one level.")
+            .result
+            .clone();
+        result.iterations = iterations;
+        (result, reports)
+    }
+}
+
+/// Settings of one pyramid level of an [`IcpPyramidSchedule`].
+#[derive(Debug, Clone, Copy)]
+pub struct IcpLevel {
+    /// Maximum number of iterations.
+    pub max_iterations: usize,
+    /// Maximum distance between corresponding points.
+    pub max_distance: f32,
+    /// Robust loss of the geometric residuals, see [`IcpParams::robust_loss`].
+    pub robust_loss: Option<RobustKernel>,
+}
+
+/// Diagnostics of one pyramid level run by [`MultiscaleAlign::align_with_reports`].
+#[derive(Debug, Clone)]
+pub struct IcpLevelReport {
+    /// Pyramid level, 0 is the finest.
+    pub level: usize,
+    /// Result of the level, the inliers are the geometric residual terms, one per
+    /// correspondence for point-to-plane.
+    pub result: IcpResult,
+    /// Time spent on the level.
+    pub elapsed: Duration,
+}
+
+/// Coarse-to-fine ICP over range image pyramids, with settings scheduled per level, e.g.,
+/// loose distances and strong robust kernels at the coarse levels, where the misalignment
+/// is large, tightening towards the finest one.
+#[derive(Debug, Clone)]
+pub struct IcpPyramidSchedule {
+    /// Parameters shared by all levels.
+    pub base: IcpParams,
+    /// Settings of each level, index 0 is the finest.
+    pub levels: Vec<IcpLevel>,
+}
+
+impl Default for IcpPyramidSchedule {
+    /// Three levels, same iterations as [`MsIcpParams::default`], with the correspondence
+    /// distance and the robust scale halved at each finer level.
+    fn default() -> Self {
+        let base = MsIcpParams::default()[0];
+        Self {
+            base,
+            levels: [(20, 0.1), (20, 0.2), (30, 0.4)]
+                .into_iter()
+                .map(|(max_iterations, max_distance)| IcpLevel {
+                    max_iterations,
+                    max_distance,
+                    robust_loss: Some(RobustKernel::Huber {
+                        scale: max_distance * 0.25,
+                    }),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl IcpPyramidSchedule {
+    /// The ICP parameters of each level.
+    pub fn params(&self) -> MsIcpParams {
+        MsIcpParams::new(
+            self.levels
+                .iter()
+                .map(|level| IcpParams {
+                    max_iterations: level.max_iterations,
+                    max_distance: level.max_distance,
+                    robust_loss: level.robust_loss,
+                    ..self.base
+                })
+                .collect(),
+        )
+    }
+
+    /// The multiscale alignment of the schedule, to run it with a callback or a motion
+    /// prior.
+    ///
+    /// # Arguments
+    ///
+    /// * target_pyramid - The target range images, index 0 is the finest.
+    ///
+    /// # Returns
+    ///
+    /// * The alignment to the target pyramid.
+    /// * Err(Error(InvalidParameter)) if the pyramid and the schedule have different
+    ///   number of levels.
+    pub fn multiscale<'pyramid_lt>(
+        &self,
+        target_pyramid: &'pyramid_lt [RangeImage],
+    ) -> Result<MultiscaleAlign<'pyramid_lt>, A3dError> {
+        MultiscaleAlign::new(self.params(), target_pyramid)
+    }
+
+    /// Aligns the source pyramid to the target pyramid, from the coarsest level to the
+    /// finest, see [`IcpPyramidSchedule::multiscale`].
+    ///
+    /// # Arguments
+    ///
+    /// * target_pyramid - The target range images, index 0 is the finest.
+    /// * source_pyramid - The source range images, with the same levels.
+    /// * initial_transform - Initial guess of the alignment.
+    ///
+    /// # Returns
+    ///
+    /// * The optimized transform and the report of each level, from the coarsest.
+    /// * Err(Error(InvalidParameter)) if the pyramids and the schedule have different
+    ///   number of levels.
+    pub fn align(
+        &self,
+        target_pyramid: &[RangeImage],
+        source_pyramid: &[RangeImage],
+        initial_transform: Transform,
+    ) -> Result<(Transform, Vec<IcpLevelReport>), A3dError> {
+        if target_pyramid.len() != self.levels.len() || source_pyramid.len() != self.levels.len() {
+            return Err(A3dError::invalid_parameter(
+                "The number of range images pyramid levels and scheduled levels must be equal.",
+            ));
         }
 
-        optim_transform
+        let (result, reports) = self
+            .multiscale(target_pyramid)?
+            .with_initial_transform(initial_transform)
+            .align_with_reports(source_pyramid, |_| ControlFlow::Continue(()));
+        Ok((result.transform, reports))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::ops::ControlFlow;
+
     use rstest::rstest;
 
+    use super::IcpPyramidSchedule;
     use crate::{
         bilateral::BilateralFilter,
         icp::{IcpParams, MsIcpParams},
+        io::dataset::RgbdDataset,
+        metrics::TransformMetrics,
         range_image::RangeImageBuilder,
-        unit_test::{sample_rgbd_frame_dataset1, TestRgbdFrameDataset},
+        transform::Transform,
+        unit_test::{sample_rgbd_dataset1, sample_rgbd_frame_dataset1, TestRgbdFrameDataset},
     };
 
     #[rstest]
@@ -90,8 +320,54 @@ mod tests {
         let align = super::MultiscaleAlign {
             target_pyramid: &target,
             params: MsIcpParams::repeat(3, &IcpParams::default()),
+            initial_transform: None,
+            motion_prior: None,
         };
         // Just test that it doesn't crash. Use integration tests for more thorough testing.
         let _ = align.align(&source);
     }
+
+    #[rstest]
+    fn test_pyramid_schedule(
+        sample_rgbd_frame_dataset1: TestRgbdFrameDataset,
+        sample_rgbd_dataset1: impl RgbdDataset,
+    ) {
+        let ri_builder = RangeImageBuilder::default()
+            .with_intensity(true)
+            .with_normals(true);
+        let target = ri_builder.build(sample_rgbd_frame_dataset1.get_item(0).unwrap());
+        let source = ri_builder.build(sample_rgbd_frame_dataset1.get_item(2).unwrap());
+
+        let schedule = IcpPyramidSchedule::default();
+        let (transform, reports) = schedule.align(&target, &source, Transform::eye()).unwrap();
+        assert_eq!(
+            reports
+                .iter()
+                .map(|report| report.level)
+                .collect::<Vec<_>>(),
+            vec![2, 1, 0]
+        );
+        assert!(reports.iter().all(|report| report.result.inlier_count > 0));
+        assert_eq!(reports[2].result.transform.0, transform.0);
+
+        let gt_transform = sample_rgbd_dataset1
+            .trajectory()
+            .unwrap()
+            .get_relative_transform(2, 0)
+            .unwrap();
+        assert!(TransformMetrics::new(&transform, &gt_transform).angle < 0.02);
+
+        assert!(schedule
+            .align(&target[..2], &source[..2], Transform::eye())
+            .is_err());
+
+        // Aborting at the first iteration skips the finer levels.
+        let (result, reports) = schedule
+            .multiscale(&target)
+            .unwrap()
+            .align_with_reports(&source, |_| ControlFlow::Break(()));
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].level, 2);
+        assert_eq!(result.iterations, 1);
+    }
 }
@@ -0,0 +1,317 @@
+use nalgebra::{DMatrix, DVector, Vector6};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use super::cost_function::PointPlaneDistance;
+use crate::{
+    error::A3dError,
+    kdtree::R3dTree,
+    optim::{GaussNewton, RobustKernel, RobustLoss},
+    pointcloud::PointCloud,
+    transform::{LieGroup, Transform},
+};
+
+/// Parameters of [`multiview_registration`].
+#[derive(Debug, Clone, Copy)]
+pub struct MultiviewParams {
+    /// Maximum number of iterations.
+    pub max_iterations: usize,
+    /// Maximum distance between two points to be considered as the same.
+    pub max_distance: f32,
+    /// Robust loss of the point-to-plane residuals, `None` for least squares.
+    pub robust_loss: Option<RobustKernel>,
+    /// Converged when no pose update is larger than this, as the norm of its twist.
+    pub min_update: f32,
+}
+
+impl Default for MultiviewParams {
+    fn default() -> Self {
+        Self {
+            max_iterations: 30,
+            max_distance: 0.1,
+            robust_loss: None,
+            min_update: 1e-5,
+        }
+    }
+}
+
+/// Result of [`multiview_registration`].
+#[derive(Debug, Clone)]
+pub struct MultiviewResult {
+    /// Transformation from each cloud into the frame of the first one.
+    pub poses: Vec<Transform>,
+    /// Number of iterations run.
+    pub iterations: usize,
+    /// Root mean squared point-to-plane residual of the last iteration.
+    pub final_rmse: f32,
+    /// Whether the updates became smaller than [`MultiviewParams::min_update`].
+    pub converged: bool,
+}
+
+/// Whether every cloud is reachable from the first one through the overlaps.
+fn is_connected(num_clouds: usize, overlaps: &[(usize, usize)]) -> bool {
+    let mut reached = vec![false; num_clouds];
+    let mut stack = vec![0];
+    reached[0] = true;
+    while let Some(current) = stack.pop() {
+        for &(i, j) in overlaps {
+            for (from, to) in [(i, j), (j, i)] {
+                if from == current && !reached[to] {
+                    reached[to] = true;
+                    stack.push(to);
+                }
+            }
+        }
+    }
+    reached.into_iter().all(|reached| reached)
+}
+
+/// Accumulates the point-to-plane residuals between the points of a source cloud and their
+/// nearest neighbors in a target cloud. The Jacobian has the source pose update in its
+/// first 6 entries and the target one in its last 6.
+fn pair_normal_equations(
+    source: &PointCloud,
+    target: &PointCloud,
+    target_kdtree: &R3dTree,
+    source_pose: &Transform,
+    target_pose: &Transform,
+    params: &MultiviewParams,
+) -> GaussNewton<12> {
+    let target_normals = target.normals.as_ref().unwrap();
+    let source_to_target = &target_pose.inverse() * source_pose;
+    let max_distance_sqr = params.max_distance * params.max_distance;
+    let point_plane = PointPlaneDistance {};
+
+    let mut optimizer = GaussNewton::<12>::new();
+    for source_point in source.points.iter() {
+        let (index, sqr_distance) =
+            target_kdtree.nearest(&source_to_target.transform_vector(source_point));
+        if sqr_distance > max_distance_sqr {
+            continue;
+        }
+
+        let source_point = source_pose.transform_vector(source_point);
+        let target_point = target_pose.transform_vector(&target.points[index]);
+        let target_normal = target_pose.transform_normal(&target_normals[index]);
+        let (residual, jacobian) =
+            point_plane.jacobian(&source_point, &target_point, &target_normal);
+
+        // Moving the target pose along the residual's normal moves both the plane and
+        // its normal, which has the opposite effect of moving the source point.
+        let mut pair_jacobian = [0.0; 12];
+        for (k, value) in jacobian.iter().enumerate() {
+            pair_jacobian[k] = *value;
+            pair_jacobian[k + 6] = -*value;
+        }
+        let weight = params
+            .robust_loss
+            .as_ref()
+            .map_or(1.0, |loss| loss.weight(residual));
+        optimizer.weighted_step(residual, &pair_jacobian, weight);
+    }
+    optimizer
+}
+
+/// Registers several overlapping point clouds at once. Every overlapping pair contributes
+/// its point-to-plane ICP residuals, and all the poses are updated together by Gauss-Newton,
+/// which spreads the error over the views instead of accumulating it along a chain of
+/// pairwise alignments.
+///
+/// # Arguments
+///
+/// * clouds - The point clouds, all must have normals.
+/// * overlaps - Pairs of indices of overlapping clouds, every cloud must be connected to
+///   the first one through them.
+/// * initial_poses - Initial transformation from each cloud into a common frame. The first
+///   pose is kept fixed and anchors the solution.
+/// * params - Parameters of the registration.
+///
+/// # Returns
+///
+/// The poses of the clouds, or error if the inputs are inconsistent.
+pub fn multiview_registration(
+    clouds: &[PointCloud],
+    overlaps: &[(usize, usize)],
+    initial_poses: &[Transform],
+    params: &MultiviewParams,
+) -> Result<MultiviewResult, A3dError> {
+    if clouds.is_empty() || clouds.len() != initial_poses.len() {
+        return Err(A3dError::invalid_parameter(
+            "Multiview registration needs one initial pose per cloud.",
+        ));
+    }
+    if clouds.iter().any(|cloud| cloud.normals.is_none()) {
+        return Err(A3dError::invalid_parameter(
+            "Multiview registration requires the clouds normals.",
+        ));
+    }
+    if overlaps
+        .iter()
+        .any(|&(i, j)| i == j || i >= clouds.len() || j >= clouds.len())
+    {
+        return Err(A3dError::invalid_parameter(
+            "The overlaps must be pairs of different cloud indices.",
+        ));
+    }
+    if !is_connected(clouds.len(), overlaps) {
+        return Err(A3dError::invalid_parameter(
+            "Every cloud must overlap, directly or not, with the first one.",
+        ));
+    }
+
+    let kdtrees = clouds
+        .iter()
+        .map(|cloud| R3dTree::new(&cloud.points.view()))
+        .collect::<Vec<_>>();
+    let mut poses = initial_poses.to_vec();
+    let num_free = 6 * (clouds.len() - 1);
+    // Offset of a pose update in the system, the first pose is fixed.
+    let offset = |cloud: usize| (cloud > 0).then(|| 6 * (cloud - 1));
+
+    let mut result = MultiviewResult {
+        poses: Vec::new(),
+        iterations: 0,
+        final_rmse: 0.0,
+        converged: false,
+    };
+    for _ in 0..params.max_iterations {
+        let pair_optimizers = overlaps
+            .par_iter()
+            .map(|&(i, j)| {
+                pair_normal_equations(
+                    &clouds[i],
+                    &clouds[j],
+                    &kdtrees[j],
+                    &poses[i],
+                    &poses[j],
+                    params,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut hessian = DMatrix::<f64>::zeros(num_free, num_free);
+        let mut gradient = DVector::<f64>::zeros(num_free);
+        let (mut squared_residual_sum, mut count) = (0.0, 0);
+        for (&(i, j), optimizer) in overlaps.iter().zip(pair_optimizers.iter()) {
+            if optimizer.count() == 0 {
+                continue;
+            }
+            squared_residual_sum += optimizer.mean_squared_residual() * optimizer.count() as f32;
+            count += optimizer.count();
+
+            let blocks = [(0, offset(i)), (6, offset(j))];
+            for (row_block, row_offset) in blocks {
+                let Some(row_offset) = row_offset else {
+                    continue;
+                };
+                for row in 0..6 {
+                    gradient[row_offset + row] += optimizer.gradient()[row_block + row] as f64;
+                }
+                for (col_block, col_offset) in blocks {
+                    let Some(col_offset) = col_offset else {
+                        continue;
+                    };
+                    for row in 0..6 {
+                        for col in 0..6 {
+                            hessian[(row_offset + row, col_offset + col)] +=
+                                optimizer.hessian()[(row_block + row, col_block + col)] as f64;
+                        }
+                    }
+                }
+            }
+        }
+
+        if count == 0 {
+            break;
+        }
+        result.iterations += 1;
+        result.final_rmse = (squared_residual_sum / count as f32).sqrt();
+        let Some(cholesky) = hessian.cholesky() else {
+            break;
+        };
+        let update = cholesky.solve(&gradient);
+
+        let mut max_update = 0.0f32;
+        for (cloud, pose) in poses.iter_mut().enumerate().skip(1) {
+            let offset = 6 * (cloud - 1);
+            let twist = Vector6::from_fn(|k, _| update[offset + k] as f32);
+            max_update = max_update.max(twist.norm());
+            *pose = &Transform::exp(&LieGroup::Se3(twist)) * &*pose;
+        }
+        if max_update < params.min_update {
+            result.converged = true;
+            break;
+        }
+    }
+
+    result.poses = poses;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rstest::rstest;
+
+    use super::{multiview_registration, MultiviewParams};
+    use crate::{
+        metrics::TransformMetrics,
+        pointcloud::PointCloud,
+        transform::{Transform, TransformBuilder},
+        unit_test::sample_teapot_surface,
+    };
+
+    #[rstest]
+    fn test_multiview_registration(sample_teapot_surface: PointCloud) {
+        let gt_poses = [
+            Transform::eye(),
+            TransformBuilder::default()
+                .translation(Vector3::new(0.3, -0.1, 0.2))
+                .axis_angle(Vector3::z_axis(), 0.4)
+                .build(),
+            TransformBuilder::default()
+                .translation(Vector3::new(-0.2, 0.2, 0.1))
+                .axis_angle(Vector3::x_axis(), -0.3)
+                .build(),
+        ];
+        let clouds = gt_poses
+            .iter()
+            .map(|pose| &pose.inverse() * &sample_teapot_surface)
+            .collect::<Vec<_>>();
+
+        // A few centimeters and degrees off.
+        let noise = TransformBuilder::default()
+            .translation(Vector3::new(0.03, -0.02, 0.02))
+            .axis_angle(Vector3::y_axis(), 0.03)
+            .build();
+        let initial_poses = gt_poses
+            .iter()
+            .enumerate()
+            .map(|(i, pose)| if i == 0 { pose.clone() } else { &noise * pose })
+            .collect::<Vec<_>>();
+
+        let result = multiview_registration(
+            &clouds,
+            &[(1, 0), (2, 0), (2, 1)],
+            &initial_poses,
+            &MultiviewParams {
+                max_distance: 0.2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(result.converged);
+        for (actual, expected) in result.poses.iter().zip(gt_poses.iter()) {
+            let metrics = TransformMetrics::new(actual, expected);
+            assert!(metrics.translation < 1e-3, "{metrics}");
+            assert!(metrics.angle < 1e-3, "{metrics}");
+        }
+
+        assert!(multiview_registration(
+            &clouds,
+            &[(1, 0)],
+            &initial_poses,
+            &MultiviewParams::default()
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use std::{collections::HashMap, ops::ControlFlow};
+
+use nalgebra::{Matrix3, Vector3};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use super::{
+    cost_function::DistributionDistance,
+    icp_params::IcpParams,
+    icp_result::{IcpResult, IterationCallback, IterationInfo, IterationTracker},
+    point_repr::PointRepr,
+};
+use crate::{
+    optim::GaussNewton,
+    transform::{LieGroup, Transform},
+};
+
+/// Minimum number of points of a voxel to estimate its distribution.
+const MIN_CELL_POINTS: usize = 5;
+/// Smallest eigenvalue of a cell covariance relative to its largest one, keeps the
+/// distributions of planar cells invertible.
+const MIN_EIGENVALUE_RATIO: f32 = 0.01;
+
+/// Gaussian of the points in a voxel.
+struct NdtCell {
+    mean: Vector3<f32>,
+    covariance: Matrix3<f32>,
+}
+
+/// Normal Distributions Transform (NDT) registration, Magnusson, The Three-Dimensional
+/// Normal-Distributions Transform, 2009.
+///
+/// The target is summarized as a voxel grid with a Gaussian per voxel, so each iteration
+/// only needs a hash lookup per source point instead of a nearest neighbor search, which
+/// makes it suited to large scans, e.g., outdoor LiDAR. The NDT score is maximized by
+/// Gauss-Newton, with each point weighted by its likelihood under the voxel distributions.
+///
+/// The clouds can be [`crate::pointcloud::PointCloud`] or any other layout implementing [`PointRepr`].
+pub struct Ndt {
+    /// Parameters of the algorithm. Uses the maximum number of iterations and the maximum
+    /// distance between a point and a voxel mean.
+    pub params: IcpParams,
+    /// Initial transformation to start the algorithm. Default is the identity.
+    pub initial_transform: Transform,
+    resolution: f32,
+    cells: HashMap<[i32; 3], NdtCell>,
+}
+
+impl Ndt {
+    /// Create a new NDT instance, computing the voxel distributions of the target.
+    ///
+    /// # Arguments
+    ///
+    /// * params - Parameters of the algorithm.
+    /// * resolution - Voxel size, it should be a few times the point spacing and cover
+    ///   the expected misalignment.
+    /// * target - Target point cloud.
+    pub fn new<T: PointRepr + ?Sized>(params: IcpParams, resolution: f32, target: &T) -> Self {
+        let mut accumulators: HashMap<[i32; 3], (Vector3<f32>, Matrix3<f32>, usize)> =
+            HashMap::new();
+        for point in (0..target.len()).map(|index| target.position(index)) {
+            let (sum, outer_sum, count) = accumulators
+                .entry(Self::voxel(&point, resolution))
+                .or_insert((Vector3::zeros(), Matrix3::zeros(), 0));
+            *sum += point;
+            *outer_sum += point * point.transpose();
+            *count += 1;
+        }
+
+        let cells = accumulators
+            .into_iter()
+            .filter(|(_, (_, _, count))| *count >= MIN_CELL_POINTS)
+            .map(|(voxel, (sum, outer_sum, count))| {
+                let mean = sum / count as f32;
+                let covariance = outer_sum / count as f32 - mean * mean.transpose();
+
+                let mut eigen = covariance.symmetric_eigen();
+                let min_eigenvalue = eigen.eigenvalues.max() * MIN_EIGENVALUE_RATIO;
+                eigen
+                    .eigenvalues
+                    .iter_mut()
+                    .for_each(|value| *value = value.max(min_eigenvalue));
+                (
+                    voxel,
+                    NdtCell {
+                        mean,
+                        covariance: eigen.recompose(),
+                    },
+                )
+            })
+            .filter(|(_, cell)| cell.covariance.determinant() > 0.0)
+            .collect();
+
+        Self {
+            params,
+            initial_transform: Transform::eye(),
+            resolution,
+            cells,
+        }
+    }
+
+    fn voxel(point: &Vector3<f32>, resolution: f32) -> [i32; 3] {
+        [0, 1, 2].map(|axis| (point[axis] / resolution).floor() as i32)
+    }
+
+    /// Aligns the source point cloud to the target point cloud.
+    ///
+    /// # Arguments
+    ///
+    /// * source - Source point cloud.
+    ///
+    /// # Returns
+    ///
+    /// The transformation that aligns the source point cloud to the target point cloud, the
+    /// inliers are the point and voxel pairs.
+    pub fn align<S: PointRepr + ?Sized>(&self, source: &S) -> IcpResult {
+        self.align_impl(source, None)
+    }
+
+    /// Same as `align`, reporting the progress of every iteration to a callback that can
+    /// abort the run.
+    ///
+    /// # Arguments
+    ///
+    /// * source - Same as `align`.
+    /// * callback - Called after every iteration, see [`IterationCallback`].
+    pub fn align_with_callback<S: PointRepr + ?Sized>(
+        &self,
+        source: &S,
+        mut callback: impl FnMut(IterationInfo) -> ControlFlow<()>,
+    ) -> IcpResult {
+        self.align_impl(source, Some(&mut callback))
+    }
+
+    fn align_impl<'a, S: PointRepr + ?Sized>(
+        &self,
+        source: &S,
+        callback: Option<&'a mut IterationCallback<'a>>,
+    ) -> IcpResult {
+        let source_points = source.positions();
+        let max_distance_sqr = self.params.max_distance * self.params.max_distance;
+        let cost = DistributionDistance {};
+
+        let mut optim_transform = self.initial_transform.clone();
+        let mut tracker =
+            IterationTracker::new(&self.params, &optim_transform).with_callback(callback);
+        for _ in 0..self.params.max_iterations {
+            // The weighted residuals grow as points fall into the distributions, so the
+            // best iteration is tracked by the NDT score instead.
+            let (optimizer, score) = (0..source_points.len())
+                .into_par_iter()
+                .fold(
+                    || (GaussNewton::<6>::new(), 0.0),
+                    |(mut optimizer, mut score), index| {
+                        let source_point = optim_transform.transform_vector(&source_points[index]);
+                        let [x, y, z] = Self::voxel(&source_point, self.resolution);
+
+                        // The neighbor voxels also score the point, smoothing the cost
+                        // across voxel boundaries.
+                        for neighbor in
+                            (0..27).map(|k| [x + k % 3 - 1, y + k / 3 % 3 - 1, z + k / 9 - 1])
+                        {
+                            let Some(cell) = self.cells.get(&neighbor) else {
+                                continue;
+                            };
+                            if (cell.mean - source_point).norm_squared() > max_distance_sqr {
+                                continue;
+                            }
+                            let Some(residuals) =
+                                cost.jacobian(&source_point, &cell.mean, &cell.covariance)
+                            else {
+                                continue;
+                            };
+                            let mahalanobis_sqr = residuals
+                                .iter()
+                                .map(|(residual, _)| residual * residual)
+                                .sum::<f32>();
+                            let likelihood = (-0.5 * mahalanobis_sqr).exp();
+                            score += likelihood;
+                            for (residual, jacobian) in residuals {
+                                optimizer.weighted_step(residual, &jacobian, likelihood);
+                            }
+                        }
+                        (optimizer, score)
+                    },
+                )
+                .reduce(
+                    || (GaussNewton::<6>::new(), 0.0),
+                    |(mut optimizer, score), (other, other_score)| {
+                        optimizer.add(&other);
+                        (optimizer, score + other_score)
+                    },
+                );
+
+            let residual = optimizer.mean_squared_residual();
+            let Some(update) = optimizer.solve() else {
+                break;
+            };
+            optim_transform = &Transform::exp(&LieGroup::Se3(update)) * &optim_transform;
+            tracker.record_with_cost(&optim_transform, -score, &optimizer, optimizer.count() / 3);
+            if tracker.should_stop(&update, residual) {
+                break;
+            }
+        }
+
+        tracker.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rstest::rstest;
+
+    use super::Ndt;
+    use crate::{
+        icp::IcpParams, metrics::TransformMetrics, pointcloud::PointCloud,
+        transform::TransformBuilder, unit_test::sample_teapot_surface,
+    };
+
+    #[rstest]
+    fn test_ndt_teapot(sample_teapot_surface: PointCloud) {
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.05, -0.03, 0.02))
+            .axis_angle(Vector3::y_axis(), 0.05)
+            .build();
+        let source = &displacement * &sample_teapot_surface;
+
+        let actual = Ndt::new(
+            IcpParams {
+                max_iterations: 30,
+                ..Default::default()
+            },
+            0.15,
+            &sample_teapot_surface,
+        )
+        .align(&source)
+        .transform;
+        let metrics = TransformMetrics::new(&actual, &displacement.inverse());
+        assert!(metrics.translation < 4e-3);
+        assert!(metrics.angle < 4e-3);
+    }
+}
@@ -1,40 +1,240 @@
-use super::cost_function::PointPlaneDistance;
-use super::icp_params::IcpParams;
+use std::ops::ControlFlow;
+
+use super::{
+    cost_function::ColorDistance,
+    icp_params::IcpParams,
+    icp_result::{IcpResult, IterationCallback, IterationInfo, IterationTracker},
+    motion_prior::MotionPrior,
+    point_repr::PointRepr,
+    rejection::RejectionStats,
+};
+#[cfg(feature = "viz")]
+use super::{GpuNearestNeighbor, GpuPoints};
 use crate::{
+    error::A3dError,
     extra_math,
     kdtree::R3dTree,
-    optim::GaussNewton,
+    optim::{GaussNewton, RobustLoss},
     pointcloud::PointCloud,
+    range_image::RangeImage,
     transform::{LieGroup, Transform},
 };
 use itertools::izip;
-use num::Float;
+use nalgebra::{Matrix3, Vector3};
+use ndarray::{Array1, Array2};
+
+/// Number of neighbors fitting the intensity gradient of a target point.
+const INTENSITY_NEIGHBORS: usize = 10;
+
+/// A target point, its normal and its index.
+type Match = (Vector3<f32>, Vector3<f32>, usize);
+
+/// The match of the target point at an index.
+fn nearest_match<T: PointRepr + ?Sized>(target: &T, index: usize) -> Option<Match> {
+    Some((target.position(index), target.normal(index)?, index))
+}
+
+/// How the target points matching the source points are found.
+enum Association<'target, T: PointRepr + ?Sized> {
+    /// The closest target point, searched in a k-d tree.
+    NearestNeighbor { target: &'target T, kdtree: R3dTree },
+    /// The target pixel that the source point projects to.
+    Projective(&'target RangeImage),
+}
+
+impl<T: PointRepr + ?Sized> Association<'_, T> {
+    /// The target point, normal and index associated with a source point in the target
+    /// frame. Pixels are indexed in row-major order.
+    fn find(&self, point: &Vector3<f32>) -> Option<Match> {
+        match self {
+            Association::NearestNeighbor { target, kdtree } => {
+                let (index, _) = kdtree.nearest(point);
+                nearest_match(*target, index)
+            }
+            Association::Projective(target) => {
+                let (row, col) = target.project_to_pixel(point)?;
+                Some((
+                    target.get_point(row, col)?,
+                    target.normals.as_ref()?[(row, col)],
+                    row * target.width() + col,
+                ))
+            }
+        }
+    }
+
+    /// Same as [`Association::find`] for all the source points, the nearest neighbors are
+    /// searched in parallel.
+    fn find_all(&self, points: &[Vector3<f32>]) -> Vec<Option<Match>> {
+        match self {
+            Association::NearestNeighbor { target, kdtree } => {
+                let queries =
+                    Array2::from_shape_fn((points.len(), 3), |(row, axis)| points[row][axis]);
+                let (indices, _) = kdtree.batch_nearest(&queries);
+                indices
+                    .iter()
+                    .map(|&index| nearest_match(*target, index))
+                    .collect()
+            }
+            Association::Projective(_) => points.iter().map(|point| self.find(point)).collect(),
+        }
+    }
+}
 
 /// Standard Iterative Closest Point (ICP) algorithm for aligning two point clouds.
-/// This implementation uses the point-to-plane distance.
-pub struct Icp<'target> {
+/// The geometric residual is selected by [`IcpParams::geometric_cost`].
+///
+/// The clouds are [`PointCloud`] by default, or any other layout implementing
+/// [`PointRepr`], and the source may have a different layout than the target.
+///
+/// With the `viz` feature, the nearest neighbors can be searched on the GPU, see
+/// [`Icp::with_gpu`]. The k-d tree is used otherwise and whenever a GPU search fails.
+pub struct Icp<'target, T: PointRepr + ?Sized = PointCloud> {
     // Parameters of the ICP algorithm.
     pub params: IcpParams,
     // Initial transformation to start the algorithm. Default is the identity.
     pub initial_transform: Transform,
-    target: &'target PointCloud,
-    kdtree: R3dTree,
+    /// Prior whose residual is added to every iteration, see [`Icp::with_motion_prior`].
+    pub motion_prior: Option<MotionPrior>,
+    association: Association<'target, T>,
+    /// Target intensities and their gradients on the tangent planes.
+    intensity: Option<(Array1<f32>, Array1<Vector3<f32>>)>,
+    #[cfg(feature = "viz")]
+    gpu: Option<(&'target GpuNearestNeighbor, GpuPoints)>,
 }
 
-impl<'target> Icp<'target> {
+/// Fits the intensity gradient of each point on its tangent plane, from its neighbors.
+fn intensity_gradients<T: PointRepr + ?Sized>(
+    pcl: &T,
+    intensities: &Array1<f32>,
+    kdtree: &R3dTree,
+) -> Array1<Vector3<f32>> {
+    (0..pcl.len())
+        .map(|index| {
+            let point = pcl.position(index);
+            let normal = pcl.normal(index).expect("Checked by the caller");
+            let intensity = intensities[index];
+            // The normal row keeps the gradient on the tangent plane.
+            let mut hessian = normal * normal.transpose() * INTENSITY_NEIGHBORS as f32;
+            let mut gradient = Vector3::zeros();
+            for (index, _) in kdtree.knn(&point, INTENSITY_NEIGHBORS + 1) {
+                let offset = pcl.position(index) - point;
+                let offset = offset - normal * offset.dot(&normal);
+                hessian += offset * offset.transpose();
+                gradient += offset * (intensities[index] - intensity);
+            }
+            (hessian + Matrix3::identity() * 1e-6)
+                .try_inverse()
+                .map_or_else(Vector3::zeros, |inverse| inverse * gradient)
+        })
+        .collect()
+}
+
+impl<'target, T: PointRepr + ?Sized> Icp<'target, T> {
     /// Create a new ICP instance.
     ///
     /// # Arguments
     ///
     /// * params - Parameters of the ICP algorithm.
     /// * target - Target point cloud.
-    pub fn new(params: IcpParams, target: &'target PointCloud) -> Self {
+    pub fn new(params: IcpParams, target: &'target T) -> Self {
         Self {
             params,
             initial_transform: Transform::eye(),
-            target,
-            kdtree: R3dTree::new(&target.points.view()),
+            motion_prior: None,
+            association: Association::NearestNeighbor {
+                target,
+                kdtree: R3dTree::new(&target.positions().view()),
+            },
+            intensity: None,
+            #[cfg(feature = "viz")]
+            gpu: None,
+        }
+    }
+
+    /// Starts the alignment from a motion prior and adds its residual to every iteration.
+    ///
+    /// # Arguments
+    ///
+    /// * prior - The motion prior.
+    pub fn with_motion_prior(mut self, prior: MotionPrior) -> Self {
+        self.initial_transform = prior.transform.clone();
+        self.motion_prior = Some(prior);
+        self
+    }
+
+    /// Searches the nearest neighbors on the GPU, uploading the target points. Keeps
+    /// using the k-d tree if the upload fails, and has no effect with projective
+    /// association.
+    ///
+    /// # Arguments
+    ///
+    /// * kernel - The GPU kernel.
+    #[cfg(feature = "viz")]
+    pub fn with_gpu(mut self, kernel: &'target GpuNearestNeighbor) -> Self {
+        if let Association::NearestNeighbor { target, .. } = &self.association {
+            self.gpu = kernel
+                .upload(&target.positions().view())
+                .ok()
+                .map(|target| (kernel, target));
         }
+        self
+    }
+
+    /// Whether the nearest neighbors are searched on the GPU.
+    pub fn uses_gpu(&self) -> bool {
+        #[cfg(feature = "viz")]
+        return self.gpu.is_some();
+        #[cfg(not(feature = "viz"))]
+        false
+    }
+
+    /// Finds the matches of the source points in the target frame, on the GPU if enabled.
+    fn find_all(&self, points: &[Vector3<f32>]) -> Vec<Option<Match>> {
+        #[cfg(feature = "viz")]
+        if let (Some((kernel, gpu_target)), Association::NearestNeighbor { target, .. }) =
+            (&self.gpu, &self.association)
+        {
+            if let Ok((indices, _)) = kernel.nearest(gpu_target, points) {
+                return indices
+                    .into_iter()
+                    .map(|index| nearest_match(*target, index))
+                    .collect();
+            }
+        }
+        self.association.find_all(points)
+    }
+
+    /// Adds an intensity consistency term to the geometric residual, for clouds with
+    /// per point intensities, e.g., LiDAR reflectance. It constrains the motions along
+    /// which the geometry is degenerate, like the axis of a corridor. The intensity of a
+    /// source point is compared with the one predicted at it by the gradient of the target
+    /// intensities on the tangent plane of its correspondence, as in Park et al., Colored
+    /// Point Cloud Registration Revisited, 2017. The term is weighted by
+    /// [`IcpParams::color_weight`], uses [`IcpParams::color_robust_loss`] and rejects
+    /// differences above [`IcpParams::max_color_distance`], so intensities should be scaled
+    /// like colors, e.g., in [0, 1].
+    ///
+    /// # Returns
+    ///
+    /// The ICP instance, or error if it uses projective association or the target has no
+    /// normals or intensities.
+    pub fn with_intensity(mut self) -> Result<Self, A3dError> {
+        let Association::NearestNeighbor { target, kdtree } = &self.association else {
+            return Err(A3dError::invalid_parameter(
+                "The intensity term requires nearest neighbor association.",
+            ));
+        };
+        if !target.has_normals() || !target.has_intensities() {
+            return Err(A3dError::invalid_parameter(
+                "The intensity term requires target normals and intensities.",
+            ));
+        }
+        let intensities = (0..target.len())
+            .map(|index| target.intensity(index).expect("Checked above"))
+            .collect();
+        let gradients = intensity_gradients(*target, &intensities, kdtree);
+        self.intensity = Some((intensities, gradients));
+        Ok(self)
     }
 
     /// Aligns the source point cloud to the target point cloud.
@@ -45,65 +245,212 @@ impl<'target> Icp<'target> {
     ///
     /// # Returns
     ///
-    /// The transformation that aligns the source point cloud to the target point cloud.
-    pub fn align(&self, source: &PointCloud) -> Transform {
-        let target_normals = self
-            .target
-            .normals
-            .as_ref()
-            .expect("Please, the target point cloud should have normals.");
-        let source_normals = source
-            .normals
-            .as_ref()
-            .expect("Please, the source point cloud should have normals.");
-        let mut optim_transform = Transform::eye();
+    /// The transformation that aligns the source point cloud to the target point cloud,
+    /// with the counts of rejected correspondences of its iteration.
+    pub fn align<S: PointRepr + ?Sized>(&self, source: &S) -> IcpResult {
+        self.align_impl(source, None)
+    }
+
+    /// Same as `align`, reporting the progress of every iteration to a callback that can
+    /// abort the run.
+    ///
+    /// # Arguments
+    ///
+    /// * source - Same as `align`.
+    /// * callback - Called after every iteration, see [`IterationCallback`].
+    pub fn align_with_callback<S: PointRepr + ?Sized>(
+        &self,
+        source: &S,
+        mut callback: impl FnMut(IterationInfo) -> ControlFlow<()>,
+    ) -> IcpResult {
+        self.align_impl(source, Some(&mut callback))
+    }
+
+    fn align_impl<'a, S: PointRepr + ?Sized>(
+        &self,
+        source: &S,
+        callback: Option<&'a mut IterationCallback<'a>>,
+    ) -> IcpResult {
+        let has_target_normals = match &self.association {
+            Association::NearestNeighbor { target, .. } => target.has_normals(),
+            Association::Projective(target) => target.normals.is_some(),
+        };
+        assert!(
+            has_target_normals,
+            "Please, the target point cloud should have normals."
+        );
+        assert!(
+            source.has_normals(),
+            "Please, the source point cloud should have normals."
+        );
+        let source_normals = (0..source.len())
+            .map(|index| source.normal(index).expect("Checked above"))
+            .collect::<Vec<_>>();
+        let source_intensities = self.intensity.as_ref().map(|_| {
+            assert!(
+                source.has_intensities(),
+                "Please, the source point cloud should have intensities."
+            );
+            (0..source.len())
+                .map(|index| source.intensity(index).expect("Checked above"))
+                .collect::<Vec<_>>()
+        });
+        let max_color_distance_sqr =
+            self.params.max_color_distance * self.params.max_color_distance;
+        let color_distance = ColorDistance {};
+        let source_positions = source.positions();
+        let rejection = &self.params.rejection;
+        let source_kdtree = rejection
+            .reciprocal
+            .then(|| R3dTree::new(&source_positions.view()));
+
+        let mut optim_transform = self.initial_transform.clone();
         let mut optimizer = GaussNewton::<6>::new();
-        let geom_cost = PointPlaneDistance {};
 
         let max_distance_sqr = self.params.max_distance * self.params.max_distance;
 
-        let mut best_residual = Float::infinity();
-        let mut best_transform = optim_transform.clone();
+        let mut tracker =
+            IterationTracker::new(&self.params, &optim_transform).with_callback(callback);
         for _ in 0..self.params.max_iterations {
-            for (source_point, source_normal) in izip!(source.points.iter(), source_normals.iter())
+            let mut stats = RejectionStats::default();
+            let inverse_transform = optim_transform.inverse();
+            let mut correspondences = Vec::with_capacity(source.len());
+            let source_points = source_positions
+                .iter()
+                .map(|point| optim_transform.transform_vector(point))
+                .collect::<Vec<_>>();
+            let matches = self.find_all(&source_points);
+            for (source_index, (source_point, source_normal, found)) in
+                izip!(source_points, source_normals.iter(), matches).enumerate()
             {
-                let source_point = optim_transform.transform_vector(source_point);
                 let source_normal = optim_transform.transform_normal(source_normal);
 
-                let (found_index, found_sqr_distance) = self.kdtree.nearest(&source_point);
+                stats.candidates += 1;
+                let Some((target_point, target_normal, target_index)) = found else {
+                    stats.distance += 1;
+                    continue;
+                };
+                let found_sqr_distance = (target_point - source_point).norm_squared();
                 if found_sqr_distance > max_distance_sqr {
+                    stats.distance += 1;
                     continue;
                 }
 
-                let target_normal = target_normals[found_index];
-
                 if extra_math::angle_between_normals(&source_normal, &target_normal)
                     > self.params.max_normal_angle
                 {
+                    stats.normal_angle += 1;
                     continue;
                 }
 
-                let target_point = self.target.points[found_index];
+                if let Some(source_kdtree) = &source_kdtree {
+                    let back_point = inverse_transform.transform_vector(&target_point);
+                    if source_kdtree
+                        .knn(&back_point, 1)
+                        .first()
+                        .is_none_or(|(back_index, _)| *back_index != source_index)
+                    {
+                        stats.reciprocal += 1;
+                        continue;
+                    }
+                }
+
+                correspondences.push((
+                    source_point,
+                    target_point,
+                    target_normal,
+                    found_sqr_distance,
+                    source_index,
+                    target_index,
+                ));
+            }
+            stats.trimmed = rejection.trim(&mut correspondences, |correspondence| correspondence.3);
 
-                let (residual, jacobian) =
-                    geom_cost.jacobian(&source_point, &target_point, &target_normal);
+            let inlier_count = correspondences.len();
+            let mut intensity_optimizer = GaussNewton::<6>::new();
+            for (source_point, target_point, target_normal, _, source_index, target_index) in
+                correspondences
+            {
+                if let (Some((target_intensities, gradients)), Some(source_intensities)) =
+                    (&self.intensity, &source_intensities)
+                {
+                    let gradient = gradients[target_index];
+                    let target_intensity = target_intensities[target_index]
+                        + gradient.dot(&(source_point - target_point));
+                    let (residual, jacobian) = color_distance.jacobian(
+                        &source_point,
+                        &gradient,
+                        source_intensities[source_index],
+                        target_intensity,
+                    );
+                    if residual * residual <= max_color_distance_sqr {
+                        let robust_weight = self
+                            .params
+                            .color_robust_loss
+                            .map_or(1.0, |loss| loss.weight(residual));
+                        intensity_optimizer.weighted_step(
+                            residual,
+                            &jacobian,
+                            self.params.color_weight * robust_weight,
+                        );
+                    }
+                }
 
-                optimizer.step(residual, &jacobian);
+                self.params.geometric_cost.step(
+                    &mut optimizer,
+                    &source_point,
+                    &target_point,
+                    &target_normal,
+                    1.0,
+                    self.params.robust_loss.as_ref(),
+                );
             }
 
-            let residual = optimizer.mean_squared_residual();
+            let mut residual = optimizer.mean_squared_residual();
             optimizer.weight(self.params.weight);
+            if self.intensity.is_some() {
+                // The geometry alone may not change along its degenerate directions.
+                optimizer.add(&intensity_optimizer);
+                residual = optimizer.mean_squared_residual();
+            }
+            if let Some(prior) = &self.motion_prior {
+                prior.add_residuals(&mut optimizer, &optim_transform);
+            }
             let update = optimizer.solve().unwrap();
             optim_transform = &Transform::exp(&LieGroup::Se3(update)) * &optim_transform;
+            if tracker.record(&optim_transform, &optimizer, inlier_count) {
+                tracker.set_rejection(stats);
+            }
             optimizer.reset();
-
-            if residual < best_residual {
-                best_residual = residual;
-                best_transform = optim_transform.clone();
+            if tracker.should_stop(&update, residual) {
+                break;
             }
         }
 
-        best_transform
+        tracker.finish()
+    }
+}
+
+impl<'target> Icp<'target> {
+    /// Create a new ICP instance that associates each source point with the target pixel
+    /// it projects to, instead of searching its nearest neighbor. The association is
+    /// constant time per point and needs no k-d tree, which is much faster for organized
+    /// frames, but it requires a good initial alignment, e.g., consecutive frames.
+    ///
+    /// # Arguments
+    ///
+    /// * params - Parameters of the ICP algorithm.
+    /// * target - Target range image.
+    pub fn projective(params: IcpParams, target: &'target RangeImage) -> Self {
+        Self {
+            params,
+            initial_transform: Transform::eye(),
+            motion_prior: None,
+            association: Association::Projective(target),
+            intensity: None,
+            #[cfg(feature = "viz")]
+            gpu: None,
+        }
     }
 }
 
@@ -112,9 +459,16 @@ mod tests {
     use super::*;
     use rstest::*;
 
+    use nalgebra::{Matrix6, Vector3};
+
     use crate::{
+        icp::{CorrespondenceRejection, GeometricCost},
         metrics::TransformMetrics,
-        unit_test::{sample_pcl_ds1, TestPclDataset},
+        transform::TransformBuilder,
+        unit_test::{
+            sample_pcl_ds1, sample_range_img_ds2, sample_teapot_surface, TestPclDataset,
+            TestRangeImageDataset,
+        },
     };
 
     /// Test the ICP algorithm.
@@ -130,8 +484,265 @@ mod tests {
             },
             &target_pcl,
         )
-        .align(&source_pcl);
+        .align(&source_pcl)
+        .transform;
         let gt_transform = sample_pcl_ds1.get_ground_truth(1, 0);
         assert!(TransformMetrics::new(&actual, &gt_transform).angle.abs() < 0.1);
     }
+
+    /// Point-to-plane converges in a few iterations on the smooth teapot surface, while
+    /// point-to-point needs many more.
+    #[rstest]
+    fn test_geometric_costs(sample_teapot_surface: PointCloud) {
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.04, -0.03, 0.02))
+            .axis_angle(Vector3::y_axis(), 0.05)
+            .build();
+        let source = &displacement * &sample_teapot_surface;
+
+        let translation_error = |geometric_cost, max_iterations| {
+            let actual = Icp::new(
+                IcpParams {
+                    max_iterations,
+                    max_normal_angle: std::f32::consts::PI,
+                    geometric_cost,
+                    ..Default::default()
+                },
+                &sample_teapot_surface,
+            )
+            .align(&source)
+            .transform;
+            TransformMetrics::new(&actual, &displacement.inverse()).translation
+        };
+
+        let hybrid = GeometricCost::Hybrid {
+            point_to_point_weight: 0.1,
+        };
+        assert!(translation_error(GeometricCost::PointToPlane, 10) < 1e-3);
+        assert!(translation_error(hybrid, 10) < 1e-3);
+        assert!(translation_error(GeometricCost::PointToPoint, 10) > 1e-2);
+        assert!(translation_error(GeometricCost::PointToPoint, 40) < 2e-3);
+    }
+
+    #[rstest]
+    fn test_rejection(sample_teapot_surface: PointCloud) {
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.04, -0.03, 0.02))
+            .axis_angle(Vector3::y_axis(), 0.05)
+            .build();
+        let source = &displacement * &sample_teapot_surface;
+
+        let result = Icp::new(
+            IcpParams {
+                max_iterations: 30,
+                max_normal_angle: std::f32::consts::PI,
+                rejection: CorrespondenceRejection {
+                    keep_fraction: 0.8,
+                    reciprocal: true,
+                },
+                ..Default::default()
+            },
+            &sample_teapot_surface,
+        )
+        .align(&source);
+        let stats = result.rejection;
+        assert!(result.converged);
+        assert!(result.iterations < 30);
+        assert_eq!(result.inlier_count, stats.inliers());
+        let covariance = result.covariance().unwrap();
+        assert!((0..6).all(|axis| covariance[(axis, axis)] > 0.0));
+
+        assert!(
+            TransformMetrics::new(&result.transform, &displacement.inverse()).translation < 2e-3
+        );
+        assert_eq!(stats.candidates, source.len());
+        assert!(stats.reciprocal > 0);
+        let matched = stats.candidates - stats.distance - stats.normal_angle - stats.reciprocal;
+        assert_eq!(stats.inliers(), (matched as f32 * 0.8).ceil() as usize);
+    }
+
+    #[rstest]
+    fn test_align_with_callback(sample_teapot_surface: PointCloud) {
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.04, -0.03, 0.02))
+            .build();
+        let source = &displacement * &sample_teapot_surface;
+        let icp = Icp::new(
+            IcpParams {
+                max_iterations: 20,
+                max_normal_angle: std::f32::consts::PI,
+                ..Default::default()
+            },
+            &sample_teapot_surface,
+        );
+        assert!(!icp.uses_gpu());
+
+        let mut infos = Vec::new();
+        let result = icp.align_with_callback(&source, |info| {
+            infos.push(info);
+            ControlFlow::Continue(())
+        });
+        assert_eq!(infos.len(), result.iterations);
+        assert!(infos
+            .iter()
+            .enumerate()
+            .all(|(i, info)| info.iteration == i + 1 && info.inlier_count > 0));
+        assert!(infos.last().unwrap().mean_squared_residual < infos[0].mean_squared_residual);
+        assert!(infos.last().unwrap().update_translation < infos[0].update_translation);
+
+        let result = icp.align_with_callback(&source, |info| {
+            if info.iteration == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(result.iterations, 2);
+        assert!(!result.converged);
+    }
+
+    #[rstest]
+    fn test_projective(sample_range_img_ds2: TestRangeImageDataset) {
+        let target = sample_range_img_ds2.get(0).unwrap();
+        let source = PointCloud::from(&sample_range_img_ds2.get(1).unwrap());
+
+        let result = Icp::projective(
+            IcpParams {
+                max_iterations: 10,
+                ..Default::default()
+            },
+            &target,
+        )
+        .align(&source);
+        let metrics = TransformMetrics::new(
+            &result.transform,
+            &sample_range_img_ds2.get_ground_truth(1, 0),
+        );
+        assert!(metrics.translation < 0.01, "{metrics}");
+        assert!(metrics.angle < 0.01, "{metrics}");
+        assert!(result.rejection.distance > 0);
+        assert!(result.inlier_count > source.len() / 2);
+    }
+
+    /// On a plane, only the intensities constrain the in-plane motion.
+    #[test]
+    fn test_with_intensity() {
+        let points = (0..100)
+            .flat_map(|i| (0..100).map(move |j| Vector3::new(i as f32, j as f32, 0.0) * 0.01))
+            .collect::<Array1<_>>();
+        let period = 2.0 * std::f32::consts::PI / 0.3;
+        let target = PointCloud {
+            intensities: Some(
+                points
+                    .iter()
+                    .map(|p| 0.5 + 0.25 * (p.x * period).sin() + 0.25 * (p.y * period).sin())
+                    .collect(),
+            ),
+            curvatures: None,
+            attributes: Default::default(),
+            normals: Some(Array1::from_elem(points.len(), Vector3::z())),
+            colors: None,
+            points,
+        };
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.03, -0.02, 0.0))
+            .build();
+        let source = &displacement * &target;
+
+        let params = IcpParams {
+            max_iterations: 20,
+            color_weight: 1.0,
+            ..Default::default()
+        };
+        let result = Icp::new(params, &target)
+            .with_intensity()
+            .unwrap()
+            .align(&source);
+        // From 36 mm, limited by the approximate nearest neighbors on the grid.
+        let metrics = TransformMetrics::new(&result.transform, &displacement.inverse());
+        assert!(metrics.translation < 5e-3, "{metrics}");
+        assert!(metrics.angle < 1e-2, "{metrics}");
+
+        let no_intensities = PointCloud {
+            intensities: None,
+            ..target
+        };
+        assert!(Icp::new(params, &no_intensities).with_intensity().is_err());
+    }
+
+    /// The returned transform includes the update of the last iteration.
+    #[rstest]
+    fn test_single_iteration(sample_teapot_surface: PointCloud) {
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.02, -0.01, 0.01))
+            .build();
+        let source = &displacement * &sample_teapot_surface;
+
+        let result = Icp::new(
+            IcpParams {
+                max_iterations: 1,
+                max_normal_angle: std::f32::consts::PI,
+                ..Default::default()
+            },
+            &sample_teapot_surface,
+        )
+        .align(&source);
+        assert_eq!(result.iterations, 1);
+        let moved = TransformMetrics::new(&result.transform, &Transform::eye());
+        assert!(moved.translation > 1e-3, "{moved}");
+    }
+
+    #[rstest]
+    fn test_initial_transform(sample_teapot_surface: PointCloud) {
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.04, -0.03, 0.02))
+            .axis_angle(Vector3::y_axis(), 0.05)
+            .build();
+        let source = &displacement * &sample_teapot_surface;
+
+        let mut icp = Icp::new(
+            IcpParams {
+                max_iterations: 1,
+                ..Default::default()
+            },
+            &sample_teapot_surface,
+        );
+        icp.initial_transform = displacement.inverse();
+        let metrics = TransformMetrics::new(&icp.align(&source).transform, &displacement.inverse());
+        assert!(metrics.translation < 1e-4, "{metrics}");
+        assert!(metrics.angle < 1e-4, "{metrics}");
+    }
+
+    /// On a plane, only the prior constrains the in-plane motion.
+    #[test]
+    fn test_motion_prior() {
+        let points = (0..50)
+            .flat_map(|i| (0..50).map(move |j| Vector3::new(i as f32, j as f32, 0.0) * 0.02))
+            .collect::<Array1<_>>();
+        let target = PointCloud {
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+            normals: Some(Array1::from_elem(points.len(), Vector3::z())),
+            colors: None,
+            points,
+        };
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.1, -0.06, 0.01))
+            .build();
+        let source = &displacement * &target;
+        let params = IcpParams {
+            max_iterations: 10,
+            ..Default::default()
+        };
+
+        let prior =
+            MotionPrior::new(displacement.inverse(), &(Matrix6::identity() * 1e-4)).unwrap();
+        let result = Icp::new(params, &target)
+            .with_motion_prior(prior)
+            .align(&source);
+        let metrics = TransformMetrics::new(&result.transform, &displacement.inverse());
+        assert!(metrics.translation < 1e-3, "{metrics}");
+        assert!(metrics.angle < 1e-3, "{metrics}");
+    }
 }
@@ -0,0 +1,295 @@
+use std::ops::ControlFlow;
+
+use nalgebra::{Matrix3, Vector3};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use super::{
+    cost_function::PointPlaneDistance,
+    icp_params::IcpParams,
+    icp_result::{IcpResult, IterationCallback, IterationInfo, IterationTracker},
+    point_repr::PointRepr,
+    rejection::RejectionStats,
+};
+use crate::{
+    kdtree::R3dTree,
+    optim::{GaussNewton, RobustLoss},
+    pointcloud::PointCloud,
+    transform::{LieGroup, Transform},
+};
+
+/// Weight of the constraints that keep the motion in the XY plane.
+const PLANAR_WEIGHT: f32 = 1e6;
+
+/// Fits a line to the neighborhood of each point.
+///
+/// # Arguments
+///
+/// * points - The points.
+/// * kdtree - Tree built from the same points.
+/// * num_neighbors - Neighborhood size, including the point.
+/// * planar - Whether to keep the directions in the XY plane.
+///
+/// # Returns
+///
+/// The unit direction of the line of each point.
+fn fit_lines(
+    points: &ndarray::Array1<Vector3<f32>>,
+    kdtree: &R3dTree,
+    num_neighbors: usize,
+    planar: bool,
+) -> Vec<Vector3<f32>> {
+    (0..points.len())
+        .into_par_iter()
+        .map(|index| {
+            let neighbors = kdtree.knn(&points[index], num_neighbors);
+            let mean = neighbors
+                .iter()
+                .fold(Vector3::zeros(), |sum, (neighbor, _)| {
+                    sum + points[*neighbor]
+                })
+                / neighbors.len() as f32;
+            let covariance = neighbors
+                .iter()
+                .fold(Matrix3::zeros(), |sum, (neighbor, _)| {
+                    let centered = points[*neighbor] - mean;
+                    let centered = if planar {
+                        Vector3::new(centered[0], centered[1], 0.0)
+                    } else {
+                        centered
+                    };
+                    sum + centered * centered.transpose()
+                });
+
+            let eigen = covariance.symmetric_eigen();
+            let direction = eigen
+                .eigenvectors
+                .column(eigen.eigenvalues.imax())
+                .into_owned();
+            direction.try_normalize(1e-8).unwrap_or_else(Vector3::x)
+        })
+        .collect()
+}
+
+/// Point-to-line ICP (PL-ICP, Censi, 2008) for scans of sparse line-like structures, e.g.,
+/// the planar scans of 2D LiDARs on mobile robots. A line is fitted to the neighborhood of
+/// each target point, and the distance of the source points to the lines of their closest
+/// target points is minimized.
+///
+/// Create 2D scans with [`PointCloud::from_laser_scan`] and construct it as `planar`, so the
+/// motion is restricted to X, Y and yaw. Other layouts implementing [`PointRepr`] are
+/// aligned the same way.
+pub struct PointLineIcp<'target, T: PointRepr + ?Sized = PointCloud> {
+    /// Parameters of the ICP algorithm, the normal and color thresholds are not used.
+    pub params: IcpParams,
+    /// Initial transformation to start the algorithm. Default is the identity.
+    pub initial_transform: Transform,
+    planar: bool,
+    target: &'target T,
+    kdtree: R3dTree,
+    target_lines: Vec<Vector3<f32>>,
+}
+
+impl<'target, T: PointRepr + ?Sized> PointLineIcp<'target, T> {
+    /// Create a new point-to-line ICP instance, fitting the target lines.
+    ///
+    /// # Arguments
+    ///
+    /// * params - Parameters of the ICP algorithm.
+    /// * target - Target point cloud.
+    /// * num_neighbors - Number of points used to fit each line, including the point.
+    /// * planar - Whether the scans lie in the XY plane. The motion is then restricted to
+    ///   the plane: the Z translation, roll and pitch of the updates are constrained to zero.
+    pub fn new(params: IcpParams, target: &'target T, num_neighbors: usize, planar: bool) -> Self {
+        let target_points = target.positions();
+        let kdtree = R3dTree::new(&target_points.view());
+        let target_lines = fit_lines(&target_points, &kdtree, num_neighbors, planar);
+        Self {
+            params,
+            initial_transform: Transform::eye(),
+            planar,
+            target,
+            kdtree,
+            target_lines,
+        }
+    }
+
+    /// Aligns the source point cloud to the target point cloud.
+    ///
+    /// # Arguments
+    ///
+    /// * source - Source point cloud.
+    ///
+    /// # Returns
+    ///
+    /// The transformation that aligns the source point cloud to the target point cloud.
+    pub fn align<S: PointRepr + ?Sized>(&self, source: &S) -> IcpResult {
+        self.align_impl(source, None)
+    }
+
+    /// Same as `align`, reporting the progress of every iteration to a callback that can
+    /// abort the run.
+    ///
+    /// # Arguments
+    ///
+    /// * source - Same as `align`.
+    /// * callback - Called after every iteration, see [`IterationCallback`].
+    pub fn align_with_callback<S: PointRepr + ?Sized>(
+        &self,
+        source: &S,
+        mut callback: impl FnMut(IterationInfo) -> ControlFlow<()>,
+    ) -> IcpResult {
+        self.align_impl(source, Some(&mut callback))
+    }
+
+    fn align_impl<'a, S: PointRepr + ?Sized>(
+        &self,
+        source: &S,
+        callback: Option<&'a mut IterationCallback<'a>>,
+    ) -> IcpResult {
+        let source_points = source.positions();
+        let max_distance_sqr = self.params.max_distance * self.params.max_distance;
+
+        let mut optim_transform = self.initial_transform.clone();
+        let mut optimizer = GaussNewton::<6>::new();
+        let mut tracker =
+            IterationTracker::new(&self.params, &optim_transform).with_callback(callback);
+        for _ in 0..self.params.max_iterations {
+            let mut stats = RejectionStats::default();
+            for source_point in source_points.iter() {
+                let source_point = optim_transform.transform_vector(source_point);
+
+                stats.candidates += 1;
+                let (found_index, found_sqr_distance) = self.kdtree.nearest(&source_point);
+                if found_sqr_distance > max_distance_sqr {
+                    stats.distance += 1;
+                    continue;
+                }
+
+                let target_point = self.target.position(found_index);
+                let direction = self.target_lines[found_index];
+                // The residuals are the distances along the normals of the line.
+                let first_normal = if self.planar {
+                    Vector3::z().cross(&direction).normalize()
+                } else {
+                    let helper = if direction[0].abs() < 0.9 {
+                        Vector3::x()
+                    } else {
+                        Vector3::y()
+                    };
+                    direction.cross(&helper).normalize()
+                };
+                let normals = if self.planar {
+                    vec![first_normal]
+                } else {
+                    vec![first_normal, direction.cross(&first_normal)]
+                };
+
+                let difference = target_point - source_point;
+                let line_distance = normals
+                    .iter()
+                    .map(|normal| difference.dot(normal).powi(2))
+                    .sum::<f32>()
+                    .sqrt();
+                let weight = self
+                    .params
+                    .robust_loss
+                    .as_ref()
+                    .map_or(1.0, |loss| loss.weight(line_distance));
+                let point_plane = PointPlaneDistance {};
+                for normal in normals {
+                    let (residual, jacobian) =
+                        point_plane.jacobian(&source_point, &target_point, &normal);
+                    optimizer.weighted_step(residual, &jacobian, weight);
+                }
+            }
+
+            if self.planar {
+                // Z translation, roll and pitch.
+                for axis in [2, 3, 4] {
+                    let mut jacobian = [0.0; 6];
+                    jacobian[axis] = 1.0;
+                    optimizer.weighted_step(0.0, &jacobian, PLANAR_WEIGHT);
+                }
+            }
+
+            let residual = optimizer.mean_squared_residual();
+            optimizer.weight(self.params.weight);
+            let Some(update) = optimizer.solve() else {
+                break;
+            };
+            optim_transform = &Transform::exp(&LieGroup::Se3(update)) * &optim_transform;
+            if tracker.record(&optim_transform, &optimizer, stats.inliers()) {
+                tracker.set_rejection(stats);
+            }
+            optimizer.reset();
+            if tracker.should_stop(&update, residual) {
+                break;
+            }
+        }
+
+        tracker.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rstest::rstest;
+
+    use super::PointLineIcp;
+    use crate::{
+        icp::IcpParams, metrics::TransformMetrics, pointcloud::PointCloud,
+        transform::TransformBuilder,
+    };
+
+    /// Scan of the walls of a 4 x 3 m room from a pose.
+    fn room_scan(x: f32, y: f32, yaw: f32) -> PointCloud {
+        let angle_increment = std::f32::consts::TAU / 360.0;
+        let ranges = (0..360)
+            .map(|ray| {
+                let angle = yaw + ray as f32 * angle_increment;
+                let (sin, cos) = angle.sin_cos();
+                [
+                    (2.0 - x) / cos,
+                    (-2.0 - x) / cos,
+                    (1.5 - y) / sin,
+                    (-1.5 - y) / sin,
+                ]
+                .into_iter()
+                .filter(|range| *range > 0.0 && range.is_finite())
+                .fold(f32::INFINITY, f32::min)
+            })
+            .collect::<Vec<_>>();
+        PointCloud::from_laser_scan(&ranges, 0.0, angle_increment)
+    }
+
+    #[rstest]
+    #[case(true)]
+    #[case(false)]
+    fn test_point_line_icp(#[case] planar: bool) {
+        let target = room_scan(0.0, 0.0, 0.0);
+        let source = room_scan(0.1, -0.05, 0.05);
+
+        let result = PointLineIcp::new(
+            IcpParams {
+                max_iterations: 30,
+                ..Default::default()
+            },
+            &target,
+            5,
+            planar,
+        )
+        .align(&source);
+
+        let expected = TransformBuilder::default()
+            .translation(Vector3::new(0.1, -0.05, 0.0))
+            .axis_angle(Vector3::z_axis(), 0.05)
+            .build();
+        let metrics = TransformMetrics::new(&result.transform, &expected);
+        assert!(metrics.translation < 1e-2, "{metrics}");
+        assert!(metrics.angle < 1e-2, "{metrics}");
+        if planar {
+            assert!(result.transform.translation()[2].abs() < 1e-5);
+        }
+    }
+}
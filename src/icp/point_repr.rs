@@ -0,0 +1,190 @@
+use nalgebra::Vector3;
+use ndarray::Array1;
+
+use crate::pointcloud::PointCloud;
+
+/// Access to the points of a cloud, for the point cloud registrations to align custom
+/// point layouts, e.g., struct-of-arrays LiDAR packets, without converting them to a
+/// [`PointCloud`] first.
+///
+/// Only the positions are required. The attributes return `None` by default, and
+/// implementations providing one must also override its `has_` method. It is `Sync` as the
+/// registrations read the points from parallel workers.
+pub trait PointRepr: Sync {
+    /// Number of points.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Position of a point.
+    fn position(&self, index: usize) -> Vector3<f32>;
+
+    /// Normal of a point.
+    fn normal(&self, _index: usize) -> Option<Vector3<f32>> {
+        None
+    }
+
+    /// Whether all the points have normals.
+    fn has_normals(&self) -> bool {
+        false
+    }
+
+    /// Intensity of a point, e.g., the LiDAR return strength.
+    fn intensity(&self, _index: usize) -> Option<f32> {
+        None
+    }
+
+    /// Whether all the points have intensities.
+    fn has_intensities(&self) -> bool {
+        false
+    }
+
+    /// The positions of all the points, e.g., to build a k-d tree.
+    fn positions(&self) -> Array1<Vector3<f32>> {
+        (0..self.len()).map(|index| self.position(index)).collect()
+    }
+}
+
+impl PointRepr for PointCloud {
+    fn len(&self) -> usize {
+        PointCloud::len(self)
+    }
+
+    fn position(&self, index: usize) -> Vector3<f32> {
+        self.points[index]
+    }
+
+    fn normal(&self, index: usize) -> Option<Vector3<f32>> {
+        self.normals.as_ref().map(|normals| normals[index])
+    }
+
+    fn has_normals(&self) -> bool {
+        self.normals.is_some()
+    }
+
+    fn intensity(&self, index: usize) -> Option<f32> {
+        self.intensities
+            .as_ref()
+            .map(|intensities| intensities[index])
+    }
+
+    fn has_intensities(&self) -> bool {
+        self.intensities.is_some()
+    }
+
+    fn positions(&self) -> Array1<Vector3<f32>> {
+        self.points.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rstest::rstest;
+
+    use super::PointRepr;
+    use crate::{
+        icp::{Gicp, GicpParams, Icp, IcpParams, Ndt},
+        metrics::TransformMetrics,
+        pointcloud::PointCloud,
+        transform::{Transform, TransformBuilder},
+        unit_test::sample_teapot_surface,
+    };
+
+    /// Points stored as separate coordinate arrays, like a LiDAR packet.
+    struct Packet {
+        x: Vec<f32>,
+        y: Vec<f32>,
+        z: Vec<f32>,
+        normals: Vec<[f32; 3]>,
+    }
+
+    impl Packet {
+        fn new(pcl: &PointCloud) -> Self {
+            let normals = pcl.normals.as_ref().unwrap();
+            Self {
+                x: pcl.points.iter().map(|point| point.x).collect(),
+                y: pcl.points.iter().map(|point| point.y).collect(),
+                z: pcl.points.iter().map(|point| point.z).collect(),
+                normals: normals.iter().map(|normal| (*normal).into()).collect(),
+            }
+        }
+    }
+
+    impl PointRepr for Packet {
+        fn len(&self) -> usize {
+            self.x.len()
+        }
+
+        fn position(&self, index: usize) -> Vector3<f32> {
+            Vector3::new(self.x[index], self.y[index], self.z[index])
+        }
+
+        fn normal(&self, index: usize) -> Option<Vector3<f32>> {
+            Some(self.normals[index].into())
+        }
+
+        fn has_normals(&self) -> bool {
+            true
+        }
+    }
+
+    fn assert_same(actual: &Transform, expected: &Transform) {
+        let metrics = TransformMetrics::new(actual, expected);
+        assert!(metrics.angle < 1e-5, "{metrics}");
+        assert!(metrics.translation < 1e-5, "{metrics}");
+    }
+
+    #[rstest]
+    fn test_align_custom_points(sample_teapot_surface: PointCloud) {
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.03, -0.02, 0.01))
+            .axis_angle(Vector3::y_axis(), 0.04)
+            .build();
+        let source = &displacement * &sample_teapot_surface;
+        let target_packet = Packet::new(&sample_teapot_surface);
+        let source_packet = Packet::new(&source);
+        assert_eq!(source_packet.positions(), source.points);
+
+        let params = IcpParams {
+            max_iterations: 10,
+            ..Default::default()
+        };
+        let expected = Icp::new(params, &sample_teapot_surface)
+            .align(&source)
+            .transform;
+        assert_same(
+            &Icp::new(params, &target_packet)
+                .align(&source_packet)
+                .transform,
+            &expected,
+        );
+        assert_same(
+            &Icp::new(params, &target_packet).align(&source).transform,
+            &expected,
+        );
+
+        let gicp_params = GicpParams {
+            max_iterations: 5,
+            ..Default::default()
+        };
+        assert_same(
+            &Gicp::new(gicp_params, &target_packet)
+                .align(&source_packet)
+                .transform,
+            &Gicp::new(gicp_params, &sample_teapot_surface)
+                .align(&source)
+                .transform,
+        );
+        assert_same(
+            &Ndt::new(params, 0.15, &target_packet)
+                .align(&source_packet)
+                .transform,
+            &Ndt::new(params, 0.15, &sample_teapot_surface)
+                .align(&source)
+                .transform,
+        );
+    }
+}
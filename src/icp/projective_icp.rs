@@ -0,0 +1,279 @@
+use std::ops::ControlFlow;
+
+use nalgebra::Vector3;
+use ndarray::Array2;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+#[cfg(feature = "viz")]
+use super::gpu_icp::{GpuIcpKernel, GpuRangeImage};
+use super::{
+    cost_function::PointPlaneDistance,
+    icp_params::IcpParams,
+    icp_result::{IcpResult, IterationCallback, IterationInfo, IterationTracker},
+};
+use crate::{
+    optim::{GaussNewton, RobustLoss},
+    range_image::RangeImage,
+    transform::{LieGroup, Transform},
+};
+
+/// Finds the projective correspondence of a transformed source point.
+///
+/// # Arguments
+///
+/// * target - The target image.
+/// * target_normals - The target normals.
+/// * point - The source point in the target frame.
+/// * normal - The source normal in the target frame.
+/// * max_distance_sqr - Maximum squared distance between the points.
+/// * min_normal_cos - Minimum cosine of the angle between the normals.
+///
+/// # Returns
+///
+/// The target point and normal, or `None` if the point projects outside the target or
+/// the pair is rejected.
+fn correspondence(
+    target: &RangeImage,
+    target_normals: &Array2<Vector3<f32>>,
+    point: &Vector3<f32>,
+    normal: &Vector3<f32>,
+    max_distance_sqr: f32,
+    min_normal_cos: f32,
+) -> Option<(Vector3<f32>, Vector3<f32>)> {
+    let (row, col) = target.project_to_pixel(point)?;
+    let target_point = target.get_point(row, col)?;
+    let target_normal = target_normals[(row, col)];
+    if (target_point - point).norm_squared() > max_distance_sqr
+        || normal.dot(&target_normal) < min_normal_cos
+    {
+        return None;
+    }
+    Some((target_point, target_normal))
+}
+
+/// Accumulates the point-to-plane normal equations of the projective correspondences on the
+/// CPU.
+///
+/// # Arguments
+///
+/// * target - The target image, must have normals.
+/// * source - The source image, must have normals.
+/// * transform - The current source to target transformation.
+/// * params - The ICP parameters.
+pub(super) fn normal_equations(
+    target: &RangeImage,
+    source: &RangeImage,
+    transform: &Transform,
+    params: &IcpParams,
+) -> GaussNewton<6> {
+    let target_normals = target
+        .normals
+        .as_ref()
+        .expect("Please, the target image should have normals.");
+    let source_normals = source
+        .normals
+        .as_ref()
+        .expect("Please, the source image should have normals.");
+    let max_distance_sqr = params.max_distance * params.max_distance;
+    let min_normal_cos = params.max_normal_angle.cos();
+    let point_plane = PointPlaneDistance {};
+
+    (0..source.height())
+        .into_par_iter()
+        .map(|row| {
+            let mut optimizer = GaussNewton::<6>::new();
+            for col in 0..source.width() {
+                let Some(source_point) = source.get_point(row, col) else {
+                    continue;
+                };
+                let point = transform.transform_vector(&source_point);
+                let normal = transform.transform_normal(&source_normals[(row, col)]);
+                let Some((target_point, target_normal)) = correspondence(
+                    target,
+                    target_normals,
+                    &point,
+                    &normal,
+                    max_distance_sqr,
+                    min_normal_cos,
+                ) else {
+                    continue;
+                };
+
+                let (residual, jacobian) =
+                    point_plane.jacobian(&point, &target_point, &target_normal);
+                let weight = params
+                    .robust_loss
+                    .as_ref()
+                    .map_or(1.0, |loss| loss.weight(residual));
+                optimizer.weighted_step(residual, &jacobian, weight);
+            }
+            optimizer
+        })
+        .reduce(GaussNewton::new, |mut optimizer, other| {
+            optimizer.add(&other);
+            optimizer
+        })
+}
+
+/// Projective point-to-plane ICP between range images, as in KinectFusion: each source
+/// point is associated with the target pixel it projects to. Unlike [`super::ImageIcp`],
+/// it has no color term, which makes each iteration a single pass over the source pixels.
+///
+/// With the `viz` feature, the association and the reduction of the normal equations can
+/// run on the GPU, see [`ProjectiveIcp::with_gpu`]. The CPU implementation is used
+/// otherwise and whenever a GPU step fails.
+pub struct ProjectiveIcp<'target> {
+    /// Parameters of the ICP algorithm, the geometric cost is always point-to-plane and
+    /// the color parameters are not used.
+    pub params: IcpParams,
+    /// Initial transformation to start the algorithm. Default is the identity.
+    pub initial_transform: Transform,
+    target: &'target RangeImage,
+    #[cfg(feature = "viz")]
+    gpu: Option<(&'target GpuIcpKernel, GpuRangeImage)>,
+}
+
+impl<'target> ProjectiveIcp<'target> {
+    /// Creates a new projective ICP instance running on the CPU.
+    ///
+    /// # Arguments
+    ///
+    /// * params - Parameters of the ICP algorithm.
+    /// * target - Target range image, must have normals.
+    pub fn new(params: IcpParams, target: &'target RangeImage) -> Self {
+        Self {
+            params,
+            initial_transform: Transform::eye(),
+            target,
+            #[cfg(feature = "viz")]
+            gpu: None,
+        }
+    }
+
+    /// Runs the iterations on the GPU, uploading the target image. Keeps using the CPU if
+    /// the upload fails.
+    ///
+    /// # Arguments
+    ///
+    /// * kernel - The GPU kernel.
+    #[cfg(feature = "viz")]
+    pub fn with_gpu(mut self, kernel: &'target GpuIcpKernel) -> Self {
+        self.gpu = kernel
+            .upload(self.target)
+            .ok()
+            .map(|target| (kernel, target));
+        self
+    }
+
+    /// Whether the iterations run on the GPU.
+    pub fn uses_gpu(&self) -> bool {
+        #[cfg(feature = "viz")]
+        return self.gpu.is_some();
+        #[cfg(not(feature = "viz"))]
+        false
+    }
+
+    /// Aligns the source range image to the target range image.
+    ///
+    /// # Arguments
+    ///
+    /// * source - The source range image, must have normals.
+    ///
+    /// # Returns
+    ///
+    /// The transformation that aligns the source to the target, the inliers are the
+    /// correspondences.
+    pub fn align(&self, source: &RangeImage) -> IcpResult {
+        self.align_impl(source, None)
+    }
+
+    /// Same as `align`, reporting the progress of every iteration to a callback that can
+    /// abort the run.
+    ///
+    /// # Arguments
+    ///
+    /// * source - Same as `align`.
+    /// * callback - Called after every iteration, see [`IterationCallback`].
+    pub fn align_with_callback(
+        &self,
+        source: &RangeImage,
+        mut callback: impl FnMut(IterationInfo) -> ControlFlow<()>,
+    ) -> IcpResult {
+        self.align_impl(source, Some(&mut callback))
+    }
+
+    fn align_impl<'a>(
+        &self,
+        source: &RangeImage,
+        callback: Option<&'a mut IterationCallback<'a>>,
+    ) -> IcpResult {
+        #[cfg(feature = "viz")]
+        let gpu_source = self
+            .gpu
+            .as_ref()
+            .and_then(|(kernel, _)| kernel.upload(source).ok());
+
+        let accumulate = |transform: &Transform| {
+            #[cfg(feature = "viz")]
+            if let (Some((kernel, target)), Some(gpu_source)) = (&self.gpu, &gpu_source) {
+                if let Ok(optimizer) =
+                    kernel.normal_equations(target, gpu_source, transform, &self.params)
+                {
+                    return optimizer;
+                }
+            }
+            normal_equations(self.target, source, transform, &self.params)
+        };
+
+        let mut optim_transform = self.initial_transform.clone();
+        let mut tracker =
+            IterationTracker::new(&self.params, &optim_transform).with_callback(callback);
+        for _ in 0..self.params.max_iterations {
+            let mut optimizer = accumulate(&optim_transform);
+            let num_residuals = optimizer.count();
+            let residual = optimizer.mean_squared_residual();
+            optimizer.weight(self.params.weight);
+            let Some(update) = optimizer.solve() else {
+                break;
+            };
+            optim_transform = &Transform::exp(&LieGroup::Se3(update)) * &optim_transform;
+            tracker.record(&optim_transform, &optimizer, num_residuals);
+            if tracker.should_stop(&update, residual) {
+                break;
+            }
+        }
+        tracker.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::ProjectiveIcp;
+    use crate::{
+        icp::icp_params::IcpParams,
+        metrics::TransformMetrics,
+        unit_test::{sample_range_img_ds2, TestRangeImageDataset},
+    };
+
+    #[rstest]
+    fn test_align(sample_range_img_ds2: TestRangeImageDataset) {
+        let target = sample_range_img_ds2.get(0).unwrap();
+        let source = sample_range_img_ds2.get(1).unwrap();
+        let gt_transform = sample_range_img_ds2.get_ground_truth(1, 0);
+
+        let icp = ProjectiveIcp::new(
+            IcpParams {
+                max_iterations: 10,
+                ..Default::default()
+            },
+            &target,
+        );
+        assert!(!icp.uses_gpu());
+        let result = icp.align(&source);
+        let metrics = TransformMetrics::new(&result.transform, &gt_transform);
+        assert!(metrics.angle < 0.01, "{metrics}");
+        assert!(result.inlier_count > source.valid_points_count() / 2);
+    }
+}
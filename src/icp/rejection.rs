@@ -0,0 +1,92 @@
+/// Rejection of correspondences beyond the distance and normal angle thresholds of
+/// [`super::IcpParams`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CorrespondenceRejection {
+    /// Fraction of the correspondences kept in each iteration, the closest ones. Trimmed ICP
+    /// for partially overlapping scans, 1.0 keeps all.
+    pub keep_fraction: f32,
+    /// Keeps only the pairs whose source point is also the closest source point to their
+    /// target point.
+    pub reciprocal: bool,
+}
+
+impl Default for CorrespondenceRejection {
+    fn default() -> Self {
+        Self {
+            keep_fraction: 1.0,
+            reciprocal: false,
+        }
+    }
+}
+
+impl CorrespondenceRejection {
+    /// Keeps the closest fraction of the correspondences.
+    ///
+    /// # Arguments
+    ///
+    /// * correspondences - The correspondences, reordered by distance if trimmed.
+    /// * sqr_distance - Squared distance of a correspondence.
+    ///
+    /// # Returns
+    ///
+    /// The number of trimmed correspondences.
+    pub(super) fn trim<T, F>(&self, correspondences: &mut Vec<T>, sqr_distance: F) -> usize
+    where
+        F: Fn(&T) -> f32,
+    {
+        if self.keep_fraction >= 1.0 {
+            return 0;
+        }
+        let count = correspondences.len();
+        let keep = (count as f32 * self.keep_fraction.max(0.0)).ceil() as usize;
+        correspondences.sort_by(|a, b| sqr_distance(a).total_cmp(&sqr_distance(b)));
+        correspondences.truncate(keep);
+        count - keep
+    }
+}
+
+/// Counts of the correspondence candidates and why they were rejected, for the iteration
+/// of the returned transform.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RejectionStats {
+    /// Number of source points matched to a target point.
+    pub candidates: usize,
+    /// Rejected by the maximum distance, or without a projective correspondence.
+    pub distance: usize,
+    /// Rejected by the maximum normal angle.
+    pub normal_angle: usize,
+    /// Rejected by the reciprocal check.
+    pub reciprocal: usize,
+    /// Rejected by trimming.
+    pub trimmed: usize,
+}
+
+impl RejectionStats {
+    /// Number of correspondences used in the optimization.
+    pub fn inliers(&self) -> usize {
+        self.candidates - self.distance - self.normal_angle - self.reciprocal - self.trimmed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CorrespondenceRejection;
+
+    #[test]
+    fn test_trim() {
+        let rejection = CorrespondenceRejection {
+            keep_fraction: 0.5,
+            ..Default::default()
+        };
+        let mut correspondences = vec![0.4, 0.1, 0.3, 0.2, 0.5];
+        assert_eq!(rejection.trim(&mut correspondences, |value| *value), 2);
+        assert_eq!(correspondences, vec![0.1, 0.2, 0.3]);
+
+        let mut correspondences = vec![0.4, 0.1];
+        assert_eq!(
+            CorrespondenceRejection::default().trim(&mut correspondences, |value| *value),
+            0
+        );
+        assert_eq!(correspondences, vec![0.4, 0.1]);
+    }
+}
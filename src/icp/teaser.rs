@@ -0,0 +1,503 @@
+use nalgebra::{Isometry3, Matrix3, Rotation3, Translation3, UnitQuaternion, Vector3};
+
+use super::global_registration::{match_features, GlobalRegistrationResult};
+use crate::{
+    error::A3dError,
+    features::{compute_fpfh, FpfhParams},
+    pointcloud::PointCloud,
+    transform::Transform,
+};
+
+/// Parameters of the TEASER registration.
+#[derive(Debug, Clone, Copy)]
+pub struct TeaserParams {
+    /// Parameters of the FPFH features, only used by [`teaser_registration`].
+    pub feature: FpfhParams,
+    /// Maximum distance between an aligned source point and its target of an inlier
+    /// correspondence. Set it a bit above the noise of the points.
+    pub noise_bound: f32,
+    /// Estimates the scale of the source, e.g., for clouds of different sensors. Otherwise
+    /// the scale is 1.
+    pub estimate_scale: bool,
+    /// Maximum number of iterations of the rotation estimation.
+    pub rotation_max_iterations: usize,
+    /// Growth factor of the graduated non-convexity of the rotation estimation, smaller
+    /// values are slower but more robust.
+    pub rotation_gnc_factor: f32,
+    /// The rotation estimation stops once its cost changes less than this.
+    pub rotation_cost_threshold: f32,
+}
+
+impl Default for TeaserParams {
+    fn default() -> Self {
+        Self {
+            feature: FpfhParams::default(),
+            noise_bound: 0.02,
+            estimate_scale: false,
+            rotation_max_iterations: 100,
+            rotation_gnc_factor: 1.4,
+            rotation_cost_threshold: 1e-6,
+        }
+    }
+}
+
+/// Outcome of [`teaser_solve`].
+#[derive(Debug, Clone)]
+pub struct TeaserSolution {
+    /// The rigid part of the alignment, `target = scale * rotation * source + translation`.
+    pub transform: Transform,
+    /// Scale of the source, 1 if not estimated.
+    pub scale: f32,
+    /// Indices of the inlier correspondences.
+    pub inliers: Vec<usize>,
+}
+
+/// Truncated least squares estimate of a scalar from measurements with individual bounds,
+/// by adaptive voting: the consensus set only changes at the interval boundaries, so the
+/// optimum is the weighted mean of one of the sets between them.
+///
+/// # Arguments
+///
+/// * measurements - Pairs of value and noise bound.
+///
+/// # Returns
+///
+/// The estimate, `None` without measurements.
+fn adaptive_voting(measurements: &[(f32, f32)]) -> Option<f32> {
+    let mut boundaries = measurements
+        .iter()
+        .flat_map(|&(value, bound)| {
+            let weight = 1.0 / (bound as f64 * bound as f64).max(f64::EPSILON);
+            [
+                (value - bound, 1.0_f64, weight, value as f64),
+                (value + bound, -1.0, weight, value as f64),
+            ]
+        })
+        .collect::<Vec<_>>();
+    // Closed intervals, the openings go first on ties.
+    boundaries.sort_by(|a, b| a.0.total_cmp(&b.0).then(b.1.total_cmp(&a.1)));
+
+    let (mut sum_w, mut sum_wv, mut sum_wvv, mut count) = (0.0, 0.0, 0.0, 0.0);
+    let mut best: Option<(f64, f64)> = None;
+    for (_, sign, weight, value) in boundaries {
+        sum_w += sign * weight;
+        sum_wv += sign * weight * value;
+        sum_wvv += sign * weight * value * value;
+        count += sign;
+        if sign < 0.0 || count < 0.5 {
+            continue;
+        }
+        let estimate = sum_wv / sum_w;
+        let cost = (sum_wvv - sum_wv * estimate).max(0.0) + measurements.len() as f64 - count;
+        if best.is_none_or(|(best_cost, _)| cost < best_cost) {
+            best = Some((cost, estimate));
+        }
+    }
+    best.map(|(_, estimate)| estimate as f32)
+}
+
+/// Rotation minimizing `sum(w |b - R a|^2)`, without centering as the measurements are
+/// translation invariant. `None` if they are degenerate.
+fn weighted_rotation(
+    source: &[Vector3<f32>],
+    target: &[Vector3<f32>],
+    weights: &[f32],
+) -> Option<Rotation3<f32>> {
+    let covariance = source
+        .iter()
+        .zip(target)
+        .zip(weights)
+        .fold(Matrix3::zeros(), |acc, ((source, target), weight)| {
+            acc + source * target.transpose() * *weight
+        });
+    let svd = covariance.try_svd(true, true, f32::EPSILON, 100)?;
+    let (u, v_t) = (svd.u?, svd.v_t?);
+    let mut correction = Matrix3::identity();
+    if (v_t.transpose() * u.transpose()).determinant() < 0.0 {
+        correction[(2, 2)] = -1.0;
+    }
+    Some(Rotation3::from_matrix_unchecked(
+        v_t.transpose() * correction * u.transpose(),
+    ))
+}
+
+/// Rotation by graduated non-convexity of the truncated least squares cost (Yang et al.,
+/// 2020): the cost starts convex and is made closer to the truncation every iteration,
+/// each solved as a weighted least squares.
+fn gnc_tls_rotation(
+    source: &[Vector3<f32>],
+    target: &[Vector3<f32>],
+    bound: f32,
+    params: &TeaserParams,
+) -> Option<Rotation3<f32>> {
+    let sqr_bound = bound * bound;
+    let mut weights = vec![1.0; source.len()];
+    let mut rotation = weighted_rotation(source, target, &weights)?;
+    let sqr_residuals = |rotation: &Rotation3<f32>| {
+        source
+            .iter()
+            .zip(target)
+            .map(|(source, target)| (target - rotation * source).norm_squared())
+            .collect::<Vec<_>>()
+    };
+
+    let max_sqr_residual = sqr_residuals(&rotation).into_iter().fold(0.0, f32::max);
+    if max_sqr_residual <= sqr_bound {
+        return Some(rotation);
+    }
+    let mut mu = sqr_bound / (2.0 * max_sqr_residual - sqr_bound).max(f32::EPSILON);
+    let mut previous_cost = f32::INFINITY;
+    for _ in 0..params.rotation_max_iterations {
+        let residuals = sqr_residuals(&rotation);
+        let (upper, lower) = ((mu + 1.0) / mu * sqr_bound, mu / (mu + 1.0) * sqr_bound);
+        for (weight, residual) in weights.iter_mut().zip(&residuals) {
+            *weight = if *residual >= upper {
+                0.0
+            } else if *residual <= lower {
+                1.0
+            } else {
+                bound * (mu * (mu + 1.0) / residual).sqrt() - mu
+            };
+        }
+        rotation = weighted_rotation(source, target, &weights)?;
+
+        let cost = sqr_residuals(&rotation)
+            .iter()
+            .zip(&weights)
+            .map(|(residual, weight)| weight * residual)
+            .sum::<f32>();
+        if (cost - previous_cost).abs() < params.rotation_cost_threshold
+            || weights
+                .iter()
+                .all(|weight| *weight == 0.0 || *weight == 1.0)
+        {
+            break;
+        }
+        previous_cost = cost;
+        mu *= params.rotation_gnc_factor;
+    }
+    Some(rotation)
+}
+
+/// Approximates the largest set of pairwise consistent correspondences, greedily growing
+/// a clique from each vertex by decreasing degree. Growths that can't beat the best clique
+/// are abandoned.
+fn max_clique(adjacency: &[Vec<bool>]) -> Vec<usize> {
+    let degrees = adjacency
+        .iter()
+        .map(|row| row.iter().filter(|adjacent| **adjacent).count())
+        .collect::<Vec<_>>();
+    let mut order = (0..adjacency.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| degrees[*b].cmp(&degrees[*a]));
+
+    let mut best = Vec::new();
+    for &start in &order {
+        if degrees[start] < best.len() {
+            break;
+        }
+        let mut clique = vec![start];
+        // Vertices adjacent to the whole clique, by decreasing degree.
+        let mut candidates = order
+            .iter()
+            .copied()
+            .filter(|&vertex| adjacency[start][vertex])
+            .collect::<Vec<_>>();
+        while let Some((&next, rest)) = candidates.split_first() {
+            if clique.len() + candidates.len() <= best.len() {
+                break;
+            }
+            clique.push(next);
+            candidates = rest
+                .iter()
+                .copied()
+                .filter(|&vertex| adjacency[next][vertex])
+                .collect();
+        }
+        if clique.len() > best.len() {
+            best = clique;
+        }
+    }
+    best
+}
+
+/// Robustly solves the transformation between matched points with TEASER (Yang et al.,
+/// TEASER: Fast and Certifiable Point Cloud Registration, 2020), which tolerates most of
+/// the correspondences being outliers. The scale, rotation and translation are estimated
+/// in sequence:
+///
+/// 1. The differences between pairs of correspondences are invariant to the translation
+///    and their lengths to the rotation. Their length ratios vote the scale, and the pairs
+///    inconsistent with it are pruned.
+/// 2. The largest set of mutually consistent correspondences (maximum clique) is kept.
+/// 3. The rotation is solved on the pair differences of the clique by graduated
+///    non-convexity of a truncated least squares cost.
+/// 4. Each translation axis is voted by truncated least squares.
+///
+/// The maximum clique is approximated greedily, so the result isn't certified.
+///
+/// # Arguments
+///
+/// * source - The source points.
+/// * target - The target point of each source point.
+/// * params - The solver parameters.
+///
+/// # Returns
+///
+/// The solution, or error if the point slices have different lengths or fewer than 3
+/// correspondences are consistent.
+pub fn teaser_solve(
+    source: &[Vector3<f32>],
+    target: &[Vector3<f32>],
+    params: &TeaserParams,
+) -> Result<TeaserSolution, A3dError> {
+    if source.len() != target.len() {
+        return Err(A3dError::invalid_parameter(
+            "The source and target points must have the same length.",
+        ));
+    }
+    if params.noise_bound <= 0.0 {
+        return Err(A3dError::invalid_parameter(
+            "The noise bound must be positive.",
+        ));
+    }
+    let count = source.len();
+    let pair_bound = 2.0 * params.noise_bound;
+    let pairs = (0..count)
+        .flat_map(|i| (i + 1..count).map(move |j| (i, j)))
+        .map(|(i, j)| {
+            (
+                i,
+                j,
+                (source[j] - source[i]).norm(),
+                (target[j] - target[i]).norm(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let scale = if params.estimate_scale {
+        adaptive_voting(
+            &pairs
+                .iter()
+                // Short pairs vote for almost any scale.
+                .filter(|(_, _, source_length, _)| *source_length > pair_bound)
+                .map(|(_, _, source_length, target_length)| {
+                    (target_length / source_length, pair_bound / source_length)
+                })
+                .collect::<Vec<_>>(),
+        )
+        .unwrap_or(1.0)
+    } else {
+        1.0
+    };
+
+    let mut adjacency = vec![vec![false; count]; count];
+    for (i, j, source_length, target_length) in pairs {
+        if (target_length - scale * source_length).abs() <= pair_bound {
+            adjacency[i][j] = true;
+            adjacency[j][i] = true;
+        }
+    }
+    let clique = max_clique(&adjacency);
+    if clique.len() < 3 {
+        return Err(A3dError::invalid_parameter(
+            "TEASER found fewer than 3 consistent correspondences.",
+        ));
+    }
+
+    let (source_differences, target_differences): (Vec<_>, Vec<_>) = clique
+        .iter()
+        .enumerate()
+        .flat_map(|(k, &i)| clique[k + 1..].iter().map(move |&j| (i, j)))
+        .map(|(i, j)| (scale * (source[j] - source[i]), target[j] - target[i]))
+        .unzip();
+    let rotation =
+        gnc_tls_rotation(&source_differences, &target_differences, pair_bound, params)
+            .ok_or_else(|| A3dError::invalid_parameter("TEASER couldn't estimate the rotation."))?;
+
+    let translation_measurements = clique
+        .iter()
+        .map(|&i| target[i] - scale * (rotation * source[i]))
+        .collect::<Vec<_>>();
+    let translation = Vector3::from_fn(|axis, _| {
+        adaptive_voting(
+            &translation_measurements
+                .iter()
+                .map(|measurement| (measurement[axis], params.noise_bound))
+                .collect::<Vec<_>>(),
+        )
+        .unwrap_or_default()
+    });
+
+    let mut inliers = clique
+        .into_iter()
+        .filter(|&i| {
+            (target[i] - scale * (rotation * source[i]) - translation).amax() <= params.noise_bound
+        })
+        .collect::<Vec<_>>();
+    inliers.sort_unstable();
+    Ok(TeaserSolution {
+        transform: Transform(Isometry3::from_parts(
+            Translation3::from(translation),
+            UnitQuaternion::from_rotation_matrix(&rotation),
+        )),
+        scale,
+        inliers,
+    })
+}
+
+/// Globally aligns two point clouds by matching their FPFH features and solving the
+/// matches with [`teaser_solve`]. Unlike RANSAC, its runtime doesn't grow with the outlier
+/// ratio, but it is quadratic on the number of mutual matches. Refine the result with ICP.
+///
+/// # Arguments
+///
+/// * target - The reference point cloud, must have normals.
+/// * source - The point cloud to align, must have normals.
+/// * params - The registration parameters, the scale estimation is ignored.
+///
+/// # Returns
+///
+/// The registration, or error if a point cloud has no normals or fewer than 3 matches are
+/// consistent.
+pub fn teaser_registration(
+    target: &PointCloud,
+    source: &PointCloud,
+    params: &TeaserParams,
+) -> Result<GlobalRegistrationResult, A3dError> {
+    let target_features = compute_fpfh(target, &params.feature)?;
+    let source_features = compute_fpfh(source, &params.feature)?;
+
+    // Mutual nearest features.
+    let target_matches = match_features(&source_features, &target_features);
+    let correspondences = match_features(&target_features, &source_features)
+        .into_iter()
+        .filter(|&(source_index, target_index)| target_matches[target_index].1 == source_index)
+        .collect::<Vec<_>>();
+
+    let (source_points, target_points): (Vec<_>, Vec<_>) = correspondences
+        .iter()
+        .map(|&(source_index, target_index)| {
+            (source.points[source_index], target.points[target_index])
+        })
+        .unzip();
+    let solution = teaser_solve(
+        &source_points,
+        &target_points,
+        &TeaserParams {
+            estimate_scale: false,
+            ..*params
+        },
+    )?;
+    Ok(GlobalRegistrationResult {
+        transform: solution.transform,
+        inlier_count: solution.inliers.len(),
+        correspondence_count: correspondences.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Axis;
+    use rand::Rng;
+    use rstest::rstest;
+
+    use super::{adaptive_voting, teaser_registration, teaser_solve, TeaserParams};
+    use crate::{
+        features::FpfhParams, metrics::TransformMetrics, pointcloud::PointCloud,
+        random::RandomState, transform::TransformBuilder, unit_test::sample_teapot_surface,
+    };
+
+    #[test]
+    fn test_adaptive_voting() {
+        let measurements = [
+            (1.0, 0.1),
+            (1.05, 0.1),
+            (0.95, 0.1),
+            (3.0, 0.1),
+            (-2.0, 0.1),
+        ];
+        assert!((adaptive_voting(&measurements).unwrap() - 1.0).abs() < 1e-5);
+        assert!(adaptive_voting(&[]).is_none());
+    }
+
+    #[test]
+    fn test_teaser_solve() {
+        let mut rng = RandomState::new(5).rng();
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.5, -0.3, 0.2))
+            .axis_angle(Vector3::z_axis(), 2.0)
+            .build();
+        let scale = 1.5;
+        let count = 100;
+        let source = (0..count)
+            .map(|_| Vector3::from_fn(|_, _| rng.gen_range(-1.0..1.0)))
+            .collect::<Vec<_>>();
+        // 80% of the correspondences are outliers.
+        let target = source
+            .iter()
+            .enumerate()
+            .map(|(i, point)| {
+                if i % 5 == 0 {
+                    displacement.transform_vector(&(point * scale))
+                        + Vector3::from_fn(|_, _| rng.gen_range(-0.005..0.005))
+                } else {
+                    Vector3::from_fn(|_, _| rng.gen_range(-2.0..2.0))
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let solution = teaser_solve(
+            &source,
+            &target,
+            &TeaserParams {
+                noise_bound: 0.01,
+                estimate_scale: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let metrics = TransformMetrics::new(&solution.transform, &displacement);
+        assert!(metrics.angle < 0.01, "{metrics}");
+        assert!(metrics.translation < 0.01, "{metrics}");
+        assert!((solution.scale - scale).abs() < 0.01);
+        assert_eq!(solution.inliers, (0..count).step_by(5).collect::<Vec<_>>());
+
+        assert!(teaser_solve(&source, &target[1..], &TeaserParams::default()).is_err());
+    }
+
+    #[rstest]
+    fn test_teaser_registration(sample_teapot_surface: PointCloud) {
+        let indices = (0..sample_teapot_surface.len())
+            .step_by(4)
+            .collect::<Vec<_>>();
+        let target = PointCloud {
+            points: sample_teapot_surface.points.select(Axis(0), &indices),
+            normals: sample_teapot_surface
+                .normals
+                .map(|normals| normals.select(Axis(0), &indices)),
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+        let displacement = TransformBuilder::default()
+            .translation(Vector3::new(0.5, -0.3, 0.2))
+            .axis_angle(Vector3::z_axis(), 2.0)
+            .build();
+        let source = &displacement * &target;
+
+        let params = TeaserParams {
+            feature: FpfhParams {
+                radius: 0.15,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = teaser_registration(&target, &source, &params).unwrap();
+        let metrics = TransformMetrics::new(&result.transform, &displacement.inverse());
+        assert!(metrics.angle < 0.05, "{metrics}");
+        assert!(metrics.translation < 0.05, "{metrics}");
+        assert!(result.inlier_count >= 3);
+    }
+}
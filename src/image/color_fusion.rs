@@ -0,0 +1,218 @@
+use std::collections::VecDeque;
+
+use nalgebra::Vector3;
+
+/// How the color samples of a surface element are combined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorFusionPolicy {
+    /// Weighted mean of all accepted samples.
+    Mean,
+    /// Per channel mean of the recent samples after discarding the given fraction of the
+    /// lowest and of the highest values.
+    TrimmedMean(f32),
+    /// Per channel median of the recent samples.
+    Median,
+}
+
+/// Parameters of the color fusion.
+#[derive(Debug, Clone, Copy)]
+pub struct ColorFusionParams {
+    /// The combination policy.
+    pub policy: ColorFusionPolicy,
+    /// Number of recent samples kept for the robust policies.
+    pub max_samples: usize,
+    /// Samples whose linear RGB distance to the current color is larger than this are
+    /// rejected. Rejection only starts after `min_samples_for_rejection` samples.
+    pub outlier_threshold: f32,
+    /// Number of accepted samples before outliers are rejected.
+    pub min_samples_for_rejection: usize,
+}
+
+impl Default for ColorFusionParams {
+    fn default() -> Self {
+        Self {
+            policy: ColorFusionPolicy::TrimmedMean(0.2),
+            max_samples: 16,
+            outlier_threshold: 0.25,
+            min_samples_for_rejection: 3,
+        }
+    }
+}
+
+/// Converts an 8 bit sRGB value into linear RGB in the [0, 1] range.
+pub fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear RGB value in the [0, 1] range into 8 bit sRGB.
+pub fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let value = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value * 255.0).round() as u8
+}
+
+/// Accumulates the color observations of a surface element, e.g., a surfel or a voxel.
+/// Colors are fused in linear RGB, averaging sRGB values directly darkens and washes out
+/// the result.
+#[derive(Debug, Clone, Default)]
+pub struct ColorAccumulator {
+    pub(crate) sum: Vector3<f32>,
+    pub(crate) weight_sum: f32,
+    pub(crate) count: usize,
+    pub(crate) samples: VecDeque<Vector3<f32>>,
+}
+
+impl ColorAccumulator {
+    /// Creates an empty accumulator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of accepted samples.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Adds a color observation.
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The observed sRGB color.
+    /// * `weight` - The confidence of the observation, used by the mean policy.
+    /// * `params` - The fusion parameters.
+    ///
+    /// # Returns
+    ///
+    /// * Whether the sample was accepted, false for outliers.
+    pub fn add(&mut self, color: &Vector3<u8>, weight: f32, params: &ColorFusionParams) -> bool {
+        let sample = color.map(srgb_to_linear);
+        if self.count >= params.min_samples_for_rejection {
+            if let Some(current) = self.linear_color(params) {
+                if (sample - current).norm() > params.outlier_threshold {
+                    return false;
+                }
+            }
+        }
+
+        self.sum += sample * weight;
+        self.weight_sum += weight;
+        self.count += 1;
+        self.samples.push_back(sample);
+        while self.samples.len() > params.max_samples.max(1) {
+            self.samples.pop_front();
+        }
+        true
+    }
+
+    /// The fused color in linear RGB, or `None` if no sample was accepted.
+    pub fn linear_color(&self, params: &ColorFusionParams) -> Option<Vector3<f32>> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let trim = match params.policy {
+            ColorFusionPolicy::Mean => {
+                return if self.weight_sum > 0.0 {
+                    Some(self.sum / self.weight_sum)
+                } else {
+                    None
+                };
+            }
+            ColorFusionPolicy::TrimmedMean(fraction) => fraction.clamp(0.0, 0.49),
+            ColorFusionPolicy::Median => 0.5,
+        };
+
+        let mut color = Vector3::zeros();
+        let mut channel = Vec::with_capacity(self.samples.len());
+        for c in 0..3 {
+            channel.clear();
+            channel.extend(self.samples.iter().map(|sample| sample[c]));
+            channel.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+            let n = channel.len();
+            color[c] = if trim >= 0.5 {
+                if n % 2 == 1 {
+                    channel[n / 2]
+                } else {
+                    (channel[n / 2 - 1] + channel[n / 2]) * 0.5
+                }
+            } else {
+                let cut = ((n as f32 * trim) as usize).min((n - 1) / 2);
+                let kept = &channel[cut..n - cut];
+                kept.iter().sum::<f32>() / kept.len() as f32
+            };
+        }
+        Some(color)
+    }
+
+    /// The fused sRGB color, or `None` if no sample was accepted.
+    pub fn color(&self, params: &ColorFusionParams) -> Option<Vector3<u8>> {
+        self.linear_color(params)
+            .map(|color| color.map(linear_to_srgb))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::{
+        linear_to_srgb, srgb_to_linear, ColorAccumulator, ColorFusionParams, ColorFusionPolicy,
+    };
+
+    #[test]
+    fn test_srgb_roundtrip() {
+        for value in 0..=255u8 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(value)), value);
+        }
+    }
+
+    #[test]
+    fn test_linear_mean() {
+        let params = ColorFusionParams {
+            policy: ColorFusionPolicy::Mean,
+            outlier_threshold: f32::INFINITY,
+            ..Default::default()
+        };
+        let mut accum = ColorAccumulator::new();
+        accum.add(&Vector3::new(0, 0, 0), 1.0, &params);
+        accum.add(&Vector3::new(255, 255, 255), 1.0, &params);
+
+        // The sRGB mean would be 128, mixing in linear space is brighter.
+        assert_eq!(accum.color(&params), Some(Vector3::new(188, 188, 188)));
+    }
+
+    #[test]
+    fn test_outlier_rejection() {
+        for policy in [
+            ColorFusionPolicy::Mean,
+            ColorFusionPolicy::TrimmedMean(0.2),
+            ColorFusionPolicy::Median,
+        ] {
+            let params = ColorFusionParams {
+                policy,
+                ..Default::default()
+            };
+            let mut accum = ColorAccumulator::new();
+            for value in [100, 102, 98, 101, 99] {
+                assert!(accum.add(&Vector3::new(value, 50, 20), 1.0, &params));
+            }
+            assert!(!accum.add(&Vector3::new(255, 0, 255), 1.0, &params));
+            assert_eq!(accum.count(), 5);
+
+            let color = accum.color(&params).unwrap();
+            assert!((98..=102).contains(&color[0]));
+            assert_eq!(color[1], 50);
+            assert_eq!(color[2], 20);
+        }
+    }
+}
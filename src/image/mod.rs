@@ -6,3 +6,7 @@ pub use luma::{rgb_to_luma, rgb_to_luma_u8, IntoLumaArray, IntoLumaImage};
 
 mod rgbd_image;
 pub use rgbd_image::{RgbdFrame, RgbdImage};
+
+mod color_fusion;
+pub(crate) use color_fusion::{linear_to_srgb, srgb_to_linear};
+pub use color_fusion::{ColorAccumulator, ColorFusionParams, ColorFusionPolicy};
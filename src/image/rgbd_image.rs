@@ -3,6 +3,7 @@ use ndarray::{Array2, Array3};
 use crate::{
     bilateral::BilateralFilter,
     camera::{CameraIntrinsics, PinholeCamera},
+    metadata::{Exposure, Metadata},
     sampling::Downsample,
     transform::Transform,
 };
@@ -14,6 +15,12 @@ pub struct RgbdImage {
     pub color: Array3<u8>,
     pub depth: Array2<u16>,
     pub depth_scale: Option<f64>,
+    /// Per-pixel confidence of the depth in [0, 1], with shape (height, width). Provided by
+    /// some sensors, e.g., stereo matching confidence or ToF amplitude.
+    pub confidence: Option<Array2<f32>>,
+    /// Per-pixel class labels, with shape (height, width). Produced by segmentation models,
+    /// see [`crate::pipeline::FrameModelStage`].
+    pub labels: Option<Array2<u32>>,
 }
 
 impl RgbdImage {
@@ -22,6 +29,8 @@ impl RgbdImage {
             color,
             depth,
             depth_scale: None,
+            confidence: None,
+            labels: None,
         }
     }
 
@@ -30,9 +39,23 @@ impl RgbdImage {
             color,
             depth,
             depth_scale: Some(depth_scale),
+            confidence: None,
+            labels: None,
         }
     }
 
+    /// Sets the depth confidence map, see [`RgbdImage::confidence`].
+    pub fn with_confidence(mut self, confidence: Array2<f32>) -> Self {
+        self.confidence = Some(confidence);
+        self
+    }
+
+    /// Sets the class labels, see [`RgbdImage::labels`].
+    pub fn with_labels(mut self, labels: Array2<u32>) -> Self {
+        self.labels = Some(labels);
+        self
+    }
+
     pub fn width(&self) -> usize {
         self.color.shape()[1]
     }
@@ -40,6 +63,32 @@ impl RgbdImage {
     pub fn height(&self) -> usize {
         self.color.shape()[0]
     }
+
+    /// Rescales the colors captured with `exposure` to how they would appear with the
+    /// `reference` exposure. Colors are linearized with a 2.2 gamma before scaling.
+    ///
+    /// # Arguments
+    ///
+    /// * `exposure` - The exposure used to capture the image.
+    /// * `reference` - The target exposure, e.g., the one of the first frame.
+    pub fn correct_exposure(&mut self, exposure: &Exposure, reference: &Exposure) {
+        const GAMMA: f64 = 2.2;
+        let scales = exposure.linear_scale_to(reference);
+        let lookup = scales.map(|scale| {
+            let mut table = [0u8; 256];
+            for (value, corrected) in table.iter_mut().enumerate() {
+                let linear = (value as f64 / 255.0).powf(GAMMA) * scale;
+                *corrected = (linear.min(1.0).powf(1.0 / GAMMA) * 255.0).round() as u8;
+            }
+            table
+        });
+
+        for mut pixel in self.color.rows_mut() {
+            for (channel, value) in pixel.iter_mut().enumerate().take(3) {
+                *value = lookup[channel][*value as usize];
+            }
+        }
+    }
 }
 
 impl Downsample for RgbdImage {
@@ -50,10 +99,21 @@ impl Downsample for RgbdImage {
 
         let resized_depth = depth_filter.scale_down(&self.depth);
 
+        let confidence = self.confidence.as_ref().map(|confidence| {
+            Array2::from_shape_fn(resized_depth.dim(), |(row, col)| {
+                confidence[(row * 2, col * 2)]
+            })
+        });
+        let labels = self.labels.as_ref().map(|labels| {
+            Array2::from_shape_fn(resized_depth.dim(), |(row, col)| labels[(row * 2, col * 2)])
+        });
+
         RgbdImage {
             color: resized_color,
             depth: resized_depth,
             depth_scale: self.depth_scale,
+            confidence,
+            labels,
         }
     }
 }
@@ -66,6 +126,8 @@ pub struct RgbdFrame {
     pub camera_to_world: Option<Transform>,
     /// The RGB-D image.
     pub image: RgbdImage,
+    /// Provenance of the frame, e.g., its dataset index and timestamp.
+    pub metadata: Metadata,
 }
 
 impl RgbdFrame {
@@ -78,6 +140,30 @@ impl RgbdFrame {
             camera,
             image,
             camera_to_world,
+            metadata: Metadata::default(),
+        }
+    }
+
+    /// Sets the provenance of the frame.
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Corrects the colors to the `reference` exposure, see [`RgbdImage::correct_exposure`].
+    /// Use it to compensate auto-exposure and white balance changes before fusing colors.
+    ///
+    /// # Returns
+    ///
+    /// Whether the correction was applied, it requires the frame's exposure metadata.
+    pub fn correct_exposure(&mut self, reference: &Exposure) -> bool {
+        match self.metadata.exposure {
+            Some(exposure) => {
+                self.image.correct_exposure(&exposure, reference);
+                self.metadata.exposure = Some(*reference);
+                true
+            }
+            None => false,
         }
     }
 
@@ -100,6 +186,7 @@ impl Downsample for RgbdFrame {
             camera: self.camera.scale(0.5),
             image: self.image.downsample(scale),
             camera_to_world: self.camera_to_world.clone(),
+            metadata: self.metadata.clone(),
         }
     }
 }
@@ -109,7 +196,10 @@ mod tests {
     use rstest::rstest;
 
     use crate::{
-        image::IntoImageRgb8, io::dataset::RgbdDataset, sampling::Downsample,
+        image::IntoImageRgb8,
+        io::dataset::RgbdDataset,
+        metadata::{Exposure, Metadata},
+        sampling::Downsample,
         unit_test::sample_rgbd_dataset1,
     };
 
@@ -127,4 +217,33 @@ mod tests {
             .save("scale_05_color.png")
             .unwrap();
     }
+
+    #[rstest]
+    fn test_correct_exposure(sample_rgbd_dataset1: impl RgbdDataset) {
+        let mut frame = sample_rgbd_dataset1.get(0).unwrap();
+        let reference = Exposure::new(0.02);
+        assert!(!frame.correct_exposure(&reference));
+
+        let original = frame.image.color.clone();
+        frame = frame.with_metadata(Metadata {
+            exposure: Some(Exposure {
+                white_balance: [1.0, 2.0, 1.0],
+                ..Exposure::new(0.01)
+            }),
+            ..Default::default()
+        });
+        assert!(frame.correct_exposure(&reference));
+        assert_eq!(frame.metadata.exposure, Some(reference));
+
+        // Red doubles its exposure, green is kept as its white balance gain is halved.
+        let mean = |image: &ndarray::Array3<u8>, channel: usize| {
+            image
+                .index_axis(ndarray::Axis(2), channel)
+                .mapv(f64::from)
+                .mean()
+                .unwrap()
+        };
+        assert!(mean(&frame.image.color, 0) > mean(&original, 0) * 1.2);
+        assert!((mean(&frame.image.color, 1) - mean(&original, 1)).abs() < 0.5);
+    }
 }
@@ -1,15 +1,36 @@
-use image::ImageError;
+use image::{DynamicImage, ImageError};
+use ndarray::{Array2, Array3};
+use nshare::ToNdarray2;
 
 use crate::{
-    camera::CameraIntrinsics, image::RgbdFrame, trajectory::Trajectory, transform::Transform,
+    camera::CameraIntrinsics,
+    image::{IntoArray3, RgbdFrame},
+    trajectory::Trajectory,
+    transform::Transform,
 };
-use std::io::Error;
+use std::{io::Error, path::Path};
 
 #[derive(Debug)]
 pub enum DatasetError {
     Io(Error),
     Parser(String),
     Image(ImageError),
+    /// A frame could not be loaded (e.g., truncated or missing image). Holds the frame
+    /// index and the cause.
+    Frame(usize, Box<DatasetError>),
+}
+
+impl DatasetError {
+    /// Creates an error of the kind `Frame`. Errors that already are from a frame are kept.
+    /// # Arguments
+    /// * `index` - The index of the frame.
+    /// * `err` - The cause.
+    pub fn frame(index: usize, err: DatasetError) -> Self {
+        match err {
+            DatasetError::Frame(..) => err,
+            err => DatasetError::Frame(index, Box::new(err)),
+        }
+    }
 }
 
 impl From<Error> for DatasetError {
@@ -24,6 +45,7 @@ impl std::error::Error for DatasetError {
             DatasetError::Io(err) => Some(err),
             DatasetError::Parser(_) => None,
             DatasetError::Image(err) => Some(err),
+            DatasetError::Frame(_, err) => Some(err.as_ref()),
         }
     }
 }
@@ -34,6 +56,7 @@ impl std::fmt::Display for DatasetError {
             DatasetError::Io(err) => write!(f, "IO error: {err}"),
             DatasetError::Parser(err) => write!(f, "Parser error: {err}"),
             DatasetError::Image(err) => write!(f, "Image error: {err}"),
+            DatasetError::Frame(index, err) => write!(f, "Frame {index} error: {err}"),
         }
     }
 }
@@ -44,6 +67,52 @@ impl From<ImageError> for DatasetError {
     }
 }
 
+/// Loads the color and depth images of a frame, checking that both have the same size.
+pub(super) fn load_rgbd_images<P: AsRef<Path>>(
+    rgb_filepath: P,
+    depth_filepath: P,
+) -> Result<(Array3<u8>, Array2<u16>), DatasetError> {
+    let rgb_image = image::open(rgb_filepath)?.into_rgb8().into_array3();
+    let rgb_image = rgb_image.as_standard_layout().into_owned();
+    let depth_image = image::open(depth_filepath)?.into_luma16().into_ndarray2();
+
+    let (height, width, _) = rgb_image.dim();
+    if depth_image.dim() != (height, width) {
+        return Err(DatasetError::Parser(format!(
+            "Depth image shape {:?} differs from the color image shape {:?}",
+            depth_image.dim(),
+            (height, width)
+        )));
+    }
+
+    Ok((rgb_image, depth_image))
+}
+
+/// Loads a sensor confidence map (e.g., RealSense stereo confidence or ToF amplitude image)
+/// to be used as [`crate::image::RgbdImage::confidence`].
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the 8 or 16 bits image.
+/// * `max_value` - Raw value that maps to full confidence, higher values are clamped.
+///
+/// # Returns
+///
+/// The confidence map in [0, 1].
+pub fn load_confidence<P: AsRef<Path>>(
+    filepath: P,
+    max_value: f32,
+) -> Result<Array2<f32>, DatasetError> {
+    let raw = match image::open(filepath)? {
+        DynamicImage::ImageLuma8(image) => image.into_ndarray2().map(|value| *value as f32),
+        image => image
+            .into_luma16()
+            .into_ndarray2()
+            .map(|value| *value as f32),
+    };
+    Ok(raw.map(|value| (value / max_value).min(1.0)))
+}
+
 pub trait RgbdDataset {
     fn len(&self) -> usize;
     fn is_empty(&self) -> bool;
@@ -75,7 +144,13 @@ impl RgbdDataset for SubsetDataset {
     }
 
     fn get(&self, index: usize) -> Result<RgbdFrame, DatasetError> {
-        self.dataset.get(self.indices[index])
+        self.dataset
+            .get(self.indices[index])
+            .map_err(|err| match err {
+                // The wrapped dataset reports its own index.
+                DatasetError::Frame(_, cause) => DatasetError::Frame(index, cause),
+                err => DatasetError::frame(index, err),
+            })
     }
 
     fn trajectory(&self) -> Option<Trajectory> {
@@ -91,3 +166,136 @@ impl RgbdDataset for SubsetDataset {
         self.dataset.camera(self.indices[index])
     }
 }
+
+/// What to do when a frame of a dataset cannot be loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameErrorPolicy {
+    /// Skips the frame, the error is recorded in [`DatasetIter::skipped`].
+    Skip,
+    /// Returns the error and stops the iteration.
+    Abort,
+}
+
+/// Iterates over the frames of a dataset, yielding `(index, frame)` pairs, and handles
+/// unreadable frames according to a [`FrameErrorPolicy`].
+pub struct DatasetIter<'a> {
+    dataset: &'a dyn RgbdDataset,
+    next: usize,
+    policy: FrameErrorPolicy,
+    skipped: Vec<DatasetError>,
+}
+
+impl<'a> DatasetIter<'a> {
+    /// Creates the iterator starting at the first frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `dataset` - The dataset.
+    /// * `policy` - Whether to skip unreadable frames or to abort.
+    pub fn new(dataset: &'a dyn RgbdDataset, policy: FrameErrorPolicy) -> Self {
+        Self {
+            dataset,
+            next: 0,
+            policy,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Starts the iteration at a given frame instead, e.g., to resume an interrupted run.
+    pub fn start_at(mut self, index: usize) -> Self {
+        self.next = index;
+        self
+    }
+
+    /// Errors of the frames skipped so far, they have the kind [`DatasetError::Frame`].
+    pub fn skipped(&self) -> &[DatasetError] {
+        &self.skipped
+    }
+}
+
+impl Iterator for DatasetIter<'_> {
+    type Item = Result<(usize, RgbdFrame), DatasetError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next < self.dataset.len() {
+            let index = self.next;
+            self.next += 1;
+
+            match self.dataset.get(index) {
+                Ok(frame) => return Some(Ok((index, frame))),
+                Err(err) => {
+                    let err = DatasetError::frame(index, err);
+                    match self.policy {
+                        FrameErrorPolicy::Skip => self.skipped.push(err),
+                        FrameErrorPolicy::Abort => {
+                            self.next = self.dataset.len();
+                            return Some(Err(err));
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+
+    use super::{
+        load_confidence, DatasetError, DatasetIter, FrameErrorPolicy, RgbdDataset, SubsetDataset,
+    };
+    use crate::io::dataset::SlamTbDataset;
+
+    #[test]
+    fn test_corrupt_frames() {
+        let dataset_dir = tempfile::tempdir().unwrap();
+        for entry in std::fs::read_dir("tests/data/rgbd/sample1").unwrap() {
+            let entry = entry.unwrap();
+            std::fs::copy(entry.path(), dataset_dir.path().join(entry.file_name())).unwrap();
+        }
+        // Truncated depth and missing color image.
+        let depth = std::fs::read(dataset_dir.path().join("frame_00001_depth.png")).unwrap();
+        std::fs::write(
+            dataset_dir.path().join("frame_00001_depth.png"),
+            &depth[..depth.len() / 2],
+        )
+        .unwrap();
+        std::fs::remove_file(dataset_dir.path().join("frame_00002_rgb.png")).unwrap();
+
+        let dataset = SlamTbDataset::load(dataset_dir.path().to_str().unwrap()).unwrap();
+
+        let mut iter = DatasetIter::new(&dataset, FrameErrorPolicy::Skip);
+        let indices = iter
+            .by_ref()
+            .map(|item| item.unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(indices.len(), dataset.len() - 2);
+        assert!(!indices.contains(&1) && !indices.contains(&2));
+        assert!(matches!(iter.skipped()[0], DatasetError::Frame(1, _)));
+        assert!(matches!(iter.skipped()[1], DatasetError::Frame(2, _)));
+
+        let mut iter = DatasetIter::new(&dataset, FrameErrorPolicy::Abort);
+        assert!(matches!(iter.next(), Some(Ok((0, _)))));
+        assert!(matches!(iter.next(), Some(Err(DatasetError::Frame(1, _)))));
+        assert!(iter.next().is_none());
+
+        let subset = SubsetDataset::new(Box::new(dataset), vec![0, 2]);
+        assert!(matches!(subset.get(1), Err(DatasetError::Frame(1, _))));
+    }
+
+    #[test]
+    fn test_load_confidence() {
+        let raw = Array2::from_shape_fn((4, 6), |(row, col)| (row * 6 + col) as u8 * 10);
+        image::GrayImage::from_raw(6, 4, raw.iter().cloned().collect())
+            .unwrap()
+            .save("tests/outputs/confidence.png")
+            .unwrap();
+
+        let confidence = load_confidence("tests/outputs/confidence.png", 100.0).unwrap();
+        assert_eq!(confidence.dim(), (4, 6));
+        assert_eq!(confidence[(0, 5)], 0.5);
+        assert_eq!(confidence[(3, 5)], 1.0);
+    }
+}
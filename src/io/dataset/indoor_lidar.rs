@@ -3,22 +3,23 @@ use std::{io::BufRead, path::Path};
 use glob::PatternError;
 use itertools::Itertools;
 use nalgebra::Matrix4;
-use nshare::ToNdarray2;
 
 use crate::{
     camera::CameraIntrinsics,
-    image::{IntoArray3, RgbdFrame, RgbdImage},
+    image::{RgbdFrame, RgbdImage},
+    metadata::Metadata,
     trajectory::Trajectory,
     transform::Transform,
 };
 
-use super::core::{DatasetError, RgbdDataset};
+use super::core::{load_rgbd_images, DatasetError, RgbdDataset};
 
 /// Parser for the IndoorLidar dataset. Available at:
 /// http://redwood-data.org/indoor_lidar_rgbd/index.html.
 /// Jaesik Park and Qian-Yi Zhou and Vladlen Koltun,
 /// Colored Point Cloud Registration Revisited. ICCV, 2017.
 pub struct IndoorLidarDataset {
+    base_dir: String,
     rgb_images: Vec<String>,
     depth_images: Vec<String>,
     trajectory: Trajectory,
@@ -70,6 +71,7 @@ impl IndoorLidarDataset {
             })
             .collect::<Trajectory>();
         Ok(IndoorLidarDataset {
+            base_dir: base_dir.to_string(),
             rgb_images,
             depth_images,
             trajectory,
@@ -87,18 +89,15 @@ impl RgbdDataset for IndoorLidarDataset {
     }
 
     fn get(&self, idx: usize) -> Result<RgbdFrame, DatasetError> {
-        let rgb_image = image::open(&self.rgb_images[idx])?
-            .into_rgb8()
-            .into_array3();
-
-        let depth_image = image::open(&self.depth_images[idx])?
-            .into_luma16()
-            .into_ndarray2();
+        let (rgb_image, depth_image) =
+            load_rgbd_images(&self.rgb_images[idx], &self.depth_images[idx])
+                .map_err(|err| DatasetError::frame(idx, err))?;
         let rgbd_image = RgbdImage::with_depth_scale(rgb_image, depth_image, 0.001);
 
         let (camera, transform) = self.camera(idx);
 
-        Ok(RgbdFrame::new(camera, rgbd_image, transform))
+        let metadata = Metadata::from_frame(format!("indoor_lidar:{}", self.base_dir), idx, None);
+        Ok(RgbdFrame::new(camera, rgbd_image, transform).with_metadata(metadata))
     }
 
     fn trajectory(&self) -> Option<Trajectory> {
@@ -1,12 +1,26 @@
 mod core;
-pub use self::core::{DatasetError, RgbdDataset, SubsetDataset};
+pub use self::core::{
+    load_confidence, DatasetError, DatasetIter, FrameErrorPolicy, RgbdDataset, SubsetDataset,
+};
 
 mod indoor_lidar;
 pub use indoor_lidar::IndoorLidarDataset;
 
+mod noisy;
+pub use noisy::{DepthNoiseParams, NoisyDataset};
+
+mod rectified;
+pub use rectified::RectifiedDataset;
+
+mod registry;
+pub use registry::{DatasetLoader, DatasetRegistry};
+
 mod slamtb;
 #[doc(hidden)]
 pub use slamtb::SlamTbDataset;
 
+mod stream;
+pub use stream::{RgbdStreamWriter, StreamingRgbdSource};
+
 mod tum;
 pub use tum::TumRgbdDataset;
@@ -0,0 +1,250 @@
+use ndarray::Array2;
+use ndarray_rand::rand_distr::{Distribution, StandardNormal};
+use rand::{rngs::StdRng, Rng};
+
+use super::core::{DatasetError, RgbdDataset};
+use crate::{
+    camera::CameraIntrinsics, error::A3dError, image::RgbdFrame, random::RandomState,
+    trajectory::Trajectory, transform::Transform,
+};
+
+/// Parameters of the depth noise model. The defaults follow a Kinect v1 sensor, with the
+/// axial noise model from Nguyen et al., Modeling Kinect Sensor Noise for Improved 3D
+/// Reconstruction and Tracking, 3DIMPVT 2012.
+#[derive(Debug, Clone, Copy)]
+pub struct DepthNoiseParams {
+    /// Multiplier of the axial (along the ray) noise standard deviation,
+    /// `0.0012 + 0.0019 * (z - 0.4)^2` meters. Zero disables it.
+    pub axial_scale: f32,
+    /// Standard deviation of the lateral noise in pixels. Zero disables it.
+    pub lateral_sigma: f32,
+    /// Disparity quantization step in pixels. Zero disables it.
+    pub disparity_step: f32,
+    /// Baseline of the stereo/structured light pair in meters, used for the quantization.
+    pub baseline: f32,
+    /// Probability of a valid depth pixel being dropped.
+    pub dropout: f32,
+}
+
+impl DepthNoiseParams {
+    /// Checks that the standard deviations, steps and the baseline are finite and
+    /// non-negative, and that the dropout is a probability.
+    pub fn validate(&self) -> Result<(), A3dError> {
+        for (name, value) in [
+            ("axial_scale", self.axial_scale),
+            ("lateral_sigma", self.lateral_sigma),
+            ("disparity_step", self.disparity_step),
+            ("baseline", self.baseline),
+        ] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(A3dError::invalid_parameter(format!(
+                    "{name} must be finite and non-negative, got {value}"
+                )));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.dropout) {
+            return Err(A3dError::invalid_parameter(format!(
+                "dropout must be in [0, 1], got {}",
+                self.dropout
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for DepthNoiseParams {
+    fn default() -> Self {
+        Self {
+            axial_scale: 1.0,
+            lateral_sigma: 0.5,
+            disparity_step: 0.125,
+            baseline: 0.075,
+            dropout: 0.01,
+        }
+    }
+}
+
+/// Dataset wrapper that adds synthetic sensor noise to the depth images of another dataset.
+/// The noise of a frame depends only on the random state and on the frame index, so repeated reads
+/// of a frame return the same image.
+pub struct NoisyDataset {
+    dataset: Box<dyn RgbdDataset>,
+    params: DepthNoiseParams,
+    random_state: RandomState,
+}
+
+impl NoisyDataset {
+    /// Wraps a dataset.
+    ///
+    /// # Arguments
+    ///
+    /// * `dataset` - The dataset with the clean frames.
+    /// * `params` - The noise model parameters.
+    /// * `random_state` - Random state of the noise.
+    ///
+    /// # Returns
+    ///
+    /// The dataset, or an error if the parameters are invalid, see
+    /// [`DepthNoiseParams::validate`].
+    pub fn new(
+        dataset: Box<dyn RgbdDataset>,
+        params: DepthNoiseParams,
+        random_state: RandomState,
+    ) -> Result<Self, A3dError> {
+        params.validate()?;
+        Ok(Self {
+            dataset,
+            params,
+            random_state,
+        })
+    }
+
+    fn add_noise(
+        &self,
+        depth: &Array2<u16>,
+        depth_scale: f32,
+        camera: &CameraIntrinsics,
+        rng: &mut StdRng,
+    ) -> Array2<u16> {
+        let params = &self.params;
+        let (height, width) = depth.dim();
+        let focal_baseline = camera.fx as f32 * params.baseline;
+
+        Array2::from_shape_fn((height, width), |(row, col)| {
+            let (row, col) = if params.lateral_sigma > 0.0 {
+                (
+                    (row as f32 + params.lateral_sigma * sample_normal(rng))
+                        .round()
+                        .clamp(0.0, (height - 1) as f32) as usize,
+                    (col as f32 + params.lateral_sigma * sample_normal(rng))
+                        .round()
+                        .clamp(0.0, (width - 1) as f32) as usize,
+                )
+            } else {
+                (row, col)
+            };
+
+            let raw_z = depth[(row, col)];
+            if raw_z == 0 || rng.gen::<f32>() < params.dropout {
+                return 0;
+            }
+
+            let mut z = raw_z as f32 * depth_scale;
+            if params.axial_scale > 0.0 {
+                let sigma = params.axial_scale * (0.0012 + 0.0019 * (z - 0.4).powi(2));
+                z += sigma * sample_normal(rng);
+            }
+            if params.disparity_step > 0.0 && z > 0.0 {
+                let disparity = focal_baseline / z;
+                let disparity = (disparity / params.disparity_step).round() * params.disparity_step;
+                z = if disparity > 0.0 {
+                    focal_baseline / disparity
+                } else {
+                    0.0
+                };
+            }
+
+            (z / depth_scale).round().clamp(0.0, u16::MAX as f32) as u16
+        })
+    }
+}
+
+fn sample_normal(rng: &mut StdRng) -> f32 {
+    StandardNormal.sample(rng)
+}
+
+impl RgbdDataset for NoisyDataset {
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.dataset.is_empty()
+    }
+
+    fn get(&self, index: usize) -> Result<RgbdFrame, DatasetError> {
+        let mut frame = self.dataset.get(index)?;
+        let depth_scale = frame.image.depth_scale.ok_or_else(|| {
+            DatasetError::frame(
+                index,
+                DatasetError::Parser("The noise model requires the depth scale".to_string()),
+            )
+        })? as f32;
+
+        let mut rng = self.random_state.stream(index as u64);
+        frame.image.depth =
+            self.add_noise(&frame.image.depth, depth_scale, &frame.camera, &mut rng);
+        Ok(frame)
+    }
+
+    fn trajectory(&self) -> Option<Trajectory> {
+        self.dataset.trajectory()
+    }
+
+    fn camera(&self, index: usize) -> (CameraIntrinsics, Option<Transform>) {
+        self.dataset.camera(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DepthNoiseParams, NoisyDataset};
+    use crate::{
+        io::dataset::{RgbdDataset, SlamTbDataset},
+        random::RandomState,
+    };
+
+    #[test]
+    fn test_noisy_dataset() {
+        let clean = SlamTbDataset::load("tests/data/rgbd/sample1").unwrap();
+        let noisy = NoisyDataset::new(
+            Box::new(SlamTbDataset::load("tests/data/rgbd/sample1").unwrap()),
+            DepthNoiseParams {
+                dropout: 0.1,
+                ..Default::default()
+            },
+            RandomState::new(7),
+        )
+        .unwrap();
+
+        let clean_depth = clean.get(0).unwrap().image.depth;
+        let noisy_depth = noisy.get(0).unwrap().image.depth;
+        assert_eq!(noisy_depth, noisy.get(0).unwrap().image.depth);
+        assert_ne!(noisy_depth, clean_depth);
+
+        let valid = clean_depth.iter().filter(|z| **z > 0).count() as f32;
+        let noisy_valid = noisy_depth.iter().filter(|z| **z > 0).count() as f32;
+        assert!((0.85..0.95).contains(&(noisy_valid / valid)));
+
+        let (error_sum, count) = clean_depth
+            .iter()
+            .zip(noisy_depth.iter())
+            .filter(|(clean, noisy)| **clean > 0 && **noisy > 0)
+            .fold((0.0, 0), |(sum, count), (clean, noisy)| {
+                (sum + (*clean as f32 - *noisy as f32).abs(), count + 1)
+            });
+        // Mean error below 5cm, the depth is in millimeters.
+        assert!(error_sum / (count as f32) < 50.0);
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        for params in [
+            DepthNoiseParams {
+                lateral_sigma: -1.0,
+                ..Default::default()
+            },
+            DepthNoiseParams {
+                axial_scale: f32::NAN,
+                ..Default::default()
+            },
+            DepthNoiseParams {
+                dropout: 1.5,
+                ..Default::default()
+            },
+        ] {
+            let dataset = SlamTbDataset::load("tests/data/rgbd/sample1").unwrap();
+            assert!(NoisyDataset::new(Box::new(dataset), params, RandomState::new(7)).is_err());
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use ndarray::{Array2, Array3};
+
+use super::core::{DatasetError, RgbdDataset};
+use crate::{
+    camera::{CameraIntrinsics, Distortion},
+    image::RgbdFrame,
+    trajectory::Trajectory,
+    transform::Transform,
+};
+
+/// Dataset wrapper that corrects the calibration of another dataset, for datasets that
+/// ship with wrong or missing calibration files. The intrinsics can be replaced, and the
+/// images rectified with lens distortion coefficients while loading.
+pub struct RectifiedDataset {
+    dataset: Box<dyn RgbdDataset + Send>,
+    intrinsics: Option<CameraIntrinsics>,
+    distortion: Option<Distortion>,
+}
+
+impl RectifiedDataset {
+    /// Wraps a dataset.
+    ///
+    /// # Arguments
+    ///
+    /// * `dataset` - The dataset.
+    /// * `intrinsics` - Replaces the intrinsics of all frames, if given.
+    /// * `distortion` - Rectifies the images with these coefficients, if given. The
+    ///   rectified images keep the same intrinsics.
+    pub fn new(
+        dataset: Box<dyn RgbdDataset + Send>,
+        intrinsics: Option<CameraIntrinsics>,
+        distortion: Option<Distortion>,
+    ) -> Self {
+        Self {
+            dataset,
+            intrinsics,
+            distortion,
+        }
+    }
+}
+
+/// Position of each rectified pixel in the distorted image.
+fn rectification_map(
+    camera: &CameraIntrinsics,
+    distortion: &Distortion,
+    height: usize,
+    width: usize,
+) -> Array2<(f32, f32)> {
+    Array2::from_shape_fn((height, width), |(row, col)| {
+        let x = (col as f64 - camera.cx) / camera.fx;
+        let y = (row as f64 - camera.cy) / camera.fy;
+        let (x, y) = distortion.distort(x, y);
+        (
+            (x * camera.fx + camera.cx) as f32,
+            (y * camera.fy + camera.cy) as f32,
+        )
+    })
+}
+
+/// Samples an image at the map positions with nearest neighbor interpolation, which
+/// doesn't blend depth values across object boundaries. Pixels outside are set to the
+/// default value.
+fn remap_nearest<T: Copy + Default>(image: &Array2<T>, map: &Array2<(f32, f32)>) -> Array2<T> {
+    let (height, width) = image.dim();
+    map.map(|(u, v)| {
+        let (col, row) = ((u + 0.5).floor(), (v + 0.5).floor());
+        if col < 0.0 || row < 0.0 || col >= width as f32 || row >= height as f32 {
+            T::default()
+        } else {
+            image[(row as usize, col as usize)]
+        }
+    })
+}
+
+fn remap_bilinear(image: &Array3<u8>, map: &Array2<(f32, f32)>) -> Array3<u8> {
+    let (height, width, channels) = image.dim();
+    Array3::from_shape_fn(
+        (map.nrows(), map.ncols(), channels),
+        |(row, col, channel)| {
+            let (u, v) = map[(row, col)];
+            if u < 0.0 || v < 0.0 || u > (width - 1) as f32 || v > (height - 1) as f32 {
+                return 0;
+            }
+            let (u0, v0) = (u.floor() as usize, v.floor() as usize);
+            let (u1, v1) = ((u0 + 1).min(width - 1), (v0 + 1).min(height - 1));
+            let (du, dv) = (u - u0 as f32, v - v0 as f32);
+            let value = |r: usize, c: usize| image[(r, c, channel)] as f32;
+            let top = value(v0, u0) * (1.0 - du) + value(v0, u1) * du;
+            let bottom = value(v1, u0) * (1.0 - du) + value(v1, u1) * du;
+            (top * (1.0 - dv) + bottom * dv).round() as u8
+        },
+    )
+}
+
+impl RgbdDataset for RectifiedDataset {
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.dataset.is_empty()
+    }
+
+    fn get(&self, index: usize) -> Result<RgbdFrame, DatasetError> {
+        let mut frame = self.dataset.get(index)?;
+        if let Some(intrinsics) = &self.intrinsics {
+            frame.camera = intrinsics.clone();
+        }
+
+        if let Some(distortion) = &self.distortion {
+            let (height, width) = frame.image.depth.dim();
+            let map = rectification_map(&frame.camera, distortion, height, width);
+            frame.image.color = remap_bilinear(&frame.image.color, &map);
+            frame.image.depth = remap_nearest(&frame.image.depth, &map);
+            frame.image.confidence = frame
+                .image
+                .confidence
+                .as_ref()
+                .map(|confidence| remap_nearest(confidence, &map));
+            frame.image.labels = frame
+                .image
+                .labels
+                .as_ref()
+                .map(|labels| remap_nearest(labels, &map));
+        }
+        Ok(frame)
+    }
+
+    fn trajectory(&self) -> Option<Trajectory> {
+        self.dataset.trajectory()
+    }
+
+    fn camera(&self, index: usize) -> (CameraIntrinsics, Option<Transform>) {
+        let (camera, camera_to_world) = self.dataset.camera(index);
+        (self.intrinsics.clone().unwrap_or(camera), camera_to_world)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RectifiedDataset;
+    use crate::{
+        camera::{CameraIntrinsics, Distortion},
+        io::dataset::{RgbdDataset, SlamTbDataset},
+    };
+
+    #[test]
+    fn test_rectified_dataset() {
+        let load = || Box::new(SlamTbDataset::load("tests/data/rgbd/sample1").unwrap());
+        let original = load().get(0).unwrap();
+        let (width, height) = (original.camera.width, original.camera.height);
+
+        let intrinsics = CameraIntrinsics::from_simple_intrinsic(
+            500.0,
+            500.0,
+            width as f64 * 0.5,
+            height as f64 * 0.5,
+            width,
+            height,
+        );
+        let overridden = RectifiedDataset::new(load(), Some(intrinsics.clone()), None);
+        assert_eq!(overridden.camera(0).0.fx, 500.0);
+        let frame = overridden.get(0).unwrap();
+        assert_eq!(frame.camera.fx, 500.0);
+        assert_eq!(frame.image.depth, original.image.depth);
+
+        let rectified = RectifiedDataset::new(
+            load(),
+            Some(intrinsics),
+            Some(Distortion::new([-0.2, 0.05, 0.0, 0.0, 0.0])),
+        );
+        let frame = rectified.get(0).unwrap();
+        assert_eq!(frame.image.depth.dim(), original.image.depth.dim());
+        assert_eq!(frame.image.color.dim(), original.image.color.dim());
+        // The principal point is not moved by the distortion.
+        let center = (height / 2, width / 2);
+        assert_eq!(frame.image.depth[center], original.image.depth[center]);
+        assert_ne!(frame.image.depth, original.image.depth);
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    collections::BTreeMap,
+    sync::{OnceLock, RwLock},
+};
+
+use super::{DatasetError, IndoorLidarDataset, RgbdDataset, SlamTbDataset, TumRgbdDataset};
+
+/// Opens a dataset from its path.
+pub type DatasetLoader =
+    Box<dyn Fn(&str) -> Result<Box<dyn RgbdDataset + Send>, DatasetError> + Send + Sync>;
+
+/// Dataset formats by name, e.g., to select the format from the command line.
+pub struct DatasetRegistry {
+    loaders: BTreeMap<String, DatasetLoader>,
+}
+
+impl Default for DatasetRegistry {
+    /// Registry with the formats of this crate: `ilrgbd`, `tum` and `slamtb`.
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register("ilrgbd", |path| {
+            Ok(Box::new(IndoorLidarDataset::load(path)?) as Box<dyn RgbdDataset + Send>)
+        });
+        registry.register("tum", |path| {
+            Ok(Box::new(TumRgbdDataset::load(path)?) as Box<dyn RgbdDataset + Send>)
+        });
+        registry.register("slamtb", |path| {
+            Ok(Box::new(SlamTbDataset::load(path)?) as Box<dyn RgbdDataset + Send>)
+        });
+        registry
+    }
+}
+
+impl DatasetRegistry {
+    /// Registry without formats.
+    pub fn empty() -> Self {
+        Self {
+            loaders: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a format, replacing any other with the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The format name.
+    /// * `loader` - Opens a dataset of the format from its path.
+    pub fn register<F>(&mut self, name: &str, loader: F)
+    where
+        F: Fn(&str) -> Result<Box<dyn RgbdDataset + Send>, DatasetError> + Send + Sync + 'static,
+    {
+        self.loaders.insert(name.to_string(), Box::new(loader));
+    }
+
+    /// Names of the registered formats, sorted.
+    pub fn formats(&self) -> Vec<String> {
+        self.loaders.keys().cloned().collect()
+    }
+
+    /// Opens a dataset.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format name.
+    /// * `path` - Path to the dataset.
+    ///
+    /// # Returns
+    ///
+    /// The dataset, or error if the format is unknown or the loader fails.
+    pub fn load(
+        &self,
+        format: &str,
+        path: &str,
+    ) -> Result<Box<dyn RgbdDataset + Send>, DatasetError> {
+        let loader = self.loaders.get(format).ok_or_else(|| {
+            DatasetError::Parser(format!(
+                "Unknown dataset format {format}, expected one of: {}",
+                self.formats().join(", ")
+            ))
+        })?;
+        loader(path)
+    }
+
+    /// The process wide registry, used by the command line tools. Downstream crates may
+    /// register their own formats into it before running the tools' code.
+    pub fn global() -> &'static RwLock<DatasetRegistry> {
+        static REGISTRY: OnceLock<RwLock<DatasetRegistry>> = OnceLock::new();
+        REGISTRY.get_or_init(|| RwLock::new(DatasetRegistry::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DatasetRegistry;
+    use crate::io::dataset::{DatasetError, RgbdDataset, SubsetDataset};
+
+    #[test]
+    fn test_registry() {
+        let mut registry = DatasetRegistry::default();
+        assert_eq!(registry.formats(), vec!["ilrgbd", "slamtb", "tum"]);
+
+        registry.register("first-frames", |path| {
+            let dataset = DatasetRegistry::default().load("slamtb", path)?;
+            Ok(Box::new(SubsetDataset::new(dataset, vec![0, 1])) as Box<dyn RgbdDataset + Send>)
+        });
+        let dataset = registry
+            .load("first-frames", "tests/data/rgbd/sample1")
+            .unwrap();
+        assert_eq!(dataset.len(), 2);
+
+        assert!(matches!(
+            registry.load("unknown", "tests/data/rgbd/sample1"),
+            Err(DatasetError::Parser(_))
+        ));
+    }
+}
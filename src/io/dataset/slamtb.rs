@@ -1,21 +1,22 @@
 use std::path::{Path, PathBuf};
 
-use super::core::{DatasetError, RgbdDataset};
+use super::core::{load_rgbd_images, DatasetError, RgbdDataset};
 use crate::{
     camera::CameraIntrinsics,
-    image::{IntoArray3, RgbdFrame, RgbdImage},
+    image::{RgbdFrame, RgbdImage},
+    metadata::{Exposure, Metadata},
     trajectory::Trajectory,
     transform::Transform,
 };
 
-use nshare::ToNdarray2;
-
 pub struct SlamTbDataset {
     cameras: Vec<CameraIntrinsics>,
     extrinsic_cameras: Vec<Transform>,
     rgb_images: Vec<String>,
     depth_images: Vec<String>,
     depth_scales: Vec<f64>,
+    timestamps: Vec<f64>,
+    exposures: Vec<Option<Exposure>>,
     base_dir: PathBuf,
 }
 
@@ -42,6 +43,11 @@ mod json {
         pub depth_max: f64,
         pub rt_cam: RTCam,
         pub timestamp: f64,
+        /// Exposure time in seconds, written by recorders that lock the exposure.
+        #[serde(default)]
+        pub exposure_time: Option<f64>,
+        #[serde(default)]
+        pub gain: Option<f64>,
     }
 
     #[derive(Deserialize, Debug)]
@@ -69,6 +75,8 @@ impl SlamTbDataset {
                 let mut rgb_images = Vec::new();
                 let mut depth_images = Vec::new();
                 let mut depth_scales = Vec::new();
+                let mut timestamps = Vec::new();
+                let mut exposures = Vec::new();
 
                 for frame in doc.root.iter() {
                     let info = &frame.info;
@@ -98,6 +106,11 @@ impl SlamTbDataset {
                     rgb_images.push(frame.rgb_image.clone());
                     depth_images.push(frame.depth_image.clone());
                     depth_scales.push(info.depth_scale);
+                    timestamps.push(info.timestamp);
+                    exposures.push(info.exposure_time.map(|exposure_time| Exposure {
+                        gain: info.gain.unwrap_or(1.0),
+                        ..Exposure::new(exposure_time)
+                    }));
                 }
                 Self {
                     cameras,
@@ -105,6 +118,8 @@ impl SlamTbDataset {
                     rgb_images,
                     depth_images,
                     depth_scales,
+                    timestamps,
+                    exposures,
                     base_dir: PathBuf::from(base_dir),
                 }
             })
@@ -122,19 +137,24 @@ impl RgbdDataset for SlamTbDataset {
     }
 
     fn get(&self, index: usize) -> Result<RgbdFrame, DatasetError> {
-        let rgb_image = image::open(self.base_dir.join(&self.rgb_images[index]))?
-            .into_rgb8()
-            .into_array3();
-
-        let rgb_image = rgb_image.as_standard_layout().into_owned();
-        let depth_image = image::open(self.base_dir.join(&self.depth_images[index]))?
-            .into_luma16()
-            .into_ndarray2();
+        let (rgb_image, depth_image) = load_rgbd_images(
+            self.base_dir.join(&self.rgb_images[index]),
+            self.base_dir.join(&self.depth_images[index]),
+        )
+        .map_err(|err| DatasetError::frame(index, err))?;
         Ok(RgbdFrame::new(
             self.cameras[index].clone(),
             RgbdImage::with_depth_scale(rgb_image, depth_image, self.depth_scales[index]),
             Some(self.extrinsic_cameras[index].clone()),
-        ))
+        )
+        .with_metadata(Metadata {
+            exposure: self.exposures[index],
+            ..Metadata::from_frame(
+                format!("slamtb:{}", self.base_dir.display()),
+                index,
+                Some(self.timestamps[index]),
+            )
+        }))
     }
 
     fn trajectory(&self) -> Option<Trajectory> {
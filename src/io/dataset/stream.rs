@@ -0,0 +1,300 @@
+//! Streaming of RGB-D frames over a byte stream, e.g., a TCP connection between a capture
+//! machine and the host running the odometry.
+//!
+//! The wire protocol is little-endian. The stream starts with a header:
+//!
+//! | Field            | Type       |
+//! |------------------|------------|
+//! | magic `A3DS`     | `[u8; 4]`  |
+//! | version          | `u16`      |
+//! | fx, fy, cx, cy   | `f64` x 4  |
+//! | width, height    | `u32` x 2  |
+//! | depth scale      | `f64`      |
+//!
+//! Followed by messages, each starting with a `u8` tag. Tag 1 is a frame, with a `f64`
+//! timestamp, the depth image (`width * height` `u16`) and the color image
+//! (`width * height * 3` `u8`, RGB, row-major). Tag 0 ends the stream.
+
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use ndarray::{Array2, Array3};
+
+use super::core::DatasetError;
+use crate::{
+    camera::CameraIntrinsics,
+    image::{RgbdFrame, RgbdImage},
+    metadata::Metadata,
+};
+
+const MAGIC: &[u8; 4] = b"A3DS";
+const VERSION: u16 = 1;
+const TAG_END: u8 = 0;
+const TAG_FRAME: u8 = 1;
+/// Largest accepted image size, in pixels (8K resolution), so a corrupted header can't make
+/// the reader allocate arbitrarily large frames.
+const MAX_PIXELS: usize = 7680 * 4320;
+
+/// Sends RGB-D frames into a byte stream. Use it on the capture side.
+pub struct RgbdStreamWriter<W: Write> {
+    writer: BufWriter<W>,
+    camera: CameraIntrinsics,
+}
+
+impl RgbdStreamWriter<TcpStream> {
+    /// Connects to a [`StreamingRgbdSource`] listening at the given address.
+    pub fn connect<A: ToSocketAddrs>(
+        address: A,
+        camera: CameraIntrinsics,
+        depth_scale: f64,
+    ) -> Result<Self, DatasetError> {
+        Self::new(TcpStream::connect(address)?, camera, depth_scale)
+    }
+}
+
+impl<W: Write> RgbdStreamWriter<W> {
+    /// Creates the writer and sends the stream header.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The output stream.
+    /// * `camera` - The camera intrinsics, all frames must have its size.
+    /// * `depth_scale` - The scale that converts depth values into meters.
+    pub fn new(
+        writer: W,
+        camera: CameraIntrinsics,
+        depth_scale: f64,
+    ) -> Result<Self, DatasetError> {
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        for value in [camera.fx, camera.fy, camera.cx, camera.cy] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&(camera.width as u32).to_le_bytes())?;
+        writer.write_all(&(camera.height as u32).to_le_bytes())?;
+        writer.write_all(&depth_scale.to_le_bytes())?;
+        writer.flush()?;
+
+        Ok(Self { writer, camera })
+    }
+
+    /// Sends a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The RGB-D image, with the size of the camera.
+    /// * `timestamp` - The capture time in seconds.
+    pub fn send(&mut self, image: &RgbdImage, timestamp: f64) -> Result<(), DatasetError> {
+        if image.depth.dim() != (self.camera.height, self.camera.width)
+            || image.color.dim() != (self.camera.height, self.camera.width, 3)
+        {
+            return Err(DatasetError::Parser(format!(
+                "Frame size {:?} differs from the stream size {:?}",
+                image.color.dim(),
+                (self.camera.height, self.camera.width, 3)
+            )));
+        }
+
+        self.writer.write_all(&[TAG_FRAME])?;
+        self.writer.write_all(&timestamp.to_le_bytes())?;
+        for z in image.depth.iter() {
+            self.writer.write_all(&z.to_le_bytes())?;
+        }
+        for value in image.color.iter() {
+            self.writer.write_all(&[*value])?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Ends the stream.
+    pub fn finish(mut self) -> Result<(), DatasetError> {
+        self.writer.write_all(&[TAG_END])?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Receives RGB-D frames sent by a [`RgbdStreamWriter`]. Iterate over it to get the frames
+/// as they arrive, the iteration ends with the stream.
+pub struct StreamingRgbdSource<R: Read> {
+    reader: BufReader<R>,
+    camera: CameraIntrinsics,
+    depth_scale: f64,
+    frame_count: usize,
+    finished: bool,
+}
+
+impl StreamingRgbdSource<TcpStream> {
+    /// Waits for one capture machine to connect at the given address.
+    pub fn listen<A: ToSocketAddrs>(address: A) -> Result<Self, DatasetError> {
+        let listener = std::net::TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+        Self::new(stream)
+    }
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], DatasetError> {
+    let mut buffer = [0u8; N];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+impl<R: Read> StreamingRgbdSource<R> {
+    /// Creates the source by reading the stream header.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The input stream.
+    pub fn new(reader: R) -> Result<Self, DatasetError> {
+        let mut reader = BufReader::new(reader);
+        if &read_array::<_, 4>(&mut reader)? != MAGIC {
+            return Err(DatasetError::Parser("Invalid stream header".to_string()));
+        }
+        let version = u16::from_le_bytes(read_array(&mut reader)?);
+        if version != VERSION {
+            return Err(DatasetError::Parser(format!(
+                "Unsupported stream version {version}"
+            )));
+        }
+
+        let mut intrinsics = [0.0; 4];
+        for value in intrinsics.iter_mut() {
+            *value = f64::from_le_bytes(read_array(&mut reader)?);
+        }
+        let width = u32::from_le_bytes(read_array(&mut reader)?) as usize;
+        let height = u32::from_le_bytes(read_array(&mut reader)?) as usize;
+        let depth_scale = f64::from_le_bytes(read_array(&mut reader)?);
+        match width.checked_mul(height) {
+            Some(pixels) if pixels > 0 && pixels <= MAX_PIXELS => {}
+            _ => {
+                return Err(DatasetError::Parser(format!(
+                    "Invalid stream image size {width}x{height}"
+                )))
+            }
+        }
+
+        let [fx, fy, cx, cy] = intrinsics;
+        Ok(Self {
+            reader,
+            camera: CameraIntrinsics::from_simple_intrinsic(fx, fy, cx, cy, width, height),
+            depth_scale,
+            frame_count: 0,
+            finished: false,
+        })
+    }
+
+    /// The camera intrinsics of the stream.
+    pub fn camera(&self) -> &CameraIntrinsics {
+        &self.camera
+    }
+
+    fn read_frame(&mut self) -> Result<Option<RgbdFrame>, DatasetError> {
+        let [tag] = read_array(&mut self.reader)?;
+        match tag {
+            TAG_END => return Ok(None),
+            TAG_FRAME => {}
+            tag => {
+                return Err(DatasetError::Parser(format!(
+                    "Unknown stream message {tag}"
+                )))
+            }
+        }
+
+        // The size was validated against `MAX_PIXELS` when reading the header.
+        let (width, height) = (self.camera.width, self.camera.height);
+        let timestamp = f64::from_le_bytes(read_array(&mut self.reader)?);
+
+        let mut depth_bytes = vec![0u8; width * height * 2];
+        self.reader.read_exact(&mut depth_bytes)?;
+        let depth = depth_bytes
+            .chunks_exact(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect::<Vec<_>>();
+
+        let mut color = vec![0u8; width * height * 3];
+        self.reader.read_exact(&mut color)?;
+
+        let image = RgbdImage::with_depth_scale(
+            Array3::from_shape_vec((height, width, 3), color).unwrap(),
+            Array2::from_shape_vec((height, width), depth).unwrap(),
+            self.depth_scale,
+        );
+        let frame = RgbdFrame::new(self.camera.clone(), image, None).with_metadata(
+            Metadata::from_frame("stream", self.frame_count, Some(timestamp)),
+        );
+        self.frame_count += 1;
+        Ok(Some(frame))
+    }
+}
+
+impl<R: Read> Iterator for StreamingRgbdSource<R> {
+    type Item = Result<RgbdFrame, DatasetError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let frame = self.read_frame().transpose();
+        self.finished = !matches!(frame, Some(Ok(_)));
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::{RgbdStreamWriter, StreamingRgbdSource, MAGIC, VERSION};
+    use crate::io::dataset::{RgbdDataset, SlamTbDataset};
+
+    #[test]
+    fn test_tcp_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let sender = std::thread::spawn(move || {
+            let dataset = SlamTbDataset::load("tests/data/rgbd/sample1").unwrap();
+            let (camera, _) = dataset.camera(0);
+            let mut writer = RgbdStreamWriter::connect(address, camera, 0.001).unwrap();
+            for i in 0..2 {
+                writer
+                    .send(&dataset.get(i).unwrap().image, i as f64 * 0.5)
+                    .unwrap();
+            }
+            writer.finish().unwrap();
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let source = StreamingRgbdSource::new(stream).unwrap();
+        let frames = source.collect::<Result<Vec<_>, _>>().unwrap();
+        sender.join().unwrap();
+
+        let dataset = SlamTbDataset::load("tests/data/rgbd/sample1").unwrap();
+        assert_eq!(frames.len(), 2);
+        for (i, frame) in frames.iter().enumerate() {
+            let expected = dataset.get(i).unwrap();
+            assert_eq!(frame.image.depth, expected.image.depth);
+            assert_eq!(frame.image.color, expected.image.color);
+            assert_eq!(frame.camera.fx, expected.camera.fx);
+            assert_eq!(frame.metadata.timestamp, Some(i as f64 * 0.5));
+        }
+    }
+
+    #[test]
+    fn test_invalid_image_size() {
+        for (width, height) in [(0u32, 480u32), (u32::MAX, u32::MAX)] {
+            let mut header = MAGIC.to_vec();
+            header.extend_from_slice(&VERSION.to_le_bytes());
+            for value in [525.0f64, 525.0, 319.5, 239.5] {
+                header.extend_from_slice(&value.to_le_bytes());
+            }
+            header.extend_from_slice(&width.to_le_bytes());
+            header.extend_from_slice(&height.to_le_bytes());
+            header.extend_from_slice(&0.001f64.to_le_bytes());
+
+            assert!(StreamingRgbdSource::new(header.as_slice()).is_err());
+        }
+    }
+}
@@ -1,41 +1,47 @@
 use std::{io::BufRead, path::PathBuf};
 
 use nalgebra::{Quaternion, Vector3};
-use nshare::ToNdarray2;
 
 use crate::{
     camera::CameraIntrinsics,
-    image::{IntoArray3, RgbdFrame, RgbdImage},
+    image::{RgbdFrame, RgbdImage},
+    metadata::Metadata,
     trajectory::Trajectory,
     transform::Transform,
 };
 
-use super::{DatasetError, RgbdDataset};
+use super::{core::load_rgbd_images, DatasetError, RgbdDataset};
 
 pub struct TumRgbdDataset {
     base_dir: PathBuf,
     rgb_images: Vec<String>,
     depth_images: Vec<String>,
+    timestamps: Vec<f64>,
     trajectory: Trajectory,
 }
 
 fn read_file_list(filepath: &PathBuf) -> Result<Vec<(f64, String)>, DatasetError> {
     let file = std::fs::File::open(filepath)?;
     let reader = std::io::BufReader::new(file);
-    let file_list: Vec<(f64, String)> = reader
+    reader
         .lines()
         .map_while(Result::ok)
-        .filter(|line| !line.trim().starts_with('#'))
+        .filter(|line| !line.trim().is_empty() && !line.trim().starts_with('#'))
         .map(|line| {
             let tokens: Vec<&str> = line.split(&[',', '\t', ' ']).collect();
-            (
-                tokens[0].trim().parse::<f64>().unwrap(),
-                tokens[1].trim().to_string(),
-            )
+            match tokens[..] {
+                [time, filename, ..] => time
+                    .trim()
+                    .parse::<f64>()
+                    .map(|time| (time, filename.trim().to_string()))
+                    .ok(),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                DatasetError::Parser(format!("{}: invalid line `{line}`", filepath.display()))
+            })
         })
-        .collect();
-
-    Ok(file_list)
+        .collect()
 }
 
 fn associate<T1: Clone, T2: Clone>(
@@ -70,16 +76,21 @@ fn associate<T1: Clone, T2: Clone>(
 fn load_trajectory(filepath: &str) -> Result<Vec<(f64, Transform)>, DatasetError> {
     let file = std::fs::File::open(filepath)?;
     let reader = std::io::BufReader::new(file);
-    let trajectory = reader
+    reader
         .lines()
         .map_while(Result::ok)
-        .filter(|line| !line.trim().starts_with('#'))
+        .filter(|line| !line.trim().is_empty() && !line.trim().starts_with('#'))
         .map(|line| {
-            let tokens: Vec<f64> = line
+            let tokens = line
                 .split_whitespace()
-                .map(|token| token.trim().parse::<f64>().unwrap())
-                .collect();
-            (
+                .map(|token| token.trim().parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .ok()
+                .filter(|tokens| tokens.len() == 8)
+                .ok_or_else(|| {
+                    DatasetError::Parser(format!("{filepath}: invalid pose `{line}`"))
+                })?;
+            Ok((
                 tokens[0],
                 Transform::new(
                     &Vector3::new(tokens[1] as f32, tokens[2] as f32, tokens[3] as f32),
@@ -90,11 +101,9 @@ fn load_trajectory(filepath: &str) -> Result<Vec<(f64, Transform)>, DatasetError
                         tokens[6] as f32,
                     ),
                 ),
-            )
+            ))
         })
-        .collect::<Vec<(f64, Transform)>>();
-
-    Ok(trajectory)
+        .collect()
 }
 
 impl TumRgbdDataset {
@@ -110,6 +119,10 @@ impl TumRgbdDataset {
             .iter()
             .map(|entry| entry.1.clone())
             .collect::<Vec<String>>();
+        let timestamps = depth_rgb_assoc
+            .iter()
+            .map(|entry| entry.0)
+            .collect::<Vec<f64>>();
 
         let trajectory = load_trajectory(
             PathBuf::from(base_dirpath)
@@ -129,6 +142,7 @@ impl TumRgbdDataset {
             base_dir: PathBuf::from(base_dirpath),
             rgb_images,
             depth_images,
+            timestamps,
             trajectory,
         })
     }
@@ -136,18 +150,21 @@ impl TumRgbdDataset {
 
 impl RgbdDataset for TumRgbdDataset {
     fn get(&self, index: usize) -> Result<RgbdFrame, DatasetError> {
-        let rgb_image = image::open(self.base_dir.join(&self.rgb_images[index]))?
-            .into_rgb8()
-            .into_array3();
-
-        let depth_image = image::open(self.base_dir.join(&self.depth_images[index]))?
-            .into_luma16()
-            .into_ndarray2();
+        let (rgb_image, depth_image) = load_rgbd_images(
+            self.base_dir.join(&self.rgb_images[index]),
+            self.base_dir.join(&self.depth_images[index]),
+        )
+        .map_err(|err| DatasetError::frame(index, err))?;
         let mut rgbd_image = RgbdImage::new(rgb_image, depth_image);
         rgbd_image.depth_scale = Some(1.0 / 5000.0);
 
         let (camera, transform) = self.camera(index);
-        Ok(RgbdFrame::new(camera, rgbd_image, transform))
+        let metadata = Metadata::from_frame(
+            format!("tum:{}", self.base_dir.display()),
+            index,
+            Some(self.timestamps[index]),
+        );
+        Ok(RgbdFrame::new(camera, rgbd_image, transform).with_metadata(metadata))
     }
 
     fn len(&self) -> usize {
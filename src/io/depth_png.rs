@@ -0,0 +1,203 @@
+//! Re-encoding of processed depth images (e.g., after filtering or depth completion) into
+//! 16 bits PNG sequences, so preprocessed datasets can be cached and shared.
+//!
+//! Quantization rounds to the nearest step, ties away from zero, the same input always
+//! gives the same file. PNG is lossless, so reading a written image gives back the exact
+//! values, and depth written at the scale it was captured with is bit exact.
+
+use std::path::{Path, PathBuf};
+
+use image::ImageBuffer;
+use ndarray::{Array2, ArrayView2};
+use nshare::ToNdarray2;
+
+use super::dataset::DatasetError;
+use crate::image::RgbdImage;
+
+const SCALE_FILENAME: &str = "depth_scale.txt";
+
+fn quantize(meters: f64, depth_scale: f64) -> Result<u16, DatasetError> {
+    let value = (meters / depth_scale).round();
+    if value > u16::MAX as f64 {
+        return Err(DatasetError::Parser(format!(
+            "Depth of {meters}m overflows 16 bits at the scale {depth_scale}"
+        )));
+    }
+    // Valid depth too close to zero would become invalid.
+    Ok((value as u16).max(1))
+}
+
+/// Converts depth in meters into 16 bits values. Non positive and non finite values are
+/// invalid and become 0.
+///
+/// # Arguments
+///
+/// * `depth` - Depth in meters.
+/// * `depth_scale` - Meters per unit of the output, e.g., 0.001 for millimeters.
+///
+/// # Returns
+///
+/// The quantized depth, or error if a value doesn't fit into 16 bits.
+pub fn quantize_depth(
+    depth: &ArrayView2<f32>,
+    depth_scale: f64,
+) -> Result<Array2<u16>, DatasetError> {
+    let mut quantized = Array2::zeros(depth.dim());
+    for (value, meters) in quantized.iter_mut().zip(depth.iter()) {
+        if meters.is_finite() && *meters > 0.0 {
+            *value = quantize(*meters as f64, depth_scale)?;
+        }
+    }
+    Ok(quantized)
+}
+
+/// Converts 16 bits depth into another scale, see [`quantize_depth`].
+///
+/// # Arguments
+///
+/// * `depth` - The depth values, 0 is invalid.
+/// * `source_scale` - Meters per unit of the input.
+/// * `target_scale` - Meters per unit of the output.
+pub fn requantize_depth(
+    depth: &ArrayView2<u16>,
+    source_scale: f64,
+    target_scale: f64,
+) -> Result<Array2<u16>, DatasetError> {
+    if source_scale == target_scale {
+        return Ok(depth.to_owned());
+    }
+    let mut quantized = Array2::zeros(depth.dim());
+    for (value, source) in quantized.iter_mut().zip(depth.iter()) {
+        if *source > 0 {
+            *value = quantize(*source as f64 * source_scale, target_scale)?;
+        }
+    }
+    Ok(quantized)
+}
+
+/// Writes a depth image as a 16 bits grayscale PNG.
+pub fn write_depth_png<P: AsRef<Path>>(
+    filepath: P,
+    depth: &ArrayView2<u16>,
+) -> Result<(), DatasetError> {
+    let (height, width) = depth.dim();
+    ImageBuffer::<image::Luma<u16>, _>::from_raw(
+        width as u32,
+        height as u32,
+        depth.iter().cloned().collect::<Vec<_>>(),
+    )
+    .unwrap()
+    .save(filepath)?;
+    Ok(())
+}
+
+/// Reads a depth image written by [`write_depth_png`], or any 16 bits PNG.
+pub fn read_depth_png<P: AsRef<Path>>(filepath: P) -> Result<Array2<u16>, DatasetError> {
+    Ok(image::open(filepath)?.into_luma16().into_ndarray2())
+}
+
+/// Directory of depth images sharing a scale, named by frame index (`000042.png`). The
+/// scale is stored with them in `depth_scale.txt`.
+pub struct DepthPngSequence {
+    dirpath: PathBuf,
+    depth_scale: f64,
+}
+
+impl DepthPngSequence {
+    /// Creates the sequence directory, if needed, and writes its scale.
+    ///
+    /// # Arguments
+    ///
+    /// * `dirpath` - The directory.
+    /// * `depth_scale` - Meters per unit of the written images. Use the scale of the
+    ///   source dataset for bit exact copies, or a finer one for completed or filtered depth
+    ///   with sub-unit precision.
+    pub fn create<P: AsRef<Path>>(dirpath: P, depth_scale: f64) -> Result<Self, DatasetError> {
+        let dirpath = dirpath.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dirpath)?;
+        std::fs::write(dirpath.join(SCALE_FILENAME), format!("{depth_scale:e}\n"))?;
+        Ok(Self {
+            dirpath,
+            depth_scale,
+        })
+    }
+
+    /// Opens a sequence written by [`DepthPngSequence::create`].
+    pub fn open<P: AsRef<Path>>(dirpath: P) -> Result<Self, DatasetError> {
+        let dirpath = dirpath.as_ref().to_path_buf();
+        let depth_scale = std::fs::read_to_string(dirpath.join(SCALE_FILENAME))?
+            .trim()
+            .parse()
+            .map_err(|_| DatasetError::Parser(format!("Invalid {SCALE_FILENAME}")))?;
+        Ok(Self {
+            dirpath,
+            depth_scale,
+        })
+    }
+
+    /// Meters per unit of the images.
+    pub fn depth_scale(&self) -> f64 {
+        self.depth_scale
+    }
+
+    fn filepath(&self, index: usize) -> PathBuf {
+        self.dirpath.join(format!("{index:06}.png"))
+    }
+
+    /// Writes the depth of an image, converted into the sequence scale.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The frame index.
+    /// * `image` - The image, it must have a depth scale.
+    pub fn write(&self, index: usize, image: &RgbdImage) -> Result<(), DatasetError> {
+        let source_scale = image
+            .depth_scale
+            .ok_or_else(|| DatasetError::Parser("The image has no depth scale".to_string()))?;
+        let depth = requantize_depth(&image.depth.view(), source_scale, self.depth_scale)?;
+        write_depth_png(self.filepath(index), &depth.view())
+    }
+
+    /// Reads the depth of a frame, in the sequence scale.
+    pub fn read(&self, index: usize) -> Result<Array2<u16>, DatasetError> {
+        read_depth_png(self.filepath(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array2, Array3};
+
+    use super::{quantize_depth, requantize_depth, DepthPngSequence};
+    use crate::image::RgbdImage;
+
+    #[test]
+    fn test_quantize_depth() {
+        let depth = array![[0.0, -1.0, f32::NAN], [1.2345, 0.0001, 6.5]];
+        let quantized = quantize_depth(&depth.view(), 0.001).unwrap();
+        assert_eq!(quantized, array![[0, 0, 0], [1235, 1, 6500]]);
+        assert!(quantize_depth(&depth.view(), 0.00005).is_err());
+
+        let fine = requantize_depth(&quantized.view(), 0.001, 0.0002).unwrap();
+        assert_eq!(fine[(1, 0)], 6175);
+        assert_eq!(
+            requantize_depth(&fine.view(), 0.0002, 0.001).unwrap(),
+            quantized
+        );
+    }
+
+    #[test]
+    fn test_sequence_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let depth = Array2::from_shape_fn((12, 16), |(row, col)| (row * 4000 + col * 7) as u16);
+        let image = RgbdImage::with_depth_scale(Array3::zeros((12, 16, 3)), depth.clone(), 0.001);
+
+        let sequence = DepthPngSequence::create(dir.path(), 0.001).unwrap();
+        sequence.write(3, &image).unwrap();
+
+        let sequence = DepthPngSequence::open(dir.path()).unwrap();
+        assert_eq!(sequence.depth_scale(), 0.001);
+        assert_eq!(sequence.read(3).unwrap(), depth);
+        assert!(sequence.read(4).is_err());
+    }
+}
@@ -11,3 +11,9 @@ impl From<io::Error> for LoadError {
         LoadError::IO(err)
     }
 }
+
+impl From<ndarray::ShapeError> for LoadError {
+    fn from(err: ndarray::ShapeError) -> Self {
+        LoadError::ParseError(err.to_string())
+    }
+}
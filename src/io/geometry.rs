@@ -1,7 +1,10 @@
 use nalgebra::{Vector2, Vector3};
 use ndarray::prelude::*;
 
+use crate::metadata::Metadata;
+
 /// Generic representation of attributes found in 3D model/object/geometry files.
+#[derive(Clone)]
 pub struct Geometry {
     /// The 3D points. Shape is (Nx3).
     pub points: Array1<Vector3<f32>>,
@@ -14,6 +17,8 @@ pub struct Geometry {
     pub faces: Option<Array2<usize>>,
     /// The texture coordinates.
     pub texcoords: Option<Array1<Vector2<f32>>>,
+    /// Provenance of the geometry, written into the file header when supported.
+    pub metadata: Option<Metadata>,
 }
 
 impl Geometry {
@@ -41,6 +46,7 @@ impl GeometryBuilder {
                 normals: None,
                 faces: None,
                 texcoords: None,
+                metadata: None,
             },
         }
     }
@@ -70,6 +76,11 @@ impl GeometryBuilder {
         self
     }
 
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.geometry.metadata = Some(metadata);
+        self
+    }
+
     pub fn build(self) -> Geometry {
         self.geometry
     }
@@ -7,4 +7,18 @@ pub use geometry::{Geometry, GeometryBuilder};
 mod error;
 pub use error::LoadError;
 mod ply;
-pub use ply::{read_ply, write_ply};
+pub use ply::{read_ply, read_ply_pointcloud, write_ply, write_ply_pointcloud, write_textured_ply};
+mod ply_stream;
+pub use ply_stream::{
+    write_ply_with_properties, PlyPropertyValues, PlyScalar, PlyStreamWriter, PlyVertexLayout,
+};
+mod quantized;
+pub use quantized::{read_quantized, write_quantized, QuantizationParams};
+mod depth_png;
+pub use depth_png::{
+    quantize_depth, read_depth_png, requantize_depth, write_depth_png, DepthPngSequence,
+};
+mod xyz;
+pub use xyz::read_xyz;
+pub mod npy;
+pub mod video;
@@ -0,0 +1,410 @@
+//! Reading and writing of point buffers in the NumPy `.npy` and `.npz` formats.
+//!
+//! Arrays are stored as little-endian `float32` with shape (N, 3), so they load in
+//! numpy with `np.load` without conversion. The readers also accept `float64` and
+//! Fortran-ordered arrays. `.npz` archives are written without compression, as produced
+//! by `np.savez`; compressed archives (`np.savez_compressed`) are not supported.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use nalgebra::Vector3;
+use ndarray::{Array1, Array2, ArrayView2, ShapeBuilder};
+
+use super::LoadError;
+
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+const ZIP_LOCAL_HEADER: u32 = 0x04034b50;
+const ZIP_CENTRAL_HEADER: u32 = 0x02014b50;
+const ZIP_END_OF_CENTRAL_DIR: u32 = 0x06054b50;
+
+/// Converts a point (or normal) buffer into a (N, 3) array.
+pub fn to_array2(points: &Array1<Vector3<f32>>) -> Array2<f32> {
+    Array2::from_shape_fn((points.len(), 3), |(i, j)| points[i][j])
+}
+
+/// Converts a (N, 3) array into a point (or normal) buffer.
+///
+/// # Returns
+///
+/// The buffer or an error if the array doesn't have 3 columns.
+pub fn from_array2(array: &ArrayView2<f32>) -> Result<Array1<Vector3<f32>>, LoadError> {
+    if array.ncols() != 3 {
+        return Err(LoadError::ParseError(format!(
+            "Expected an array with 3 columns, got shape {:?}",
+            array.dim()
+        )));
+    }
+    Ok(array
+        .rows()
+        .into_iter()
+        .map(|row| Vector3::new(row[0], row[1], row[2]))
+        .collect())
+}
+
+fn encode_npy(array: &ArrayView2<f32>) -> Vec<u8> {
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        array.nrows(),
+        array.ncols()
+    );
+    // The header is padded so the data starts aligned to 64 bytes.
+    let unpadded_len = NPY_MAGIC.len() + 2 + 2 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded_len % 64) % 64));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(unpadded_len + 64 + array.len() * 4);
+    bytes.extend_from_slice(NPY_MAGIC);
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for value in array.iter() {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+/// Extracts the value of a key from the header dictionary, e.g., `'descr': '<f4'`.
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{key}'"))? + key.len() + 2;
+    let value = header[start..].trim_start().strip_prefix(':')?.trim_start();
+    let end = if value.starts_with('(') {
+        value.find(')')? + 1
+    } else {
+        value.find(',').unwrap_or(value.len())
+    };
+    Some(value[..end].trim())
+}
+
+fn decode_npy(bytes: &[u8]) -> Result<Array2<f32>, LoadError> {
+    let error = |message: &str| LoadError::ParseError(format!("Invalid .npy data: {message}"));
+
+    if bytes.len() < 10 || &bytes[..6] != NPY_MAGIC {
+        return Err(error("missing magic string"));
+    }
+    let (header_start, header_len) = match bytes[6] {
+        1 => (10, u16::from_le_bytes([bytes[8], bytes[9]]) as usize),
+        2 | 3 if bytes.len() >= 12 => (
+            12,
+            u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize,
+        ),
+        _ => return Err(error("unsupported version")),
+    };
+    let header = bytes
+        .get(header_start..header_start + header_len)
+        .and_then(|header| std::str::from_utf8(header).ok())
+        .ok_or_else(|| error("truncated header"))?;
+    let data = &bytes[header_start + header_len..];
+
+    let descr = header_value(header, "descr").ok_or_else(|| error("missing descr"))?;
+    let fortran_order = header_value(header, "fortran_order") == Some("True");
+    let shape = header_value(header, "shape")
+        .ok_or_else(|| error("missing shape"))?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| error("invalid shape"))?;
+    let (rows, cols) = match shape[..] {
+        [rows, cols] => (rows, cols),
+        [rows] => (rows, 1),
+        _ => return Err(error(&format!("expected 2 dimensions, got {shape:?}"))),
+    };
+    let len = rows
+        .checked_mul(cols)
+        .ok_or_else(|| error(&format!("shape {shape:?} is too large")))?;
+
+    let values: Vec<f32> = match descr.trim_matches('\'') {
+        "<f4" => data
+            .chunks_exact(4)
+            .take(len)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect(),
+        "<f8" => data
+            .chunks_exact(8)
+            .take(len)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()) as f32)
+            .collect(),
+        descr => return Err(error(&format!("unsupported type {descr}"))),
+    };
+    if values.len() != len {
+        return Err(error("truncated data"));
+    }
+
+    Array2::from_shape_vec((rows, cols).set_f(fortran_order), values)
+        .map(|array| array.as_standard_layout().into_owned())
+        .map_err(|err| error(&err.to_string()))
+}
+
+/// Writes an array into a `.npy` file.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the output file.
+/// * `array` - The array, e.g., a (N, 3) point buffer from [`to_array2`].
+pub fn write_npy<P: AsRef<Path>>(
+    filepath: P,
+    array: &ArrayView2<f32>,
+) -> Result<(), std::io::Error> {
+    let mut writer = BufWriter::new(File::create(filepath)?);
+    writer.write_all(&encode_npy(array))?;
+    writer.flush()
+}
+
+/// Reads an array from a `.npy` file. One dimensional arrays are read as a column.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the `.npy` file.
+///
+/// # Returns
+///
+/// The array converted to `f32`.
+pub fn read_npy<P: AsRef<Path>>(filepath: P) -> Result<Array2<f32>, LoadError> {
+    let mut bytes = Vec::new();
+    BufReader::new(File::open(filepath)?).read_to_end(&mut bytes)?;
+    decode_npy(&bytes)
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & 0u32.wrapping_sub(crc & 1));
+        }
+    }
+    !crc
+}
+
+/// Writes arrays into a `.npz` archive, the names are the keys used by `np.load`.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the output file.
+/// * `arrays` - Pairs of name and array, e.g., `[("points", ...), ("normals", ...)]`.
+pub fn write_npz<P: AsRef<Path>>(
+    filepath: P,
+    arrays: &[(&str, ArrayView2<f32>)],
+) -> Result<(), std::io::Error> {
+    let mut writer = BufWriter::new(File::create(filepath)?);
+    let mut central_directory = Vec::new();
+    let mut offset = 0u32;
+
+    for (name, array) in arrays.iter() {
+        let filename = format!("{name}.npy");
+        let data = encode_npy(array);
+        let crc = crc32(&data);
+
+        // Fields shared by the local and central headers: version, flags, method (stored),
+        // time, date, crc, sizes and name length.
+        let mut common = Vec::new();
+        common.extend_from_slice(&20u16.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&0x21u16.to_le_bytes());
+        common.extend_from_slice(&crc.to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(filename.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+
+        writer.write_all(&ZIP_LOCAL_HEADER.to_le_bytes())?;
+        writer.write_all(&common)?;
+        writer.write_all(filename.as_bytes())?;
+        writer.write_all(&data)?;
+
+        central_directory.extend_from_slice(&ZIP_CENTRAL_HEADER.to_le_bytes());
+        central_directory.extend_from_slice(&20u16.to_le_bytes());
+        central_directory.extend_from_slice(&common);
+        // Comment length, disk, internal and external attributes.
+        central_directory.extend_from_slice(&[0; 10]);
+        central_directory.extend_from_slice(&offset.to_le_bytes());
+        central_directory.extend_from_slice(filename.as_bytes());
+
+        offset += (4 + common.len() + filename.len() + data.len()) as u32;
+    }
+
+    writer.write_all(&central_directory)?;
+    writer.write_all(&ZIP_END_OF_CENTRAL_DIR.to_le_bytes())?;
+    writer.write_all(&[0; 4])?;
+    writer.write_all(&(arrays.len() as u16).to_le_bytes())?;
+    writer.write_all(&(arrays.len() as u16).to_le_bytes())?;
+    writer.write_all(&(central_directory.len() as u32).to_le_bytes())?;
+    writer.write_all(&offset.to_le_bytes())?;
+    writer.write_all(&0u16.to_le_bytes())?;
+    writer.flush()
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// Reads the arrays of a `.npz` archive.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the `.npz` file.
+///
+/// # Returns
+///
+/// The arrays by name (without the `.npy` extension).
+pub fn read_npz<P: AsRef<Path>>(filepath: P) -> Result<BTreeMap<String, Array2<f32>>, LoadError> {
+    let mut bytes = Vec::new();
+    BufReader::new(File::open(filepath)?).read_to_end(&mut bytes)?;
+    let error = |message: &str| LoadError::ParseError(format!("Invalid .npz archive: {message}"));
+
+    let end_offset = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .find(|offset| read_u32(&bytes, *offset) == Some(ZIP_END_OF_CENTRAL_DIR))
+        .ok_or_else(|| error("missing end of central directory"))?;
+    let num_entries = read_u16(&bytes, end_offset + 10).unwrap() as usize;
+    let mut offset = read_u32(&bytes, end_offset + 16).unwrap() as usize;
+
+    let mut arrays = BTreeMap::new();
+    for _ in 0..num_entries {
+        if read_u32(&bytes, offset) != Some(ZIP_CENTRAL_HEADER) {
+            return Err(error("invalid central directory"));
+        }
+        let entry = || -> Option<_> {
+            let method = read_u16(&bytes, offset + 10)?;
+            let mut size = read_u32(&bytes, offset + 24)? as u64;
+            let name_len = read_u16(&bytes, offset + 28)? as usize;
+            let extra_len = read_u16(&bytes, offset + 30)? as usize;
+            let comment_len = read_u16(&bytes, offset + 32)? as usize;
+            let mut local_offset = read_u32(&bytes, offset + 42)? as u64;
+            let name = std::str::from_utf8(bytes.get(offset + 46..offset + 46 + name_len)?).ok()?;
+
+            // Zip64 extra field, used by numpy for large arrays.
+            let extra_start = offset + 46 + name_len;
+            let mut extra = extra_start;
+            while extra + 4 <= extra_start + extra_len {
+                let (tag, len) = (read_u16(&bytes, extra)?, read_u16(&bytes, extra + 2)?);
+                if tag == 1 {
+                    let mut field = extra + 4;
+                    if size == 0xFFFF_FFFF {
+                        size = read_u64(&bytes, field)?;
+                        field += 8;
+                    }
+                    if read_u32(&bytes, offset + 20)? == 0xFFFF_FFFF {
+                        field += 8;
+                    }
+                    if local_offset == 0xFFFF_FFFF {
+                        local_offset = read_u64(&bytes, field)?;
+                    }
+                }
+                extra += 4 + len as usize;
+            }
+
+            Some((
+                method,
+                size as usize,
+                local_offset as usize,
+                name.to_string(),
+                46 + name_len + extra_len + comment_len,
+            ))
+        }()
+        .ok_or_else(|| error("truncated central directory"))?;
+        let (method, size, local_offset, name, entry_len) = entry;
+        offset += entry_len;
+
+        if method != 0 {
+            return Err(error(&format!(
+                "{name} is compressed, only uncompressed archives (np.savez) are supported"
+            )));
+        }
+
+        let data_start = (|| -> Option<usize> {
+            if read_u32(&bytes, local_offset)? != ZIP_LOCAL_HEADER {
+                return None;
+            }
+            let name_len = read_u16(&bytes, local_offset + 26)? as usize;
+            let extra_len = read_u16(&bytes, local_offset + 28)? as usize;
+            Some(local_offset + 30 + name_len + extra_len)
+        })()
+        .ok_or_else(|| error("invalid local header"))?;
+        let data = bytes
+            .get(data_start..data_start + size)
+            .ok_or_else(|| error("truncated data"))?;
+
+        arrays.insert(
+            name.strip_suffix(".npy").unwrap_or(&name).to_string(),
+            decode_npy(data)?,
+        );
+    }
+
+    Ok(arrays)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array2};
+    use rstest::rstest;
+
+    use super::{
+        decode_npy, from_array2, read_npy, read_npz, to_array2, write_npy, write_npz, NPY_MAGIC,
+    };
+    use crate::{pointcloud::PointCloud, unit_test::sample_teapot_pointcloud};
+
+    #[rstest]
+    fn test_npy_round_trip(sample_teapot_pointcloud: PointCloud) {
+        let points = to_array2(&sample_teapot_pointcloud.points);
+        write_npy("tests/outputs/teapot-points.npy", &points.view()).unwrap();
+
+        let loaded = read_npy("tests/outputs/teapot-points.npy").unwrap();
+        assert_eq!(loaded, points);
+        assert_eq!(
+            from_array2(&loaded.view()).unwrap(),
+            sample_teapot_pointcloud.points
+        );
+        assert!(from_array2(&Array2::zeros((2, 2)).view()).is_err());
+    }
+
+    #[test]
+    fn test_npy_overflowing_shape() {
+        let header =
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (18446744073709551615, 2), }";
+        let mut bytes = NPY_MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+
+        assert!(decode_npy(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_npz_round_trip() {
+        let points = array![[1.0f32, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        let normals = array![[0.0f32, 0.0, 1.0], [0.0, 1.0, 0.0]];
+        write_npz(
+            "tests/outputs/points.npz",
+            &[("points", points.view()), ("normals", normals.view())],
+        )
+        .unwrap();
+
+        let arrays = read_npz("tests/outputs/points.npz").unwrap();
+        assert_eq!(arrays.len(), 2);
+        assert_eq!(arrays["points"], points);
+        assert_eq!(arrays["normals"], normals);
+    }
+}
@@ -130,6 +130,7 @@ pub fn read_off(filepath: &str) -> Result<Geometry, LoadError> {
         normals: None,
         faces: Some(faces),
         texcoords: None,
+        metadata: None,
     })
 }
 
@@ -3,7 +3,12 @@ use std::io::BufWriter;
 use std::path::Path;
 
 use super::{Geometry, LoadError};
-use nalgebra::Vector3;
+use crate::{
+    metadata::Metadata,
+    pointcloud::{AttributeChannel, PointCloud},
+};
+use image::RgbImage;
+use nalgebra::{Vector2, Vector3};
 use ndarray::{Array1, Array2, Axis};
 use ply_rs::ply::{
     Addable, DefaultElement, ElementDef, Encoding, Ply, Property, PropertyDef, PropertyType,
@@ -19,8 +24,16 @@ struct Vertex {
     point: [f32; 3],
     normal: [f32; 3],
     color: [u8; 3],
+    texcoord: [f32; 2],
+    /// Values of the other properties, in the header order.
+    properties: Vec<ply::Property>,
 }
 
+/// Vertex properties read into the [`Vertex`] fields.
+const VERTEX_KEYS: [&str; 11] = [
+    "x", "y", "z", "nx", "ny", "nz", "red", "green", "blue", "s", "t",
+];
+
 #[derive(Debug)]
 struct Face {
     vertex_index: Vec<i32>,
@@ -35,6 +48,8 @@ impl ply::PropertyAccess for Vertex {
             point: [0f32; 3],
             normal: [0f32; 3],
             color: [0u8; 3],
+            texcoord: [0f32; 2],
+            properties: Vec::new(),
         }
     }
     fn set_property(&mut self, key: String, property: ply::Property) {
@@ -48,6 +63,9 @@ impl ply::PropertyAccess for Vertex {
             ("red", ply::Property::UChar(v)) => self.color[0] = v,
             ("green", ply::Property::UChar(v)) => self.color[1] = v,
             ("blue", ply::Property::UChar(v)) => self.color[2] = v,
+            ("s", ply::Property::Float(v)) => self.texcoord[0] = v,
+            ("t", ply::Property::Float(v)) => self.texcoord[1] = v,
+            (key, property) if !VERTEX_KEYS.contains(&key) => self.properties.push(property),
             (_, _) => (), // TODO: Add log
         }
     }
@@ -71,6 +89,14 @@ impl ply::PropertyAccess for Face {
 }
 
 pub fn read_ply<P>(filepath: P) -> Result<Geometry, LoadError>
+where
+    P: AsRef<Path>,
+{
+    read_ply_with_properties(filepath).map(|(geometry, _)| geometry)
+}
+
+/// Reads a PLY file with the values of its other scalar vertex properties, e.g., labels.
+fn read_ply_with_properties<P>(filepath: P) -> Result<(Geometry, VertexProperties), LoadError>
 where
     P: AsRef<Path>,
 {
@@ -84,7 +110,9 @@ where
     let mut point_array = None;
     let mut normal_array = None;
     let mut color_array = None;
+    let mut texcoord_array = None;
     let mut face_array = None;
+    let mut properties = Vec::new();
     for (_ignore_key, element) in &header.elements {
         match element.name.as_ref() {
             "vertex" => {
@@ -93,6 +121,18 @@ where
                     .read_payload_for_element(&mut f, element, &header)
                     .unwrap();
 
+                let names = element
+                    .properties
+                    .keys()
+                    .filter(|key| !VERTEX_KEYS.contains(&key.as_str()));
+                for (i, name) in names.enumerate() {
+                    let values = vertex_vec
+                        .iter()
+                        .map(|vertex| vertex.properties[i].clone())
+                        .collect();
+                    properties.push((name.clone(), values));
+                }
+
                 point_array = Some(Array1::<Vector3<f32>>::from_shape_fn(
                     vertex_vec.len(),
                     |i| Vector3::from_row_slice(&vertex_vec[i].point),
@@ -117,6 +157,16 @@ where
                         |i| Vector3::from_row_slice(&vertex_vec[i].color),
                     ));
                 }
+
+                if ["s", "t"]
+                    .iter()
+                    .all(|k| element.properties.contains_key(*k))
+                {
+                    texcoord_array = Some(Array1::<Vector2<f32>>::from_shape_fn(
+                        vertex_vec.len(),
+                        |i| Vector2::from_row_slice(&vertex_vec[i].texcoord),
+                    ));
+                }
             }
             "face" => {
                 let face_parser = parser::Parser::<Face>::new();
@@ -132,16 +182,197 @@ where
         }
     }
 
-    Ok(Geometry {
-        points: point_array.unwrap(),
-        colors: color_array,
-        normals: normal_array,
-        faces: face_array,
-        texcoords: None,
+    Ok((
+        Geometry {
+            points: point_array.unwrap(),
+            colors: color_array,
+            normals: normal_array,
+            faces: face_array,
+            texcoords: texcoord_array,
+            metadata: Metadata::from_comments(&header.comments),
+        },
+        properties,
+    ))
+}
+
+/// Values of named vertex properties.
+type VertexProperties = Vec<(String, Vec<Property>)>;
+
+/// Converts the values of a scalar vertex property into an attribute channel, `None` for
+/// list properties.
+fn property_channel(values: Vec<Property>) -> Option<AttributeChannel> {
+    fn collect<T>(
+        values: &[Property],
+        value: impl Fn(&Property) -> Option<T>,
+    ) -> Option<Array1<T>> {
+        values.iter().map(value).collect()
+    }
+    Some(match values.first()? {
+        Property::Float(_) => AttributeChannel::F32(collect(&values, |value| match value {
+            Property::Float(value) => Some(*value),
+            _ => None,
+        })?),
+        Property::Double(_) => AttributeChannel::F64(collect(&values, |value| match value {
+            Property::Double(value) => Some(*value),
+            _ => None,
+        })?),
+        Property::UChar(_) | Property::UShort(_) | Property::UInt(_) => {
+            AttributeChannel::U32(collect(&values, |value| match value {
+                Property::UChar(value) => Some(*value as u32),
+                Property::UShort(value) => Some(*value as u32),
+                Property::UInt(value) => Some(*value),
+                _ => None,
+            })?)
+        }
+        Property::Char(_) | Property::Short(_) | Property::Int(_) => {
+            AttributeChannel::I32(collect(&values, |value| match value {
+                Property::Char(value) => Some(*value as i32),
+                Property::Short(value) => Some(*value as i32),
+                Property::Int(value) => Some(*value),
+                _ => None,
+            })?)
+        }
+        _ => return None,
     })
 }
 
+/// Reads a point cloud from a PLY file. The `intensity` and `curvature` vertex properties
+/// are read into the point cloud fields and the other scalar properties into attribute
+/// channels, so clouds written by [`write_ply_pointcloud`] are read back unchanged.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the PLY file.
+pub fn read_ply_pointcloud<P>(filepath: P) -> Result<PointCloud, LoadError>
+where
+    P: AsRef<Path>,
+{
+    let (geometry, properties) = read_ply_with_properties(filepath)?;
+    let mut pcl = PointCloud::from_geometry(geometry);
+    for (name, values) in properties {
+        match (name.as_str(), property_channel(values)) {
+            ("intensity", Some(AttributeChannel::F32(values))) => pcl.intensities = Some(values),
+            ("curvature", Some(AttributeChannel::F32(values))) => pcl.curvatures = Some(values),
+            (_, Some(channel)) => {
+                pcl.attributes.insert(name, channel);
+            }
+            (_, None) => (),
+        }
+    }
+    Ok(pcl)
+}
+
 pub fn write_ply<P>(filepath: P, geom: &Geometry) -> Result<(), std::io::Error>
+where
+    P: AsRef<Path>,
+{
+    write_ply_with_comments(filepath, geom, Vec::new(), Vec::new())
+}
+
+/// Writes a point cloud into a PLY file, with its intensities, curvatures and attribute
+/// channels as extra vertex properties. See [`read_ply_pointcloud`].
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the PLY file.
+/// * `pcl` - The point cloud.
+pub fn write_ply_pointcloud<P>(filepath: P, pcl: &PointCloud) -> Result<(), std::io::Error>
+where
+    P: AsRef<Path>,
+{
+    fn property<T: Copy>(
+        name: &str,
+        scalar: ScalarType,
+        values: &Array1<T>,
+        property: impl Fn(T) -> Property,
+    ) -> (String, ScalarType, Vec<Property>) {
+        let values = values.iter().map(|value| property(*value)).collect();
+        (name.to_string(), scalar, values)
+    }
+
+    let mut properties = Vec::new();
+    if let Some(intensities) = &pcl.intensities {
+        properties.push(property(
+            "intensity",
+            ScalarType::Float,
+            intensities,
+            Property::Float,
+        ));
+    }
+    if let Some(curvatures) = &pcl.curvatures {
+        properties.push(property(
+            "curvature",
+            ScalarType::Float,
+            curvatures,
+            Property::Float,
+        ));
+    }
+    for (name, channel) in pcl.attributes.iter() {
+        properties.push(match channel {
+            AttributeChannel::F32(values) => {
+                property(name, ScalarType::Float, values, Property::Float)
+            }
+            AttributeChannel::F64(values) => {
+                property(name, ScalarType::Double, values, Property::Double)
+            }
+            AttributeChannel::U32(values) => {
+                property(name, ScalarType::UInt, values, Property::UInt)
+            }
+            AttributeChannel::I32(values) => property(name, ScalarType::Int, values, Property::Int),
+        });
+    }
+
+    let geometry = Geometry {
+        points: pcl.points.clone(),
+        colors: pcl.colors.clone(),
+        normals: pcl.normals.clone(),
+        faces: None,
+        texcoords: None,
+        metadata: None,
+    };
+    write_ply_with_comments(filepath, &geometry, Vec::new(), properties)
+}
+
+/// Writes a textured mesh, e.g., from [`crate::mesh::bake_texture_atlas`]. The texture is
+/// saved as a PNG next to the PLY file and referenced by a `TextureFile` comment, as read
+/// by MeshLab and other viewers.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the PLY file.
+/// * `geom` - The geometry, it should have texture coordinates.
+/// * `texture` - The texture image.
+pub fn write_textured_ply<P>(
+    filepath: P,
+    geom: &Geometry,
+    texture: &RgbImage,
+) -> Result<(), std::io::Error>
+where
+    P: AsRef<Path>,
+{
+    let texture_path = filepath.as_ref().with_extension("png");
+    texture.save(&texture_path).map_err(std::io::Error::other)?;
+
+    let texture_name = texture_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    write_ply_with_comments(
+        filepath,
+        geom,
+        vec![format!("TextureFile {texture_name}")],
+        Vec::new(),
+    )
+}
+
+/// Writes a geometry with header comments and extra scalar vertex properties, given by
+/// name, type and per vertex values.
+fn write_ply_with_comments<P>(
+    filepath: P,
+    geom: &Geometry,
+    comments: Vec<String>,
+    properties: Vec<(String, ScalarType, Vec<Property>)>,
+) -> Result<(), std::io::Error>
 where
     P: AsRef<Path>,
 {
@@ -197,6 +428,29 @@ where
             });
         }
 
+        if let Some(texcoords) = &geom.texcoords {
+            ["s", "t"].iter().for_each(|key| {
+                vertex_element.properties.add(PropertyDef::new(
+                    key.to_string(),
+                    PropertyType::Scalar(ScalarType::Float),
+                ));
+            });
+
+            texcoords.iter().enumerate().for_each(|(i, texcoord)| {
+                vertex_array[i].insert("s".to_string(), Property::Float(texcoord[0]));
+                vertex_array[i].insert("t".to_string(), Property::Float(texcoord[1]));
+            });
+        }
+
+        for (name, scalar, values) in properties {
+            vertex_element
+                .properties
+                .add(PropertyDef::new(name.clone(), PropertyType::Scalar(scalar)));
+            for (vertex, value) in vertex_array.iter_mut().zip(values) {
+                vertex.insert(name.clone(), value);
+            }
+        }
+
         ply.header.elements.add(vertex_element);
         ply.payload.insert("vertex".to_string(), vertex_array);
 
@@ -228,6 +482,10 @@ where
     };
 
     ply.header.encoding = Encoding::Ascii;
+    ply.header.comments.extend(comments);
+    if let Some(metadata) = &geom.metadata {
+        ply.header.comments.extend(metadata.to_comments());
+    }
 
     let mut buf = BufWriter::new(File::create(filepath)?);
     Writer::new().write_ply(&mut buf, &mut ply)?;
@@ -237,11 +495,55 @@ where
 
 #[cfg(test)]
 mod test {
-    use super::{read_ply, write_ply};
+    use nalgebra::Vector3;
+    use ndarray::array;
+
+    use super::{read_ply, read_ply_pointcloud, write_ply, write_ply_pointcloud};
+    use crate::{metadata::Metadata, pointcloud::PointCloud};
 
     #[test]
     fn should_write_the_same_as_read() {
         let geom = read_ply("tests/data/teapot.ply").unwrap();
-        write_ply("tests/data/out-teapot.ply", &geom).unwrap();
+        write_ply("tests/outputs/out-teapot.ply", &geom).unwrap();
+    }
+
+    #[test]
+    fn should_keep_metadata() {
+        let mut geom = read_ply("tests/data/teapot.ply").unwrap();
+        assert!(geom.metadata.is_none());
+
+        let metadata = Metadata::from_frame("teapot", 3, Some(0.5)).with_parameter("scale", 2);
+        geom.metadata = Some(metadata.clone());
+        write_ply("tests/outputs/out-teapot-metadata.ply", &geom).unwrap();
+
+        let geom = read_ply("tests/outputs/out-teapot-metadata.ply").unwrap();
+        assert_eq!(geom.metadata, Some(metadata));
+    }
+
+    #[test]
+    fn should_keep_pointcloud_attributes() {
+        let mut pcl = PointCloud {
+            points: array![Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 2.0, 3.0)],
+            normals: Some(array![Vector3::z(), Vector3::x()]),
+            colors: None,
+            intensities: Some(array![0.25, 0.5]),
+            curvatures: None,
+            attributes: Default::default(),
+        };
+        pcl.set_attribute("label", array![3u32, 4])
+            .unwrap()
+            .set_attribute("cluster", array![-1i32, 2])
+            .unwrap()
+            .set_attribute("timestamp", array![1.5e9f64, 1.5e9 + 0.125])
+            .unwrap();
+        write_ply_pointcloud("tests/outputs/out-pointcloud-attributes.ply", &pcl).unwrap();
+
+        let read = read_ply_pointcloud("tests/outputs/out-pointcloud-attributes.ply").unwrap();
+        assert_eq!(read.points, pcl.points);
+        assert_eq!(read.normals, pcl.normals);
+        assert!(read.colors.is_none());
+        assert_eq!(read.intensities, pcl.intensities);
+        assert!(read.curvatures.is_none());
+        assert_eq!(read.attributes, pcl.attributes);
     }
 }
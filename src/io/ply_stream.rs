@@ -0,0 +1,504 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use nalgebra::Vector3;
+use ndarray::{s, ArrayView2, Axis};
+
+use super::Geometry;
+use crate::metadata::Metadata;
+
+/// Scalar type of a user-defined PLY vertex property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlyScalar {
+    Float,
+    UChar,
+    Int,
+}
+
+impl PlyScalar {
+    fn name(&self) -> &'static str {
+        match self {
+            PlyScalar::Float => "float",
+            PlyScalar::UChar => "uchar",
+            PlyScalar::Int => "int",
+        }
+    }
+}
+
+/// Values of a user-defined per-vertex property, e.g., confidence, radius or label.
+#[derive(Debug, Clone, Copy)]
+pub enum PlyPropertyValues<'a> {
+    Float(&'a [f32]),
+    UChar(&'a [u8]),
+    Int(&'a [i32]),
+}
+
+impl<'a> PlyPropertyValues<'a> {
+    fn scalar(&self) -> PlyScalar {
+        match self {
+            PlyPropertyValues::Float(_) => PlyScalar::Float,
+            PlyPropertyValues::UChar(_) => PlyScalar::UChar,
+            PlyPropertyValues::Int(_) => PlyScalar::Int,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            PlyPropertyValues::Float(values) => values.len(),
+            PlyPropertyValues::UChar(values) => values.len(),
+            PlyPropertyValues::Int(values) => values.len(),
+        }
+    }
+
+    fn slice(&self, start: usize, end: usize) -> Self {
+        match self {
+            PlyPropertyValues::Float(values) => PlyPropertyValues::Float(&values[start..end]),
+            PlyPropertyValues::UChar(values) => PlyPropertyValues::UChar(&values[start..end]),
+            PlyPropertyValues::Int(values) => PlyPropertyValues::Int(&values[start..end]),
+        }
+    }
+
+    fn write_value<W: Write>(&self, writer: &mut W, index: usize) -> std::io::Result<()> {
+        match self {
+            PlyPropertyValues::Float(values) => writer.write_all(&values[index].to_le_bytes()),
+            PlyPropertyValues::UChar(values) => writer.write_all(&[values[index]]),
+            PlyPropertyValues::Int(values) => writer.write_all(&values[index].to_le_bytes()),
+        }
+    }
+}
+
+/// Per-vertex layout of a PLY file written by [`PlyStreamWriter`].
+#[derive(Debug, Clone, Default)]
+pub struct PlyVertexLayout {
+    pub normals: bool,
+    pub colors: bool,
+    pub properties: Vec<(String, PlyScalar)>,
+}
+
+impl PlyVertexLayout {
+    /// Layout with only the point positions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_normals(mut self) -> Self {
+        self.normals = true;
+        self
+    }
+
+    pub fn with_colors(mut self) -> Self {
+        self.colors = true;
+        self
+    }
+
+    pub fn with_property(mut self, name: &str, scalar: PlyScalar) -> Self {
+        self.properties.push((name.to_string(), scalar));
+        self
+    }
+}
+
+fn invalid_input(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+/// Names of the vertex properties written by the layout itself.
+const RESERVED_PROPERTIES: [&str; 9] = ["x", "y", "z", "nx", "ny", "nz", "red", "green", "blue"];
+
+/// Writes binary PLY files chunk by chunk, so large clouds don't need to be in memory at
+/// once. The number of vertices and faces is declared upfront, vertices are written with
+/// `write_vertices` and then faces with `write_faces`.
+pub struct PlyStreamWriter<W: Write> {
+    writer: BufWriter<W>,
+    layout: PlyVertexLayout,
+    num_vertices: usize,
+    num_faces: usize,
+    written_vertices: usize,
+    written_faces: usize,
+}
+
+impl PlyStreamWriter<File> {
+    /// Creates a PLY file and writes its header.
+    ///
+    /// # Arguments
+    ///
+    /// * `filepath` - Path to the output file.
+    /// * `num_vertices` - Total number of vertices that will be written.
+    /// * `num_faces` - Total number of triangles that will be written.
+    /// * `layout` - Which attributes each vertex has.
+    /// * `metadata` - Optional metadata written as header comments.
+    pub fn create<P: AsRef<Path>>(
+        filepath: P,
+        num_vertices: usize,
+        num_faces: usize,
+        layout: PlyVertexLayout,
+        metadata: Option<&Metadata>,
+    ) -> std::io::Result<Self> {
+        Self::new(
+            File::create(filepath)?,
+            num_vertices,
+            num_faces,
+            layout,
+            metadata,
+        )
+    }
+}
+
+impl<W: Write> PlyStreamWriter<W> {
+    /// Creates the writer and writes the header. See [`PlyStreamWriter::create`].
+    ///
+    /// Fails if a property name is empty, has whitespace, is repeated or is one of the
+    /// position, normal and color properties.
+    pub fn new(
+        writer: W,
+        num_vertices: usize,
+        num_faces: usize,
+        layout: PlyVertexLayout,
+        metadata: Option<&Metadata>,
+    ) -> std::io::Result<Self> {
+        for (i, (name, _)) in layout.properties.iter().enumerate() {
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(invalid_input(format!("Invalid property name {name:?}")));
+            }
+            if RESERVED_PROPERTIES.contains(&name.as_str())
+                || layout.properties[..i]
+                    .iter()
+                    .any(|(other, _)| other == name)
+            {
+                return Err(invalid_input(format!("Duplicated property {name}")));
+            }
+        }
+
+        let mut writer = BufWriter::new(writer);
+        writeln!(writer, "ply")?;
+        writeln!(writer, "format binary_little_endian 1.0")?;
+        if let Some(metadata) = metadata {
+            for comment in metadata.to_comments() {
+                writeln!(writer, "comment {comment}")?;
+            }
+        }
+
+        writeln!(writer, "element vertex {num_vertices}")?;
+        for key in ["x", "y", "z"] {
+            writeln!(writer, "property float {key}")?;
+        }
+        if layout.normals {
+            for key in ["nx", "ny", "nz"] {
+                writeln!(writer, "property float {key}")?;
+            }
+        }
+        if layout.colors {
+            for key in ["red", "green", "blue"] {
+                writeln!(writer, "property uchar {key}")?;
+            }
+        }
+        for (name, scalar) in layout.properties.iter() {
+            writeln!(writer, "property {} {name}", scalar.name())?;
+        }
+        if num_faces > 0 {
+            writeln!(writer, "element face {num_faces}")?;
+            writeln!(writer, "property list uchar int vertex_indices")?;
+        }
+        writeln!(writer, "end_header")?;
+
+        Ok(Self {
+            writer,
+            layout,
+            num_vertices,
+            num_faces,
+            written_vertices: 0,
+            written_faces: 0,
+        })
+    }
+
+    /// Writes the next chunk of vertices.
+    ///
+    /// # Arguments
+    ///
+    /// * `points` - The vertex positions.
+    /// * `normals` - The vertex normals, required if the layout has normals.
+    /// * `colors` - The vertex colors, required if the layout has colors.
+    /// * `properties` - The values of the user-defined properties, in the layout order.
+    pub fn write_vertices(
+        &mut self,
+        points: &[Vector3<f32>],
+        normals: Option<&[Vector3<f32>]>,
+        colors: Option<&[Vector3<u8>]>,
+        properties: &[PlyPropertyValues],
+    ) -> std::io::Result<()> {
+        let count = points.len();
+        if self.written_vertices + count > self.num_vertices {
+            return Err(invalid_input(format!(
+                "Writing more than the {} declared vertices",
+                self.num_vertices
+            )));
+        }
+        if normals.map(|normals| normals.len()) != self.layout.normals.then_some(count)
+            || colors.map(|colors| colors.len()) != self.layout.colors.then_some(count)
+        {
+            return Err(invalid_input(
+                "Normals or colors don't match the layout or the number of points".to_string(),
+            ));
+        }
+        if properties.len() != self.layout.properties.len()
+            || properties
+                .iter()
+                .zip(self.layout.properties.iter())
+                .any(|(values, (_, scalar))| values.scalar() != *scalar || values.len() != count)
+        {
+            return Err(invalid_input(
+                "Properties don't match the layout or the number of points".to_string(),
+            ));
+        }
+
+        for i in 0..count {
+            for value in points[i].iter() {
+                self.writer.write_all(&value.to_le_bytes())?;
+            }
+            if let Some(normals) = normals {
+                for value in normals[i].iter() {
+                    self.writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+            if let Some(colors) = colors {
+                self.writer.write_all(colors[i].as_slice())?;
+            }
+            for values in properties.iter() {
+                values.write_value(&mut self.writer, i)?;
+            }
+        }
+        self.written_vertices += count;
+        Ok(())
+    }
+
+    /// Writes the next chunk of triangles, after all vertices were written.
+    ///
+    /// # Arguments
+    ///
+    /// * `faces` - Vertex indices of the triangles. Shape is (Nx3). The indices must be
+    ///   lower than the number of vertices.
+    pub fn write_faces(&mut self, faces: ArrayView2<usize>) -> std::io::Result<()> {
+        if self.written_vertices != self.num_vertices {
+            return Err(invalid_input(
+                "All vertices must be written before the faces".to_string(),
+            ));
+        }
+        if self.written_faces + faces.nrows() > self.num_faces {
+            return Err(invalid_input(format!(
+                "Writing more than the {} declared faces",
+                self.num_faces
+            )));
+        }
+
+        let face_size = u8::try_from(faces.ncols())
+            .map_err(|_| invalid_input(format!("Faces with {} vertices", faces.ncols())))?;
+        // PLY indices are `int`, so the vertex count must also fit.
+        if let Some(index) = faces
+            .iter()
+            .find(|index| **index >= self.num_vertices || i32::try_from(**index).is_err())
+        {
+            return Err(invalid_input(format!(
+                "Face index {index} is out of range for {} vertices",
+                self.num_vertices
+            )));
+        }
+
+        for face in faces.axis_iter(Axis(0)) {
+            self.writer.write_all(&[face_size])?;
+            for index in face.iter() {
+                self.writer.write_all(&(*index as i32).to_le_bytes())?;
+            }
+        }
+        self.written_faces += faces.nrows();
+        Ok(())
+    }
+
+    /// Flushes the file, failing if fewer elements than declared were written.
+    pub fn finish(mut self) -> std::io::Result<()> {
+        if self.written_vertices != self.num_vertices || self.written_faces != self.num_faces {
+            return Err(invalid_input(format!(
+                "Wrote {} of {} vertices and {} of {} faces",
+                self.written_vertices, self.num_vertices, self.written_faces, self.num_faces
+            )));
+        }
+        self.writer.flush()
+    }
+}
+
+/// Writes a geometry into a binary PLY file together with user-defined per-vertex
+/// properties. The data is written in chunks through a [`PlyStreamWriter`].
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the output file.
+/// * `geom` - The geometry, its normals and colors are written when present.
+/// * `properties` - Name and values of the extra properties, one value per vertex.
+pub fn write_ply_with_properties<P>(
+    filepath: P,
+    geom: &Geometry,
+    properties: &[(&str, PlyPropertyValues)],
+) -> std::io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    const CHUNK_SIZE: usize = 65536;
+
+    let mut layout = PlyVertexLayout {
+        normals: geom.normals.is_some(),
+        colors: geom.colors.is_some(),
+        properties: Vec::new(),
+    };
+    for (name, values) in properties.iter() {
+        layout = layout.with_property(name, values.scalar());
+    }
+
+    let mut writer = PlyStreamWriter::create(
+        filepath,
+        geom.len_vertices(),
+        geom.len_faces(),
+        layout,
+        geom.metadata.as_ref(),
+    )?;
+
+    for start in (0..geom.len_vertices()).step_by(CHUNK_SIZE) {
+        let end = (start + CHUNK_SIZE).min(geom.len_vertices());
+        let points = geom.points.slice(s![start..end]);
+        let normals = geom
+            .normals
+            .as_ref()
+            .map(|normals| normals.slice(s![start..end]));
+        let colors = geom
+            .colors
+            .as_ref()
+            .map(|colors| colors.slice(s![start..end]));
+        let chunk_properties = properties
+            .iter()
+            .map(|(_, values)| values.slice(start, end))
+            .collect::<Vec<_>>();
+
+        writer.write_vertices(
+            &points.to_vec(),
+            normals.map(|normals| normals.to_vec()).as_deref(),
+            colors.map(|colors| colors.to_vec()).as_deref(),
+            &chunk_properties,
+        )?;
+    }
+
+    if let Some(faces) = &geom.faces {
+        for start in (0..faces.nrows()).step_by(CHUNK_SIZE) {
+            let end = (start + CHUNK_SIZE).min(faces.nrows());
+            writer.write_faces(faces.slice(s![start..end, ..]))?;
+        }
+    }
+
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::array;
+
+    use super::{
+        write_ply_with_properties, PlyPropertyValues, PlyScalar, PlyStreamWriter, PlyVertexLayout,
+    };
+    use crate::io::GeometryBuilder;
+
+    #[test]
+    fn test_write_ply_with_properties() {
+        let geom = GeometryBuilder::new(array![
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0)
+        ])
+        .with_colors(array![
+            Vector3::new(255, 0, 0),
+            Vector3::new(0, 255, 0),
+            Vector3::new(0, 0, 255)
+        ])
+        .with_faces(array![[0, 1, 2]])
+        .build();
+
+        let confidences = [0.5f32, 0.75, 1.0];
+        let labels = [3i32, 4, 5];
+        write_ply_with_properties(
+            "tests/outputs/ply-properties.ply",
+            &geom,
+            &[
+                ("confidence", PlyPropertyValues::Float(&confidences)),
+                ("label", PlyPropertyValues::Int(&labels)),
+            ],
+        )
+        .unwrap();
+
+        let content = std::fs::read("tests/outputs/ply-properties.ply").unwrap();
+        let header = b"ply\nformat binary_little_endian 1.0\nelement vertex 3\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            property float confidence\nproperty int label\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+        assert!(content.starts_with(header));
+
+        let vertex_size = 3 * 4 + 3 + 4 + 4;
+        assert_eq!(content.len(), header.len() + 3 * vertex_size + 1 + 3 * 4);
+
+        let second_vertex = &content[header.len() + vertex_size..];
+        assert_eq!(
+            f32::from_le_bytes(second_vertex[0..4].try_into().unwrap()),
+            1.0
+        );
+        assert_eq!(&second_vertex[12..15], &[0, 255, 0]);
+        assert_eq!(
+            f32::from_le_bytes(second_vertex[15..19].try_into().unwrap()),
+            0.75
+        );
+        assert_eq!(
+            i32::from_le_bytes(second_vertex[19..23].try_into().unwrap()),
+            4
+        );
+    }
+
+    #[test]
+    fn test_stream_writer_validates() {
+        let layout = PlyVertexLayout::new().with_property("radius", PlyScalar::Float);
+        let mut writer = PlyStreamWriter::new(Vec::new(), 2, 0, layout, None).unwrap();
+        let points = [Vector3::new(0.0, 0.0, 0.0)];
+
+        assert!(writer.write_vertices(&points, None, None, &[]).is_err());
+        assert!(writer
+            .write_vertices(&points, None, None, &[PlyPropertyValues::UChar(&[1])])
+            .is_err());
+        writer
+            .write_vertices(&points, None, None, &[PlyPropertyValues::Float(&[0.1])])
+            .unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn test_stream_writer_validates_faces() {
+        let mut writer =
+            PlyStreamWriter::new(Vec::new(), 3, 1, PlyVertexLayout::new(), None).unwrap();
+        writer
+            .write_vertices(&[Vector3::zeros(); 3], None, None, &[])
+            .unwrap();
+
+        assert!(writer.write_faces(array![[0, 1, 3]].view()).is_err());
+        assert!(writer
+            .write_faces(array![[0, 1, i32::MAX as usize + 1]].view())
+            .is_err());
+        writer.write_faces(array![[0, 1, 2]].view()).unwrap();
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_stream_writer_validates_property_names() {
+        for name in ["", "my radius", "radius\nend_header", "x", "red"] {
+            let layout = PlyVertexLayout::new().with_property(name, PlyScalar::Float);
+            assert!(PlyStreamWriter::new(Vec::new(), 1, 0, layout, None).is_err());
+        }
+        let layout = PlyVertexLayout::new()
+            .with_property("radius", PlyScalar::Float)
+            .with_property("radius", PlyScalar::Int);
+        assert!(PlyStreamWriter::new(Vec::new(), 1, 0, layout, None).is_err());
+    }
+}
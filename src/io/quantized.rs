@@ -0,0 +1,547 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use nalgebra::{Vector2, Vector3};
+use ndarray::{Array1, Array2};
+
+use super::{Geometry, LoadError};
+
+const MAGIC: &[u8; 4] = b"A3DQ";
+const VERSION: u8 = 1;
+
+const HAS_NORMALS: u8 = 1;
+const HAS_COLORS: u8 = 1 << 1;
+const HAS_FACES: u8 = 1 << 2;
+/// Bytes before the quantized values: magic, header, counts and bounds.
+const HEADER_LEN: u64 = 4 + 5 + 2 * 8 + 6 * 4;
+
+/// Number of bits used to store each attribute component in the quantized format.
+#[derive(Debug, Clone, Copy)]
+pub struct QuantizationParams {
+    /// Bits per position coordinate, quantized inside the bounding box of the points. From 1 to 24.
+    pub position_bits: u8,
+    /// Bits per octahedral normal coordinate (two per normal). From 2 to 16.
+    pub normal_bits: u8,
+    /// Bits per color channel. From 1 to 8.
+    pub color_bits: u8,
+}
+
+impl Default for QuantizationParams {
+    /// Defaults to 14 bits for positions, 10 bits for normals and 8 bits for colors.
+    fn default() -> Self {
+        Self {
+            position_bits: 14,
+            normal_bits: 10,
+            color_bits: 8,
+        }
+    }
+}
+
+impl QuantizationParams {
+    fn validate(&self) -> Result<(), std::io::Error> {
+        for (name, bits, range) in [
+            ("position", self.position_bits, 1..=24),
+            ("normal", self.normal_bits, 2..=16),
+            ("color", self.color_bits, 1..=8),
+        ] {
+            if !range.contains(&bits) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Invalid number of {name} bits: {bits}, expected {range:?}"),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+struct BitWriter<W: Write> {
+    writer: W,
+    buffer: u64,
+    length: u32,
+}
+
+impl<W: Write> BitWriter<W> {
+    fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buffer: 0,
+            length: 0,
+        }
+    }
+
+    fn write(&mut self, value: u32, bits: u8) -> Result<(), std::io::Error> {
+        self.buffer |= (value as u64) << self.length;
+        self.length += bits as u32;
+        while self.length >= 8 {
+            self.writer.write_all(&[self.buffer as u8])?;
+            self.buffer >>= 8;
+            self.length -= 8;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<W, std::io::Error> {
+        if self.length > 0 {
+            self.writer.write_all(&[self.buffer as u8])?;
+        }
+        Ok(self.writer)
+    }
+}
+
+struct BitReader<R: Read> {
+    reader: R,
+    buffer: u64,
+    length: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: 0,
+            length: 0,
+        }
+    }
+
+    fn read(&mut self, bits: u8) -> Result<u32, std::io::Error> {
+        while self.length < bits as u32 {
+            let mut byte = [0u8; 1];
+            self.reader.read_exact(&mut byte)?;
+            self.buffer |= (byte[0] as u64) << self.length;
+            self.length += 8;
+        }
+        let value = self.buffer & ((1u64 << bits) - 1);
+        self.buffer >>= bits;
+        self.length -= bits as u32;
+        Ok(value as u32)
+    }
+
+    /// Drops the bits left in the current byte and returns the reader.
+    fn finish(self) -> R {
+        self.reader
+    }
+}
+
+fn max_value(bits: u8) -> f32 {
+    ((1u32 << bits) - 1) as f32
+}
+
+/// Maps an unit normal into the [-1, 1]^2 square using the octahedral projection.
+fn octahedral_encode(normal: &Vector3<f32>) -> Vector2<f32> {
+    let l1_norm = normal[0].abs() + normal[1].abs() + normal[2].abs();
+    if l1_norm == 0.0 {
+        return Vector2::zeros();
+    }
+
+    let (x, y) = (normal[0] / l1_norm, normal[1] / l1_norm);
+    if normal[2] >= 0.0 {
+        Vector2::new(x, y)
+    } else {
+        Vector2::new((1.0 - y.abs()) * x.signum(), (1.0 - x.abs()) * y.signum())
+    }
+}
+
+fn octahedral_decode(encoded: &Vector2<f32>) -> Vector3<f32> {
+    let (x, y) = (encoded[0], encoded[1]);
+    let z = 1.0 - x.abs() - y.abs();
+    let normal = if z >= 0.0 {
+        Vector3::new(x, y, z)
+    } else {
+        Vector3::new(
+            (1.0 - y.abs()) * x.signum(),
+            (1.0 - x.abs()) * y.signum(),
+            z,
+        )
+    };
+    normal.normalize()
+}
+
+fn quantize(value: f32, min: f32, extent: f32, bits: u8) -> u32 {
+    if extent <= 0.0 {
+        return 0;
+    }
+    let max = max_value(bits);
+    (((value - min) / extent) * max).round().clamp(0.0, max) as u32
+}
+
+fn dequantize(value: u32, min: f32, extent: f32, bits: u8) -> f32 {
+    min + value as f32 / max_value(bits) * extent
+}
+
+/// Writes a geometry using a compact quantized binary format. Positions are quantized inside
+/// their bounding box, normals use the octahedral projection and colors are truncated to the
+/// given number of bits. Faces, if present, are stored without compression.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the output file.
+/// * `geom` - The geometry to be written.
+/// * `params` - Number of bits of each attribute.
+pub fn write_quantized<P>(
+    filepath: P,
+    geom: &Geometry,
+    params: &QuantizationParams,
+) -> Result<(), std::io::Error>
+where
+    P: AsRef<Path>,
+{
+    params.validate()?;
+
+    let (min, max) = geom.points.iter().fold(
+        (
+            Vector3::repeat(f32::INFINITY),
+            Vector3::repeat(f32::NEG_INFINITY),
+        ),
+        |(min, max), point| (min.inf(point), max.sup(point)),
+    );
+    let (min, extent) = if geom.points.is_empty() {
+        (Vector3::zeros(), Vector3::zeros())
+    } else {
+        (min, max - min)
+    };
+
+    let mut flags = 0;
+    if geom.normals.is_some() {
+        flags |= HAS_NORMALS;
+    }
+    if geom.colors.is_some() {
+        flags |= HAS_COLORS;
+    }
+    if geom.faces.is_some() {
+        flags |= HAS_FACES;
+    }
+
+    let mut writer = BufWriter::new(File::create(filepath)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[
+        VERSION,
+        flags,
+        params.position_bits,
+        params.normal_bits,
+        params.color_bits,
+    ])?;
+    writer.write_all(&(geom.len_vertices() as u64).to_le_bytes())?;
+    writer.write_all(&(geom.len_faces() as u64).to_le_bytes())?;
+    for value in min.iter().chain(extent.iter()) {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+
+    let mut bit_writer = BitWriter::new(writer);
+    for point in geom.points.iter() {
+        for k in 0..3 {
+            bit_writer.write(
+                quantize(point[k], min[k], extent[k], params.position_bits),
+                params.position_bits,
+            )?;
+        }
+    }
+
+    if let Some(normals) = &geom.normals {
+        for normal in normals.iter() {
+            let encoded = octahedral_encode(normal);
+            for k in 0..2 {
+                bit_writer.write(
+                    quantize(encoded[k], -1.0, 2.0, params.normal_bits),
+                    params.normal_bits,
+                )?;
+            }
+        }
+    }
+
+    if let Some(colors) = &geom.colors {
+        let shift = 8 - params.color_bits;
+        for color in colors.iter() {
+            for k in 0..3 {
+                bit_writer.write((color[k] >> shift) as u32, params.color_bits)?;
+            }
+        }
+    }
+
+    let mut writer = bit_writer.finish()?;
+    if let Some(faces) = &geom.faces {
+        for index in faces.iter() {
+            writer.write_all(&(*index as u32).to_le_bytes())?;
+        }
+    }
+
+    writer.flush()
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, std::io::Error> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32<R: Read>(reader: &mut R) -> Result<f32, std::io::Error> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+/// Reads a geometry written by [`write_quantized`].
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the quantized file.
+///
+/// # Returns
+///
+/// The dequantized geometry.
+pub fn read_quantized<P>(filepath: P) -> Result<Geometry, LoadError>
+where
+    P: AsRef<Path>,
+{
+    let file = File::open(filepath)?;
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(LoadError::ParseError(
+            "Not a quantized geometry file".to_string(),
+        ));
+    }
+
+    let mut header = [0u8; 5];
+    reader.read_exact(&mut header)?;
+    let [version, flags, position_bits, normal_bits, color_bits] = header;
+    if version != VERSION {
+        return Err(LoadError::ParseError(format!(
+            "Unsupported quantized geometry version: {version}"
+        )));
+    }
+    let params = QuantizationParams {
+        position_bits,
+        normal_bits,
+        color_bits,
+    };
+    params
+        .validate()
+        .map_err(|err| LoadError::ParseError(err.to_string()))?;
+
+    let num_vertices = read_u64(&mut reader)?;
+    let num_faces = read_u64(&mut reader)?;
+    let mut bounds = [0.0f32; 6];
+    for value in bounds.iter_mut() {
+        *value = read_f32(&mut reader)?;
+    }
+    let min = Vector3::new(bounds[0], bounds[1], bounds[2]);
+    let extent = Vector3::new(bounds[3], bounds[4], bounds[5]);
+
+    // The counts come from the file, check them before allocating.
+    let mut vertex_bits = 3 * position_bits as u64;
+    if flags & HAS_NORMALS != 0 {
+        vertex_bits += 2 * normal_bits as u64;
+    }
+    if flags & HAS_COLORS != 0 {
+        vertex_bits += 3 * color_bits as u64;
+    }
+    let face_bytes = if flags & HAS_FACES != 0 { 12 } else { 0 };
+    let payload_len = num_vertices
+        .checked_mul(vertex_bits)
+        .map(|bits| bits.div_ceil(8))
+        .zip(num_faces.checked_mul(face_bytes))
+        .and_then(|(vertex_len, face_len)| vertex_len.checked_add(face_len));
+    if payload_len.is_none_or(|payload_len| payload_len > file_len - HEADER_LEN) {
+        return Err(LoadError::ParseError(format!(
+            "Quantized geometry with {num_vertices} vertices and {num_faces} faces doesn't \
+             fit in a file of {file_len} bytes"
+        )));
+    }
+    let (num_vertices, num_faces) = (num_vertices as usize, num_faces as usize);
+
+    let mut bit_reader = BitReader::new(reader);
+    let mut points = Array1::<Vector3<f32>>::zeros(num_vertices);
+    for point in points.iter_mut() {
+        for k in 0..3 {
+            let value = bit_reader.read(position_bits)?;
+            point[k] = dequantize(value, min[k], extent[k], position_bits);
+        }
+    }
+
+    let normals = if flags & HAS_NORMALS != 0 {
+        let mut normals = Array1::<Vector3<f32>>::zeros(num_vertices);
+        for normal in normals.iter_mut() {
+            let x = dequantize(bit_reader.read(normal_bits)?, -1.0, 2.0, normal_bits);
+            let y = dequantize(bit_reader.read(normal_bits)?, -1.0, 2.0, normal_bits);
+            *normal = octahedral_decode(&Vector2::new(x, y));
+        }
+        Some(normals)
+    } else {
+        None
+    };
+
+    let colors = if flags & HAS_COLORS != 0 {
+        let max = (1u32 << color_bits) - 1;
+        let mut colors = Array1::<Vector3<u8>>::zeros(num_vertices);
+        for color in colors.iter_mut() {
+            for k in 0..3 {
+                let value = bit_reader.read(color_bits)?;
+                color[k] = ((value * 255 + max / 2) / max) as u8;
+            }
+        }
+        Some(colors)
+    } else {
+        None
+    };
+
+    let mut reader = bit_reader.finish();
+    let faces = if flags & HAS_FACES != 0 {
+        let mut indices = Vec::with_capacity(num_faces * 3);
+        let mut bytes = [0u8; 4];
+        for _ in 0..num_faces * 3 {
+            reader.read_exact(&mut bytes)?;
+            let index = u32::from_le_bytes(bytes) as usize;
+            if index >= num_vertices {
+                return Err(LoadError::ParseError(format!(
+                    "Face index {index} is out of range for {num_vertices} vertices"
+                )));
+            }
+            indices.push(index);
+        }
+        Some(Array2::from_shape_vec((num_faces, 3), indices)?)
+    } else {
+        None
+    };
+
+    Ok(Geometry {
+        points,
+        colors,
+        normals,
+        faces,
+        texcoords: None,
+        metadata: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{read_quantized, write_quantized, QuantizationParams};
+    use crate::{
+        io::{Geometry, LoadError},
+        unit_test::sample_teapot_geometry,
+    };
+
+    #[rstest]
+    fn test_write_read(sample_teapot_geometry: Geometry) {
+        let params = QuantizationParams {
+            position_bits: 12,
+            normal_bits: 10,
+            color_bits: 5,
+        };
+        write_quantized(
+            "tests/outputs/teapot-quantized.a3dq",
+            &sample_teapot_geometry,
+            &params,
+        )
+        .unwrap();
+        let geom = read_quantized("tests/outputs/teapot-quantized.a3dq").unwrap();
+
+        assert_eq!(geom.len_vertices(), sample_teapot_geometry.len_vertices());
+        assert_eq!(geom.faces, sample_teapot_geometry.faces);
+
+        let tolerance = 4.0 / (1 << params.position_bits) as f32;
+        for (expected, actual) in sample_teapot_geometry.points.iter().zip(geom.points.iter()) {
+            assert!((expected - actual).amax() < tolerance);
+        }
+
+        for (expected, actual) in sample_teapot_geometry
+            .normals
+            .as_ref()
+            .unwrap()
+            .iter()
+            .zip(geom.normals.as_ref().unwrap().iter())
+        {
+            if expected.norm() > 0.0 {
+                assert!(expected.normalize().dot(actual) > 1.0_f32.to_radians().cos());
+            }
+        }
+
+        for (expected, actual) in sample_teapot_geometry
+            .colors
+            .as_ref()
+            .unwrap()
+            .iter()
+            .zip(geom.colors.as_ref().unwrap().iter())
+        {
+            for k in 0..3 {
+                assert!((expected[k] as i32 - actual[k] as i32).abs() <= 8);
+            }
+        }
+    }
+
+    #[rstest]
+    fn test_read_invalid_counts(sample_teapot_geometry: Geometry) {
+        write_quantized(
+            "tests/outputs/teapot-counts.a3dq",
+            &sample_teapot_geometry,
+            &QuantizationParams::default(),
+        )
+        .unwrap();
+        let bytes = std::fs::read("tests/outputs/teapot-counts.a3dq").unwrap();
+
+        // The vertex and face counts follow the magic and the 5 header bytes.
+        for (offset, count) in [
+            (9, u64::MAX),
+            (9, 1 << 40),
+            (17, u64::MAX / 2),
+            (17, 1 << 30),
+        ] {
+            let mut corrupted = bytes.clone();
+            corrupted[offset..offset + 8].copy_from_slice(&count.to_le_bytes());
+            std::fs::write("tests/outputs/teapot-counts-corrupted.a3dq", corrupted).unwrap();
+            assert!(matches!(
+                read_quantized("tests/outputs/teapot-counts-corrupted.a3dq"),
+                Err(LoadError::ParseError(_))
+            ));
+        }
+    }
+
+    #[rstest]
+    fn test_read_invalid_face_index(sample_teapot_geometry: Geometry) {
+        write_quantized(
+            "tests/outputs/teapot-face-index.a3dq",
+            &sample_teapot_geometry,
+            &QuantizationParams::default(),
+        )
+        .unwrap();
+        let mut bytes = std::fs::read("tests/outputs/teapot-face-index.a3dq").unwrap();
+
+        // The last face index ends the file.
+        let end = bytes.len();
+        bytes[end - 4..]
+            .copy_from_slice(&(sample_teapot_geometry.len_vertices() as u32).to_le_bytes());
+        std::fs::write("tests/outputs/teapot-face-index.a3dq", bytes).unwrap();
+        assert!(matches!(
+            read_quantized("tests/outputs/teapot-face-index.a3dq"),
+            Err(LoadError::ParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        let geom = Geometry {
+            points: ndarray::Array1::zeros(2),
+            colors: None,
+            normals: None,
+            faces: None,
+            texcoords: None,
+            metadata: None,
+        };
+        assert!(write_quantized(
+            "tests/outputs/invalid-quantized.a3dq",
+            &geom,
+            &QuantizationParams {
+                position_bits: 32,
+                ..Default::default()
+            }
+        )
+        .is_err());
+    }
+}
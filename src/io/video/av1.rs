@@ -0,0 +1,189 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use image::RgbImage;
+use rav1e::prelude::{
+    ChromaSampling, Config, Context, EncoderConfig, EncoderStatus, FrameType, PixelRange, Rational,
+};
+
+use super::{check_frame_size, rgb_to_yuv420, VideoEncoder, WebmMuxer};
+
+/// Parameters of the [`Av1Encoder`].
+#[derive(Debug, Clone, Copy)]
+pub struct Av1Params {
+    /// Encoding speed, from 0 (slowest, smallest files) to 10 (fastest).
+    pub speed: u8,
+    /// Base quantizer, from 0 (lossless) to 255 (lowest quality).
+    pub quantizer: usize,
+    /// Maximum number of frames between keyframes, i.e., the seeking granularity.
+    pub max_key_frame_interval: u64,
+}
+
+impl Default for Av1Params {
+    /// Fast encoding with good quality, and a keyframe every 8 seconds at 30 fps.
+    fn default() -> Self {
+        Self {
+            speed: 9,
+            quantizer: 100,
+            max_key_frame_interval: 240,
+        }
+    }
+}
+
+fn encoder_error(status: EncoderStatus) -> std::io::Error {
+    std::io::Error::other(format!("AV1 encoder error: {status}"))
+}
+
+/// Encodes the frames with AV1 into a WebM video, which web browsers and common video
+/// players play. Uses rav1e, a pure Rust encoder, so it needs no system libraries.
+pub struct Av1Encoder<W: Write> {
+    context: Context<u8>,
+    muxer: WebmMuxer<W>,
+    width: u32,
+    height: u32,
+    fps: u32,
+}
+
+impl Av1Encoder<File> {
+    /// Creates a `.webm` file.
+    ///
+    /// # Arguments
+    ///
+    /// * `filepath` - Path to the output file.
+    /// * `width` - Width of the frames.
+    /// * `height` - Height of the frames.
+    /// * `fps` - Frame rate of the video.
+    /// * `params` - Speed and quality of the encoding.
+    pub fn create<P: AsRef<Path>>(
+        filepath: P,
+        width: u32,
+        height: u32,
+        fps: u32,
+        params: &Av1Params,
+    ) -> std::io::Result<Self> {
+        Self::new(File::create(filepath)?, width, height, fps, params)
+    }
+}
+
+impl<W: Write> Av1Encoder<W> {
+    /// Creates the encoder and writes the WebM header.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The output stream.
+    /// * Others - Same as [`Av1Encoder::create`].
+    pub fn new(
+        writer: W,
+        width: u32,
+        height: u32,
+        fps: u32,
+        params: &Av1Params,
+    ) -> std::io::Result<Self> {
+        let config = Config::new().with_encoder_config(EncoderConfig {
+            width: width as usize,
+            height: height as usize,
+            time_base: Rational::new(1, fps as u64),
+            bit_depth: 8,
+            chroma_sampling: ChromaSampling::Cs420,
+            pixel_range: PixelRange::Full,
+            quantizer: params.quantizer,
+            max_key_frame_interval: params.max_key_frame_interval,
+            ..EncoderConfig::with_speed_preset(params.speed)
+        });
+        let context = config.new_context::<u8>().map_err(|err| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid AV1 encoder configuration: {err}"),
+            )
+        })?;
+        let muxer = WebmMuxer::new(
+            writer,
+            "V_AV1",
+            &context.container_sequence_header(),
+            width,
+            height,
+        )?;
+        Ok(Self {
+            context,
+            muxer,
+            width,
+            height,
+            fps,
+        })
+    }
+
+    /// Muxes the packets that the encoder has ready.
+    fn write_packets(&mut self) -> std::io::Result<()> {
+        loop {
+            match self.context.receive_packet() {
+                Ok(packet) => self.muxer.write_frame(
+                    &packet.data,
+                    packet.input_frameno * 1000 / self.fps as u64,
+                    packet.frame_type == FrameType::KEY,
+                )?,
+                Err(EncoderStatus::Encoded) => (),
+                Err(EncoderStatus::NeedMoreData | EncoderStatus::LimitReached) => return Ok(()),
+                Err(status) => return Err(encoder_error(status)),
+            }
+        }
+    }
+}
+
+impl<W: Write> VideoEncoder for Av1Encoder<W> {
+    /// Converts the frame into full range YCbCr (BT.601) with 4:2:0 chroma subsampling and
+    /// encodes it. The encoder looks ahead, so the packets are written some frames later.
+    fn encode(&mut self, frame: &RgbImage) -> std::io::Result<()> {
+        check_frame_size(frame, self.width, self.height)?;
+        let planes = rgb_to_yuv420(frame);
+
+        let mut input = self.context.new_frame();
+        let strides = [self.width, self.width.div_ceil(2), self.width.div_ceil(2)];
+        for ((plane, data), stride) in input.planes.iter_mut().zip(planes.iter()).zip(strides) {
+            plane.copy_from_raw_u8(data, stride as usize, 1);
+        }
+        self.context.send_frame(input).map_err(encoder_error)?;
+        self.write_packets()
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.context.flush();
+        self.write_packets()?;
+        self.muxer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::{Av1Encoder, Av1Params};
+    use crate::io::video::{webm::tests::simple_blocks, VideoEncoder};
+
+    #[test]
+    fn test_av1_encoder() {
+        let params = Av1Params {
+            speed: 10,
+            ..Default::default()
+        };
+        let mut encoder =
+            Av1Encoder::create("tests/outputs/video-av1.webm", 66, 50, 30, &params).unwrap();
+        for i in 0..10u32 {
+            let frame = RgbImage::from_fn(66, 50, |x, y| {
+                Rgb([(x * 3 + i * 5) as u8, (y * 4) as u8, 128])
+            });
+            encoder.encode(&frame).unwrap();
+        }
+        assert!(encoder.encode(&RgbImage::new(64, 48)).is_err());
+        encoder.finish().unwrap();
+
+        let content = std::fs::read("tests/outputs/video-av1.webm").unwrap();
+        let blocks = simple_blocks(&content);
+        assert_eq!(blocks.len(), 10);
+        // The first frame is a keyframe.
+        assert_eq!(blocks[0][3], 0x80);
+
+        // Raw YUV would take 10 * 66 * 50 * 1.5 bytes.
+        assert!(content.len() < 10 * 66 * 50);
+    }
+}
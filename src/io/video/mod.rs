@@ -0,0 +1,169 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use image::RgbImage;
+
+mod webm;
+pub use webm::WebmMuxer;
+#[cfg(feature = "video")]
+mod av1;
+#[cfg(feature = "video")]
+pub use av1::{Av1Encoder, Av1Params};
+
+/// Receives a sequence of rendered frames and encodes them into a video.
+/// Implemented by [`Y4mWriter`] for raw videos and, with the `video` feature, by
+/// `Av1Encoder` for compressed WebM videos. Implement it to plug other codecs.
+pub trait VideoEncoder {
+    /// Encodes the next frame. All frames must have the same size.
+    fn encode(&mut self, frame: &RgbImage) -> std::io::Result<()>;
+
+    /// Finishes the video, flushing any pending data.
+    fn finish(&mut self) -> std::io::Result<()>;
+}
+
+/// Writes uncompressed YUV4MPEG2 (`.y4m`) videos. The format is played by common video
+/// players and is accepted as input by encoders like ffmpeg, e.g.,
+/// `ffmpeg -i run.y4m run.mp4`. The frames are full range, which the header declares with
+/// the `XCOLORRANGE=FULL` tag.
+pub struct Y4mWriter<W: Write> {
+    writer: BufWriter<W>,
+    width: u32,
+    height: u32,
+}
+
+impl Y4mWriter<File> {
+    /// Creates a `.y4m` file.
+    ///
+    /// # Arguments
+    ///
+    /// * `filepath` - Path to the output file.
+    /// * `width` - Width of the frames.
+    /// * `height` - Height of the frames.
+    /// * `fps` - Frame rate of the video.
+    pub fn create<P: AsRef<Path>>(
+        filepath: P,
+        width: u32,
+        height: u32,
+        fps: u32,
+    ) -> std::io::Result<Self> {
+        Self::new(File::create(filepath)?, width, height, fps)
+    }
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Creates the writer and writes the stream header.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The output stream.
+    /// * `width` - Width of the frames.
+    /// * `height` - Height of the frames.
+    /// * `fps` - Frame rate of the video.
+    pub fn new(writer: W, width: u32, height: u32, fps: u32) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(writer);
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{width} H{height} F{fps}:1 Ip A1:1 C420jpeg XCOLORRANGE=FULL"
+        )?;
+        Ok(Self {
+            writer,
+            width,
+            height,
+        })
+    }
+}
+
+/// Checks that a frame has the size of the video.
+fn check_frame_size(frame: &RgbImage, width: u32, height: u32) -> std::io::Result<()> {
+    if frame.dimensions() != (width, height) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "Frame size {:?} differs from the video size {:?}",
+                frame.dimensions(),
+                (width, height)
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Converts a frame into full range YCbCr (BT.601) with 4:2:0 chroma subsampling.
+///
+/// # Returns
+///
+/// The Y, Cb and Cr planes, row-major. The chroma planes have half the size of the frame,
+/// rounded up.
+fn rgb_to_yuv420(frame: &RgbImage) -> [Vec<u8>; 3] {
+    let (width, height) = frame.dimensions();
+    let luma = frame
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b] = pixel.0.map(f32::from);
+            (0.299 * r + 0.587 * g + 0.114 * b).round() as u8
+        })
+        .collect::<Vec<_>>();
+
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let mut cb = Vec::with_capacity((chroma_width * chroma_height) as usize);
+    let mut cr = Vec::with_capacity((chroma_width * chroma_height) as usize);
+    for chroma_y in 0..chroma_height {
+        for chroma_x in 0..chroma_width {
+            let (mut r, mut g, mut b, mut count) = (0.0, 0.0, 0.0, 0.0);
+            for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let (x, y) = (chroma_x * 2 + x, chroma_y * 2 + y);
+                if x < width && y < height {
+                    let [pr, pg, pb] = frame.get_pixel(x, y).0.map(f32::from);
+                    (r, g, b, count) = (r + pr, g + pg, b + pb, count + 1.0);
+                }
+            }
+            let (r, g, b) = (r / count, g / count, b / count);
+            cb.push((128.0 - 0.168_736 * r - 0.331_264 * g + 0.5 * b).round() as u8);
+            cr.push((128.0 + 0.5 * r - 0.418_688 * g - 0.081_312 * b).round() as u8);
+        }
+    }
+
+    [luma, cb, cr]
+}
+
+impl<W: Write> VideoEncoder for Y4mWriter<W> {
+    /// Converts the frame into full range YCbCr (BT.601) with 4:2:0 chroma subsampling.
+    fn encode(&mut self, frame: &RgbImage) -> std::io::Result<()> {
+        check_frame_size(frame, self.width, self.height)?;
+        let [luma, cb, cr] = rgb_to_yuv420(frame);
+
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&luma)?;
+        self.writer.write_all(&cb)?;
+        self.writer.write_all(&cr)
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::{VideoEncoder, Y4mWriter};
+
+    #[test]
+    fn test_y4m_writer() {
+        let mut writer = Y4mWriter::create("tests/outputs/video.y4m", 5, 3, 30).unwrap();
+        for i in 0..4u8 {
+            let frame = RgbImage::from_fn(5, 3, |x, _| Rgb([i * 60, x as u8 * 50, 255]));
+            writer.encode(&frame).unwrap();
+        }
+        assert!(writer.encode(&RgbImage::new(4, 4)).is_err());
+        writer.finish().unwrap();
+
+        let header = "YUV4MPEG2 W5 H3 F30:1 Ip A1:1 C420jpeg XCOLORRANGE=FULL\n";
+        let frame_size = "FRAME\n".len() + 5 * 3 + 2 * (3 * 2);
+        let content = std::fs::read("tests/outputs/video.y4m").unwrap();
+        assert!(content.starts_with(header.as_bytes()));
+        assert_eq!(content.len(), header.len() + 4 * frame_size);
+    }
+}
@@ -0,0 +1,339 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const EBML: u32 = 0x1A45_DFA3;
+const EBML_VERSION: u32 = 0x4286;
+const EBML_READ_VERSION: u32 = 0x42F7;
+const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+const DOC_TYPE: u32 = 0x4282;
+const DOC_TYPE_VERSION: u32 = 0x4287;
+const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+const SEGMENT: u32 = 0x1853_8067;
+const INFO: u32 = 0x1549_A966;
+const TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+const MUXING_APP: u32 = 0x4D80;
+const WRITING_APP: u32 = 0x5741;
+const TRACKS: u32 = 0x1654_AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_UID: u32 = 0x73C5;
+const TRACK_TYPE: u32 = 0x83;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const CLUSTER: u32 = 0x1F43_B675;
+const TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+
+/// Size of an element whose end is only known when the file ends.
+const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+/// Appends an element ID, which already carries its length marker.
+fn push_id(buffer: &mut Vec<u8>, id: u32) {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+    buffer.extend_from_slice(&bytes[skip..]);
+}
+
+/// Appends an element size as a variable length integer of the shortest length.
+fn push_size(buffer: &mut Vec<u8>, size: u64) {
+    // All ones is reserved for the unknown size.
+    let length = (1..8)
+        .find(|length| size < (1 << (7 * length)) - 1)
+        .unwrap_or(8);
+    let value = size | (1 << (7 * length));
+    buffer.extend_from_slice(&value.to_be_bytes()[8 - length..]);
+}
+
+fn push_element(buffer: &mut Vec<u8>, id: u32, payload: &[u8]) {
+    push_id(buffer, id);
+    push_size(buffer, payload.len() as u64);
+    buffer.extend_from_slice(payload);
+}
+
+fn push_uint(buffer: &mut Vec<u8>, id: u32, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count().min(7);
+    push_element(buffer, id, &bytes[skip..]);
+}
+
+/// Writes encoded video frames into a WebM (Matroska) file with a single video track.
+/// It only muxes: the frames must be already compressed with a codec supported by WebM,
+/// e.g., AV1 by `Av1Encoder`.
+///
+/// Frames are grouped into clusters that start at the keyframes, and the segment size is
+/// left unknown, so the file is written in a single pass.
+pub struct WebmMuxer<W: Write> {
+    writer: BufWriter<W>,
+    cluster: Vec<u8>,
+    cluster_time: u64,
+    last_time: Option<u64>,
+}
+
+impl WebmMuxer<File> {
+    /// Creates a `.webm` file.
+    ///
+    /// # Arguments
+    ///
+    /// * `filepath` - Path to the output file.
+    /// * `codec_id` - Matroska codec ID of the frames, e.g., `V_AV1`.
+    /// * `codec_private` - Codec initialization data, e.g., the `av1C` record for AV1.
+    /// * `width` - Width of the frames.
+    /// * `height` - Height of the frames.
+    pub fn create<P: AsRef<Path>>(
+        filepath: P,
+        codec_id: &str,
+        codec_private: &[u8],
+        width: u32,
+        height: u32,
+    ) -> std::io::Result<Self> {
+        Self::new(
+            File::create(filepath)?,
+            codec_id,
+            codec_private,
+            width,
+            height,
+        )
+    }
+}
+
+impl<W: Write> WebmMuxer<W> {
+    /// Creates the muxer and writes the file header and track description.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The output stream.
+    /// * Others - Same as [`WebmMuxer::create`].
+    pub fn new(
+        writer: W,
+        codec_id: &str,
+        codec_private: &[u8],
+        width: u32,
+        height: u32,
+    ) -> std::io::Result<Self> {
+        let mut header = Vec::new();
+        let mut ebml = Vec::new();
+        push_uint(&mut ebml, EBML_VERSION, 1);
+        push_uint(&mut ebml, EBML_READ_VERSION, 1);
+        push_uint(&mut ebml, EBML_MAX_ID_LENGTH, 4);
+        push_uint(&mut ebml, EBML_MAX_SIZE_LENGTH, 8);
+        push_element(&mut ebml, DOC_TYPE, b"webm");
+        push_uint(&mut ebml, DOC_TYPE_VERSION, 4);
+        push_uint(&mut ebml, DOC_TYPE_READ_VERSION, 2);
+        push_element(&mut header, EBML, &ebml);
+
+        push_id(&mut header, SEGMENT);
+        header.extend_from_slice(&UNKNOWN_SIZE);
+
+        // Timestamps in milliseconds.
+        let mut info = Vec::new();
+        push_uint(&mut info, TIMESTAMP_SCALE, 1_000_000);
+        push_element(&mut info, MUXING_APP, b"align3d");
+        push_element(&mut info, WRITING_APP, b"align3d");
+        push_element(&mut header, INFO, &info);
+
+        let mut video = Vec::new();
+        push_uint(&mut video, PIXEL_WIDTH, width as u64);
+        push_uint(&mut video, PIXEL_HEIGHT, height as u64);
+        let mut track = Vec::new();
+        push_uint(&mut track, TRACK_NUMBER, 1);
+        push_uint(&mut track, TRACK_UID, 1);
+        push_uint(&mut track, TRACK_TYPE, 1);
+        push_element(&mut track, CODEC_ID, codec_id.as_bytes());
+        if !codec_private.is_empty() {
+            push_element(&mut track, CODEC_PRIVATE, codec_private);
+        }
+        push_element(&mut track, VIDEO, &video);
+        let mut tracks = Vec::new();
+        push_element(&mut tracks, TRACK_ENTRY, &track);
+        push_element(&mut header, TRACKS, &tracks);
+
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(&header)?;
+        Ok(Self {
+            writer,
+            cluster: Vec::new(),
+            cluster_time: 0,
+            last_time: None,
+        })
+    }
+
+    /// Adds a frame to the video.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The compressed frame.
+    /// * `time` - Presentation time of the frame in milliseconds, not smaller than the
+    ///   previous one.
+    /// * `keyframe` - Whether the frame is decodable without the previous ones.
+    ///
+    /// # Returns
+    ///
+    /// * An `InvalidInput` error if the time is smaller than the previous one.
+    pub fn write_frame(&mut self, data: &[u8], time: u64, keyframe: bool) -> std::io::Result<()> {
+        if let Some(last_time) = self.last_time.filter(|last_time| time < *last_time) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Frame time {time} is before the previous frame time {last_time}"),
+            ));
+        }
+        self.last_time = Some(time);
+
+        // Block times are 16 bits relative to their cluster.
+        if self.cluster.is_empty() || keyframe || time - self.cluster_time > i16::MAX as u64 {
+            self.write_cluster()?;
+            self.cluster_time = time;
+            push_uint(&mut self.cluster, TIMESTAMP, time);
+        }
+        let offset = i16::try_from(time - self.cluster_time)
+            .expect("A new cluster starts when the time offset exceeds i16");
+
+        let mut block = Vec::with_capacity(data.len() + 4);
+        // Track number 1 as a variable length integer.
+        block.push(0x81);
+        block.extend_from_slice(&offset.to_be_bytes());
+        block.push(if keyframe { 0x80 } else { 0 });
+        block.extend_from_slice(data);
+        push_element(&mut self.cluster, SIMPLE_BLOCK, &block);
+        Ok(())
+    }
+
+    /// Writes the last cluster and flushes the output.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        self.write_cluster()?;
+        self.writer.flush()
+    }
+
+    fn write_cluster(&mut self) -> std::io::Result<()> {
+        if self.cluster.is_empty() {
+            return Ok(());
+        }
+        let mut header = Vec::new();
+        push_id(&mut header, CLUSTER);
+        push_size(&mut header, self.cluster.len() as u64);
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&self.cluster)?;
+        self.cluster.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::{WebmMuxer, CLUSTER, EBML, SEGMENT, SIMPLE_BLOCK, TIMESTAMP, TRACKS};
+
+    /// Reads a variable length integer, keeping the length marker for IDs.
+    fn read_vint(bytes: &[u8], keep_marker: bool) -> (u64, usize) {
+        let length = bytes[0].leading_zeros() as usize + 1;
+        let value = bytes[..length]
+            .iter()
+            .fold(0, |value, byte| (value << 8) | *byte as u64);
+        if keep_marker {
+            (value, length)
+        } else {
+            (value & !(1 << (7 * length)), length)
+        }
+    }
+
+    /// Parses the elements of an EBML buffer, descending into the segment and clusters.
+    ///
+    /// # Returns
+    ///
+    /// The ID and payload of each element, in file order.
+    fn parse_elements(bytes: &[u8]) -> Vec<(u32, Vec<u8>)> {
+        let mut elements = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let (id, id_length) = read_vint(&bytes[offset..], true);
+            let (size, size_length) = read_vint(&bytes[offset + id_length..], false);
+            let start = offset + id_length + size_length;
+            let end = if size == (1 << (7 * size_length)) - 1 {
+                bytes.len()
+            } else {
+                start + size as usize
+            };
+            let payload = &bytes[start..end];
+            elements.push((id as u32, payload.to_vec()));
+            if id as u32 == SEGMENT || id as u32 == CLUSTER {
+                elements.extend(parse_elements(payload));
+            }
+            offset = end;
+        }
+        elements
+    }
+
+    /// The payloads of the simple blocks of a WebM file.
+    pub fn simple_blocks(bytes: &[u8]) -> Vec<Vec<u8>> {
+        parse_elements(bytes)
+            .into_iter()
+            .filter(|(id, _)| *id == SIMPLE_BLOCK)
+            .map(|(_, block)| block)
+            .collect()
+    }
+
+    #[test]
+    fn test_webm_muxer() {
+        let mut muxer =
+            WebmMuxer::create("tests/outputs/video.webm", "V_AV1", &[1, 2, 3], 64, 48).unwrap();
+        for (time, keyframe) in [
+            (0, true),
+            (33, false),
+            (66, false),
+            (100, true),
+            (40000, false),
+        ] {
+            muxer
+                .write_frame(&[time as u8; 10], time, keyframe)
+                .unwrap();
+        }
+        assert_eq!(
+            muxer
+                .write_frame(&[0; 10], 39999, false)
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+        muxer.finish().unwrap();
+
+        let elements = parse_elements(&std::fs::read("tests/outputs/video.webm").unwrap());
+        let ids = elements.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        assert_eq!(ids[..2], [EBML, SEGMENT]);
+        assert!(ids.contains(&TRACKS));
+        assert_eq!(ids.iter().filter(|id| **id == CLUSTER).count(), 3);
+
+        let blocks = simple_blocks(&std::fs::read("tests/outputs/video.webm").unwrap())
+            .iter()
+            .map(|block| {
+                (
+                    i16::from_be_bytes([block[1], block[2]]),
+                    block[3] == 0x80,
+                    block.len(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            [
+                (0, true, 14),
+                (33, false, 14),
+                (66, false, 14),
+                (0, true, 14),
+                (0, false, 14)
+            ]
+        );
+        let cluster_times = elements
+            .iter()
+            .filter(|(id, _)| *id == TIMESTAMP)
+            .map(|(_, payload)| {
+                payload
+                    .iter()
+                    .fold(0, |value, byte| value << 8 | *byte as u64)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(cluster_times, [0, 100, 40000]);
+    }
+}
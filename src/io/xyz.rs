@@ -0,0 +1,179 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use nalgebra::Vector3;
+use ndarray::Array1;
+
+use super::LoadError;
+use crate::pointcloud::PointCloud;
+
+const DELIMITERS: [char; 4] = [',', ';', '\t', ' '];
+
+/// Finds the delimiter of a data line, preferring the explicit separators over spaces.
+fn sniff_delimiter(line: &str) -> char {
+    DELIMITERS
+        .into_iter()
+        .find(|delimiter| line.contains(*delimiter))
+        .unwrap_or(' ')
+}
+
+fn split_fields(line: &str, delimiter: char) -> Vec<&str> {
+    line.split(delimiter)
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .collect()
+}
+
+/// Reads a point cloud from ASCII point files (`.xyz`, `.pts`, `.txt`), one point
+/// per line as `x y z [i] [r g b]`.
+///
+/// The reader is tolerant to the variations found in the wild:
+/// * The delimiter (comma, semicolon, tab or spaces) is detected from the first point.
+/// * Lines that do not start with a number (headers, `#` or `//` comments) are skipped,
+///   as well as the point count line of `.pts` files.
+/// * The column layout is given by the first point: 3 (xyz), 4 (xyz + intensity),
+///   6 (xyz + rgb) or 7 (xyz + intensity + rgb). Extra columns are ignored.
+/// * Colors in the [0, 1] range are scaled to [0, 255]. When the file only has intensities,
+///   they are also normalized into gray colors.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the file.
+///
+/// # Returns
+///
+/// The point cloud with colors, if the file has intensity or color columns, and
+/// intensities, if it has an intensity column.
+pub fn read_xyz(filepath: &str) -> Result<PointCloud, LoadError> {
+    let reader = BufReader::new(File::open(filepath)?);
+
+    let mut layout: Option<(char, usize)> = None;
+    let mut points = Vec::new();
+    let mut intensities = Vec::new();
+    let mut colors = Vec::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+
+        let delimiter = layout.map_or_else(|| sniff_delimiter(line), |(delimiter, _)| delimiter);
+        let fields = split_fields(line, delimiter);
+        let Some(Ok(_)) = fields.first().map(|field| field.parse::<f64>()) else {
+            // Header or comment line.
+            continue;
+        };
+
+        let num_columns = match layout {
+            Some((_, num_columns)) => num_columns,
+            // Point count line of .pts files.
+            None if fields.len() < 3 => continue,
+            None => {
+                layout = Some((delimiter, fields.len()));
+                fields.len()
+            }
+        };
+
+        let values = fields
+            .iter()
+            .take(num_columns.min(7))
+            .map(|field| field.parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+            .filter(|values| values.len() == num_columns.min(7))
+            .ok_or_else(|| {
+                LoadError::ParseError(format!(
+                    "{}:{}: expected {} columns, got `{line}`",
+                    filepath,
+                    line_number + 1,
+                    num_columns
+                ))
+            })?;
+
+        points.push(Vector3::new(values[0], values[1], values[2]));
+        match values.len() {
+            4 => intensities.push(values[3]),
+            6 => colors.push(Vector3::new(values[3], values[4], values[5])),
+            7 => {
+                intensities.push(values[3]);
+                colors.push(Vector3::new(values[4], values[5], values[6]));
+            }
+            _ => {}
+        }
+    }
+
+    let colors = if !colors.is_empty() {
+        let scale = if colors.iter().all(|color| color.max() <= 1.0) {
+            255.0
+        } else {
+            1.0
+        };
+        Some(
+            colors
+                .iter()
+                .map(|color| color.map(|c| (c * scale).round().clamp(0.0, 255.0) as u8))
+                .collect(),
+        )
+    } else if !intensities.is_empty() {
+        let (min, max) = intensities
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), i| {
+                (min.min(*i), max.max(*i))
+            });
+        let range = if max > min { max - min } else { 1.0 };
+        Some(
+            intensities
+                .iter()
+                .map(|i| {
+                    let gray = ((i - min) / range * 255.0).round() as u8;
+                    Vector3::new(gray, gray, gray)
+                })
+                .collect(),
+        )
+    } else {
+        None
+    };
+
+    Ok(PointCloud {
+        points: Array1::from_vec(points),
+        normals: None,
+        colors,
+        intensities: (!intensities.is_empty()).then(|| Array1::from_vec(intensities)),
+        curvatures: None,
+        attributes: Default::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::read_xyz;
+
+    #[test]
+    fn test_read_xyz() {
+        std::fs::write(
+            "tests/outputs/points.pts",
+            "3\n1.0 2.0 3.0 -100 255 0 0\n\n4.0  5.0 6.0 0 0 128 0\n7 8 9 100 0 0 64\n",
+        )
+        .unwrap();
+        let pcl = read_xyz("tests/outputs/points.pts").unwrap();
+        assert_eq!(pcl.len(), 3);
+        assert_eq!(pcl.points[1], Vector3::new(4.0, 5.0, 6.0));
+        assert_eq!(pcl.colors.unwrap()[2], Vector3::new(0, 0, 64));
+
+        std::fs::write(
+            "tests/outputs/points.txt",
+            "x;y;z;intensity\n0.5;1.5;2.5;0.0\n1.5;2.5;3.5;1.0\n",
+        )
+        .unwrap();
+        let pcl = read_xyz("tests/outputs/points.txt").unwrap();
+        assert_eq!(pcl.points[1], Vector3::new(1.5, 2.5, 3.5));
+        assert_eq!(pcl.colors.unwrap()[1], Vector3::new(255, 255, 255));
+
+        std::fs::write("tests/outputs/points.xyz", "1,2,3\n4,5\n").unwrap();
+        assert!(read_xyz("tests/outputs/points.xyz").is_err());
+    }
+}
@@ -1,5 +1,11 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
 use nalgebra::Vector3;
 use ndarray::prelude::*;
+use rayon::prelude::*;
+
+mod dynamic;
+pub use dynamic::DynamicR3dTree;
 
 enum Node {
     Leaf {
@@ -13,6 +19,56 @@ enum Node {
     },
 }
 
+/// Settings of [`R3dTree::nearest_approximate`], trading accuracy for speed on large
+/// clouds. The default is an exact search.
+#[derive(Debug, Clone, Copy)]
+pub struct ApproximateSearch {
+    /// The found neighbor is at most `1 + epsilon` times farther than the nearest one.
+    /// Larger values prune more nodes.
+    pub epsilon: f32,
+    /// Maximum number of leaves visited, the search returns the best neighbor found so far
+    /// once reached. 1 is the single descent of [`R3dTree::nearest`].
+    pub max_leaves: usize,
+}
+
+impl Default for ApproximateSearch {
+    fn default() -> Self {
+        Self {
+            epsilon: 0.0,
+            max_leaves: usize::MAX,
+        }
+    }
+}
+
+/// A neighbor found by [`R3dTree::knn`], ordered by squared distance so that the heap of
+/// candidates keeps the farthest on top.
+struct Candidate {
+    distance: f32,
+    index: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.index.cmp(&other.index))
+    }
+}
+
 /// KdTree for fast nearest neighbor search.
 pub struct R3dTree {
     root: Box<Node>,
@@ -103,19 +159,253 @@ impl R3dTree {
             }
         }
     }
+
+    /// Finds the nearest neighbor of a query point by backtracking like [`R3dTree::knn`],
+    /// but skipping the nodes that can't bring a neighbor much closer and stopping after a
+    /// budget of leaves.
+    ///
+    /// # Arguments
+    ///
+    /// * point - The query point.
+    /// * search - The approximation settings.
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the index of the neighbor and the squared distance to it.
+    pub fn nearest_approximate(
+        &self,
+        point: &Vector3<f32>,
+        search: &ApproximateSearch,
+    ) -> (usize, f32) {
+        struct State {
+            sqr_scale: f32,
+            max_leaves: usize,
+            visited_leaves: usize,
+            best: (usize, f32),
+        }
+
+        fn search_node(node: &Node, point: &Vector3<f32>, dim: usize, state: &mut State) {
+            match node {
+                Node::NonLeaf {
+                    middle_value,
+                    left,
+                    right,
+                } => {
+                    let diff = point[dim] - middle_value;
+                    let (near, far) = if diff < 0.0 {
+                        (left, right)
+                    } else {
+                        (right, left)
+                    };
+                    search_node(near, point, (dim + 1) % 3, state);
+                    if state.visited_leaves < state.max_leaves
+                        && diff * diff * state.sqr_scale < state.best.1
+                    {
+                        search_node(far, point, (dim + 1) % 3, state);
+                    }
+                }
+                Node::Leaf { points, indices } => {
+                    state.visited_leaves += 1;
+                    for (leaf_point, index) in points.iter().zip(indices.iter()) {
+                        let distance = (point - leaf_point).norm_squared();
+                        if distance < state.best.1 {
+                            state.best = (*index, distance);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut state = State {
+            sqr_scale: (1.0 + search.epsilon.max(0.0)).powi(2),
+            max_leaves: search.max_leaves.max(1),
+            visited_leaves: 0,
+            best: (0, f32::INFINITY),
+        };
+        search_node(&self.root, point, 0, &mut state);
+        state.best
+    }
+
+    /// Same as [`R3dTree::nearest`] for many query points, searched in parallel by chunks
+    /// of queries, e.g., the correspondences of all the source points of an ICP iteration.
+    ///
+    /// # Arguments
+    ///
+    /// * queries - The query points, one per row of shape (N, 3).
+    ///
+    /// # Returns
+    ///
+    /// The index of the nearest neighbor of each query and the squared distance to it.
+    pub fn batch_nearest(&self, queries: &Array2<f32>) -> (Array1<usize>, Array1<f32>) {
+        // Large enough to amortize the scheduling of each task.
+        const CHUNK_SIZE: usize = 1024;
+        let (indices, distances): (Vec<_>, Vec<_>) = queries
+            .axis_chunks_iter(Axis(0), CHUNK_SIZE)
+            .into_par_iter()
+            .flat_map_iter(|chunk| {
+                chunk
+                    .rows()
+                    .into_iter()
+                    .map(|query| self.nearest(&Vector3::new(query[0], query[1], query[2])))
+                    .collect::<Vec<_>>()
+            })
+            .unzip();
+        (Array1::from_vec(indices), Array1::from_vec(distances))
+    }
+
+    /// Finds the exact `k` nearest neighbors of a query point, backtracking into the
+    /// nodes that may contain closer points. The best candidates are kept in a heap
+    /// bounded to `k`, so large `k` stay cheap.
+    ///
+    /// # Arguments
+    ///
+    /// * point - The query point.
+    /// * k - The number of neighbors.
+    ///
+    /// # Returns
+    ///
+    /// Up to `k` tuples with the index and squared distance of the neighbors, from the
+    /// closest to the farthest.
+    pub fn knn(&self, point: &Vector3<f32>, k: usize) -> Vec<(usize, f32)> {
+        fn search(
+            node: &Node,
+            point: &Vector3<f32>,
+            dim: usize,
+            k: usize,
+            found: &mut BinaryHeap<Candidate>,
+        ) {
+            // Squared distance of the farthest kept candidate, once there are k of them.
+            let bound = |found: &BinaryHeap<Candidate>| {
+                if found.len() < k {
+                    f32::INFINITY
+                } else {
+                    found
+                        .peek()
+                        .map_or(f32::INFINITY, |farthest| farthest.distance)
+                }
+            };
+            match node {
+                Node::NonLeaf {
+                    middle_value,
+                    left,
+                    right,
+                } => {
+                    let diff = point[dim] - middle_value;
+                    let (near, far) = if diff < 0.0 {
+                        (left, right)
+                    } else {
+                        (right, left)
+                    };
+                    search(near, point, (dim + 1) % 3, k, found);
+                    if diff * diff < bound(found) {
+                        search(far, point, (dim + 1) % 3, k, found);
+                    }
+                }
+                Node::Leaf { points, indices } => {
+                    for (leaf_point, index) in points.iter().zip(indices.iter()) {
+                        let distance = (point - leaf_point).norm_squared();
+                        if distance < bound(found) {
+                            if found.len() == k {
+                                found.pop();
+                            }
+                            found.push(Candidate {
+                                distance,
+                                index: *index,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut found = BinaryHeap::with_capacity(k + 1);
+        if k > 0 {
+            search(&self.root, point, 0, k, &mut found);
+        }
+        found
+            .into_sorted_vec()
+            .into_iter()
+            .map(|candidate| (candidate.index, candidate.distance))
+            .collect()
+    }
+
+    /// Finds all the points within a radius of a query point, e.g., the neighborhood of a
+    /// point for normal estimation, outlier removal or clustering.
+    ///
+    /// # Arguments
+    ///
+    /// * point - The query point.
+    /// * radius - The search radius, inclusive.
+    ///
+    /// # Returns
+    ///
+    /// Tuples with the index and squared distance of the neighbors, in no particular order.
+    /// See [`R3dTree::radius_search_sorted`] to get them from the closest.
+    pub fn radius_search(&self, point: &Vector3<f32>, radius: f32) -> Vec<(usize, f32)> {
+        fn search(
+            node: &Node,
+            point: &Vector3<f32>,
+            dim: usize,
+            sqr_radius: f32,
+            found: &mut Vec<(usize, f32)>,
+        ) {
+            match node {
+                Node::NonLeaf {
+                    middle_value,
+                    left,
+                    right,
+                } => {
+                    let diff = point[dim] - middle_value;
+                    let (near, far) = if diff < 0.0 {
+                        (left, right)
+                    } else {
+                        (right, left)
+                    };
+                    search(near, point, (dim + 1) % 3, sqr_radius, found);
+                    if diff * diff <= sqr_radius {
+                        search(far, point, (dim + 1) % 3, sqr_radius, found);
+                    }
+                }
+                Node::Leaf { points, indices } => {
+                    for (leaf_point, index) in points.iter().zip(indices.iter()) {
+                        let distance = (point - leaf_point).norm_squared();
+                        if distance <= sqr_radius {
+                            found.push((*index, distance));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut found = Vec::new();
+        if radius >= 0.0 {
+            search(&self.root, point, 0, radius * radius, &mut found);
+        }
+        found
+    }
+
+    /// Same as [`R3dTree::radius_search`], with the neighbors sorted from the closest to
+    /// the farthest, ties by index.
+    pub fn radius_search_sorted(&self, point: &Vector3<f32>, radius: f32) -> Vec<(usize, f32)> {
+        let mut found = self.radius_search(point, radius);
+        found.sort_by(|(lhs_index, lhs), (rhs_index, rhs)| {
+            lhs.total_cmp(rhs).then(lhs_index.cmp(rhs_index))
+        });
+        found
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::time::Instant;
 
-    use crate::kdtree::R3dTree;
+    use crate::kdtree::{ApproximateSearch, R3dTree};
     use crate::unit_test::access::UnflattenVector3;
     use nalgebra::Vector3;
     use ndarray::prelude::*;
     use rand::rngs::SmallRng;
     use rand::seq::SliceRandom;
-    use rand::SeedableRng;
+    use rand::{Rng, SeedableRng};
 
     #[test]
     fn should_find_nearest_points() {
@@ -169,6 +459,108 @@ mod tests {
         }
     }
 
+    #[test]
+    fn should_find_k_nearest_points() {
+        let mut rng = SmallRng::seed_from_u64(3);
+        let points = Array1::from_shape_fn(1000, |_| {
+            Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>())
+        });
+        let tree = R3dTree::new(&points.view());
+
+        for query in points.iter().step_by(100) {
+            let mut expected = points
+                .iter()
+                .enumerate()
+                .map(|(index, point)| (index, (point - query).norm_squared()))
+                .collect::<Vec<_>>();
+            expected.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+            expected.truncate(8);
+
+            assert_eq!(tree.knn(query, 8), expected);
+        }
+        assert!(tree.knn(&points[0], 0).is_empty());
+        let all = tree.knn(&points[0], 2000);
+        assert_eq!(all.len(), 1000);
+        assert!(all.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    }
+
+    #[test]
+    fn should_find_approximate_nearest_points() {
+        let mut rng = SmallRng::seed_from_u64(7);
+        let points = Array1::from_shape_fn(5000, |_| {
+            Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>())
+        });
+        let tree = R3dTree::new(&points.view());
+
+        let approximate = ApproximateSearch {
+            epsilon: 0.5,
+            ..Default::default()
+        };
+        let single_leaf = ApproximateSearch {
+            max_leaves: 1,
+            ..Default::default()
+        };
+        for _ in 0..200 {
+            let query = Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>());
+            let exact = tree.knn(&query, 1)[0];
+            assert_eq!(
+                tree.nearest_approximate(&query, &ApproximateSearch::default()),
+                exact
+            );
+            let (_, distance) = tree.nearest_approximate(&query, &approximate);
+            assert!(distance <= exact.1 * 1.5 * 1.5);
+            assert_eq!(
+                tree.nearest_approximate(&query, &single_leaf),
+                tree.nearest(&query)
+            );
+        }
+    }
+
+    #[test]
+    fn should_find_nearest_points_in_batch() {
+        let mut rng = SmallRng::seed_from_u64(5);
+        let points = Array1::from_shape_fn(2000, |_| {
+            Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>())
+        });
+        let tree = R3dTree::new(&points.view());
+        let queries = Array2::from_shape_fn((3000, 3), |_| rng.gen::<f32>());
+
+        let (indices, distances) = tree.batch_nearest(&queries);
+        assert_eq!(indices.len(), 3000);
+        for (row, query) in queries.rows().into_iter().enumerate() {
+            let (index, distance) = tree.nearest(&Vector3::new(query[0], query[1], query[2]));
+            assert_eq!((indices[row], distances[row]), (index, distance));
+        }
+        assert!(tree.batch_nearest(&Array2::zeros((0, 3))).0.is_empty());
+    }
+
+    #[test]
+    fn should_find_points_within_radius() {
+        let mut rng = SmallRng::seed_from_u64(3);
+        let points = Array1::from_shape_fn(1000, |_| {
+            Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>())
+        });
+        let tree = R3dTree::new(&points.view());
+
+        for query in points.iter().step_by(100) {
+            let expected = points
+                .iter()
+                .enumerate()
+                .map(|(index, point)| (index, (point - query).norm_squared()))
+                .filter(|(_, sqr_distance)| *sqr_distance <= 0.15 * 0.15)
+                .collect::<Vec<_>>();
+            let mut found = tree.radius_search(query, 0.15);
+            found.sort_by_key(|(index, _)| *index);
+            assert_eq!(found, expected);
+        }
+        assert!(tree.radius_search(&points[0], -1.0).is_empty());
+
+        let sorted = tree.radius_search_sorted(&points[0], 0.15);
+        assert_eq!(sorted.len(), tree.radius_search(&points[0], 0.15).len());
+        assert_eq!(sorted[0], (0, 0.0));
+        assert!(sorted.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    }
+
     #[test]
     fn bench_nearest() {
         const N: usize = 500_000;
@@ -0,0 +1,254 @@
+use nalgebra::Vector3;
+use ndarray::Array1;
+
+use super::R3dTree;
+
+/// A static tree over some of the points, with the ids of its points.
+struct Bucket {
+    tree: R3dTree,
+    ids: Vec<usize>,
+    /// Number of its points removed since it was built.
+    removed: usize,
+}
+
+/// A k-d tree supporting insertion and removal of points, e.g., for a map whose points
+/// are added and freed at every frame.
+///
+/// It is a forest of static [`R3dTree`] whose sizes grow as powers of two: inserted points
+/// make a new tree, merged with the trees of similar size like the carries of a binary
+/// counter, so each point is rebuilt a logarithmic number of times. Removed points are
+/// skipped by the queries, and a tree is rebuilt once half of its points are removed.
+#[derive(Default)]
+pub struct DynamicR3dTree {
+    points: Vec<Vector3<f32>>,
+    alive: Vec<bool>,
+    /// Level of the bucket holding each point.
+    levels: Vec<usize>,
+    buckets: Vec<Option<Bucket>>,
+    len: usize,
+}
+
+/// Level of a bucket of `len` points, the number of bits of `len`.
+fn level_of(len: usize) -> usize {
+    (usize::BITS - len.leading_zeros()) as usize
+}
+
+impl DynamicR3dTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of points, excluding the removed ones.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The point of an id, `None` if it was removed or never inserted.
+    pub fn point(&self, id: usize) -> Option<&Vector3<f32>> {
+        self.alive
+            .get(id)
+            .copied()
+            .unwrap_or(false)
+            .then(|| &self.points[id])
+    }
+
+    /// Inserts a point.
+    ///
+    /// # Returns
+    ///
+    /// The id of the point, used by the queries and [`DynamicR3dTree::remove`]. Ids
+    /// aren't reused after removal.
+    pub fn insert(&mut self, point: Vector3<f32>) -> usize {
+        self.extend([point]).start
+    }
+
+    /// Inserts points at once, cheaper than inserting them one by one.
+    ///
+    /// # Returns
+    ///
+    /// The ids of the points, consecutive in the insertion order.
+    pub fn extend(
+        &mut self,
+        points: impl IntoIterator<Item = Vector3<f32>>,
+    ) -> std::ops::Range<usize> {
+        let start = self.points.len();
+        self.points.extend(points);
+        let end = self.points.len();
+        self.alive.resize(end, true);
+        self.levels.resize(end, 0);
+        self.len += end - start;
+        if start < end {
+            self.add_bucket((start..end).collect());
+        }
+        start..end
+    }
+
+    /// Removes a point.
+    ///
+    /// # Returns
+    ///
+    /// Whether the point was in the tree.
+    pub fn remove(&mut self, id: usize) -> bool {
+        if !self.alive.get(id).copied().unwrap_or(false) {
+            return false;
+        }
+        self.alive[id] = false;
+        self.len -= 1;
+
+        let level = self.levels[id];
+        let bucket = self.buckets[level]
+            .as_mut()
+            .expect("The bucket of an alive point exists");
+        bucket.removed += 1;
+        if bucket.removed * 2 > bucket.ids.len() {
+            let bucket = self.buckets[level].take().expect("Checked above");
+            let ids = self.alive_ids(bucket.ids);
+            if !ids.is_empty() {
+                self.add_bucket(ids);
+            }
+        }
+        true
+    }
+
+    fn alive_ids(&self, mut ids: Vec<usize>) -> Vec<usize> {
+        ids.retain(|id| self.alive[*id]);
+        ids
+    }
+
+    /// Builds a tree of points, merging it with the trees of the same level.
+    fn add_bucket(&mut self, mut ids: Vec<usize>) {
+        let mut level = level_of(ids.len());
+        while let Some(bucket) = self.buckets.get_mut(level).and_then(Option::take) {
+            ids.extend(self.alive_ids(bucket.ids));
+            level = level_of(ids.len());
+        }
+        if self.buckets.len() <= level {
+            self.buckets.resize_with(level + 1, || None);
+        }
+        for id in &ids {
+            self.levels[*id] = level;
+        }
+        let points = ids.iter().map(|id| self.points[*id]).collect::<Array1<_>>();
+        self.buckets[level] = Some(Bucket {
+            tree: R3dTree::new(&points.view()),
+            ids,
+            removed: 0,
+        });
+    }
+
+    /// Finds the nearest neighbor of a query point.
+    ///
+    /// # Returns
+    ///
+    /// The id of the neighbor and the squared distance to it, `None` if the tree is empty.
+    pub fn nearest(&self, point: &Vector3<f32>) -> Option<(usize, f32)> {
+        self.knn(point, 1).first().copied()
+    }
+
+    /// Finds the exact `k` nearest neighbors of a query point, see [`R3dTree::knn`].
+    ///
+    /// # Returns
+    ///
+    /// Up to `k` tuples with the id and squared distance of the neighbors, from the
+    /// closest to the farthest.
+    pub fn knn(&self, point: &Vector3<f32>, k: usize) -> Vec<(usize, f32)> {
+        let mut found = self
+            .buckets
+            .iter()
+            .flatten()
+            .flat_map(|bucket| {
+                // Enough candidates to find k alive ones.
+                bucket
+                    .tree
+                    .knn(point, k + bucket.removed)
+                    .into_iter()
+                    .map(|(index, distance)| (bucket.ids[index], distance))
+                    .filter(|(id, _)| self.alive[*id])
+                    .take(k)
+            })
+            .collect::<Vec<_>>();
+        found.sort_by(|(lhs_id, lhs), (rhs_id, rhs)| lhs.total_cmp(rhs).then(lhs_id.cmp(rhs_id)));
+        found.truncate(k);
+        found
+    }
+
+    /// Finds all the points within a radius of a query point, see
+    /// [`R3dTree::radius_search`].
+    ///
+    /// # Returns
+    ///
+    /// Tuples with the id and squared distance of the neighbors, in no particular order.
+    pub fn radius_search(&self, point: &Vector3<f32>, radius: f32) -> Vec<(usize, f32)> {
+        self.buckets
+            .iter()
+            .flatten()
+            .flat_map(|bucket| {
+                bucket
+                    .tree
+                    .radius_search(point, radius)
+                    .into_iter()
+                    .map(|(index, distance)| (bucket.ids[index], distance))
+                    .filter(|(id, _)| self.alive[*id])
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::DynamicR3dTree;
+
+    #[test]
+    fn should_insert_and_remove_points() {
+        let mut rng = SmallRng::seed_from_u64(9);
+        let mut random_point =
+            move || Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>());
+        let mut tree = DynamicR3dTree::new();
+        assert!(tree.nearest(&Vector3::zeros()).is_none());
+
+        let mut points = Vec::new();
+        for _ in 0..300 {
+            let point = random_point();
+            assert_eq!(tree.insert(point), points.len());
+            points.push(point);
+        }
+        let ids = tree.extend((0..700).map(|_| random_point()));
+        assert_eq!(ids, 300..1000);
+        points.extend(ids.map(|id| *tree.point(id).unwrap()));
+
+        // Removes most of the points with an even id, to trigger rebuilds.
+        for id in (0..1000).step_by(2).take(400) {
+            assert!(tree.remove(id));
+        }
+        assert!(!tree.remove(0));
+        assert!(!tree.remove(5000));
+        assert_eq!(tree.len(), 600);
+        assert!(tree.point(0).is_none());
+
+        let alive = |id: &usize| *id % 2 == 1 || *id >= 800;
+        let query = Vector3::new(0.5, 0.5, 0.5);
+        let mut expected = (0..1000)
+            .filter(alive)
+            .map(|id| (id, (points[id] - query).norm_squared()))
+            .collect::<Vec<_>>();
+        expected.sort_by(|lhs, rhs| lhs.1.total_cmp(&rhs.1));
+        assert_eq!(tree.knn(&query, 10), expected[..10]);
+        assert_eq!(tree.nearest(&query), Some(expected[0]));
+
+        let mut within = tree.radius_search(&query, 0.2);
+        within.sort_by(|lhs, rhs| lhs.1.total_cmp(&rhs.1));
+        let expected_within = expected
+            .iter()
+            .take_while(|(_, sqr_distance)| *sqr_distance <= 0.2 * 0.2)
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(within, expected_within);
+    }
+}
@@ -0,0 +1,339 @@
+//! Keyframe selection and storage, shared by relocalization and loop closure.
+
+use crate::{
+    error::A3dError,
+    icp::IcpResult,
+    loopclosure::{DescriptorParams, FrameDescriptor},
+    range_image::RangeImage,
+    transform::Transform,
+};
+
+/// Parameters of [`Keyframes`].
+#[derive(Debug, Clone, Copy)]
+pub struct KeyframeParams {
+    /// Spawns a keyframe when the camera moved more than this from the last keyframe, in
+    /// meters.
+    pub max_translation: f32,
+    /// Spawns a keyframe when the camera rotated more than this from the last keyframe, in
+    /// radians.
+    pub max_rotation: f32,
+    /// Spawns a keyframe when less than this fraction of the frame points is seen by the
+    /// last keyframe.
+    pub min_overlap: f32,
+    /// Maximum depth difference of a frame point and the keyframe pixel it projects to,
+    /// to count as overlapping, in meters.
+    pub max_depth_difference: f32,
+    /// Spawns a keyframe when less than this fraction of the frame points are inliers of
+    /// its tracking, before the tracking is lost.
+    pub min_inlier_ratio: f32,
+    /// Parameters of the keyframe descriptors.
+    pub descriptor: DescriptorParams,
+}
+
+impl Default for KeyframeParams {
+    fn default() -> Self {
+        Self {
+            max_translation: 0.3,
+            max_rotation: 0.26,
+            min_overlap: 0.7,
+            max_depth_difference: 0.05,
+            min_inlier_ratio: 0.3,
+            descriptor: DescriptorParams::default(),
+        }
+    }
+}
+
+/// Why [`Keyframes::should_spawn`] asks for a new keyframe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyframeTrigger {
+    /// There is no keyframe yet.
+    First,
+    /// The camera moved or rotated too much from the last keyframe.
+    Motion,
+    /// The frame sees too little of the last keyframe.
+    Overlap,
+    /// The tracking has too few inliers.
+    TrackingQuality,
+}
+
+/// A frame kept to relocalize the camera and close loops.
+#[derive(Debug, Clone)]
+pub struct Keyframe {
+    /// Id of the keyframe, e.g., its frame index.
+    pub id: usize,
+    /// Camera to world transformation.
+    pub pose: Transform,
+    /// Range image pyramid, the finest level first.
+    pub pyramid: Vec<RangeImage>,
+    /// Global descriptor of the finest level.
+    pub descriptor: FrameDescriptor,
+}
+
+/// Decides when a new keyframe is needed and stores the keyframes.
+pub struct Keyframes {
+    /// Parameters of the keyframe selection.
+    pub params: KeyframeParams,
+    keyframes: Vec<Keyframe>,
+}
+
+impl Keyframes {
+    /// Creates a manager without keyframes.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters of the keyframe selection.
+    pub fn new(params: KeyframeParams) -> Self {
+        Self {
+            params,
+            keyframes: Vec::new(),
+        }
+    }
+
+    /// Number of keyframes.
+    pub fn len(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    /// Whether there are no keyframes.
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Iterates the keyframes in their insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = &Keyframe> {
+        self.keyframes.iter()
+    }
+
+    /// The most recent keyframe.
+    pub fn last(&self) -> Option<&Keyframe> {
+        self.keyframes.last()
+    }
+
+    /// The keyframe with an id.
+    pub fn get(&self, id: usize) -> Option<&Keyframe> {
+        self.keyframes.iter().find(|keyframe| keyframe.id == id)
+    }
+
+    /// Updates the pose of a keyframe, e.g., after the pose graph optimization.
+    ///
+    /// # Returns
+    ///
+    /// Whether the keyframe exists.
+    pub fn set_pose(&mut self, id: usize, pose: Transform) -> bool {
+        match self.keyframes.iter_mut().find(|keyframe| keyframe.id == id) {
+            Some(keyframe) => {
+                keyframe.pose = pose;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Fraction of the valid points of a frame that the last keyframe sees at the same
+    /// depth. The coarsest levels of both pyramids are compared.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The frame, with the same number of levels as the keyframes.
+    /// * `pose` - Camera to world transformation of the frame.
+    ///
+    /// # Returns
+    ///
+    /// The overlap in [0, 1], zero when there is no keyframe.
+    pub fn overlap(&self, image: &RangeImage, pose: &Transform) -> f32 {
+        let Some(keyframe) = self.last() else {
+            return 0.0;
+        };
+        let target = keyframe.pyramid.last().unwrap();
+        let frame_to_keyframe = &keyframe.pose.inverse() * pose;
+
+        let (mut overlapping, mut total) = (0, 0);
+        for (point, mask) in image.points.iter().zip(image.mask.iter()) {
+            if *mask == 0 {
+                continue;
+            }
+            total += 1;
+            let point = frame_to_keyframe.transform_vector(point);
+            let Some(target_point) = target
+                .project_to_pixel(&point)
+                .and_then(|(row, col)| target.get_point(row, col))
+            else {
+                continue;
+            };
+            if (target_point[2] - point[2]).abs() <= self.params.max_depth_difference {
+                overlapping += 1;
+            }
+        }
+        if total > 0 {
+            overlapping as f32 / total as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// Decides whether a tracked frame should become a keyframe.
+    ///
+    /// # Arguments
+    ///
+    /// * `pyramid` - The range image pyramid of the frame, the finest level first.
+    /// * `pose` - Camera to world transformation of the frame.
+    /// * `tracking` - The ICP result of the frame tracking, its inliers are compared with
+    ///   the valid points of the finest level.
+    ///
+    /// # Returns
+    ///
+    /// The reason to spawn a keyframe, `None` if the last keyframe still covers the frame.
+    pub fn should_spawn(
+        &self,
+        pyramid: &[RangeImage],
+        pose: &Transform,
+        tracking: &IcpResult,
+    ) -> Option<KeyframeTrigger> {
+        let Some(keyframe) = self.last() else {
+            return Some(KeyframeTrigger::First);
+        };
+
+        let motion = &keyframe.pose.inverse() * pose;
+        if motion.translation().norm() > self.params.max_translation
+            || motion.angle() > self.params.max_rotation
+        {
+            return Some(KeyframeTrigger::Motion);
+        }
+        if self.overlap(pyramid.last()?, pose) < self.params.min_overlap {
+            return Some(KeyframeTrigger::Overlap);
+        }
+        let valid_points = pyramid.first()?.valid_points_count().max(1);
+        if (tracking.inlier_count as f32) < self.params.min_inlier_ratio * valid_points as f32 {
+            return Some(KeyframeTrigger::TrackingQuality);
+        }
+        None
+    }
+
+    /// Adds a keyframe, computing its descriptor.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the keyframe, e.g., its frame index.
+    /// * `pose` - Camera to world transformation.
+    /// * `pyramid` - Range image pyramid, the finest level first, which must have
+    ///   intensities.
+    ///
+    /// # Returns
+    ///
+    /// The new keyframe, or error if the pyramid is empty or has no intensities.
+    pub fn insert(
+        &mut self,
+        id: usize,
+        pose: Transform,
+        pyramid: Vec<RangeImage>,
+    ) -> Result<&Keyframe, A3dError> {
+        let finest = pyramid
+            .first()
+            .ok_or_else(|| A3dError::invalid_parameter("The keyframe pyramid is empty."))?;
+        let descriptor = FrameDescriptor::from_range_image(finest, &self.params.descriptor)?;
+        self.keyframes.push(Keyframe {
+            id,
+            pose,
+            pyramid,
+            descriptor,
+        });
+        Ok(self.keyframes.last().unwrap())
+    }
+
+    /// Keyframes that may see the same place as a frame, for relocalization.
+    ///
+    /// # Arguments
+    ///
+    /// * `descriptor` - Descriptor of the frame.
+    /// * `max_distance` - Maximum descriptor distance, see [`FrameDescriptor::distance`].
+    ///
+    /// # Returns
+    ///
+    /// The keyframes with their descriptor distances, the closest first.
+    pub fn similar(
+        &self,
+        descriptor: &FrameDescriptor,
+        max_distance: f32,
+    ) -> Vec<(&Keyframe, f32)> {
+        let mut similar = self
+            .keyframes
+            .iter()
+            .map(|keyframe| (keyframe, descriptor.distance(&keyframe.descriptor)))
+            .filter(|(_, distance)| *distance <= max_distance)
+            .collect::<Vec<_>>();
+        similar.sort_by(|a, b| a.1.total_cmp(&b.1));
+        similar
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rstest::rstest;
+
+    use super::{KeyframeParams, KeyframeTrigger, Keyframes};
+    use crate::{
+        icp::{IcpResult, RejectionStats},
+        transform::{Transform, TransformBuilder},
+        unit_test::{sample_range_img_ds2, TestRangeImageDataset},
+    };
+
+    #[rstest]
+    fn test_should_spawn(sample_range_img_ds2: TestRangeImageDataset) {
+        let pyramid0 = sample_range_img_ds2.get(0).unwrap().pyramid(3, 1.0);
+        let pyramid1 = sample_range_img_ds2.get(1).unwrap().pyramid(3, 1.0);
+        let pose1 = sample_range_img_ds2.get_ground_truth(1, 0);
+        let tracking = IcpResult {
+            transform: pose1.clone(),
+            iterations: 5,
+            final_rmse: 0.01,
+            inlier_count: pyramid1[0].valid_points_count(),
+            converged: true,
+            rejection: RejectionStats::default(),
+            information: None,
+        };
+
+        let mut keyframes = Keyframes::new(KeyframeParams::default());
+        assert_eq!(
+            keyframes.should_spawn(&pyramid0, &Transform::eye(), &tracking),
+            Some(KeyframeTrigger::First)
+        );
+        keyframes.insert(0, Transform::eye(), pyramid0).unwrap();
+
+        assert!(keyframes.overlap(&pyramid1[2], &pose1) > 0.8);
+        assert_eq!(keyframes.should_spawn(&pyramid1, &pose1, &tracking), None);
+
+        let far = &TransformBuilder::default()
+            .translation(Vector3::new(0.5, 0.0, 0.0))
+            .build()
+            * &pose1;
+        assert_eq!(
+            keyframes.should_spawn(&pyramid1, &far, &tracking),
+            Some(KeyframeTrigger::Motion)
+        );
+
+        // A wrong depth alignment doesn't overlap.
+        let shifted = &TransformBuilder::default()
+            .translation(Vector3::new(0.0, 0.0, 0.2))
+            .build()
+            * &pose1;
+        assert_eq!(
+            keyframes.should_spawn(&pyramid1, &shifted, &tracking),
+            Some(KeyframeTrigger::Overlap)
+        );
+
+        let weak_tracking = IcpResult {
+            inlier_count: pyramid1[0].valid_points_count() / 10,
+            ..tracking.clone()
+        };
+        assert_eq!(
+            keyframes.should_spawn(&pyramid1, &pose1, &weak_tracking),
+            Some(KeyframeTrigger::TrackingQuality)
+        );
+
+        let descriptor = &keyframes.last().unwrap().descriptor.clone();
+        let similar = keyframes.similar(descriptor, 0.1);
+        assert_eq!(similar.len(), 1);
+        assert_eq!(similar[0].0.id, 0);
+    }
+}
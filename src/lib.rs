@@ -1,16 +1,36 @@
 pub mod bilateral;
+pub mod bounds;
+pub mod bvh;
 pub mod camera;
+pub mod convex_hull;
+pub mod edit;
+pub mod features;
 
 pub mod icp;
 mod intensity_map;
 pub mod io;
 pub mod kdtree;
+pub mod keyframes;
+pub mod lighting;
+pub mod live_config;
+pub mod loopclosure;
 
 pub mod mesh;
+pub mod octree;
+pub mod odometry;
+pub mod pipeline;
 pub mod pointcloud;
+pub mod prelude;
+pub mod random;
 pub mod range_image;
-mod sampling;
+pub mod reconstruction;
+pub mod sampling;
+pub mod segmentation;
+pub mod session;
+pub mod slicing;
+pub mod telemetry;
 pub mod transform;
+pub mod voxel_hash;
 
 pub mod error;
 pub mod trajectory;
@@ -22,8 +42,17 @@ mod unit_test;
 pub mod viz;
 
 mod extra_math;
+pub mod metadata;
 pub mod metrics;
-mod optim;
+pub mod optim;
+
+#[cfg(feature = "ros")]
+pub mod ros;
+
+#[cfg(feature = "serde")]
+mod serialization;
 
 mod image;
-pub use crate::image::{RgbdFrame, RgbdImage};
+pub use crate::image::{
+    ColorAccumulator, ColorFusionParams, ColorFusionPolicy, RgbdFrame, RgbdImage,
+};
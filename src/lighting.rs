@@ -0,0 +1,262 @@
+//! Low-order spherical harmonics (SH) lighting, used to separate the shading baked into
+//! the fused colors from the surface albedo, so models can be rendered under a common
+//! lighting when comparing geometry.
+
+use std::f32::consts::PI;
+
+use nalgebra::{SMatrix, SVector, Vector3};
+use ndarray::Array1;
+
+use crate::{
+    image::{linear_to_srgb, srgb_to_linear},
+    io::Geometry,
+};
+
+/// Number of coefficients of second order SH.
+pub const SH_COEFFICIENTS: usize = 9;
+
+/// Real SH basis up to the second order evaluated at a unit direction.
+pub fn sh_basis(normal: &Vector3<f32>) -> [f32; SH_COEFFICIENTS] {
+    let (x, y, z) = (normal[0], normal[1], normal[2]);
+    [
+        0.282_095,
+        0.488_603 * y,
+        0.488_603 * z,
+        0.488_603 * x,
+        1.092_548 * x * y,
+        1.092_548 * y * z,
+        0.315_392 * (3.0 * z * z - 1.0),
+        1.092_548 * x * z,
+        0.546_274 * (x * x - y * y),
+    ]
+}
+
+/// Diffuse lighting as second order SH, one RGB coefficient per basis function. Evaluating
+/// it at a normal gives the linear RGB shading of a surface with unit albedo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShLighting {
+    pub coefficients: [Vector3<f32>; SH_COEFFICIENTS],
+}
+
+impl ShLighting {
+    /// Uniform lighting of the given linear RGB intensity.
+    pub fn ambient(color: Vector3<f32>) -> Self {
+        let mut coefficients = [Vector3::zeros(); SH_COEFFICIENTS];
+        coefficients[0] = color / sh_basis(&Vector3::z())[0];
+        Self { coefficients }
+    }
+
+    /// White directional light plus an ambient term, a neutral lighting to render models
+    /// with.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - Direction towards the light.
+    /// * `ambient` - Intensity of the ambient term.
+    /// * `intensity` - Intensity of the directional light.
+    pub fn directional(direction: &Vector3<f32>, ambient: f32, intensity: f32) -> Self {
+        // Convolving the light with the clamped cosine of diffuse surfaces scales each
+        // band by pi, 2pi/3 and pi/4.
+        const BAND_FACTORS: [f32; SH_COEFFICIENTS] = [
+            PI,
+            2.0 * PI / 3.0,
+            2.0 * PI / 3.0,
+            2.0 * PI / 3.0,
+            PI / 4.0,
+            PI / 4.0,
+            PI / 4.0,
+            PI / 4.0,
+            PI / 4.0,
+        ];
+        let mut lighting = Self::ambient(Vector3::repeat(ambient));
+        let basis = sh_basis(&direction.normalize());
+        for (k, coefficient) in lighting.coefficients.iter_mut().enumerate() {
+            *coefficient += Vector3::repeat(intensity * BAND_FACTORS[k] * basis[k]);
+        }
+        lighting
+    }
+
+    /// Estimates the lighting from colors and normals of a reconstruction by least squares,
+    /// assuming an uniform albedo. The lighting is normalized to an average shading of 1
+    /// over the sphere, so the mean albedo stays in the colors when relighting.
+    ///
+    /// # Arguments
+    ///
+    /// * `normals` - Unit normals of the points.
+    /// * `colors` - sRGB colors of the points.
+    ///
+    /// # Returns
+    ///
+    /// The lighting, or `None` if the normals don't cover enough directions to constrain it.
+    pub fn estimate(normals: &Array1<Vector3<f32>>, colors: &Array1<Vector3<u8>>) -> Option<Self> {
+        let mut normal_matrix = SMatrix::<f64, SH_COEFFICIENTS, SH_COEFFICIENTS>::zeros();
+        let mut rhs = [SVector::<f64, SH_COEFFICIENTS>::zeros(); 3];
+        for (normal, color) in normals.iter().zip(colors.iter()) {
+            if normal.norm_squared() < 0.5 {
+                continue;
+            }
+            let basis = SVector::<f64, SH_COEFFICIENTS>::from(sh_basis(normal).map(|b| b as f64));
+            normal_matrix += basis * basis.transpose();
+            for (channel, rhs) in rhs.iter_mut().enumerate() {
+                *rhs += basis * srgb_to_linear(color[channel]) as f64;
+            }
+        }
+
+        let cholesky = normal_matrix.cholesky()?;
+        if cholesky.l().diagonal().min() < 1e-6 {
+            return None;
+        }
+        let solutions = rhs.map(|rhs| cholesky.solve(&rhs));
+        // The average of the SH function over the sphere is given by the constant term.
+        let average = solutions.map(|solution| solution[0] * sh_basis(&Vector3::z())[0] as f64);
+        if average.iter().any(|average| *average <= 0.0) {
+            return None;
+        }
+        Some(Self {
+            coefficients: std::array::from_fn(|k| {
+                Vector3::from_fn(|c, _| (solutions[c][k] / average[c]) as f32)
+            }),
+        })
+    }
+
+    /// Linear RGB shading of a surface with the given normal.
+    pub fn shading(&self, normal: &Vector3<f32>) -> Vector3<f32> {
+        sh_basis(normal)
+            .iter()
+            .zip(self.coefficients.iter())
+            .fold(Vector3::zeros(), |sum, (basis, coefficient)| {
+                sum + coefficient * *basis
+            })
+    }
+
+    /// Replaces this lighting, baked into the colors, with another one.
+    ///
+    /// # Arguments
+    ///
+    /// * `normals` - Unit normals of the points.
+    /// * `colors` - sRGB colors of the points, shaded by this lighting.
+    /// * `target` - The new lighting, e.g., [`ShLighting::directional`] to render different
+    ///   reconstructions consistently.
+    ///
+    /// # Returns
+    ///
+    /// The relit sRGB colors.
+    pub fn relight(
+        &self,
+        normals: &Array1<Vector3<f32>>,
+        colors: &Array1<Vector3<u8>>,
+        target: &ShLighting,
+    ) -> Array1<Vector3<u8>> {
+        const MIN_SHADING: f32 = 1e-3;
+        normals
+            .iter()
+            .zip(colors.iter())
+            .map(|(normal, color)| {
+                let source = self.shading(normal);
+                let target = target.shading(normal);
+                Vector3::from_fn(|c, _| {
+                    let albedo = srgb_to_linear(color[c]) / source[c].max(MIN_SHADING);
+                    linear_to_srgb(albedo * target[c])
+                })
+            })
+            .collect()
+    }
+}
+
+/// Estimates the lighting baked into the colors of a reconstruction and relights it, e.g.,
+/// before rendering several reconstructions for visual comparison.
+///
+/// # Arguments
+///
+/// * `geometry` - The reconstruction, with normals and colors.
+/// * `target` - The lighting to render with.
+///
+/// # Returns
+///
+/// The relit geometry, or `None` if it lacks normals or colors, or the lighting can't be
+/// estimated, see [`ShLighting::estimate`].
+pub fn relight_geometry(geometry: &Geometry, target: &ShLighting) -> Option<Geometry> {
+    let (normals, colors) = (geometry.normals.as_ref()?, geometry.colors.as_ref()?);
+    let lighting = ShLighting::estimate(normals, colors)?;
+    Some(Geometry {
+        colors: Some(lighting.relight(normals, colors, target)),
+        ..geometry.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Array1;
+
+    use super::{sh_basis, ShLighting};
+    use crate::image::{linear_to_srgb, srgb_to_linear};
+
+    /// Unit normals evenly spread over the sphere.
+    fn sphere_normals(count: usize) -> Array1<Vector3<f32>> {
+        let golden_angle = std::f32::consts::PI * (3.0 - 5.0_f32.sqrt());
+        (0..count)
+            .map(|i| {
+                let z = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+                let radius = (1.0 - z * z).sqrt();
+                let theta = golden_angle * i as f32;
+                Vector3::new(radius * theta.cos(), radius * theta.sin(), z)
+            })
+            .collect()
+    }
+
+    fn shade(
+        lighting: &ShLighting,
+        normals: &Array1<Vector3<f32>>,
+        albedo: &Vector3<f32>,
+    ) -> Array1<Vector3<u8>> {
+        normals
+            .iter()
+            .map(|normal| {
+                let shading = lighting.shading(normal);
+                Vector3::from_fn(|c, _| linear_to_srgb(albedo[c] * shading[c]))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_estimate() {
+        let normals = sphere_normals(2000);
+        let lighting = ShLighting::directional(&Vector3::new(1.0, -1.0, 0.5), 0.3, 0.6);
+        let albedo = Vector3::new(0.8, 0.5, 0.3);
+        let colors = shade(&lighting, &normals, &albedo);
+
+        let estimated = ShLighting::estimate(&normals, &colors).unwrap();
+        let average = lighting.coefficients[0] * sh_basis(&Vector3::z())[0];
+        for normal in normals.iter().step_by(50) {
+            let expected = lighting.shading(normal).component_div(&average);
+            assert!((estimated.shading(normal) - expected).norm() < 0.02);
+        }
+
+        let flat = Array1::from_elem(100, Vector3::z());
+        assert!(
+            ShLighting::estimate(&flat, &colors.slice(ndarray::s![..100]).to_owned()).is_none()
+        );
+    }
+
+    #[test]
+    fn test_relight() {
+        let normals = sphere_normals(2000);
+        let lighting = ShLighting::directional(&Vector3::new(0.0, 0.0, 1.0), 0.2, 0.8);
+        let colors = shade(&lighting, &normals, &Vector3::repeat(0.6));
+
+        // Under uniform light, the shading baked into the colors is removed. The albedo
+        // keeps the average brightness, 0.2 + 0.8 / 4.
+        let estimated = ShLighting::estimate(&normals, &colors).unwrap();
+        let relit = estimated.relight(
+            &normals,
+            &colors,
+            &ShLighting::ambient(Vector3::repeat(1.0)),
+        );
+        for color in relit.iter() {
+            for c in 0..3 {
+                assert!((srgb_to_linear(color[c]) - 0.6 * 0.4).abs() < 0.01);
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Hot-reload of the parameters that are safe to change during a run, so a long
+//! reconstruction can be tuned without restarting it.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    error::A3dError,
+    icp::{IcpParams, MsIcpParams},
+    ColorFusionParams,
+};
+
+/// Parameters that can be changed while a run is in progress. Parameters that shape the
+/// state of the run, e.g., the number of pyramid levels, are not in here.
+#[derive(Debug, Clone, Default)]
+pub struct LiveParams {
+    /// ICP parameters, live keys apply to all pyramid levels.
+    pub icp: MsIcpParams,
+    /// Color fusion parameters.
+    pub color_fusion: ColorFusionParams,
+    /// Visualization options, keys with the `viz.` prefix without it, interpreted by the
+    /// viewer.
+    pub viz: BTreeMap<String, String>,
+}
+
+impl LiveParams {
+    /// Sets a parameter by its config key, e.g., `icp.max_distance`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The parameter key.
+    /// * `value` - The value as text.
+    ///
+    /// # Returns
+    ///
+    /// Error if the key isn't a live parameter or the value can't be parsed.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), A3dError> {
+        fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, A3dError> {
+            value.trim().parse().map_err(|_| {
+                A3dError::invalid_parameter(format!("Invalid value {value} for {key}"))
+            })
+        }
+
+        if let Some(option) = key.strip_prefix("viz.") {
+            self.viz.insert(option.to_string(), value.to_string());
+            return Ok(());
+        }
+
+        match key {
+            "icp.max_iterations" => {
+                let value = parse(key, value)?;
+                self.set_icp(|params| params.max_iterations = value);
+            }
+            "icp.weight" => {
+                let value = parse(key, value)?;
+                self.set_icp(|params| params.weight = value);
+            }
+            "icp.color_weight" => {
+                let value = parse(key, value)?;
+                self.set_icp(|params| params.color_weight = value);
+            }
+            "icp.max_distance" => {
+                let value = parse(key, value)?;
+                self.set_icp(|params| params.max_distance = value);
+            }
+            "icp.max_point_to_plane_distance" => {
+                let value = parse(key, value)?;
+                self.set_icp(|params| params.max_point_to_plane_distance = value);
+            }
+            "icp.max_normal_angle" => {
+                // In degrees, as people write it.
+                let value = parse::<f32>(key, value)?.to_radians();
+                self.set_icp(|params| params.max_normal_angle = value);
+            }
+            "icp.max_color_distance" => {
+                let value = parse(key, value)?;
+                self.set_icp(|params| params.max_color_distance = value);
+            }
+            "color_fusion.outlier_threshold" => {
+                self.color_fusion.outlier_threshold = parse(key, value)?
+            }
+            "color_fusion.min_samples_for_rejection" => {
+                self.color_fusion.min_samples_for_rejection = parse(key, value)?
+            }
+            _ => {
+                return Err(A3dError::invalid_parameter(format!(
+                    "{key} is not a parameter that can be changed during a run"
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn set_icp<F: Fn(&mut IcpParams)>(&mut self, f: F) {
+        for level in 0..self.icp.len() {
+            f(&mut self.icp[level]);
+        }
+    }
+}
+
+/// Watches a config file and applies its parameters to [`LiveParams`] when it changes.
+///
+/// The file is a flat JSON object whose keys are the ones accepted by [`LiveParams::set`],
+/// with numbers or strings as values, e.g.:
+///
+/// ```json
+/// {"icp.max_distance": 0.2, "icp.max_normal_angle": 25, "viz.point_size": "2"}
+/// ```
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    contents: Option<String>,
+}
+
+impl ConfigWatcher {
+    /// Creates the watcher. The first [`ConfigWatcher::poll`] applies the file if it exists.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            modified: None,
+            contents: None,
+        }
+    }
+
+    /// Applies the config file if it was changed since the last call. Cheap enough to be
+    /// called once per frame, the file is only read when its modification time changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters to update. They are left untouched if the file has any
+    ///   error, so a half-saved file doesn't leave the run with a mix of old and new values.
+    ///
+    /// # Returns
+    ///
+    /// Whether the parameters were changed, or error if the file is invalid. The same
+    /// file content is not reported twice.
+    pub fn poll(&mut self, params: &mut LiveParams) -> Result<bool, A3dError> {
+        let Ok(modified) = std::fs::metadata(&self.path).and_then(|meta| meta.modified()) else {
+            return Ok(false);
+        };
+        if self.modified == Some(modified) {
+            return Ok(false);
+        }
+        self.modified = Some(modified);
+
+        let contents = std::fs::read_to_string(&self.path)?;
+        if self.contents.as_ref() == Some(&contents) {
+            return Ok(false);
+        }
+        self.contents = Some(contents.clone());
+
+        let document: BTreeMap<String, serde_json::Value> =
+            serde_json::from_str(&contents).map_err(|err| A3dError::Parser(err.to_string()))?;
+        let mut updated = params.clone();
+        for (key, value) in document {
+            let value = match value {
+                serde_json::Value::String(value) => value,
+                value => value.to_string(),
+            };
+            updated.set(&key, &value)?;
+        }
+        *params = updated;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigWatcher, LiveParams};
+
+    #[test]
+    fn test_set() {
+        let mut params = LiveParams::default();
+        params.set("icp.max_distance", "0.2").unwrap();
+        params.set("icp.max_normal_angle", "90").unwrap();
+        params.set("viz.point_size", "3").unwrap();
+        assert!(params.icp.iter().all(|level| level.max_distance == 0.2));
+        assert!((params.icp[0].max_normal_angle - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert_eq!(params.viz["point_size"], "3");
+
+        assert!(params.set("icp.max_distance", "far").is_err());
+        assert!(params.set("pyramid_levels", "2").is_err());
+    }
+
+    #[test]
+    fn test_poll() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("live.json");
+        let mut watcher = ConfigWatcher::new(&path);
+        let mut params = LiveParams::default();
+        assert!(!watcher.poll(&mut params).unwrap());
+
+        std::fs::write(
+            &path,
+            r#"{"icp.max_iterations": 4, "color_fusion.outlier_threshold": 0.5}"#,
+        )
+        .unwrap();
+        assert!(watcher.poll(&mut params).unwrap());
+        assert!(params.icp.iter().all(|level| level.max_iterations == 4));
+        assert_eq!(params.color_fusion.outlier_threshold, 0.5);
+        assert!(!watcher.poll(&mut params).unwrap());
+
+        // Invalid files leave the parameters as they were.
+        std::fs::write(
+            &path,
+            r#"{"icp.max_iterations": 8, "icp.max_distance": "far"}"#,
+        )
+        .unwrap();
+        let mut watcher = ConfigWatcher::new(&path);
+        assert!(watcher.poll(&mut params).is_err());
+        assert!(params.icp.iter().all(|level| level.max_iterations == 4));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{error::A3dError, range_image::RangeImage};
+
+/// Parameters of [`FrameDescriptor`].
+#[derive(Debug, Clone, Copy)]
+pub struct DescriptorParams {
+    /// Width of the intensity thumbnail.
+    pub thumbnail_width: usize,
+    /// Height of the intensity thumbnail.
+    pub thumbnail_height: usize,
+    /// Number of bins of the depth histogram.
+    pub depth_bins: usize,
+    /// Depth of the last histogram bin, in meters, farther points fall in it.
+    pub max_depth: f32,
+}
+
+impl Default for DescriptorParams {
+    fn default() -> Self {
+        Self {
+            thumbnail_width: 16,
+            thumbnail_height: 12,
+            depth_bins: 16,
+            max_depth: 5.0,
+        }
+    }
+}
+
+/// Compact global descriptor of a frame, used to find the frames that may see the same
+/// place. It holds a downsampled intensity image, normalized to zero mean and unit norm to
+/// be robust to exposure changes, and the normalized histogram of the depths.
+#[derive(Debug, Clone)]
+pub struct FrameDescriptor {
+    intensity: Vec<f32>,
+    depth_histogram: Vec<f32>,
+}
+
+impl FrameDescriptor {
+    /// Computes the descriptor of a range image.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The range image, must have intensities.
+    /// * `params` - The descriptor parameters.
+    ///
+    /// # Returns
+    ///
+    /// The descriptor, or error if the image has no intensities or is smaller than the
+    /// thumbnail.
+    pub fn from_range_image(
+        image: &RangeImage,
+        params: &DescriptorParams,
+    ) -> Result<Self, A3dError> {
+        let intensities = image.intensities.as_ref().ok_or_else(|| {
+            A3dError::invalid_parameter("The frame descriptor requires the image intensities.")
+        })?;
+        let (width, height) = (image.width(), image.height());
+        let (thumb_width, thumb_height) = (params.thumbnail_width, params.thumbnail_height);
+        if thumb_width == 0 || thumb_height == 0 || width < thumb_width || height < thumb_height {
+            return Err(A3dError::invalid_parameter(
+                "The thumbnail must be non-empty and smaller than the image.",
+            ));
+        }
+
+        let mut intensity = vec![0.0; thumb_width * thumb_height];
+        let mut counts = vec![0usize; thumb_width * thumb_height];
+        for row in 0..height {
+            for col in 0..width {
+                let cell = (row * thumb_height / height) * thumb_width + col * thumb_width / width;
+                intensity[cell] += intensities[row * width + col] as f32;
+                counts[cell] += 1;
+            }
+        }
+        for (value, count) in intensity.iter_mut().zip(counts) {
+            *value /= count as f32;
+        }
+        let mean = intensity.iter().sum::<f32>() / intensity.len() as f32;
+        intensity.iter_mut().for_each(|value| *value -= mean);
+        let norm = intensity
+            .iter()
+            .map(|value| value * value)
+            .sum::<f32>()
+            .sqrt();
+        if norm > 1e-6 {
+            intensity.iter_mut().for_each(|value| *value /= norm);
+        }
+
+        let mut depth_histogram = vec![0.0; params.depth_bins.max(1)];
+        let last_bin = depth_histogram.len() - 1;
+        for (point, mask) in image.points.iter().zip(image.mask.iter()) {
+            if *mask == 1 {
+                let bin = (point[2] / params.max_depth * depth_histogram.len() as f32) as usize;
+                depth_histogram[bin.min(last_bin)] += 1.0;
+            }
+        }
+        let total = depth_histogram.iter().sum::<f32>();
+        if total > 0.0 {
+            depth_histogram.iter_mut().for_each(|value| *value /= total);
+        }
+
+        Ok(Self {
+            intensity,
+            depth_histogram,
+        })
+    }
+
+    /// Distance between two descriptors, in [0, 1]. The mean of the cosine distance of the
+    /// thumbnails, scaled to [0, 1], and the total variation distance of the depth
+    /// histograms.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - A descriptor computed with the same parameters.
+    pub fn distance(&self, other: &Self) -> f32 {
+        let correlation = self
+            .intensity
+            .iter()
+            .zip(other.intensity.iter())
+            .map(|(a, b)| a * b)
+            .sum::<f32>();
+        let depth_distance = self
+            .depth_histogram
+            .iter()
+            .zip(other.depth_histogram.iter())
+            .map(|(a, b)| (a - b).abs())
+            .sum::<f32>()
+            * 0.5;
+        (((1.0 - correlation) * 0.5 + depth_distance) * 0.5).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{DescriptorParams, FrameDescriptor};
+    use crate::unit_test::{sample_range_img_ds2, TestRangeImageDataset};
+
+    #[rstest]
+    fn test_distance(sample_range_img_ds2: TestRangeImageDataset) {
+        let params = DescriptorParams::default();
+        let image0 = sample_range_img_ds2.get(0).unwrap();
+        let descriptor0 = FrameDescriptor::from_range_image(&image0, &params).unwrap();
+        let descriptor1 =
+            FrameDescriptor::from_range_image(&sample_range_img_ds2.get(1).unwrap(), &params)
+                .unwrap();
+
+        assert!(descriptor0.distance(&descriptor0) < 1e-5);
+        let distance = descriptor0.distance(&descriptor1);
+        assert!(distance > 0.0 && distance < 0.2, "{distance}");
+
+        // Mirroring the image changes the thumbnail layout.
+        let mut mirrored = image0.clone();
+        let intensities = mirrored.intensities.as_mut().unwrap();
+        for row in 0..image0.height() {
+            intensities
+                .as_slice_mut()
+                .unwrap()
+                .get_mut(row * image0.width()..(row + 1) * image0.width())
+                .unwrap()
+                .reverse();
+        }
+        let mirrored = FrameDescriptor::from_range_image(&mirrored, &params).unwrap();
+        assert!(descriptor0.distance(&mirrored) > distance);
+    }
+}
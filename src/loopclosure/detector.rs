@@ -0,0 +1,277 @@
+use nalgebra::Matrix6;
+
+use super::descriptor::{DescriptorParams, FrameDescriptor};
+use crate::{
+    error::A3dError,
+    icp::{IcpParams, ProjectiveIcp},
+    optim::{PoseGraphEdge, RobustKernel},
+    range_image::RangeImage,
+    transform::Transform,
+};
+
+/// Parameters of [`LoopClosureDetector`].
+#[derive(Debug, Clone, Copy)]
+pub struct LoopClosureParams {
+    /// Parameters of the frame descriptors.
+    pub descriptor: DescriptorParams,
+    /// Maximum descriptor distance of a loop candidate, see [`FrameDescriptor::distance`].
+    pub max_descriptor_distance: f32,
+    /// Minimum difference of ids between a keyframe and its loop candidates, which skips
+    /// the recent keyframes already constrained by the odometry.
+    pub min_id_gap: usize,
+    /// Maximum number of candidates verified per keyframe, the closest descriptors first.
+    pub max_candidates: usize,
+    /// Parameters of the ICP verifying the candidates.
+    pub icp: IcpParams,
+    /// Minimum fraction of the keyframe valid points that must be ICP correspondences.
+    pub min_inlier_ratio: f32,
+    /// Maximum root mean squared residual of the verifying ICP, in meters.
+    pub max_rmse: f32,
+}
+
+impl Default for LoopClosureParams {
+    fn default() -> Self {
+        Self {
+            descriptor: DescriptorParams::default(),
+            max_descriptor_distance: 0.15,
+            min_id_gap: 30,
+            max_candidates: 3,
+            icp: IcpParams {
+                max_iterations: 20,
+                max_distance: 0.1,
+                ..Default::default()
+            },
+            min_inlier_ratio: 0.5,
+            max_rmse: 0.02,
+        }
+    }
+}
+
+/// A verified loop closure, an edge of the pose graph between two keyframes.
+#[derive(Debug, Clone)]
+pub struct LoopClosure {
+    /// Id of the new keyframe.
+    pub source: usize,
+    /// Id of the previous keyframe seeing the same place.
+    pub target: usize,
+    /// Transformation from the source keyframe into the target one.
+    pub transform: Transform,
+    /// Information matrix of the transformation, as estimated by ICP.
+    pub information: Matrix6<f32>,
+    /// Descriptor distance of the pair.
+    pub descriptor_distance: f32,
+}
+
+impl LoopClosure {
+    /// The pose graph edge of the loop closure, with the keyframe ids as node indices.
+    ///
+    /// # Arguments
+    ///
+    /// * `robust_kernel` - Robust kernel of the edge, which limits the harm of a wrong
+    ///   loop closure.
+    pub fn to_edge(&self, robust_kernel: Option<RobustKernel>) -> PoseGraphEdge {
+        PoseGraphEdge {
+            robust_kernel,
+            ..PoseGraphEdge::new(
+                self.target,
+                self.source,
+                self.transform.clone(),
+                self.information,
+            )
+        }
+    }
+}
+
+struct Keyframe {
+    id: usize,
+    descriptor: FrameDescriptor,
+    image: RangeImage,
+    pose: Transform,
+}
+
+/// Finds the previous keyframes that see the same place as a new one. The candidates are
+/// the keyframes with the closest [`FrameDescriptor`]s, which are then verified by
+/// projective ICP starting from the relative transformation of their current poses.
+pub struct LoopClosureDetector {
+    /// Parameters of the detection.
+    pub params: LoopClosureParams,
+    keyframes: Vec<Keyframe>,
+}
+
+impl LoopClosureDetector {
+    /// Creates a detector without keyframes.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters of the detection.
+    pub fn new(params: LoopClosureParams) -> Self {
+        Self {
+            params,
+            keyframes: Vec::new(),
+        }
+    }
+
+    /// Number of keyframes.
+    pub fn len(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    /// Whether there are no keyframes.
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Updates the pose of a keyframe, e.g., after the pose graph optimization.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the keyframe.
+    /// * `pose` - Its new camera to world transformation.
+    ///
+    /// # Returns
+    ///
+    /// Whether the keyframe exists.
+    pub fn set_pose(&mut self, id: usize, pose: Transform) -> bool {
+        match self.keyframes.iter_mut().find(|keyframe| keyframe.id == id) {
+            Some(keyframe) => {
+                keyframe.pose = pose;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Descriptor candidates of a keyframe, ordered by descriptor distance.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the keyframe.
+    /// * `descriptor` - Its descriptor.
+    ///
+    /// # Returns
+    ///
+    /// The indices of the candidate keyframes with their descriptor distances.
+    fn candidates(&self, id: usize, descriptor: &FrameDescriptor) -> Vec<(usize, f32)> {
+        let mut candidates = self
+            .keyframes
+            .iter()
+            .enumerate()
+            .filter(|(_, keyframe)| id.abs_diff(keyframe.id) >= self.params.min_id_gap)
+            .map(|(index, keyframe)| (index, descriptor.distance(&keyframe.descriptor)))
+            .filter(|(_, distance)| *distance <= self.params.max_descriptor_distance)
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+        candidates.truncate(self.params.max_candidates);
+        candidates
+    }
+
+    /// Adds a keyframe and returns its verified loop closures with the previous ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the keyframe, e.g., its frame index.
+    /// * `image` - The keyframe, must have normals and intensities.
+    /// * `pose` - Its camera to world transformation estimated by the odometry.
+    ///
+    /// # Returns
+    ///
+    /// The loop closures, or error if the image lacks normals or intensities.
+    pub fn add_keyframe(
+        &mut self,
+        id: usize,
+        image: RangeImage,
+        pose: Transform,
+    ) -> Result<Vec<LoopClosure>, A3dError> {
+        if image.normals.is_none() {
+            return Err(A3dError::invalid_parameter(
+                "Loop closure verification requires the image normals.",
+            ));
+        }
+        let descriptor = FrameDescriptor::from_range_image(&image, &self.params.descriptor)?;
+
+        let min_inliers =
+            (image.valid_points_count() as f32 * self.params.min_inlier_ratio) as usize;
+        let closures = self
+            .candidates(id, &descriptor)
+            .into_iter()
+            .filter_map(|(index, descriptor_distance)| {
+                let keyframe = &self.keyframes[index];
+                let mut icp = ProjectiveIcp::new(self.params.icp, &keyframe.image);
+                icp.initial_transform = &keyframe.pose.inverse() * &pose;
+                let result = icp.align(&image);
+                if result.inlier_count < min_inliers || result.final_rmse > self.params.max_rmse {
+                    return None;
+                }
+                Some(LoopClosure {
+                    source: id,
+                    target: keyframe.id,
+                    transform: result.transform,
+                    information: result.information.unwrap_or_else(Matrix6::identity),
+                    descriptor_distance,
+                })
+            })
+            .collect();
+
+        self.keyframes.push(Keyframe {
+            id,
+            descriptor,
+            image,
+            pose,
+        });
+        Ok(closures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rstest::rstest;
+
+    use super::{LoopClosureDetector, LoopClosureParams};
+    use crate::{
+        metrics::TransformMetrics,
+        transform::{Transform, TransformBuilder},
+        unit_test::{sample_range_img_ds2, TestRangeImageDataset},
+    };
+
+    #[rstest]
+    fn test_add_keyframe(sample_range_img_ds2: TestRangeImageDataset) {
+        let gt_transform = sample_range_img_ds2.get_ground_truth(1, 0);
+        // The odometry pose drifted a few centimeters.
+        let drift = TransformBuilder::default()
+            .translation(Vector3::new(0.02, -0.01, 0.01))
+            .build();
+        let drifted_pose = &drift * &gt_transform;
+
+        let mut detector = LoopClosureDetector::new(LoopClosureParams {
+            min_id_gap: 20,
+            ..Default::default()
+        });
+        assert!(detector
+            .add_keyframe(0, sample_range_img_ds2.get(0).unwrap(), Transform::eye())
+            .unwrap()
+            .is_empty());
+        // Too recent to be a loop.
+        assert!(detector
+            .add_keyframe(
+                5,
+                sample_range_img_ds2.get(1).unwrap(),
+                drifted_pose.clone()
+            )
+            .unwrap()
+            .is_empty());
+
+        let closures = detector
+            .add_keyframe(20, sample_range_img_ds2.get(1).unwrap(), drifted_pose)
+            .unwrap();
+        assert_eq!(detector.len(), 3);
+        assert_eq!(closures.len(), 1);
+        assert_eq!((closures[0].source, closures[0].target), (20, 0));
+        let metrics = TransformMetrics::new(&closures[0].transform, &gt_transform);
+        assert!(metrics.translation < 0.01, "{metrics}");
+        assert!(metrics.angle < 0.01, "{metrics}");
+
+        let edge = closures[0].to_edge(None);
+        assert_eq!((edge.from, edge.to), (0, 20));
+    }
+}
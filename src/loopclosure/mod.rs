@@ -0,0 +1,5 @@
+mod descriptor;
+pub use descriptor::{DescriptorParams, FrameDescriptor};
+
+mod detector;
+pub use detector::{LoopClosure, LoopClosureDetector, LoopClosureParams};
@@ -1,5 +1,8 @@
-use nalgebra::Vector3;
-use ndarray::{Array1, ArrayView1, ArrayView2, Axis};
+use image::{Rgb, RgbImage};
+use nalgebra::{Vector2, Vector3};
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2, Axis};
+
+use crate::{camera::PinholeCamera, image::RgbdFrame, io::Geometry};
 
 pub fn compute_normals(
     points: &ArrayView1<Vector3<f32>>,
@@ -50,11 +53,216 @@ pub fn compute_normals(
     vertex_normals
 }
 
+/// Parameters of [`bake_texture_atlas`].
+#[derive(Debug, Clone, Copy)]
+pub struct TextureAtlasParams {
+    /// Size in pixels of the atlas cells, each cell holds two triangles.
+    pub cell_size: u32,
+    /// Maximum difference in meters between a triangle and the keyframe depth for the
+    /// triangle to be considered visible. Only used when the keyframe has a depth scale.
+    pub occlusion_threshold: f32,
+}
+
+impl Default for TextureAtlasParams {
+    fn default() -> Self {
+        Self {
+            cell_size: 16,
+            occlusion_threshold: 0.05,
+        }
+    }
+}
+
+/// Picks the keyframe that sees the triangle most frontally and closest.
+fn best_keyframe(
+    triangle: &[Vector3<f32>; 3],
+    keyframes: &[(PinholeCamera, &RgbdFrame)],
+    occlusion_threshold: f32,
+) -> Option<usize> {
+    let centroid = (triangle[0] + triangle[1] + triangle[2]) / 3.0;
+    let normal = (triangle[1] - triangle[0])
+        .cross(&(triangle[2] - triangle[0]))
+        .try_normalize(f32::EPSILON)?;
+
+    let mut best = None;
+    let mut best_score = 0.0;
+    for (index, (camera, frame)) in keyframes.iter().enumerate() {
+        if !triangle
+            .iter()
+            .all(|vertex| matches!(camera.project_to_image(vertex), Some((_, _, z)) if z > 0.0))
+        {
+            continue;
+        }
+
+        if let Some(depth_scale) = frame.image.depth_scale {
+            let Some((x, y, z)) = camera.project_to_image(&centroid) else {
+                continue;
+            };
+            let depth = frame.image.depth[(y as usize, x as usize)] as f32 * depth_scale as f32;
+            if depth > 0.0 && (depth - z).abs() > occlusion_threshold {
+                continue;
+            }
+        }
+
+        let to_camera = camera.camera_to_world.translation() - centroid;
+        let distance = to_camera.norm();
+        let score = normal.dot(&to_camera).abs() / (distance * distance);
+        if score > best_score {
+            best_score = score;
+            best = Some(index);
+        }
+    }
+    best
+}
+
+/// Generates per-triangle texture coordinates and bakes a texture atlas from keyframe
+/// images. Each triangle is mapped into half of an atlas cell and textured from the keyframe
+/// that sees it most frontally, triangles seen by no keyframe use the vertex colors.
+///
+/// # Arguments
+///
+/// * `geometry` - The mesh, it must have faces.
+/// * `keyframes` - The keyframes, frames without a pose are ignored.
+/// * `params` - The atlas parameters.
+///
+/// # Returns
+///
+/// * A geometry with the vertices split per triangle and texture coordinates, with `v`
+///   pointing up, and the atlas image. None if the geometry has no faces.
+pub fn bake_texture_atlas(
+    geometry: &Geometry,
+    keyframes: &[RgbdFrame],
+    params: &TextureAtlasParams,
+) -> Option<(Geometry, RgbImage)> {
+    let faces = geometry.faces.as_ref()?;
+    let num_faces = faces.nrows();
+    let cell_size = params.cell_size.max(8);
+    let num_cells = num_faces.div_ceil(2).max(1) as u32;
+    let cols = (num_cells as f32).sqrt().ceil() as u32;
+    let rows = num_cells.div_ceil(cols);
+    let (width, height) = (cols * cell_size, rows * cell_size);
+    let mut atlas = RgbImage::from_pixel(width, height, Rgb([128, 128, 128]));
+
+    let keyframes = keyframes
+        .iter()
+        .filter_map(|frame| frame.get_pinhole_camera().map(|camera| (camera, frame)))
+        .collect::<Vec<_>>();
+
+    let size = cell_size as f32;
+    let (pad, gap) = (1.5, 1.5);
+    let charts = [
+        [
+            Vector2::new(pad, pad),
+            Vector2::new(size - pad - gap, pad),
+            Vector2::new(pad, size - pad - gap),
+        ],
+        [
+            Vector2::new(size - pad, size - pad),
+            Vector2::new(pad + gap, size - pad),
+            Vector2::new(size - pad, pad + gap),
+        ],
+    ];
+
+    let mut points = Array1::from_elem(num_faces * 3, Vector3::zeros());
+    let mut texcoords = Array1::from_elem(num_faces * 3, Vector2::zeros());
+    let mut normals = geometry
+        .normals
+        .as_ref()
+        .map(|_| Array1::from_elem(num_faces * 3, Vector3::zeros()));
+
+    for (face_index, face) in faces.axis_iter(Axis(0)).enumerate() {
+        let triangle = [
+            geometry.points[face[0]],
+            geometry.points[face[1]],
+            geometry.points[face[2]],
+        ];
+        let cell = (face_index / 2) as u32;
+        let origin = Vector2::new(
+            ((cell % cols) * cell_size) as f32,
+            ((cell / cols) * cell_size) as f32,
+        );
+        let chart = charts[face_index % 2].map(|corner| origin + corner);
+
+        for k in 0..3 {
+            points[face_index * 3 + k] = triangle[k];
+            texcoords[face_index * 3 + k] = Vector2::new(
+                chart[k][0] / width as f32,
+                1.0 - chart[k][1] / height as f32,
+            );
+            if let (Some(normals), Some(source)) = (normals.as_mut(), geometry.normals.as_ref()) {
+                normals[face_index * 3 + k] = source[face[k]];
+            }
+        }
+
+        let keyframe = best_keyframe(&triangle, &keyframes, params.occlusion_threshold);
+        let vertex_colors = geometry.colors.as_ref().map(|colors| {
+            [colors[face[0]], colors[face[1]], colors[face[2]]].map(|c| c.cast::<f32>())
+        });
+
+        // Barycentric coordinates of the texels, dilated by a pixel against seams.
+        let (e0, e1) = (chart[1] - chart[0], chart[2] - chart[0]);
+        let area = e0[0] * e1[1] - e0[1] * e1[0];
+        let dilation = 1.0 / area.abs().sqrt();
+        let (min_x, max_x) = (origin[0] as u32, origin[0] as u32 + cell_size);
+        let (min_y, max_y) = (origin[1] as u32, origin[1] as u32 + cell_size);
+        for y in min_y..max_y.min(height) {
+            for x in min_x..max_x.min(width) {
+                let offset = Vector2::new(x as f32 + 0.5, y as f32 + 0.5) - chart[0];
+                let b1 = (offset[0] * e1[1] - offset[1] * e1[0]) / area;
+                let b2 = (e0[0] * offset[1] - e0[1] * offset[0]) / area;
+                let b0 = 1.0 - b1 - b2;
+                if b0 < -dilation || b1 < -dilation || b2 < -dilation {
+                    continue;
+                }
+                let (b0, b1, b2) = (b0.max(0.0), b1.max(0.0), b2.max(0.0));
+                let sum = b0 + b1 + b2;
+                let (b0, b1, b2) = (b0 / sum, b1 / sum, b2 / sum);
+
+                let color = if let Some(index) = keyframe {
+                    let (camera, frame) = &keyframes[index];
+                    let point = triangle[0] * b0 + triangle[1] * b1 + triangle[2] * b2;
+                    let (u, v, _) = camera.project(&point);
+                    let row = (v.round().max(0.0) as usize).min(frame.image.height() - 1);
+                    let col = (u.round().max(0.0) as usize).min(frame.image.width() - 1);
+                    Rgb([
+                        frame.image.color[(row, col, 0)],
+                        frame.image.color[(row, col, 1)],
+                        frame.image.color[(row, col, 2)],
+                    ])
+                } else if let Some([c0, c1, c2]) = vertex_colors {
+                    let color = c0 * b0 + c1 * b1 + c2 * b2;
+                    Rgb(color.map(|c| c.round() as u8).into())
+                } else {
+                    continue;
+                };
+                atlas.put_pixel(x, y, color);
+            }
+        }
+    }
+
+    let geometry = Geometry {
+        points,
+        colors: None,
+        normals,
+        faces: Some(Array2::from_shape_fn((num_faces, 3), |(i, k)| i * 3 + k)),
+        texcoords: Some(texcoords),
+        metadata: geometry.metadata.clone(),
+    };
+    Some((geometry, atlas))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::io::read_off;
+    use nalgebra::Vector3;
+    use ndarray::{array, Array2, Array3};
 
-    use super::compute_normals;
+    use crate::{
+        camera::CameraIntrinsics,
+        image::{RgbdFrame, RgbdImage},
+        io::{read_off, write_textured_ply, GeometryBuilder},
+        transform::Transform,
+    };
+
+    use super::{bake_texture_atlas, compute_normals, TextureAtlasParams};
 
     #[test]
     fn test_compute_normals() {
@@ -62,4 +270,51 @@ mod tests {
         let normals = compute_normals(&geometry.points.view(), &geometry.faces.unwrap().view());
         assert!(normals.len() == geometry.points.len());
     }
+
+    #[test]
+    fn test_bake_texture_atlas() {
+        let geometry = GeometryBuilder::new(array![
+            Vector3::new(-0.5, -0.5, 1.0),
+            Vector3::new(0.5, -0.5, 1.0),
+            Vector3::new(0.5, 0.5, 1.0),
+            Vector3::new(-0.5, 0.5, 1.0)
+        ])
+        .with_faces(array![[0, 1, 2], [0, 2, 3]])
+        .build();
+
+        // Left half red, right half blue.
+        let color = Array3::from_shape_fn((64, 64, 3), |(_, col, channel)| match channel {
+            0 if col < 32 => 255,
+            2 if col >= 32 => 255,
+            _ => 0,
+        });
+        let depth = Array2::from_elem((64, 64), 1000u16);
+        let frame = RgbdFrame::new(
+            CameraIntrinsics::from_simple_intrinsic(40.0, 40.0, 32.0, 32.0, 64, 64),
+            RgbdImage::with_depth_scale(color, depth, 0.001),
+            Some(Transform::eye()),
+        );
+
+        let (textured, atlas) =
+            bake_texture_atlas(&geometry, &[frame], &TextureAtlasParams::default()).unwrap();
+        assert_eq!(textured.len_vertices(), 6);
+        assert_eq!(textured.len_faces(), 2);
+        let texcoords = textured.texcoords.as_ref().unwrap();
+
+        // The first triangle's vertex 1 is on the right (blue), vertex 0 on the left (red).
+        let texel = |index: usize, towards: usize| {
+            let uv = texcoords[index] * 0.8 + texcoords[towards] * 0.2;
+            let (x, y) = (
+                uv[0] * atlas.width() as f32,
+                (1.0 - uv[1]) * atlas.height() as f32,
+            );
+            atlas.get_pixel(x as u32, y as u32).0
+        };
+        assert_eq!(texel(0, 2), [255, 0, 0]);
+        assert_eq!(texel(1, 2), [0, 0, 255]);
+        assert_eq!(texel(5, 4), [255, 0, 0]);
+
+        write_textured_ply("tests/outputs/textured-quad.ply", &textured, &atlas).unwrap();
+        assert!(std::path::Path::new("tests/outputs/textured-quad.png").exists());
+    }
 }
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+const COMMENT_PREFIX: &str = "a3d.";
+
+/// Camera exposure settings of a frame, used to compensate brightness differences
+/// between frames before combining their colors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+pub struct Exposure {
+    /// Exposure time in seconds.
+    pub exposure_time: f64,
+    /// Analog/digital gain as a linear factor.
+    pub gain: f64,
+    /// Linear white balance gains of the red, green and blue channels.
+    pub white_balance: [f64; 3],
+}
+
+impl Exposure {
+    /// Creates an exposure with unit gains.
+    pub fn new(exposure_time: f64) -> Self {
+        Self {
+            exposure_time,
+            gain: 1.0,
+            white_balance: [1.0; 3],
+        }
+    }
+
+    /// Per-channel factors that map linear intensities captured with this exposure to the
+    /// ones that would be captured with the `reference` exposure.
+    pub fn linear_scale_to(&self, reference: &Exposure) -> [f64; 3] {
+        let scale = (reference.exposure_time * reference.gain) / (self.exposure_time * self.gain);
+        [0, 1, 2].map(|c| scale * reference.white_balance[c] / self.white_balance[c])
+    }
+}
+
+/// Provenance record attached to frames and exported geometries, so result files
+/// describe where they came from and how they were produced.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+pub struct Metadata {
+    /// Index of the frame in its source dataset.
+    pub frame_id: Option<usize>,
+    /// Timestamp of the frame in seconds.
+    pub timestamp: Option<f64>,
+    /// Description of the source dataset, e.g., its format and path.
+    pub source: Option<String>,
+    /// Camera exposure of the frame, when provided by the dataset.
+    pub exposure: Option<Exposure>,
+    /// Processing parameters as key-value pairs.
+    pub parameters: BTreeMap<String, String>,
+    /// Name and version of the software that produced the data.
+    pub software_version: String,
+}
+
+impl Default for Metadata {
+    /// Empty metadata with the current align3d version.
+    fn default() -> Self {
+        Self {
+            frame_id: None,
+            timestamp: None,
+            source: None,
+            exposure: None,
+            parameters: BTreeMap::new(),
+            software_version: format!("align3d {}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+impl Metadata {
+    /// Creates the metadata of a dataset frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Description of the source dataset.
+    /// * `frame_id` - Index of the frame in the dataset.
+    /// * `timestamp` - Timestamp of the frame, if known.
+    pub fn from_frame<S: ToString>(source: S, frame_id: usize, timestamp: Option<f64>) -> Self {
+        Self {
+            frame_id: Some(frame_id),
+            timestamp,
+            source: Some(source.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Records a processing parameter.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Name of the parameter, e.g., `icp.max_iterations`.
+    /// * `value` - Value of the parameter.
+    pub fn with_parameter<K: ToString, V: Display>(mut self, key: K, value: V) -> Self {
+        self.parameters.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Encodes the metadata as one line per field, suitable for file comments
+    /// (e.g., PLY header comments).
+    pub fn to_comments(&self) -> Vec<String> {
+        let mut comments = vec![format!(
+            "{COMMENT_PREFIX}software {}",
+            self.software_version
+        )];
+        if let Some(frame_id) = self.frame_id {
+            comments.push(format!("{COMMENT_PREFIX}frame_id {frame_id}"));
+        }
+        if let Some(timestamp) = self.timestamp {
+            comments.push(format!("{COMMENT_PREFIX}timestamp {timestamp}"));
+        }
+        if let Some(source) = &self.source {
+            comments.push(format!("{COMMENT_PREFIX}source {source}"));
+        }
+        if let Some(exposure) = &self.exposure {
+            let [r, g, b] = exposure.white_balance;
+            comments.push(format!(
+                "{COMMENT_PREFIX}exposure {} {} {r} {g} {b}",
+                exposure.exposure_time, exposure.gain
+            ));
+        }
+        for (key, value) in self.parameters.iter() {
+            comments.push(format!("{COMMENT_PREFIX}param.{key} {value}"));
+        }
+        comments
+    }
+
+    /// Decodes the metadata from comments created by [`Metadata::to_comments`].
+    /// Unrelated comments are ignored.
+    ///
+    /// # Returns
+    ///
+    /// The metadata or `None` if no metadata comment is found.
+    pub fn from_comments<S: AsRef<str>>(comments: &[S]) -> Option<Self> {
+        let mut metadata = Self {
+            software_version: String::new(),
+            ..Default::default()
+        };
+        let mut found = false;
+
+        for comment in comments.iter() {
+            let Some(entry) = comment.as_ref().trim().strip_prefix(COMMENT_PREFIX) else {
+                continue;
+            };
+            let (key, value) = entry.split_once(' ').unwrap_or((entry, ""));
+            match key {
+                "software" => metadata.software_version = value.to_string(),
+                "frame_id" => metadata.frame_id = value.parse().ok(),
+                "timestamp" => metadata.timestamp = value.parse().ok(),
+                "source" => metadata.source = Some(value.to_string()),
+                "exposure" => {
+                    let values = value
+                        .split_whitespace()
+                        .filter_map(|value| value.parse::<f64>().ok())
+                        .collect::<Vec<_>>();
+                    if let [exposure_time, gain, r, g, b] = values[..] {
+                        metadata.exposure = Some(Exposure {
+                            exposure_time,
+                            gain,
+                            white_balance: [r, g, b],
+                        });
+                    }
+                }
+                _ => {
+                    if let Some(param) = key.strip_prefix("param.") {
+                        metadata
+                            .parameters
+                            .insert(param.to_string(), value.to_string());
+                    } else {
+                        continue;
+                    }
+                }
+            }
+            found = true;
+        }
+
+        found.then_some(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Exposure, Metadata};
+
+    #[test]
+    fn test_comments_round_trip() {
+        let metadata = Metadata::from_frame("tum:tests/data/sample", 5, Some(1305031102.175304))
+            .with_parameter("icp.max_iterations", 15)
+            .with_parameter("bilateral.sigma_space", 4.5);
+        let metadata = Metadata {
+            exposure: Some(Exposure {
+                exposure_time: 0.015,
+                gain: 2.0,
+                white_balance: [1.8, 1.0, 1.5],
+            }),
+            ..metadata
+        };
+
+        let mut comments = vec!["VCGLIB generated".to_string()];
+        comments.extend(metadata.to_comments());
+
+        assert_eq!(Metadata::from_comments(&comments), Some(metadata));
+        assert_eq!(Metadata::from_comments(&["VCGLIB generated"]), None);
+    }
+}
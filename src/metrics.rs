@@ -1,4 +1,18 @@
-use crate::{error::A3dError, trajectory::Trajectory, transform::Transform};
+use nalgebra::Vector3;
+use ndarray::{Array1, Axis};
+use rand::seq::index::sample;
+use rayon::prelude::*;
+
+use crate::{
+    bvh::{closest_point_on_triangle, MeshBvh},
+    error::A3dError,
+    io::Geometry,
+    kdtree::R3dTree,
+    pointcloud::PointCloud,
+    random::RandomState,
+    trajectory::Trajectory,
+    transform::Transform,
+};
 
 /// Metrics for comparing two transforms.
 #[derive(Clone, Debug)]
@@ -68,11 +82,337 @@ impl std::fmt::Display for TransformMetrics {
     }
 }
 
+/// Parameters of the metrics comparing two point clouds, e.g., a reconstruction and its
+/// ground truth.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CloudMetricsParams {
+    /// Maximum number of points of each cloud, larger clouds are randomly subsampled to
+    /// bound the cost of the comparison. `None` uses all the points.
+    pub max_points: Option<usize>,
+    /// Random state of the subsampling.
+    pub random_state: RandomState,
+}
+
+/// Indices of the points of each cloud used by the metrics, subsampled according to the
+/// parameters.
+fn metric_indices<const N: usize>(
+    clouds: [&PointCloud; N],
+    params: &CloudMetricsParams,
+) -> Result<[Vec<usize>; N], A3dError> {
+    if clouds.iter().any(|pcl| pcl.is_empty()) {
+        return Err(A3dError::invalid_parameter(
+            "The compared point clouds must not be empty.",
+        ));
+    }
+    let mut rng = params.random_state.rng();
+    Ok(clouds.map(|pcl| match params.max_points {
+        Some(max_points) if pcl.len() > max_points => {
+            let mut indices = sample(&mut rng, pcl.len(), max_points.max(1)).into_vec();
+            indices.sort_unstable();
+            indices
+        }
+        _ => (0..pcl.len()).collect(),
+    }))
+}
+
+/// The points of each cloud used by the metrics, see [`metric_indices`].
+fn metric_points<const N: usize>(
+    clouds: [&PointCloud; N],
+    params: &CloudMetricsParams,
+) -> Result<[Array1<Vector3<f32>>; N], A3dError> {
+    let indices = metric_indices(clouds, params)?;
+    let mut clouds = clouds.iter();
+    Ok(indices.map(|indices| {
+        clouds
+            .next()
+            .expect("One cloud per indices")
+            .points
+            .select(Axis(0), &indices)
+    }))
+}
+
+/// Distance from each point to its nearest neighbor in the other cloud.
+fn nearest_distances(points: &Array1<Vector3<f32>>, other: &Array1<Vector3<f32>>) -> Vec<f32> {
+    let kdtree = R3dTree::new(&other.view());
+    points
+        .as_slice()
+        .expect("Points are contiguous")
+        .par_iter()
+        .map(|point| kdtree.knn(point, 1)[0].1.sqrt())
+        .collect()
+}
+
+/// Symmetric Chamfer distance between two point clouds: the average of the mean distance
+/// from the points of each cloud to their nearest neighbor in the other one. It is 0 for
+/// identical clouds, and grows with both the missing parts and the noise of a
+/// reconstruction.
+///
+/// # Arguments
+///
+/// * lhs - The first point cloud.
+/// * rhs - The second point cloud.
+/// * params - Subsampling of the clouds.
+///
+/// # Returns
+///
+/// The distance, in the units of the points, or error if a cloud is empty.
+pub fn chamfer_distance(
+    lhs: &PointCloud,
+    rhs: &PointCloud,
+    params: &CloudMetricsParams,
+) -> Result<f32, A3dError> {
+    let [lhs, rhs] = metric_points([lhs, rhs], params)?;
+    let mean = |distances: Vec<f32>| distances.iter().sum::<f32>() / distances.len() as f32;
+    Ok(0.5 * (mean(nearest_distances(&lhs, &rhs)) + mean(nearest_distances(&rhs, &lhs))))
+}
+
+/// The value below which a percentage of the values fall, by the nearest rank.
+fn percentile(mut values: Vec<f32>, percentage: f32) -> f32 {
+    values.sort_by(f32::total_cmp);
+    let rank = (percentage / 100.0 * values.len() as f32).ceil() as usize;
+    values[rank.clamp(1, values.len()) - 1]
+}
+
+fn validate_percentage(percentage: f32) -> Result<(), A3dError> {
+    if percentage > 0.0 && percentage <= 100.0 {
+        Ok(())
+    } else {
+        Err(A3dError::invalid_parameter(
+            "The percentile must be in (0, 100].",
+        ))
+    }
+}
+
+/// Symmetric Hausdorff distance between two point clouds: the largest distance from a
+/// point of a cloud to its nearest neighbor in the other one. A percentile below 100
+/// makes it robust to a few outliers.
+///
+/// # Arguments
+///
+/// * lhs - The first point cloud.
+/// * rhs - The second point cloud.
+/// * percentage - Percentile of the nearest neighbor distances of each cloud, 100 for the
+///   exact Hausdorff distance.
+/// * params - Subsampling of the clouds.
+///
+/// # Returns
+///
+/// The larger of the percentiles of both clouds, or error if a cloud is empty or the
+/// percentage isn't in (0, 100].
+pub fn hausdorff_distance(
+    lhs: &PointCloud,
+    rhs: &PointCloud,
+    percentage: f32,
+    params: &CloudMetricsParams,
+) -> Result<f32, A3dError> {
+    validate_percentage(percentage)?;
+    let [lhs, rhs] = metric_points([lhs, rhs], params)?;
+    Ok(percentile(nearest_distances(&lhs, &rhs), percentage)
+        .max(percentile(nearest_distances(&rhs, &lhs), percentage)))
+}
+
+/// Distance between a point and a triangle.
+///
+/// # Arguments
+///
+/// * point - The point.
+/// * triangle - The triangle vertices.
+pub fn point_triangle_distance(point: &Vector3<f32>, triangle: [Vector3<f32>; 3]) -> f32 {
+    (point - closest_point_on_triangle(point, triangle)).norm()
+}
+
+/// Directed Hausdorff distance from a point cloud to a mesh surface, e.g., to score a
+/// reconstruction against a ground truth CAD model. The distances are to the closest
+/// point of the triangles, not only to their vertices.
+///
+/// # Arguments
+///
+/// * pcl - The point cloud.
+/// * mesh - The triangle mesh.
+/// * percentage - Percentile of the point to mesh distances, 100 for the exact Hausdorff
+///   distance.
+/// * params - Subsampling of the cloud.
+///
+/// # Returns
+///
+/// The percentile of the distances from the points to the mesh, or error if the cloud is
+/// empty, the mesh has no faces or the percentage isn't in (0, 100].
+pub fn hausdorff_distance_to_mesh(
+    pcl: &PointCloud,
+    mesh: &Geometry,
+    percentage: f32,
+    params: &CloudMetricsParams,
+) -> Result<f32, A3dError> {
+    validate_percentage(percentage)?;
+    let bvh = MeshBvh::new(mesh)?;
+    let [points] = metric_points([pcl], params)?;
+
+    let distances = points
+        .as_slice()
+        .expect("Points are contiguous")
+        .par_iter()
+        .map(|point| bvh.closest_point(point).distance)
+        .collect();
+    Ok(percentile(distances, percentage))
+}
+
+/// Mean absolute cosine between the normals of the points of a cloud and of their nearest
+/// neighbor in the other one, among the points at the indices of each cloud.
+fn mean_normal_cosine(
+    (pcl, indices): (&PointCloud, &[usize]),
+    (other, other_indices): (&PointCloud, &[usize]),
+) -> f32 {
+    let normals = pcl.normals.as_ref().expect("Checked by the caller");
+    let other_normals = other.normals.as_ref().expect("Checked by the caller");
+    let kdtree = R3dTree::new(&other.points.select(Axis(0), other_indices).view());
+    indices
+        .par_iter()
+        .map(|index| {
+            let (nearest, _) = kdtree.knn(&pcl.points[*index], 1)[0];
+            let cosine = normals[*index]
+                .normalize()
+                .dot(&other_normals[other_indices[nearest]].normalize());
+            cosine.abs()
+        })
+        .sum::<f32>()
+        / indices.len() as f32
+}
+
+/// Normal consistency between two point clouds: the mean absolute cosine between the
+/// normal of each point and the normal of its nearest neighbor in the other cloud, over
+/// both clouds. It is 1 for matching surfaces, and drops with oversmoothed edges or
+/// noisy normals, e.g., to tune smoothing or meshing parameters against a ground truth.
+///
+/// # Arguments
+///
+/// * lhs - The first point cloud, with normals.
+/// * rhs - The second point cloud, with normals.
+/// * params - Subsampling of the clouds.
+///
+/// # Returns
+///
+/// The consistency in [0, 1], the sign of the normals is ignored. Error if a cloud is
+/// empty or has no normals.
+pub fn normal_consistency(
+    lhs: &PointCloud,
+    rhs: &PointCloud,
+    params: &CloudMetricsParams,
+) -> Result<f32, A3dError> {
+    if lhs.normals.is_none() || rhs.normals.is_none() {
+        return Err(A3dError::invalid_parameter(
+            "The normal consistency requires normals on both clouds.",
+        ));
+    }
+    let [lhs_indices, rhs_indices] = metric_indices([lhs, rhs], params)?;
+    Ok(0.5
+        * (mean_normal_cosine((lhs, &lhs_indices), (rhs, &rhs_indices))
+            + mean_normal_cosine((rhs, &rhs_indices), (lhs, &lhs_indices))))
+}
+
+/// Precision, recall and F-score of a reconstruction against its ground truth, see
+/// [`reconstruction_score`].
+#[derive(Debug, Clone)]
+pub struct ReconstructionScore {
+    /// Ratio of the reconstructed points within the threshold of the ground truth, i.e.,
+    /// the accuracy.
+    pub precision: f32,
+    /// Ratio of the ground truth points within the threshold of the reconstruction, i.e.,
+    /// the completeness.
+    pub recall: f32,
+    /// Harmonic mean of the precision and the recall.
+    pub f_score: f32,
+    /// Distance from each reconstructed point to the ground truth, NaN for the points left
+    /// out by the subsampling. Attach it with [`PointCloud::set_attribute`] to export a
+    /// heatmap of the errors, e.g., with [`crate::io::write_ply_pointcloud`].
+    pub reconstruction_errors: Array1<f32>,
+    /// Distance from each ground truth point to the reconstruction, NaN for the points
+    /// left out by the subsampling.
+    pub ground_truth_errors: Array1<f32>,
+}
+
+/// Scores a reconstruction against its ground truth with the precision, recall and
+/// F-score at a distance threshold, the standard evaluation of reconstruction
+/// benchmarks. A reconstructed mesh is scored through its vertices, see
+/// [`PointCloud::from_geometry`].
+///
+/// # Arguments
+///
+/// * reconstruction - The reconstructed point cloud.
+/// * ground_truth - The ground truth point cloud, in the same frame.
+/// * threshold - Maximum distance of a point to the other cloud to count as correct.
+/// * params - Subsampling of the clouds.
+///
+/// # Returns
+///
+/// The score with the errors of each point, or error if a cloud is empty or the
+/// threshold isn't positive.
+pub fn reconstruction_score(
+    reconstruction: &PointCloud,
+    ground_truth: &PointCloud,
+    threshold: f32,
+    params: &CloudMetricsParams,
+) -> Result<ReconstructionScore, A3dError> {
+    if threshold <= 0.0 {
+        return Err(A3dError::invalid_parameter(
+            "The distance threshold must be positive.",
+        ));
+    }
+    let [reconstruction_indices, ground_truth_indices] =
+        metric_indices([reconstruction, ground_truth], params)?;
+    let reconstruction_points = reconstruction
+        .points
+        .select(Axis(0), &reconstruction_indices);
+    let ground_truth_points = ground_truth.points.select(Axis(0), &ground_truth_indices);
+
+    let ratio_within = |distances: &[f32]| {
+        distances
+            .iter()
+            .filter(|distance| **distance <= threshold)
+            .count() as f32
+            / distances.len() as f32
+    };
+    let errors = |len: usize, indices: &[usize], distances: &[f32]| {
+        let mut errors = Array1::from_elem(len, f32::NAN);
+        for (index, distance) in indices.iter().zip(distances) {
+            errors[*index] = *distance;
+        }
+        errors
+    };
+
+    let reconstruction_distances = nearest_distances(&reconstruction_points, &ground_truth_points);
+    let ground_truth_distances = nearest_distances(&ground_truth_points, &reconstruction_points);
+    let precision = ratio_within(&reconstruction_distances);
+    let recall = ratio_within(&ground_truth_distances);
+    let f_score = if precision + recall > 0.0 {
+        2.0 * precision * recall / (precision + recall)
+    } else {
+        0.0
+    };
+    Ok(ReconstructionScore {
+        precision,
+        recall,
+        f_score,
+        reconstruction_errors: errors(
+            reconstruction.len(),
+            &reconstruction_indices,
+            &reconstruction_distances,
+        ),
+        ground_truth_errors: errors(
+            ground_truth.len(),
+            &ground_truth_indices,
+            &ground_truth_distances,
+        ),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use nalgebra::{Quaternion, Vector3};
 
+    use ndarray::array;
+
     use super::*;
+    use crate::{io::GeometryBuilder, pointcloud::PointCloudBuilder};
 
     #[test]
     fn test_transform_metrics() {
@@ -91,4 +431,185 @@ mod tests {
         assert_eq!(metrics.translation, 0.0);
         assert_eq!(metrics.total(), 0.0);
     }
+
+    #[test]
+    fn test_chamfer_distance() {
+        let grid = |offset: Vector3<f32>, count: usize| {
+            PointCloudBuilder::new()
+                .with_points((0..count * count).map(|i| {
+                    Vector3::new((i % count) as f32, (i / count) as f32, 0.0) * 0.1 + offset
+                }))
+                .build()
+                .unwrap()
+        };
+        let params = CloudMetricsParams::default();
+        let lhs = grid(Vector3::zeros(), 10);
+        assert_eq!(chamfer_distance(&lhs, &lhs, &params).unwrap(), 0.0);
+
+        let shifted = grid(Vector3::new(0.0, 0.0, 0.02), 10);
+        let distance = chamfer_distance(&lhs, &shifted, &params).unwrap();
+        assert!((distance - 0.02).abs() < 1e-6);
+
+        // The points of the larger grid outside of the smaller one are 0.1 to 1.0 away.
+        let larger = grid(Vector3::zeros(), 20);
+        let distance = chamfer_distance(&lhs, &larger, &params).unwrap();
+        let symmetric = chamfer_distance(&larger, &lhs, &params).unwrap();
+        assert_eq!(distance, symmetric);
+        assert!(distance > 0.1);
+
+        let subsampled = chamfer_distance(
+            &lhs,
+            &larger,
+            &CloudMetricsParams {
+                max_points: Some(200),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        // Dropping points makes the nearest neighbors of the other cloud a bit farther.
+        assert!((subsampled - distance).abs() < 0.2 * distance);
+
+        let empty = PointCloudBuilder::new().build().unwrap();
+        assert!(chamfer_distance(&lhs, &empty, &params).is_err());
+    }
+
+    #[test]
+    fn test_hausdorff_distance() {
+        let line = |xs: &[f32]| {
+            PointCloudBuilder::new()
+                .with_points(xs.iter().map(|x| Vector3::new(*x, 0.0, 0.0)))
+                .build()
+                .unwrap()
+        };
+        let params = CloudMetricsParams::default();
+        let lhs = line(&(0..100).map(|i| i as f32 * 0.01).collect::<Vec<_>>());
+        let mut xs = (0..100).map(|i| i as f32 * 0.01).collect::<Vec<_>>();
+        xs[50] = 3.0;
+        let rhs = line(&xs);
+        assert_eq!(hausdorff_distance(&lhs, &lhs, 100.0, &params).unwrap(), 0.0);
+        let exact = hausdorff_distance(&lhs, &rhs, 100.0, &params).unwrap();
+        assert!((exact - 2.01).abs() < 1e-5);
+        // Only the outlier and the neighbors of the point it replaces are off.
+        let robust = hausdorff_distance(&lhs, &rhs, 98.0, &params).unwrap();
+        assert_eq!(robust, 0.0);
+        assert!(hausdorff_distance(&lhs, &rhs, 0.0, &params).is_err());
+    }
+
+    #[test]
+    fn test_hausdorff_distance_to_mesh() {
+        // A unit square made of 2 triangles in the XY plane.
+        let mesh = GeometryBuilder::new(array![
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(1.0, 1.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+        ])
+        .with_faces(array![[0, 1, 2], [0, 2, 3]])
+        .build();
+        let pcl = PointCloudBuilder::new()
+            .with_points([
+                Vector3::new(0.5, 0.5, 0.1),
+                Vector3::new(0.3, 0.6, -0.2),
+                Vector3::new(1.3, 0.5, 0.0),
+                Vector3::new(-0.3, -0.4, 0.0),
+            ])
+            .build()
+            .unwrap();
+        let params = CloudMetricsParams::default();
+        let distance = hausdorff_distance_to_mesh(&pcl, &mesh, 100.0, &params).unwrap();
+        assert!((distance - 0.5).abs() < 1e-6);
+        let median = hausdorff_distance_to_mesh(&pcl, &mesh, 50.0, &params).unwrap();
+        assert!((median - 0.2).abs() < 1e-6);
+
+        let triangle = [Vector3::zeros(), Vector3::x(), Vector3::y()];
+        for (point, expected) in [
+            (Vector3::new(0.2, 0.2, 1.0), 1.0),
+            (Vector3::new(2.0, 0.0, 0.0), 1.0),
+            (Vector3::new(1.0, 1.0, 0.0), 0.5f32.sqrt()),
+            (Vector3::new(-1.0, 0.5, 0.0), 1.0),
+            (Vector3::new(0.5, -2.0, 0.0), 2.0),
+        ] {
+            assert!((point_triangle_distance(&point, triangle) - expected).abs() < 1e-6);
+        }
+
+        let points = GeometryBuilder::new(mesh.points.clone()).build();
+        assert!(hausdorff_distance_to_mesh(&pcl, &points, 100.0, &params).is_err());
+    }
+
+    #[test]
+    fn test_reconstruction_score() {
+        // The ground truth is a 10x10 grid, the reconstruction misses its last 2 rows and
+        // has 10 points 1.0 away.
+        let ground_truth = PointCloudBuilder::new()
+            .with_points((0..100).map(|i| Vector3::new((i % 10) as f32, (i / 10) as f32, 0.0)))
+            .build()
+            .unwrap();
+        let reconstruction = PointCloudBuilder::new()
+            .with_points((0..80).map(|i| Vector3::new((i % 10) as f32, (i / 10) as f32, 0.05)))
+            .with_points((0..10).map(|i| Vector3::new(i as f32, 0.0, 1.0)))
+            .build()
+            .unwrap();
+        let params = CloudMetricsParams::default();
+        let score = reconstruction_score(&reconstruction, &ground_truth, 0.1, &params).unwrap();
+        assert!((score.precision - 80.0 / 90.0).abs() < 1e-6);
+        assert!((score.recall - 0.8).abs() < 1e-6);
+        let expected = 2.0 * score.precision * score.recall / (score.precision + score.recall);
+        assert!((score.f_score - expected).abs() < 1e-6);
+        assert!((score.reconstruction_errors[0] - 0.05).abs() < 1e-6);
+        assert!((score.reconstruction_errors[85] - 1.0).abs() < 1e-6);
+        assert!((score.ground_truth_errors[85] - (1.0f32 + 0.05 * 0.05).sqrt()).abs() < 1e-6);
+
+        let subsampled = reconstruction_score(
+            &reconstruction,
+            &ground_truth,
+            0.1,
+            &CloudMetricsParams {
+                max_points: Some(50),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(subsampled.reconstruction_errors.len(), 90);
+        assert_eq!(
+            subsampled
+                .reconstruction_errors
+                .iter()
+                .filter(|error| !error.is_nan())
+                .count(),
+            50
+        );
+
+        assert!(reconstruction_score(&reconstruction, &ground_truth, 0.0, &params).is_err());
+    }
+
+    #[test]
+    fn test_normal_consistency() {
+        let plane = |normal: &dyn Fn(f32) -> Vector3<f32>| {
+            PointCloudBuilder::new()
+                .with_points((0..100).map(|i| Vector3::new((i % 10) as f32, (i / 10) as f32, 0.0)))
+                .with_normals((0..100).map(|i| normal((i % 10) as f32)))
+                .build()
+                .unwrap()
+        };
+        let params = CloudMetricsParams::default();
+        let lhs = plane(&|_| Vector3::z());
+        let flipped = plane(&|_| -Vector3::z() * 2.0);
+        assert!((normal_consistency(&lhs, &flipped, &params).unwrap() - 1.0).abs() < 1e-6);
+
+        // Half of the normals are tilted by 60 degrees.
+        let tilted = plane(&|x| {
+            if x < 5.0 {
+                Vector3::z()
+            } else {
+                Vector3::new(3.0f32.sqrt() * 0.5, 0.0, 0.5)
+            }
+        });
+        assert!((normal_consistency(&lhs, &tilted, &params).unwrap() - 0.75).abs() < 1e-6);
+
+        let without_normals = PointCloudBuilder::new()
+            .with_points([Vector3::zeros()])
+            .build()
+            .unwrap();
+        assert!(normal_consistency(&lhs, &without_normals, &params).is_err());
+    }
 }
@@ -0,0 +1,464 @@
+//! Octree over points, for streaming maps and view culling.
+
+use nalgebra::Vector3;
+use ndarray::ArrayView1;
+
+use crate::bounds::{Aabb3Df, Frustum};
+
+/// Parameters of the [`Octree`].
+#[derive(Debug, Clone, Copy)]
+pub struct OctreeParams {
+    /// Maximum depth of the nodes, the root being at depth 0.
+    pub max_depth: usize,
+    /// Leaves with more points are split, unless they are at the maximum depth.
+    pub max_leaf_size: usize,
+}
+
+impl Default for OctreeParams {
+    fn default() -> Self {
+        Self {
+            max_depth: 12,
+            max_leaf_size: 32,
+        }
+    }
+}
+
+/// Summary of a node, given to the visitor of [`Octree::visit`].
+#[derive(Debug, Clone, Copy)]
+pub struct OctreeNode {
+    /// The cubic bounds of the node.
+    pub bounds: Aabb3Df,
+    pub depth: usize,
+    /// Number of points in the node and its descendants.
+    pub count: usize,
+    /// Mean of the points in the node and its descendants.
+    pub centroid: Vector3<f32>,
+    pub is_leaf: bool,
+}
+
+struct Node {
+    bounds: Aabb3Df,
+    depth: usize,
+    /// Indices of the children nodes, by octant.
+    children: Option<[usize; 8]>,
+    /// Indices of the points of a leaf.
+    indices: Vec<usize>,
+    count: usize,
+    sum: Vector3<f32>,
+}
+
+impl Node {
+    fn new(bounds: Aabb3Df, depth: usize) -> Self {
+        Self {
+            bounds,
+            depth,
+            children: None,
+            indices: Vec::new(),
+            count: 0,
+            sum: Vector3::zeros(),
+        }
+    }
+}
+
+/// Octant of a point in a node, one bit per axis set on the upper half.
+fn octant(bounds: &Aabb3Df, point: &Vector3<f32>) -> usize {
+    let center = bounds.center();
+    (0..3)
+        .filter(|axis| point[*axis] >= center[*axis])
+        .fold(0, |octant, axis| octant | (1 << axis))
+}
+
+/// Bounds of the child of a node in an octant.
+fn octant_bounds(bounds: &Aabb3Df, octant: usize) -> Aabb3Df {
+    let center = bounds.center();
+    let (mut min, mut max) = (bounds.min, center);
+    for axis in 0..3 {
+        if octant & (1 << axis) != 0 {
+            min[axis] = center[axis];
+            max[axis] = bounds.max[axis];
+        }
+    }
+    Aabb3Df::new(min, max)
+}
+
+fn is_finite(point: &Vector3<f32>) -> bool {
+    point.iter().all(|value| value.is_finite())
+}
+
+/// Squared distance between a point and a box, 0 inside.
+fn sqr_distance_to_box(bounds: &Aabb3Df, point: &Vector3<f32>) -> f32 {
+    (point - point.sup(&bounds.min).inf(&bounds.max)).norm_squared()
+}
+
+/// An octree of points, splitting the leaves as points are inserted. Unlike
+/// [`crate::kdtree::R3dTree`], points can be added without rebuilding it, and its nodes
+/// are cubes that can be culled against a view and summarized for level of detail.
+pub struct Octree {
+    params: OctreeParams,
+    points: Vec<Vector3<f32>>,
+    nodes: Vec<Node>,
+    root: usize,
+}
+
+impl Octree {
+    /// Creates an octree of points.
+    ///
+    /// # Arguments
+    ///
+    /// * points - The points, indexed by their position. Non-finite points, e.g., invalid
+    ///   depth readings, keep their index but are left out of the tree.
+    /// * params - Depth and leaf size of the tree.
+    pub fn new(points: &ArrayView1<Vector3<f32>>, params: OctreeParams) -> Self {
+        let bounds = Aabb3Df::from_point_iter(points.iter().copied().filter(is_finite));
+        let bounds = if bounds.is_empty() {
+            Aabb3Df::new(Vector3::zeros(), Vector3::repeat(1.0))
+        } else {
+            // A cube, slightly larger than the points.
+            let half_size = (bounds.extents().max() * 0.5).max(f32::EPSILON) * 1.01;
+            let center = bounds.center();
+            Aabb3Df::new(center.add_scalar(-half_size), center.add_scalar(half_size))
+        };
+        let mut octree = Self {
+            params,
+            points: Vec::with_capacity(points.len()),
+            nodes: vec![Node::new(bounds, 0)],
+            root: 0,
+        };
+        for point in points.iter() {
+            if octree.insert(*point).is_none() {
+                octree.points.push(*point);
+            }
+        }
+        octree
+    }
+
+    /// Number of points, including the non-finite ones given to [`Octree::new`].
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// The bounds of the root node.
+    pub fn bounds(&self) -> Aabb3Df {
+        self.nodes[self.root].bounds
+    }
+
+    /// Inserts a point, growing the root if the point is outside of it.
+    ///
+    /// # Returns
+    ///
+    /// The index of the point, following the ones given to [`Octree::new`], or `None` if
+    /// the point isn't finite, as no root can contain it.
+    pub fn insert(&mut self, point: Vector3<f32>) -> Option<usize> {
+        if !is_finite(&point) {
+            return None;
+        }
+        while !self.nodes[self.root].bounds.contains(&point) {
+            self.grow_towards(&point);
+        }
+        let index = self.points.len();
+        self.points.push(point);
+
+        let mut node = self.root;
+        loop {
+            self.nodes[node].count += 1;
+            self.nodes[node].sum += point;
+            match self.nodes[node].children {
+                Some(children) => node = children[octant(&self.nodes[node].bounds, &point)],
+                None => {
+                    self.nodes[node].indices.push(index);
+                    if self.nodes[node].indices.len() > self.params.max_leaf_size
+                        && self.nodes[node].depth < self.params.max_depth
+                    {
+                        self.split(node);
+                    }
+                    return Some(index);
+                }
+            }
+        }
+    }
+
+    /// Moves the points of a leaf to new children.
+    fn split(&mut self, node: usize) {
+        let (bounds, depth) = (self.nodes[node].bounds, self.nodes[node].depth);
+        let first_child = self.nodes.len();
+        self.nodes
+            .extend((0..8).map(|octant| Node::new(octant_bounds(&bounds, octant), depth + 1)));
+        for index in std::mem::take(&mut self.nodes[node].indices) {
+            let point = self.points[index];
+            let child = &mut self.nodes[first_child + octant(&bounds, &point)];
+            child.indices.push(index);
+            child.count += 1;
+            child.sum += point;
+        }
+        self.nodes[node].children = Some([0, 1, 2, 3, 4, 5, 6, 7].map(|i| first_child + i));
+    }
+
+    /// Doubles the root size in the direction of a point, the current root becoming one of
+    /// the children of the new one.
+    fn grow_towards(&mut self, point: &Vector3<f32>) {
+        let old_root = &self.nodes[self.root];
+        let (old_bounds, count, sum) = (old_root.bounds, old_root.count, old_root.sum);
+        let size = old_bounds.extents();
+        let mut min = old_bounds.min;
+        for axis in 0..3 {
+            if point[axis] < old_bounds.min[axis] {
+                min[axis] -= size[axis];
+            }
+        }
+        let bounds = Aabb3Df::new(min, min + size * 2.0);
+
+        for node in self.nodes.iter_mut() {
+            node.depth += 1;
+        }
+        let old_octant = octant(&bounds, &old_bounds.center());
+        let mut children = [0; 8];
+        for (octant, child) in children.iter_mut().enumerate() {
+            *child = if octant == old_octant {
+                self.root
+            } else {
+                self.nodes
+                    .push(Node::new(octant_bounds(&bounds, octant), 1));
+                self.nodes.len() - 1
+            };
+        }
+        self.nodes.push(Node {
+            children: Some(children),
+            count,
+            sum,
+            ..Node::new(bounds, 0)
+        });
+        self.root = self.nodes.len() - 1;
+    }
+
+    /// Visits the nodes from the root, depth first, e.g., to draw the points at a level of
+    /// detail that depends on the distance to the viewer.
+    ///
+    /// # Arguments
+    ///
+    /// * visitor - Called with each visited node, returns whether to visit its children.
+    pub fn visit(&self, mut visitor: impl FnMut(&OctreeNode) -> bool) {
+        let mut stack = vec![self.root];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            let summary = OctreeNode {
+                bounds: node.bounds,
+                depth: node.depth,
+                count: node.count,
+                centroid: if node.count > 0 {
+                    node.sum / node.count as f32
+                } else {
+                    node.bounds.center()
+                },
+                is_leaf: node.children.is_none(),
+            };
+            if visitor(&summary) {
+                if let Some(children) = node.children {
+                    stack.extend(children.iter().rev());
+                }
+            }
+        }
+    }
+
+    /// The centroids of the non-empty nodes at a depth, and of the shallower non-empty
+    /// leaves: a simplified version of the points with about one point per cell of that
+    /// level.
+    pub fn level_of_detail(&self, depth: usize) -> Vec<Vector3<f32>> {
+        let mut centroids = Vec::new();
+        self.visit(|node| {
+            if node.count == 0 {
+                return false;
+            }
+            if node.depth == depth || node.is_leaf {
+                centroids.push(node.centroid);
+                return false;
+            }
+            true
+        });
+        centroids
+    }
+
+    /// Finds all the points within a radius of a query point.
+    ///
+    /// # Returns
+    ///
+    /// Tuples with the index and squared distance of the neighbors, in no particular order.
+    pub fn radius_search(&self, point: &Vector3<f32>, radius: f32) -> Vec<(usize, f32)> {
+        let sqr_radius = radius * radius;
+        let mut found = Vec::new();
+        if radius < 0.0 {
+            return found;
+        }
+        let mut stack = vec![self.root];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if node.count == 0 || sqr_distance_to_box(&node.bounds, point) > sqr_radius {
+                continue;
+            }
+            match node.children {
+                Some(children) => stack.extend(children),
+                None => found.extend(node.indices.iter().filter_map(|index| {
+                    let distance = (self.points[*index] - point).norm_squared();
+                    (distance <= sqr_radius).then_some((*index, distance))
+                })),
+            }
+        }
+        found
+    }
+
+    /// Finds the points inside a view frustum. The nodes outside of it are skipped and the
+    /// ones fully inside aren't tested point by point.
+    ///
+    /// # Returns
+    ///
+    /// The indices of the points, in no particular order.
+    pub fn frustum_search(&self, frustum: &Frustum) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut stack = vec![(self.root, false)];
+        while let Some((node, inside)) = stack.pop() {
+            let node = &self.nodes[node];
+            if node.count == 0 {
+                continue;
+            }
+            let inside = inside
+                || node
+                    .bounds
+                    .corners()
+                    .iter()
+                    .all(|corner| frustum.contains(corner));
+            if !inside && !frustum.intersects_aabb(&node.bounds) {
+                continue;
+            }
+            match node.children {
+                Some(children) => stack.extend(children.map(|child| (child, inside))),
+                None if inside => found.extend_from_slice(&node.indices),
+                None => found.extend(
+                    node.indices
+                        .iter()
+                        .filter(|index| frustum.contains(&self.points[**index])),
+                ),
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Array1;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::{Octree, OctreeParams};
+    use crate::{
+        bounds::Frustum,
+        camera::{CameraIntrinsics, PinholeCamera},
+        transform::TransformBuilder,
+    };
+
+    fn random_points(count: usize, seed: u64) -> Array1<Vector3<f32>> {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        Array1::from_shape_fn(count, |_| {
+            Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>())
+        })
+    }
+
+    #[test]
+    fn should_search_within_radius() {
+        let points = random_points(2000, 3);
+        let mut octree = Octree::new(&points.view(), OctreeParams::default());
+        // Grows the root twice.
+        let far = [Vector3::new(2.5, -1.5, 0.5), Vector3::new(-3.0, 4.0, 1.0)];
+        for (index, point) in far.into_iter().enumerate() {
+            assert_eq!(octree.insert(point), Some(2000 + index));
+        }
+        assert_eq!(octree.len(), 2002);
+        assert!(octree.bounds().contains(&far[1]));
+
+        let all = points.iter().chain(far.iter()).collect::<Vec<_>>();
+        for query in points.iter().step_by(200).chain(far.iter()) {
+            let mut expected = all
+                .iter()
+                .enumerate()
+                .map(|(index, point)| (index, (*point - query).norm_squared()))
+                .filter(|(_, sqr_distance)| *sqr_distance <= 0.2 * 0.2)
+                .collect::<Vec<_>>();
+            expected.sort_by_key(|(index, _)| *index);
+            let mut found = octree.radius_search(query, 0.2);
+            found.sort_by_key(|(index, _)| *index);
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn should_skip_non_finite_points() {
+        let mut points = random_points(100, 5);
+        points[10] = Vector3::new(f32::NAN, 0.5, 0.5);
+        points[20] = Vector3::new(0.5, f32::INFINITY, 0.5);
+        let mut octree = Octree::new(
+            &points.view(),
+            OctreeParams {
+                max_depth: 4,
+                max_leaf_size: 4,
+            },
+        );
+        assert_eq!(octree.len(), 100);
+        assert!(octree.bounds().max.iter().all(|value| *value <= 2.0));
+        assert_eq!(octree.insert(Vector3::new(0.5, 0.5, f32::NAN)), None);
+        assert_eq!(octree.insert(Vector3::new(0.5, 0.5, 0.5)), Some(100));
+
+        let mut found = octree
+            .radius_search(&Vector3::new(0.5, 0.5, 0.5), 2.0)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        found.sort_unstable();
+        let expected = (0..=100)
+            .filter(|index| *index != 10 && *index != 20)
+            .collect::<Vec<_>>();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn should_search_frustum_and_simplify() {
+        let points = random_points(5000, 4);
+        let octree = Octree::new(
+            &points.view(),
+            OctreeParams {
+                max_depth: 4,
+                max_leaf_size: 8,
+            },
+        );
+
+        let camera = PinholeCamera::new(
+            CameraIntrinsics::from_simple_intrinsic(50.0, 50.0, 50.0, 50.0, 100, 100),
+            TransformBuilder::default()
+                .translation(Vector3::new(0.3, 0.6, -0.5))
+                .build(),
+        );
+        let frustum = Frustum::new(&camera, 0.6, 1.2);
+        let mut found = octree.frustum_search(&frustum);
+        found.sort_unstable();
+        let expected = (0..points.len())
+            .filter(|index| frustum.contains(&points[*index]))
+            .collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+        assert_eq!(found, expected);
+
+        // At most one point per cell of the 8x8x8 grid at depth 3.
+        let simplified = octree.level_of_detail(3);
+        assert!(simplified.len() <= 512 && simplified.len() > 400);
+        let mut count = 0;
+        octree.visit(|node| {
+            if node.is_leaf {
+                count += node.count;
+            }
+            assert!(node.depth <= 4);
+            true
+        });
+        assert_eq!(count, points.len());
+    }
+}
@@ -0,0 +1,477 @@
+//! Frame by frame camera tracking of RGB-D streams.
+
+use crate::{
+    error::A3dError,
+    icp::{multiscale::MultiscaleAlign, IcpResult, MsIcpParams},
+    io::Geometry,
+    pipeline::PipelineStage,
+    pointcloud::PointCloud,
+    range_image::{RangeImage, RangeImageBuilder},
+    reconstruction::{FusionParams, VoxelFusion},
+    trajectory::Trajectory,
+    transform::{Transform, Transformable},
+    RgbdFrame,
+};
+
+/// A user stage run on the frames before the range image processing.
+pub type FrameStage = Box<dyn PipelineStage<Input = RgbdFrame, Output = RgbdFrame>>;
+
+/// Configures an [`OdometryPipeline`]. Every stage has default parameters.
+#[derive(Default)]
+pub struct OdometryPipelineBuilder {
+    preprocess: Option<FrameStage>,
+    range_image: RangeImageBuilder,
+    icp: MsIcpParams,
+    fusion: Option<FusionParams>,
+    frame_to_model: bool,
+}
+
+impl OdometryPipelineBuilder {
+    /// Runs a custom stage on every frame before the range image processing, e.g., a
+    /// depth filter or a [`FrameModelStage`](crate::pipeline::FrameModelStage). Chain
+    /// several stages with [`PipelineStage::then`]. None by default.
+    pub fn preprocess<S>(mut self, stage: S) -> Self
+    where
+        S: PipelineStage<Input = RgbdFrame, Output = RgbdFrame> + 'static,
+    {
+        self.preprocess = Some(Box::new(stage));
+        self
+    }
+
+    /// Sets the range image processing, e.g., its bilateral filter and normal estimation.
+    /// Its number of pyramid levels is replaced by the number of levels of the ICP
+    /// parameters.
+    pub fn range_image(mut self, builder: RangeImageBuilder) -> Self {
+        self.range_image = builder;
+        self
+    }
+
+    /// Sets the ICP parameters of each pyramid level.
+    pub fn icp(mut self, params: MsIcpParams) -> Self {
+        self.icp = params;
+        self
+    }
+
+    /// Fuses the tracked frames into a voxel model, see [`OdometryPipeline::into_model`].
+    /// Disabled by default.
+    pub fn fusion(mut self, params: Option<FusionParams>) -> Self {
+        self.fusion = params;
+        self
+    }
+
+    /// Aligns each frame against a prediction rendered from the fused model at the last
+    /// pose, instead of against the previous frame. Requires the fusion. Disabled by
+    /// default.
+    pub fn frame_to_model(mut self, value: bool) -> Self {
+        self.frame_to_model = value;
+        self
+    }
+
+    /// Creates the pipeline.
+    ///
+    /// # Returns
+    ///
+    /// The pipeline, or error if there are no ICP levels, the fusion voxel size isn't
+    /// positive or the frame-to-model alignment lacks the fusion.
+    pub fn build(self) -> Result<OdometryPipeline, A3dError> {
+        if self.icp.is_empty() {
+            return Err(A3dError::invalid_parameter(
+                "The odometry needs at least one ICP level.",
+            ));
+        }
+        if let Some(fusion) = self.fusion {
+            if fusion.voxel_size <= 0.0 {
+                return Err(A3dError::invalid_parameter(
+                    "The fusion voxel size must be positive.",
+                ));
+            }
+        } else if self.frame_to_model {
+            return Err(A3dError::invalid_parameter(
+                "The frame-to-model alignment requires the fusion.",
+            ));
+        }
+
+        Ok(OdometryPipeline {
+            preprocess: self.preprocess,
+            range_processing: self.range_image.pyramid_levels(self.icp.len()),
+            icp: self.icp,
+            model: self.fusion.map(VoxelFusion::new),
+            frame_to_model: self.frame_to_model,
+            last_frame: None,
+            last_result: None,
+            camera_to_world: Transform::eye(),
+            trajectory: Trajectory::default(),
+        })
+    }
+}
+
+/// The state of an [`OdometryPipeline`] besides its trajectory, captured by
+/// [`Session::capture`](crate::session::Session::capture) for checkpointing.
+#[derive(Debug, Clone)]
+pub(crate) struct OdometryState {
+    /// Range image pyramid of the last frame, the target of the next alignment.
+    pub(crate) last_frame: Option<Vec<RangeImage>>,
+    /// The fused model, if the fusion is enabled.
+    pub(crate) model: Option<VoxelFusion>,
+}
+
+/// Tracks the camera of an RGB-D stream. Each frame goes through the custom stage, if
+/// any, and the range image processing, is aligned by multiscale ICP to the previous frame, or to the fused model,
+/// and its pose is accumulated into the trajectory. The first frame is at the origin.
+pub struct OdometryPipeline {
+    preprocess: Option<FrameStage>,
+    range_processing: RangeImageBuilder,
+    icp: MsIcpParams,
+    model: Option<VoxelFusion>,
+    frame_to_model: bool,
+    last_frame: Option<Vec<RangeImage>>,
+    last_result: Option<IcpResult>,
+    camera_to_world: Transform,
+    trajectory: Trajectory,
+}
+
+impl OdometryPipeline {
+    /// Starts configuring a pipeline.
+    ///
+    /// ```no_run
+    /// use align3d::{
+    ///     io::dataset::{DatasetIter, FrameErrorPolicy},
+    ///     odometry::OdometryPipeline,
+    ///     prelude::*,
+    /// };
+    ///
+    /// let dataset = DatasetRegistry::default()
+    ///     .load("slamtb", "tests/data/rgbd/sample1")
+    ///     .unwrap();
+    /// let mut odometry = OdometryPipeline::builder().build().unwrap();
+    /// for (_, frame) in DatasetIter::new(dataset.as_ref(), FrameErrorPolicy::Skip).flatten() {
+    ///     let camera_to_world = odometry.process(frame).unwrap();
+    /// }
+    /// ```
+    pub fn builder() -> OdometryPipelineBuilder {
+        OdometryPipelineBuilder::default()
+    }
+
+    /// Tracks a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The next frame of the stream. Its trajectory time is its
+    ///   `metadata.timestamp` relative to the trajectory's `time_origin`, else its
+    ///   `metadata.frame_id`, else its position in the stream.
+    ///
+    /// # Returns
+    ///
+    /// The camera to world transformation of the frame, or error if the custom stage
+    /// failed.
+    pub fn process(&mut self, frame: RgbdFrame) -> Result<Transform, A3dError> {
+        let frame = match self.preprocess.as_mut() {
+            Some(stage) => stage.process(frame)?,
+            None => frame,
+        };
+        let time = match (frame.metadata.timestamp, frame.metadata.frame_id) {
+            (Some(timestamp), _) => {
+                if self.trajectory.is_empty() {
+                    self.trajectory.time_origin = timestamp;
+                }
+                (timestamp - self.trajectory.time_origin) as f32
+            }
+            (None, Some(id)) => id as f32,
+            (None, None) => self.trajectory.len() as f32,
+        };
+        let current_frame = self.range_processing.build(frame);
+
+        if let Some(last_frame) = self.last_frame.take() {
+            let target = match (&self.model, self.frame_to_model) {
+                (Some(model), true) => self.range_processing.build_pyramid(
+                    model.render(&current_frame[0].intrinsics, &self.camera_to_world),
+                ),
+                _ => last_frame,
+            };
+            let result = MultiscaleAlign::new(self.icp.clone(), &target)?.align(&current_frame);
+            self.camera_to_world = &self.camera_to_world * &result.transform;
+            self.last_result = Some(result);
+        }
+        self.trajectory.push(self.camera_to_world.clone(), time);
+
+        if let Some(model) = self.model.as_mut() {
+            model.add(
+                &self
+                    .camera_to_world
+                    .transform(&PointCloud::from(&current_frame[0])),
+            );
+        }
+        self.last_frame = Some(current_frame);
+        Ok(self.camera_to_world.clone())
+    }
+
+    /// Replaces the ICP parameters for the next frames, e.g., after a live config reload.
+    ///
+    /// # Returns
+    ///
+    /// Error if the number of levels differs from the current one, as it sets the
+    /// range image pyramid of the already processed frame.
+    pub fn set_icp(&mut self, params: MsIcpParams) -> Result<(), A3dError> {
+        if params.len() != self.icp.len() {
+            return Err(A3dError::invalid_parameter(format!(
+                "The odometry has {} ICP levels, got {}.",
+                self.icp.len(),
+                params.len()
+            )));
+        }
+        self.icp = params;
+        Ok(())
+    }
+
+    /// Camera to world transformation of the last frame.
+    pub fn pose(&self) -> &Transform {
+        &self.camera_to_world
+    }
+
+    /// Camera poses of the processed frames.
+    pub fn trajectory(&self) -> &Trajectory {
+        &self.trajectory
+    }
+
+    /// Range image pyramid of the last frame, the finest level first.
+    pub fn last_frame(&self) -> Option<&[RangeImage]> {
+        self.last_frame.as_deref()
+    }
+
+    /// ICP result of the last frame, `None` before the second frame.
+    pub fn last_result(&self) -> Option<&IcpResult> {
+        self.last_result.as_ref()
+    }
+
+    /// Snapshot of the state, see [`OdometryPipeline::restore`].
+    pub(crate) fn state(&self) -> OdometryState {
+        OdometryState {
+            last_frame: self.last_frame.clone(),
+            model: self.model.clone(),
+        }
+    }
+
+    /// Continues tracking from a captured state, as if the frames of the trajectory had
+    /// been processed by this pipeline. The ICP result of the last frame isn't restored.
+    ///
+    /// # Arguments
+    ///
+    /// * `trajectory` - The trajectory of the processed frames, its last pose is the
+    ///   current one.
+    /// * `state` - The state captured with the same parameters.
+    ///
+    /// # Returns
+    ///
+    /// Error if the state was captured with another fusion voxel size or number of ICP
+    /// levels, or with the fusion enabled and not in this pipeline, or vice versa.
+    pub(crate) fn restore(
+        &mut self,
+        trajectory: Trajectory,
+        state: OdometryState,
+    ) -> Result<(), A3dError> {
+        let OdometryState {
+            mut last_frame,
+            mut model,
+        } = state;
+        match (&mut model, &self.model) {
+            (Some(model), Some(current)) => {
+                if model.params.voxel_size != current.params.voxel_size {
+                    return Err(A3dError::invalid_parameter(format!(
+                        "The session has a fusion voxel size of {}, the pipeline {}.",
+                        model.params.voxel_size, current.params.voxel_size
+                    )));
+                }
+                model.params = current.params;
+            }
+            (None, None) => {}
+            _ => {
+                return Err(A3dError::invalid_parameter(
+                    "The session and the pipeline differ in whether the fusion is enabled.",
+                ))
+            }
+        }
+        if let Some(pyramid) = last_frame.as_mut() {
+            if pyramid.len() != self.icp.len() {
+                return Err(A3dError::invalid_parameter(format!(
+                    "The session has {} pyramid levels, the pipeline {} ICP levels.",
+                    pyramid.len(),
+                    self.icp.len()
+                )));
+            }
+            if self.range_processing.with_intensity {
+                for image in pyramid.iter_mut() {
+                    image.compute_intensity();
+                    image.compute_intensity_map();
+                }
+            }
+        }
+
+        self.camera_to_world = trajectory
+            .camera_to_world
+            .last()
+            .cloned()
+            .unwrap_or_else(Transform::eye);
+        self.trajectory = trajectory;
+        self.last_frame = last_frame;
+        self.model = model;
+        self.last_result = None;
+        Ok(())
+    }
+
+    /// Consumes the pipeline into its trajectory and fused model.
+    ///
+    /// # Returns
+    ///
+    /// The trajectory, and the fused point cloud if the fusion is enabled.
+    pub fn into_model(self) -> (Trajectory, Option<Geometry>) {
+        (
+            self.trajectory,
+            self.model.map(|model| model.into_geometry()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::OdometryPipeline;
+    use crate::{
+        error::A3dError,
+        icp::{IcpParams, MsIcpParams},
+        image::RgbdFrame,
+        io::dataset::RgbdDataset,
+        metadata::Metadata,
+        metrics::TransformMetrics,
+        pipeline::from_fn,
+        reconstruction::FusionParams,
+        unit_test::sample_rgbd_dataset1,
+    };
+
+    #[rstest]
+    fn test_process(sample_rgbd_dataset1: impl RgbdDataset) {
+        let gt_trajectory = sample_rgbd_dataset1
+            .trajectory()
+            .unwrap()
+            .slice(0, 3)
+            .first_frame_at_origin();
+        let mut odometry = OdometryPipeline::builder()
+            .icp(MsIcpParams::repeat(
+                2,
+                &IcpParams {
+                    max_iterations: 5,
+                    ..Default::default()
+                },
+            ))
+            .build()
+            .unwrap();
+
+        for i in 0..3 {
+            odometry
+                .process(sample_rgbd_dataset1.get(i).unwrap())
+                .unwrap();
+        }
+        assert_eq!(odometry.trajectory().len(), 3);
+        assert!(odometry.set_icp(MsIcpParams::default()).is_err());
+        assert_eq!(odometry.last_frame().unwrap().len(), 2);
+        assert!(odometry.last_result().is_some());
+        let metrics =
+            TransformMetrics::mean_trajectory_error(odometry.trajectory(), &gt_trajectory).unwrap();
+        assert!(metrics.translation < 0.02, "{metrics}");
+
+        let (_, model) = odometry.into_model();
+        assert!(model.is_none());
+
+        assert!(OdometryPipeline::builder()
+            .frame_to_model(true)
+            .build()
+            .is_err());
+        assert!(OdometryPipeline::builder()
+            .fusion(Some(FusionParams {
+                voxel_size: 0.0,
+                ..Default::default()
+            }))
+            .build()
+            .is_err());
+    }
+
+    #[rstest]
+    fn test_process_timestamps(sample_rgbd_dataset1: impl RgbdDataset) {
+        let mut odometry = OdometryPipeline::builder()
+            .icp(MsIcpParams::repeat(
+                1,
+                &IcpParams {
+                    max_iterations: 2,
+                    ..Default::default()
+                },
+            ))
+            .build()
+            .unwrap();
+
+        // TUM frames carry both their index and their Unix timestamp.
+        for (i, timestamp) in [1305031102.175304, 1305031102.211214].iter().enumerate() {
+            let frame = sample_rgbd_dataset1.get(i).unwrap();
+            odometry
+                .process(frame.with_metadata(Metadata::from_frame(
+                    "tum:rgbd_dataset_freiburg1_xyz",
+                    i + 10,
+                    Some(*timestamp),
+                )))
+                .unwrap();
+        }
+        let trajectory = odometry.trajectory();
+        assert_eq!(trajectory.time_origin, 1305031102.175304);
+        assert_eq!(trajectory.times[0], 0.0);
+        assert!((trajectory.times[1] - 0.03591).abs() < 1e-5);
+
+        let mut odometry = OdometryPipeline::builder().build().unwrap();
+        let frame = sample_rgbd_dataset1.get(0).unwrap();
+        odometry
+            .process(frame.with_metadata(Metadata::from_frame("slamtb", 7, None)))
+            .unwrap();
+        assert_eq!(odometry.trajectory().times, [7.0]);
+    }
+
+    #[rstest]
+    fn test_preprocess(sample_rgbd_dataset1: impl RgbdDataset) {
+        // Discards the left half of the depth image.
+        let mut odometry = OdometryPipeline::builder()
+            .preprocess(from_fn(|mut frame: RgbdFrame| {
+                let half_width = frame.image.width() / 2;
+                frame
+                    .image
+                    .depth
+                    .columns_mut()
+                    .into_iter()
+                    .take(half_width)
+                    .for_each(|mut column| column.fill(0));
+                Ok(frame)
+            }))
+            .build()
+            .unwrap();
+        odometry
+            .process(sample_rgbd_dataset1.get(0).unwrap())
+            .unwrap();
+
+        let range_image = &odometry.last_frame().unwrap()[0];
+        let half_width = range_image.width() / 2;
+        assert!(range_image
+            .mask
+            .columns()
+            .into_iter()
+            .take(half_width)
+            .all(|column| column.iter().all(|mask| *mask == 0)));
+        assert!(range_image.valid_points_count() > 0);
+
+        let mut failing = OdometryPipeline::builder()
+            .preprocess(from_fn(|_: RgbdFrame| -> Result<RgbdFrame, A3dError> {
+                Err(A3dError::invalid_parameter("corrupt frame"))
+            }))
+            .build()
+            .unwrap();
+        assert!(failing
+            .process(sample_rgbd_dataset1.get(0).unwrap())
+            .is_err());
+        assert!(failing.trajectory().is_empty());
+    }
+}
@@ -30,6 +30,30 @@ impl<const DIM: usize> GaussNewton<DIM> {
         }
     }
 
+    /// Creates an optimizer from already accumulated normal equations, e.g., reduced on
+    /// the GPU.
+    ///
+    /// # Arguments
+    ///
+    /// * `hessian` - The sum of the weighted `J^T J`.
+    /// * `gradient` - The sum of the weighted `J^T r`.
+    /// * `squared_residual_sum` - The sum of the weighted squared residuals.
+    /// * `count` - The number of steps.
+    #[cfg_attr(not(feature = "viz"), allow(dead_code))]
+    pub fn from_normal_equations(
+        hessian: SMatrix<f32, DIM, DIM>,
+        gradient: SVector<f32, DIM>,
+        squared_residual_sum: f32,
+        count: usize,
+    ) -> Self {
+        Self {
+            hessian,
+            gradient,
+            squared_residual_sum,
+            count,
+        }
+    }
+
     /// Resets the optimizer.
     pub fn reset(&mut self) {
         self.hessian.set_zero();
@@ -38,22 +62,23 @@ impl<const DIM: usize> GaussNewton<DIM> {
         self.count = 0;
     }
 
-    /// Adds a new step to the optimizer.
+    /// Adds a new step to the optimizer, scaling its contribution by a weight.
     ///
     /// # Arguments
     ///
     /// * `residual` - The residual of the step.
     /// * `jacobian` - The jacobian of the step.
-    pub fn step(&mut self, residual: f32, jacobian: &[f32; DIM]) {
+    /// * `weight` - The weight of the step, e.g., the confidence of the measurement.
+    pub fn weighted_step(&mut self, residual: f32, jacobian: &[f32; DIM], weight: f32) {
         let mut jt_j = [[0.0; DIM]; DIM];
         for i in 0..DIM {
             let ival = jacobian[i];
-            self.gradient[i] += ival * residual;
+            self.gradient[i] += weight * ival * residual;
 
-            jt_j[i][i] = ival * ival;
+            jt_j[i][i] = weight * ival * ival;
             for j in i + 1..DIM {
                 let jval = jacobian[j];
-                let mul = ival * jval;
+                let mul = weight * ival * jval;
                 jt_j[i][j] = mul;
                 jt_j[j][i] = mul;
             }
@@ -66,7 +91,7 @@ impl<const DIM: usize> GaussNewton<DIM> {
             }
         }
 
-        self.squared_residual_sum += residual * residual;
+        self.squared_residual_sum += weight * residual * residual;
 
         // Improved test results.
         // for i in 0..DIM {
@@ -127,6 +152,21 @@ impl<const DIM: usize> GaussNewton<DIM> {
         self.squared_residual_sum *= weight;
     }
 
+    /// Returns the accumulated Hessian approximation, `J^T W J`.
+    pub fn hessian(&self) -> &SMatrix<f32, DIM, DIM> {
+        &self.hessian
+    }
+
+    /// Returns the accumulated gradient, `J^T W r`.
+    pub fn gradient(&self) -> &SVector<f32, DIM> {
+        &self.gradient
+    }
+
+    /// Returns the number of steps added.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
     /// Returns the mean squared residual.
     pub fn mean_squared_residual(&self) -> f32 {
         self.squared_residual_sum / self.count as f32
@@ -1,5 +1,13 @@
 mod gaussnewton;
 pub use gaussnewton::GaussNewton;
 
-mod robust_estimator;
-pub use robust_estimator::{HuberEstimator, RobustEstimator};
+mod robust_loss;
+pub use robust_loss::{RobustKernel, RobustLoss};
+
+mod sparse_cholesky;
+
+mod pose_graph;
+pub use pose_graph::{PoseGraph, PoseGraphEdge, PoseGraphParams, PoseGraphResult};
+
+mod pose_graph_io;
+pub use pose_graph_io::{read_g2o, read_toro, write_g2o, write_toro};
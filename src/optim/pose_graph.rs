@@ -0,0 +1,419 @@
+use nalgebra::{Matrix6, Vector3, Vector6};
+
+use super::{
+    robust_loss::{RobustKernel, RobustLoss},
+    sparse_cholesky::BlockSparseMatrix,
+};
+use crate::{
+    error::A3dError,
+    trajectory::Trajectory,
+    transform::{LieGroup, Transform},
+};
+
+/// Relative pose measurement between two nodes of a [`PoseGraph`], e.g., from odometry or
+/// a loop closure.
+#[derive(Debug, Clone)]
+pub struct PoseGraphEdge {
+    /// Index of the first node.
+    pub from: usize,
+    /// Index of the second node.
+    pub to: usize,
+    /// Measured transformation from the `to` node frame into the `from` node frame, i.e.,
+    /// `from⁻¹ * to`.
+    pub transform: Transform,
+    /// Information matrix of the measurement, in the order of [`Transform::exp`].
+    pub information: Matrix6<f32>,
+    /// Robust kernel applied to the Mahalanobis distance of the edge error, use it on
+    /// loop closures that may be wrong.
+    pub robust_kernel: Option<RobustKernel>,
+}
+
+impl PoseGraphEdge {
+    /// Creates an edge without robust kernel.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Index of the first node.
+    /// * `to` - Index of the second node.
+    /// * `transform` - Transformation from the `to` node frame into the `from` node frame.
+    /// * `information` - Information matrix of the measurement.
+    pub fn new(from: usize, to: usize, transform: Transform, information: Matrix6<f32>) -> Self {
+        Self {
+            from,
+            to,
+            transform,
+            information,
+            robust_kernel: None,
+        }
+    }
+
+    /// Sets the robust kernel of the edge.
+    pub fn with_robust_kernel(mut self, kernel: RobustKernel) -> Self {
+        self.robust_kernel = Some(kernel);
+        self
+    }
+
+    /// Error of the edge, the twist between the measured and the current relative poses.
+    fn error(&self, nodes: &[Transform]) -> Vector6<f32> {
+        (&(&self.transform.inverse() * &nodes[self.from].inverse()) * &nodes[self.to]).log()
+    }
+}
+
+/// Second order approximation of the inverse left Jacobian of SE(3), the derivative of
+/// `log(exp(δ) * exp(error))` by `δ`, which is the identity for small errors.
+fn inverse_left_jacobian(error: &Vector6<f64>) -> Matrix6<f64> {
+    let translation = Vector3::new(error[0], error[1], error[2]).cross_matrix();
+    let rotation = Vector3::new(error[3], error[4], error[5]).cross_matrix();
+    let mut adjoint = Matrix6::zeros();
+    adjoint.fixed_view_mut::<3, 3>(0, 0).copy_from(&rotation);
+    adjoint.fixed_view_mut::<3, 3>(0, 3).copy_from(&translation);
+    adjoint.fixed_view_mut::<3, 3>(3, 3).copy_from(&rotation);
+    Matrix6::identity() - adjoint * 0.5 + adjoint * adjoint / 12.0
+}
+
+/// Parameters of [`PoseGraph::optimize`].
+#[derive(Debug, Clone, Copy)]
+pub struct PoseGraphParams {
+    /// Maximum number of Gauss-Newton iterations.
+    pub max_iterations: usize,
+    /// Converged when no node update is larger than this, as the norm of its twist.
+    pub min_update: f32,
+}
+
+impl Default for PoseGraphParams {
+    fn default() -> Self {
+        Self {
+            max_iterations: 20,
+            min_update: 1e-5,
+        }
+    }
+}
+
+/// Result of [`PoseGraph::optimize`].
+#[derive(Debug, Clone, Copy)]
+pub struct PoseGraphResult {
+    /// Number of iterations run.
+    pub iterations: usize,
+    /// Sum of the squared Mahalanobis distances of the edges before the optimization.
+    pub initial_cost: f32,
+    /// Sum of the squared Mahalanobis distances of the edges after the optimization.
+    pub final_cost: f32,
+    /// Whether the updates became smaller than [`PoseGraphParams::min_update`].
+    pub converged: bool,
+}
+
+/// Graph of SE(3) poses constrained by relative pose measurements. Optimizing it spreads
+/// the error revealed by loop closures over the trajectory. The normal equations are solved
+/// by a block sparse Cholesky factorization, with one 6x6 block per node.
+#[derive(Debug, Clone, Default)]
+pub struct PoseGraph {
+    nodes: Vec<Transform>,
+    edges: Vec<PoseGraphEdge>,
+    fixed: Vec<bool>,
+}
+
+impl PoseGraph {
+    /// Creates a graph with the poses of a trajectory, linked by edges with their relative
+    /// poses, as odometry measurements.
+    ///
+    /// # Arguments
+    ///
+    /// * `trajectory` - The trajectory.
+    /// * `information` - Information matrix of the odometry edges.
+    pub fn from_trajectory(trajectory: &Trajectory, information: &Matrix6<f32>) -> Self {
+        let mut graph = Self::default();
+        for (pose, _) in trajectory.iter() {
+            graph.add_node(pose);
+        }
+        for index in 1..graph.nodes.len() {
+            let transform = &graph.nodes[index - 1].inverse() * &graph.nodes[index];
+            graph.edges.push(PoseGraphEdge::new(
+                index - 1,
+                index,
+                transform,
+                *information,
+            ));
+        }
+        graph
+    }
+
+    /// Poses of the nodes, e.g., camera to world transformations.
+    pub fn nodes(&self) -> &[Transform] {
+        &self.nodes
+    }
+
+    /// Relative pose measurements.
+    pub fn edges(&self) -> &[PoseGraphEdge] {
+        &self.edges
+    }
+
+    /// Adds a node.
+    ///
+    /// # Arguments
+    ///
+    /// * `pose` - The initial pose of the node.
+    ///
+    /// # Returns
+    ///
+    /// The index of the node.
+    pub fn add_node(&mut self, pose: Transform) -> usize {
+        self.nodes.push(pose);
+        self.fixed.push(false);
+        self.nodes.len() - 1
+    }
+
+    /// Adds an edge.
+    ///
+    /// # Returns
+    ///
+    /// Error if the edge links a node to itself or to a node that doesn't exist.
+    pub fn add_edge(&mut self, edge: PoseGraphEdge) -> Result<(), A3dError> {
+        if edge.from == edge.to || edge.from >= self.nodes.len() || edge.to >= self.nodes.len() {
+            return Err(A3dError::invalid_parameter(
+                "Pose graph edges must link two different existing nodes.",
+            ));
+        }
+        self.edges.push(edge);
+        Ok(())
+    }
+
+    /// Keeps a node at its pose during the optimization. When no node is fixed, the first
+    /// one is, which removes the gauge freedom of the graph.
+    ///
+    /// # Returns
+    ///
+    /// Error if the node doesn't exist.
+    pub fn fix_node(&mut self, index: usize) -> Result<(), A3dError> {
+        let fixed = self.fixed.get_mut(index).ok_or_else(|| {
+            A3dError::invalid_parameter("Only existing pose graph nodes can be fixed.")
+        })?;
+        *fixed = true;
+        Ok(())
+    }
+
+    /// Whether a node is fixed.
+    pub fn is_fixed(&self, index: usize) -> bool {
+        self.fixed[index]
+    }
+
+    /// Sum of the squared Mahalanobis distances of the edge errors.
+    pub fn cost(&self) -> f32 {
+        self.edges
+            .iter()
+            .map(|edge| {
+                let error = edge.error(&self.nodes);
+                error.dot(&(edge.information * error))
+            })
+            .sum()
+    }
+
+    /// Optimizes the node poses by Gauss-Newton.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters of the optimization.
+    ///
+    /// # Returns
+    ///
+    /// The costs and iterations of the optimization. It stops early if the normal
+    /// equations are singular, e.g., when a node isn't linked to a fixed one.
+    pub fn optimize(&mut self, params: &PoseGraphParams) -> PoseGraphResult {
+        let any_fixed = self.fixed.iter().any(|fixed| *fixed);
+        // Index of each free node in the system.
+        let mut num_free = 0;
+        let free_index = self
+            .fixed
+            .iter()
+            .enumerate()
+            .map(|(node, fixed)| {
+                if *fixed || (!any_fixed && node == 0) {
+                    None
+                } else {
+                    num_free += 1;
+                    Some(num_free - 1)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut result = PoseGraphResult {
+            iterations: 0,
+            initial_cost: self.cost(),
+            final_cost: 0.0,
+            converged: false,
+        };
+        for _ in 0..params.max_iterations {
+            let mut hessian = BlockSparseMatrix::new(num_free);
+            let mut gradient = vec![Vector6::<f64>::zeros(); num_free];
+            for edge in self.edges.iter() {
+                let error = edge.error(&self.nodes);
+                let weight = edge.robust_kernel.map_or(1.0, |kernel| {
+                    kernel.weight(error.dot(&(edge.information * error)).sqrt())
+                });
+                let information: Matrix6<f64> = nalgebra::convert(edge.information * weight);
+                let error: Vector6<f64> = nalgebra::convert(error);
+
+                // With left perturbations, the error moves by `A δ_to - A δ_from`.
+                let adjoint: Matrix6<f64> = nalgebra::convert(
+                    (&edge.transform.inverse() * &self.nodes[edge.from].inverse()).adjoint(),
+                );
+                let adjoint = inverse_left_jacobian(&error) * adjoint;
+                let block = adjoint.transpose() * information * adjoint;
+                let residual = adjoint.transpose() * information * error;
+                let (from, to) = (free_index[edge.from], free_index[edge.to]);
+                if let Some(from) = from {
+                    hessian.add(from, from, &block);
+                    gradient[from] += residual;
+                }
+                if let Some(to) = to {
+                    hessian.add(to, to, &block);
+                    gradient[to] -= residual;
+                }
+                if let (Some(from), Some(to)) = (from, to) {
+                    hessian.add(from, to, &-block);
+                }
+            }
+
+            let Some(update) = hessian.solve(&gradient) else {
+                break;
+            };
+            result.iterations += 1;
+            let mut max_update = 0.0f32;
+            for (node, index) in free_index.iter().enumerate() {
+                if let Some(index) = index {
+                    let twist: Vector6<f32> = nalgebra::convert(update[*index]);
+                    max_update = max_update.max(twist.norm());
+                    self.nodes[node] = &Transform::exp(&LieGroup::Se3(twist)) * &self.nodes[node];
+                }
+            }
+            if max_update < params.min_update {
+                result.converged = true;
+                break;
+            }
+        }
+        result.final_cost = self.cost();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix6, Vector6};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{PoseGraph, PoseGraphEdge, PoseGraphParams};
+    use crate::{
+        metrics::TransformMetrics,
+        optim::RobustKernel,
+        transform::{LieGroup, Transform},
+    };
+
+    /// Poses along a circle, the last one back at the start.
+    fn circle_poses(count: usize) -> Vec<Transform> {
+        (0..count)
+            .map(|i| {
+                let angle = std::f32::consts::TAU * i as f32 / count as f32;
+                Transform::exp(&LieGroup::Se3(Vector6::new(
+                    2.0 * angle.cos(),
+                    2.0 * angle.sin(),
+                    0.1 * angle.sin(),
+                    0.0,
+                    0.0,
+                    angle,
+                )))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_optimize_loop() {
+        let gt_poses = circle_poses(24);
+        let mut rng = StdRng::seed_from_u64(7);
+
+        // Odometry with noise, accumulated into drifting poses.
+        let mut graph = PoseGraph::default();
+        graph.add_node(gt_poses[0].clone());
+        for i in 1..gt_poses.len() {
+            let noise = Transform::exp(&LieGroup::Se3(Vector6::from_fn(|_, _| {
+                rng.gen_range(-0.02..0.02)
+            })));
+            let odometry = &(&gt_poses[i - 1].inverse() * &gt_poses[i]) * &noise;
+            let pose = &graph.nodes()[i - 1] * &odometry;
+            graph.add_node(pose);
+            graph
+                .add_edge(PoseGraphEdge::new(
+                    i - 1,
+                    i,
+                    odometry,
+                    Matrix6::identity() * 100.0,
+                ))
+                .unwrap();
+        }
+        let last = gt_poses.len() - 1;
+        let drift = TransformMetrics::new(&graph.nodes()[last], &gt_poses[last]);
+
+        // Exact loop closure between the last and the first poses.
+        graph
+            .add_edge(PoseGraphEdge::new(
+                last,
+                0,
+                &gt_poses[last].inverse() * &gt_poses[0],
+                Matrix6::identity() * 1e4,
+            ))
+            .unwrap();
+        // A wrong loop closure, down weighted by its robust kernel.
+        graph
+            .add_edge(
+                PoseGraphEdge::new(last / 2, 0, Transform::eye(), Matrix6::identity() * 1e4)
+                    .with_robust_kernel(RobustKernel::Cauchy { scale: 1.0 }),
+            )
+            .unwrap();
+
+        let result = graph.optimize(&PoseGraphParams::default());
+        assert!(result.converged);
+        assert!(result.final_cost < result.initial_cost);
+        let metrics = TransformMetrics::new(&graph.nodes()[last], &gt_poses[last]);
+        assert!(
+            metrics.translation < drift.translation * 0.25,
+            "{metrics} vs {drift}"
+        );
+        assert!(TransformMetrics::new(&graph.nodes()[0], &gt_poses[0]).translation < 1e-6);
+
+        assert!(graph
+            .add_edge(PoseGraphEdge::new(
+                0,
+                100,
+                Transform::eye(),
+                Matrix6::identity()
+            ))
+            .is_err());
+        assert!(graph.fix_node(100).is_err());
+    }
+
+    #[test]
+    fn test_optimize_nearly_consistent() {
+        let measurement =
+            Transform::exp(&LieGroup::Se3(Vector6::new(0.5, 0.1, 0.0, 0.0, 0.2, 0.0)));
+        let perturbation =
+            Transform::exp(&LieGroup::Se3(Vector6::new(0.0, 0.0, 0.0, 1e-4, 0.0, 0.0)));
+
+        let mut graph = PoseGraph::default();
+        graph.add_node(Transform::eye());
+        graph.add_node(&measurement * &perturbation);
+        graph
+            .add_edge(PoseGraphEdge::new(
+                0,
+                1,
+                measurement.clone(),
+                Matrix6::identity() * 100.0,
+            ))
+            .unwrap();
+        assert!(graph.cost().is_finite());
+        assert!(graph.cost() > 0.0);
+
+        let result = graph.optimize(&PoseGraphParams::default());
+        assert!(result.converged);
+        assert!(result.final_cost < result.initial_cost);
+        let error = (&graph.nodes()[1].inverse() * &measurement).log();
+        assert!(error.norm() < 1e-5, "{error}");
+    }
+}
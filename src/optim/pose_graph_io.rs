@@ -0,0 +1,391 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use nalgebra::{Matrix6, Quaternion, UnitQuaternion, Vector3};
+
+use super::pose_graph::{PoseGraph, PoseGraphEdge};
+use crate::{error::A3dError, transform::Transform};
+
+/// Scales the rotation part of an information matrix. g2o measures the rotation error by
+/// the imaginary part of a quaternion, half the rotation vector of [`Transform::log`].
+fn scale_rotation_information(information: &Matrix6<f32>, scale: f32) -> Matrix6<f32> {
+    let scaling =
+        Matrix6::from_diagonal(&nalgebra::Vector6::new(1.0, 1.0, 1.0, scale, scale, scale));
+    scaling * information * scaling
+}
+
+/// Parses the 21 values of the upper triangle of an information matrix, row by row.
+fn parse_information(values: &[f32]) -> Matrix6<f32> {
+    let mut information = Matrix6::zeros();
+    let mut k = 0;
+    for row in 0..6 {
+        for col in row..6 {
+            information[(row, col)] = values[k];
+            information[(col, row)] = values[k];
+            k += 1;
+        }
+    }
+    information
+}
+
+/// Formats the upper triangle of an information matrix, row by row.
+fn format_information(information: &Matrix6<f32>) -> String {
+    (0..6)
+        .flat_map(|row| (row..6).map(move |col| information[(row, col)].to_string()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Lines of a pose graph file, split into their tag, integer ids, and values.
+struct GraphLine {
+    number: usize,
+    tag: String,
+    ids: Vec<usize>,
+    values: Vec<f32>,
+}
+
+fn read_graph_lines(
+    filepath: &Path,
+    num_ids: fn(&str) -> usize,
+) -> Result<Vec<GraphLine>, A3dError> {
+    let reader = BufReader::new(File::open(filepath)?);
+    let mut lines = Vec::new();
+    for (line_count, line) in reader.lines().enumerate() {
+        let line = line?;
+        let mut tokens = line.split_whitespace();
+        let Some(tag) = tokens.next() else {
+            continue;
+        };
+        let parse_error = |err: &dyn std::fmt::Display| {
+            A3dError::Parser(format!("{}:{}: {err}", filepath.display(), line_count + 1))
+        };
+        let tokens = tokens.collect::<Vec<_>>();
+        let num_ids = num_ids(tag).min(tokens.len());
+        let ids = tokens[..num_ids]
+            .iter()
+            .map(|token| token.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| parse_error(&err))?;
+        let values = tokens[num_ids..]
+            .iter()
+            .map(|token| token.parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| parse_error(&err))?;
+        lines.push(GraphLine {
+            number: line_count + 1,
+            tag: tag.to_string(),
+            ids,
+            values,
+        });
+    }
+    Ok(lines)
+}
+
+/// Builds a graph from its parsed vertices and edges, mapping the file ids to node indices
+/// in the order of the vertices.
+fn assemble_graph(
+    filepath: &Path,
+    vertices: Vec<(usize, Transform)>,
+    edges: Vec<(usize, usize, usize, Transform, Matrix6<f32>)>,
+    fixed: Vec<usize>,
+) -> Result<PoseGraph, A3dError> {
+    let mut graph = PoseGraph::default();
+    let mut indices = HashMap::new();
+    for (id, pose) in vertices {
+        indices.insert(id, graph.add_node(pose));
+    }
+    let index = |line: usize, id: usize| {
+        indices.get(&id).copied().ok_or_else(|| {
+            A3dError::Parser(format!(
+                "{}:{line}: unknown vertex {id}",
+                filepath.display()
+            ))
+        })
+    };
+    for (line, from, to, transform, information) in edges {
+        let edge = PoseGraphEdge::new(index(line, from)?, index(line, to)?, transform, information);
+        graph
+            .add_edge(edge)
+            .map_err(|err| A3dError::Parser(format!("{}:{line}: {err}", filepath.display())))?;
+    }
+    for id in fixed {
+        if let Some(index) = indices.get(&id) {
+            graph.fix_node(*index)?;
+        }
+    }
+    Ok(graph)
+}
+
+fn wrong_count(filepath: &Path, line: &GraphLine) -> A3dError {
+    A3dError::Parser(format!(
+        "{}:{}: unexpected number of values for {}",
+        filepath.display(),
+        line.number,
+        line.tag
+    ))
+}
+
+fn quaternion_transform(values: &[f32]) -> Transform {
+    Transform::new(
+        &Vector3::new(values[0], values[1], values[2]),
+        &Quaternion::new(values[6], values[3], values[4], values[5]),
+    )
+}
+
+/// Reads a pose graph in the g2o format, with `VERTEX_SE3:QUAT id x y z qx qy qz qw`,
+/// `EDGE_SE3:QUAT from to x y z qx qy qz qw` followed by the 21 values of the upper triangle
+/// of the information matrix, and `FIX id` lines. Other lines are ignored.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the graph file.
+///
+/// # Returns
+///
+/// The pose graph, its nodes are in the order of the vertices in the file.
+pub fn read_g2o<P: AsRef<Path>>(filepath: P) -> Result<PoseGraph, A3dError> {
+    let filepath = filepath.as_ref();
+    let lines = read_graph_lines(filepath, |tag| match tag {
+        "VERTEX_SE3:QUAT" | "FIX" => 1,
+        "EDGE_SE3:QUAT" => 2,
+        _ => 0,
+    })?;
+
+    let (mut vertices, mut edges, mut fixed) = (Vec::new(), Vec::new(), Vec::new());
+    for line in lines.iter() {
+        match line.tag.as_str() {
+            "VERTEX_SE3:QUAT" => {
+                if line.ids.len() != 1 || line.values.len() != 7 {
+                    return Err(wrong_count(filepath, line));
+                }
+                vertices.push((line.ids[0], quaternion_transform(&line.values)));
+            }
+            "EDGE_SE3:QUAT" => {
+                if line.ids.len() != 2 || line.values.len() != 28 {
+                    return Err(wrong_count(filepath, line));
+                }
+                edges.push((
+                    line.number,
+                    line.ids[0],
+                    line.ids[1],
+                    quaternion_transform(&line.values),
+                    scale_rotation_information(&parse_information(&line.values[7..]), 0.5),
+                ));
+            }
+            "FIX" => fixed.extend(line.ids.iter().copied()),
+            _ => (),
+        }
+    }
+    assemble_graph(filepath, vertices, edges, fixed)
+}
+
+/// Writes a pose graph in the g2o format, see [`read_g2o`]. The vertex ids are the node
+/// indices. Robust kernels are not written.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the output file.
+/// * `graph` - The pose graph.
+pub fn write_g2o<P: AsRef<Path>>(filepath: P, graph: &PoseGraph) -> Result<(), A3dError> {
+    let mut writer = BufWriter::new(File::create(filepath)?);
+    let format_pose = |pose: &Transform| {
+        let translation = pose.translation();
+        let rotation = pose.0.rotation;
+        format!(
+            "{} {} {} {} {} {} {}",
+            translation[0],
+            translation[1],
+            translation[2],
+            rotation.i,
+            rotation.j,
+            rotation.k,
+            rotation.w
+        )
+    };
+
+    for (index, pose) in graph.nodes().iter().enumerate() {
+        writeln!(writer, "VERTEX_SE3:QUAT {index} {}", format_pose(pose))?;
+    }
+    for edge in graph.edges().iter() {
+        writeln!(
+            writer,
+            "EDGE_SE3:QUAT {} {} {} {}",
+            edge.from,
+            edge.to,
+            format_pose(&edge.transform),
+            format_information(&scale_rotation_information(&edge.information, 2.0))
+        )?;
+    }
+    for index in (0..graph.nodes().len()).filter(|index| graph.is_fixed(*index)) {
+        writeln!(writer, "FIX {index}")?;
+    }
+    Ok(())
+}
+
+fn euler_transform(values: &[f32]) -> Transform {
+    Transform(nalgebra::Isometry3::from_parts(
+        Vector3::new(values[0], values[1], values[2]).into(),
+        UnitQuaternion::from_euler_angles(values[3], values[4], values[5]),
+    ))
+}
+
+/// Reads a pose graph in the 3D TORO format, with `VERTEX3 id x y z roll pitch yaw` and
+/// `EDGE3 from to x y z roll pitch yaw` followed by the 21 values of the upper triangle of
+/// the information matrix. Other lines are ignored.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the graph file.
+///
+/// # Returns
+///
+/// The pose graph, its nodes are in the order of the vertices in the file.
+pub fn read_toro<P: AsRef<Path>>(filepath: P) -> Result<PoseGraph, A3dError> {
+    let filepath = filepath.as_ref();
+    let lines = read_graph_lines(filepath, |tag| match tag {
+        "VERTEX3" => 1,
+        "EDGE3" => 2,
+        _ => 0,
+    })?;
+
+    let (mut vertices, mut edges) = (Vec::new(), Vec::new());
+    for line in lines.iter() {
+        match line.tag.as_str() {
+            "VERTEX3" => {
+                if line.ids.len() != 1 || line.values.len() != 6 {
+                    return Err(wrong_count(filepath, line));
+                }
+                vertices.push((line.ids[0], euler_transform(&line.values)));
+            }
+            "EDGE3" => {
+                if line.ids.len() != 2 || line.values.len() != 27 {
+                    return Err(wrong_count(filepath, line));
+                }
+                edges.push((
+                    line.number,
+                    line.ids[0],
+                    line.ids[1],
+                    euler_transform(&line.values),
+                    parse_information(&line.values[6..]),
+                ));
+            }
+            _ => (),
+        }
+    }
+    assemble_graph(filepath, vertices, edges, Vec::new())
+}
+
+/// Writes a pose graph in the 3D TORO format, see [`read_toro`]. The format has no fixed
+/// nodes nor robust kernels. The rotation information is written as is, which matches the
+/// Euler angles for small rotation errors.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the output file.
+/// * `graph` - The pose graph.
+pub fn write_toro<P: AsRef<Path>>(filepath: P, graph: &PoseGraph) -> Result<(), A3dError> {
+    let mut writer = BufWriter::new(File::create(filepath)?);
+    let format_pose = |pose: &Transform| {
+        let translation = pose.translation();
+        let (roll, pitch, yaw) = pose.0.rotation.euler_angles();
+        format!(
+            "{} {} {} {roll} {pitch} {yaw}",
+            translation[0], translation[1], translation[2]
+        )
+    };
+
+    for (index, pose) in graph.nodes().iter().enumerate() {
+        writeln!(writer, "VERTEX3 {index} {}", format_pose(pose))?;
+    }
+    for edge in graph.edges().iter() {
+        writeln!(
+            writer,
+            "EDGE3 {} {} {} {}",
+            edge.from,
+            edge.to,
+            format_pose(&edge.transform),
+            format_information(&edge.information)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix6, Vector6};
+
+    use super::{read_g2o, read_toro, write_g2o, write_toro};
+    use crate::{
+        metrics::TransformMetrics,
+        optim::{PoseGraph, PoseGraphEdge},
+        transform::{LieGroup, Transform},
+    };
+
+    fn sample_graph() -> PoseGraph {
+        let mut graph = PoseGraph::default();
+        for i in 0..3 {
+            let i = i as f32;
+            graph.add_node(Transform::exp(&LieGroup::Se3(Vector6::new(
+                i,
+                0.5 * i,
+                -0.2 * i,
+                0.1 * i,
+                -0.05 * i,
+                0.3 * i,
+            ))));
+        }
+        let information = Matrix6::from_fn(|row, col| if row == col { 10.0 } else { 0.5 });
+        for (from, to) in [(0, 1), (1, 2), (2, 0)] {
+            let transform = &graph.nodes()[from].inverse() * &graph.nodes()[to];
+            graph
+                .add_edge(PoseGraphEdge::new(from, to, transform, information))
+                .unwrap();
+        }
+        graph.fix_node(0).unwrap();
+        graph
+    }
+
+    fn assert_graph_eq(expected: &PoseGraph, actual: &PoseGraph) {
+        assert_eq!(expected.nodes().len(), actual.nodes().len());
+        assert_eq!(expected.edges().len(), actual.edges().len());
+        for (expected, actual) in expected.nodes().iter().zip(actual.nodes().iter()) {
+            let metrics = TransformMetrics::new(expected, actual);
+            assert!(
+                metrics.angle < 1e-4 && metrics.translation < 1e-4,
+                "{metrics}"
+            );
+        }
+        for (expected, actual) in expected.edges().iter().zip(actual.edges().iter()) {
+            assert_eq!((expected.from, expected.to), (actual.from, actual.to));
+            assert!((expected.information - actual.information).norm() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_read_write_g2o() {
+        let graph = sample_graph();
+        write_g2o("tests/outputs/pose-graph.g2o", &graph).unwrap();
+        let loaded = read_g2o("tests/outputs/pose-graph.g2o").unwrap();
+        assert_graph_eq(&graph, &loaded);
+        assert!(loaded.is_fixed(0) && !loaded.is_fixed(1));
+
+        std::fs::write(
+            "tests/outputs/pose-graph-invalid.g2o",
+            "VERTEX_SE3:QUAT 0 0 0 0 0 0 0 1\nEDGE_SE3:QUAT 0 7 0 0 0 0 0 0 1\n",
+        )
+        .unwrap();
+        assert!(read_g2o("tests/outputs/pose-graph-invalid.g2o").is_err());
+    }
+
+    #[test]
+    fn test_read_write_toro() {
+        let graph = sample_graph();
+        write_toro("tests/outputs/pose-graph.graph", &graph).unwrap();
+        let loaded = read_toro("tests/outputs/pose-graph.graph").unwrap();
+        assert_graph_eq(&graph, &loaded);
+    }
+}
@@ -0,0 +1,160 @@
+/// Robust loss of a residual, which reduces the influence of outliers in least squares
+/// problems. Optimizers use it by iteratively reweighted least squares (IRLS): each
+/// residual is weighted by [`RobustLoss::weight`] at the current estimate.
+pub trait RobustLoss {
+    /// The loss of a residual, close to `residual² / 2` around zero.
+    fn loss(&self, residual: f32) -> f32;
+
+    /// The IRLS weight of a residual, `loss'(residual) / residual`. It is 1 at zero and
+    /// decreases as the residual grows.
+    fn weight(&self, residual: f32) -> f32;
+}
+
+/// Robust kernels. The `scale` of each one is the residual from which it starts to
+/// downweight, in the units of the residual, e.g., meters for geometric terms.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RobustKernel {
+    /// Quadratic up to the scale and linear beyond it. Convex, a safe default.
+    Huber { scale: f32 },
+    /// Tukey's biweight. Residuals larger than the scale are ignored.
+    Tukey { scale: f32 },
+    /// Cauchy (Lorentzian), logarithmic growth.
+    Cauchy { scale: f32 },
+    /// Geman-McClure, bounded loss that rejects outliers more aggressively than Cauchy.
+    GemanMcClure { scale: f32 },
+    /// Barron, A General and Adaptive Robust Loss Function, 2019. The shape `alpha`
+    /// spans the other kernels: 2 is least squares, 1 a smooth Huber (pseudo-Huber),
+    /// 0 Cauchy, -2 Geman-McClure and negative infinity Welsch.
+    Barron { alpha: f32, scale: f32 },
+}
+
+impl RobustLoss for RobustKernel {
+    fn loss(&self, residual: f32) -> f32 {
+        match *self {
+            RobustKernel::Huber { scale } => {
+                let abs = residual.abs();
+                if abs <= scale {
+                    0.5 * residual * residual
+                } else {
+                    scale * (abs - 0.5 * scale)
+                }
+            }
+            RobustKernel::Tukey { scale } => {
+                let scale_sqr = scale * scale;
+                let ratio = (residual * residual / scale_sqr).min(1.0);
+                scale_sqr / 6.0 * (1.0 - (1.0 - ratio).powi(3))
+            }
+            RobustKernel::Cauchy { scale } => {
+                let scale_sqr = scale * scale;
+                0.5 * scale_sqr * (residual * residual / scale_sqr).ln_1p()
+            }
+            RobustKernel::GemanMcClure { scale } => {
+                let residual_sqr = residual * residual;
+                0.5 * residual_sqr / (1.0 + residual_sqr / (scale * scale))
+            }
+            RobustKernel::Barron { alpha, scale } => {
+                let scale_sqr = scale * scale;
+                let ratio = residual * residual / scale_sqr;
+                if alpha == 2.0 {
+                    0.5 * residual * residual
+                } else if alpha == 0.0 {
+                    scale_sqr * (0.5 * ratio).ln_1p()
+                } else if alpha == f32::NEG_INFINITY {
+                    scale_sqr * (1.0 - (-0.5 * ratio).exp())
+                } else {
+                    let shift = (alpha - 2.0).abs();
+                    scale_sqr * shift / alpha * ((ratio / shift + 1.0).powf(0.5 * alpha) - 1.0)
+                }
+            }
+        }
+    }
+
+    fn weight(&self, residual: f32) -> f32 {
+        match *self {
+            RobustKernel::Huber { scale } => {
+                let abs = residual.abs();
+                if abs <= scale {
+                    1.0
+                } else {
+                    scale / abs
+                }
+            }
+            RobustKernel::Tukey { scale } => {
+                let ratio = residual * residual / (scale * scale);
+                if ratio < 1.0 {
+                    (1.0 - ratio) * (1.0 - ratio)
+                } else {
+                    0.0
+                }
+            }
+            RobustKernel::Cauchy { scale } => 1.0 / (1.0 + residual * residual / (scale * scale)),
+            RobustKernel::GemanMcClure { scale } => {
+                let denominator = 1.0 + residual * residual / (scale * scale);
+                1.0 / (denominator * denominator)
+            }
+            RobustKernel::Barron { alpha, scale } => {
+                let ratio = residual * residual / (scale * scale);
+                if alpha == 2.0 {
+                    1.0
+                } else if alpha == f32::NEG_INFINITY {
+                    (-0.5 * ratio).exp()
+                } else {
+                    let shift = (alpha - 2.0).abs();
+                    (ratio / shift + 1.0).powf(0.5 * alpha - 1.0)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{RobustKernel, RobustLoss};
+
+    #[rstest]
+    #[case(RobustKernel::Huber { scale: 0.5 })]
+    #[case(RobustKernel::Tukey { scale: 0.5 })]
+    #[case(RobustKernel::Cauchy { scale: 0.5 })]
+    #[case(RobustKernel::GemanMcClure { scale: 0.5 })]
+    #[case(RobustKernel::Barron { alpha: 2.0, scale: 0.5 })]
+    #[case(RobustKernel::Barron { alpha: 1.0, scale: 0.5 })]
+    #[case(RobustKernel::Barron { alpha: 0.0, scale: 0.5 })]
+    #[case(RobustKernel::Barron { alpha: -2.0, scale: 0.5 })]
+    #[case(RobustKernel::Barron { alpha: f32::NEG_INFINITY, scale: 0.5 })]
+    fn test_weight_is_loss_derivative(#[case] kernel: RobustKernel) {
+        assert_eq!(kernel.loss(0.0), 0.0);
+        assert_eq!(kernel.weight(0.0), 1.0);
+
+        let step = 1e-3;
+        for residual in [0.1f32, 0.3, 0.7, 1.5] {
+            let derivative =
+                (kernel.loss(residual + step) - kernel.loss(residual - step)) / (2.0 * step);
+            assert!(
+                (derivative / residual - kernel.weight(residual)).abs() < 1e-2,
+                "{kernel:?} at {residual}"
+            );
+            assert!(kernel.weight(residual) <= 1.0);
+        }
+    }
+
+    #[test]
+    fn test_barron_matches_kernels() {
+        let cauchy = RobustKernel::Cauchy {
+            scale: std::f32::consts::SQRT_2,
+        };
+        let barron = RobustKernel::Barron {
+            alpha: 0.0,
+            scale: 1.0,
+        };
+        assert!((cauchy.weight(0.8) - barron.weight(0.8)).abs() < 1e-6);
+
+        let geman_mcclure = RobustKernel::GemanMcClure { scale: 2.0 };
+        let barron = RobustKernel::Barron {
+            alpha: -2.0,
+            scale: 1.0,
+        };
+        assert!((geman_mcclure.weight(0.8) - barron.weight(0.8)).abs() < 1e-6);
+    }
+}
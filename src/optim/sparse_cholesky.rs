@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+
+use nalgebra::{Cholesky, Matrix6, Vector6};
+
+/// Symmetric positive definite matrix made of 6x6 blocks, storing only the nonzero blocks
+/// of its upper triangle. Normal equations of pose graphs have this form, one block row per
+/// pose and one off-diagonal block per pair of poses sharing an edge.
+pub(crate) struct BlockSparseMatrix {
+    rows: Vec<BTreeMap<usize, Matrix6<f64>>>,
+}
+
+impl BlockSparseMatrix {
+    /// Creates a zero matrix.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_blocks` - Number of block rows and columns.
+    pub(crate) fn new(num_blocks: usize) -> Self {
+        Self {
+            rows: vec![BTreeMap::new(); num_blocks],
+        }
+    }
+
+    /// Adds a block to the matrix, and its transpose to the symmetric position.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - Block row.
+    /// * `col` - Block column.
+    /// * `block` - The block, must be symmetric when `row == col`.
+    pub(crate) fn add(&mut self, row: usize, col: usize, block: &Matrix6<f64>) {
+        let (row, col, block) = if row <= col {
+            (row, col, *block)
+        } else {
+            (col, row, block.transpose())
+        };
+        *self.rows[row].entry(col).or_insert_with(Matrix6::zeros) += block;
+    }
+
+    /// Solves `A x = b` by the block Cholesky factorization `A = U^T U`. The blocks are
+    /// eliminated in their index order, so the fill-in is small when the connected poses
+    /// have close indices, e.g., along a trajectory.
+    ///
+    /// # Arguments
+    ///
+    /// * `rhs` - The right hand side, one vector per block row.
+    ///
+    /// # Returns
+    ///
+    /// The solution, or `None` if the matrix isn't positive definite.
+    pub(crate) fn solve(mut self, rhs: &[Vector6<f64>]) -> Option<Vec<Vector6<f64>>> {
+        let num_blocks = self.rows.len();
+
+        // Factorization, row `k` of `A` becomes row `k` of `U`.
+        for k in 0..num_blocks {
+            let mut row = std::mem::take(&mut self.rows[k]);
+            let lower = Cholesky::new(*row.get(&k)?)?.l();
+            for (col, block) in row.iter_mut() {
+                *block = if *col == k {
+                    lower.transpose()
+                } else {
+                    lower.solve_lower_triangular(block)?
+                };
+            }
+            for (i, u_ki) in row.range(k + 1..) {
+                for (j, u_kj) in row.range(*i..) {
+                    *self.rows[*i].entry(*j).or_insert_with(Matrix6::zeros) -=
+                        u_ki.transpose() * u_kj;
+                }
+            }
+            self.rows[k] = row;
+        }
+
+        // Forward substitution, U^T y = b.
+        let mut solution = rhs.to_vec();
+        for k in 0..num_blocks {
+            let row = &self.rows[k];
+            solution[k] = row[&k].transpose().solve_lower_triangular(&solution[k])?;
+            let y_k = solution[k];
+            for (col, block) in row.range(k + 1..) {
+                solution[*col] -= block.transpose() * y_k;
+            }
+        }
+
+        // Backward substitution, U x = y.
+        for k in (0..num_blocks).rev() {
+            let row = &self.rows[k];
+            let mut value = solution[k];
+            for (col, block) in row.range(k + 1..) {
+                value -= block * solution[*col];
+            }
+            solution[k] = row[&k].solve_upper_triangular(&value)?;
+        }
+        Some(solution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::AddAssign;
+
+    use nalgebra::{DMatrix, DVector, Matrix6, Vector6};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::BlockSparseMatrix;
+
+    #[test]
+    fn test_solve() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let num_blocks = 5;
+        let mut sparse = BlockSparseMatrix::new(num_blocks);
+        let mut dense = DMatrix::<f64>::zeros(6 * num_blocks, 6 * num_blocks);
+        // A chain and a loop between the first and last blocks.
+        let pairs = (0..num_blocks - 1)
+            .map(|i| (i, i + 1))
+            .chain([(num_blocks - 1, 0)]);
+        for (i, j) in pairs {
+            let jacobian = Matrix6::<f64>::from_fn(|_, _| rng.gen_range(-1.0..1.0));
+            let block = jacobian.transpose() * jacobian + Matrix6::identity();
+            for (row, col, value) in [(i, i, block), (j, j, block), (i, j, -block)] {
+                sparse.add(row, col, &value);
+            }
+            for (row, col, value) in [(i, i, block), (j, j, block), (i, j, -block), (j, i, -block)]
+            {
+                dense
+                    .view_mut((6 * row, 6 * col), (6, 6))
+                    .add_assign(&value);
+            }
+        }
+        // Anchors the first block, like a fixed pose.
+        sparse.add(0, 0, &Matrix6::identity());
+        dense
+            .view_mut((0, 0), (6, 6))
+            .add_assign(&Matrix6::identity());
+
+        let rhs = (0..num_blocks)
+            .map(|_| Vector6::from_fn(|_, _| rng.gen_range(-1.0..1.0)))
+            .collect::<Vec<_>>();
+        let expected = dense.cholesky().unwrap().solve(&DVector::from_iterator(
+            6 * num_blocks,
+            rhs.iter().flatten().cloned(),
+        ));
+        let actual = sparse.solve(&rhs).unwrap();
+        for (k, value) in actual.iter().enumerate() {
+            assert!((value - expected.rows(6 * k, 6)).norm() < 1e-8);
+        }
+
+        assert!(BlockSparseMatrix::new(2).solve(&rhs[..2]).is_none());
+    }
+}
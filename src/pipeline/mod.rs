@@ -0,0 +1,321 @@
+use std::marker::PhantomData;
+
+use ndarray::Array2;
+
+use crate::{
+    bilateral::BilateralFilter, error::A3dError, image::RgbdFrame, range_image::RangeImage,
+    range_image::RangeImageBuilder,
+};
+
+#[cfg(feature = "onnx")]
+mod onnx;
+#[cfg(feature = "onnx")]
+pub use onnx::{OnnxFrameModel, OnnxOutputs};
+
+/// A processing step of a pipeline with typed input and output.
+///
+/// Stages are composed at compile time with [`PipelineStage::then`], so a custom step
+/// (e.g., a learned depth refinement or a custom filter) can be inserted anywhere its types
+/// match the neighbor stages.
+pub trait PipelineStage {
+    /// The data consumed by the stage.
+    type Input;
+    /// The data produced by the stage.
+    type Output;
+
+    /// Processes one input item.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The item to be processed.
+    ///
+    /// # Returns
+    ///
+    /// The processed item or an error if the stage failed.
+    fn process(&mut self, input: Self::Input) -> Result<Self::Output, A3dError>;
+
+    /// Creates a new stage that feeds the output of this stage into `next`.
+    fn then<Next>(self, next: Next) -> Chain<Self, Next>
+    where
+        Self: Sized,
+        Next: PipelineStage<Input = Self::Output>,
+    {
+        Chain {
+            first: self,
+            second: next,
+        }
+    }
+}
+
+/// Two stages executed in sequence. Created by [`PipelineStage::then`].
+pub struct Chain<First, Second> {
+    first: First,
+    second: Second,
+}
+
+impl<First, Second> PipelineStage for Chain<First, Second>
+where
+    First: PipelineStage,
+    Second: PipelineStage<Input = First::Output>,
+{
+    type Input = First::Input;
+    type Output = Second::Output;
+
+    fn process(&mut self, input: Self::Input) -> Result<Self::Output, A3dError> {
+        let intermediate = self.first.process(input)?;
+        self.second.process(intermediate)
+    }
+}
+
+impl<Stage: PipelineStage + ?Sized> PipelineStage for Box<Stage> {
+    type Input = Stage::Input;
+    type Output = Stage::Output;
+
+    fn process(&mut self, input: Self::Input) -> Result<Self::Output, A3dError> {
+        (**self).process(input)
+    }
+}
+
+/// A stage that wraps a closure. Created by [`from_fn`].
+pub struct FnStage<F, Input, Output> {
+    func: F,
+    _phantom: PhantomData<fn(Input) -> Output>,
+}
+
+/// Creates a stage from a closure.
+///
+/// # Arguments
+///
+/// * `func` - Closure called for every item that passes through the stage.
+pub fn from_fn<F, Input, Output>(func: F) -> FnStage<F, Input, Output>
+where
+    F: FnMut(Input) -> Result<Output, A3dError>,
+{
+    FnStage {
+        func,
+        _phantom: PhantomData,
+    }
+}
+
+impl<F, Input, Output> PipelineStage for FnStage<F, Input, Output>
+where
+    F: FnMut(Input) -> Result<Output, A3dError>,
+{
+    type Input = Input;
+    type Output = Output;
+
+    fn process(&mut self, input: Input) -> Result<Output, A3dError> {
+        (self.func)(input)
+    }
+}
+
+impl PipelineStage for BilateralFilter<u16> {
+    type Input = RgbdFrame;
+    type Output = RgbdFrame;
+
+    /// Filters the depth image of the frame.
+    fn process(&mut self, mut frame: RgbdFrame) -> Result<RgbdFrame, A3dError> {
+        frame.image.depth = self.filter(&frame.image.depth);
+        Ok(frame)
+    }
+}
+
+impl PipelineStage for RangeImageBuilder {
+    type Input = RgbdFrame;
+    type Output = Vec<RangeImage>;
+
+    /// Builds the range image pyramid of the frame. See [`RangeImageBuilder::build`].
+    fn process(&mut self, frame: RgbdFrame) -> Result<Vec<RangeImage>, A3dError> {
+        Ok(self.build(frame))
+    }
+}
+
+/// Per-pixel outputs of a learned model (e.g., an ONNX network) run on a frame.
+/// All arrays have the shape (height, width) of the frame.
+#[derive(Debug, Clone, Default)]
+pub struct FrameInference {
+    /// Replacement depth image (e.g., from depth completion or monocular depth),
+    /// in the same units as the frame's depth.
+    pub depth: Option<Array2<u16>>,
+    /// Validity mask, pixels with zero value (e.g., dynamic objects) are discarded.
+    pub mask: Option<Array2<u8>>,
+    /// Per-pixel class labels (e.g., semantic segmentation).
+    pub labels: Option<Array2<u32>>,
+}
+
+/// Integration point for learned components. Implement it by wrapping the runtime
+/// session of the model and converting its tensors into a [`FrameInference`]. The `onnx`
+/// feature provides an implementation for ONNX models, `OnnxFrameModel`.
+pub trait FrameModel {
+    /// Runs the model on the frame.
+    fn infer(&mut self, frame: &RgbdFrame) -> Result<FrameInference, A3dError>;
+}
+
+/// Stage that runs a [`FrameModel`] and feeds its outputs into the frame: the depth is
+/// replaced when the model produces one, and masked pixels have their depth set to zero, so
+/// they become invalid points in the range images. Labels are stored in
+/// [`RgbdImage::labels`](crate::image::RgbdImage::labels), so the stage chains with the
+/// other frame stages.
+pub struct FrameModelStage<Model> {
+    model: Model,
+}
+
+impl<Model: FrameModel> FrameModelStage<Model> {
+    /// Creates the stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The model to run on every frame.
+    pub fn new(model: Model) -> Self {
+        Self { model }
+    }
+}
+
+impl<Model: FrameModel> PipelineStage for FrameModelStage<Model> {
+    type Input = RgbdFrame;
+    type Output = RgbdFrame;
+
+    fn process(&mut self, mut frame: RgbdFrame) -> Result<RgbdFrame, A3dError> {
+        let inference = self.model.infer(&frame)?;
+        let shape = frame.image.depth.dim();
+
+        for (name, dim) in [
+            ("depth", inference.depth.as_ref().map(|depth| depth.dim())),
+            ("mask", inference.mask.as_ref().map(|mask| mask.dim())),
+            (
+                "labels",
+                inference.labels.as_ref().map(|labels| labels.dim()),
+            ),
+        ] {
+            if dim.is_some_and(|dim| dim != shape) {
+                return Err(A3dError::invalid_parameter(format!(
+                    "Model {name} output shape {dim:?} differs from the frame shape {shape:?}"
+                )));
+            }
+        }
+
+        if let Some(depth) = inference.depth {
+            frame.image.depth = depth;
+        }
+
+        if let Some(mask) = inference.mask {
+            frame
+                .image
+                .depth
+                .iter_mut()
+                .zip(mask.iter())
+                .for_each(|(z, mask)| {
+                    if *mask == 0 {
+                        *z = 0;
+                    }
+                });
+        }
+
+        if inference.labels.is_some() {
+            frame.image.labels = inference.labels;
+        }
+
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use ndarray::Array2;
+
+    use super::{from_fn, FrameInference, FrameModel, FrameModelStage, PipelineStage};
+    use crate::{
+        bilateral::BilateralFilter,
+        error::A3dError,
+        image::RgbdFrame,
+        io::dataset::RgbdDataset,
+        range_image::{RangeImage, RangeImageBuilder},
+        unit_test::sample_rgbd_dataset1,
+    };
+
+    #[rstest]
+    fn test_chain(sample_rgbd_dataset1: impl RgbdDataset) {
+        let mut pipeline = BilateralFilter::<u16>::default()
+            .then(from_fn(|mut frame: RgbdFrame| {
+                frame.image.depth.iter_mut().for_each(|z| {
+                    if *z > 4000 {
+                        *z = 0;
+                    }
+                });
+                Ok(frame)
+            }))
+            .then(RangeImageBuilder::default().pyramid_levels(2))
+            .then(from_fn(|pyramid: Vec<RangeImage>| Ok(pyramid.len())));
+
+        assert_eq!(
+            pipeline
+                .process(sample_rgbd_dataset1.get(0).unwrap())
+                .unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_error_stops_chain() {
+        let mut pipeline = from_fn(|value: i32| {
+            if value < 0 {
+                Err(A3dError::invalid_parameter("negative value"))
+            } else {
+                Ok(value)
+            }
+        })
+        .then(from_fn(|value: i32| Ok(value * 2)));
+
+        assert_eq!(pipeline.process(2).unwrap(), 4);
+        assert!(pipeline.process(-1).is_err());
+    }
+
+    struct UpperHalfMask;
+
+    impl FrameModel for UpperHalfMask {
+        fn infer(&mut self, frame: &RgbdFrame) -> Result<FrameInference, A3dError> {
+            let (height, width) = frame.image.depth.dim();
+            Ok(FrameInference {
+                mask: Some(Array2::from_shape_fn((height, width), |(row, _)| {
+                    (row >= height / 2) as u8
+                })),
+                labels: Some(Array2::ones((height, width))),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[rstest]
+    fn test_frame_model_stage(sample_rgbd_dataset1: impl RgbdDataset) {
+        let mut stage = FrameModelStage::new(UpperHalfMask);
+        let frame = stage.process(sample_rgbd_dataset1.get(0).unwrap()).unwrap();
+
+        let height = frame.image.height();
+        assert!(frame
+            .image
+            .depth
+            .rows()
+            .into_iter()
+            .take(height / 2)
+            .all(|row| row.iter().all(|z| *z == 0)));
+        assert_eq!(
+            frame.image.labels.as_ref().unwrap().dim(),
+            frame.image.depth.dim()
+        );
+
+        let mut pipeline = FrameModelStage::new(UpperHalfMask)
+            .then(BilateralFilter::<u16>::default())
+            .then(RangeImageBuilder::default().pyramid_levels(1));
+        let pyramid = pipeline
+            .process(sample_rgbd_dataset1.get(0).unwrap())
+            .unwrap();
+        assert!(pyramid[0]
+            .mask
+            .rows()
+            .into_iter()
+            .take(height / 2)
+            .all(|row| row.iter().all(|mask| *mask == 0)));
+    }
+}
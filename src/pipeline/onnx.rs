@@ -0,0 +1,223 @@
+use std::path::Path;
+
+use ndarray::{Array2, Axis};
+use ort::{session::Session, value::Tensor};
+
+use super::{FrameInference, FrameModel};
+use crate::{error::A3dError, image::RgbdFrame};
+
+fn runtime_error(err: ort::Error) -> A3dError {
+    A3dError::invalid_parameter(format!("ONNX runtime error: {err}"))
+}
+
+/// Names of the model outputs converted into the [`FrameInference`] fields. Outputs without
+/// a name are not read.
+#[derive(Debug, Clone, Default)]
+pub struct OnnxOutputs {
+    /// Float depth in meters, with one value per pixel.
+    pub depth: Option<String>,
+    /// Float validity score per pixel, pixels scoring 0.5 or less are masked.
+    pub mask: Option<String>,
+    /// Either int64 class labels per pixel or float class scores with shape
+    /// (1, classes, height, width), whose highest score gives the label.
+    pub labels: Option<String>,
+}
+
+/// A [`FrameModel`] that runs an ONNX network with ONNX Runtime.
+///
+/// The model's first input receives the frame colors as a float tensor with shape
+/// (1, 3, height, width) and values in [0, 1]. Its outputs must have the frame resolution.
+///
+/// The runtime library is loaded at the first session, from the path in the
+/// `ORT_DYLIB_PATH` environment variable or from the system library paths.
+pub struct OnnxFrameModel {
+    session: Session,
+    outputs: OnnxOutputs,
+}
+
+impl OnnxFrameModel {
+    /// Loads the model.
+    ///
+    /// # Arguments
+    ///
+    /// * `filepath` - Path to the `.onnx` file.
+    /// * `outputs` - Which model outputs to use.
+    pub fn from_file<P: AsRef<Path>>(filepath: P, outputs: OnnxOutputs) -> Result<Self, A3dError> {
+        let session = Session::builder()
+            .and_then(|builder| builder.commit_from_file(filepath))
+            .map_err(runtime_error)?;
+        if session.inputs.is_empty() {
+            return Err(A3dError::invalid_parameter("The ONNX model has no inputs"));
+        }
+        for name in [&outputs.depth, &outputs.mask, &outputs.labels]
+            .into_iter()
+            .flatten()
+        {
+            if !session.outputs.iter().any(|output| &output.name == name) {
+                return Err(A3dError::invalid_parameter(format!(
+                    "The ONNX model has no output named {name}"
+                )));
+            }
+        }
+        Ok(Self { session, outputs })
+    }
+}
+
+/// Converts the colors into a planar (1, 3, height, width) tensor data in [0, 1].
+fn color_input(frame: &RgbdFrame) -> ([usize; 4], Vec<f32>) {
+    let color = &frame.image.color;
+    let (height, width) = (color.shape()[0], color.shape()[1]);
+    let data = (0..3)
+        .flat_map(|channel| color.index_axis(Axis(2), channel).into_iter())
+        .map(|value| *value as f32 / 255.0)
+        .collect();
+    ([1, 3, height, width], data)
+}
+
+/// Reshapes an output with one value per pixel into an image.
+fn to_image<T: Copy>(name: &str, data: &[T], shape: (usize, usize)) -> Result<Array2<T>, A3dError> {
+    Array2::from_shape_vec(shape, data.to_vec()).map_err(|_| {
+        A3dError::invalid_parameter(format!(
+            "Model {name} output has {} values, expected {shape:?} pixels",
+            data.len()
+        ))
+    })
+}
+
+/// Converts metric depth into the frame units, invalid and negative depths become zero.
+fn quantize_depth(depth: Array2<f32>, depth_scale: f64) -> Array2<u16> {
+    depth.mapv(|meters| {
+        let value = (meters as f64 / depth_scale).round();
+        if value.is_finite() && value > 0.0 {
+            value.min(u16::MAX as f64) as u16
+        } else {
+            0
+        }
+    })
+}
+
+/// The label of each pixel is the class with the highest score.
+///
+/// # Arguments
+///
+/// * `scores` - Class scores, with the classes in the outer dimension.
+/// * `shape` - The (height, width) of the frame.
+fn scores_to_labels(scores: &[f32], shape: (usize, usize)) -> Result<Array2<u32>, A3dError> {
+    let pixels = shape.0 * shape.1;
+    if pixels == 0 || scores.is_empty() || !scores.len().is_multiple_of(pixels) {
+        return Err(A3dError::invalid_parameter(format!(
+            "Model labels output has {} scores, expected a multiple of {shape:?} pixels",
+            scores.len()
+        )));
+    }
+
+    let mut labels = Array2::zeros(shape);
+    let mut best = scores[..pixels].to_vec();
+    for (class, class_scores) in scores.chunks(pixels).enumerate().skip(1) {
+        for ((label, best), score) in labels.iter_mut().zip(best.iter_mut()).zip(class_scores) {
+            if *score > *best {
+                *best = *score;
+                *label = class as u32;
+            }
+        }
+    }
+    Ok(labels)
+}
+
+impl FrameModel for OnnxFrameModel {
+    fn infer(&mut self, frame: &RgbdFrame) -> Result<FrameInference, A3dError> {
+        let shape = frame.image.depth.dim();
+        let input = Tensor::from_array(color_input(frame)).map_err(runtime_error)?;
+        let outputs = self
+            .session
+            .run(ort::inputs![input])
+            .map_err(runtime_error)?;
+
+        let mut inference = FrameInference::default();
+        if let Some(name) = &self.outputs.depth {
+            let depth_scale = frame.image.depth_scale.ok_or_else(|| {
+                A3dError::invalid_parameter("The frame needs a depth scale to use model depth")
+            })?;
+            let (_, data) = outputs[name.as_str()]
+                .try_extract_tensor::<f32>()
+                .map_err(runtime_error)?;
+            inference.depth = Some(quantize_depth(to_image(name, data, shape)?, depth_scale));
+        }
+
+        if let Some(name) = &self.outputs.mask {
+            let (_, data) = outputs[name.as_str()]
+                .try_extract_tensor::<f32>()
+                .map_err(runtime_error)?;
+            inference.mask = Some(to_image(name, data, shape)?.mapv(|score| (score > 0.5) as u8));
+        }
+
+        if let Some(name) = &self.outputs.labels {
+            let output = &outputs[name.as_str()];
+            inference.labels = Some(match output.try_extract_tensor::<i64>() {
+                Ok((_, data)) => to_image(name, data, shape)?.mapv(|label| label as u32),
+                Err(_) => {
+                    let (_, data) = output.try_extract_tensor::<f32>().map_err(runtime_error)?;
+                    scores_to_labels(data, shape)?
+                }
+            });
+        }
+
+        Ok(inference)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array3};
+
+    use super::{color_input, quantize_depth, scores_to_labels};
+    use crate::{
+        camera::CameraIntrinsics,
+        image::{RgbdFrame, RgbdImage},
+    };
+
+    #[test]
+    fn test_color_input() {
+        let mut color = Array3::zeros((2, 3, 3));
+        color[(1, 2, 0)] = 255;
+        color[(0, 1, 2)] = 51;
+        let frame = RgbdFrame::new(
+            CameraIntrinsics::from_simple_intrinsic(1.0, 1.0, 1.0, 1.0, 3, 2),
+            RgbdImage::new(color, ndarray::Array2::zeros((2, 3))),
+            None,
+        );
+
+        let (shape, data) = color_input(&frame);
+        assert_eq!(shape, [1, 3, 2, 3]);
+        assert_eq!(data.len(), 18);
+        assert_eq!(data[5], 1.0);
+        assert_eq!(data[12 + 1], 0.2);
+        assert_eq!(data.iter().filter(|value| **value != 0.0).count(), 2);
+    }
+
+    #[test]
+    fn test_quantize_depth() {
+        assert_eq!(
+            quantize_depth(array![[1.5, 0.0], [-1.0, f32::NAN]], 0.001),
+            array![[1500, 0], [0, 0]]
+        );
+        assert_eq!(quantize_depth(array![[100.0]], 0.001), array![[u16::MAX]]);
+    }
+
+    #[test]
+    fn test_scores_to_labels() {
+        let scores = [
+            // Class 0.
+            0.9, 0.1, 0.2, 0.3, //
+            // Class 1.
+            0.05, 0.8, 0.1, 0.3, //
+            // Class 2.
+            0.05, 0.1, 0.7, 0.4,
+        ];
+        assert_eq!(
+            scores_to_labels(&scores, (2, 2)).unwrap(),
+            array![[0, 1], [2, 2]]
+        );
+        assert!(scores_to_labels(&scores[..10], (2, 2)).is_err());
+    }
+}
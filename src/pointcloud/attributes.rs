@@ -0,0 +1,226 @@
+use ndarray::Array1;
+
+use super::PointCloud;
+use crate::error::A3dError;
+
+/// Values of a named per point attribute, e.g., labels, timestamps or confidences.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeChannel {
+    F32(Array1<f32>),
+    F64(Array1<f64>),
+    U32(Array1<u32>),
+    I32(Array1<i32>),
+}
+
+/// Scalar types that can be stored in an [`AttributeChannel`].
+pub trait AttributeType: Copy + Default + Send + Sync + 'static {
+    /// Wraps the values into a channel.
+    fn into_channel(values: Array1<Self>) -> AttributeChannel;
+    /// The values of the channel, `None` if it has another type.
+    fn from_channel(channel: &AttributeChannel) -> Option<&Array1<Self>>;
+}
+
+macro_rules! impl_attribute_type {
+    ($type:ty, $variant:ident) => {
+        impl AttributeType for $type {
+            fn into_channel(values: Array1<Self>) -> AttributeChannel {
+                AttributeChannel::$variant(values)
+            }
+
+            fn from_channel(channel: &AttributeChannel) -> Option<&Array1<Self>> {
+                match channel {
+                    AttributeChannel::$variant(values) => Some(values),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_attribute_type!(f32, F32);
+impl_attribute_type!(f64, F64);
+impl_attribute_type!(u32, U32);
+impl_attribute_type!(i32, I32);
+
+/// Applies an expression to the values of a channel, whatever their type, and wraps the
+/// result back into a channel of the same type.
+macro_rules! map_channel {
+    ($channel:expr, $values:ident => $expr:expr) => {
+        match $channel {
+            AttributeChannel::F32($values) => AttributeChannel::F32($expr),
+            AttributeChannel::F64($values) => AttributeChannel::F64($expr),
+            AttributeChannel::U32($values) => AttributeChannel::U32($expr),
+            AttributeChannel::I32($values) => AttributeChannel::I32($expr),
+        }
+    };
+}
+
+impl AttributeChannel {
+    /// Number of values.
+    pub fn len(&self) -> usize {
+        match self {
+            AttributeChannel::F32(values) => values.len(),
+            AttributeChannel::F64(values) => values.len(),
+            AttributeChannel::U32(values) => values.len(),
+            AttributeChannel::I32(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The values at the indices, in their order.
+    pub fn select(&self, indices: &[usize]) -> Self {
+        map_channel!(self, values => indices.iter().map(|&i| values[i]).collect())
+    }
+
+    /// A channel of the same type filled with zeros.
+    pub(crate) fn zeros_like(&self, len: usize) -> Self {
+        map_channel!(self, _values => Array1::default(len))
+    }
+
+    /// Whether both channels have the same value type.
+    pub(crate) fn same_type(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    /// Concatenates channels, `None` if there are none or their types differ.
+    pub(crate) fn concat(channels: &[AttributeChannel]) -> Option<Self> {
+        fn concat<T: AttributeType>(channels: &[AttributeChannel]) -> Option<Array1<T>> {
+            let mut values = Vec::new();
+            for channel in channels {
+                values.extend(T::from_channel(channel)?.iter().copied());
+            }
+            Some(values.into())
+        }
+        Some(match channels.first()? {
+            AttributeChannel::F32(_) => AttributeChannel::F32(concat(channels)?),
+            AttributeChannel::F64(_) => AttributeChannel::F64(concat(channels)?),
+            AttributeChannel::U32(_) => AttributeChannel::U32(concat(channels)?),
+            AttributeChannel::I32(_) => AttributeChannel::I32(concat(channels)?),
+        })
+    }
+}
+
+impl PointCloud {
+    /// Adds or replaces an attribute channel, e.g., the labels of [`crate::segmentation::dbscan`].
+    ///
+    /// # Arguments
+    ///
+    /// * name - Name of the attribute.
+    /// * values - One value per point.
+    ///
+    /// # Returns
+    ///
+    /// The point cloud, or error if the number of values differs from the number of
+    /// points.
+    pub fn set_attribute<T: AttributeType>(
+        &mut self,
+        name: &str,
+        values: Array1<T>,
+    ) -> Result<&mut Self, A3dError> {
+        if values.len() != self.len() {
+            return Err(A3dError::invalid_parameter(format!(
+                "The attribute {name} has {} values for {} points.",
+                values.len(),
+                self.len()
+            )));
+        }
+        self.attributes
+            .insert(name.to_string(), T::into_channel(values));
+        Ok(self)
+    }
+
+    /// The values of an attribute, `None` if it doesn't exist or has another type.
+    pub fn attribute<T: AttributeType>(&self, name: &str) -> Option<&Array1<T>> {
+        self.attributes.get(name).and_then(T::from_channel)
+    }
+
+    /// Removes an attribute, returning its values if it existed.
+    pub fn remove_attribute(&mut self, name: &str) -> Option<AttributeChannel> {
+        self.attributes.remove(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::{array, Array1};
+
+    use super::AttributeChannel;
+    use crate::{
+        pointcloud::{AttributePolicy, PointCloud, VoxelReduction},
+        transform::{TransformBuilder, Transformable},
+    };
+
+    fn sample_pcl() -> PointCloud {
+        let mut pcl = PointCloud {
+            points: array![
+                Vector3::new(0.1, 0.1, 0.1),
+                Vector3::new(0.3, 0.3, 0.3),
+                Vector3::new(1.5, 0.5, 0.5),
+            ],
+            normals: None,
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+        pcl.set_attribute("label", array![7u32, 8, 9])
+            .unwrap()
+            .set_attribute("confidence", array![0.25f32, 0.75, 1.0])
+            .unwrap()
+            .set_attribute("timestamp", array![1e9f64, 1e9 + 1.0, 1e9 + 2.0])
+            .unwrap();
+        pcl
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut pcl = sample_pcl();
+        assert_eq!(pcl.attribute::<u32>("label"), Some(&array![7, 8, 9]));
+        assert!(pcl.attribute::<f32>("label").is_none());
+        assert!(pcl.attribute::<u32>("missing").is_none());
+        assert!(pcl.set_attribute("label", array![1u32]).is_err());
+
+        let selected = pcl.select(&[2, 0]);
+        assert_eq!(selected.attribute::<u32>("label"), Some(&array![9, 7]));
+
+        let transform = TransformBuilder::default()
+            .translation(Vector3::new(1.0, 0.0, 0.0))
+            .build();
+        let transformed = transform.transform(&pcl);
+        assert_eq!(transformed.attributes, pcl.attributes);
+
+        let downsampled = pcl.voxel_downsample(1.0, VoxelReduction::Average).unwrap();
+        assert_eq!(downsampled.attribute::<u32>("label"), Some(&array![7, 9]));
+        assert_eq!(
+            downsampled.attribute::<f32>("confidence"),
+            Some(&array![0.5, 1.0])
+        );
+        assert_eq!(
+            downsampled.attribute::<f64>("timestamp"),
+            Some(&array![1e9 + 0.5, 1e9 + 2.0])
+        );
+
+        assert_eq!(
+            pcl.remove_attribute("confidence"),
+            Some(AttributeChannel::F32(array![0.25, 0.75, 1.0]))
+        );
+        let merged = PointCloud::concat(&[&pcl, &sample_pcl()], AttributePolicy::Drop).unwrap();
+        assert!(merged.attribute::<f32>("confidence").is_none());
+        assert_eq!(merged.attribute::<u32>("label").unwrap().len(), 6);
+        let merged = PointCloud::concat(&[&pcl, &sample_pcl()], AttributePolicy::Fill).unwrap();
+        assert_eq!(
+            merged.attribute::<f32>("confidence"),
+            Some(&array![0.0, 0.0, 0.0, 0.25, 0.75, 1.0])
+        );
+
+        let mut relabeled = sample_pcl();
+        relabeled
+            .set_attribute("label", Array1::from_elem(3, -1i32))
+            .unwrap();
+        assert!(PointCloud::concat(&[&pcl, &relabeled], AttributePolicy::Fill).is_err());
+    }
+}
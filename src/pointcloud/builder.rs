@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+
+use nalgebra::Vector3;
+use ndarray::Array1;
+
+use super::{AttributeChannel, AttributeType, PointCloud};
+use crate::error::A3dError;
+
+/// Assembles a point cloud from iterators, e.g., while parsing a sensor message or
+/// filtering another cloud. Each method appends to its channel, so they can be called
+/// repeatedly, and [`PointCloudBuilder::build`] checks that the channels line up.
+#[derive(Debug, Clone, Default)]
+pub struct PointCloudBuilder {
+    points: Vec<Vector3<f32>>,
+    normals: Option<Vec<Vector3<f32>>>,
+    colors: Option<Vec<Vector3<u8>>>,
+    intensities: Option<Vec<f32>>,
+    curvatures: Option<Vec<f32>>,
+    attributes: BTreeMap<String, Vec<AttributeChannel>>,
+}
+
+/// Appends values to an optional channel, creating it on the first call.
+fn append<T>(channel: &mut Option<Vec<T>>, values: impl IntoIterator<Item = T>) {
+    channel.get_or_insert_with(Vec::new).extend(values);
+}
+
+/// Converts an optional channel, checking it has one value per point.
+fn finish<T>(
+    name: &str,
+    channel: Option<Vec<T>>,
+    len: usize,
+) -> Result<Option<Array1<T>>, A3dError> {
+    match channel {
+        Some(values) if values.len() != len => Err(A3dError::invalid_parameter(format!(
+            "The {name} channel has {} values for {len} points.",
+            values.len()
+        ))),
+        channel => Ok(channel.map(Array1::from_vec)),
+    }
+}
+
+impl PointCloudBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a builder with room for `capacity` points.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            points: Vec::with_capacity(capacity),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_points(mut self, points: impl IntoIterator<Item = Vector3<f32>>) -> Self {
+        self.points.extend(points);
+        self
+    }
+
+    pub fn with_normals(mut self, normals: impl IntoIterator<Item = Vector3<f32>>) -> Self {
+        append(&mut self.normals, normals);
+        self
+    }
+
+    pub fn with_colors(mut self, colors: impl IntoIterator<Item = Vector3<u8>>) -> Self {
+        append(&mut self.colors, colors);
+        self
+    }
+
+    pub fn with_intensities(mut self, intensities: impl IntoIterator<Item = f32>) -> Self {
+        append(&mut self.intensities, intensities);
+        self
+    }
+
+    pub fn with_curvatures(mut self, curvatures: impl IntoIterator<Item = f32>) -> Self {
+        append(&mut self.curvatures, curvatures);
+        self
+    }
+
+    /// Appends values to a named attribute, see [`PointCloud::set_attribute`].
+    pub fn with_attribute<T: AttributeType>(
+        mut self,
+        name: &str,
+        values: impl IntoIterator<Item = T>,
+    ) -> Self {
+        self.attributes
+            .entry(name.to_string())
+            .or_default()
+            .push(T::into_channel(values.into_iter().collect()));
+        self
+    }
+
+    /// Builds the point cloud.
+    ///
+    /// # Returns
+    ///
+    /// The point cloud, or error if a channel doesn't have one value per point or an
+    /// attribute was given values of different types.
+    pub fn build(self) -> Result<PointCloud, A3dError> {
+        let len = self.points.len();
+        let attributes = self
+            .attributes
+            .into_iter()
+            .map(|(name, chunks)| {
+                let channel = AttributeChannel::concat(&chunks).ok_or_else(|| {
+                    A3dError::invalid_parameter(format!(
+                        "The attribute {name} was given values of different types."
+                    ))
+                })?;
+                if channel.len() != len {
+                    return Err(A3dError::invalid_parameter(format!(
+                        "The attribute {name} has {} values for {len} points.",
+                        channel.len()
+                    )));
+                }
+                Ok((name, channel))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(PointCloud {
+            points: Array1::from_vec(self.points),
+            normals: finish("normals", self.normals, len)?,
+            colors: finish("colors", self.colors, len)?,
+            intensities: finish("intensities", self.intensities, len)?,
+            curvatures: finish("curvatures", self.curvatures, len)?,
+            attributes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::array;
+
+    use super::PointCloudBuilder;
+    use crate::transform::{TransformBuilder, Transformable};
+
+    #[test]
+    fn test_build() {
+        let pcl = PointCloudBuilder::with_capacity(3)
+            .with_points((0..2).map(|i| Vector3::new(i as f32, 0.0, 0.0)))
+            .with_intensities([0.5, 0.25])
+            .with_attribute("label", [4u32, 5])
+            .with_points([Vector3::new(0.0, 1.0, 0.0)])
+            .with_intensities([1.0])
+            .with_attribute("label", [6u32])
+            .build()
+            .unwrap();
+        assert_eq!(pcl.len(), 3);
+        assert_eq!(pcl.points[2], Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(pcl.intensities, Some(array![0.5, 0.25, 1.0]));
+        assert_eq!(pcl.attribute::<u32>("label"), Some(&array![4, 5, 6]));
+        assert!(pcl.normals.is_none());
+
+        let builder = PointCloudBuilder::new().with_points([Vector3::zeros(); 2]);
+        assert!(builder
+            .clone()
+            .with_normals([Vector3::z()])
+            .build()
+            .is_err());
+        assert!(builder
+            .clone()
+            .with_attribute("label", [1u32])
+            .with_attribute("label", [1i32])
+            .build()
+            .is_err());
+        assert!(builder.with_colors([Vector3::zeros(); 2]).build().is_ok());
+    }
+
+    #[test]
+    fn test_transform_mut() {
+        let mut pcl = PointCloudBuilder::new()
+            .with_points([Vector3::new(1.0, 2.0, 3.0), Vector3::new(-1.0, 0.5, 0.0)])
+            .with_normals([Vector3::x(), Vector3::z()])
+            .build()
+            .unwrap();
+        let transform = TransformBuilder::default()
+            .axis_angle(Vector3::y_axis(), 0.4)
+            .translation(Vector3::new(1.0, -2.0, 0.5))
+            .build();
+        let expected = transform.transform(&pcl);
+        pcl.transform_mut(&transform);
+        assert_eq!(pcl.points, expected.points);
+        assert_eq!(pcl.normals, expected.normals);
+    }
+}
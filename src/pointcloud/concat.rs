@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use nalgebra::Vector3;
+use ndarray::Array1;
+
+use super::{AttributeChannel, PointCloud};
+use crate::error::A3dError;
+
+/// How an attribute, e.g., normals or a channel, present in only some of the concatenated clouds is
+/// handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AttributePolicy {
+    /// The output doesn't have the attribute.
+    #[default]
+    Drop,
+    /// The points of the clouds without the attribute get zeros, e.g., black colors.
+    Fill,
+    /// The concatenation fails.
+    Error,
+}
+
+/// Concatenates an attribute of the clouds according to the policy.
+fn merge_attribute<T: Copy>(
+    clouds: &[&PointCloud],
+    attribute: impl Fn(&PointCloud) -> Option<&Array1<T>>,
+    zero: T,
+    name: &str,
+    policy: AttributePolicy,
+) -> Result<Option<Array1<T>>, A3dError> {
+    let count = clouds.iter().filter(|pcl| attribute(pcl).is_some()).count();
+    if count == 0 {
+        return Ok(None);
+    }
+    if count < clouds.len() {
+        match policy {
+            AttributePolicy::Drop => return Ok(None),
+            AttributePolicy::Error => {
+                return Err(A3dError::invalid_parameter(format!(
+                    "Only {count} of the {} point clouds have {name}.",
+                    clouds.len()
+                )))
+            }
+            AttributePolicy::Fill => {}
+        }
+    }
+    Ok(Some(
+        clouds
+            .iter()
+            .flat_map(|pcl| match attribute(pcl) {
+                Some(values) => values.to_vec(),
+                None => vec![zero; pcl.len()],
+            })
+            .collect(),
+    ))
+}
+
+/// Concatenates an attribute channel of the clouds according to the policy. Channels
+/// with different types can't be reconciled and are an error unless dropped.
+fn merge_channel(
+    clouds: &[&PointCloud],
+    name: &str,
+    policy: AttributePolicy,
+) -> Result<Option<AttributeChannel>, A3dError> {
+    let channels = clouds
+        .iter()
+        .map(|pcl| pcl.attributes.get(name))
+        .collect::<Vec<_>>();
+    let Some(first) = channels.iter().flatten().next().copied() else {
+        return Ok(None);
+    };
+    let same_types = channels
+        .iter()
+        .flatten()
+        .all(|channel| channel.same_type(first));
+    let count = channels.iter().flatten().count();
+    if !same_types || count < clouds.len() {
+        match policy {
+            AttributePolicy::Drop => return Ok(None),
+            AttributePolicy::Error | AttributePolicy::Fill if !same_types => {
+                return Err(A3dError::invalid_parameter(format!(
+                    "The attribute {name} has different types across the point clouds."
+                )))
+            }
+            AttributePolicy::Error => {
+                return Err(A3dError::invalid_parameter(format!(
+                    "Only {count} of the {} point clouds have {name}.",
+                    clouds.len()
+                )))
+            }
+            AttributePolicy::Fill => {}
+        }
+    }
+    let channels = clouds
+        .iter()
+        .zip(channels)
+        .map(|(pcl, channel)| {
+            channel
+                .cloned()
+                .unwrap_or_else(|| first.zeros_like(pcl.len()))
+        })
+        .collect::<Vec<_>>();
+    Ok(AttributeChannel::concat(&channels))
+}
+
+impl PointCloud {
+    /// Concatenates point clouds with their attributes, e.g., to merge registered scans.
+    ///
+    /// # Arguments
+    ///
+    /// * clouds - The point clouds, in the output order.
+    /// * policy - How the attributes present in only some of the clouds are handled.
+    ///
+    /// # Returns
+    ///
+    /// The concatenated point cloud, or error if an attribute is partially present with
+    /// [`AttributePolicy::Error`].
+    pub fn concat(clouds: &[&PointCloud], policy: AttributePolicy) -> Result<Self, A3dError> {
+        let names = clouds
+            .iter()
+            .flat_map(|pcl| pcl.attributes.keys())
+            .collect::<BTreeSet<_>>();
+        let mut attributes = BTreeMap::new();
+        for name in names {
+            if let Some(channel) = merge_channel(clouds, name, policy)? {
+                attributes.insert(name.clone(), channel);
+            }
+        }
+
+        Ok(Self {
+            points: clouds
+                .iter()
+                .flat_map(|pcl| pcl.points.iter().copied())
+                .collect(),
+            normals: merge_attribute(
+                clouds,
+                |pcl| pcl.normals.as_ref(),
+                Vector3::zeros(),
+                "normals",
+                policy,
+            )?,
+            colors: merge_attribute(
+                clouds,
+                |pcl| pcl.colors.as_ref(),
+                Vector3::zeros(),
+                "colors",
+                policy,
+            )?,
+            intensities: merge_attribute(
+                clouds,
+                |pcl| pcl.intensities.as_ref(),
+                0.0,
+                "intensities",
+                policy,
+            )?,
+            curvatures: merge_attribute(
+                clouds,
+                |pcl| pcl.curvatures.as_ref(),
+                0.0,
+                "curvatures",
+                policy,
+            )?,
+            attributes,
+        })
+    }
+
+    /// Appends the points of another cloud, see [`PointCloud::concat`].
+    ///
+    /// # Arguments
+    ///
+    /// * other - The appended point cloud.
+    /// * policy - How the attributes present in only one of the clouds are handled.
+    ///
+    /// # Returns
+    ///
+    /// Error if an attribute is partially present with [`AttributePolicy::Error`], this
+    /// cloud is then unchanged.
+    pub fn extend(&mut self, other: &PointCloud, policy: AttributePolicy) -> Result<(), A3dError> {
+        *self = Self::concat(&[self, other], policy)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::array;
+
+    use super::AttributePolicy;
+    use crate::pointcloud::PointCloud;
+
+    #[test]
+    fn test_concat() {
+        let first = PointCloud {
+            points: array![Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0)],
+            normals: Some(array![Vector3::z(), Vector3::z()]),
+            colors: Some(array![Vector3::new(255, 0, 0), Vector3::new(0, 255, 0)]),
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+        let second = PointCloud {
+            points: array![Vector3::new(2.0, 0.0, 0.0)],
+            normals: Some(array![Vector3::x()]),
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+
+        let merged = PointCloud::concat(&[&first, &second], AttributePolicy::Drop).unwrap();
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged.points[2], Vector3::new(2.0, 0.0, 0.0));
+        assert_eq!(
+            merged.normals.unwrap(),
+            array![Vector3::z(), Vector3::z(), Vector3::x()]
+        );
+        assert!(merged.colors.is_none());
+        assert!(merged.intensities.is_none());
+
+        let merged = PointCloud::concat(&[&second, &first], AttributePolicy::Fill).unwrap();
+        assert_eq!(
+            merged.colors.unwrap(),
+            array![
+                Vector3::new(0, 0, 0),
+                Vector3::new(255, 0, 0),
+                Vector3::new(0, 255, 0)
+            ]
+        );
+
+        assert!(PointCloud::concat(&[&first, &second], AttributePolicy::Error).is_err());
+        assert!(PointCloud::concat(&[&first, &first], AttributePolicy::Error).is_ok());
+        assert!(PointCloud::concat(&[], AttributePolicy::Error)
+            .unwrap()
+            .is_empty());
+
+        let mut extended = PointCloud::concat(&[&first], AttributePolicy::Drop).unwrap();
+        assert!(extended.extend(&second, AttributePolicy::Error).is_err());
+        assert_eq!(extended.len(), 2);
+        extended.extend(&second, AttributePolicy::Fill).unwrap();
+        assert_eq!(extended.len(), 3);
+        assert_eq!(extended.colors.unwrap()[2], Vector3::new(0, 0, 0));
+    }
+}
@@ -0,0 +1,159 @@
+use nalgebra::Vector3;
+
+use super::PointCloud;
+use crate::{
+    bounds::{Aabb3Df, Frustum, Obb3Df},
+    camera::PinholeCamera,
+};
+
+impl PointCloud {
+    /// The points accepted by a predicate, with their attributes, and their indices in
+    /// this cloud.
+    fn crop_by(&self, inside: impl Fn(&Vector3<f32>) -> bool) -> (PointCloud, Vec<usize>) {
+        let indices = self
+            .points
+            .iter()
+            .enumerate()
+            .filter(|(_, point)| inside(point))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        (self.select(&indices), indices)
+    }
+
+    /// Extracts the points inside an axis-aligned box, e.g., a submap around the robot.
+    ///
+    /// # Arguments
+    ///
+    /// * aabb - The box, in the cloud frame.
+    ///
+    /// # Returns
+    ///
+    /// The cropped cloud and the index in this cloud of each of its points.
+    pub fn crop(&self, aabb: &Aabb3Df) -> (PointCloud, Vec<usize>) {
+        self.crop_by(|point| aabb.contains(point))
+    }
+
+    /// Extracts the points inside an oriented box.
+    ///
+    /// # Arguments
+    ///
+    /// * obb - The box, in the cloud frame.
+    ///
+    /// # Returns
+    ///
+    /// The cropped cloud and the index in this cloud of each of its points.
+    pub fn crop_obb(&self, obb: &Obb3Df) -> (PointCloud, Vec<usize>) {
+        self.crop_by(|point| obb.contains(point))
+    }
+
+    /// Extracts the points visible by a camera, i.e., projecting inside its image between
+    /// two depths. Occlusions aren't considered.
+    ///
+    /// # Arguments
+    ///
+    /// * camera - The camera, with its pose in the cloud frame.
+    /// * near - Minimum depth of the points.
+    /// * far - Maximum depth of the points.
+    ///
+    /// # Returns
+    ///
+    /// The cropped cloud and the index in this cloud of each of its points.
+    pub fn crop_frustum(
+        &self,
+        camera: &PinholeCamera,
+        near: f32,
+        far: f32,
+    ) -> (PointCloud, Vec<usize>) {
+        let frustum = Frustum::new(camera, near, far);
+        self.crop_by(|point| frustum.contains(point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Rotation3, Vector3};
+    use ndarray::Array1;
+
+    use crate::{
+        bounds::{Aabb3Df, Obb3Df},
+        camera::{CameraIntrinsics, PinholeCamera},
+        pointcloud::PointCloud,
+        transform::TransformBuilder,
+    };
+
+    /// A 10x10x10 grid of points 0.1 apart with their index as intensity.
+    fn sample_grid() -> PointCloud {
+        PointCloud {
+            points: (0..1000)
+                .map(|i| {
+                    Vector3::new((i % 10) as f32, (i / 10 % 10) as f32, (i / 100) as f32) * 0.1
+                })
+                .collect(),
+            normals: None,
+            colors: None,
+            intensities: Some(Array1::from_shape_fn(1000, |i| i as f32)),
+            curvatures: None,
+            attributes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_crop() {
+        let grid = sample_grid();
+        let (cropped, indices) = grid.crop(&Aabb3Df::new(
+            Vector3::new(-1.0, -1.0, 0.25),
+            Vector3::new(0.15, 1.0, 0.45),
+        ));
+        assert_eq!(cropped.len(), 2 * 10 * 2);
+        assert_eq!(indices.len(), cropped.len());
+        assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        let intensities = cropped.intensities.as_ref().unwrap();
+        for (i, index) in indices.iter().enumerate() {
+            assert_eq!(cropped.points[i], grid.points[*index]);
+            assert_eq!(intensities[i], *index as f32);
+        }
+
+        // A box rotated 45 degrees around Z through the grid center.
+        let obb = Obb3Df::new(
+            Vector3::new(0.45, 0.45, 0.45),
+            *Rotation3::from_axis_angle(&Vector3::z_axis(), std::f32::consts::FRAC_PI_4).matrix(),
+            Vector3::new(0.1, 10.0, 10.0),
+        );
+        let (cropped, indices) = grid.crop_obb(&obb);
+        assert!(!cropped.is_empty());
+        assert!(indices.iter().all(|index| {
+            let point = grid.points[*index];
+            (point.x + point.y - 0.9).abs() / 2f32.sqrt() <= 0.1
+        }));
+    }
+
+    #[test]
+    fn test_crop_frustum() {
+        let grid = sample_grid();
+        // Looking down the Z axis from below the grid center.
+        let camera = PinholeCamera::new(
+            CameraIntrinsics::from_simple_intrinsic(50.0, 50.0, 50.0, 50.0, 100, 100),
+            TransformBuilder::default()
+                .translation(Vector3::new(0.45, 0.45, -0.5))
+                .build(),
+        );
+        let (cropped, indices) = grid.crop_frustum(&camera, 0.0, 10.0);
+        assert_eq!(cropped.len(), grid.len());
+        assert_eq!(indices, (0..1000).collect::<Vec<_>>());
+
+        // The first slice is at depth 0.5 and spans 0.9 of the 1.0 wide view.
+        let (cropped, _) = grid.crop_frustum(&camera, 0.0, 0.55);
+        assert_eq!(cropped.len(), 100);
+        let (cropped, _) = grid.crop_frustum(&camera, 0.55, 10.0);
+        assert_eq!(cropped.len(), 900);
+
+        let narrow = PinholeCamera::new(
+            CameraIntrinsics::from_simple_intrinsic(50.0, 50.0, 50.0, 50.0, 100, 100),
+            TransformBuilder::default()
+                .translation(Vector3::new(0.45, 0.45, -0.1))
+                .build(),
+        );
+        let (cropped, _) = grid.crop_frustum(&narrow, 0.0, 0.15);
+        assert_eq!(cropped.len(), 4);
+    }
+}
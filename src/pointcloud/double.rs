@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+
+use nalgebra::Vector3;
+use ndarray::Array1;
+
+use super::{AttributeChannel, PointCloud};
+use crate::transform::{TransformF64, Transformable};
+
+/// A point cloud with double precision positions, e.g., georeferenced LiDAR in UTM
+/// coordinates, where `f32` has a resolution of decimeters. Processing is done on
+/// [`PointCloud`]s in local coordinates, see [`PointCloudF64::to_local`].
+///
+/// Normals and other attributes don't depend on the position scale and stay in single
+/// precision.
+pub struct PointCloudF64 {
+    pub points: Array1<Vector3<f64>>,
+    pub normals: Option<Array1<Vector3<f32>>>,
+    pub colors: Option<Array1<Vector3<u8>>>,
+    pub intensities: Option<Array1<f32>>,
+    pub curvatures: Option<Array1<f32>>,
+    pub attributes: BTreeMap<String, AttributeChannel>,
+}
+
+impl PointCloudF64 {
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Mean of the points, a good origin for [`PointCloudF64::to_local`]. Zero if the
+    /// cloud is empty.
+    pub fn centroid(&self) -> Vector3<f64> {
+        if self.is_empty() {
+            return Vector3::zeros();
+        }
+        self.points.iter().sum::<Vector3<f64>>() / self.len() as f64
+    }
+
+    /// Converts to a single precision point cloud with coordinates relative to an origin.
+    ///
+    /// # Arguments
+    ///
+    /// * origin - Origin of the local coordinates, close to the points, e.g., their
+    ///   centroid or the first sensor position.
+    ///
+    /// # Returns
+    ///
+    /// The point cloud, whose points are `point - origin`.
+    pub fn to_local(&self, origin: &Vector3<f64>) -> PointCloud {
+        PointCloud {
+            points: self
+                .points
+                .iter()
+                .map(|point| (point - origin).cast::<f32>())
+                .collect(),
+            normals: self.normals.clone(),
+            colors: self.colors.clone(),
+            intensities: self.intensities.clone(),
+            curvatures: self.curvatures.clone(),
+            attributes: self.attributes.clone(),
+        }
+    }
+
+    /// Converts a point cloud in local coordinates back to double precision, the inverse
+    /// of [`PointCloudF64::to_local`].
+    ///
+    /// # Arguments
+    ///
+    /// * pcl - The point cloud in local coordinates.
+    /// * origin - Origin of the local coordinates.
+    pub fn from_local(pcl: &PointCloud, origin: &Vector3<f64>) -> Self {
+        Self {
+            points: pcl
+                .points
+                .iter()
+                .map(|point| point.cast::<f64>() + origin)
+                .collect(),
+            normals: pcl.normals.clone(),
+            colors: pcl.colors.clone(),
+            intensities: pcl.intensities.clone(),
+            curvatures: pcl.curvatures.clone(),
+            attributes: pcl.attributes.clone(),
+        }
+    }
+}
+
+impl Transformable<PointCloudF64> for TransformF64 {
+    fn transform(&self, pcl: &PointCloudF64) -> PointCloudF64 {
+        let rotation = self.0.rotation.cast::<f32>();
+        PointCloudF64 {
+            points: self.transform_vectors(pcl.points.clone()),
+            normals: pcl
+                .normals
+                .as_ref()
+                .map(|normals| normals.map(|normal| rotation * normal)),
+            colors: pcl.colors.clone(),
+            intensities: pcl.intensities.clone(),
+            curvatures: pcl.curvatures.clone(),
+            attributes: pcl.attributes.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+    use ndarray::Array1;
+
+    use super::PointCloudF64;
+    use crate::transform::{TransformF64, Transformable};
+
+    /// Points 1 mm apart in UTM coordinates.
+    fn sample_utm_cloud() -> PointCloudF64 {
+        PointCloudF64 {
+            points: (0..100)
+                .map(|i| Vector3::new(500_000.0 + i as f64 * 1e-3, 4_100_000.0, 120.0))
+                .collect(),
+            normals: Some(Array1::from_elem(100, Vector3::z())),
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_local_coordinates() {
+        let utm = sample_utm_cloud();
+        // Single precision merges neighbor points.
+        assert_eq!(utm.points[0].cast::<f32>(), utm.points[1].cast::<f32>());
+
+        let origin = utm.centroid();
+        let local = utm.to_local(&origin);
+        assert!((local.points[1].x - local.points[0].x - 1e-3).abs() < 1e-7);
+
+        let back = PointCloudF64::from_local(&local, &origin);
+        assert!(back
+            .points
+            .iter()
+            .zip(utm.points.iter())
+            .all(|(a, b)| (a - b).norm() < 1e-7));
+    }
+
+    #[test]
+    fn test_transform_f64() {
+        let utm = sample_utm_cloud();
+        // Rotation around the cloud center followed by a small translation.
+        let origin = utm.centroid();
+        let transform = &TransformF64::new(
+            &(origin + Vector3::new(10.0, -5.0, 0.5)),
+            &UnitQuaternion::from_axis_angle(&Vector3::z_axis(), 0.3),
+        ) * &TransformF64::new(&-origin, &Quaternion::identity());
+        let transformed = transform.transform(&utm);
+        let expected = transform.0.transform_point(&utm.points[0].into());
+        assert!((transformed.points[0] - expected.coords).norm() < 1e-6);
+        assert!((transformed.normals.as_ref().unwrap()[0] - Vector3::z()).norm() < 1e-6);
+
+        // The local transform moves the local points as the global one the global points.
+        let local = transform
+            .to_local(&origin)
+            .transform(&utm.to_local(&origin));
+        let expected = transformed.to_local(&origin);
+        assert!(local
+            .points
+            .iter()
+            .zip(expected.points.iter())
+            .all(|(a, b)| (a - b).norm() < 1e-5));
+
+        let identity = &transform * &transform.inverse();
+        assert!(identity.translation().norm() < 1e-9);
+    }
+}
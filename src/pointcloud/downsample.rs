@@ -0,0 +1,239 @@
+use std::{collections::HashMap, iter::Sum};
+
+use nalgebra::Vector3;
+use ndarray::Array1;
+use num::Float;
+
+use super::{AttributeChannel, PointCloud};
+use crate::error::A3dError;
+
+/// How the points falling in the same voxel are reduced to one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VoxelReduction {
+    /// Averages the points and their attributes. Normals are renormalized, and integer
+    /// attribute channels take the value of the first point of the voxel.
+    #[default]
+    Average,
+    /// Keeps the point closest to the voxel centroid, with its original attributes.
+    ClosestToCentroid,
+}
+
+/// Points of a voxel.
+struct Voxel {
+    point_sum: Vector3<f32>,
+    indices: Vec<usize>,
+}
+
+/// Mean of a per point scalar over each voxel.
+fn scalar_means<T: Float + Sum>(voxels: &[Voxel], values: &Array1<T>) -> Array1<T> {
+    voxels
+        .iter()
+        .map(|voxel| {
+            voxel.indices.iter().map(|&i| values[i]).sum::<T>()
+                / T::from(voxel.indices.len()).unwrap()
+        })
+        .collect()
+}
+
+/// Value of the first point of each voxel, for values that can't be averaged, e.g.,
+/// labels.
+fn first_values<T: Copy>(voxels: &[Voxel], values: &Array1<T>) -> Array1<T> {
+    voxels
+        .iter()
+        .map(|voxel| values[voxel.indices[0]])
+        .collect()
+}
+
+impl PointCloud {
+    /// Reduces the cloud to one point per occupied voxel of a regular grid, e.g., to
+    /// speed up ICP on dense clouds. Voxels are output in the order of their first point,
+    /// so the result is deterministic.
+    ///
+    /// # Arguments
+    ///
+    /// * voxel_size - Edge length of the voxels.
+    /// * reduction - How the points of a voxel are combined.
+    ///
+    /// # Returns
+    ///
+    /// The downsampled point cloud, or error if the voxel size isn't positive.
+    pub fn voxel_downsample(
+        &self,
+        voxel_size: f32,
+        reduction: VoxelReduction,
+    ) -> Result<PointCloud, A3dError> {
+        if voxel_size <= 0.0 {
+            return Err(A3dError::invalid_parameter(
+                "The voxel size must be positive.",
+            ));
+        }
+
+        let mut keys: HashMap<[i32; 3], usize> = HashMap::new();
+        let mut voxels: Vec<Voxel> = Vec::new();
+        for (i, point) in self.points.iter().enumerate() {
+            let key = [0, 1, 2].map(|axis| (point[axis] / voxel_size).floor() as i32);
+            let index = *keys.entry(key).or_insert_with(|| {
+                voxels.push(Voxel {
+                    point_sum: Vector3::zeros(),
+                    indices: Vec::new(),
+                });
+                voxels.len() - 1
+            });
+            voxels[index].point_sum += point;
+            voxels[index].indices.push(i);
+        }
+
+        match reduction {
+            VoxelReduction::ClosestToCentroid => {
+                let indices = voxels
+                    .iter()
+                    .map(|voxel| {
+                        let centroid = voxel.point_sum / voxel.indices.len() as f32;
+                        *voxel
+                            .indices
+                            .iter()
+                            .min_by(|a, b| {
+                                (self.points[**a] - centroid)
+                                    .norm_squared()
+                                    .total_cmp(&(self.points[**b] - centroid).norm_squared())
+                            })
+                            .unwrap()
+                    })
+                    .collect::<Vec<_>>();
+                Ok(self.select(&indices))
+            }
+            VoxelReduction::Average => {
+                let mean = |voxel: &Voxel, value: &dyn Fn(usize) -> Vector3<f32>| {
+                    voxel
+                        .indices
+                        .iter()
+                        .fold(Vector3::zeros(), |sum, &i| sum + value(i))
+                        / voxel.indices.len() as f32
+                };
+                Ok(PointCloud {
+                    points: voxels
+                        .iter()
+                        .map(|voxel| voxel.point_sum / voxel.indices.len() as f32)
+                        .collect(),
+                    normals: self.normals.as_ref().map(|normals| {
+                        voxels
+                            .iter()
+                            .map(|voxel| {
+                                mean(voxel, &|i| normals[i])
+                                    .try_normalize(1e-6)
+                                    .unwrap_or_else(Vector3::zeros)
+                            })
+                            .collect()
+                    }),
+                    colors: self.colors.as_ref().map(|colors| {
+                        voxels
+                            .iter()
+                            .map(|voxel| {
+                                mean(voxel, &|i| colors[i].cast::<f32>())
+                                    .map(|channel| channel.round() as u8)
+                            })
+                            .collect()
+                    }),
+                    intensities: self
+                        .intensities
+                        .as_ref()
+                        .map(|intensities| scalar_means(&voxels, intensities)),
+                    curvatures: self
+                        .curvatures
+                        .as_ref()
+                        .map(|curvatures| scalar_means(&voxels, curvatures)),
+                    attributes: self
+                        .attributes
+                        .iter()
+                        .map(|(name, channel)| {
+                            let channel = match channel {
+                                AttributeChannel::F32(values) => {
+                                    AttributeChannel::F32(scalar_means(&voxels, values))
+                                }
+                                AttributeChannel::F64(values) => {
+                                    AttributeChannel::F64(scalar_means(&voxels, values))
+                                }
+                                AttributeChannel::U32(values) => {
+                                    AttributeChannel::U32(first_values(&voxels, values))
+                                }
+                                AttributeChannel::I32(values) => {
+                                    AttributeChannel::I32(first_values(&voxels, values))
+                                }
+                            };
+                            (name.clone(), channel)
+                        })
+                        .collect(),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::array;
+    use rstest::rstest;
+
+    use super::VoxelReduction;
+    use crate::{pointcloud::PointCloud, unit_test::sample_teapot_surface};
+
+    #[test]
+    fn test_voxel_downsample() {
+        let pcl = PointCloud {
+            points: array![
+                Vector3::new(0.1, 0.1, 0.1),
+                Vector3::new(0.3, 0.3, 0.3),
+                Vector3::new(1.5, 0.5, 0.5),
+                Vector3::new(0.25, 0.2, 0.2),
+            ],
+            normals: Some(array![
+                Vector3::x(),
+                Vector3::y(),
+                Vector3::z(),
+                Vector3::x()
+            ]),
+            colors: Some(array![
+                Vector3::new(0, 0, 0),
+                Vector3::new(30, 60, 90),
+                Vector3::new(255, 255, 255),
+                Vector3::new(0, 0, 0)
+            ]),
+            intensities: Some(array![0.0, 0.3, 1.0, 0.6]),
+            curvatures: None,
+            attributes: Default::default(),
+        };
+
+        let average = pcl.voxel_downsample(1.0, VoxelReduction::Average).unwrap();
+        assert_eq!(average.len(), 2);
+        assert!((average.points[0] - Vector3::new(0.65, 0.6, 0.6) / 3.0).norm() < 1e-6);
+        assert_eq!(average.points[1], Vector3::new(1.5, 0.5, 0.5));
+        let normal = average.normals.as_ref().unwrap()[0];
+        assert!((normal - Vector3::new(2.0, 1.0, 0.0).normalize()).norm() < 1e-6);
+        assert_eq!(
+            average.colors.as_ref().unwrap()[0],
+            Vector3::new(10, 20, 30)
+        );
+        assert!((average.intensities.as_ref().unwrap()[0] - 0.3).abs() < 1e-6);
+
+        let closest = pcl
+            .voxel_downsample(1.0, VoxelReduction::ClosestToCentroid)
+            .unwrap();
+        assert_eq!(closest.points, array![pcl.points[3], pcl.points[2]]);
+        assert_eq!(closest.intensities.unwrap(), array![0.6, 1.0]);
+
+        assert!(pcl.voxel_downsample(0.0, VoxelReduction::Average).is_err());
+    }
+
+    #[rstest]
+    fn test_voxel_downsample_resolution(sample_teapot_surface: PointCloud) {
+        let coarse = sample_teapot_surface
+            .voxel_downsample(0.2, VoxelReduction::Average)
+            .unwrap();
+        let fine = sample_teapot_surface
+            .voxel_downsample(0.05, VoxelReduction::Average)
+            .unwrap();
+        assert!(coarse.len() < fine.len());
+        assert!(fine.len() <= sample_teapot_surface.len());
+    }
+}
@@ -0,0 +1,362 @@
+use nalgebra::{DMatrix, DVector, Matrix3, Vector2, Vector3};
+use rayon::prelude::*;
+
+use super::PointCloud;
+use crate::{error::A3dError, kdtree::R3dTree};
+
+/// Adds samples around each point on its fitted surface, filling the holes of sparse
+/// scans.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MlsUpsampling {
+    /// Radius of the disk sampled around each point, in its local tangent plane.
+    pub radius: f32,
+    /// Spacing of the samples on the disk.
+    pub step: f32,
+}
+
+/// Parameters of the Moving Least Squares filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MlsParams {
+    /// Radius of the neighbors fitted around each point. The neighbors are also weighted
+    /// by a Gaussian of this width.
+    pub search_radius: f32,
+    /// Order of the polynomial height field fitted over the local tangent plane. 0 projects
+    /// the points on their local plane, 2 follows curved surfaces.
+    pub polynomial_order: usize,
+    /// Maximum number of neighbors of each point, the closest ones are used.
+    pub max_neighbors: usize,
+    /// Optional upsampling of the output.
+    pub upsampling: Option<MlsUpsampling>,
+}
+
+impl Default for MlsParams {
+    fn default() -> Self {
+        Self {
+            search_radius: 0.03,
+            polynomial_order: 2,
+            max_neighbors: 64,
+            upsampling: None,
+        }
+    }
+}
+
+/// Polynomial height field over the weighted tangent plane of a neighborhood.
+struct LocalSurface {
+    origin: Vector3<f32>,
+    axes: Matrix3<f32>,
+    /// Scale of the plane coordinates given to the polynomial, keeps it well conditioned.
+    scale: f32,
+    order: usize,
+    coefficients: DVector<f32>,
+}
+
+/// Monomials `u^(d - i) v^i` of degrees `d` up to `order`, and their partial derivatives.
+fn monomials(order: usize, uv: &Vector2<f32>) -> Vec<(f32, f32, f32)> {
+    let power = |base: f32, exponent: usize| base.powi(exponent as i32);
+    let mut terms = Vec::with_capacity((order + 1) * (order + 2) / 2);
+    for degree in 0..=order {
+        for i in 0..=degree {
+            let (pu, pv) = (degree - i, i);
+            let du = if pu > 0 {
+                pu as f32 * power(uv.x, pu - 1) * power(uv.y, pv)
+            } else {
+                0.0
+            };
+            let dv = if pv > 0 {
+                pv as f32 * power(uv.x, pu) * power(uv.y, pv - 1)
+            } else {
+                0.0
+            };
+            terms.push((power(uv.x, pu) * power(uv.y, pv), du, dv));
+        }
+    }
+    terms
+}
+
+impl LocalSurface {
+    /// Fits the surface to the neighbors of `query`, `None` with fewer than 3 neighbors.
+    fn fit(
+        points: &[Vector3<f32>],
+        query: &Vector3<f32>,
+        search_radius: f32,
+        order: usize,
+    ) -> Option<Self> {
+        if points.len() < 3 {
+            return None;
+        }
+        let sqr_radius = search_radius * search_radius;
+        let weights = points
+            .iter()
+            .map(|point| (-(point - query).norm_squared() / sqr_radius).exp())
+            .collect::<Vec<_>>();
+        let weight_sum = weights.iter().sum::<f32>();
+        let origin = points
+            .iter()
+            .zip(&weights)
+            .fold(Vector3::zeros(), |sum, (point, weight)| {
+                sum + point * *weight
+            })
+            / weight_sum;
+        let covariance =
+            points
+                .iter()
+                .zip(&weights)
+                .fold(Matrix3::zeros(), |sum, (point, weight)| {
+                    let centered = point - origin;
+                    sum + centered * centered.transpose() * *weight
+                });
+
+        // Columns are the tangent directions and the normal, by decreasing variance.
+        let eigen = covariance.symmetric_eigen();
+        let mut order_by_variance = [0, 1, 2];
+        order_by_variance.sort_by(|a, b| eigen.eigenvalues[*b].total_cmp(&eigen.eigenvalues[*a]));
+        let mut axes =
+            Matrix3::from_columns(&order_by_variance.map(|i| eigen.eigenvectors.column(i)));
+        if axes.determinant() < 0.0 {
+            axes.set_column(1, &-axes.column(1));
+        }
+
+        let mut surface = Self {
+            origin,
+            axes,
+            scale: search_radius,
+            order: 0,
+            coefficients: DVector::zeros(0),
+        };
+        let num_coefficients = (order + 1) * (order + 2) / 2;
+        if order == 0 || points.len() < num_coefficients {
+            return Some(surface);
+        }
+
+        let mut design = DMatrix::zeros(points.len(), num_coefficients);
+        let mut heights = DVector::zeros(points.len());
+        for (row, (point, weight)) in points.iter().zip(&weights).enumerate() {
+            let (uv, height) = surface.to_local(point);
+            let weight = weight.sqrt();
+            for (column, (value, _, _)) in monomials(order, &uv).into_iter().enumerate() {
+                design[(row, column)] = value * weight;
+            }
+            heights[row] = height * weight;
+        }
+        let transposed = design.transpose();
+        if let Some(cholesky) = (&transposed * design).cholesky() {
+            surface.coefficients = cholesky.solve(&(transposed * heights));
+            surface.order = order;
+        }
+        Some(surface)
+    }
+
+    /// Scaled tangent plane coordinates and height of a point.
+    fn to_local(&self, point: &Vector3<f32>) -> (Vector2<f32>, f32) {
+        let local = self.axes.transpose() * (point - self.origin);
+        (local.xy() / self.scale, local.z)
+    }
+
+    /// The point of the surface above tangent plane coordinates, and its normal.
+    fn evaluate(&self, uv: &Vector2<f32>) -> (Vector3<f32>, Vector3<f32>) {
+        let (mut height, mut du, mut dv) = (0.0, 0.0, 0.0);
+        if self.order > 0 {
+            for ((value, value_du, value_dv), coefficient) in monomials(self.order, uv)
+                .into_iter()
+                .zip(self.coefficients.iter())
+            {
+                height += coefficient * value;
+                du += coefficient * value_du;
+                dv += coefficient * value_dv;
+            }
+        }
+        let point =
+            self.origin + self.axes * Vector3::new(uv.x * self.scale, uv.y * self.scale, height);
+        let normal =
+            (self.axes * Vector3::new(-du / self.scale, -dv / self.scale, 1.0)).normalize();
+        (point, normal)
+    }
+}
+
+impl PointCloud {
+    /// Smooths the cloud with Moving Least Squares: each point is projected on a polynomial
+    /// surface fitted to its neighbors, reducing the sensor noise, e.g., before meshing.
+    ///
+    /// # Arguments
+    ///
+    /// * params - Parameters of the filter.
+    ///
+    /// # Returns
+    ///
+    /// The smoothed point cloud, with the normals of the fitted surfaces, flipped to agree
+    /// with the input normals if any. The other attributes are copied from the source
+    /// point, and points with fewer than 3 neighbors are kept as is. Error if the radii,
+    /// the step or `max_neighbors` aren't positive.
+    pub fn mls_smooth(&self, params: &MlsParams) -> Result<PointCloud, A3dError> {
+        if params.search_radius <= 0.0 || params.max_neighbors == 0 {
+            return Err(A3dError::invalid_parameter(
+                "The MLS search radius and maximum number of neighbors must be positive.",
+            ));
+        }
+        if let Some(upsampling) = params.upsampling {
+            if upsampling.radius <= 0.0 || upsampling.step <= 0.0 {
+                return Err(A3dError::invalid_parameter(
+                    "The MLS upsampling radius and step must be positive.",
+                ));
+            }
+        }
+
+        let kdtree = R3dTree::new(&self.points.view());
+        let sqr_radius = params.search_radius * params.search_radius;
+        let samples = (0..self.len())
+            .into_par_iter()
+            .map(|index| {
+                let query = &self.points[index];
+                let neighbors = kdtree
+                    .knn(query, params.max_neighbors)
+                    .into_iter()
+                    .filter(|(_, sqr_distance)| *sqr_distance <= sqr_radius)
+                    .map(|(neighbor, _)| self.points[neighbor])
+                    .collect::<Vec<_>>();
+                let original_normal = self.normals.as_ref().map(|normals| normals[index]);
+                let Some(surface) = LocalSurface::fit(
+                    &neighbors,
+                    query,
+                    params.search_radius,
+                    params.polynomial_order,
+                ) else {
+                    return vec![(
+                        *query,
+                        original_normal.unwrap_or_else(Vector3::zeros),
+                        index,
+                    )];
+                };
+
+                let (uv, _) = surface.to_local(query);
+                let offsets = match params.upsampling {
+                    None => vec![Vector2::zeros()],
+                    Some(MlsUpsampling { radius, step }) => {
+                        let steps = (radius / step).floor() as i32;
+                        (-steps..=steps)
+                            .flat_map(|i| {
+                                (-steps..=steps)
+                                    .map(move |j| Vector2::new(i as f32, j as f32) * step)
+                            })
+                            .filter(|offset| offset.norm() <= radius)
+                            .collect()
+                    }
+                };
+                offsets
+                    .into_iter()
+                    .map(|offset| {
+                        let (point, mut normal) = surface.evaluate(&(uv + offset / surface.scale));
+                        if original_normal.is_some_and(|original| original.dot(&normal) < 0.0) {
+                            normal = -normal;
+                        }
+                        (point, normal, index)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+
+        let sources = samples
+            .iter()
+            .map(|(_, _, index)| *index)
+            .collect::<Vec<_>>();
+        Ok(PointCloud {
+            points: samples.iter().map(|(point, _, _)| *point).collect(),
+            normals: Some(samples.iter().map(|(_, normal, _)| *normal).collect()),
+            ..self.select(&sources)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Array1;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{MlsParams, MlsUpsampling};
+    use crate::pointcloud::PointCloud;
+
+    /// Height of the sampled surface.
+    fn surface_height(point: &Vector3<f32>) -> f32 {
+        0.5 * point.x * point.x + 0.2 * point.y
+    }
+
+    /// Root mean square distance, along Z, between the points and the surface.
+    fn rms_error(pcl: &PointCloud) -> f32 {
+        let sum = pcl
+            .points
+            .iter()
+            .map(|point| (point.z - surface_height(point)).powi(2))
+            .sum::<f32>();
+        (sum / pcl.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_mls_smooth() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let points = (0..40 * 40)
+            .map(|i| {
+                let mut point = Vector3::new(
+                    (i % 40) as f32 * 0.025 - 0.5,
+                    (i / 40) as f32 * 0.025 - 0.5,
+                    0.0,
+                );
+                point.z = surface_height(&point) + rng.gen_range(-0.005..0.005);
+                point
+            })
+            .collect::<Array1<_>>();
+        let pcl = PointCloud {
+            points,
+            normals: None,
+            colors: None,
+            intensities: Some(Array1::from_elem(1600, 0.5)),
+            curvatures: None,
+            attributes: Default::default(),
+        };
+        let noise = rms_error(&pcl);
+
+        let params = MlsParams {
+            search_radius: 0.1,
+            ..Default::default()
+        };
+        let smoothed = pcl.mls_smooth(&params).unwrap();
+        assert_eq!(smoothed.len(), pcl.len());
+        assert!(
+            rms_error(&smoothed) < noise * 0.5,
+            "{} {noise}",
+            rms_error(&smoothed)
+        );
+        assert_eq!(smoothed.intensities.as_ref().unwrap()[0], 0.5);
+        // Normal of the center point, up to the sign.
+        let normal = smoothed.normals.as_ref().unwrap()[20 * 40 + 20];
+        assert!(normal.dot(&Vector3::new(0.0, -0.2, 1.0).normalize()).abs() > 0.99);
+
+        // A plane can't follow the curvature as closely.
+        let plane = pcl
+            .mls_smooth(&MlsParams {
+                polynomial_order: 0,
+                ..params
+            })
+            .unwrap();
+        assert!(rms_error(&plane) > rms_error(&smoothed));
+
+        let upsampled = pcl
+            .mls_smooth(&MlsParams {
+                upsampling: Some(MlsUpsampling {
+                    radius: 0.01,
+                    step: 0.01,
+                }),
+                ..params
+            })
+            .unwrap();
+        assert_eq!(upsampled.len(), pcl.len() * 5);
+        assert!(rms_error(&upsampled) < noise * 0.5);
+
+        assert!(pcl
+            .mls_smooth(&MlsParams {
+                search_radius: 0.0,
+                ..params
+            })
+            .is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{
+    io::Geometry,
+    transform::{Transform, Transformable},
+};
+use nalgebra::Vector3;
+use ndarray::prelude::*;
+use std::collections::BTreeMap;
+
+mod attributes;
+mod builder;
+mod concat;
+mod crop;
+mod double;
+mod downsample;
+mod mls;
+mod normals;
+mod outlier;
+mod resolution;
+mod visibility;
+pub use attributes::{AttributeChannel, AttributeType};
+pub use builder::PointCloudBuilder;
+pub use concat::AttributePolicy;
+pub use double::PointCloudF64;
+pub use downsample::VoxelReduction;
+pub use mls::{MlsParams, MlsUpsampling};
+pub(crate) use normals::neighborhood_covariance;
+pub use normals::Neighborhood;
+pub use resolution::ResolutionStats;
+
+pub struct PointCloud {
+    pub points: Array1<Vector3<f32>>,
+    pub normals: Option<Array1<Vector3<f32>>>,
+    pub colors: Option<Array1<Vector3<u8>>>,
+    /// Per point intensity or reflectance, e.g., of LiDAR returns.
+    pub intensities: Option<Array1<f32>>,
+    /// Per point surface variation, see [`PointCloud::estimate_curvatures`].
+    pub curvatures: Option<Array1<f32>>,
+    /// Other named per point attributes, see [`PointCloud::set_attribute`].
+    pub attributes: BTreeMap<String, AttributeChannel>,
+}
+
+impl PointCloud {
+    pub fn from_geometry(geometry: Geometry) -> Self {
+        Self {
+            points: geometry.points,
+            normals: geometry.normals,
+            colors: geometry.colors,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        }
+    }
+
+    pub fn zeros(len: usize) -> Self {
+        Self {
+            points: Array1::zeros(len),
+            normals: Some(Array1::zeros(len)),
+            colors: Some(Array1::zeros(len)),
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        }
+    }
+
+    /// Creates a planar point cloud in the XY plane from the ranges of a 2D LiDAR scan, as
+    /// in ROS' `LaserScan`. Ranges that aren't finite and positive are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * ranges - The range of each ray, in meters.
+    /// * angle_min - Angle of the first ray, counterclockwise from the X axis, in radians.
+    /// * angle_increment - Angle between consecutive rays, in radians.
+    pub fn from_laser_scan(ranges: &[f32], angle_min: f32, angle_increment: f32) -> Self {
+        let points = ranges
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| range.is_finite() && **range > 0.0)
+            .map(|(ray, range)| {
+                let (sin, cos) = (angle_min + ray as f32 * angle_increment).sin_cos();
+                Vector3::new(range * cos, range * sin, 0.0)
+            })
+            .collect();
+        Self {
+            points,
+            normals: None,
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len_of(Axis(0))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Creates a point cloud with a subset of the points and their attributes.
+    ///
+    /// # Arguments
+    ///
+    /// * indices - Indices of the points to keep, in the output order.
+    pub fn select(&self, indices: &[usize]) -> PointCloud {
+        fn select<T: Copy>(array: &Array1<T>, indices: &[usize]) -> Array1<T> {
+            indices.iter().map(|&i| array[i]).collect()
+        }
+        PointCloud {
+            points: select(&self.points, indices),
+            normals: self
+                .normals
+                .as_ref()
+                .map(|normals| select(normals, indices)),
+            colors: self.colors.as_ref().map(|colors| select(colors, indices)),
+            intensities: self
+                .intensities
+                .as_ref()
+                .map(|intensities| select(intensities, indices)),
+            curvatures: self
+                .curvatures
+                .as_ref()
+                .map(|curvatures| select(curvatures, indices)),
+            attributes: self
+                .attributes
+                .iter()
+                .map(|(name, channel)| (name.clone(), channel.select(indices)))
+                .collect(),
+        }
+    }
+
+    /// Transforms the points and normals in place, without allocating a new cloud like
+    /// [`Transformable::transform`].
+    ///
+    /// # Arguments
+    ///
+    /// * transform - The rigid transform to apply.
+    ///
+    /// # Returns
+    ///
+    /// The point cloud, for chaining.
+    pub fn transform_mut(&mut self, transform: &Transform) -> &mut Self {
+        for point in self.points.iter_mut() {
+            *point = transform.transform_vector(point);
+        }
+        if let Some(normals) = self.normals.as_mut() {
+            for normal in normals.iter_mut() {
+                *normal = transform.transform_normal(normal);
+            }
+        }
+        self
+    }
+}
+
+impl std::ops::Mul<&PointCloud> for &Transform {
+    type Output = PointCloud;
+    fn mul(self, rhs: &PointCloud) -> PointCloud {
+        PointCloud {
+            points: self.transform_vectors(rhs.points.clone()),
+            normals: rhs
+                .normals
+                .as_ref()
+                .map(|normals| self.transform_normals(normals.clone())),
+            colors: rhs.colors.clone(),
+            intensities: rhs.intensities.clone(),
+            curvatures: rhs.curvatures.clone(),
+            attributes: rhs.attributes.clone(),
+        }
+    }
+}
+
+impl Transformable<PointCloud> for Transform {
+    fn transform(&self, pcl: &PointCloud) -> PointCloud {
+        PointCloud {
+            points: self.transform_vectors(pcl.points.clone()),
+            normals: pcl
+                .normals
+                .as_ref()
+                .map(|normals| self.transform_normals(normals.clone())),
+            colors: pcl.colors.clone(),
+            intensities: pcl.intensities.clone(),
+            curvatures: pcl.curvatures.clone(),
+            attributes: pcl.attributes.clone(),
+        }
+    }
+}
+
+impl From<PointCloud> for Geometry {
+    fn from(pcl: PointCloud) -> Geometry {
+        Geometry {
+            points: pcl.points,
+            normals: pcl.normals,
+            colors: pcl.colors,
+            faces: None,
+            texcoords: None,
+            metadata: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::io::read_off;
+    use super::PointCloud;
+    use rstest::*;
+    #[fixture]
+    fn sample_pcl1() -> PointCloud {
+        PointCloud::from_geometry(read_off("tests/data/teapot.off").unwrap())
+    }
+
+    #[rstest]
+    fn test_point_cloud_from_file(sample_pcl1: PointCloud) {
+        assert_eq!(sample_pcl1.len(), 480);
+    }
+}
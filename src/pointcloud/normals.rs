@@ -0,0 +1,425 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use nalgebra::{Matrix3, Vector3};
+use ndarray::Array1;
+use rayon::prelude::*;
+
+use super::PointCloud;
+use crate::{error::A3dError, kdtree::R3dTree};
+
+/// Neighborhood of a point used to estimate local surface properties.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Neighborhood {
+    /// The `k` nearest points, including the point itself. Adapts to the sampling density.
+    Knn(usize),
+    /// The points within a radius, up to the closest `max_neighbors`. Keeps the same
+    /// support scale across the cloud.
+    Radius { radius: f32, max_neighbors: usize },
+}
+
+impl Neighborhood {
+    fn validate(&self) -> Result<(), A3dError> {
+        match *self {
+            Neighborhood::Knn(k) if k < 3 => Err(A3dError::invalid_parameter(
+                "The neighborhood needs at least 3 points.",
+            )),
+            Neighborhood::Radius {
+                radius,
+                max_neighbors,
+            } if radius <= 0.0 || max_neighbors < 3 => Err(A3dError::invalid_parameter(
+                "The neighborhood radius must be positive and have at least 3 points.",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Indices of the neighbors of a point, including itself.
+    pub(crate) fn search(&self, kdtree: &R3dTree, point: &Vector3<f32>) -> Vec<usize> {
+        match *self {
+            Neighborhood::Knn(k) => kdtree
+                .knn(point, k)
+                .into_iter()
+                .map(|(index, _)| index)
+                .collect(),
+            Neighborhood::Radius {
+                radius,
+                max_neighbors,
+            } => kdtree
+                .knn(point, max_neighbors)
+                .into_iter()
+                .filter(|(_, sqr_distance)| *sqr_distance <= radius * radius)
+                .map(|(index, _)| index)
+                .collect(),
+        }
+    }
+}
+
+/// Covariance of the neighbors of a point, `None` with fewer than 3 neighbors.
+pub(crate) fn neighborhood_covariance(
+    points: &Array1<Vector3<f32>>,
+    neighbors: &[usize],
+) -> Option<Matrix3<f32>> {
+    if neighbors.len() < 3 {
+        return None;
+    }
+    let mean = neighbors
+        .iter()
+        .fold(Vector3::zeros(), |sum, index| sum + points[*index])
+        / neighbors.len() as f32;
+    Some(
+        neighbors.iter().fold(Matrix3::zeros(), |sum, index| {
+            let centered = points[*index] - mean;
+            sum + centered * centered.transpose()
+        }) / neighbors.len() as f32,
+    )
+}
+
+/// Edge of the neighborhood graph, ordered by increasing cost for [`BinaryHeap`].
+struct GraphEdge {
+    cost: f32,
+    parent: usize,
+    child: usize,
+}
+
+impl PartialEq for GraphEdge {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for GraphEdge {}
+
+impl PartialOrd for GraphEdge {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for GraphEdge {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl PointCloud {
+    /// Estimates the normals of an unorganized cloud, e.g., loaded from a PLY file, by
+    /// principal component analysis: the normal of a point is the direction of least
+    /// variance of its neighborhood. The sign of the normals is arbitrary.
+    ///
+    /// # Arguments
+    ///
+    /// * neighborhood - The points around each point that fit its tangent plane.
+    ///
+    /// # Returns
+    ///
+    /// The point cloud with the new normals, zero for points with fewer than 3 neighbors.
+    /// Error if the neighborhood can't fit a plane.
+    pub fn estimate_normals(&mut self, neighborhood: Neighborhood) -> Result<&mut Self, A3dError> {
+        neighborhood.validate()?;
+        let kdtree = R3dTree::new(&self.points.view());
+        let points = &self.points;
+        let normals = (0..points.len())
+            .into_par_iter()
+            .map(|index| {
+                let neighbors = neighborhood.search(&kdtree, &points[index]);
+                neighborhood_covariance(points, &neighbors).map_or_else(
+                    Vector3::zeros,
+                    |covariance| {
+                        let eigen = covariance.symmetric_eigen();
+                        eigen
+                            .eigenvectors
+                            .column(eigen.eigenvalues.imin())
+                            .into_owned()
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        self.normals = Some(normals.into());
+        Ok(self)
+    }
+
+    /// Estimates the surface variation of each point, `λ0 / (λ0 + λ1 + λ2)` for the
+    /// increasing eigenvalues of its neighborhood covariance, as in Pauly et al., Efficient
+    /// Simplification of Point-Sampled Surfaces, 2002. It is 0 on planes and up to 1/3 on
+    /// isotropic neighborhoods, so high values mark the edges and corners that constrain
+    /// ICP, e.g., to sample its points there.
+    ///
+    /// # Arguments
+    ///
+    /// * neighborhood - The points around each point.
+    ///
+    /// # Returns
+    ///
+    /// The point cloud with the new curvatures, zero for points with fewer than 3 neighbors.
+    /// Error if the neighborhood has fewer than 3 points.
+    pub fn estimate_curvatures(
+        &mut self,
+        neighborhood: Neighborhood,
+    ) -> Result<&mut Self, A3dError> {
+        neighborhood.validate()?;
+        let kdtree = R3dTree::new(&self.points.view());
+        let points = &self.points;
+        let curvatures = (0..points.len())
+            .into_par_iter()
+            .map(|index| {
+                let neighbors = neighborhood.search(&kdtree, &points[index]);
+                neighborhood_covariance(points, &neighbors).map_or(0.0, |covariance| {
+                    let eigenvalues = covariance.symmetric_eigenvalues();
+                    let sum = eigenvalues.sum();
+                    if sum > 0.0 {
+                        eigenvalues.min().max(0.0) / sum
+                    } else {
+                        0.0
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        self.curvatures = Some(curvatures.into());
+        Ok(self)
+    }
+
+    /// Flips the normals that point away from a viewpoint, e.g., the sensor origin of a
+    /// scan, so they all face it.
+    ///
+    /// # Arguments
+    ///
+    /// * viewpoint - The point the normals should face, in the cloud frame.
+    ///
+    /// # Returns
+    ///
+    /// The point cloud, or error if it has no normals.
+    pub fn orient_normals_towards(
+        &mut self,
+        viewpoint: &Vector3<f32>,
+    ) -> Result<&mut Self, A3dError> {
+        let normals = self
+            .normals
+            .as_mut()
+            .ok_or_else(|| A3dError::invalid_parameter("The point cloud has no normals."))?;
+        for (normal, point) in normals.iter_mut().zip(self.points.iter()) {
+            if normal.dot(&(viewpoint - point)) < 0.0 {
+                *normal = -*normal;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Orients the normals consistently when the viewpoint is unknown, as in Hoppe et al.,
+    /// Surface Reconstruction from Unorganized Points, 1992. The orientation is propagated
+    /// along the minimum spanning tree of the k nearest neighbor graph, whose edges cost
+    /// `1 - |n_i · n_j|`, so it travels across flat regions before sharp edges. Each
+    /// connected component starts from its highest point, oriented towards +Z.
+    ///
+    /// # Arguments
+    ///
+    /// * k - Number of neighbors of each point in the graph.
+    ///
+    /// # Returns
+    ///
+    /// The point cloud, or error if it has no normals or `k` is zero.
+    pub fn orient_normals_consistently(&mut self, k: usize) -> Result<&mut Self, A3dError> {
+        if k == 0 {
+            return Err(A3dError::invalid_parameter(
+                "The number of neighbors must be positive.",
+            ));
+        }
+        let kdtree = R3dTree::new(&self.points.view());
+        let points = &self.points;
+        let normals = self
+            .normals
+            .as_mut()
+            .ok_or_else(|| A3dError::invalid_parameter("The point cloud has no normals."))?;
+
+        // Symmetric graph, as the nearest neighbor relation isn't.
+        let mut graph = vec![Vec::new(); points.len()];
+        for (index, point) in points.iter().enumerate() {
+            for (neighbor, _) in kdtree.knn(point, k + 1) {
+                if neighbor != index {
+                    graph[index].push(neighbor);
+                    graph[neighbor].push(index);
+                }
+            }
+        }
+
+        let mut seeds = (0..points.len()).collect::<Vec<_>>();
+        seeds.sort_by(|a, b| points[*b][2].total_cmp(&points[*a][2]));
+        let mut visited = vec![false; points.len()];
+        let mut heap = BinaryHeap::new();
+        for seed in seeds {
+            if visited[seed] {
+                continue;
+            }
+            if normals[seed][2] < 0.0 {
+                normals[seed] = -normals[seed];
+            }
+            heap.push(GraphEdge {
+                cost: 0.0,
+                parent: seed,
+                child: seed,
+            });
+            while let Some(GraphEdge { parent, child, .. }) = heap.pop() {
+                if visited[child] {
+                    continue;
+                }
+                visited[child] = true;
+                if normals[child].dot(&normals[parent]) < 0.0 {
+                    normals[child] = -normals[child];
+                }
+                for &neighbor in &graph[child] {
+                    if !visited[neighbor] {
+                        heap.push(GraphEdge {
+                            cost: 1.0 - normals[child].dot(&normals[neighbor]).abs(),
+                            parent: child,
+                            child: neighbor,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Array1;
+    use rstest::rstest;
+
+    use super::Neighborhood;
+    use crate::{pointcloud::PointCloud, unit_test::sample_teapot_surface};
+
+    #[rstest]
+    fn test_estimate_normals(sample_teapot_surface: PointCloud) {
+        let expected = sample_teapot_surface.normals.clone().unwrap();
+        let mut pcl = PointCloud {
+            normals: None,
+            ..sample_teapot_surface
+        };
+
+        for neighborhood in [
+            Neighborhood::Knn(10),
+            Neighborhood::Radius {
+                radius: 0.05,
+                max_neighbors: 30,
+            },
+        ] {
+            let normals = pcl
+                .estimate_normals(neighborhood)
+                .unwrap()
+                .normals
+                .clone()
+                .unwrap();
+            let aligned = normals
+                .iter()
+                .zip(expected.iter())
+                .filter(|(normal, expected)| normal.dot(expected).abs() > 0.9)
+                .count();
+            assert!(aligned > expected.len() * 8 / 10, "{aligned}");
+        }
+
+        assert!(pcl.estimate_normals(Neighborhood::Knn(2)).is_err());
+
+        let mut no_normals = PointCloud {
+            points: pcl.points.clone(),
+            normals: None,
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+        assert!(no_normals
+            .orient_normals_towards(&Vector3::zeros())
+            .is_err());
+        assert!(no_normals.orient_normals_consistently(10).is_err());
+        assert!(pcl
+            .estimate_normals(Neighborhood::Radius {
+                radius: 0.0,
+                max_neighbors: 10
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn test_estimate_curvatures() {
+        // Two planes meeting at a right angle along the Y axis.
+        let points = (0..40)
+            .flat_map(|i| {
+                (0..40).map(move |j| {
+                    let (u, y) = (i as f32 * 0.05 - 1.0, j as f32 * 0.05);
+                    Vector3::new(u.min(0.0), y, u.max(0.0))
+                })
+            })
+            .collect::<Array1<_>>();
+        let mut pcl = PointCloud {
+            points,
+            normals: None,
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+        let curvatures = pcl
+            .estimate_curvatures(Neighborhood::Knn(10))
+            .unwrap()
+            .curvatures
+            .clone()
+            .unwrap();
+        let (crease, flat) = pcl.points.iter().zip(curvatures.iter()).fold(
+            (0.0_f32, 0.0_f32),
+            |(crease, flat), (point, curvature)| {
+                if point.x.abs() + point.z.abs() < 0.01 {
+                    (crease.max(*curvature), flat)
+                } else if point.x.abs() + point.z.abs() > 0.2 {
+                    (crease, flat.max(*curvature))
+                } else {
+                    (crease, flat)
+                }
+            },
+        );
+        assert!(flat < 1e-4, "{flat}");
+        assert!(crease > 0.05, "{crease}");
+        assert!(curvatures
+            .iter()
+            .all(|curvature| *curvature <= 1.0 / 3.0 + 1e-6));
+    }
+
+    #[test]
+    fn test_orient_normals() {
+        // A Fibonacci sphere, whose outward normals are its points.
+        let count = 3000;
+        let golden_angle = std::f32::consts::PI * (3.0 - 5.0_f32.sqrt());
+        let points = (0..count)
+            .map(|i| {
+                let z = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+                let radius = (1.0 - z * z).sqrt();
+                let (sin, cos) = (golden_angle * i as f32).sin_cos();
+                Vector3::new(radius * cos, radius * sin, z)
+            })
+            .collect::<Array1<_>>();
+        let mut pcl = PointCloud {
+            points: points.clone(),
+            normals: None,
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+        pcl.estimate_normals(Neighborhood::Knn(10)).unwrap();
+        let outward = |pcl: &PointCloud| {
+            pcl.normals
+                .as_ref()
+                .unwrap()
+                .iter()
+                .zip(points.iter())
+                .filter(|(normal, point)| normal.dot(point) > 0.0)
+                .count()
+        };
+
+        pcl.orient_normals_towards(&Vector3::zeros()).unwrap();
+        assert_eq!(outward(&pcl), 0);
+
+        pcl.orient_normals_consistently(10).unwrap();
+        assert_eq!(outward(&pcl), points.len());
+    }
+}
@@ -0,0 +1,189 @@
+use rayon::prelude::*;
+
+use super::PointCloud;
+use crate::{error::A3dError, kdtree::R3dTree};
+
+impl PointCloud {
+    /// Creates a point cloud with the points of a mask, e.g., the inliers of a filter.
+    fn select_mask(&self, mask: &[bool]) -> PointCloud {
+        let indices = mask
+            .iter()
+            .enumerate()
+            .filter_map(|(index, inlier)| inlier.then_some(index))
+            .collect::<Vec<_>>();
+        self.select(&indices)
+    }
+
+    /// Removes the points whose mean distance to their `k` nearest neighbors is larger
+    /// than the average over the cloud by more than `std_ratio` standard deviations, e.g.,
+    /// depth noise floating around the surfaces.
+    ///
+    /// # Arguments
+    ///
+    /// * k - Number of neighbors of each point.
+    /// * std_ratio - Number of standard deviations above the average that are kept, lower
+    ///   values remove more points.
+    ///
+    /// # Returns
+    ///
+    /// The inlier point cloud with its attributes, and the inlier mask of the input points
+    /// to filter other per point data consistently. Error if `k` is zero.
+    pub fn remove_statistical_outliers(
+        &self,
+        k: usize,
+        std_ratio: f32,
+    ) -> Result<(PointCloud, Vec<bool>), A3dError> {
+        if k == 0 {
+            return Err(A3dError::invalid_parameter(
+                "The number of neighbors must be positive.",
+            ));
+        }
+        if self.len() < 2 {
+            // No neighbors to compare with.
+            let mask = vec![true; self.len()];
+            return Ok((self.select_mask(&mask), mask));
+        }
+
+        let kdtree = R3dTree::new(&self.points.view());
+        let mean_distances = (0..self.len())
+            .into_par_iter()
+            .map(|index| {
+                let neighbors = kdtree
+                    .knn(&self.points[index], k + 1)
+                    .into_iter()
+                    .filter(|(neighbor, _)| *neighbor != index)
+                    .take(k)
+                    .map(|(_, sqr_distance)| sqr_distance.sqrt())
+                    .collect::<Vec<_>>();
+                neighbors.iter().sum::<f32>() / neighbors.len() as f32
+            })
+            .collect::<Vec<_>>();
+
+        let count = mean_distances.len() as f32;
+        let mean = mean_distances.iter().sum::<f32>() / count;
+        let std = (mean_distances
+            .iter()
+            .map(|distance| (distance - mean).powi(2))
+            .sum::<f32>()
+            / (count - 1.0))
+            .sqrt();
+        let max_distance = mean + std_ratio * std;
+        let mask = mean_distances
+            .iter()
+            .map(|distance| *distance <= max_distance)
+            .collect::<Vec<_>>();
+        Ok((self.select_mask(&mask), mask))
+    }
+
+    /// Removes the points with fewer than `min_neighbors` other points within `radius`.
+    /// It is cheaper than [`PointCloud::remove_statistical_outliers`], but the radius
+    /// depends on the sampling density.
+    ///
+    /// # Arguments
+    ///
+    /// * radius - Radius of the neighborhoods.
+    /// * min_neighbors - Minimum number of neighbors of an inlier, not counting itself.
+    ///
+    /// # Returns
+    ///
+    /// The inlier point cloud with its attributes, and the inlier mask of the input
+    /// points. Error if the radius isn't positive.
+    pub fn remove_radius_outliers(
+        &self,
+        radius: f32,
+        min_neighbors: usize,
+    ) -> Result<(PointCloud, Vec<bool>), A3dError> {
+        if radius <= 0.0 {
+            return Err(A3dError::invalid_parameter("The radius must be positive."));
+        }
+
+        let kdtree = R3dTree::new(&self.points.view());
+        let sqr_radius = radius * radius;
+        let mask = (0..self.len())
+            .into_par_iter()
+            .map(|index| {
+                // Only the farthest of the closest neighbors needs to be within the radius.
+                kdtree
+                    .knn(&self.points[index], min_neighbors + 1)
+                    .into_iter()
+                    .filter(|(neighbor, sqr_distance)| {
+                        *neighbor != index && *sqr_distance <= sqr_radius
+                    })
+                    .count()
+                    >= min_neighbors
+            })
+            .collect::<Vec<_>>();
+        Ok((self.select_mask(&mask), mask))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Array1;
+    use rstest::rstest;
+
+    use crate::{pointcloud::PointCloud, unit_test::sample_teapot_surface};
+
+    /// The teapot surface with a few points floating far from it.
+    fn with_outliers(pcl: &PointCloud) -> (PointCloud, usize) {
+        let outliers = [
+            Vector3::new(3.0, 0.0, 0.0),
+            Vector3::new(0.0, -3.0, 1.0),
+            Vector3::new(2.0, 2.0, 2.0),
+        ];
+        let points = pcl
+            .points
+            .iter()
+            .chain(outliers.iter())
+            .cloned()
+            .collect::<Array1<_>>();
+        (
+            PointCloud {
+                intensities: Some(Array1::from_iter((0..points.len()).map(|i| i as f32))),
+                curvatures: None,
+                attributes: Default::default(),
+                points,
+                normals: None,
+                colors: None,
+            },
+            pcl.len(),
+        )
+    }
+
+    #[rstest]
+    fn test_remove_statistical_outliers(sample_teapot_surface: PointCloud) {
+        let (pcl, num_inliers) = with_outliers(&sample_teapot_surface);
+        let (filtered, mask) = pcl.remove_statistical_outliers(10, 2.0).unwrap();
+        assert_eq!(mask.len(), pcl.len());
+        assert!(mask[num_inliers..].iter().all(|inlier| !inlier));
+        assert!(filtered.len() > num_inliers * 95 / 100);
+        assert_eq!(
+            filtered.len(),
+            mask.iter().filter(|inlier| **inlier).count()
+        );
+        assert!(filtered
+            .intensities
+            .unwrap()
+            .iter()
+            .all(|index| mask[*index as usize]));
+
+        assert!(pcl.remove_statistical_outliers(0, 2.0).is_err());
+    }
+
+    #[rstest]
+    fn test_remove_radius_outliers(sample_teapot_surface: PointCloud) {
+        let (pcl, num_inliers) = with_outliers(&sample_teapot_surface);
+        let (filtered, mask) = pcl.remove_radius_outliers(0.1, 5).unwrap();
+        assert!(mask[num_inliers..].iter().all(|inlier| !inlier));
+        assert!(filtered.len() > num_inliers * 95 / 100);
+        assert_eq!(
+            filtered.len(),
+            mask.iter().filter(|inlier| **inlier).count()
+        );
+
+        let (_, mask) = pcl.remove_radius_outliers(1e-6, 1).unwrap();
+        assert!(mask.iter().all(|inlier| !inlier));
+        assert!(pcl.remove_radius_outliers(0.0, 5).is_err());
+    }
+}
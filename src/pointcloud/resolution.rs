@@ -0,0 +1,124 @@
+use ndarray::Array1;
+use rayon::prelude::*;
+
+use super::PointCloud;
+use crate::{error::A3dError, kdtree::R3dTree};
+
+/// Sampling statistics of a point cloud, see [`PointCloud::estimate_resolution`].
+#[derive(Debug, Clone)]
+pub struct ResolutionStats {
+    /// Median distance between the points and their nearest neighbor. It is robust to
+    /// outliers and duplicated points, and a good unit for the dataset dependent
+    /// parameters, e.g., 2 to 3 spacings for a voxel size or an ICP correspondence
+    /// distance.
+    pub median_spacing: f32,
+    /// Mean distance between the points and their nearest neighbor.
+    pub mean_spacing: f32,
+    /// Standard deviation of the distance between the points and their nearest neighbor.
+    pub std_spacing: f32,
+    /// Number of points per cubic unit around each point, from the ball of its `k`
+    /// nearest neighbors. Infinite if all the neighbors are at the same position.
+    pub densities: Array1<f32>,
+    /// Median of the densities.
+    pub median_density: f32,
+}
+
+/// The median of values, averaging the two middle ones for an even count.
+fn median(mut values: Vec<f32>) -> f32 {
+    values.sort_by(f32::total_cmp);
+    let len = values.len();
+    (values[(len - 1) / 2] + values[len / 2]) * 0.5
+}
+
+impl PointCloud {
+    /// Estimates the sampling resolution and the local density of the cloud, to choose
+    /// voxel sizes and distance thresholds automatically instead of tuning them for each
+    /// dataset.
+    ///
+    /// # Arguments
+    ///
+    /// * k - Number of neighbors used for the density of each point.
+    ///
+    /// # Returns
+    ///
+    /// The statistics, or error if `k` is zero or the cloud has fewer than 2 points.
+    pub fn estimate_resolution(&self, k: usize) -> Result<ResolutionStats, A3dError> {
+        if k == 0 {
+            return Err(A3dError::invalid_parameter(
+                "The number of neighbors must be positive.",
+            ));
+        }
+        if self.len() < 2 {
+            return Err(A3dError::invalid_parameter(
+                "The resolution requires at least 2 points.",
+            ));
+        }
+
+        let kdtree = R3dTree::new(&self.points.view());
+        let (spacings, densities): (Vec<_>, Vec<_>) = (0..self.len())
+            .into_par_iter()
+            .map(|index| {
+                let distances = kdtree
+                    .knn(&self.points[index], k + 1)
+                    .into_iter()
+                    .filter(|(neighbor, _)| *neighbor != index)
+                    .take(k)
+                    .map(|(_, sqr_distance)| sqr_distance.sqrt())
+                    .collect::<Vec<_>>();
+                let radius = distances[distances.len() - 1];
+                let volume = 4.0 / 3.0 * std::f32::consts::PI * radius.powi(3);
+                (distances[0], distances.len() as f32 / volume)
+            })
+            .unzip();
+
+        let count = spacings.len() as f32;
+        let mean_spacing = spacings.iter().sum::<f32>() / count;
+        let std_spacing = (spacings
+            .iter()
+            .map(|spacing| (spacing - mean_spacing).powi(2))
+            .sum::<f32>()
+            / count)
+            .sqrt();
+        Ok(ResolutionStats {
+            median_spacing: median(spacings),
+            mean_spacing,
+            std_spacing,
+            median_density: median(densities.clone()),
+            densities: Array1::from_vec(densities),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use crate::pointcloud::PointCloudBuilder;
+
+    #[test]
+    fn test_estimate_resolution() {
+        // A 20x20x20 grid with a spacing of 0.1, that is 1000 points per cubic unit, and
+        // an outlier.
+        let pcl = PointCloudBuilder::new()
+            .with_points((0..8000).map(|i| {
+                Vector3::new((i % 20) as f32, (i / 20 % 20) as f32, (i / 400) as f32) * 0.1
+            }))
+            .with_points([Vector3::new(10.0, 10.0, 10.0)])
+            .build()
+            .unwrap();
+        let stats = pcl.estimate_resolution(26).unwrap();
+        assert!((stats.median_spacing - 0.1).abs() < 1e-5);
+        assert!(stats.mean_spacing > stats.median_spacing);
+        assert!(stats.std_spacing > 0.0);
+        assert_eq!(stats.densities.len(), pcl.len());
+        assert!(
+            (500.0..2000.0).contains(&stats.median_density),
+            "{}",
+            stats.median_density
+        );
+        assert!(stats.densities[8000] < 1.0);
+
+        assert!(pcl.estimate_resolution(0).is_err());
+        assert!(pcl.select(&[0]).estimate_resolution(4).is_err());
+    }
+}
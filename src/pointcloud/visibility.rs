@@ -0,0 +1,110 @@
+use nalgebra::Vector3;
+
+use super::PointCloud;
+use crate::{convex_hull::convex_hull, error::A3dError};
+
+impl PointCloud {
+    /// Finds the points visible from a viewpoint with the hidden point removal operator of
+    /// Katz et al., "Direct Visibility of Point Sets". The points are flipped about a
+    /// sphere centered on the viewpoint, and the visible ones are those on the convex hull
+    /// of the flipped points and the viewpoint. Useful to render a cloud or to simulate
+    /// what a sensor sees.
+    ///
+    /// # Arguments
+    ///
+    /// * viewpoint - The viewpoint, in the cloud frame.
+    /// * radius - Radius of the flipping sphere. Larger radii keep more points, e.g., a
+    ///   hundred times the cloud diameter.
+    ///
+    /// # Returns
+    ///
+    /// The visible points and the index in this cloud of each of them. Error if the radius
+    /// isn't larger than the distance between the viewpoint and every point.
+    pub fn hidden_point_removal(
+        &self,
+        viewpoint: &Vector3<f32>,
+        radius: f32,
+    ) -> Result<(PointCloud, Vec<usize>), A3dError> {
+        let viewpoint = viewpoint.cast::<f64>();
+        let relative = self
+            .points
+            .iter()
+            .map(|point| point.cast::<f64>() - viewpoint)
+            .collect::<Vec<_>>();
+        let radius = radius as f64;
+        if relative.iter().any(|point| point.norm() >= radius) {
+            return Err(A3dError::invalid_parameter(
+                "The flipping radius must be larger than the distance to the points.",
+            ));
+        }
+
+        // The viewpoint is the last point.
+        let flipped = relative
+            .iter()
+            .map(|point| {
+                let norm = point.norm();
+                if norm > 0.0 {
+                    point * ((2.0 * radius - norm) / norm)
+                } else {
+                    *point
+                }
+            })
+            .chain([Vector3::zeros()])
+            .collect::<Vec<_>>();
+
+        let mut visible = vec![false; self.len()];
+        for vertex in convex_hull(&flipped).into_iter().flatten().flatten() {
+            if vertex < self.len() && relative[vertex].norm() > 0.0 {
+                visible[vertex] = true;
+            }
+        }
+        let indices = (0..self.len())
+            .filter(|index| visible[*index])
+            .collect::<Vec<_>>();
+        Ok((self.select(&indices), indices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use crate::pointcloud::PointCloud;
+
+    #[test]
+    fn test_hidden_point_removal() {
+        // A Fibonacci sphere of radius 1.
+        let count = 2000;
+        let golden_angle = std::f32::consts::PI * (3.0 - 5f32.sqrt());
+        let sphere = PointCloud {
+            points: (0..count)
+                .map(|i| {
+                    let z = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+                    let radius = (1.0 - z * z).sqrt();
+                    let (sin, cos) = (i as f32 * golden_angle).sin_cos();
+                    Vector3::new(radius * cos, radius * sin, z)
+                })
+                .collect(),
+            normals: None,
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+
+        // From a distance of 3, the visible cap is x > 1/3, the operator keeps a few points
+        // past the horizon.
+        let viewpoint = Vector3::new(3.0, 0.0, 0.0);
+        let (visible, indices) = sphere.hidden_point_removal(&viewpoint, 400.0).unwrap();
+        assert_eq!(visible.len(), indices.len());
+        assert!(visible.points.iter().all(|point| point.x > 0.25));
+        let cap = sphere.points.iter().filter(|point| point.x > 0.4).count();
+        let visible_cap = visible.points.iter().filter(|point| point.x > 0.4).count();
+        assert!(
+            visible_cap as f32 > 0.95 * cap as f32,
+            "{visible_cap} {cap}"
+        );
+
+        assert!(sphere.hidden_point_removal(&viewpoint, 3.0).is_err());
+    }
+}
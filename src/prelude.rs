@@ -0,0 +1,16 @@
+//! The types needed by most programs, `use align3d::prelude::*;` imports them.
+
+pub use crate::{
+    camera::CameraIntrinsics,
+    error::A3dError,
+    icp::{Icp, IcpParams, IcpResult, MsIcpParams},
+    io::{
+        dataset::{DatasetRegistry, RgbdDataset},
+        read_ply, write_ply, Geometry,
+    },
+    pointcloud::PointCloud,
+    range_image::{RangeImage, RangeImageBuilder},
+    reconstruction::{FusionParams, Reconstruction},
+    trajectory::Trajectory,
+    transform::{Transform, Transformable},
+};
@@ -0,0 +1,79 @@
+//! Reproducible random number generation.
+
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+/// Seed of the random number generators of the stochastic routines, e.g., the RANSAC
+/// registration or the synthetic noise. Routines take it in their parameters, so their
+/// results are the same across runs for the same state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RandomState {
+    seed: u64,
+}
+
+impl Default for RandomState {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl RandomState {
+    /// Creates a state from a seed.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Seed of the random number generators.
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Creates a state from a seed drawn from the operating system. Log its
+    /// [`RandomState::seed`] to reproduce a run.
+    pub fn from_entropy() -> Self {
+        Self::new(StdRng::from_entropy().next_u64())
+    }
+
+    /// The seed of the state.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// A generator starting at the state.
+    pub fn rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.seed)
+    }
+
+    /// A generator of a substream, e.g., one per frame, which depends only on the seed and
+    /// the substream index, so items can be processed in any order.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the substream.
+    pub fn stream(&self, index: u64) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ index.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::RandomState;
+
+    #[test]
+    fn test_random_state() {
+        let state = RandomState::new(11);
+        assert_eq!(state.rng().gen::<u64>(), state.rng().gen::<u64>());
+        assert_eq!(state.stream(3).gen::<u64>(), state.stream(3).gen::<u64>());
+        assert_ne!(state.stream(3).gen::<u64>(), state.stream(4).gen::<u64>());
+        assert_ne!(
+            state.rng().gen::<u64>(),
+            RandomState::new(12).rng().gen::<u64>()
+        );
+
+        let entropy = RandomState::from_entropy();
+        assert_eq!(
+            RandomState::new(entropy.seed()).rng().gen::<u64>(),
+            entropy.rng().gen::<u64>()
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{bilateral::BilateralFilter, image::RgbdFrame};
+use crate::{bilateral::BilateralFilter, image::RgbdFrame, io::dataset::DepthNoiseParams};
 
 use super::RangeImage;
 
@@ -6,11 +6,12 @@ use super::RangeImage;
 /// Builder for multiple range images from RGB-D data.
 pub struct RangeImageBuilder {
     with_normals: bool,
-    with_intensity: bool,
+    pub(crate) with_intensity: bool,
     bilateral_filter: Option<BilateralFilter<u16>>,
     // bilateral_data: Array2Recycle<u16>,
-    pyramid_levels: usize,
+    pub(super) pyramid_levels: usize,
     blur_sigma: f32,
+    sensor_noise: Option<DepthNoiseParams>,
 }
 
 impl Default for RangeImageBuilder {
@@ -22,6 +23,7 @@ impl Default for RangeImageBuilder {
             bilateral_filter: None,
             pyramid_levels: 3,
             blur_sigma: 1.0,
+            sensor_noise: None,
         }
     }
 }
@@ -62,6 +64,14 @@ impl RangeImageBuilder {
         self
     }
 
+    /// Computes the point covariances from a depth sensor noise model, used to weight
+    /// the geometric residuals of ICP.
+    /// See [`RangeImage::compute_covariances`].
+    pub fn with_covariances(mut self, noise: Option<DepthNoiseParams>) -> Self {
+        self.sensor_noise = noise;
+        self
+    }
+
     /// Builds the range images from the given RGB-D frame.
     ///
     /// # Arguments
@@ -79,6 +89,23 @@ impl RangeImageBuilder {
         if self.with_normals {
             first_image.compute_normals();
         }
+        if let Some(noise) = &self.sensor_noise {
+            first_image.compute_covariances(noise);
+        }
+        self.build_pyramid(first_image)
+    }
+
+    /// Builds the pyramid and the intensities of an existing range image, e.g., one
+    /// rendered from a model, keeping its normals.
+    ///
+    /// # Arguments
+    ///
+    /// * `first_image` - The finest level.
+    ///
+    /// # Returns
+    ///
+    /// A vector of range images, the length of the vector depends on the number of pyramid levels.
+    pub fn build_pyramid(&self, first_image: RangeImage) -> Vec<RangeImage> {
         let mut range_images = first_image.pyramid(self.pyramid_levels, self.blur_sigma);
         for range_image in range_images.iter_mut() {
             if self.with_intensity {
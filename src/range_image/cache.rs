@@ -0,0 +1,273 @@
+use std::path::{Path, PathBuf};
+
+use nalgebra::{Matrix3, Vector3};
+use ndarray::Array2;
+
+use super::{RangeImage, RangeImageBuilder};
+use crate::{
+    camera::CameraIntrinsics,
+    io::{
+        dataset::{DatasetError, RgbdDataset},
+        npy::{read_npz, write_npz},
+    },
+};
+
+/// FNV-1a hash, stable across runs and compiler versions, unlike the std hasher.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn vectors_to_array2<T: Copy + Into<f32> + nalgebra::Scalar>(
+    values: &Array2<Vector3<T>>,
+) -> Array2<f32> {
+    let data = values
+        .iter()
+        .flat_map(|value| [value[0].into(), value[1].into(), value[2].into()])
+        .collect();
+    Array2::from_shape_vec((values.len(), 3), data).unwrap()
+}
+
+fn matrices_to_array2(values: &Array2<Matrix3<f32>>) -> Array2<f32> {
+    let data = values
+        .iter()
+        .flat_map(|value| value.transpose().iter().copied().collect::<Vec<_>>())
+        .collect();
+    Array2::from_shape_vec((values.len(), 9), data).unwrap()
+}
+
+fn scalars_to_array2<T: Copy + Into<f32>>(values: &Array2<T>) -> Array2<f32> {
+    let data = values.iter().map(|value| (*value).into()).collect();
+    Array2::from_shape_vec((values.len(), 1), data).unwrap()
+}
+
+/// Opt-in disk cache of preprocessed range image pyramids. The expensive per-frame work
+/// of a [`RangeImageBuilder`] (bilateral filtering, normals, pyramids) is stored under a
+/// directory keyed by the dataset and the builder parameters, so repeated runs of an
+/// experiment load it instead of recomputing it. Intensity maps are recomputed on load.
+pub struct RangeImageCache {
+    builder: RangeImageBuilder,
+    directory: PathBuf,
+}
+
+impl RangeImageCache {
+    /// Creates the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `builder` - The preprocessing parameters.
+    /// * `cache_root` - Directory where caches are stored, shared by datasets and parameters.
+    /// * `dataset_key` - Identifies the dataset, e.g., its path.
+    pub fn new<P: AsRef<Path>>(
+        builder: RangeImageBuilder,
+        cache_root: P,
+        dataset_key: &str,
+    ) -> Result<Self, DatasetError> {
+        let key = format!("{dataset_key}\n{builder:?}");
+        let directory = cache_root
+            .as_ref()
+            .join(format!("{:016x}", fnv1a(key.as_bytes())));
+        std::fs::create_dir_all(&directory)?;
+        std::fs::write(directory.join("key.txt"), &key)?;
+        Ok(Self { builder, directory })
+    }
+
+    /// The directory with the cached frames.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn frame_path(&self, index: usize) -> PathBuf {
+        self.directory.join(format!("frame_{index:06}.npz"))
+    }
+
+    /// Returns the range image pyramid of a dataset frame, loading it from the cache when
+    /// available. Otherwise the frame is preprocessed and stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `dataset` - The dataset, it must be the one identified by the cache's key.
+    /// * `index` - The frame index.
+    ///
+    /// # Returns
+    ///
+    /// The pyramid, as returned by [`RangeImageBuilder::build`].
+    pub fn get(
+        &self,
+        dataset: &dyn RgbdDataset,
+        index: usize,
+    ) -> Result<Vec<RangeImage>, DatasetError> {
+        let path = self.frame_path(index);
+        if path.exists() {
+            // Unreadable entries, e.g., from an interrupted run, are rebuilt.
+            if let Some(pyramid) = self.load(&path, &dataset.camera(index).0) {
+                return Ok(pyramid);
+            }
+        }
+
+        let pyramid = self.builder.build(dataset.get(index)?);
+        self.store(&path, &pyramid)?;
+        Ok(pyramid)
+    }
+
+    fn store(&self, path: &Path, pyramid: &[RangeImage]) -> Result<(), DatasetError> {
+        let tmp_path = path.with_extension("npz.tmp");
+        write_pyramid(&tmp_path, pyramid)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    fn load(&self, path: &Path, camera: &CameraIntrinsics) -> Option<Vec<RangeImage>> {
+        read_pyramid(path, camera, self.builder.with_intensity)
+            .filter(|pyramid| pyramid.len() == self.builder.pyramid_levels)
+    }
+}
+
+/// Writes a range image pyramid into a `.npz` archive. Intensities are not stored.
+pub(crate) fn write_pyramid(path: &Path, pyramid: &[RangeImage]) -> Result<(), std::io::Error> {
+    let mut arrays = vec![(
+        "shape".to_string(),
+        Array2::from_shape_fn((pyramid.len(), 2), |(level, dim)| {
+            let (height, width) = pyramid[level].points.dim();
+            [height, width][dim] as f32
+        }),
+    )];
+    for (level, image) in pyramid.iter().enumerate() {
+        arrays.push((format!("{level}_points"), vectors_to_array2(&image.points)));
+        arrays.push((format!("{level}_mask"), scalars_to_array2(&image.mask)));
+        if let Some(normals) = &image.normals {
+            arrays.push((format!("{level}_normals"), vectors_to_array2(normals)));
+        }
+        if let Some(colors) = &image.colors {
+            arrays.push((format!("{level}_colors"), vectors_to_array2(colors)));
+        }
+        if let Some(confidences) = &image.confidences {
+            arrays.push((
+                format!("{level}_confidences"),
+                scalars_to_array2(confidences),
+            ));
+        }
+        if let Some(covariances) = &image.covariances {
+            arrays.push((
+                format!("{level}_covariances"),
+                matrices_to_array2(covariances),
+            ));
+        }
+    }
+
+    write_npz(
+        path,
+        &arrays
+            .iter()
+            .map(|(name, array)| (name.as_str(), array.view()))
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Reads a pyramid written by [`write_pyramid`].
+///
+/// # Arguments
+///
+/// * `path` - Path to the archive.
+/// * `camera` - The intrinsics of the finest level, the next levels halve them.
+/// * `with_intensity` - Whether to compute the intensities and intensity maps.
+///
+/// # Returns
+///
+/// The pyramid, or `None` if the archive is unreadable or has missing arrays.
+pub(crate) fn read_pyramid(
+    path: &Path,
+    camera: &CameraIntrinsics,
+    with_intensity: bool,
+) -> Option<Vec<RangeImage>> {
+    let arrays = read_npz(path).ok()?;
+    let shape = arrays.get("shape")?;
+
+    let mut intrinsics = camera.clone();
+    let mut pyramid = Vec::new();
+    for (level, dims) in shape.rows().into_iter().enumerate() {
+        let (height, width) = (dims[0] as usize, dims[1] as usize);
+        let get = |name: &str, columns: usize| {
+            arrays
+                .get(&format!("{level}_{name}"))
+                .filter(|array| array.dim() == (height * width, columns))
+        };
+        let vectors = |array: &Array2<f32>| {
+            Array2::from_shape_fn((height, width), |(row, col)| {
+                let i = row * width + col;
+                Vector3::new(array[(i, 0)], array[(i, 1)], array[(i, 2)])
+            })
+        };
+        let matrices = |array: &Array2<f32>| {
+            Array2::from_shape_fn((height, width), |(row, col)| {
+                let i = row * width + col;
+                Matrix3::from_fn(|r, c| array[(i, r * 3 + c)])
+            })
+        };
+        let scalars = |array: &Array2<f32>| {
+            Array2::from_shape_fn((height, width), |(row, col)| array[(row * width + col, 0)])
+        };
+
+        let mut image = RangeImage::from_parts(
+            intrinsics.clone(),
+            vectors(get("points", 3)?),
+            scalars(get("mask", 1)?).mapv(|value| value as u8),
+            get("normals", 3).map(vectors),
+            get("colors", 3).map(|colors| vectors(colors).map(|color| color.map(|c| c as u8))),
+            get("confidences", 1).map(scalars),
+            get("covariances", 9).map(matrices),
+        );
+        if with_intensity {
+            image.compute_intensity();
+            image.compute_intensity_map();
+        }
+        pyramid.push(image);
+        intrinsics = intrinsics.scale(0.5);
+    }
+
+    Some(pyramid)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::RangeImageCache;
+    use crate::{
+        io::dataset::{DepthNoiseParams, RgbdDataset},
+        range_image::RangeImageBuilder,
+        unit_test::sample_rgbd_dataset1,
+    };
+
+    #[rstest]
+    fn test_cache(sample_rgbd_dataset1: impl RgbdDataset) {
+        let cache_root = tempfile::tempdir().unwrap();
+        let builder = RangeImageBuilder::default()
+            .with_covariances(Some(DepthNoiseParams::default()))
+            .pyramid_levels(2);
+        let cache = RangeImageCache::new(builder.clone(), cache_root.path(), "sample1").unwrap();
+
+        let built = cache.get(&sample_rgbd_dataset1, 0).unwrap();
+        assert!(cache.directory().join("frame_000000.npz").exists());
+        let cached = cache.get(&sample_rgbd_dataset1, 0).unwrap();
+
+        assert_eq!(cached.len(), built.len());
+        for (cached, built) in cached.iter().zip(built.iter()) {
+            assert_eq!(cached.points, built.points);
+            assert_eq!(cached.mask, built.mask);
+            assert_eq!(cached.normals, built.normals);
+            assert_eq!(cached.colors, built.colors);
+            assert_eq!(cached.intensities, built.intensities);
+            assert!(built.covariances.is_some());
+            assert_eq!(cached.covariances, built.covariances);
+            assert_eq!(cached.valid_points_count(), built.valid_points_count());
+            assert_eq!(cached.intrinsics.fx, built.intrinsics.fx);
+            assert!(cached.intensity_map.is_some());
+        }
+
+        let other =
+            RangeImageCache::new(builder.pyramid_levels(3), cache_root.path(), "sample1").unwrap();
+        assert_ne!(other.directory(), cache.directory());
+    }
+}
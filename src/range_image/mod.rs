@@ -5,3 +5,7 @@ pub use structure::RangeImage;
 
 mod builder;
 pub use builder::RangeImageBuilder;
+
+mod cache;
+pub use cache::RangeImageCache;
+pub(crate) use cache::{read_pyramid, write_pyramid};
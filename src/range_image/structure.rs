@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use crate::camera::CameraIntrinsics;
 
 use crate::image::{rgb_to_luma_u8, RgbdFrame, RgbdImage, ToImageRgb8};
@@ -5,13 +7,13 @@ use crate::intensity_map::IntensityMap;
 
 use image::imageops::blur;
 use image::{ImageBuffer, Rgb};
-use nalgebra::Vector3;
+use nalgebra::{Matrix3, Vector3};
 
 use ndarray::{Array1, Array2, Axis};
 use rayon::prelude::{ParallelBridge, ParallelIterator};
 
-use crate::io::Geometry;
-use crate::pointcloud::PointCloud;
+use crate::io::{dataset::DepthNoiseParams, Geometry};
+use crate::pointcloud::{AttributeChannel, PointCloud};
 
 use super::resize::{resize_range_normals, resize_range_points};
 
@@ -32,6 +34,10 @@ pub struct RangeImage {
     pub intensities: Option<Array1<u8>>,
     /// Intensity map of the points, as array with shape: (height, width)
     pub intensity_map: Option<IntensityMap>,
+    /// Confidence of the points in [0, 1], as array with shape: (height, width)
+    pub confidences: Option<Array2<f32>>,
+    /// Covariance of the point noise in squared meters, as array with shape: (height, width)
+    pub covariances: Option<Array2<Matrix3<f32>>>,
     valid_points: usize,
 }
 
@@ -90,6 +96,8 @@ impl RangeImage {
             intrinsics: camera.clone(),
             intensities: None,
             intensity_map: None,
+            confidences: rgbd_image.confidence.clone(),
+            covariances: None,
             valid_points,
         }
     }
@@ -123,6 +131,8 @@ impl RangeImage {
             valid_points,
             intensities: None,
             intensity_map: None,
+            confidences: None,
+            covariances: None,
             normals: Some(Array2::from_shape_fn(
                 (camera.height, camera.width),
                 |(i, j)| normal_fn(i, j).unwrap_or(Vector3::zeros()),
@@ -134,6 +144,31 @@ impl RangeImage {
         }
     }
 
+    /// Assembles a range image from its arrays, e.g., when loading it from a cache.
+    pub(crate) fn from_parts(
+        intrinsics: CameraIntrinsics,
+        points: Array2<Vector3<f32>>,
+        mask: Array2<u8>,
+        normals: Option<Array2<Vector3<f32>>>,
+        colors: Option<Array2<Vector3<u8>>>,
+        confidences: Option<Array2<f32>>,
+        covariances: Option<Array2<Matrix3<f32>>>,
+    ) -> Self {
+        let valid_points = mask.iter().map(|x| (*x == 1) as usize).sum();
+        Self {
+            points,
+            mask,
+            normals,
+            colors,
+            intrinsics,
+            intensities: None,
+            intensity_map: None,
+            confidences,
+            covariances,
+            valid_points,
+        }
+    }
+
     /// Width of the image.
     pub fn width(&self) -> usize {
         self.points.shape()[1]
@@ -180,6 +215,28 @@ impl RangeImage {
         }
     }
 
+    /// Pixel that a point in the camera frame projects to, for projective data association.
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - 3D point in the camera frame of the image.
+    ///
+    /// # Returns
+    ///
+    /// The row and column of the nearest pixel, or `None` if the point is behind the
+    /// camera or projects outside the image.
+    pub fn project_to_pixel(&self, point: &nalgebra::Vector3<f32>) -> Option<(usize, usize)> {
+        if point[2] <= 0.0 {
+            return None;
+        }
+        let (u, v) = self.intrinsics.project(point);
+        if u + 0.5 < 0.0 || v + 0.5 < 0.0 {
+            return None;
+        }
+        let (row, col) = ((v + 0.5) as usize, (u + 0.5) as usize);
+        (row < self.height() && col < self.width()).then_some((row, col))
+    }
+
     /// Updates the image with normals computed from the 3D points.
     pub fn compute_normals(&mut self) -> &mut Self {
         let height = self.height();
@@ -261,6 +318,34 @@ impl RangeImage {
         self
     }
 
+    /// Updates the image with the covariances of the depth sensor noise, the axial noise
+    /// along the camera ray and the lateral noise across it, see [`DepthNoiseParams`].
+    ///
+    /// # Arguments
+    ///
+    /// * `noise` - The noise model, the quantization and dropout are not used.
+    pub fn compute_covariances(&mut self, noise: &DepthNoiseParams) -> &mut Self {
+        let fx = self.intrinsics.fx as f32;
+        self.covariances = Some(Array2::from_shape_fn(
+            (self.height(), self.width()),
+            |(row, col)| {
+                let point = self.points[(row, col)];
+                if self.mask[(row, col)] == 0 || point[2] <= 0.0 {
+                    return Matrix3::zeros();
+                }
+                let z = point[2];
+                let axial_sigma = noise.axial_scale * (0.0012 + 0.0019 * (z - 0.4).powi(2));
+                let lateral_sigma = noise.lateral_sigma * z / fx;
+                let (axial_var, lateral_var) =
+                    (axial_sigma * axial_sigma, lateral_sigma * lateral_sigma);
+                let ray = point.normalize();
+                Matrix3::identity() * lateral_var
+                    + ray * ray.transpose() * (axial_var - lateral_var)
+            },
+        ));
+        self
+    }
+
     /// By default, range image have only the RGB colors, this method
     /// will convert them into luma values, which are used as color optimization term in ICP.
     pub fn compute_intensity(&mut self) -> &mut Self {
@@ -326,6 +411,44 @@ impl RangeImage {
             None
         };
 
+        // Mean confidence of the valid points in each 2x2 block.
+        let confidences = self.confidences.as_ref().map(|confidences| {
+            Array2::from_shape_fn((height, width), |(row, col)| {
+                let (sum, count) = (0..4).fold((0.0, 0), |(sum, count), k| {
+                    let (src_row, src_col) = (row * 2 + k / 2, col * 2 + k % 2);
+                    if self.mask[(src_row, src_col)] == 1 {
+                        (sum + confidences[(src_row, src_col)], count + 1)
+                    } else {
+                        (sum, count)
+                    }
+                });
+                if count > 0 {
+                    sum / count as f32
+                } else {
+                    0.0
+                }
+            })
+        });
+
+        // Mean covariance of the valid points in each 2x2 block.
+        let covariances = self.covariances.as_ref().map(|covariances| {
+            Array2::from_shape_fn((height, width), |(row, col)| {
+                let (sum, count) = (0..4).fold((Matrix3::zeros(), 0), |(sum, count), k| {
+                    let (src_row, src_col) = (row * 2 + k / 2, col * 2 + k % 2);
+                    if self.mask[(src_row, src_col)] == 1 {
+                        (sum + covariances[(src_row, src_col)], count + 1)
+                    } else {
+                        (sum, count)
+                    }
+                });
+                if count > 0 {
+                    sum / count as f32
+                } else {
+                    Matrix3::zeros()
+                }
+            })
+        });
+
         let valid_points = mask.iter().map(|x| (*x == 1) as usize).sum();
         RangeImage {
             points,
@@ -335,6 +458,8 @@ impl RangeImage {
             intrinsics: self.intrinsics.scale(0.5),
             intensities: None,
             intensity_map: None,
+            confidences,
+            covariances,
             valid_points,
         }
     }
@@ -372,6 +497,8 @@ impl RangeImage {
     }
 }
 
+/// The valid points of the image, with their confidences, if any, as the `confidence`
+/// attribute.
 impl From<&RangeImage> for PointCloud {
     fn from(image_pcl: &RangeImage) -> PointCloud {
         let points = image_pcl
@@ -397,10 +524,31 @@ impl From<&RangeImage> for PointCloud {
                 .collect()
         });
 
+        let intensities = image_pcl.intensities.as_ref().map(|intensities| {
+            intensities
+                .iter()
+                .zip(image_pcl.mask.iter())
+                .filter_map(|(intensity, mask)| (*mask != 0).then_some(*intensity as f32 / 255.0))
+                .collect()
+        });
+
+        let mut attributes = BTreeMap::new();
+        if let Some(confidences) = &image_pcl.confidences {
+            let confidences = confidences
+                .iter()
+                .zip(image_pcl.mask.iter())
+                .filter_map(|(confidence, mask)| (*mask != 0).then_some(*confidence))
+                .collect();
+            attributes.insert("confidence".to_string(), AttributeChannel::F32(confidences));
+        }
+
         PointCloud {
             points,
             normals,
             colors,
+            intensities,
+            curvatures: None,
+            attributes,
         }
     }
 }
@@ -510,4 +658,53 @@ mod tests {
         }
         assert_eq!(pyramid.len(), 3);
     }
+
+    #[rstest]
+    fn should_propagate_confidences(sample1: SlamTbDataset) {
+        let mut frame = sample1.get(0).unwrap();
+        let (height, width) = frame.image.depth.dim();
+        frame.image.confidence = Some(Array2::from_elem((height, width), 0.5));
+
+        let pyramid = RangeImage::from_rgbd_frame(&frame).pyramid(3, 1.0);
+        for im in pyramid.iter() {
+            let confidences = im.confidences.as_ref().unwrap();
+            assert_eq!(confidences.dim(), im.mask.dim());
+            assert!(ndarray::Zip::from(confidences)
+                .and(&im.mask)
+                .all(|confidence, mask| *mask == 0 || *confidence == 0.5));
+        }
+
+        let pcl = PointCloud::from(&pyramid[0]);
+        let confidences = pcl.attribute::<f32>("confidence").unwrap();
+        assert_eq!(confidences.len(), pcl.len());
+        assert!(confidences.iter().all(|confidence| *confidence == 0.5));
+    }
+
+    #[rstest]
+    fn test_compute_covariances(sample1: SlamTbDataset) {
+        let mut image = RangeImage::from_rgbd_frame(&sample1.get(0).unwrap());
+        let noise = DepthNoiseParams::default();
+        image.compute_covariances(&noise);
+
+        let (row, col) = image
+            .mask
+            .indexed_iter()
+            .find(|(_, mask)| **mask == 1)
+            .unwrap()
+            .0;
+        let point = image.points[(row, col)];
+        let covariance = image.covariances.as_ref().unwrap()[(row, col)];
+        let ray = point.normalize();
+        let axial_sigma = 0.0012 + 0.0019 * (point[2] - 0.4).powi(2);
+        let axial_var = (ray.transpose() * covariance * ray)[0];
+        assert!((axial_var - axial_sigma * axial_sigma).abs() < 1e-8);
+
+        let lateral_sigma = noise.lateral_sigma * point[2] / image.intrinsics.fx as f32;
+        let lateral = ray.cross(&Vector3::x()).normalize();
+        let lateral_var = (lateral.transpose() * covariance * lateral)[0];
+        assert!((lateral_var - lateral_sigma * lateral_sigma).abs() < 1e-8);
+
+        let pyramid = image.pyramid(2, 1.0);
+        assert!(pyramid[1].covariances.is_some());
+    }
 }
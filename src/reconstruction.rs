@@ -0,0 +1,626 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use itertools::iproduct;
+use nalgebra::Vector3;
+use ndarray::{Array1, Array2};
+
+use crate::{
+    camera::CameraIntrinsics,
+    error::A3dError,
+    icp::MsIcpParams,
+    image::{ColorAccumulator, ColorFusionParams},
+    io::{
+        dataset::{DatasetIter, FrameErrorPolicy, RgbdDataset},
+        npy::{read_npz, write_npz},
+        Geometry,
+    },
+    odometry::{FrameStage, OdometryPipeline},
+    pipeline::PipelineStage,
+    pointcloud::PointCloud,
+    range_image::{RangeImage, RangeImageBuilder},
+    session::Session,
+    trajectory::Trajectory,
+    transform::Transform,
+    RgbdFrame,
+};
+
+/// Parameters of the fusion of the aligned frames into a single point cloud.
+#[derive(Debug, Clone, Copy)]
+pub struct FusionParams {
+    /// Edge length of the voxels, the points falling in the same voxel are averaged.
+    pub voxel_size: f32,
+    /// How the colors of a voxel are combined.
+    pub color: ColorFusionParams,
+}
+
+impl Default for FusionParams {
+    fn default() -> Self {
+        Self {
+            voxel_size: 0.01,
+            color: ColorFusionParams::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Voxel {
+    point_sum: Vector3<f32>,
+    normal_sum: Vector3<f32>,
+    /// Sum of the confidences of the points.
+    weight: f32,
+    color: ColorAccumulator,
+}
+
+/// Averages world points by voxel, weighted by their `confidence` attribute when they have
+/// one. Voxels are kept in insertion order, so the output doesn't depend on hashing.
+#[derive(Debug, Clone)]
+pub(crate) struct VoxelFusion {
+    pub(crate) params: FusionParams,
+    indices: HashMap<[i32; 3], usize>,
+    voxels: Vec<Voxel>,
+}
+
+impl VoxelFusion {
+    pub(crate) fn new(params: FusionParams) -> Self {
+        Self {
+            params,
+            indices: HashMap::new(),
+            voxels: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, world_pcl: &PointCloud) {
+        let confidences = world_pcl.attribute::<f32>("confidence");
+        for (i, point) in world_pcl.points.iter().enumerate() {
+            let weight = confidences.map_or(1.0, |confidences| confidences[i]);
+            if weight <= 0.0 {
+                continue;
+            }
+            let key = (point / self.params.voxel_size).map(|coord| coord.floor() as i32);
+            let index = *self.indices.entry(key.into()).or_insert_with(|| {
+                self.voxels.push(Voxel::default());
+                self.voxels.len() - 1
+            });
+
+            let voxel = &mut self.voxels[index];
+            voxel.point_sum += point * weight;
+            voxel.weight += weight;
+            if let Some(normals) = &world_pcl.normals {
+                voxel.normal_sum += normals[i] * weight;
+            }
+            if let Some(colors) = &world_pcl.colors {
+                voxel.color.add(&colors[i], weight, &self.params.color);
+            }
+        }
+    }
+
+    /// Renders the predicted vertex and normal maps of the model seen from a camera, for
+    /// frame-to-model alignment. Each voxel is splatted over the pixels it covers.
+    ///
+    /// # Arguments
+    ///
+    /// * camera - The camera intrinsics.
+    /// * camera_to_world - The camera pose.
+    pub(crate) fn render(
+        &self,
+        camera: &CameraIntrinsics,
+        camera_to_world: &Transform,
+    ) -> RangeImage {
+        let world_to_camera = camera_to_world.inverse();
+        let mut index_map =
+            Array2::<Option<(usize, f32)>>::from_elem((camera.height, camera.width), None);
+        for (index, voxel) in self.voxels.iter().enumerate() {
+            let point = world_to_camera.transform_vector(&(voxel.point_sum / voxel.weight));
+            if point[2] <= 0.0 {
+                continue;
+            }
+            let (x, y) = camera.project(&point);
+            // Rounded down, larger splats make halos around the silhouettes.
+            let radius = (0.5 * self.params.voxel_size * camera.fx as f32 / point[2])
+                .floor()
+                .clamp(0.0, 4.0) as isize;
+            let (x, y) = (x.round() as isize, y.round() as isize);
+            for (row, col) in iproduct!(y - radius..=y + radius, x - radius..=x + radius) {
+                if row < 0
+                    || col < 0
+                    || row >= camera.height as isize
+                    || col >= camera.width as isize
+                {
+                    continue;
+                }
+                let pixel = &mut index_map[(row as usize, col as usize)];
+                if pixel.is_none_or(|(_, depth)| point[2] < depth) {
+                    *pixel = Some((index, point[2]));
+                }
+            }
+        }
+
+        let voxel_at =
+            |row: usize, col: usize| index_map[(row, col)].map(|(index, _)| &self.voxels[index]);
+        RangeImage::from_intrinsics_fn(
+            camera,
+            |row, col| {
+                voxel_at(row, col).map(|voxel| {
+                    world_to_camera.transform_vector(&(voxel.point_sum / voxel.weight))
+                })
+            },
+            |row, col| {
+                voxel_at(row, col).and_then(|voxel| {
+                    voxel
+                        .normal_sum
+                        .try_normalize(1e-6)
+                        .map(|normal| world_to_camera.transform_normal(&normal))
+                })
+            },
+            |row, col| voxel_at(row, col).and_then(|voxel| voxel.color.color(&self.params.color)),
+        )
+    }
+
+    /// Writes the voxels into a `.npz` archive, for checkpointing. Integers are bit-cast
+    /// into the `f32` arrays, which keeps them exact.
+    pub(crate) fn save(&self, path: &Path) -> Result<(), std::io::Error> {
+        let mut keys = vec![[0; 3]; self.voxels.len()];
+        for (key, index) in self.indices.iter() {
+            keys[*index] = *key;
+        }
+        let bits = |value: usize| f32::from_bits(value as u32);
+
+        let num_voxels = self.voxels.len();
+        let keys =
+            Array2::from_shape_fn((num_voxels, 3), |(i, j)| f32::from_bits(keys[i][j] as u32));
+        let sums = Array2::from_shape_fn((num_voxels, 11), |(i, j)| {
+            let voxel = &self.voxels[i];
+            match j {
+                0..=2 => voxel.point_sum[j],
+                3..=5 => voxel.normal_sum[j - 3],
+                6 => voxel.weight,
+                7..=9 => voxel.color.sum[j - 7],
+                _ => voxel.color.weight_sum,
+            }
+        });
+        let counts = Array2::from_shape_fn((num_voxels, 2), |(i, j)| {
+            let voxel = &self.voxels[i];
+            bits([voxel.color.count, voxel.color.samples.len()][j])
+        });
+        let samples = self
+            .voxels
+            .iter()
+            .flat_map(|voxel| voxel.color.samples.iter())
+            .collect::<Vec<_>>();
+        let samples = Array2::from_shape_fn((samples.len(), 3), |(i, j)| samples[i][j]);
+
+        write_npz(
+            path,
+            &[
+                (
+                    "voxel_size",
+                    Array2::from_elem((1, 1), self.params.voxel_size).view(),
+                ),
+                ("keys", keys.view()),
+                ("sums", sums.view()),
+                ("counts", counts.view()),
+                ("color_samples", samples.view()),
+            ],
+        )
+    }
+
+    /// Reads the voxels written by [`VoxelFusion::save`].
+    ///
+    /// # Returns
+    ///
+    /// The model, with the stored voxel size and default color parameters, or error if
+    /// the archive is unreadable or inconsistent.
+    pub(crate) fn load(path: &Path) -> Result<Self, A3dError> {
+        let error = |message: &str| {
+            A3dError::Parser(format!("Invalid model file {}: {message}", path.display()))
+        };
+        let arrays = read_npz(path).map_err(|err| error(&format!("{err:?}")))?;
+        let get = |name: &str, columns: usize| {
+            arrays
+                .get(name)
+                .filter(|array| array.ncols() == columns)
+                .ok_or_else(|| error(&format!("missing or malformed {name}")))
+        };
+        let voxel_size = get("voxel_size", 1)?
+            .iter()
+            .next()
+            .copied()
+            .ok_or_else(|| error("missing voxel size"))?;
+        let (keys, sums, counts, samples) = (
+            get("keys", 3)?,
+            get("sums", 11)?,
+            get("counts", 2)?,
+            get("color_samples", 3)?,
+        );
+        let num_voxels = keys.nrows();
+        if sums.nrows() != num_voxels || counts.nrows() != num_voxels {
+            return Err(error("the voxel arrays have different lengths"));
+        }
+
+        let mut model = Self::new(FusionParams {
+            voxel_size,
+            ..Default::default()
+        });
+        let mut samples = samples.rows().into_iter();
+        for i in 0..num_voxels {
+            let count = |j: usize| counts[(i, j)].to_bits() as usize;
+            let mut color = ColorAccumulator {
+                sum: Vector3::new(sums[(i, 7)], sums[(i, 8)], sums[(i, 9)]),
+                weight_sum: sums[(i, 10)],
+                count: count(0),
+                samples: Default::default(),
+            };
+            for _ in 0..count(1) {
+                let sample = samples
+                    .next()
+                    .ok_or_else(|| error("missing color samples"))?;
+                color
+                    .samples
+                    .push_back(Vector3::new(sample[0], sample[1], sample[2]));
+            }
+
+            let key = [0, 1, 2].map(|j| keys[(i, j)].to_bits() as i32);
+            if model.indices.insert(key, i).is_some() {
+                return Err(error("duplicated voxel"));
+            }
+            model.voxels.push(Voxel {
+                point_sum: Vector3::new(sums[(i, 0)], sums[(i, 1)], sums[(i, 2)]),
+                normal_sum: Vector3::new(sums[(i, 3)], sums[(i, 4)], sums[(i, 5)]),
+                weight: sums[(i, 6)],
+                color,
+            });
+        }
+        if samples.next().is_some() {
+            return Err(error("extra color samples"));
+        }
+
+        Ok(model)
+    }
+
+    pub(crate) fn into_geometry(self) -> Geometry {
+        let points = self
+            .voxels
+            .iter()
+            .map(|voxel| voxel.point_sum / voxel.weight)
+            .collect::<Array1<_>>();
+        let normals = self
+            .voxels
+            .iter()
+            .map(|voxel| voxel.normal_sum.try_normalize(1e-6).unwrap_or_default())
+            .collect::<Array1<_>>();
+        let colors = self
+            .voxels
+            .iter()
+            .map(|voxel| voxel.color.color(&self.params.color).unwrap_or_default())
+            .collect::<Array1<_>>();
+
+        PointCloud {
+            points,
+            normals: Some(normals),
+            colors: Some(colors),
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        }
+        .into()
+    }
+}
+
+/// Result of reconstructing a dataset.
+pub struct Reconstruction {
+    /// Camera poses of the read frames, the first one at the origin.
+    pub trajectory: Trajectory,
+    /// The fused point cloud, in the frame of the first camera.
+    pub geometry: Geometry,
+    /// Number of frames that couldn't be read and were skipped, by this run only when it
+    /// was resumed.
+    pub skipped_frames: usize,
+}
+
+impl Reconstruction {
+    /// Starts configuring a reconstruction.
+    ///
+    /// ```no_run
+    /// use align3d::prelude::*;
+    ///
+    /// let dataset = DatasetRegistry::default()
+    ///     .load("slamtb", "tests/data/rgbd/sample1")
+    ///     .unwrap();
+    /// let reconstruction = Reconstruction::builder().dataset(dataset).run().unwrap();
+    /// write_ply("map.ply", &reconstruction.geometry).unwrap();
+    /// ```
+    pub fn builder() -> ReconstructionBuilder {
+        ReconstructionBuilder::default()
+    }
+}
+
+/// Wires the dataset, the range image processing, the multiscale ICP odometry and the
+/// fusion. Every stage but the dataset has default parameters.
+#[derive(Default)]
+pub struct ReconstructionBuilder {
+    dataset: Option<Box<dyn RgbdDataset>>,
+    preprocess: Option<FrameStage>,
+    range_image: RangeImageBuilder,
+    icp: MsIcpParams,
+    fusion: FusionParams,
+    frame_to_model: bool,
+    checkpoint: Option<(PathBuf, usize)>,
+    resume_from: Option<PathBuf>,
+}
+
+impl ReconstructionBuilder {
+    /// Sets the dataset to reconstruct.
+    pub fn dataset(mut self, dataset: Box<dyn RgbdDataset>) -> Self {
+        self.dataset = Some(dataset);
+        self
+    }
+
+    /// Runs a custom stage on every frame before the range image processing, see
+    /// [`OdometryPipelineBuilder::preprocess`](crate::odometry::OdometryPipelineBuilder::preprocess).
+    pub fn preprocess<S>(mut self, stage: S) -> Self
+    where
+        S: PipelineStage<Input = RgbdFrame, Output = RgbdFrame> + 'static,
+    {
+        self.preprocess = Some(Box::new(stage));
+        self
+    }
+
+    /// Sets the range image processing. Its number of pyramid levels is replaced by the
+    /// number of levels of the ICP parameters.
+    pub fn range_image(mut self, builder: RangeImageBuilder) -> Self {
+        self.range_image = builder;
+        self
+    }
+
+    /// Sets the ICP parameters of each pyramid level.
+    pub fn icp(mut self, params: MsIcpParams) -> Self {
+        self.icp = params;
+        self
+    }
+
+    /// Sets the fusion parameters.
+    pub fn fusion(mut self, params: FusionParams) -> Self {
+        self.fusion = params;
+        self
+    }
+
+    /// Aligns each frame against a prediction rendered from the fused model at the last
+    /// pose, instead of against the previous frame. The model averages the noise of many
+    /// frames, so the odometry usually drifts less. Use a voxel size close to the pixel
+    /// footprint, e.g., 5 mm, a coarse model predicts a blocky surface. Disabled by default.
+    pub fn frame_to_model(mut self, value: bool) -> Self {
+        self.frame_to_model = value;
+        self
+    }
+
+    /// Saves a [`Session`] checkpoint with the trajectory, the last frame and the model
+    /// into a directory every `interval` processed frames. Disabled by default.
+    pub fn checkpoint<P: AsRef<Path>>(mut self, dirpath: P, interval: usize) -> Self {
+        self.checkpoint = Some((dirpath.as_ref().to_path_buf(), interval));
+        self
+    }
+
+    /// Continues an interrupted run from the checkpoint saved into a directory, starting
+    /// at the frame after the last checkpointed one. The dataset and the parameters must
+    /// be the ones of the interrupted run, so the result matches an uninterrupted run.
+    pub fn resume_from<P: AsRef<Path>>(mut self, dirpath: P) -> Self {
+        self.resume_from = Some(dirpath.as_ref().to_path_buf());
+        self
+    }
+
+    /// Aligns each frame to the previous one, or to the model, and fuses them.
+    ///
+    /// # Returns
+    ///
+    /// The reconstruction, or error if no dataset was set, the parameters are invalid,
+    /// the checkpoint can't be read or written, or no frame could be read.
+    pub fn run(self) -> Result<Reconstruction, A3dError> {
+        let dataset = self
+            .dataset
+            .ok_or_else(|| A3dError::invalid_parameter("No dataset was set."))?;
+        if self
+            .checkpoint
+            .as_ref()
+            .is_some_and(|(_, interval)| *interval == 0)
+        {
+            return Err(A3dError::invalid_parameter(
+                "The checkpoint interval must be positive.",
+            ));
+        }
+        let mut builder = OdometryPipeline::builder();
+        if let Some(stage) = self.preprocess {
+            builder = builder.preprocess(stage);
+        }
+        let mut odometry = builder
+            .range_image(self.range_image)
+            .icp(self.icp)
+            .fusion(Some(self.fusion))
+            .frame_to_model(self.frame_to_model)
+            .build()?;
+
+        let mut session = match &self.resume_from {
+            Some(dirpath) => {
+                let session = Session::load(dirpath)?;
+                session.resume(&mut odometry)?;
+                session
+            }
+            None => Session::new(0),
+        };
+
+        let mut frames =
+            DatasetIter::new(dataset.as_ref(), FrameErrorPolicy::Skip).start_at(session.next_frame);
+        let mut processed = 0;
+        for item in frames.by_ref() {
+            let (i, mut frame) = item.map_err(|err| A3dError::Parser(err.to_string()))?;
+            frame.metadata.frame_id = Some(i);
+            odometry.process(frame)?;
+
+            session.next_frame = i + 1;
+            processed += 1;
+            if let Some((dirpath, interval)) = &self.checkpoint {
+                if processed % interval == 0 {
+                    session.capture(&odometry);
+                    session.save(dirpath)?;
+                }
+            }
+        }
+        if odometry.trajectory().is_empty() {
+            return Err(A3dError::invalid_parameter(
+                "The dataset has no readable frames.",
+            ));
+        }
+        let (trajectory, geometry) = odometry.into_model();
+
+        Ok(Reconstruction {
+            trajectory,
+            geometry: geometry.unwrap(),
+            skipped_frames: frames.skipped().len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rstest::rstest;
+
+    use super::{FusionParams, Reconstruction, VoxelFusion};
+    use crate::{
+        icp::{IcpParams, MsIcpParams},
+        io::dataset::{RgbdDataset, SlamTbDataset, SubsetDataset},
+        metrics::TransformMetrics,
+        pointcloud::PointCloudBuilder,
+        unit_test::sample_rgbd_dataset1,
+    };
+
+    #[test]
+    fn test_fusion_confidences() {
+        let pcl = PointCloudBuilder::new()
+            .with_points([
+                Vector3::new(0.001, 0.0, 0.0),
+                Vector3::new(0.003, 0.0, 0.0),
+                Vector3::new(0.002, 0.0, 0.0),
+            ])
+            .with_normals([Vector3::x(), Vector3::y(), Vector3::z()])
+            .with_attribute("confidence", [0.25f32, 0.75, 0.0])
+            .build()
+            .unwrap();
+        let mut fusion = VoxelFusion::new(FusionParams::default());
+        fusion.add(&pcl);
+
+        let geometry = fusion.into_geometry();
+        assert_eq!(geometry.points.len(), 1);
+        assert!((geometry.points[0] - Vector3::new(0.0025, 0.0, 0.0)).norm() < 1e-6);
+        let normal = geometry.normals.unwrap()[0];
+        assert!((normal - Vector3::new(1.0, 3.0, 0.0).normalize()).norm() < 1e-6);
+    }
+
+    #[rstest]
+    #[case(false)]
+    #[case(true)]
+    fn test_reconstruction(
+        sample_rgbd_dataset1: impl RgbdDataset + 'static,
+        #[case] frame_to_model: bool,
+    ) {
+        let dataset = SubsetDataset::new(Box::new(sample_rgbd_dataset1), vec![0, 1, 2]);
+        let gt_trajectory = dataset.trajectory().unwrap().first_frame_at_origin();
+        let reconstruction = Reconstruction::builder()
+            .dataset(Box::new(dataset))
+            .frame_to_model(frame_to_model)
+            .icp(MsIcpParams::repeat(
+                2,
+                &IcpParams {
+                    max_iterations: 5,
+                    ..Default::default()
+                },
+            ))
+            .fusion(FusionParams {
+                voxel_size: 0.005,
+                ..Default::default()
+            })
+            .run()
+            .unwrap();
+
+        assert_eq!(reconstruction.trajectory.len(), 3);
+        assert_eq!(reconstruction.skipped_frames, 0);
+        assert!(!reconstruction.geometry.points.is_empty());
+        let metrics =
+            TransformMetrics::mean_trajectory_error(&reconstruction.trajectory, &gt_trajectory)
+                .unwrap();
+        assert!(metrics.translation < 0.02, "{metrics}");
+
+        assert!(Reconstruction::builder().run().is_err());
+    }
+
+    /// A run stopped after the second frame and resumed from its checkpoint gives the
+    /// same result as an uninterrupted run.
+    #[test]
+    fn test_resume() {
+        let builder = |frames: Vec<usize>| {
+            let dataset = SlamTbDataset::load("tests/data/rgbd/sample1").unwrap();
+            Reconstruction::builder()
+                .dataset(Box::new(SubsetDataset::new(Box::new(dataset), frames)))
+                .frame_to_model(true)
+                .icp(MsIcpParams::repeat(
+                    2,
+                    &IcpParams {
+                        max_iterations: 5,
+                        ..Default::default()
+                    },
+                ))
+                .fusion(FusionParams {
+                    voxel_size: 0.005,
+                    ..Default::default()
+                })
+        };
+        let checkpoint = tempfile::tempdir().unwrap();
+
+        let uninterrupted = builder(vec![0, 1, 2]).run().unwrap();
+        builder(vec![0, 1])
+            .checkpoint(checkpoint.path(), 1)
+            .run()
+            .unwrap();
+        let mut files = std::fs::read_dir(checkpoint.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(
+            files,
+            ["last_frame_000002.npz", "model_000002.npz", "session.json"]
+        );
+        let resumed = builder(vec![0, 1, 2])
+            .resume_from(checkpoint.path())
+            .run()
+            .unwrap();
+
+        assert_eq!(resumed.trajectory.times, uninterrupted.trajectory.times);
+        for (resumed_pose, pose) in resumed
+            .trajectory
+            .camera_to_world
+            .iter()
+            .zip(uninterrupted.trajectory.camera_to_world.iter())
+        {
+            assert_eq!(resumed_pose.0, pose.0);
+        }
+        assert_eq!(resumed.geometry.points, uninterrupted.geometry.points);
+        assert_eq!(resumed.geometry.normals, uninterrupted.geometry.normals);
+        assert_eq!(resumed.geometry.colors, uninterrupted.geometry.colors);
+
+        assert!(builder(vec![0, 1, 2])
+            .fusion(FusionParams {
+                voxel_size: 0.01,
+                ..Default::default()
+            })
+            .resume_from(checkpoint.path())
+            .run()
+            .is_err());
+        assert!(builder(vec![0])
+            .checkpoint(checkpoint.path(), 0)
+            .run()
+            .is_err());
+    }
+}
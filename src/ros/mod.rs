@@ -0,0 +1,598 @@
+//! Runs align3d as a ROS 2 mapping node, enabled by the `ros` feature.
+//!
+//! [`RosNode`] publishes the odometry on `/odom` (`nav_msgs/Odometry`), the camera pose on
+//! `/tf` and the map on `/map_points` (`sensor_msgs/PointCloud2`), and subscribes to the
+//! color and depth images of a camera as a live dataset, a [`RosFrameSource`]. It uses the
+//! pure Rust `ros2-client`, so no ROS installation is needed for building.
+//!
+//! The message types below have the field layout of their ROS counterparts, which is what
+//! the CDR serialization of ROS 2 requires.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use nalgebra::Matrix6;
+use ndarray::{Array2, Array3};
+use serde::{ser::SerializeTuple, Serializer};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    camera::CameraIntrinsics,
+    image::{RgbdFrame, RgbdImage},
+    io::dataset::DatasetError,
+    metadata::Metadata,
+    pointcloud::PointCloud,
+    transform::Transform,
+};
+
+mod node;
+pub use node::RosNode;
+
+/// Variance reported for the unknown parts of a message, e.g., the pose without an ICP
+/// covariance and the twist, so consumers don't take them as perfectly known.
+pub const UNKNOWN_VARIANCE: f64 = 1.0e6;
+
+/// `builtin_interfaces/Time`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Time {
+    pub sec: i32,
+    pub nanosec: u32,
+}
+
+impl Time {
+    /// Creates the time from seconds.
+    pub fn from_seconds(seconds: f64) -> Self {
+        let sec = seconds.floor();
+        Self {
+            sec: sec as i32,
+            nanosec: ((seconds - sec) * 1.0e9).round().min(999_999_999.0) as u32,
+        }
+    }
+
+    /// The time in seconds.
+    pub fn seconds(&self) -> f64 {
+        self.sec as f64 + self.nanosec as f64 * 1.0e-9
+    }
+}
+
+/// `std_msgs/Header`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub stamp: Time,
+    pub frame_id: String,
+}
+
+impl Header {
+    /// Creates the header.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp` - Time stamp in seconds.
+    /// * `frame_id` - The frame of the data.
+    pub fn new(stamp: f64, frame_id: &str) -> Self {
+        Self {
+            stamp: Time::from_seconds(stamp),
+            frame_id: frame_id.to_string(),
+        }
+    }
+}
+
+/// Serializes the 6x6 covariances as fixed size arrays, serde only derives up to 32 items.
+fn serialize_covariance<S: Serializer>(
+    values: &[f64; 36],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut tuple = serializer.serialize_tuple(values.len())?;
+    for value in values.iter() {
+        tuple.serialize_element(value)?;
+    }
+    tuple.end()
+}
+
+/// Row-major 6x6 covariance with [`UNKNOWN_VARIANCE`] on the diagonal.
+fn unknown_covariance() -> [f64; 36] {
+    let mut covariance = [0.0; 36];
+    for i in 0..6 {
+        covariance[i * 7] = UNKNOWN_VARIANCE;
+    }
+    covariance
+}
+
+/// `nav_msgs/Odometry`. align3d does not estimate the twist, so it is zero with
+/// [`UNKNOWN_VARIANCE`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OdometryMsg {
+    pub header: Header,
+    pub child_frame_id: String,
+    pub position: [f64; 3],
+    /// Quaternion as (x, y, z, w).
+    pub orientation: [f64; 4],
+    /// Row-major 6x6 covariance of (x, y, z, rot_x, rot_y, rot_z).
+    #[serde(serialize_with = "serialize_covariance")]
+    pub pose_covariance: [f64; 36],
+    pub linear_velocity: [f64; 3],
+    pub angular_velocity: [f64; 3],
+    /// Row-major 6x6 covariance of the linear and angular velocities.
+    #[serde(serialize_with = "serialize_covariance")]
+    pub twist_covariance: [f64; 36],
+}
+
+/// `geometry_msgs/TransformStamped`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TransformStampedMsg {
+    pub header: Header,
+    pub child_frame_id: String,
+    pub translation: [f64; 3],
+    /// Quaternion as (x, y, z, w).
+    pub rotation: [f64; 4],
+}
+
+/// `tf2_msgs/TFMessage`, published on `/tf`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TfMsg {
+    pub transforms: Vec<TransformStampedMsg>,
+}
+
+fn pose_parts(transform: &Transform) -> ([f64; 3], [f64; 4]) {
+    let translation = transform.translation();
+    let rotation = transform.0.rotation.quaternion();
+    (
+        [0, 1, 2].map(|i| translation[i] as f64),
+        [rotation.i, rotation.j, rotation.k, rotation.w].map(|value| value as f64),
+    )
+}
+
+impl OdometryMsg {
+    /// Creates the message of a camera pose.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The stamp and the fixed (e.g., `odom`) frame.
+    /// * `child_frame_id` - The camera frame.
+    /// * `camera_to_world` - The camera pose.
+    /// * `covariance` - Covariance of the pose, e.g., from `IcpResult::covariance`, whose
+    ///   order (translation, then rotation as a perturbation in the fixed frame) already is
+    ///   the ROS one. Without it, the pose gets [`UNKNOWN_VARIANCE`].
+    pub fn new(
+        header: Header,
+        child_frame_id: &str,
+        camera_to_world: &Transform,
+        covariance: Option<&Matrix6<f32>>,
+    ) -> Self {
+        let (position, orientation) = pose_parts(camera_to_world);
+        let pose_covariance = match covariance {
+            Some(covariance) => {
+                let mut values = [0.0; 36];
+                for (i, value) in values.iter_mut().enumerate() {
+                    *value = covariance[(i / 6, i % 6)] as f64;
+                }
+                values
+            }
+            None => unknown_covariance(),
+        };
+        Self {
+            header,
+            child_frame_id: child_frame_id.to_string(),
+            position,
+            orientation,
+            pose_covariance,
+            linear_velocity: [0.0; 3],
+            angular_velocity: [0.0; 3],
+            twist_covariance: unknown_covariance(),
+        }
+    }
+}
+
+impl TransformStampedMsg {
+    /// Creates the TF message of a camera pose, see [`OdometryMsg::new`].
+    pub fn new(header: Header, child_frame_id: &str, camera_to_world: &Transform) -> Self {
+        let (translation, rotation) = pose_parts(camera_to_world);
+        Self {
+            header,
+            child_frame_id: child_frame_id.to_string(),
+            translation,
+            rotation,
+        }
+    }
+}
+
+/// `sensor_msgs/PointField`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PointField {
+    pub name: String,
+    pub offset: u32,
+    pub datatype: u8,
+    pub count: u32,
+}
+
+/// `sensor_msgs/PointField` FLOAT32 datatype.
+pub const FLOAT32: u8 = 7;
+
+/// `sensor_msgs/PointCloud2`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PointCloud2Msg {
+    pub header: Header,
+    pub height: u32,
+    pub width: u32,
+    pub fields: Vec<PointField>,
+    pub is_bigendian: bool,
+    pub point_step: u32,
+    pub row_step: u32,
+    pub data: Vec<u8>,
+    pub is_dense: bool,
+}
+
+impl PointCloud2Msg {
+    /// Packs a point cloud, e.g., the map, as an unorganized cloud with the fields `x`, `y`,
+    /// `z`, then `normal_x`, `normal_y`, `normal_z` and `rgb` when present. The color uses
+    /// the PCL convention of a `0x00RRGGBB` integer stored in a float field.
+    pub fn from_point_cloud(header: Header, cloud: &PointCloud) -> Self {
+        let mut names = vec!["x", "y", "z"];
+        if cloud.normals.is_some() {
+            names.extend(["normal_x", "normal_y", "normal_z"]);
+        }
+        if cloud.colors.is_some() {
+            names.push("rgb");
+        }
+        let fields = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| PointField {
+                name: name.to_string(),
+                offset: i as u32 * 4,
+                datatype: FLOAT32,
+                count: 1,
+            })
+            .collect::<Vec<_>>();
+        let point_step = fields.len() as u32 * 4;
+
+        let mut data = Vec::with_capacity(cloud.len() * point_step as usize);
+        for i in 0..cloud.len() {
+            for value in cloud.points[i].iter() {
+                data.extend(value.to_le_bytes());
+            }
+            if let Some(normals) = &cloud.normals {
+                for value in normals[i].iter() {
+                    data.extend(value.to_le_bytes());
+                }
+            }
+            if let Some(colors) = &cloud.colors {
+                let [r, g, b] = [0, 1, 2].map(|c| colors[i][c] as u32);
+                data.extend(((r << 16) | (g << 8) | b).to_le_bytes());
+            }
+        }
+
+        Self {
+            header,
+            height: 1,
+            width: cloud.len() as u32,
+            fields,
+            is_bigendian: false,
+            point_step,
+            row_step: point_step * cloud.len() as u32,
+            data,
+            is_dense: cloud
+                .points
+                .iter()
+                .all(|point| point.iter().all(|value| value.is_finite())),
+        }
+    }
+}
+
+/// `sensor_msgs/Image`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageMsg {
+    pub header: Header,
+    pub height: u32,
+    pub width: u32,
+    pub encoding: String,
+    pub is_bigendian: u8,
+    /// Row length in bytes.
+    pub step: u32,
+    pub data: Vec<u8>,
+}
+
+impl ImageMsg {
+    /// The rows of the image without their padding.
+    fn rows(&self, bytes_per_pixel: usize) -> Result<impl Iterator<Item = &[u8]>, DatasetError> {
+        let (width, height, step) = (
+            self.width as usize,
+            self.height as usize,
+            self.step as usize,
+        );
+        let row_length = width * bytes_per_pixel;
+        if step < row_length || self.data.len() < step * height {
+            return Err(DatasetError::Parser(format!(
+                "Image data of {} bytes is too short for {width}x{height} {} pixels",
+                self.data.len(),
+                self.encoding
+            )));
+        }
+        Ok(self
+            .data
+            .chunks(step)
+            .take(height)
+            .map(move |row| &row[..row_length]))
+    }
+
+    /// Converts an `rgb8` or `bgr8` image into RGB colors.
+    pub fn to_color(&self) -> Result<Array3<u8>, DatasetError> {
+        let bgr = match self.encoding.as_str() {
+            "rgb8" => false,
+            "bgr8" => true,
+            encoding => {
+                return Err(DatasetError::Parser(format!(
+                    "Unsupported color encoding {encoding}"
+                )))
+            }
+        };
+        let mut color = Vec::with_capacity((self.width * self.height * 3) as usize);
+        for row in self.rows(3)? {
+            if bgr {
+                color.extend(
+                    row.chunks(3)
+                        .flat_map(|pixel| [pixel[2], pixel[1], pixel[0]]),
+                );
+            } else {
+                color.extend_from_slice(row);
+            }
+        }
+        Ok(Array3::from_shape_vec((self.height as usize, self.width as usize, 3), color).unwrap())
+    }
+
+    /// Converts a `16UC1` or `mono16` image into depth values.
+    pub fn to_depth(&self) -> Result<Array2<u16>, DatasetError> {
+        if self.encoding != "16UC1" && self.encoding != "mono16" {
+            return Err(DatasetError::Parser(format!(
+                "Unsupported depth encoding {}",
+                self.encoding
+            )));
+        }
+        let mut depth = Vec::with_capacity((self.width * self.height) as usize);
+        for row in self.rows(2)? {
+            depth.extend(row.chunks(2).map(|bytes| {
+                let bytes = [bytes[0], bytes[1]];
+                if self.is_bigendian != 0 {
+                    u16::from_be_bytes(bytes)
+                } else {
+                    u16::from_le_bytes(bytes)
+                }
+            }));
+        }
+        Ok(Array2::from_shape_vec((self.height as usize, self.width as usize), depth).unwrap())
+    }
+}
+
+/// Feeds images received by ROS subscribers into a [`RosFrameSource`]. It can be moved
+/// into the subscriber callbacks.
+#[derive(Clone)]
+pub struct RosFrameSender {
+    sender: Sender<RgbdFrame>,
+    camera: CameraIntrinsics,
+    depth_scale: f64,
+}
+
+impl RosFrameSender {
+    /// Sends a synchronized color and depth pair.
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp` - The image time stamp in seconds.
+    /// * `color` - RGB colors, row-major.
+    /// * `depth` - Depth values, row-major.
+    ///
+    /// # Returns
+    ///
+    /// Error if the image sizes don't match the camera, or an `Io` error if the source was
+    /// dropped.
+    pub fn send(&self, stamp: f64, color: &[u8], depth: &[u16]) -> Result<(), DatasetError> {
+        let (width, height) = (self.camera.width, self.camera.height);
+        if color.len() != width * height * 3 || depth.len() != width * height {
+            return Err(DatasetError::Parser(format!(
+                "Image sizes differ from the camera size {width}x{height}"
+            )));
+        }
+
+        let image = RgbdImage::with_depth_scale(
+            Array3::from_shape_vec((height, width, 3), color.to_vec()).unwrap(),
+            Array2::from_shape_vec((height, width), depth.to_vec()).unwrap(),
+            self.depth_scale,
+        );
+        let frame = RgbdFrame::new(self.camera.clone(), image, None).with_metadata(Metadata {
+            timestamp: Some(stamp),
+            source: Some("ros".to_string()),
+            ..Default::default()
+        });
+        self.sender.send(frame).map_err(|_| {
+            DatasetError::Io(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "The frame source was dropped",
+            ))
+        })
+    }
+
+    /// Sends a synchronized pair of `sensor_msgs/Image`, stamped with the color one.
+    pub fn send_images(&self, color: &ImageMsg, depth: &ImageMsg) -> Result<(), DatasetError> {
+        let (color_values, depth_values) = (color.to_color()?, depth.to_depth()?);
+        self.send(
+            color.header.stamp.seconds(),
+            color_values.as_slice().unwrap(),
+            depth_values.as_slice().unwrap(),
+        )
+    }
+}
+
+/// Live dataset of frames received from ROS topics. Iterating blocks until the next frame
+/// arrives, and ends when all the senders are dropped.
+pub struct RosFrameSource {
+    receiver: Receiver<RgbdFrame>,
+}
+
+impl RosFrameSource {
+    /// Creates the source and the sender for the subscriber callbacks.
+    ///
+    /// # Arguments
+    ///
+    /// * `camera` - The camera intrinsics, e.g., from `sensor_msgs/CameraInfo`.
+    /// * `depth_scale` - The scale that converts depth values into meters.
+    pub fn new(camera: CameraIntrinsics, depth_scale: f64) -> (RosFrameSender, Self) {
+        let (sender, receiver) = channel();
+        (
+            RosFrameSender {
+                sender,
+                camera,
+                depth_scale,
+            },
+            Self { receiver },
+        )
+    }
+
+    /// Returns a frame if one is waiting, without blocking.
+    pub fn try_next(&self) -> Option<RgbdFrame> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Iterator for RosFrameSource {
+    type Item = RgbdFrame;
+
+    fn next(&mut self) -> Option<RgbdFrame> {
+        self.receiver.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix6, Quaternion, Vector3};
+    use ndarray::array;
+
+    use super::{
+        Header, ImageMsg, OdometryMsg, PointCloud2Msg, RosFrameSource, Time, UNKNOWN_VARIANCE,
+    };
+    use crate::{camera::CameraIntrinsics, pointcloud::PointCloud, transform::Transform};
+
+    fn header() -> Header {
+        Header::new(1.5, "odom")
+    }
+
+    #[test]
+    fn test_time() {
+        let time = Time::from_seconds(1305031102.175304);
+        assert_eq!(time.sec, 1305031102);
+        assert!((time.seconds() - 1305031102.175304).abs() < 1e-6);
+        assert_eq!(
+            header().stamp,
+            Time {
+                sec: 1,
+                nanosec: 500_000_000
+            }
+        );
+    }
+
+    #[test]
+    fn test_odometry() {
+        let pose = Transform::new(
+            &Vector3::new(1.0, 2.0, 3.0),
+            &Quaternion::new(1.0, 0.0, 0.0, 0.0),
+        );
+        let msg = OdometryMsg::new(header(), "camera", &pose, None);
+        assert_eq!(msg.position, [1.0, 2.0, 3.0]);
+        assert_eq!(msg.orientation, [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(msg.pose_covariance[0], UNKNOWN_VARIANCE);
+        assert_eq!(msg.pose_covariance[35], UNKNOWN_VARIANCE);
+        assert_eq!(msg.pose_covariance[1], 0.0);
+
+        let mut covariance = Matrix6::from_diagonal_element(0.01);
+        covariance[(0, 3)] = 0.5;
+        let msg = OdometryMsg::new(header(), "camera", &pose, Some(&covariance));
+        assert!((msg.pose_covariance[7] - 0.01).abs() < 1e-9);
+        assert_eq!(msg.pose_covariance[3], 0.5);
+
+        // The covariances serialize as fixed size arrays.
+        let json = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["pose_covariance"].as_array().unwrap().len(), 36);
+        assert_eq!(json["twist_covariance"].as_array().unwrap().len(), 36);
+    }
+
+    #[test]
+    fn test_point_cloud2() {
+        let mut cloud = PointCloud {
+            points: array![Vector3::new(1.0, 2.0, 3.0), Vector3::new(4.0, 5.0, 6.0)],
+            normals: None,
+            colors: Some(array![Vector3::new(255, 128, 1), Vector3::new(0, 0, 0)]),
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+        let msg = PointCloud2Msg::from_point_cloud(header(), &cloud);
+        assert_eq!(msg.fields.len(), 4);
+        assert_eq!(msg.point_step, 16);
+        assert_eq!(msg.data.len(), 32);
+        assert!(msg.is_dense);
+        assert_eq!(
+            f32::from_le_bytes(msg.data[16..20].try_into().unwrap()),
+            4.0
+        );
+        assert_eq!(
+            u32::from_le_bytes(msg.data[12..16].try_into().unwrap()),
+            0x00FF_8001
+        );
+
+        cloud.points[1][2] = f32::NAN;
+        assert!(!PointCloud2Msg::from_point_cloud(header(), &cloud).is_dense);
+    }
+
+    #[test]
+    fn test_image_msg() {
+        // One pixel of padding per row.
+        let color = ImageMsg {
+            header: header(),
+            height: 2,
+            width: 1,
+            encoding: "bgr8".to_string(),
+            is_bigendian: 0,
+            step: 4,
+            data: vec![1, 2, 3, 0, 4, 5, 6, 0],
+        };
+        assert_eq!(
+            color.to_color().unwrap().into_raw_vec(),
+            vec![3, 2, 1, 6, 5, 4]
+        );
+
+        let depth = ImageMsg {
+            encoding: "16UC1".to_string(),
+            step: 2,
+            data: vec![0xe8, 0x03, 0xd0, 0x07],
+            ..color.clone()
+        };
+        assert_eq!(depth.to_depth().unwrap(), array![[1000], [2000]]);
+        assert!(depth.to_color().is_err());
+        assert!(ImageMsg {
+            data: vec![0xe8],
+            ..depth
+        }
+        .to_depth()
+        .is_err());
+    }
+
+    #[test]
+    fn test_frame_source() {
+        let camera = CameraIntrinsics::from_simple_intrinsic(1.0, 1.0, 1.0, 1.0, 2, 2);
+        let (sender, source) = RosFrameSource::new(camera, 0.001);
+
+        let callback_sender = sender.clone();
+        std::thread::spawn(move || {
+            callback_sender
+                .send(0.5, &[0; 12], &[1000, 0, 2000, 0])
+                .unwrap();
+        })
+        .join()
+        .unwrap();
+        assert!(sender.send(1.0, &[0; 3], &[0; 4]).is_err());
+        drop(sender);
+
+        let frames = source.collect::<Vec<_>>();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].metadata.timestamp, Some(0.5));
+        assert_eq!(frames[0].image.depth[(1, 0)], 2000);
+    }
+}
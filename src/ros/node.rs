@@ -0,0 +1,221 @@
+use std::{fmt::Debug, thread, time::Duration};
+
+use nalgebra::Matrix6;
+use ros2_client::{
+    Context, MessageTypeName, Name, Node, NodeName, NodeOptions, Publisher, Subscription,
+};
+use rustdds::{
+    policy::{Durability, History, Reliability},
+    QosPolicies, QosPolicyBuilder,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    Header, ImageMsg, OdometryMsg, PointCloud2Msg, RosFrameSource, TfMsg, TransformStampedMsg,
+};
+use crate::{
+    camera::CameraIntrinsics, error::A3dError, io::dataset::DatasetError, pointcloud::PointCloud,
+    transform::Transform,
+};
+
+/// Color and depth images with stamps closer than this are paired into a frame, in seconds.
+const SYNC_TOLERANCE: f64 = 0.02;
+
+fn ros_error<E: Debug>(err: E) -> A3dError {
+    A3dError::Io(std::io::Error::other(format!("ROS error: {err:?}")))
+}
+
+/// Splits a topic name, e.g., `/camera/color/image_raw`, into its namespace and base name.
+fn topic_name(topic: &str) -> Result<Name, A3dError> {
+    let (namespace, base_name) = topic.rsplit_once('/').unwrap_or(("", topic));
+    let namespace = if namespace.is_empty() { "/" } else { namespace };
+    Name::new(namespace, base_name).map_err(ros_error)
+}
+
+fn qos(reliability: Reliability) -> QosPolicies {
+    QosPolicyBuilder::new()
+        .reliability(reliability)
+        .durability(Durability::Volatile)
+        .history(History::KeepLast { depth: 10 })
+        .build()
+}
+
+/// A ROS 2 node that publishes the results of align3d, see the [module](super)
+/// documentation.
+pub struct RosNode {
+    node: Node,
+    odometry: Publisher<OdometryMsg>,
+    tf: Publisher<TfMsg>,
+    map: Publisher<PointCloud2Msg>,
+}
+
+impl RosNode {
+    /// Creates the node and its publishers.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The node name.
+    pub fn new(name: &str) -> Result<Self, A3dError> {
+        let context = Context::new().map_err(ros_error)?;
+        let mut node = context
+            .new_node(
+                NodeName::new("/", name).map_err(ros_error)?,
+                NodeOptions::new(),
+            )
+            .map_err(ros_error)?;
+
+        let odometry = Self::publisher(&mut node, "/odom", "nav_msgs", "Odometry")?;
+        let tf = Self::publisher(&mut node, "/tf", "tf2_msgs", "TFMessage")?;
+        let map = Self::publisher(&mut node, "/map_points", "sensor_msgs", "PointCloud2")?;
+        Ok(Self {
+            node,
+            odometry,
+            tf,
+            map,
+        })
+    }
+
+    fn publisher<M: Serialize>(
+        node: &mut Node,
+        topic: &str,
+        package: &str,
+        type_name: &str,
+    ) -> Result<Publisher<M>, A3dError> {
+        let reliable = Reliability::Reliable {
+            max_blocking_time: rustdds::Duration::from_millis(100),
+        };
+        let topic = node
+            .create_topic(
+                &topic_name(topic)?,
+                MessageTypeName::new(package, type_name),
+                &qos(reliable),
+            )
+            .map_err(ros_error)?;
+        node.create_publisher(&topic, None).map_err(ros_error)
+    }
+
+    fn subscription<M: DeserializeOwned + 'static>(
+        &mut self,
+        topic: &str,
+        package: &str,
+        type_name: &str,
+    ) -> Result<Subscription<M>, A3dError> {
+        // Best effort matches both the best effort and the reliable camera drivers.
+        let topic = self
+            .node
+            .create_topic(
+                &topic_name(topic)?,
+                MessageTypeName::new(package, type_name),
+                &qos(Reliability::BestEffort),
+            )
+            .map_err(ros_error)?;
+        self.node
+            .create_subscription(&topic, None)
+            .map_err(ros_error)
+    }
+
+    /// Publishes a camera pose as odometry and TF.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The stamp and the fixed (e.g., `odom`) frame.
+    /// * `child_frame_id` - The camera frame.
+    /// * `camera_to_world` - The camera pose.
+    /// * `covariance` - Covariance of the pose, see [`OdometryMsg::new`].
+    pub fn publish_pose(
+        &self,
+        header: Header,
+        child_frame_id: &str,
+        camera_to_world: &Transform,
+        covariance: Option<&Matrix6<f32>>,
+    ) -> Result<(), A3dError> {
+        let transform = TransformStampedMsg::new(header.clone(), child_frame_id, camera_to_world);
+        self.odometry
+            .publish(OdometryMsg::new(
+                header,
+                child_frame_id,
+                camera_to_world,
+                covariance,
+            ))
+            .map_err(ros_error)?;
+        self.tf
+            .publish(TfMsg {
+                transforms: vec![transform],
+            })
+            .map_err(ros_error)
+    }
+
+    /// Publishes the map.
+    pub fn publish_map(&self, header: Header, map: &PointCloud) -> Result<(), A3dError> {
+        self.map
+            .publish(PointCloud2Msg::from_point_cloud(header, map))
+            .map_err(ros_error)
+    }
+
+    /// Subscribes to the images of a camera and pairs them into frames by their stamps.
+    ///
+    /// # Arguments
+    ///
+    /// * `color_topic` - Topic of the `rgb8` or `bgr8` images.
+    /// * `depth_topic` - Topic of the `16UC1` depth images, registered to the color ones.
+    /// * `camera` - The camera intrinsics, e.g., from `sensor_msgs/CameraInfo`.
+    /// * `depth_scale` - The scale that converts depth values into meters.
+    ///
+    /// # Returns
+    ///
+    /// The live dataset of the frames. It ends if the node's subscriptions fail.
+    pub fn subscribe_frames(
+        &mut self,
+        color_topic: &str,
+        depth_topic: &str,
+        camera: CameraIntrinsics,
+        depth_scale: f64,
+    ) -> Result<RosFrameSource, A3dError> {
+        let color = self.subscription::<ImageMsg>(color_topic, "sensor_msgs", "Image")?;
+        let depth = self.subscription::<ImageMsg>(depth_topic, "sensor_msgs", "Image")?;
+        let (sender, source) = RosFrameSource::new(camera, depth_scale);
+
+        thread::spawn(move || {
+            let (mut last_color, mut last_depth): (Option<ImageMsg>, Option<ImageMsg>) =
+                (None, None);
+            loop {
+                let mut received = false;
+                for (subscription, last) in [(&color, &mut last_color), (&depth, &mut last_depth)] {
+                    match subscription.take() {
+                        Ok(Some((message, _))) => {
+                            *last = Some(message);
+                            received = true;
+                        }
+                        Ok(None) => {}
+                        Err(_) => return,
+                    }
+                }
+
+                if let (Some(color_msg), Some(depth_msg)) = (&last_color, &last_depth) {
+                    let (color_stamp, depth_stamp) = (
+                        color_msg.header.stamp.seconds(),
+                        depth_msg.header.stamp.seconds(),
+                    );
+                    if (color_stamp - depth_stamp).abs() <= SYNC_TOLERANCE {
+                        match sender.send_images(color_msg, depth_msg) {
+                            // The source was dropped.
+                            Err(DatasetError::Io(_)) => return,
+                            // Images not matching the camera are skipped.
+                            Ok(()) | Err(_) => {}
+                        }
+                        (last_color, last_depth) = (None, None);
+                    } else if color_stamp < depth_stamp {
+                        last_color = None;
+                    } else {
+                        last_depth = None;
+                    }
+                }
+
+                if !received {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        });
+        Ok(source)
+    }
+}
@@ -1,4 +1,91 @@
+use crate::pointcloud::PointCloud;
+
 pub trait Downsample {
     type Output;
     fn downsample(&self, scale: f32) -> Self::Output;
 }
+
+/// Farthest point sampling: picks points that are spread over the cloud, each one the
+/// farthest from the ones already picked. Unlike random sampling, it doesn't cluster on
+/// densely sampled regions, e.g., close to an RGB-D camera. The first point is the
+/// farthest from the centroid, so the sampling is deterministic. It takes `O(N count)`.
+///
+/// # Arguments
+///
+/// * pcl - The point cloud.
+/// * count - Number of points to sample, the whole cloud if it has fewer points.
+///
+/// # Returns
+///
+/// The sampled point cloud with its attributes, and the indices of its points in the input.
+pub fn farthest_point_sampling(pcl: &PointCloud, count: usize) -> (PointCloud, Vec<usize>) {
+    let count = count.min(pcl.len());
+    let mut indices = Vec::with_capacity(count);
+    if count > 0 {
+        let centroid = pcl.points.sum() / pcl.len() as f32;
+        let mut sqr_distances = pcl
+            .points
+            .iter()
+            .map(|point| (point - centroid).norm_squared())
+            .collect::<Vec<_>>();
+        let mut next = argmax(&sqr_distances);
+        sqr_distances.fill(f32::INFINITY);
+        while indices.len() < count {
+            indices.push(next);
+            let picked = pcl.points[next];
+            for (sqr_distance, point) in sqr_distances.iter_mut().zip(pcl.points.iter()) {
+                *sqr_distance = sqr_distance.min((point - picked).norm_squared());
+            }
+            next = argmax(&sqr_distances);
+        }
+    }
+    (pcl.select(&indices), indices)
+}
+
+fn argmax(values: &[f32]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map_or(0, |(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::farthest_point_sampling;
+    use crate::{pointcloud::PointCloud, unit_test::sample_teapot_surface};
+
+    #[rstest]
+    fn test_farthest_point_sampling(sample_teapot_surface: PointCloud) {
+        let (sampled, indices) = farthest_point_sampling(&sample_teapot_surface, 64);
+        assert_eq!(sampled.len(), 64);
+        assert_eq!(sampled.normals.as_ref().unwrap().len(), 64);
+        assert!(indices
+            .iter()
+            .enumerate()
+            .all(|(i, index)| sampled.points[i] == sample_teapot_surface.points[*index]));
+
+        let min_spacing = |points: &[usize]| {
+            let mut min = f32::INFINITY;
+            for (i, a) in points.iter().enumerate() {
+                for b in &points[i + 1..] {
+                    min = min.min(
+                        (sample_teapot_surface.points[*a] - sample_teapot_surface.points[*b])
+                            .norm(),
+                    );
+                }
+            }
+            min
+        };
+        // Evenly strided points are much closer to each other.
+        let strided = (0..64)
+            .map(|i| i * sample_teapot_surface.len() / 64)
+            .collect::<Vec<_>>();
+        assert!(min_spacing(&indices) > 2.0 * min_spacing(&strided));
+
+        let (all, _) = farthest_point_sampling(&sampled, 100);
+        assert_eq!(all.len(), 64);
+    }
+}
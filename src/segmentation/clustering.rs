@@ -0,0 +1,207 @@
+use std::ops::RangeInclusive;
+
+use ndarray::Array1;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::{error::A3dError, kdtree::R3dTree, pointcloud::PointCloud};
+
+/// Label of the points that [`dbscan`] doesn't assign to any cluster.
+pub const NOISE: i32 = -1;
+
+/// Splits a point cloud into the connected components of the points closer than a
+/// tolerance, e.g., to isolate the objects of a fused scene after removing its floor with
+/// [`super::segment_plane`].
+///
+/// # Arguments
+///
+/// * pcl - The point cloud.
+/// * tolerance - Maximum distance between two neighbor points of a cluster.
+/// * size_range - Number of points of the kept clusters, smaller clusters are usually
+///   noise and larger ones the background.
+///
+/// # Returns
+///
+/// The indices of the points of each cluster in increasing order, the largest cluster
+/// first. Error if the tolerance isn't positive.
+pub fn euclidean_clusters(
+    pcl: &PointCloud,
+    tolerance: f32,
+    size_range: RangeInclusive<usize>,
+) -> Result<Vec<Vec<usize>>, A3dError> {
+    if tolerance <= 0.0 {
+        return Err(A3dError::invalid_parameter(
+            "The cluster tolerance must be positive.",
+        ));
+    }
+
+    let kdtree = R3dTree::new(&pcl.points.view());
+    let mut visited = vec![false; pcl.len()];
+    let mut clusters = Vec::new();
+    for seed in 0..pcl.len() {
+        if visited[seed] {
+            continue;
+        }
+        visited[seed] = true;
+        let mut cluster = vec![seed];
+        let mut next = 0;
+        while next < cluster.len() {
+            for (neighbor, _) in kdtree.radius_search(&pcl.points[cluster[next]], tolerance) {
+                if !visited[neighbor] {
+                    visited[neighbor] = true;
+                    cluster.push(neighbor);
+                }
+            }
+            next += 1;
+        }
+        if size_range.contains(&cluster.len()) {
+            cluster.sort_unstable();
+            clusters.push(cluster);
+        }
+    }
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.len()));
+    Ok(clusters)
+}
+
+/// Density-based clustering (DBSCAN), which unlike [`euclidean_clusters`] separates
+/// touching objects of different densities and labels the sparse points as noise.
+///
+/// # Arguments
+///
+/// * pcl - The point cloud.
+/// * eps - Radius of the neighborhood of a point.
+/// * min_points - Number of points, itself included, within `eps` of a core point.
+///
+/// # Returns
+///
+/// The cluster label of each point, from 0 in the order of their first point, or
+/// [`NOISE`]. Error if `eps` isn't positive or `min_points` is zero.
+pub fn dbscan(pcl: &PointCloud, eps: f32, min_points: usize) -> Result<Array1<i32>, A3dError> {
+    if eps <= 0.0 {
+        return Err(A3dError::invalid_parameter(
+            "The DBSCAN radius must be positive.",
+        ));
+    }
+    if min_points == 0 {
+        return Err(A3dError::invalid_parameter(
+            "The DBSCAN minimum number of points must be positive.",
+        ));
+    }
+
+    let kdtree = R3dTree::new(&pcl.points.view());
+    let neighborhoods = (0..pcl.len())
+        .into_par_iter()
+        .map(|index| {
+            kdtree
+                .radius_search(&pcl.points[index], eps)
+                .into_iter()
+                .map(|(neighbor, _)| neighbor)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let is_core = |index: usize| neighborhoods[index].len() >= min_points;
+
+    let mut labels = Array1::from_elem(pcl.len(), NOISE);
+    let mut num_clusters = 0;
+    for seed in 0..pcl.len() {
+        if labels[seed] != NOISE || !is_core(seed) {
+            continue;
+        }
+        labels[seed] = num_clusters;
+        let mut frontier = vec![seed];
+        while let Some(core) = frontier.pop() {
+            for &neighbor in &neighborhoods[core] {
+                if labels[neighbor] != NOISE {
+                    continue;
+                }
+                // Border points join the first cluster reaching them, only core points
+                // expand it.
+                labels[neighbor] = num_clusters;
+                if is_core(neighbor) {
+                    frontier.push(neighbor);
+                }
+            }
+        }
+        num_clusters += 1;
+    }
+    Ok(labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Array1;
+
+    use super::{dbscan, euclidean_clusters, NOISE};
+    use crate::pointcloud::PointCloud;
+
+    /// A square grid of points.
+    fn grid(origin: Vector3<f32>, size: usize, spacing: f32) -> impl Iterator<Item = Vector3<f32>> {
+        (0..size * size).map(move |i| {
+            origin + Vector3::new((i % size) as f32, (i / size) as f32, 0.0) * spacing
+        })
+    }
+
+    #[test]
+    fn test_euclidean_clusters() {
+        // Two grids of 10x10 and 5x5 points 1 meter apart, plus an isolated point.
+        let grid = |origin, size| grid(origin, size, 0.01);
+        let points = grid(Vector3::zeros(), 5)
+            .chain(grid(Vector3::new(1.0, 0.0, 0.0), 10))
+            .chain([Vector3::new(0.0, 1.0, 0.0)])
+            .collect::<Array1<_>>();
+        let pcl = PointCloud {
+            points,
+            normals: None,
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+
+        let clusters = euclidean_clusters(&pcl, 0.015, 1..=usize::MAX).unwrap();
+        assert_eq!(clusters.len(), 3);
+        assert_eq!(clusters[0], (25..125).collect::<Vec<_>>());
+        assert_eq!(clusters[1], (0..25).collect::<Vec<_>>());
+        assert_eq!(clusters[2], vec![125]);
+
+        let clusters = euclidean_clusters(&pcl, 0.015, 2..=50).unwrap();
+        assert_eq!(clusters, vec![(0..25).collect::<Vec<_>>()]);
+
+        // The grid spacing is larger than the tolerance.
+        let clusters = euclidean_clusters(&pcl, 0.005, 2..=usize::MAX).unwrap();
+        assert!(clusters.is_empty());
+        assert!(euclidean_clusters(&pcl, 0.0, 1..=10).is_err());
+    }
+
+    #[test]
+    fn test_dbscan() {
+        // A dense grid touching a sparse one, and a few isolated points.
+        let points = grid(Vector3::zeros(), 10, 0.01)
+            .chain(grid(Vector3::new(0.1, 0.0, 0.0), 5, 0.05))
+            .chain([Vector3::new(0.0, 1.0, 0.0), Vector3::new(1.0, 1.0, 0.0)])
+            .collect::<Array1<_>>();
+        let pcl = PointCloud {
+            points,
+            normals: None,
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+
+        let labels = dbscan(&pcl, 0.015, 4).unwrap();
+        assert!(labels.iter().take(100).all(|label| *label == 0));
+        // The sparse grid is too sparse at this radius, only its three points next to the
+        // dense grid are border points.
+        assert_eq!(labels.iter().filter(|label| **label == 0).count(), 103);
+        assert_eq!(labels.iter().filter(|label| **label == NOISE).count(), 24);
+
+        let labels = dbscan(&pcl, 0.06, 4).unwrap();
+        assert!(labels.iter().take(125).all(|label| *label == 0));
+        assert_eq!(labels[125], NOISE);
+        assert_eq!(labels[126], NOISE);
+
+        assert!(dbscan(&pcl, 0.0, 4).is_err());
+        assert!(dbscan(&pcl, 0.1, 0).is_err());
+    }
+}
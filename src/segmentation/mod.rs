@@ -0,0 +1,13 @@
+//! Segmentation of point clouds into geometric primitives and objects.
+
+mod clustering;
+mod primitives;
+mod ransac;
+mod region_growing;
+
+pub use clustering::{dbscan, euclidean_clusters, NOISE};
+pub use primitives::{
+    segment_cylinder, segment_sphere, Cylinder, CylinderSegment, Sphere, SphereSegment,
+};
+pub use ransac::{segment_plane, segment_planes, PlaneSegment, RansacParams};
+pub use region_growing::{region_growing, RegionGrowingParams};
@@ -0,0 +1,348 @@
+use std::ops::RangeInclusive;
+
+use nalgebra::{Matrix2, Matrix3, Matrix4, Vector2, Vector3, Vector4};
+
+use super::ransac::{ransac, shape_inliers, validate, RansacParams};
+use crate::{error::A3dError, pointcloud::PointCloud};
+
+/// A sphere.
+#[derive(Debug, Clone, Copy)]
+pub struct Sphere {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl Sphere {
+    /// The sphere through 4 points, `None` if they are coplanar.
+    fn from_points([p0, p1, p2, p3]: [Vector3<f32>; 4]) -> Option<Self> {
+        // The center is equidistant to the points: 2 (p_i - p_0) · c = |p_i|² - |p_0|².
+        let system = Matrix3::from_rows(&[
+            (2.0 * (p1 - p0)).transpose(),
+            (2.0 * (p2 - p0)).transpose(),
+            (2.0 * (p3 - p0)).transpose(),
+        ]);
+        let rhs = Vector3::new(
+            p1.norm_squared() - p0.norm_squared(),
+            p2.norm_squared() - p0.norm_squared(),
+            p3.norm_squared() - p0.norm_squared(),
+        );
+        let center = system.lu().solve(&rhs)?;
+        Some(Self {
+            center,
+            radius: (p0 - center).norm(),
+        })
+    }
+
+    /// Algebraic least squares sphere of points, solving
+    /// `2 c · p + (r² - |c|²) = |p|²` for the center and radius.
+    fn fit(points: impl Iterator<Item = Vector3<f32>>) -> Option<Self> {
+        let (mut lhs, mut rhs) = (Matrix4::<f64>::zeros(), Vector4::<f64>::zeros());
+        for point in points {
+            let point = point.cast::<f64>();
+            let row = Vector4::new(2.0 * point.x, 2.0 * point.y, 2.0 * point.z, 1.0);
+            lhs += row * row.transpose();
+            rhs += row * point.norm_squared();
+        }
+        let solution = lhs.cholesky()?.solve(&rhs);
+        let center = Vector3::new(solution.x, solution.y, solution.z);
+        let sqr_radius = solution.w + center.norm_squared();
+        (sqr_radius > 0.0).then(|| Self {
+            center: center.cast(),
+            radius: sqr_radius.sqrt() as f32,
+        })
+    }
+
+    /// Distance of a point to the sphere surface.
+    pub fn distance(&self, point: &Vector3<f32>) -> f32 {
+        ((point - self.center).norm() - self.radius).abs()
+    }
+}
+
+/// An infinite cylinder.
+#[derive(Debug, Clone, Copy)]
+pub struct Cylinder {
+    /// A point on the axis.
+    pub axis_point: Vector3<f32>,
+    /// Unit direction of the axis.
+    pub axis: Vector3<f32>,
+    pub radius: f32,
+}
+
+/// A unit vector orthogonal to another one.
+fn orthogonal(vector: &Vector3<f32>) -> Vector3<f32> {
+    let other = if vector.x.abs() < 0.9 {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+    vector.cross(&other).normalize()
+}
+
+impl Cylinder {
+    /// The cylinder through 2 points with their normals, `None` if the normals are
+    /// parallel or their lines don't meet.
+    fn from_oriented_points(
+        [(p0, n0), (p1, n1)]: [(Vector3<f32>, Vector3<f32>); 2],
+    ) -> Option<Self> {
+        let axis = n0.cross(&n1).try_normalize(1e-6)?;
+        // The normals cross the axis, find where on the plane orthogonal to it.
+        let project = |point: Vector3<f32>| point - axis * axis.dot(&point);
+        let (q0, q1) = (project(p0), project(p1));
+        let (d0, d1) = (project(n0), project(n1));
+        let system = Matrix2::new(d0.dot(&d0), -d0.dot(&d1), d0.dot(&d1), -d1.dot(&d1));
+        let rhs = Vector2::new(d0.dot(&(q1 - q0)), d1.dot(&(q1 - q0)));
+        let steps = system.lu().solve(&rhs)?;
+        let axis_point = q0 + d0 * steps.x;
+        let radius = (q0 - axis_point).norm();
+        (radius > 0.0).then_some(Self {
+            axis_point,
+            axis,
+            radius,
+        })
+    }
+
+    /// Least squares cylinder of oriented points. The axis is the direction most
+    /// orthogonal to the normals, and the circle is fitted algebraically to the points
+    /// projected along it.
+    fn fit(points: &[(Vector3<f32>, Vector3<f32>)]) -> Option<Self> {
+        let normal_scatter = points.iter().fold(Matrix3::zeros(), |sum, (_, normal)| {
+            sum + normal * normal.transpose()
+        });
+        let eigen = normal_scatter.symmetric_eigen();
+        let axis = eigen
+            .eigenvectors
+            .column(eigen.eigenvalues.imin())
+            .into_owned();
+        let u = orthogonal(&axis);
+        let v = axis.cross(&u);
+
+        let (mut lhs, mut rhs) = (Matrix3::<f64>::zeros(), Vector3::<f64>::zeros());
+        for (point, _) in points {
+            let (x, y) = (u.dot(point) as f64, v.dot(point) as f64);
+            let row = Vector3::new(2.0 * x, 2.0 * y, 1.0);
+            lhs += row * row.transpose();
+            rhs += row * (x * x + y * y);
+        }
+        let solution = lhs.cholesky()?.solve(&rhs);
+        let sqr_radius = solution.z + solution.x * solution.x + solution.y * solution.y;
+        (sqr_radius > 0.0).then(|| Self {
+            axis_point: u * solution.x as f32 + v * solution.y as f32,
+            axis,
+            radius: sqr_radius.sqrt() as f32,
+        })
+    }
+
+    /// Distance of a point to the cylinder surface.
+    pub fn distance(&self, point: &Vector3<f32>) -> f32 {
+        let offset = point - self.axis_point;
+        ((offset - self.axis * self.axis.dot(&offset)).norm() - self.radius).abs()
+    }
+}
+
+/// A sphere found in a point cloud.
+#[derive(Debug, Clone)]
+pub struct SphereSegment {
+    /// The sphere, refitted to its inliers.
+    pub sphere: Sphere,
+    /// Indices of the points within the distance threshold, in increasing order.
+    pub inliers: Vec<usize>,
+}
+
+/// A cylinder found in a point cloud.
+#[derive(Debug, Clone)]
+pub struct CylinderSegment {
+    /// The cylinder, refitted to its inliers.
+    pub cylinder: Cylinder,
+    /// Indices of the points within the distance threshold, in increasing order.
+    pub inliers: Vec<usize>,
+}
+
+/// Finds the sphere with the most points by RANSAC, e.g., a calibration target, and
+/// refits it to its inliers by least squares.
+///
+/// # Arguments
+///
+/// * pcl - The point cloud.
+/// * params - Parameters of the RANSAC.
+/// * radius_range - Radii of the sampled spheres that are scored, e.g., around the
+///   known radius of a target.
+///
+/// # Returns
+///
+/// The sphere and its inliers, or error if the cloud has fewer than 4 points, the
+/// threshold isn't positive or no sampled sphere was within the radius range.
+pub fn segment_sphere(
+    pcl: &PointCloud,
+    params: &RansacParams,
+    radius_range: RangeInclusive<f32>,
+) -> Result<SphereSegment, A3dError> {
+    validate(pcl, params, 4)?;
+    let candidates = (0..pcl.len()).collect::<Vec<_>>();
+    let distance = |sphere: &Sphere, index: usize| sphere.distance(&pcl.points[index]);
+    let (sphere, inliers) = ransac(
+        &candidates,
+        params,
+        |sample: [usize; 4]| {
+            Sphere::from_points(sample.map(|index| pcl.points[index]))
+                .filter(|sphere| radius_range.contains(&sphere.radius))
+        },
+        distance,
+    )
+    .ok_or_else(|| A3dError::invalid_parameter("No valid sphere was sampled."))?;
+
+    let sphere = Sphere::fit(inliers.iter().map(|index| pcl.points[*index])).unwrap_or(sphere);
+    let inliers = shape_inliers(&sphere, &candidates, params, distance);
+    Ok(SphereSegment { sphere, inliers })
+}
+
+/// Finds the cylinder with the most points by RANSAC, e.g., a pipe in an industrial scan,
+/// and refits it to its inliers by least squares. Each hypothesis is sampled from 2 points
+/// and their normals.
+///
+/// # Arguments
+///
+/// * pcl - The point cloud, must have normals.
+/// * params - Parameters of the RANSAC.
+/// * radius_range - Radii of the sampled cylinders that are scored.
+///
+/// # Returns
+///
+/// The cylinder and its inliers, or error if the cloud has no normals or fewer than 2
+/// points, the threshold isn't positive or no sampled cylinder was within the radius
+/// range.
+pub fn segment_cylinder(
+    pcl: &PointCloud,
+    params: &RansacParams,
+    radius_range: RangeInclusive<f32>,
+) -> Result<CylinderSegment, A3dError> {
+    validate(pcl, params, 2)?;
+    let normals = pcl.normals.as_ref().ok_or_else(|| {
+        A3dError::invalid_parameter("Cylinder segmentation requires the point normals.")
+    })?;
+    let candidates = (0..pcl.len()).collect::<Vec<_>>();
+    let distance = |cylinder: &Cylinder, index: usize| cylinder.distance(&pcl.points[index]);
+    let (cylinder, inliers) = ransac(
+        &candidates,
+        params,
+        |sample: [usize; 2]| {
+            Cylinder::from_oriented_points(sample.map(|index| (pcl.points[index], normals[index])))
+                .filter(|cylinder| radius_range.contains(&cylinder.radius))
+        },
+        distance,
+    )
+    .ok_or_else(|| A3dError::invalid_parameter("No valid cylinder was sampled."))?;
+
+    let oriented_inliers = inliers
+        .iter()
+        .map(|index| (pcl.points[*index], normals[*index]))
+        .collect::<Vec<_>>();
+    let cylinder = Cylinder::fit(&oriented_inliers).unwrap_or(cylinder);
+    let inliers = shape_inliers(&cylinder, &candidates, params, distance);
+    Ok(CylinderSegment { cylinder, inliers })
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Array1;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{segment_cylinder, segment_sphere};
+    use crate::{pointcloud::PointCloud, segmentation::RansacParams};
+
+    /// Adds uniform clutter with random normals to oriented points.
+    fn with_clutter(mut points: Vec<(Vector3<f32>, Vector3<f32>)>) -> PointCloud {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..points.len() / 2 {
+            let point = Vector3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            let normal = Vector3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            points.push((point, normal.normalize()));
+        }
+        PointCloud {
+            points: points
+                .iter()
+                .map(|(point, _)| *point)
+                .collect::<Array1<_>>(),
+            normals: Some(points.iter().map(|(_, normal)| *normal).collect()),
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_segment_sphere() {
+        let center = Vector3::new(0.2, -0.1, 0.3);
+        let mut rng = StdRng::seed_from_u64(3);
+        let points = (0..1000)
+            .map(|_| {
+                let direction = Vector3::new(
+                    rng.gen_range(-1.0..1.0_f32),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .normalize();
+                let radius = 0.25 + rng.gen_range(-0.002..0.002);
+                (center + direction * radius, direction)
+            })
+            .collect();
+        let pcl = with_clutter(points);
+
+        let segment = segment_sphere(&pcl, &RansacParams::default(), 0.1..=0.5).unwrap();
+        assert!((segment.sphere.center - center).norm() < 2e-3);
+        assert!((segment.sphere.radius - 0.25).abs() < 2e-3);
+        assert!(segment.inliers.len() >= 1000);
+        assert!((0..1000).all(|index| segment.inliers.binary_search(&index).is_ok()));
+
+        // Only clutter is left outside of the radius range.
+        assert!(segment_sphere(&pcl, &RansacParams::default(), 2.0..=3.0)
+            .map_or(true, |segment| segment.inliers.len() < 100));
+        assert!(
+            segment_sphere(&pcl.select(&[0, 1, 2]), &RansacParams::default(), 0.1..=0.5).is_err()
+        );
+    }
+
+    #[test]
+    fn test_segment_cylinder() {
+        let axis = Vector3::new(1.0, 1.0, 0.5).normalize();
+        let u = axis.cross(&Vector3::z()).normalize();
+        let v = axis.cross(&u);
+        let axis_point = Vector3::new(0.1, 0.0, -0.2);
+        let mut rng = StdRng::seed_from_u64(3);
+        let points = (0..1000)
+            .map(|_| {
+                let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+                let normal = u * angle.cos() + v * angle.sin();
+                let radius = 0.15 + rng.gen_range(-0.002..0.002);
+                (
+                    axis_point + axis * rng.gen_range(-0.8..0.8) + normal * radius,
+                    normal,
+                )
+            })
+            .collect();
+        let pcl = with_clutter(points);
+
+        let segment = segment_cylinder(&pcl, &RansacParams::default(), 0.05..=0.5).unwrap();
+        let cylinder = segment.cylinder;
+        assert!(cylinder.axis.dot(&axis).abs() > 0.999);
+        assert!((cylinder.radius - 0.15).abs() < 2e-3);
+        let offset = cylinder.axis_point - axis_point;
+        assert!((offset - axis * axis.dot(&offset)).norm() < 2e-3);
+        assert!((0..1000).all(|index| segment.inliers.binary_search(&index).is_ok()));
+
+        let without_normals = PointCloud {
+            normals: None,
+            ..pcl
+        };
+        assert!(segment_cylinder(&without_normals, &RansacParams::default(), 0.05..=0.5).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use nalgebra::Vector3;
+use ndarray::Array1;
+use rand::Rng;
+
+use crate::{
+    error::A3dError,
+    pointcloud::{neighborhood_covariance, PointCloud},
+    random::RandomState,
+    slicing::Plane,
+};
+
+/// Parameters of the RANSAC shape fitting.
+#[derive(Debug, Clone, Copy)]
+pub struct RansacParams {
+    /// Maximum distance between an inlier and the shape.
+    pub distance_threshold: f32,
+    /// Maximum number of sampled hypotheses.
+    pub max_iterations: usize,
+    /// Probability of sampling an outlier free hypothesis, used to stop before
+    /// `max_iterations` once the inlier ratio is known.
+    pub confidence: f32,
+    /// Random state of the sampling, the fitting is deterministic for a state.
+    pub random_state: RandomState,
+}
+
+impl Default for RansacParams {
+    fn default() -> Self {
+        Self {
+            distance_threshold: 0.01,
+            max_iterations: 1000,
+            confidence: 0.999,
+            random_state: RandomState::default(),
+        }
+    }
+}
+
+/// A plane found in a point cloud.
+#[derive(Debug, Clone)]
+pub struct PlaneSegment {
+    /// The plane, refitted to its inliers.
+    pub plane: Plane,
+    /// Indices of the points within the distance threshold, in increasing order.
+    pub inliers: Vec<usize>,
+}
+
+/// The candidate points within the distance threshold of a shape.
+pub(super) fn shape_inliers<M>(
+    model: &M,
+    candidates: &[usize],
+    params: &RansacParams,
+    distance: impl Fn(&M, usize) -> f32,
+) -> Vec<usize> {
+    candidates
+        .iter()
+        .copied()
+        .filter(|index| distance(model, *index) <= params.distance_threshold)
+        .collect()
+}
+
+/// Fits a shape of `N` sample points to the candidate points by RANSAC.
+///
+/// # Arguments
+///
+/// * candidates - Indices of the points to fit.
+/// * params - Parameters of the RANSAC.
+/// * fit - The shape through the sampled points, `None` if they are degenerate.
+/// * distance - Distance between a shape and a point.
+///
+/// # Returns
+///
+/// The shape with the most inliers, `None` if no sample gave a shape.
+pub(super) fn ransac<M, const N: usize>(
+    candidates: &[usize],
+    params: &RansacParams,
+    fit: impl Fn([usize; N]) -> Option<M>,
+    distance: impl Fn(&M, usize) -> f32,
+) -> Option<(M, Vec<usize>)> {
+    if candidates.len() < N {
+        return None;
+    }
+    let inliers = |model: &M| shape_inliers(model, candidates, params, &distance);
+
+    let mut rng = params.random_state.rng();
+    let mut best: Option<(M, Vec<usize>)> = None;
+    let mut required_iterations = params.max_iterations;
+    let mut iteration = 0;
+    while iteration < required_iterations {
+        iteration += 1;
+        let sample = [(); N].map(|_| candidates[rng.gen_range(0..candidates.len())]);
+        let Some(model) = fit(sample) else {
+            continue;
+        };
+        let model_inliers = inliers(&model);
+        if best
+            .as_ref()
+            .is_none_or(|(_, best_inliers)| model_inliers.len() > best_inliers.len())
+        {
+            let inlier_ratio = model_inliers.len() as f32 / candidates.len() as f32;
+            let outlier_free = 1.0 - inlier_ratio.powi(N as i32);
+            if outlier_free <= 0.0 {
+                required_iterations = 0;
+            } else if outlier_free < 1.0 {
+                let needed = (1.0 - params.confidence).ln() / outlier_free.ln();
+                required_iterations = required_iterations.min(needed.ceil() as usize);
+            }
+            best = Some((model, model_inliers));
+        }
+    }
+    best
+}
+
+/// Least squares plane of points, through their centroid and normal to their direction of
+/// least variance.
+fn fit_plane(points: &Array1<Vector3<f32>>, indices: &[usize]) -> Option<Plane> {
+    let covariance = neighborhood_covariance(points, indices)?;
+    let centroid = indices
+        .iter()
+        .fold(Vector3::zeros(), |sum, index| sum + points[*index])
+        / indices.len() as f32;
+    let eigen = covariance.symmetric_eigen();
+    let normal = eigen
+        .eigenvectors
+        .column(eigen.eigenvalues.imin())
+        .into_owned();
+    Some(Plane::from_point_normal(&centroid, &normal))
+}
+
+fn segment_plane_among(
+    pcl: &PointCloud,
+    candidates: &[usize],
+    params: &RansacParams,
+) -> Option<PlaneSegment> {
+    let distance = |plane: &Plane, index: usize| plane.signed_distance(&pcl.points[index]).abs();
+    let (plane, inliers) = ransac(
+        candidates,
+        params,
+        |sample: [usize; 3]| {
+            let [p0, p1, p2] = sample.map(|index| pcl.points[index]);
+            let normal = (p1 - p0).cross(&(p2 - p0)).try_normalize(1e-12)?;
+            Some(Plane::from_point_normal(&p0, &normal))
+        },
+        distance,
+    )?;
+
+    // Refits to the inliers, which also smooths out the noise of the sample.
+    let plane = fit_plane(&pcl.points, &inliers).unwrap_or(plane);
+    let inliers = shape_inliers(&plane, candidates, params, distance);
+    Some(PlaneSegment { plane, inliers })
+}
+
+/// Checks the parameters of the segmentation of a shape of `sample_size` points.
+pub(super) fn validate(
+    pcl: &PointCloud,
+    params: &RansacParams,
+    sample_size: usize,
+) -> Result<(), A3dError> {
+    if params.distance_threshold <= 0.0 {
+        return Err(A3dError::invalid_parameter(
+            "The distance threshold must be positive.",
+        ));
+    }
+    if pcl.len() < sample_size {
+        return Err(A3dError::invalid_parameter(format!(
+            "The segmentation requires at least {sample_size} points."
+        )));
+    }
+    Ok(())
+}
+
+/// Finds the plane with the most points by RANSAC, e.g., the floor or a wall to remove
+/// before object-level registration.
+///
+/// # Arguments
+///
+/// * pcl - The point cloud.
+/// * params - Parameters of the RANSAC.
+///
+/// # Returns
+///
+/// The plane and its inliers, or error if the cloud has fewer than 3 points, the threshold
+/// isn't positive or all the samples were degenerate.
+pub fn segment_plane(pcl: &PointCloud, params: &RansacParams) -> Result<PlaneSegment, A3dError> {
+    validate(pcl, params, 3)?;
+    let candidates = (0..pcl.len()).collect::<Vec<_>>();
+    segment_plane_among(pcl, &candidates, params)
+        .ok_or_else(|| A3dError::invalid_parameter("No valid plane was sampled."))
+}
+
+/// Extracts planes one after the other, each one found by [`segment_plane`] among the
+/// points that aren't inliers of the previous ones.
+///
+/// # Arguments
+///
+/// * pcl - The point cloud.
+/// * params - Parameters of the RANSAC of each plane.
+/// * max_planes - Maximum number of planes.
+/// * min_inliers - Stops when the next plane has fewer inliers than this.
+///
+/// # Returns
+///
+/// The planes, the largest first, with disjoint inliers. Error if the threshold isn't
+/// positive.
+pub fn segment_planes(
+    pcl: &PointCloud,
+    params: &RansacParams,
+    max_planes: usize,
+    min_inliers: usize,
+) -> Result<Vec<PlaneSegment>, A3dError> {
+    validate(pcl, params, 3)?;
+    let mut remaining = (0..pcl.len()).collect::<Vec<_>>();
+    let mut planes = Vec::new();
+    while planes.len() < max_planes {
+        let Some(segment) = segment_plane_among(pcl, &remaining, params) else {
+            break;
+        };
+        if segment.inliers.len() < min_inliers.max(3) {
+            break;
+        }
+        // Both lists are sorted.
+        let mut inliers = segment.inliers.iter().peekable();
+        remaining.retain(|index| {
+            while inliers.next_if(|inlier| *inlier < index).is_some() {}
+            inliers.peek() != Some(&index)
+        });
+        planes.push(segment);
+    }
+    Ok(planes)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Array1;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{segment_plane, segment_planes, RansacParams};
+    use crate::pointcloud::PointCloud;
+
+    /// A floor at z = 0 with 2000 points, a wall at x = 1 with 1000 points and 500 points
+    /// of clutter.
+    fn sample_room() -> PointCloud {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut noise = move || rng.gen_range(-0.003..0.003);
+        let mut points = Vec::new();
+        for i in 0..2000 {
+            let (x, y) = ((i % 50) as f32 * 0.02, (i / 50) as f32 * 0.025);
+            points.push(Vector3::new(x, y, noise()));
+        }
+        for i in 0..1000 {
+            let (y, z) = ((i % 40) as f32 * 0.025, (i / 40) as f32 * 0.04 + 0.05);
+            points.push(Vector3::new(1.0 + noise(), y, z));
+        }
+        let mut rng = StdRng::seed_from_u64(4);
+        for _ in 0..500 {
+            points.push(Vector3::new(
+                rng.gen_range(0.1..0.9),
+                rng.gen_range(0.1..0.9),
+                rng.gen_range(0.1..0.9),
+            ));
+        }
+        PointCloud {
+            points: Array1::from_vec(points),
+            normals: None,
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_segment_plane() {
+        let room = sample_room();
+        let segment = segment_plane(&room, &RansacParams::default()).unwrap();
+        assert!(segment.plane.normal.z.abs() > 0.999);
+        assert!(segment.plane.offset.abs() < 2e-3);
+        assert!(segment.inliers.len() >= 2000);
+        assert!((0..2000).all(|index| segment.inliers.binary_search(&index).is_ok()));
+
+        let planes = segment_planes(&room, &RansacParams::default(), 5, 200).unwrap();
+        assert_eq!(planes.len(), 2);
+        assert!(planes[1].plane.normal.x.abs() > 0.999);
+        assert!((planes[1].plane.offset.abs() - 1.0).abs() < 2e-3);
+        assert!(planes[1]
+            .inliers
+            .iter()
+            .all(|index| planes[0].inliers.binary_search(index).is_err()));
+
+        assert!(segment_plane(
+            &room,
+            &RansacParams {
+                distance_threshold: 0.0,
+                ..Default::default()
+            }
+        )
+        .is_err());
+    }
+}
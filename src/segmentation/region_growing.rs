@@ -0,0 +1,170 @@
+use std::f32::consts::PI;
+
+use crate::{
+    error::A3dError,
+    kdtree::R3dTree,
+    pointcloud::{Neighborhood, PointCloud},
+};
+
+/// Parameters of the region growing segmentation.
+#[derive(Debug, Clone, Copy)]
+pub struct RegionGrowingParams {
+    /// Neighbors a region grows into from each of its points.
+    pub neighborhood: Neighborhood,
+    /// Maximum angle, in radians, between the normals of two neighbor points of a region.
+    pub max_angle: f32,
+    /// Only points with a lower curvature keep growing their region, so regions stop at
+    /// creases.
+    pub max_curvature: f32,
+    /// Minimum number of points of a region, smaller ones are dropped.
+    pub min_region_size: usize,
+    /// Maximum number of points of a region, larger ones are dropped.
+    pub max_region_size: usize,
+}
+
+impl Default for RegionGrowingParams {
+    fn default() -> Self {
+        Self {
+            neighborhood: Neighborhood::Knn(20),
+            max_angle: 5.0 * PI / 180.0,
+            max_curvature: 0.05,
+            min_region_size: 10,
+            max_region_size: usize::MAX,
+        }
+    }
+}
+
+/// Groups the points into smooth surface patches, growing each region from its flattest
+/// point to the neighbors with similar normals. Unlike [`super::segment_planes`], patches
+/// can be curved, as long as they bend smoothly.
+///
+/// # Arguments
+///
+/// * pcl - The point cloud, with normals and curvatures, see
+///   [`PointCloud::estimate_normals`] and [`PointCloud::estimate_curvatures`].
+/// * params - Parameters of the segmentation.
+///
+/// # Returns
+///
+/// The indices of the points of each region in increasing order, the largest region
+/// first. Error if the cloud has no normals or curvatures, or the neighborhood is invalid.
+pub fn region_growing(
+    pcl: &PointCloud,
+    params: &RegionGrowingParams,
+) -> Result<Vec<Vec<usize>>, A3dError> {
+    let (Some(normals), Some(curvatures)) = (pcl.normals.as_ref(), pcl.curvatures.as_ref()) else {
+        return Err(A3dError::invalid_parameter(
+            "Region growing requires normals and curvatures.",
+        ));
+    };
+    let valid_neighborhood = match params.neighborhood {
+        Neighborhood::Knn(k) => k > 1,
+        Neighborhood::Radius {
+            radius,
+            max_neighbors,
+        } => radius > 0.0 && max_neighbors > 1,
+    };
+    if !valid_neighborhood {
+        return Err(A3dError::invalid_parameter(
+            "The region growing neighborhood must have other points.",
+        ));
+    }
+
+    let kdtree = R3dTree::new(&pcl.points.view());
+    // Normals may be unoriented, so both directions are similar.
+    let min_cos_angle = params.max_angle.cos();
+
+    let mut seeds = (0..pcl.len()).collect::<Vec<_>>();
+    seeds.sort_by(|a, b| curvatures[*a].total_cmp(&curvatures[*b]));
+
+    let mut assigned = vec![false; pcl.len()];
+    let mut regions = Vec::new();
+    for seed in seeds {
+        if assigned[seed] {
+            continue;
+        }
+        assigned[seed] = true;
+        let mut region = vec![seed];
+        let mut frontier = vec![seed];
+        while let Some(current) = frontier.pop() {
+            for neighbor in params.neighborhood.search(&kdtree, &pcl.points[current]) {
+                if assigned[neighbor]
+                    || normals[current].dot(&normals[neighbor]).abs() < min_cos_angle
+                {
+                    continue;
+                }
+                assigned[neighbor] = true;
+                region.push(neighbor);
+                if curvatures[neighbor] < params.max_curvature {
+                    frontier.push(neighbor);
+                }
+            }
+        }
+        if (params.min_region_size..=params.max_region_size).contains(&region.len()) {
+            region.sort_unstable();
+            regions.push(region);
+        }
+    }
+    regions.sort_by_key(|region| std::cmp::Reverse(region.len()));
+    Ok(regions)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Array1;
+
+    use super::{region_growing, RegionGrowingParams};
+    use crate::pointcloud::{Neighborhood, PointCloud};
+
+    #[test]
+    fn test_region_growing() {
+        // Two planes meeting at a right angle along the Y axis, and a half cylinder next
+        // to them.
+        let planes = (0..40).flat_map(|i| {
+            (0..40).map(move |j| {
+                let (u, y) = (i as f32 * 0.05 - 1.0, j as f32 * 0.05);
+                Vector3::new(u.min(0.0), y, u.max(0.0))
+            })
+        });
+        let cylinder = (0..60).flat_map(|i| {
+            (0..40).map(move |j| {
+                let angle = i as f32 * std::f32::consts::PI / 59.0;
+                Vector3::new(0.5 * angle.cos(), j as f32 * 0.05, -2.0 + 0.5 * angle.sin())
+            })
+        });
+        let mut pcl = PointCloud {
+            points: planes.chain(cylinder).collect::<Array1<_>>(),
+            normals: None,
+            colors: None,
+            intensities: None,
+            curvatures: None,
+            attributes: Default::default(),
+        };
+        assert!(region_growing(&pcl, &RegionGrowingParams::default()).is_err());
+
+        pcl.estimate_normals(Neighborhood::Knn(10))
+            .unwrap()
+            .estimate_curvatures(Neighborhood::Knn(10))
+            .unwrap();
+        let params = RegionGrowingParams {
+            neighborhood: Neighborhood::Knn(10),
+            max_angle: 10f32.to_radians(),
+            min_region_size: 100,
+            ..Default::default()
+        };
+        let regions = region_growing(&pcl, &params).unwrap();
+        assert_eq!(regions.len(), 3);
+
+        let is_cylinder = |index: &usize| *index >= 1600;
+        assert!(regions[0].iter().all(is_cylinder));
+        assert!(regions[0].len() > 2000);
+        for plane in &regions[1..] {
+            assert!(plane.len() > 700 && plane.len() <= 800);
+            let side = pcl.points[plane[0]].x < 0.0;
+            assert!(plane
+                .iter()
+                .all(|index| !is_cylinder(index) && (pcl.points[*index].x < 0.0) == side));
+        }
+    }
+}
@@ -0,0 +1,367 @@
+//! Serde support, enabled by the `serde` feature. Arrays of vectors are stored as flat
+//! scalar sequences, so binary formats write them without per-element overhead.
+
+use std::collections::BTreeMap;
+
+use nalgebra::{Isometry3, Quaternion, Scalar, Translation3, UnitQuaternion, Vector2, Vector3};
+use ndarray::{Array1, Array2};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    camera::{CameraIntrinsics, PinholeCamera},
+    io::Geometry,
+    metadata::Metadata,
+    pointcloud::{AttributeChannel, PointCloud},
+    transform::Transform,
+};
+
+fn flatten3<T: Scalar + Copy>(values: &Array1<Vector3<T>>) -> Vec<T> {
+    values
+        .iter()
+        .flat_map(|value| [value[0], value[1], value[2]])
+        .collect()
+}
+
+fn unflatten3<T: Scalar + Copy, E: Error>(values: Vec<T>) -> Result<Array1<Vector3<T>>, E> {
+    if !values.len().is_multiple_of(3) {
+        return Err(E::custom("vector array length is not a multiple of 3"));
+    }
+    Ok(values
+        .chunks_exact(3)
+        .map(|chunk| Vector3::new(chunk[0], chunk[1], chunk[2]))
+        .collect())
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+struct PointCloudRepr {
+    points: Vec<f32>,
+    normals: Option<Vec<f32>>,
+    colors: Option<Vec<u8>>,
+    #[serde(default)]
+    intensities: Option<Vec<f32>>,
+    #[serde(default)]
+    curvatures: Option<Vec<f32>>,
+    #[serde(default)]
+    attributes: BTreeMap<String, AttributeChannelRepr>,
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+enum AttributeChannelRepr {
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+    U32(Vec<u32>),
+    I32(Vec<i32>),
+}
+
+impl From<&AttributeChannel> for AttributeChannelRepr {
+    fn from(channel: &AttributeChannel) -> Self {
+        match channel {
+            AttributeChannel::F32(values) => AttributeChannelRepr::F32(values.to_vec()),
+            AttributeChannel::F64(values) => AttributeChannelRepr::F64(values.to_vec()),
+            AttributeChannel::U32(values) => AttributeChannelRepr::U32(values.to_vec()),
+            AttributeChannel::I32(values) => AttributeChannelRepr::I32(values.to_vec()),
+        }
+    }
+}
+
+impl From<AttributeChannelRepr> for AttributeChannel {
+    fn from(repr: AttributeChannelRepr) -> Self {
+        match repr {
+            AttributeChannelRepr::F32(values) => AttributeChannel::F32(values.into()),
+            AttributeChannelRepr::F64(values) => AttributeChannel::F64(values.into()),
+            AttributeChannelRepr::U32(values) => AttributeChannel::U32(values.into()),
+            AttributeChannelRepr::I32(values) => AttributeChannel::I32(values.into()),
+        }
+    }
+}
+
+impl Serialize for PointCloud {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PointCloudRepr {
+            points: flatten3(&self.points),
+            normals: self.normals.as_ref().map(flatten3),
+            colors: self.colors.as_ref().map(flatten3),
+            intensities: self.intensities.as_ref().map(|i| i.to_vec()),
+            curvatures: self.curvatures.as_ref().map(|c| c.to_vec()),
+            attributes: self
+                .attributes
+                .iter()
+                .map(|(name, channel)| (name.clone(), channel.into()))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PointCloud {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = PointCloudRepr::deserialize(deserializer)?;
+        let cloud = PointCloud {
+            points: unflatten3(repr.points)?,
+            normals: repr.normals.map(unflatten3).transpose()?,
+            colors: repr.colors.map(unflatten3).transpose()?,
+            intensities: repr.intensities.map(Array1::from_vec),
+            curvatures: repr.curvatures.map(Array1::from_vec),
+            attributes: repr
+                .attributes
+                .into_iter()
+                .map(|(name, channel)| (name, channel.into()))
+                .collect(),
+        };
+        if cloud
+            .normals
+            .as_ref()
+            .is_some_and(|n| n.len() != cloud.len())
+            || cloud
+                .colors
+                .as_ref()
+                .is_some_and(|c| c.len() != cloud.len())
+            || cloud
+                .intensities
+                .as_ref()
+                .is_some_and(|i| i.len() != cloud.len())
+            || cloud
+                .curvatures
+                .as_ref()
+                .is_some_and(|c| c.len() != cloud.len())
+            || cloud
+                .attributes
+                .values()
+                .any(|channel| channel.len() != cloud.len())
+        {
+            return Err(D::Error::custom("point attributes have different lengths"));
+        }
+        Ok(cloud)
+    }
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+struct GeometryRepr {
+    points: Vec<f32>,
+    colors: Option<Vec<u8>>,
+    normals: Option<Vec<f32>>,
+    faces: Option<Vec<usize>>,
+    texcoords: Option<Vec<f32>>,
+    metadata: Option<Metadata>,
+}
+
+impl Serialize for Geometry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        GeometryRepr {
+            points: flatten3(&self.points),
+            colors: self.colors.as_ref().map(flatten3),
+            normals: self.normals.as_ref().map(flatten3),
+            faces: self
+                .faces
+                .as_ref()
+                .map(|faces| faces.iter().copied().collect()),
+            texcoords: self.texcoords.as_ref().map(|texcoords| {
+                texcoords
+                    .iter()
+                    .flat_map(|texcoord| [texcoord[0], texcoord[1]])
+                    .collect()
+            }),
+            metadata: self.metadata.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Geometry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = GeometryRepr::deserialize(deserializer)?;
+        let faces = repr
+            .faces
+            .map(|faces| {
+                let num_faces = faces.len() / 3;
+                Array2::from_shape_vec((num_faces, 3), faces)
+                    .map_err(|_| D::Error::custom("face array length is not a multiple of 3"))
+            })
+            .transpose()?;
+        let texcoords = repr
+            .texcoords
+            .map(|texcoords| {
+                if !texcoords.len().is_multiple_of(2) {
+                    return Err(D::Error::custom(
+                        "texcoord array length is not a multiple of 2",
+                    ));
+                }
+                Ok(texcoords
+                    .chunks_exact(2)
+                    .map(|chunk| Vector2::new(chunk[0], chunk[1]))
+                    .collect())
+            })
+            .transpose()?;
+
+        let geometry = Geometry {
+            points: unflatten3(repr.points)?,
+            colors: repr.colors.map(unflatten3).transpose()?,
+            normals: repr.normals.map(unflatten3).transpose()?,
+            faces,
+            texcoords,
+            metadata: repr.metadata,
+        };
+        let num_vertices = geometry.len_vertices();
+        if geometry
+            .colors
+            .as_ref()
+            .is_some_and(|c| c.len() != num_vertices)
+            || geometry
+                .normals
+                .as_ref()
+                .is_some_and(|n| n.len() != num_vertices)
+            || geometry
+                .texcoords
+                .as_ref()
+                .is_some_and(|t| t.len() != num_vertices)
+        {
+            return Err(D::Error::custom("vertex attributes have different lengths"));
+        }
+        if geometry
+            .faces
+            .as_ref()
+            .is_some_and(|faces| faces.iter().any(|index| *index >= num_vertices))
+        {
+            return Err(D::Error::custom("face index out of range"));
+        }
+        Ok(geometry)
+    }
+}
+
+/// Deviation of the norm of a deserialized rotation quaternion from 1 that is accepted.
+const UNIT_QUATERNION_TOLERANCE: f32 = 1e-4;
+
+/// Stored as translation (x, y, z) followed by the rotation quaternion (i, j, k, w).
+impl Serialize for Transform {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let translation = &self.0.translation.vector;
+        let rotation = self.0.rotation.quaternion();
+        [
+            translation[0],
+            translation[1],
+            translation[2],
+            rotation.i,
+            rotation.j,
+            rotation.k,
+            rotation.w,
+        ]
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Transform {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let [x, y, z, i, j, k, w] = <[f32; 7]>::deserialize(deserializer)?;
+        let rotation = Quaternion::new(w, i, j, k);
+        // Only accepts normalized quaternions, which are kept bitwise.
+        let norm_error = (rotation.norm() - 1.0).abs();
+        if norm_error.is_nan() || norm_error > UNIT_QUATERNION_TOLERANCE {
+            return Err(D::Error::custom("rotation quaternion is not normalized"));
+        }
+        Ok(Transform(Isometry3::from_parts(
+            Translation3::new(x, y, z),
+            UnitQuaternion::new_unchecked(rotation),
+        )))
+    }
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+struct PinholeCameraRepr {
+    intrinsics: CameraIntrinsics,
+    camera_to_world: Transform,
+}
+
+impl Serialize for PinholeCamera {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PinholeCameraRepr {
+            intrinsics: self.intrinsics.clone(),
+            camera_to_world: self.camera_to_world.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PinholeCamera {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = PinholeCameraRepr::deserialize(deserializer)?;
+        Ok(PinholeCamera::new(repr.intrinsics, repr.camera_to_world))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Quaternion, Vector3};
+    use ndarray::Array1;
+    use rstest::rstest;
+
+    use crate::{
+        camera::{CameraIntrinsics, PinholeCamera},
+        io::{read_off, Geometry},
+        metadata::Metadata,
+        pointcloud::PointCloud,
+        transform::Transform,
+        unit_test::sample_teapot_pointcloud,
+    };
+
+    #[rstest]
+    fn test_point_cloud_roundtrip(mut sample_teapot_pointcloud: PointCloud) {
+        let labels = Array1::from_shape_fn(sample_teapot_pointcloud.len(), |i| i as u32 % 3);
+        sample_teapot_pointcloud
+            .set_attribute("label", labels)
+            .unwrap();
+        let json = serde_json::to_string(&sample_teapot_pointcloud).unwrap();
+        let cloud: PointCloud = serde_json::from_str(&json).unwrap();
+        assert_eq!(cloud.points, sample_teapot_pointcloud.points);
+        assert_eq!(cloud.normals, sample_teapot_pointcloud.normals);
+        assert_eq!(cloud.colors, sample_teapot_pointcloud.colors);
+        assert_eq!(cloud.attributes, sample_teapot_pointcloud.attributes);
+
+        assert!(serde_json::from_str::<PointCloud>(
+            r#"{"points": [1.0, 2.0], "normals": null, "colors": null}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_geometry_roundtrip() {
+        let mut teapot = read_off("tests/data/teapot.off").unwrap();
+        teapot.metadata = Some(Metadata::from_frame("teapot", 2, Some(1.5)));
+
+        let json = serde_json::to_string(&teapot).unwrap();
+        let geometry: Geometry = serde_json::from_str(&json).unwrap();
+        assert_eq!(geometry.points, teapot.points);
+        assert_eq!(geometry.faces, teapot.faces);
+        assert_eq!(geometry.metadata, teapot.metadata);
+
+        assert!(serde_json::from_str::<Geometry>(
+            r#"{"points": [0.0, 0.0, 0.0, 1.0, 0.0, 0.0], "colors": [255, 0, 0],
+                "normals": null, "faces": null, "texcoords": null, "metadata": null}"#
+        )
+        .is_err());
+        assert!(serde_json::from_str::<Geometry>(
+            r#"{"points": [0.0, 0.0, 0.0, 1.0, 0.0, 0.0], "colors": null, "normals": null,
+                "faces": [0, 1, 2], "texcoords": null, "metadata": null}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_camera_roundtrip() {
+        let camera = PinholeCamera::new(
+            CameraIntrinsics::from_simple_intrinsic(525.0, 525.0, 319.5, 239.5, 640, 480),
+            Transform::new(
+                &Vector3::new(1.0, -2.0, 0.5),
+                &Quaternion::new(0.9, 0.1, -0.3, 0.2),
+            ),
+        );
+
+        let json = serde_json::to_string(&camera).unwrap();
+        let actual: PinholeCamera = serde_json::from_str(&json).unwrap();
+        assert_eq!(actual.intrinsics.fx, camera.intrinsics.fx);
+        assert_eq!(actual.intrinsics.height, camera.intrinsics.height);
+        assert_eq!(actual.camera_to_world.0, camera.camera_to_world.0);
+
+        assert!(serde_json::from_str::<Transform>("[0, 0, 0, 0, 0, 0, 2]").is_err());
+        assert!(serde_json::from_str::<Transform>("[1, 2, 3, 0, 0, 0, 1]").is_ok());
+    }
+}
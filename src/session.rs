@@ -0,0 +1,415 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use nalgebra::{Isometry3, Quaternion, Translation3, UnitQuaternion};
+use rand::rngs::StdRng;
+
+use crate::{
+    camera::CameraIntrinsics,
+    error::A3dError,
+    odometry::{OdometryPipeline, OdometryState},
+    random::RandomState,
+    range_image::{read_pyramid, write_pyramid},
+    reconstruction::VoxelFusion,
+    trajectory::{Trajectory, TrajectoryBuilder},
+    transform::Transform,
+};
+
+const SESSION_FILENAME: &str = "session.json";
+const LAST_FRAME_PREFIX: &str = "last_frame_";
+const MODEL_PREFIX: &str = "model_";
+
+mod json {
+    use std::collections::BTreeMap;
+
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Pose {
+        pub time: f32,
+        pub translation: [f32; 3],
+        /// Quaternion in the `[i, j, k, w]` order.
+        pub rotation: [f32; 4],
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Camera {
+        pub fx: f64,
+        pub fy: f64,
+        pub cx: f64,
+        pub cy: f64,
+        pub width: usize,
+        pub height: usize,
+    }
+
+    /// The arrays are stored in `.npz` archives next to the document.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Odometry {
+        pub last_frame: Option<String>,
+        pub last_frame_camera: Option<Camera>,
+        pub model: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Document {
+        pub software_version: String,
+        pub next_frame: usize,
+        pub seed: u64,
+        pub keyframes: Vec<usize>,
+        pub parameters: BTreeMap<String, String>,
+        #[serde(default)]
+        pub time_origin: f64,
+        pub trajectory: Vec<Pose>,
+        #[serde(default)]
+        pub odometry: Option<Odometry>,
+    }
+}
+
+/// State of a reconstruction run over a dataset. Save it periodically with
+/// [`Session::save`], so a run that crashes or is stopped can continue from the
+/// last checkpoint with [`Session::load`] and produce the same result. The state of an
+/// [`OdometryPipeline`], its last frame and fused model, is included with
+/// [`Session::capture`] and restored with [`Session::resume`].
+///
+/// ```no_run
+/// use align3d::{odometry::OdometryPipeline, session::Session};
+///
+/// let mut odometry = OdometryPipeline::builder().build().unwrap();
+/// let session = Session::load("checkpoint").unwrap();
+/// session.resume(&mut odometry).unwrap();
+/// // Continue processing the dataset from `session.next_frame`.
+/// ```
+///
+/// Random number generation should use [`Session::frame_rng`], which depends only on the
+/// seed and the frame index, to be reproducible after resuming.
+#[derive(Clone, Debug)]
+pub struct Session {
+    /// Camera poses estimated so far.
+    pub trajectory: Trajectory,
+    /// Indices of the frames selected as keyframes.
+    pub keyframes: Vec<usize>,
+    /// Index of the next frame to process in the dataset.
+    pub next_frame: usize,
+    /// Seed of the random number generators.
+    pub seed: u64,
+    /// Processing parameters as key-value pairs, used to check that the session is
+    /// resumed with the same configuration.
+    pub parameters: BTreeMap<String, String>,
+    /// State of the odometry pipeline, set by [`Session::capture`].
+    pub(crate) odometry: Option<OdometryState>,
+}
+
+impl Session {
+    /// Creates an empty session starting at the first frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Seed of the random number generators.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            trajectory: Trajectory::default(),
+            keyframes: Vec::new(),
+            next_frame: 0,
+            seed,
+            parameters: BTreeMap::new(),
+            odometry: None,
+        }
+    }
+
+    /// Records the trajectory and the state of an odometry pipeline into the session.
+    /// Set `next_frame` to the index after the last processed frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `odometry` - The pipeline.
+    pub fn capture(&mut self, odometry: &OdometryPipeline) {
+        self.trajectory = odometry.trajectory().clone();
+        self.odometry = Some(odometry.state());
+    }
+
+    /// Restores the captured trajectory and state into a pipeline, which continues
+    /// tracking as if it had processed the frames before `next_frame`. The pipeline's
+    /// custom stage is not part of the state.
+    ///
+    /// # Arguments
+    ///
+    /// * `odometry` - A pipeline built with the same parameters as the captured one.
+    ///
+    /// # Returns
+    ///
+    /// Error if no pipeline was captured or the parameters differ.
+    pub fn resume(&self, odometry: &mut OdometryPipeline) -> Result<(), A3dError> {
+        let state = self.odometry.clone().ok_or_else(|| {
+            A3dError::invalid_parameter("The session has no captured odometry state.")
+        })?;
+        odometry.restore(self.trajectory.clone(), state)
+    }
+
+    /// Random number generator for processing a given frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Index of the frame in the dataset.
+    pub fn frame_rng(&self, frame: usize) -> StdRng {
+        RandomState::new(self.seed).stream(frame as u64)
+    }
+
+    /// Creates a trajectory builder that continues the session's trajectory.
+    pub fn trajectory_builder(&self) -> TrajectoryBuilder {
+        TrajectoryBuilder::from_trajectory(self.trajectory.clone())
+    }
+
+    /// Saves the session into a directory, which is created if needed.
+    /// The previous checkpoint is only replaced once the new one is fully written, its
+    /// odometry archives are removed afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `dirpath` - Path to the checkpoint directory.
+    pub fn save<P: AsRef<Path>>(&self, dirpath: P) -> Result<(), A3dError> {
+        let dirpath = dirpath.as_ref();
+        std::fs::create_dir_all(dirpath)?;
+
+        // Archive names are unique to the checkpoint, so the ones of the previous
+        // checkpoint are kept until the new document replaces it.
+        let write_archive = |prefix: &str, write: &dyn Fn(&Path) -> std::io::Result<()>| {
+            let filename = format!("{prefix}{:06}.npz", self.next_frame);
+            let temp_filepath = dirpath.join(format!("{filename}.tmp"));
+            write(&temp_filepath)?;
+            std::fs::rename(temp_filepath, dirpath.join(&filename))?;
+            Ok::<_, std::io::Error>(filename)
+        };
+        let odometry = match &self.odometry {
+            Some(state) => {
+                let last_frame = state
+                    .last_frame
+                    .as_ref()
+                    .map(|pyramid| {
+                        write_archive(LAST_FRAME_PREFIX, &|path| write_pyramid(path, pyramid))
+                    })
+                    .transpose()?;
+                let model = state
+                    .model
+                    .as_ref()
+                    .map(|model| write_archive(MODEL_PREFIX, &|path| model.save(path)))
+                    .transpose()?;
+                let last_frame_camera = state.last_frame.as_ref().map(|pyramid| {
+                    let camera = &pyramid[0].intrinsics;
+                    json::Camera {
+                        fx: camera.fx,
+                        fy: camera.fy,
+                        cx: camera.cx,
+                        cy: camera.cy,
+                        width: camera.width,
+                        height: camera.height,
+                    }
+                });
+                Some(json::Odometry {
+                    last_frame,
+                    last_frame_camera,
+                    model,
+                })
+            }
+            None => None,
+        };
+
+        let document = json::Document {
+            software_version: env!("CARGO_PKG_VERSION").to_string(),
+            next_frame: self.next_frame,
+            seed: self.seed,
+            keyframes: self.keyframes.clone(),
+            parameters: self.parameters.clone(),
+            time_origin: self.trajectory.time_origin,
+            trajectory: self
+                .trajectory
+                .iter()
+                .map(|(camera_to_world, time)| {
+                    let translation = camera_to_world.0.translation;
+                    let rotation = camera_to_world.0.rotation;
+                    json::Pose {
+                        time,
+                        translation: [translation.x, translation.y, translation.z],
+                        rotation: [rotation.i, rotation.j, rotation.k, rotation.w],
+                    }
+                })
+                .collect(),
+            odometry,
+        };
+
+        let temp_filepath = dirpath.join(format!("{SESSION_FILENAME}.tmp"));
+        serde_json::to_writer(BufWriter::new(File::create(&temp_filepath)?), &document)
+            .map_err(|err| A3dError::Parser(err.to_string()))?;
+        std::fs::rename(temp_filepath, dirpath.join(SESSION_FILENAME))?;
+
+        let current = document
+            .odometry
+            .iter()
+            .flat_map(|odometry| [&odometry.last_frame, &odometry.model])
+            .flatten()
+            .collect::<Vec<_>>();
+        for entry in std::fs::read_dir(dirpath)? {
+            let filename = entry?.file_name().to_string_lossy().to_string();
+            if (filename.starts_with(LAST_FRAME_PREFIX) || filename.starts_with(MODEL_PREFIX))
+                && !current.contains(&&filename)
+            {
+                std::fs::remove_file(dirpath.join(filename))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Loads a session saved with [`Session::save`].
+    ///
+    /// # Arguments
+    ///
+    /// * `dirpath` - Path to the checkpoint directory.
+    ///
+    /// # Returns
+    ///
+    /// The session as it was when saved.
+    pub fn load<P: AsRef<Path>>(dirpath: P) -> Result<Self, A3dError> {
+        let dirpath = dirpath.as_ref();
+        let reader = BufReader::new(File::open(dirpath.join(SESSION_FILENAME))?);
+        let document: json::Document =
+            serde_json::from_reader(reader).map_err(|err| A3dError::Parser(err.to_string()))?;
+
+        // The rotation is stored already normalized, building it unchecked keeps the
+        // poses bitwise identical to the saved ones.
+        let mut trajectory = document
+            .trajectory
+            .iter()
+            .map(|pose| {
+                let [x, y, z] = pose.translation;
+                let [i, j, k, w] = pose.rotation;
+                (
+                    Transform(Isometry3::from_parts(
+                        Translation3::new(x, y, z),
+                        UnitQuaternion::new_unchecked(Quaternion::new(w, i, j, k)),
+                    )),
+                    pose.time,
+                )
+            })
+            .collect::<Trajectory>();
+        trajectory.time_origin = document.time_origin;
+
+        let odometry = match document.odometry {
+            Some(odometry) => {
+                let last_frame = match (odometry.last_frame, odometry.last_frame_camera) {
+                    (Some(filename), Some(camera)) => {
+                        let camera = CameraIntrinsics::from_simple_intrinsic(
+                            camera.fx,
+                            camera.fy,
+                            camera.cx,
+                            camera.cy,
+                            camera.width,
+                            camera.height,
+                        );
+                        // Intensities are computed by the pipeline when resuming.
+                        Some(
+                            read_pyramid(&dirpath.join(&filename), &camera, false).ok_or_else(
+                                || A3dError::Parser(format!("Invalid last frame file {filename}")),
+                            )?,
+                        )
+                    }
+                    (None, None) => None,
+                    _ => {
+                        return Err(A3dError::Parser(
+                            "The last frame needs both its file and camera".to_string(),
+                        ))
+                    }
+                };
+                let model = odometry
+                    .model
+                    .map(|filename| VoxelFusion::load(&dirpath.join(filename)))
+                    .transpose()?;
+                Some(OdometryState { last_frame, model })
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            trajectory,
+            keyframes: document.keyframes,
+            next_frame: document.next_frame,
+            seed: document.seed,
+            parameters: document.parameters,
+            odometry,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector6;
+    use rand::Rng;
+
+    use super::Session;
+    use crate::{
+        odometry::OdometryPipeline,
+        transform::{LieGroup, Transform},
+    };
+
+    #[test]
+    fn test_save_and_resume() {
+        let mut session = Session::new(42);
+        session
+            .parameters
+            .insert("icp.max_iterations".to_string(), "15".to_string());
+
+        let mut builder = session.trajectory_builder();
+        for i in 1..4 {
+            builder.accumulate(
+                &Transform::exp(&LieGroup::Se3(Vector6::new(
+                    0.1,
+                    0.02 * i as f32,
+                    0.0,
+                    0.01,
+                    0.03,
+                    0.02 * i as f32,
+                ))),
+                Some(i as f32),
+            );
+        }
+        session.trajectory = builder.build();
+        session.trajectory.time_origin = 1305031102.175304;
+        session.keyframes = vec![0, 2];
+        session.next_frame = 4;
+        session.save("tests/outputs/session-checkpoint").unwrap();
+
+        let resumed = Session::load("tests/outputs/session-checkpoint").unwrap();
+        assert_eq!(resumed.next_frame, 4);
+        assert_eq!(resumed.keyframes, session.keyframes);
+        assert_eq!(resumed.parameters, session.parameters);
+        assert_eq!(resumed.trajectory.times, session.trajectory.times);
+        assert_eq!(
+            resumed.trajectory.time_origin,
+            session.trajectory.time_origin
+        );
+        for (resumed_pose, pose) in resumed
+            .trajectory
+            .camera_to_world
+            .iter()
+            .zip(session.trajectory.camera_to_world.iter())
+        {
+            assert_eq!(resumed_pose.0, pose.0);
+        }
+
+        assert_eq!(
+            resumed.frame_rng(4).gen::<u64>(),
+            session.frame_rng(4).gen::<u64>()
+        );
+        assert_ne!(
+            session.frame_rng(4).gen::<u64>(),
+            session.frame_rng(5).gen::<u64>()
+        );
+
+        // No pipeline was captured.
+        let mut odometry = OdometryPipeline::builder().build().unwrap();
+        assert!(resumed.resume(&mut odometry).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use nalgebra::Vector3;
+use ndarray::{Array1, ArrayView2, Axis};
+
+use crate::{edit::extract_selection, io::Geometry};
+
+/// A plane with unit normal `normal`, made of the points `x` with `normal.dot(x) == offset`.
+#[derive(Debug, Clone, Copy)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub offset: f32,
+}
+
+impl Plane {
+    /// Creates the plane that passes through `point` with the given normal.
+    pub fn from_point_normal(point: &Vector3<f32>, normal: &Vector3<f32>) -> Self {
+        let normal = normal.normalize();
+        Self {
+            normal,
+            offset: normal.dot(point),
+        }
+    }
+
+    /// Signed distance of a point to the plane, positive on the normal side.
+    pub fn signed_distance(&self, point: &Vector3<f32>) -> f32 {
+        self.normal.dot(point) - self.offset
+    }
+
+    /// Moves the plane along its normal, e.g., to sweep it through a reconstruction.
+    pub fn translated(&self, distance: f32) -> Self {
+        Self {
+            normal: self.normal,
+            offset: self.offset + distance,
+        }
+    }
+}
+
+/// Indices of the points within a slab around the plane.
+///
+/// # Arguments
+///
+/// * `points` - The points.
+/// * `plane` - The slicing plane.
+/// * `thickness` - Total thickness of the slab, centered on the plane.
+pub fn slab_indices(points: &Array1<Vector3<f32>>, plane: &Plane, thickness: f32) -> Vec<usize> {
+    let half_thickness = thickness * 0.5;
+    points
+        .iter()
+        .enumerate()
+        .filter(|(_, point)| plane.signed_distance(point).abs() <= half_thickness)
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Extracts the part of a geometry within a slab around the plane.
+/// See [`slab_indices`].
+pub fn slice_geometry(geometry: &Geometry, plane: &Plane, thickness: f32) -> Geometry {
+    extract_selection(geometry, &slab_indices(&geometry.points, plane, thickness))
+}
+
+/// Clips a geometry with the plane, keeping the part behind it, e.g., to look inside a
+/// reconstruction. Faces crossing the plane are removed.
+pub fn clip_geometry(geometry: &Geometry, plane: &Plane) -> Geometry {
+    let behind = geometry
+        .points
+        .iter()
+        .enumerate()
+        .filter(|(_, point)| plane.signed_distance(point) <= 0.0)
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    extract_selection(geometry, &behind)
+}
+
+/// Intersects a triangle mesh with a plane, producing a 2D profile of the mesh.
+///
+/// # Arguments
+///
+/// * `points` - The mesh vertices.
+/// * `faces` - The triangles, shape is (Nx3).
+/// * `plane` - The slicing plane.
+///
+/// # Returns
+///
+/// * The intersection polylines. Closed loops repeat their first point at the end.
+pub fn intersect_mesh(
+    points: &Array1<Vector3<f32>>,
+    faces: &ArrayView2<usize>,
+    plane: &Plane,
+) -> Vec<Vec<Vector3<f32>>> {
+    let distances = points
+        .iter()
+        .map(|point| plane.signed_distance(point))
+        .collect::<Vec<_>>();
+
+    // Each segment connects two crossed mesh edges, identified by their sorted vertices.
+    let mut crossings = HashMap::new();
+    let mut segments = Vec::new();
+    for face in faces.axis_iter(Axis(0)) {
+        let mut ends = Vec::with_capacity(2);
+        for k in 0..3 {
+            let (a, b) = (face[k], face[(k + 1) % 3]);
+            if (distances[a] >= 0.0) != (distances[b] >= 0.0) {
+                let edge = (a.min(b), a.max(b));
+                crossings.entry(edge).or_insert_with(|| {
+                    let t = distances[a] / (distances[a] - distances[b]);
+                    points[a] + (points[b] - points[a]) * t
+                });
+                ends.push(edge);
+            }
+        }
+        if let [start, end] = ends[..] {
+            segments.push((start, end));
+        }
+    }
+
+    let mut edge_segments: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (index, (start, end)) in segments.iter().enumerate() {
+        edge_segments.entry(*start).or_default().push(index);
+        edge_segments.entry(*end).or_default().push(index);
+    }
+
+    let mut visited = vec![false; segments.len()];
+    let walk = |mut edge: (usize, usize), visited: &mut Vec<bool>| {
+        let mut chain = Vec::new();
+        while let Some(&next) = edge_segments[&edge].iter().find(|index| !visited[**index]) {
+            visited[next] = true;
+            let (start, end) = segments[next];
+            edge = if start == edge { end } else { start };
+            chain.push(edge);
+        }
+        chain
+    };
+
+    let mut polylines = Vec::new();
+    for index in 0..segments.len() {
+        if visited[index] {
+            continue;
+        }
+        visited[index] = true;
+        let (start, end) = segments[index];
+        let forward = walk(end, &mut visited);
+        let backward = walk(start, &mut visited);
+
+        let ends = [start, end];
+        let edges = backward
+            .iter()
+            .rev()
+            .chain(ends.iter())
+            .chain(forward.iter());
+        polylines.push(edges.map(|edge| crossings[edge]).collect());
+    }
+    polylines
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::array;
+
+    use super::{clip_geometry, intersect_mesh, slice_geometry, Plane};
+    use crate::io::read_off;
+
+    #[test]
+    fn test_slice_geometry() {
+        let teapot = read_off("tests/data/teapot.off").unwrap();
+        let plane = Plane::from_point_normal(&Vector3::zeros(), &Vector3::new(0.0, 2.0, 0.0));
+        let slice = slice_geometry(&teapot, &plane, 0.2);
+        assert!(slice.len_vertices() > 0);
+        assert!(slice.len_vertices() < teapot.len_vertices());
+        assert!(slice.points.iter().all(|point| point[1].abs() <= 0.1));
+    }
+
+    #[test]
+    fn test_clip_geometry() {
+        let teapot = read_off("tests/data/teapot.off").unwrap();
+        let plane = Plane::from_point_normal(&Vector3::zeros(), &Vector3::x());
+        let clipped = clip_geometry(&teapot, &plane);
+        assert!(clipped.len_vertices() > 0);
+        assert!(clipped.len_faces() < teapot.len_faces());
+        assert!(clipped.points.iter().all(|point| point[0] <= 0.0));
+    }
+
+    #[test]
+    fn test_intersect_mesh() {
+        // Square pyramid without the base, cut at half height gives a closed square.
+        let points = array![
+            Vector3::new(-1.0, -1.0, 0.0),
+            Vector3::new(1.0, -1.0, 0.0),
+            Vector3::new(1.0, 1.0, 0.0),
+            Vector3::new(-1.0, 1.0, 0.0),
+            Vector3::new(0.0, 0.0, 2.0)
+        ];
+        let faces = array![[0, 1, 4], [1, 2, 4], [2, 3, 4], [3, 0, 4]];
+        let plane = Plane::from_point_normal(&Vector3::new(0.0, 0.0, 1.0), &Vector3::z());
+
+        let polylines = intersect_mesh(&points, &faces.view(), &plane);
+        assert_eq!(polylines.len(), 1);
+        let polyline = &polylines[0];
+        assert_eq!(polyline.len(), 5);
+        assert_eq!(polyline.first(), polyline.last());
+        assert!(polyline.iter().all(|point| (point[2] - 1.0).abs() < 1e-6
+            && (point[0].abs() - 0.5).abs() < 1e-6
+            && (point[1].abs() - 0.5).abs() < 1e-6));
+
+        let plane = plane.translated(5.0);
+        assert!(intersect_mesh(&points, &faces.view(), &plane).is_empty());
+    }
+}
@@ -0,0 +1,292 @@
+//! Per-frame metrics of mapping runs, exported as CSV or in the Prometheus text format, so
+//! runs deployed as services can be monitored with standard dashboards.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::error::A3dError;
+
+/// Metrics of a processed frame. Unknown values are left as `None`.
+#[derive(Debug, Clone, Default)]
+pub struct FrameTelemetry {
+    /// Index of the frame in the dataset.
+    pub frame: usize,
+    /// Capture time in seconds.
+    pub timestamp: Option<f64>,
+    /// Time spent on each processing stage, e.g., `icp` or `fusion`.
+    pub timings: BTreeMap<String, Duration>,
+    /// Scalar uncertainty of the estimated pose, e.g., the trace of its covariance.
+    pub pose_uncertainty: Option<f64>,
+    /// Number of surfels in the model.
+    pub num_surfels: Option<usize>,
+    /// Resident memory of the process in bytes, see [`resident_memory`].
+    pub memory_bytes: Option<u64>,
+}
+
+/// Resident memory of the current process in bytes. Only available on Linux.
+pub fn resident_memory() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let resident_pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    // The page size is 4 KiB on the platforms we run on.
+    Some(resident_pages * 4096)
+}
+
+/// Destination of the frame metrics.
+pub trait TelemetrySink {
+    /// Records the metrics of a frame.
+    fn record(&mut self, telemetry: &FrameTelemetry) -> Result<(), A3dError>;
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// Writes one CSV row per frame. The timing columns are the stages of the first recorded
+/// frame, in milliseconds.
+pub struct CsvTelemetry<W: Write> {
+    writer: W,
+    stages: Option<Vec<String>>,
+}
+
+impl<W: Write> CsvTelemetry<W> {
+    /// Creates the sink, the header is written with the first frame.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            stages: None,
+        }
+    }
+}
+
+impl<W: Write> TelemetrySink for CsvTelemetry<W> {
+    fn record(&mut self, telemetry: &FrameTelemetry) -> Result<(), A3dError> {
+        let stages = match &self.stages {
+            Some(stages) => stages,
+            None => {
+                let stages = telemetry.timings.keys().cloned().collect::<Vec<_>>();
+                let timing_columns = stages
+                    .iter()
+                    .map(|stage| format!(",{stage}_ms"))
+                    .collect::<String>();
+                writeln!(
+                    self.writer,
+                    "frame,timestamp{timing_columns},pose_uncertainty,num_surfels,memory_bytes"
+                )?;
+                self.stages.insert(stages)
+            }
+        };
+
+        let timings = stages
+            .iter()
+            .map(|stage| {
+                format!(
+                    ",{}",
+                    optional(
+                        telemetry
+                            .timings
+                            .get(stage)
+                            .map(|elapsed| elapsed.as_secs_f64() * 1000.0)
+                    )
+                )
+            })
+            .collect::<String>();
+        writeln!(
+            self.writer,
+            "{},{}{timings},{},{},{}",
+            telemetry.frame,
+            optional(telemetry.timestamp),
+            optional(telemetry.pose_uncertainty),
+            optional(telemetry.num_surfels),
+            optional(telemetry.memory_bytes),
+        )?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Keeps the metrics in the Prometheus text exposition format: gauges with the values of
+/// the last frame and counters of the processed frames and time per stage.
+///
+/// When a file path is given, every record rewrites it atomically, to be collected by the
+/// node exporter textfile collector. Otherwise, serve [`PrometheusTelemetry::render`] from
+/// the service's HTTP endpoint.
+pub struct PrometheusTelemetry {
+    filepath: Option<PathBuf>,
+    last: FrameTelemetry,
+    frames_total: u64,
+    stage_seconds_total: BTreeMap<String, f64>,
+}
+
+impl PrometheusTelemetry {
+    /// Creates the sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `filepath` - Optional `.prom` file rewritten at every frame.
+    pub fn new<P: AsRef<Path>>(filepath: Option<P>) -> Self {
+        Self {
+            filepath: filepath.map(|filepath| filepath.as_ref().to_path_buf()),
+            last: FrameTelemetry::default(),
+            frames_total: 0,
+            stage_seconds_total: BTreeMap::new(),
+        }
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, f64)>| {
+            if samples.is_empty() {
+                return;
+            }
+            let _ = writeln!(text, "# HELP align3d_{name} {help}");
+            let _ = writeln!(text, "# TYPE align3d_{name} {kind}");
+            for (labels, value) in samples {
+                let _ = writeln!(text, "align3d_{name}{labels} {value}");
+            }
+        };
+        let unlabeled = |value: Option<f64>| {
+            value
+                .map(|value| vec![(String::new(), value)])
+                .unwrap_or_default()
+        };
+        let by_stage = |values: Vec<(&String, f64)>| {
+            values
+                .into_iter()
+                .map(|(stage, value)| (format!("{{stage=\"{stage}\"}}"), value))
+                .collect()
+        };
+
+        metric(
+            "frames_total",
+            "counter",
+            "Number of processed frames.",
+            vec![(String::new(), self.frames_total as f64)],
+        );
+        metric(
+            "frame_index",
+            "gauge",
+            "Index of the last processed frame.",
+            unlabeled((self.frames_total > 0).then_some(self.last.frame as f64)),
+        );
+        metric(
+            "stage_seconds",
+            "gauge",
+            "Time spent on each stage in the last frame.",
+            by_stage(
+                self.last
+                    .timings
+                    .iter()
+                    .map(|(stage, elapsed)| (stage, elapsed.as_secs_f64()))
+                    .collect(),
+            ),
+        );
+        metric(
+            "stage_seconds_total",
+            "counter",
+            "Time spent on each stage.",
+            by_stage(
+                self.stage_seconds_total
+                    .iter()
+                    .map(|(stage, total)| (stage, *total))
+                    .collect(),
+            ),
+        );
+        metric(
+            "pose_uncertainty",
+            "gauge",
+            "Uncertainty of the last estimated pose.",
+            unlabeled(self.last.pose_uncertainty),
+        );
+        metric(
+            "surfels",
+            "gauge",
+            "Number of surfels in the model.",
+            unlabeled(self.last.num_surfels.map(|count| count as f64)),
+        );
+        metric(
+            "resident_memory_bytes",
+            "gauge",
+            "Resident memory of the process.",
+            unlabeled(self.last.memory_bytes.map(|bytes| bytes as f64)),
+        );
+        text
+    }
+}
+
+impl TelemetrySink for PrometheusTelemetry {
+    fn record(&mut self, telemetry: &FrameTelemetry) -> Result<(), A3dError> {
+        self.frames_total += 1;
+        for (stage, elapsed) in telemetry.timings.iter() {
+            *self.stage_seconds_total.entry(stage.clone()).or_default() += elapsed.as_secs_f64();
+        }
+        self.last = telemetry.clone();
+
+        if let Some(filepath) = &self.filepath {
+            // The collector may read at any time, so it must never see a partial file.
+            let temp_filepath = filepath.with_extension("prom.tmp");
+            std::fs::write(&temp_filepath, self.render())?;
+            std::fs::rename(temp_filepath, filepath)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{CsvTelemetry, FrameTelemetry, PrometheusTelemetry, TelemetrySink};
+
+    fn frame(frame: usize) -> FrameTelemetry {
+        FrameTelemetry {
+            frame,
+            timestamp: Some(frame as f64 * 0.5),
+            timings: [
+                ("fusion".to_string(), Duration::from_millis(4)),
+                ("icp".to_string(), Duration::from_millis(10)),
+            ]
+            .into_iter()
+            .collect(),
+            pose_uncertainty: None,
+            num_surfels: Some(1000 * frame),
+            memory_bytes: Some(2048),
+        }
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut sink = CsvTelemetry::new(Vec::new());
+        sink.record(&frame(1)).unwrap();
+        sink.record(&frame(2)).unwrap();
+
+        let csv = String::from_utf8(sink.writer).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "frame,timestamp,fusion_ms,icp_ms,pose_uncertainty,num_surfels,memory_bytes"
+        );
+        assert_eq!(lines[2], "2,1,4,10,,2000,2048");
+    }
+
+    #[test]
+    fn test_prometheus() {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("align3d.prom");
+        let mut sink = PrometheusTelemetry::new(Some(&filepath));
+        sink.record(&frame(1)).unwrap();
+        sink.record(&frame(2)).unwrap();
+
+        let text = std::fs::read_to_string(&filepath).unwrap();
+        assert_eq!(text, sink.render());
+        assert!(text.contains("align3d_frames_total 2\n"));
+        assert!(text.contains("align3d_stage_seconds_total{stage=\"icp\"} 0.02\n"));
+        assert!(text.contains("align3d_surfels 2000\n"));
+        assert!(!text.contains("pose_uncertainty"));
+    }
+}
@@ -0,0 +1,285 @@
+use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
+
+use super::Trajectory;
+use crate::{error::A3dError, transform::Transform};
+
+/// How poses are interpolated between the trajectory keyframes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoseInterpolation {
+    /// Linear positions and slerp rotations. Passes through the keyframes with visible
+    /// changes of direction at them.
+    Linear,
+    /// Catmull-Rom splines for positions and squad for rotations. Smooth camera motion
+    /// through the keyframes, suited for presentation.
+    CatmullRom,
+}
+
+/// Parameters for resampling a trajectory into the camera path of a flythrough video.
+#[derive(Debug, Clone)]
+pub struct FlythroughParams {
+    /// Frames per second of the output.
+    pub fps: f32,
+    /// Playback speed, as trajectory time units per second of output.
+    pub speed: f32,
+    /// The pose interpolation.
+    pub interpolation: PoseInterpolation,
+}
+
+impl Default for FlythroughParams {
+    fn default() -> Self {
+        Self {
+            fps: 30.0,
+            speed: 1.0,
+            interpolation: PoseInterpolation::CatmullRom,
+        }
+    }
+}
+
+fn slerp(from: &UnitQuaternion<f32>, to: &UnitQuaternion<f32>, t: f32) -> UnitQuaternion<f32> {
+    // `rotation_to` and `powf` take the shortest path.
+    from.rotation_to(to).powf(t) * from
+}
+
+impl Trajectory {
+    /// Position and rotation of the pose at `index`, clamped to the trajectory bounds.
+    fn pose_parts(&self, index: isize) -> (Vector3<f32>, UnitQuaternion<f32>) {
+        let pose = &self.camera_to_world[index.clamp(0, self.len() as isize - 1) as usize];
+        (pose.0.translation.vector, pose.0.rotation)
+    }
+
+    fn time_at(&self, index: isize) -> f32 {
+        self.times[index.clamp(0, self.len() as isize - 1) as usize]
+    }
+
+    /// Squad control rotation of the keyframe `index`.
+    fn squad_control(&self, index: isize) -> UnitQuaternion<f32> {
+        let (_, previous) = self.pose_parts(index - 1);
+        let (_, current) = self.pose_parts(index);
+        let (_, next) = self.pose_parts(index + 1);
+        let inverse = current.inverse();
+        current
+            * UnitQuaternion::from_scaled_axis(
+                -((inverse * next).scaled_axis() + (inverse * previous).scaled_axis()) * 0.25,
+            )
+    }
+
+    /// Interpolates the camera pose at a given time.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The time, in the same unit as the trajectory timestamps, that must be
+    ///   increasing.
+    /// * `interpolation` - The interpolation method.
+    ///
+    /// # Returns
+    ///
+    /// The camera to world pose, or `None` if the time is outside the trajectory.
+    pub fn interpolate(&self, time: f32, interpolation: PoseInterpolation) -> Option<Transform> {
+        let (first, last) = (*self.times.first()?, *self.times.last()?);
+        if time < first || time > last {
+            return None;
+        }
+        if self.len() == 1 {
+            return Some(self.camera_to_world[0].clone());
+        }
+
+        let index = (self.times.partition_point(|t| *t <= time).max(1) - 1).min(self.len() - 2);
+        let i = index as isize;
+        let (t0, t1) = (self.time_at(i), self.time_at(i + 1));
+        let duration = t1 - t0;
+        let u = if duration > 0.0 {
+            (time - t0) / duration
+        } else {
+            0.0
+        };
+
+        let (p0, q0) = self.pose_parts(i);
+        let (p1, q1) = self.pose_parts(i + 1);
+        let (position, rotation) = match interpolation {
+            PoseInterpolation::Linear => (p0.lerp(&p1, u), slerp(&q0, &q1, u)),
+            PoseInterpolation::CatmullRom => {
+                // Hermite spline with tangents from the neighbor keyframes, scaled by their
+                // time spacing so irregular timestamps don't overshoot.
+                let tangent = |k: isize| {
+                    let span = self.time_at(k + 1) - self.time_at(k - 1);
+                    if span > 0.0 {
+                        (self.pose_parts(k + 1).0 - self.pose_parts(k - 1).0) * (duration / span)
+                    } else {
+                        Vector3::zeros()
+                    }
+                };
+                let (u2, u3) = (u * u, u * u * u);
+                let position = p0 * (2.0 * u3 - 3.0 * u2 + 1.0)
+                    + tangent(i) * (u3 - 2.0 * u2 + u)
+                    + p1 * (-2.0 * u3 + 3.0 * u2)
+                    + tangent(i + 1) * (u3 - u2);
+
+                let outer = slerp(&q0, &q1, u);
+                let inner = slerp(&self.squad_control(i), &self.squad_control(i + 1), u);
+                (position, slerp(&outer, &inner, 2.0 * u * (1.0 - u)))
+            }
+        };
+
+        Some(Transform(Isometry3::from_parts(
+            Translation3::from(position),
+            rotation,
+        )))
+    }
+
+    /// Resamples the trajectory into the camera path of a flythrough, one pose per output
+    /// frame, e.g., to render a video of the reconstruction along a user-authored or
+    /// estimated trajectory.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The frame rate, speed and interpolation.
+    ///
+    /// # Returns
+    ///
+    /// The trajectory with one pose per frame, timestamped in seconds of output, or error
+    /// if the frame rate or the speed isn't finite and positive.
+    pub fn flythrough(&self, params: &FlythroughParams) -> Result<Trajectory, A3dError> {
+        for (name, value) in [("fps", params.fps), ("speed", params.speed)] {
+            if !value.is_finite() || value <= 0.0 {
+                return Err(A3dError::invalid_parameter(format!(
+                    "{name} must be finite and positive, got {value}"
+                )));
+            }
+        }
+        let (Some(first), Some(last)) = (self.times.first(), self.times.last()) else {
+            return Ok(Trajectory::default());
+        };
+
+        let step = params.speed / params.fps;
+        let num_frames = ((last - first) / step + 1e-4).floor() as usize + 1;
+        Ok((0..num_frames)
+            .filter_map(|frame| {
+                let time = (first + frame as f32 * step).min(*last);
+                self.interpolate(time, params.interpolation)
+                    .map(|pose| (pose, frame as f32 / params.fps))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rstest::{fixture, rstest};
+
+    use super::{FlythroughParams, PoseInterpolation};
+    use crate::{
+        trajectory::Trajectory,
+        transform::{Transform, TransformBuilder},
+    };
+
+    #[fixture]
+    fn keyframes() -> Trajectory {
+        (0..4)
+            .map(|i| {
+                let pose = TransformBuilder::default()
+                    .translation(Vector3::new(i as f32, (i % 2) as f32, 0.0))
+                    .axis_angle(Vector3::y_axis(), i as f32 * 0.4)
+                    .build();
+                (pose, i as f32 * 2.0)
+            })
+            .collect()
+    }
+
+    fn assert_pose_eq(actual: &Transform, expected: &Transform) {
+        assert!((actual.translation() - expected.translation()).norm() < 1e-4);
+        assert!(actual.0.rotation.angle_to(&expected.0.rotation) < 1e-3);
+    }
+
+    #[rstest]
+    fn test_interpolate_keyframes(keyframes: Trajectory) {
+        for interpolation in [PoseInterpolation::Linear, PoseInterpolation::CatmullRom] {
+            for (pose, time) in keyframes.iter() {
+                assert_pose_eq(&keyframes.interpolate(time, interpolation).unwrap(), &pose);
+            }
+            assert!(keyframes.interpolate(-0.5, interpolation).is_none());
+            assert!(keyframes.interpolate(6.5, interpolation).is_none());
+        }
+
+        let middle = keyframes
+            .interpolate(1.0, PoseInterpolation::Linear)
+            .unwrap();
+        assert!((middle.translation() - Vector3::new(0.5, 0.5, 0.0)).norm() < 1e-5);
+        assert!((middle.0.rotation.angle() - 0.2).abs() < 1e-4);
+    }
+
+    #[rstest]
+    fn test_catmull_rom_is_smooth(keyframes: Trajectory) {
+        // Velocity right before and after a keyframe should match.
+        let velocity = |time: f32| {
+            let (a, b) = (
+                keyframes.interpolate(time - 1e-2, PoseInterpolation::CatmullRom),
+                keyframes.interpolate(time + 1e-2, PoseInterpolation::CatmullRom),
+            );
+            (b.unwrap().translation() - a.unwrap().translation()) / 2e-2
+        };
+        let (before, after) = (velocity(2.0 - 0.02), velocity(2.0 + 0.02));
+        assert!((before - after).norm() < 0.05);
+
+        let linear_velocity = |time: f32| {
+            let (a, b) = (
+                keyframes.interpolate(time - 1e-2, PoseInterpolation::Linear),
+                keyframes.interpolate(time + 1e-2, PoseInterpolation::Linear),
+            );
+            (b.unwrap().translation() - a.unwrap().translation()) / 2e-2
+        };
+        assert!((linear_velocity(1.98) - linear_velocity(2.02)).norm() > 0.4);
+    }
+
+    #[rstest]
+    fn test_flythrough(keyframes: Trajectory) {
+        let path = keyframes
+            .flythrough(&FlythroughParams {
+                fps: 10.0,
+                speed: 2.0,
+                interpolation: PoseInterpolation::CatmullRom,
+            })
+            .unwrap();
+        // 6 time units at 2 units per second is 3 seconds of video.
+        assert_eq!(path.len(), 31);
+        assert!((path.times[30] - 3.0).abs() < 1e-5);
+        assert_pose_eq(&path[0], &keyframes[0]);
+        assert_pose_eq(&path[30], &keyframes[3]);
+        assert!(path
+            .camera_to_world
+            .windows(2)
+            .all(|pair| (pair[1].translation() - pair[0].translation()).norm() < 0.5));
+
+        let slower = keyframes
+            .flythrough(&FlythroughParams {
+                fps: 10.0,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(slower.len(), 61);
+        assert!(Trajectory::default()
+            .flythrough(&Default::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[rstest]
+    fn test_flythrough_invalid_params(keyframes: Trajectory) {
+        for (fps, speed) in [
+            (0.0, 1.0),
+            (-30.0, 1.0),
+            (f32::NAN, 1.0),
+            (30.0, 0.0),
+            (30.0, -1.0),
+            (30.0, f32::INFINITY),
+        ] {
+            let params = FlythroughParams {
+                fps,
+                speed,
+                ..Default::default()
+            };
+            assert!(keyframes.flythrough(&params).is_err());
+            assert!(Trajectory::default().flythrough(&params).is_err());
+        }
+    }
+}
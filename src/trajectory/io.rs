@@ -0,0 +1,426 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use nalgebra::{Matrix4, Quaternion, Vector3};
+
+use super::Trajectory;
+use crate::{error::A3dError, transform::Transform};
+
+/// Reads a trajectory in the TUM RGB-D format. Each line has the form
+/// `timestamp tx ty tz qx qy qz qw`, lines starting with `#` are ignored. The first
+/// timestamp becomes the trajectory's `time_origin`.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the trajectory file.
+///
+/// # Returns
+///
+/// The trajectory with the poses and timestamps of the file.
+pub fn read_tum<P: AsRef<Path>>(filepath: P) -> Result<Trajectory, A3dError> {
+    let filepath = filepath.as_ref();
+    let reader = BufReader::new(File::open(filepath)?);
+
+    let mut trajectory = Trajectory::default();
+    for (line_count, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let values = line
+            .split_whitespace()
+            .map(|token| token.parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|err| {
+                A3dError::Parser(format!("{}:{}: {err}", filepath.display(), line_count + 1))
+            })?;
+
+        if let [time, tx, ty, tz, qx, qy, qz, qw] = values[..] {
+            if trajectory.is_empty() {
+                trajectory.time_origin = time;
+            }
+            trajectory.push(
+                Transform::new(
+                    &Vector3::new(tx as f32, ty as f32, tz as f32),
+                    &Quaternion::new(qw as f32, qx as f32, qy as f32, qz as f32),
+                ),
+                (time - trajectory.time_origin) as f32,
+            );
+        } else {
+            return Err(A3dError::Parser(format!(
+                "{}:{}: expected 8 values, got {}",
+                filepath.display(),
+                line_count + 1,
+                values.len()
+            )));
+        }
+    }
+
+    Ok(trajectory)
+}
+
+/// Writes a trajectory in the TUM RGB-D format (`timestamp tx ty tz qx qy qz qw`), which
+/// is also the format read by evaluation tools like evo.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the output file.
+/// * `trajectory` - The trajectory to be written.
+pub fn write_tum<P: AsRef<Path>>(filepath: P, trajectory: &Trajectory) -> Result<(), A3dError> {
+    let mut writer = BufWriter::new(File::create(filepath)?);
+
+    writeln!(writer, "# timestamp tx ty tz qx qy qz qw")?;
+    for (camera_to_world, time) in trajectory.iter() {
+        let translation = camera_to_world.translation();
+        let rotation = camera_to_world.0.rotation;
+        writeln!(
+            writer,
+            "{:.6} {} {} {} {} {} {} {}",
+            trajectory.time_origin + time as f64,
+            translation[0],
+            translation[1],
+            translation[2],
+            rotation.i,
+            rotation.j,
+            rotation.k,
+            rotation.w
+        )?;
+    }
+
+    Ok(())
+}
+
+fn parse_kitti_matrix(tokens: &[&str]) -> Option<Transform> {
+    if tokens.len() != 12 {
+        return None;
+    }
+
+    let mut matrix = Matrix4::<f32>::identity();
+    for (i, token) in tokens.iter().enumerate() {
+        matrix[(i / 4, i % 4)] = token.parse::<f32>().ok()?;
+    }
+    Some(Transform::from_matrix4(&matrix))
+}
+
+/// Reads a trajectory in the KITTI odometry format. Each line has the 12 values of the 3x4
+/// pose matrix in row-major order. As the format has no timestamps, the line index is used.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the poses file.
+///
+/// # Returns
+///
+/// The trajectory with the poses of the file.
+pub fn read_kitti<P: AsRef<Path>>(filepath: P) -> Result<Trajectory, A3dError> {
+    let filepath = filepath.as_ref();
+    let reader = BufReader::new(File::open(filepath)?);
+
+    let mut trajectory = Trajectory::default();
+    for (line_count, line) in reader.lines().enumerate() {
+        let line = line?;
+        let tokens = line.split_whitespace().collect::<Vec<&str>>();
+        if tokens.is_empty() {
+            continue;
+        }
+
+        let pose = parse_kitti_matrix(&tokens).ok_or_else(|| {
+            A3dError::Parser(format!(
+                "{}:{}: expected 12 numeric values",
+                filepath.display(),
+                line_count + 1
+            ))
+        })?;
+        trajectory.push(pose, trajectory.len() as f32);
+    }
+
+    Ok(trajectory)
+}
+
+/// Writes a trajectory in the KITTI odometry format, i.e., the first 3 rows of each pose matrix
+/// in row-major order. Timestamps are not written.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the output file.
+/// * `trajectory` - The trajectory to be written.
+pub fn write_kitti<P: AsRef<Path>>(filepath: P, trajectory: &Trajectory) -> Result<(), A3dError> {
+    let mut writer = BufWriter::new(File::create(filepath)?);
+
+    for camera_to_world in trajectory.camera_to_world.iter() {
+        let matrix = Matrix4::from(camera_to_world);
+        let row_major = (0..12)
+            .map(|i| format!("{:e}", matrix[(i / 4, i % 4)]))
+            .collect::<Vec<String>>();
+        writeln!(writer, "{}", row_major.join(" "))?;
+    }
+
+    Ok(())
+}
+
+/// Reads a transform from a KITTI calibration file, like the `Tr` (LiDAR to left camera) entry
+/// of the odometry `calib.txt` files. Entries have the form `key: r00 r01 r02 t0 ... t2`.
+///
+/// # Arguments
+///
+/// * `filepath` - Path to the calibration file.
+/// * `key` - The name of the entry, e.g., `Tr`.
+///
+/// # Returns
+///
+/// The transform of the entry.
+pub fn read_kitti_calibration<P: AsRef<Path>>(
+    filepath: P,
+    key: &str,
+) -> Result<Transform, A3dError> {
+    let filepath = filepath.as_ref();
+    let reader = BufReader::new(File::open(filepath)?);
+
+    for line in reader.lines() {
+        let line = line?;
+        if let Some((entry, values)) = line.split_once(':') {
+            if entry.trim() != key {
+                continue;
+            }
+            return parse_kitti_matrix(&values.split_whitespace().collect::<Vec<&str>>())
+                .ok_or_else(|| {
+                    A3dError::Parser(format!(
+                        "{}: entry `{key}` is not a 3x4 matrix",
+                        filepath.display()
+                    ))
+                });
+        }
+    }
+
+    Err(A3dError::Parser(format!(
+        "{}: entry `{key}` not found",
+        filepath.display()
+    )))
+}
+
+/// Converts a trajectory of camera poses into the poses of a LiDAR rigidly attached to it.
+///
+/// # Arguments
+///
+/// * `trajectory` - Camera trajectory, e.g., KITTI ground truth.
+/// * `lidar_to_camera` - Calibration from LiDAR to camera frame (the KITTI `Tr` entry).
+///
+/// # Returns
+///
+/// The LiDAR trajectory, expressed in the frame of the first LiDAR pose's world.
+pub fn camera_to_lidar_trajectory(
+    trajectory: &Trajectory,
+    lidar_to_camera: &Transform,
+) -> Trajectory {
+    let camera_to_lidar = lidar_to_camera.inverse();
+    let converted = trajectory
+        .iter()
+        .map(|(camera_to_world, time)| {
+            (
+                &(&camera_to_lidar * &camera_to_world) * lidar_to_camera,
+                time,
+            )
+        })
+        .collect::<Trajectory>();
+    Trajectory {
+        time_origin: trajectory.time_origin,
+        ..converted
+    }
+}
+
+/// Converts a trajectory of LiDAR poses into the poses of a camera rigidly attached to it.
+/// This is the inverse of [`camera_to_lidar_trajectory`].
+///
+/// # Arguments
+///
+/// * `trajectory` - LiDAR trajectory.
+/// * `lidar_to_camera` - Calibration from LiDAR to camera frame (the KITTI `Tr` entry).
+///
+/// # Returns
+///
+/// The camera trajectory.
+pub fn lidar_to_camera_trajectory(
+    trajectory: &Trajectory,
+    lidar_to_camera: &Transform,
+) -> Trajectory {
+    let camera_to_lidar = lidar_to_camera.inverse();
+    let converted = trajectory
+        .iter()
+        .map(|(lidar_to_world, time)| {
+            (
+                &(lidar_to_camera * &lidar_to_world) * &camera_to_lidar,
+                time,
+            )
+        })
+        .collect::<Trajectory>();
+    Trajectory {
+        time_origin: trajectory.time_origin,
+        ..converted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        camera_to_lidar_trajectory, lidar_to_camera_trajectory, read_kitti, read_kitti_calibration,
+        read_tum, write_kitti, write_tum,
+    };
+    use crate::{
+        metrics::TransformMetrics,
+        trajectory::Trajectory,
+        transform::{LieGroup, Transform},
+    };
+
+    #[test]
+    fn test_read_write_tum() {
+        let trajectory = (0..5)
+            .map(|i| {
+                let i = i as f32;
+                (
+                    Transform::exp(&LieGroup::Se3(nalgebra::Vector6::new(
+                        i,
+                        0.5 * i,
+                        -i,
+                        0.1 * i,
+                        0.05,
+                        -0.2 * i,
+                    ))),
+                    i * 0.5,
+                )
+            })
+            .collect::<Trajectory>();
+
+        write_tum("tests/outputs/trajectory-tum.txt", &trajectory).unwrap();
+        let loaded = read_tum("tests/outputs/trajectory-tum.txt").unwrap();
+
+        assert_eq!(loaded.len(), trajectory.len());
+        for ((expected, expected_time), (actual, actual_time)) in
+            trajectory.iter().zip(loaded.iter())
+        {
+            assert_eq!(expected_time, actual_time);
+            let metrics = TransformMetrics::new(&expected, &actual);
+            assert!(metrics.angle < 1e-3);
+            assert!(metrics.translation < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_read_write_tum_unix_timestamps() {
+        let stamps = [
+            "1305031102.175304",
+            "1305031102.211214",
+            "1305031108.743502",
+        ];
+        let content = stamps
+            .iter()
+            .map(|stamp| format!("{stamp} 1.0 2.0 3.0 0.0 0.0 0.0 1.0\n"))
+            .collect::<String>();
+        std::fs::write("tests/outputs/trajectory-tum-unix.txt", content).unwrap();
+
+        let loaded = read_tum("tests/outputs/trajectory-tum-unix.txt").unwrap();
+        assert_eq!(loaded.time_origin, 1305031102.175304);
+        assert_eq!(loaded.times[0], 0.0);
+        assert!((loaded.times[1] - 0.03591).abs() < 1e-6);
+        assert!((loaded.times[2] - 6.568198).abs() < 1e-6);
+
+        write_tum("tests/outputs/trajectory-tum-unix-out.txt", &loaded).unwrap();
+        let written = std::fs::read_to_string("tests/outputs/trajectory-tum-unix-out.txt")
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| line.split_whitespace().next().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(written, stamps);
+    }
+
+    #[test]
+    fn test_read_tum_invalid_line() {
+        std::fs::write(
+            "tests/outputs/trajectory-tum-invalid.txt",
+            "# comment\n0.0 1.0 2.0 3.0 0.0 0.0 0.0\n",
+        )
+        .unwrap();
+        assert!(read_tum("tests/outputs/trajectory-tum-invalid.txt").is_err());
+    }
+
+    #[test]
+    fn test_read_write_kitti() {
+        let trajectory = (0..4)
+            .map(|i| {
+                let i = i as f32;
+                (
+                    Transform::exp(&LieGroup::Se3(nalgebra::Vector6::new(
+                        0.1 * i,
+                        0.0,
+                        2.0 * i,
+                        0.0,
+                        0.02 * i,
+                        0.0,
+                    ))),
+                    i,
+                )
+            })
+            .collect::<Trajectory>();
+
+        write_kitti("tests/outputs/trajectory-kitti.txt", &trajectory).unwrap();
+        let loaded = read_kitti("tests/outputs/trajectory-kitti.txt").unwrap();
+
+        assert_eq!(loaded.len(), trajectory.len());
+        for ((expected, expected_time), (actual, actual_time)) in
+            trajectory.iter().zip(loaded.iter())
+        {
+            assert_eq!(expected_time, actual_time);
+            let metrics = TransformMetrics::new(&expected, &actual);
+            assert!(metrics.angle < 1e-3);
+            assert!(metrics.translation < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_camera_lidar_conversion() {
+        std::fs::write(
+            "tests/outputs/kitti-calib.txt",
+            "P0: 7.1e+02 0 6.0e+02 0 0 7.1e+02 1.8e+02 0 0 0 1 0\n\
+             Tr: 4.2e-04 -9.9e-01 -7.2e-03 -1.1e-02 -7.2e-03 8.1e-03 -9.9e-01 -5.4e-02 \
+             9.9e-01 4.8e-04 -7.2e-03 -2.9e-01\n",
+        )
+        .unwrap();
+        let lidar_to_camera =
+            read_kitti_calibration("tests/outputs/kitti-calib.txt", "Tr").unwrap();
+        assert!(read_kitti_calibration("tests/outputs/kitti-calib.txt", "Tr_imu").is_err());
+
+        let camera_trajectory = (0..3)
+            .map(|i| {
+                (
+                    Transform::exp(&LieGroup::Se3(nalgebra::Vector6::new(
+                        0.0,
+                        0.0,
+                        i as f32,
+                        0.0,
+                        0.1 * i as f32,
+                        0.0,
+                    ))),
+                    i as f32,
+                )
+            })
+            .collect::<Trajectory>();
+
+        let lidar_trajectory = camera_to_lidar_trajectory(&camera_trajectory, &lidar_to_camera);
+        let round_trip = lidar_to_camera_trajectory(&lidar_trajectory, &lidar_to_camera);
+        for ((expected, _), (actual, _)) in camera_trajectory.iter().zip(round_trip.iter()) {
+            let metrics = TransformMetrics::new(&expected, &actual);
+            assert!(metrics.angle < 1e-3);
+            assert!(metrics.translation < 1e-4);
+        }
+
+        // Relative motions have the same magnitude in both frames.
+        let camera_motion = camera_trajectory.get_relative_transform(1, 0).unwrap();
+        let lidar_motion = lidar_trajectory.get_relative_transform(1, 0).unwrap();
+        assert!((camera_motion.angle() - lidar_motion.angle()).abs() < 1e-3);
+    }
+}
@@ -2,13 +2,21 @@ use std::ops::Index;
 
 use crate::transform::Transform;
 
+mod flythrough;
+pub mod io;
+
+pub use flythrough::{FlythroughParams, PoseInterpolation};
+
 /// Trajectory of camera poses. Use it to store or create trajectories while aligning scans.
 #[derive(Clone, Debug)]
 pub struct Trajectory {
     /// Camera poses, transforms points from camera to world.
     pub camera_to_world: Vec<Transform>,
-    /// Timestamps of each pose.
+    /// Timestamps of each pose, relative to `time_origin`.
     pub times: Vec<f32>,
+    /// Absolute time of the zero of `times`, e.g., the Unix time of the first pose of a
+    /// TUM file. Large timestamps only keep their precision as offsets from it.
+    pub time_origin: f64,
 }
 
 impl Default for Trajectory {
@@ -17,6 +25,7 @@ impl Default for Trajectory {
         Self {
             camera_to_world: Vec::new(),
             times: Vec::new(),
+            time_origin: 0.0,
         }
     }
 }
@@ -74,6 +83,7 @@ impl Trajectory {
                 .map(|transform| &first_inv * transform)
                 .collect::<Vec<Transform>>(),
             times: self.times.clone(),
+            time_origin: self.time_origin,
         }
     }
 
@@ -91,6 +101,7 @@ impl Trajectory {
         Self {
             camera_to_world: self.camera_to_world[start..end].to_vec(),
             times: self.times[start..end].to_vec(),
+            time_origin: self.time_origin,
         }
     }
 
@@ -159,6 +170,17 @@ impl TrajectoryBuilder {
         }
     }
 
+    /// Continues a previously built trajectory, e.g., when resuming an interrupted run.
+    /// An empty trajectory is the same as [`TrajectoryBuilder::default`].
+    pub fn from_trajectory(trajectory: Trajectory) -> Self {
+        let (last, last_time) = trajectory.last().unwrap_or((Transform::eye(), 0.0));
+        Self {
+            trajectory,
+            last,
+            last_time,
+        }
+    }
+
     /// Accumulates the given transform and timestamp into the previous ones and adds
     /// it to the trajectory being build.
     pub fn accumulate(&mut self, now_to_previous: &Transform, timestamp: Option<f32>) {
@@ -1,6 +1,6 @@
 use nalgebra::{
-    Isometry3, Matrix3, Matrix4, Quaternion, Rotation3, Translation3, UnitQuaternion, UnitVector3,
-    Vector3, Vector6,
+    Isometry3, Matrix3, Matrix4, Matrix6, Quaternion, Rotation3, Translation3, UnitQuaternion,
+    UnitVector3, Vector3, Vector6,
 };
 use ndarray::Array1;
 
@@ -81,8 +81,6 @@ impl Transform {
     ///
     /// * Transform
     pub fn exp(params: &LieGroup) -> Self {
-        const EPSILON: f32 = 1e-8;
-
         match params {
             LieGroup::Se3(xyz_so3) => {
                 let omega = Vector3::new(xyz_so3[3], xyz_so3[4], xyz_so3[5]);
@@ -91,15 +89,19 @@ impl Transform {
                 let xyz = {
                     let left_jacobian = {
                         let big_omega = omega.cross_matrix();
-
-                        if theta_sq < EPSILON {
-                            Matrix3::identity() + (big_omega * 0.5)
+                        // Same as in `log`, the closed forms of the coefficients cancel
+                        // for small angles.
+                        let (linear, quadratic) = if theta_sq < 1e-2 {
+                            (0.5 - theta_sq / 24.0, 1.0 / 6.0 - theta_sq / 120.0)
                         } else {
-                            let big_omega_squared = big_omega * big_omega;
-                            Matrix3::identity()
-                                + (1.0 - theta.cos()) / theta_sq * big_omega
-                                + (theta - theta.sin()) / (theta_sq * theta) * big_omega_squared
-                        }
+                            let theta = theta as f64;
+                            let theta_sq = theta * theta;
+                            (
+                                ((1.0 - theta.cos()) / theta_sq) as f32,
+                                ((theta - theta.sin()) / (theta_sq * theta)) as f32,
+                            )
+                        };
+                        Matrix3::identity() + big_omega * linear + big_omega * big_omega * quadratic
                     };
 
                     left_jacobian * Vector3::new(xyz_so3[0], xyz_so3[1], xyz_so3[2])
@@ -117,6 +119,30 @@ impl Transform {
         }
     }
 
+    /// Inverse of [`Transform::exp`] for SE(3).
+    ///
+    /// # Returns
+    ///
+    /// * 6D vector of the form [x, y, z, rx, ry, rz], whose exponential is this transform.
+    pub fn log(&self) -> Vector6<f32> {
+        let omega = self.0.rotation.scaled_axis();
+        let theta_sq = omega.norm_squared();
+        let big_omega = omega.cross_matrix();
+        // Coefficient of the squared term of the inverse left Jacobian of `exp`. Its closed
+        // form cancels catastrophically for small angles, where its Taylor series is used
+        // instead, and loses precision in f32 for the larger ones.
+        let coefficient = if theta_sq < 1e-2 {
+            1.0 / 12.0 + theta_sq / 720.0
+        } else {
+            let theta = (theta_sq as f64).sqrt();
+            ((1.0 - theta * theta.sin() / (2.0 * (1.0 - theta.cos()))) / (theta * theta)) as f32
+        };
+        let inv_left_jacobian =
+            Matrix3::identity() - big_omega * 0.5 + big_omega * big_omega * coefficient;
+        let xyz = inv_left_jacobian * self.0.translation.vector;
+        Vector6::new(xyz[0], xyz[1], xyz[2], omega[0], omega[1], omega[2])
+    }
+
     /// Create a transform from a 4x4 matrix homogeneous matrix.
     pub fn from_matrix4(matrix: &Matrix4<f32>) -> Self {
         let translation = Translation3::new(matrix[(0, 3)], matrix[(1, 3)], matrix[(2, 3)]);
@@ -200,6 +226,19 @@ impl Transform {
     pub fn translation(&self) -> Vector3<f32> {
         self.0.translation.vector
     }
+
+    /// Adjoint matrix, which moves a perturbation across the transform:
+    /// `T * exp(ξ) * T⁻¹ = exp(Ad(T) * ξ)`, with `ξ` in the order of [`Transform::exp`].
+    pub fn adjoint(&self) -> Matrix6<f32> {
+        let rotation = self.0.rotation.to_rotation_matrix().into_inner();
+        let mut adjoint = Matrix6::zeros();
+        adjoint.fixed_view_mut::<3, 3>(0, 0).copy_from(&rotation);
+        adjoint
+            .fixed_view_mut::<3, 3>(0, 3)
+            .copy_from(&(self.translation().cross_matrix() * rotation));
+        adjoint.fixed_view_mut::<3, 3>(3, 3).copy_from(&rotation);
+        adjoint
+    }
 }
 
 impl ops::Mul<&Transform> for &Transform {
@@ -240,6 +279,93 @@ impl From<&Matrix4<f32>> for Transform {
     }
 }
 
+/// A double precision rigid body transform, e.g., for poses in georeferenced coordinates
+/// where [`Transform`] loses precision.
+#[derive(Clone, Debug)]
+pub struct TransformF64(pub Isometry3<f64>);
+
+impl Default for TransformF64 {
+    /// Create a new transform with zero translation and zero rotation.
+    fn default() -> Self {
+        Self::eye()
+    }
+}
+
+impl TransformF64 {
+    /// Create a new transform with zero translation and zero rotation.
+    pub fn eye() -> Self {
+        Self(Isometry3::identity())
+    }
+
+    /// Create a new transform from a translation vector and a rotation quaternion.
+    pub fn new(xyz: &Vector3<f64>, rotation: &Quaternion<f64>) -> Self {
+        Self(Isometry3::from_parts(
+            Translation3::from(*xyz),
+            UnitQuaternion::from_quaternion(*rotation),
+        ))
+    }
+
+    /// Transforms a 3D point.
+    pub fn transform_vector(&self, rhs: &Vector3<f64>) -> Vector3<f64> {
+        self.0.rotation * rhs + self.0.translation.vector
+    }
+
+    /// Transforms an array of 3D points, reusing its storage.
+    pub fn transform_vectors(&self, mut rhs: Array1<Vector3<f64>>) -> Array1<Vector3<f64>> {
+        for point in rhs.iter_mut() {
+            *point = self.transform_vector(point);
+        }
+        rhs
+    }
+
+    /// Inverts the transform.
+    pub fn inverse(&self) -> Self {
+        Self(self.0.inverse())
+    }
+
+    /// Returns the translation part.
+    pub fn translation(&self) -> Vector3<f64> {
+        self.0.translation.vector
+    }
+
+    /// The single precision transform acting on coordinates relative to an origin, i.e.,
+    /// `p - origin`, so it stays accurate for points far from the world origin.
+    ///
+    /// # Arguments
+    ///
+    /// * origin - The origin of the local coordinates, see
+    ///   [`crate::pointcloud::PointCloudF64::to_local`].
+    pub fn to_local(&self, origin: &Vector3<f64>) -> Transform {
+        let translation = self.transform_vector(origin) - origin;
+        Transform(Isometry3::from_parts(
+            Translation3::from(translation.cast::<f32>()),
+            self.0.rotation.cast::<f32>(),
+        ))
+    }
+}
+
+impl ops::Mul<&TransformF64> for &TransformF64 {
+    type Output = TransformF64;
+
+    /// Composes two transforms, `rhs` is applied first.
+    fn mul(self, rhs: &TransformF64) -> Self::Output {
+        TransformF64(self.0 * rhs.0)
+    }
+}
+
+impl From<&Transform> for TransformF64 {
+    fn from(transform: &Transform) -> Self {
+        Self(transform.0.cast::<f64>())
+    }
+}
+
+impl From<&TransformF64> for Transform {
+    /// Converts to single precision, which is lossy for large translations.
+    fn from(transform: &TransformF64) -> Self {
+        Self(transform.0.cast::<f32>())
+    }
+}
+
 pub struct TransformBuilder {
     pub translation: Vector3<f32>,
     pub rotation: UnitQuaternion<f32>,
@@ -387,6 +513,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_log() {
+        for se3 in [
+            Vector6::new(1.0, 2.0, 3.0, 0.4, 0.5, 0.3),
+            Vector6::new(-0.1, 0.0, 0.2, 0.0, 0.0, 0.0),
+            Vector6::new(0.0, 0.5, 0.0, 2.5, -0.5, 0.1),
+        ] {
+            let log = Transform::exp(&LieGroup::Se3(se3)).log();
+            assert!((log - se3).norm() < 1e-4, "{log} != {se3}");
+        }
+
+        // Small angles, where the closed form of the inverse Jacobian cancels.
+        for angle in [1e-5, 2e-4, 1e-3, 1e-2] {
+            let se3 = Vector6::new(0.1, 0.2, 0.3, angle, 0.0, 0.0);
+            let log = Transform::exp(&LieGroup::Se3(se3)).log();
+            assert!((log - se3).norm() < 1e-6, "{log} != {se3}");
+        }
+    }
+
+    #[test]
+    fn test_adjoint() {
+        let transform =
+            Transform::exp(&LieGroup::Se3(Vector6::new(1.0, -0.5, 0.2, 0.3, 0.1, -0.4)));
+        let se3 = Vector6::new(0.01, 0.02, -0.01, 0.02, -0.01, 0.03);
+        let expected =
+            (&(&transform * &Transform::exp(&LieGroup::Se3(se3))) * &transform.inverse()).log();
+        assert!((transform.adjoint() * se3 - expected).norm() < 1e-4);
+    }
+
     #[test]
     fn test_compose() {
         let transform1 = Transform(Isometry3::from_parts(
@@ -1,4 +1,5 @@
 use ndarray::Array1;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 use rstest::fixture;
 
 use crate::{io::read_off, pointcloud::PointCloud, transform::Transform};
@@ -15,6 +16,39 @@ pub fn sample_teapot_pointcloud() -> PointCloud {
     PointCloud::from_geometry(geometry)
 }
 
+/// Teapot surface densely sampled with face normals. The mesh vertices alone are
+/// too sparse for nearest neighbor matching.
+#[fixture]
+pub fn sample_teapot_surface() -> PointCloud {
+    let teapot = read_off("tests/data/teapot.off").unwrap();
+    let mut rng = SmallRng::seed_from_u64(5);
+    let mut points = Vec::new();
+    let mut normals = Vec::new();
+    for face in teapot.faces.as_ref().unwrap().rows() {
+        let [p0, p1, p2] = [0, 1, 2].map(|i| teapot.points[face[i]]);
+        let normal = (p1 - p0).cross(&(p2 - p0));
+        if normal.norm() < 1e-8 {
+            continue;
+        }
+        for _ in 0..20 {
+            let (mut a, mut b) = (rng.gen::<f32>(), rng.gen::<f32>());
+            if a + b > 1.0 {
+                (a, b) = (1.0 - a, 1.0 - b);
+            }
+            points.push(p0 + (p1 - p0) * a + (p2 - p0) * b);
+            normals.push(normal.normalize());
+        }
+    }
+    PointCloud {
+        colors: Some(Array1::zeros(points.len())),
+        points: Array1::from_vec(points),
+        normals: Some(Array1::from_vec(normals)),
+        intensities: None,
+        curvatures: None,
+        attributes: Default::default(),
+    }
+}
+
 pub struct TestPclDataset {
     dataset: TestRangeImageDataset,
 }
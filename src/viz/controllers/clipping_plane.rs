@@ -0,0 +1,150 @@
+use nalgebra::{UnitQuaternion, Vector3};
+use winit::event::VirtualKeyCode;
+
+use crate::{
+    io::Geometry,
+    slicing::{clip_geometry, slice_geometry, Plane},
+};
+
+/// Moves a clipping plane with the keyboard, e.g., to inspect the interior of a
+/// reconstruction.
+///
+/// * PageDown/PageUp move the plane along its normal.
+/// * J/L and K/I tilt the plane around the x and y axes, X, Y and Z align its normal with an
+///   axis.
+/// * `[` and `]` halve and double the step sizes.
+/// * T switches between clipping and showing only a slab around the plane.
+pub struct ClippingPlaneControl {
+    /// A point on the plane, the plane rotates around it.
+    pub center: Vector3<f32>,
+    /// Unit normal of the plane, the part in front of it is clipped.
+    pub normal: Vector3<f32>,
+    /// Translation per key press.
+    pub translation_step: f32,
+    /// Rotation per key press, in radians.
+    pub angle_step: f32,
+    /// Total thickness of the slab.
+    pub thickness: f32,
+    /// Whether to show only a slab around the plane instead of clipping.
+    pub show_slab: bool,
+}
+
+impl ClippingPlaneControl {
+    /// Creates the control.
+    ///
+    /// # Arguments
+    ///
+    /// * `center` - A point on the initial plane.
+    /// * `normal` - Normal of the initial plane.
+    /// * `translation_step` - Translation per key press, also the slab thickness.
+    pub fn new(center: Vector3<f32>, normal: Vector3<f32>, translation_step: f32) -> Self {
+        Self {
+            center,
+            normal: normal.normalize(),
+            translation_step,
+            angle_step: 5.0f32.to_radians(),
+            thickness: translation_step,
+            show_slab: false,
+        }
+    }
+
+    /// The current plane.
+    pub fn plane(&self) -> Plane {
+        Plane::from_point_normal(&self.center, &self.normal)
+    }
+
+    /// Applies a key press.
+    ///
+    /// # Returns
+    ///
+    /// * True if the plane or the mode changed.
+    pub fn key_event(&mut self, key: VirtualKeyCode) -> bool {
+        let (axis, sign) = match key {
+            VirtualKeyCode::PageUp => {
+                self.center += self.normal * self.translation_step;
+                return true;
+            }
+            VirtualKeyCode::PageDown => {
+                self.center -= self.normal * self.translation_step;
+                return true;
+            }
+            VirtualKeyCode::X | VirtualKeyCode::Y | VirtualKeyCode::Z => {
+                self.normal = match key {
+                    VirtualKeyCode::X => Vector3::x(),
+                    VirtualKeyCode::Y => Vector3::y(),
+                    _ => Vector3::z(),
+                };
+                return true;
+            }
+            VirtualKeyCode::T => {
+                self.show_slab = !self.show_slab;
+                return true;
+            }
+            VirtualKeyCode::RBracket => {
+                self.translation_step *= 2.0;
+                self.angle_step *= 2.0;
+                return false;
+            }
+            VirtualKeyCode::LBracket => {
+                self.translation_step *= 0.5;
+                self.angle_step *= 0.5;
+                return false;
+            }
+            VirtualKeyCode::L => (Vector3::x(), 1.0),
+            VirtualKeyCode::J => (Vector3::x(), -1.0),
+            VirtualKeyCode::I => (Vector3::y(), 1.0),
+            VirtualKeyCode::K => (Vector3::y(), -1.0),
+            _ => return false,
+        };
+
+        self.normal = UnitQuaternion::from_scaled_axis(axis * sign * self.angle_step) * self.normal;
+        true
+    }
+
+    /// Clips the geometry, or extracts the slab around the plane.
+    pub fn apply(&self, geometry: &Geometry) -> Geometry {
+        if self.show_slab {
+            slice_geometry(geometry, &self.plane(), self.thickness)
+        } else {
+            clip_geometry(geometry, &self.plane())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::array;
+    use winit::event::VirtualKeyCode;
+
+    use super::ClippingPlaneControl;
+    use crate::io::GeometryBuilder;
+
+    #[test]
+    fn test_key_event() {
+        let geometry = GeometryBuilder::new(array![
+            Vector3::new(0.0, 0.0, -1.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0)
+        ])
+        .build();
+        let mut control = ClippingPlaneControl::new(Vector3::zeros(), Vector3::z(), 0.5);
+        assert_eq!(control.apply(&geometry).len_vertices(), 2);
+
+        assert!(control.key_event(VirtualKeyCode::PageDown));
+        assert_eq!(control.apply(&geometry).len_vertices(), 1);
+
+        assert!(control.key_event(VirtualKeyCode::T));
+        control.thickness = 1.0;
+        let slab = control.apply(&geometry);
+        assert_eq!(slab.len_vertices(), 2);
+
+        assert!(!control.key_event(VirtualKeyCode::LBracket));
+        assert!(control.key_event(VirtualKeyCode::L));
+        assert!((control.normal.norm() - 1.0).abs() < 1e-6);
+        assert!((control.normal.angle(&Vector3::z()) - 2.5f32.to_radians()).abs() < 1e-5);
+        assert!(control.key_event(VirtualKeyCode::Z));
+        assert_eq!(control.normal, Vector3::z());
+        assert!(!control.key_event(VirtualKeyCode::W));
+    }
+}
@@ -0,0 +1,307 @@
+use nalgebra::{Vector2, Vector3};
+use nalgebra_glm::Mat4;
+use ndarray::Array1;
+use winit::event::VirtualKeyCode;
+
+use super::{FrameStepInfo, PoseNudgeControl};
+use crate::{
+    edit::{inside_polygon, inside_rectangle, select_sphere, EditCommand, GeometryEditor},
+    io::Geometry,
+    pointcloud::PointCloud,
+    transform::Transform,
+    viz::node::IntoVulkanWorldSpace,
+};
+
+/// Color of the selected points in the preview.
+const SELECTED_COLOR: Vector3<u8> = Vector3::new(255, 0, 255);
+/// Color of the points inside the brush in the preview.
+const BRUSH_COLOR: Vector3<u8> = Vector3::new(0, 255, 0);
+
+/// Cleans up a geometry in the viewer with a spherical brush.
+///
+/// * Left/Right, Down/Up and PageDown/PageUp move the brush, `[` and `]` halve and double
+///   its step.
+/// * Minus and Equals shrink and grow the brush.
+/// * Space adds the points inside the brush to the selection and Back clears it.
+/// * R at two cursor positions adds the points inside the rectangle between them.
+/// * P adds the cursor position to a lasso and Return adds the points inside the lasso.
+/// * Delete removes the selected points and C crops the geometry to their bounding box.
+/// * Z and Y undo and redo.
+pub struct GeometryEditControl {
+    /// The edited geometry.
+    pub editor: GeometryEditor,
+    /// The brush center is the translation of its pose.
+    pub brush: PoseNudgeControl,
+    /// Brush radius.
+    pub radius: f32,
+    /// Transformation of the node showing the preview, used for selecting with the cursor.
+    pub node_transformation: Mat4,
+    selection: Vec<usize>,
+    rectangle_corner: Option<Vector2<f32>>,
+    lasso: Vec<Vector2<f32>>,
+}
+
+impl GeometryEditControl {
+    /// Starts editing a geometry.
+    ///
+    /// # Arguments
+    ///
+    /// * `geometry` - The geometry to edit.
+    /// * `radius` - The brush radius, also its initial step.
+    pub fn new(geometry: Geometry, radius: f32) -> Self {
+        let center = if geometry.points.is_empty() {
+            Vector3::zeros()
+        } else {
+            geometry.points.iter().sum::<Vector3<f32>>() / geometry.points.len() as f32
+        };
+        Self {
+            editor: GeometryEditor::new(geometry),
+            brush: PoseNudgeControl::new(
+                Transform::new(&center, &nalgebra::Quaternion::identity()),
+                radius,
+            ),
+            radius,
+            node_transformation: Transform::eye().into_vulkan_coordinate_system(),
+            selection: Vec::new(),
+            rectangle_corner: None,
+            lasso: Vec::new(),
+        }
+    }
+
+    /// The indices of the selected vertices.
+    pub fn selection(&self) -> &[usize] {
+        &self.selection
+    }
+
+    /// Finishes editing, returning the edited geometry.
+    pub fn into_geometry(self) -> Geometry {
+        self.editor.into_geometry()
+    }
+
+    fn brush_selection(&self) -> Vec<usize> {
+        select_sphere(
+            &self.editor.geometry().points,
+            &self.brush.transform.translation(),
+            self.radius,
+        )
+    }
+
+    /// Projects the vertices in front of the viewer camera into window pixels.
+    fn project_points(&self, window_state: &FrameStepInfo) -> Vec<(usize, Vector2<f32>)> {
+        let matrix =
+            window_state.projection_matrix * window_state.view_matrix * self.node_transformation;
+        let [width, height] = window_state.viewport_size;
+        self.editor
+            .geometry()
+            .points
+            .iter()
+            .enumerate()
+            .filter_map(|(index, point)| {
+                let clip = matrix * point.push(1.0);
+                (clip[3] > 0.0).then(|| {
+                    let pixel = Vector2::new(
+                        (clip[0] / clip[3] + 1.0) * 0.5 * width,
+                        (clip[1] / clip[3] + 1.0) * 0.5 * height,
+                    );
+                    (index, pixel)
+                })
+            })
+            .collect()
+    }
+
+    fn add_to_selection(&mut self, indices: impl IntoIterator<Item = usize>) {
+        self.selection.extend(indices);
+        self.selection.sort_unstable();
+        self.selection.dedup();
+    }
+
+    /// Applies a key press.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The pressed key.
+    /// * `window_state` - The viewer state, for the cursor and camera.
+    ///
+    /// # Returns
+    ///
+    /// * True if the preview changed.
+    pub fn key_event(&mut self, key: VirtualKeyCode, window_state: &FrameStepInfo) -> bool {
+        let cursor = Vector2::from(window_state.cursor_position);
+        let edited = match key {
+            VirtualKeyCode::Minus => {
+                self.radius *= 0.5;
+                return true;
+            }
+            VirtualKeyCode::Equals => {
+                self.radius *= 2.0;
+                return true;
+            }
+            VirtualKeyCode::Space => {
+                self.add_to_selection(self.brush_selection());
+                return true;
+            }
+            VirtualKeyCode::R => {
+                let Some(corner) = self.rectangle_corner.take() else {
+                    self.rectangle_corner = Some(cursor);
+                    return false;
+                };
+                let inside = self
+                    .project_points(window_state)
+                    .into_iter()
+                    .filter(|(_, pixel)| inside_rectangle(pixel, &corner, &cursor))
+                    .map(|(index, _)| index);
+                self.add_to_selection(inside);
+                return true;
+            }
+            VirtualKeyCode::P => {
+                self.lasso.push(cursor);
+                return false;
+            }
+            VirtualKeyCode::Return => {
+                let lasso = std::mem::take(&mut self.lasso);
+                if lasso.len() < 3 {
+                    return false;
+                }
+                let inside = self
+                    .project_points(window_state)
+                    .into_iter()
+                    .filter(|(_, pixel)| inside_polygon(pixel, &lasso))
+                    .map(|(index, _)| index);
+                self.add_to_selection(inside);
+                return true;
+            }
+            VirtualKeyCode::Back => {
+                self.selection.clear();
+                self.rectangle_corner = None;
+                self.lasso.clear();
+                return true;
+            }
+            VirtualKeyCode::Delete if !self.selection.is_empty() => {
+                self.editor
+                    .apply(EditCommand::DeleteSelection(self.selection.clone()));
+                true
+            }
+            VirtualKeyCode::C if !self.selection.is_empty() => {
+                let points = &self.editor.geometry().points;
+                let (mut min, mut max) = (points[self.selection[0]], points[self.selection[0]]);
+                for index in self.selection.iter() {
+                    min = min.inf(&points[*index]);
+                    max = max.sup(&points[*index]);
+                }
+                self.editor.apply(EditCommand::Crop { min, max });
+                true
+            }
+            VirtualKeyCode::Z => self.editor.undo(),
+            VirtualKeyCode::Y => self.editor.redo(),
+            // Rotating the brush has no effect.
+            VirtualKeyCode::I
+            | VirtualKeyCode::J
+            | VirtualKeyCode::K
+            | VirtualKeyCode::L
+            | VirtualKeyCode::U
+            | VirtualKeyCode::O => false,
+            _ => return self.brush.key_event(key),
+        };
+
+        if edited {
+            // The indices refer to the previous geometry.
+            self.selection.clear();
+        }
+        edited
+    }
+
+    /// Creates a point cloud for showing the current geometry, with the selected points and
+    /// the points inside the brush highlighted.
+    pub fn preview(&self) -> PointCloud {
+        let geometry = self.editor.geometry();
+        let mut colors = geometry
+            .colors
+            .clone()
+            .unwrap_or_else(|| Array1::from_elem(geometry.len_vertices(), Vector3::repeat(160)));
+        for index in self.brush_selection() {
+            colors[index] = BRUSH_COLOR;
+        }
+        for index in self.selection.iter() {
+            colors[*index] = SELECTED_COLOR;
+        }
+
+        let mut pcl = PointCloud::from_geometry(geometry.clone());
+        pcl.colors = Some(colors);
+        if pcl.normals.is_none() {
+            pcl.normals = Some(Array1::zeros(pcl.len()));
+        }
+        pcl
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::array;
+    use winit::event::VirtualKeyCode;
+
+    use super::{GeometryEditControl, SELECTED_COLOR};
+    use crate::{io::GeometryBuilder, viz::controllers::FrameStepInfo};
+
+    #[test]
+    fn test_key_event() {
+        let geometry = GeometryBuilder::new(array![
+            Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0)
+        ])
+        .build();
+        let mut control = GeometryEditControl::new(geometry, 0.5);
+        let state = FrameStepInfo::default();
+
+        // The brush starts at the centroid.
+        assert!(control.key_event(VirtualKeyCode::Space, &state));
+        assert_eq!(control.selection(), &[1]);
+        assert_eq!(control.preview().colors.unwrap()[1], SELECTED_COLOR);
+
+        // Moves the brush twice to reach the third point.
+        assert!(control.key_event(VirtualKeyCode::Right, &state));
+        assert!(control.key_event(VirtualKeyCode::Right, &state));
+        assert!(control.key_event(VirtualKeyCode::Space, &state));
+        assert_eq!(control.selection(), &[1, 2]);
+
+        assert!(control.key_event(VirtualKeyCode::Delete, &state));
+        assert!(control.selection().is_empty());
+        assert_eq!(control.editor.geometry().len_vertices(), 1);
+        assert!(!control.key_event(VirtualKeyCode::Delete, &state));
+
+        assert!(control.key_event(VirtualKeyCode::Z, &state));
+        assert_eq!(control.editor.geometry().len_vertices(), 3);
+        assert!(control.key_event(VirtualKeyCode::Y, &state));
+        assert_eq!(control.into_geometry().len_vertices(), 1);
+    }
+
+    #[test]
+    fn test_cursor_selection() {
+        let geometry = GeometryBuilder::new(array![
+            Vector3::new(-0.5, -0.5, 0.0),
+            Vector3::new(0.5, -0.5, 0.0),
+            Vector3::new(0.5, 0.5, 0.0),
+            Vector3::new(-0.5, 0.5, 0.0)
+        ])
+        .build();
+        let mut control = GeometryEditControl::new(geometry, 0.1);
+        // Identity camera, so the points are at (50, 50), (150, 50), (150, 150) and (50, 150).
+        control.node_transformation = nalgebra_glm::Mat4::identity();
+        let mut state = FrameStepInfo::new([200.0, 200.0]);
+
+        state.cursor_position = [0.0, 0.0];
+        assert!(!control.key_event(VirtualKeyCode::R, &state));
+        state.cursor_position = [100.0, 200.0];
+        assert!(control.key_event(VirtualKeyCode::R, &state));
+        assert_eq!(control.selection(), &[0, 3]);
+
+        for cursor in [[100.0, 0.0], [200.0, 0.0], [200.0, 200.0]] {
+            state.cursor_position = cursor;
+            assert!(!control.key_event(VirtualKeyCode::P, &state));
+        }
+        assert!(control.key_event(VirtualKeyCode::Return, &state));
+        assert_eq!(control.selection(), &[0, 1, 3]);
+        assert!(!control.key_event(VirtualKeyCode::Return, &state));
+    }
+}
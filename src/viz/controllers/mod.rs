@@ -3,3 +3,12 @@ pub use virtual_camera_controller::{VirtualCameraControl, WASDVirtualCameraContr
 
 mod state;
 pub use state::{FrameStepInfo, SceneState};
+
+mod pose_nudge;
+pub use pose_nudge::PoseNudgeControl;
+
+mod geometry_edit;
+pub use geometry_edit::GeometryEditControl;
+
+mod clipping_plane;
+pub use clipping_plane::ClippingPlaneControl;
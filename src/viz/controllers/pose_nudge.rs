@@ -0,0 +1,94 @@
+use nalgebra::{Translation3, UnitQuaternion, Vector3};
+use winit::event::VirtualKeyCode;
+
+use crate::transform::Transform;
+
+/// Moves a node with the keyboard, e.g., to roughly align two maps by hand.
+///
+/// * Left/Right, Down/Up and PageDown/PageUp translate along x, y and z.
+/// * J/L, K/I and U/O rotate around x, y and z, in place.
+/// * `[` and `]` halve and double the step sizes.
+pub struct PoseNudgeControl {
+    /// The current pose of the node.
+    pub transform: Transform,
+    /// Translation per key press.
+    pub translation_step: f32,
+    /// Rotation per key press, in radians.
+    pub angle_step: f32,
+}
+
+impl PoseNudgeControl {
+    pub fn new(transform: Transform, translation_step: f32) -> Self {
+        Self {
+            transform,
+            translation_step,
+            angle_step: 2.0f32.to_radians(),
+        }
+    }
+
+    /// Applies a key press.
+    ///
+    /// # Returns
+    ///
+    /// * True if the pose changed.
+    pub fn key_event(&mut self, key: VirtualKeyCode) -> bool {
+        let (translation, angle) = (self.translation_step, self.angle_step);
+        let (axis, sign, rotate) = match key {
+            VirtualKeyCode::Right => (Vector3::x(), 1.0, false),
+            VirtualKeyCode::Left => (Vector3::x(), -1.0, false),
+            VirtualKeyCode::Up => (Vector3::y(), 1.0, false),
+            VirtualKeyCode::Down => (Vector3::y(), -1.0, false),
+            VirtualKeyCode::PageUp => (Vector3::z(), 1.0, false),
+            VirtualKeyCode::PageDown => (Vector3::z(), -1.0, false),
+            VirtualKeyCode::L => (Vector3::x(), 1.0, true),
+            VirtualKeyCode::J => (Vector3::x(), -1.0, true),
+            VirtualKeyCode::I => (Vector3::y(), 1.0, true),
+            VirtualKeyCode::K => (Vector3::y(), -1.0, true),
+            VirtualKeyCode::O => (Vector3::z(), 1.0, true),
+            VirtualKeyCode::U => (Vector3::z(), -1.0, true),
+            VirtualKeyCode::RBracket => {
+                self.translation_step *= 2.0;
+                self.angle_step *= 2.0;
+                return false;
+            }
+            VirtualKeyCode::LBracket => {
+                self.translation_step *= 0.5;
+                self.angle_step *= 0.5;
+                return false;
+            }
+            _ => return false,
+        };
+
+        let isometry = &mut self.transform.0;
+        if rotate {
+            isometry.rotation =
+                UnitQuaternion::from_scaled_axis(axis * sign * angle) * isometry.rotation;
+        } else {
+            isometry.translation =
+                Translation3::from(isometry.translation.vector + axis * sign * translation);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use winit::event::VirtualKeyCode;
+
+    use super::PoseNudgeControl;
+    use crate::transform::Transform;
+
+    #[test]
+    fn test_key_event() {
+        let mut control = PoseNudgeControl::new(Transform::eye(), 0.1);
+        assert!(control.key_event(VirtualKeyCode::Right));
+        assert!(control.key_event(VirtualKeyCode::PageUp));
+        assert!(!control.key_event(VirtualKeyCode::LBracket));
+        assert!(control.key_event(VirtualKeyCode::O));
+        assert!(!control.key_event(VirtualKeyCode::W));
+
+        assert!((control.transform.translation() - Vector3::new(0.1, 0.0, 0.1)).norm() < 1e-6);
+        assert!((control.transform.angle() - 1.0f32.to_radians()).abs() < 1e-6);
+    }
+}
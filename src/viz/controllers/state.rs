@@ -1,5 +1,6 @@
 use std::{collections::HashMap, time::Duration};
 
+use nalgebra_glm::Mat4;
 use winit::event::{ElementState, MouseButton, VirtualKeyCode};
 
 use crate::viz::sphere3d::Sphere3Df;
@@ -9,6 +10,12 @@ pub struct FrameStepInfo {
     pub keyboard_state: HashMap<VirtualKeyCode, ElementState>,
     pub mouse_state: HashMap<MouseButton, ElementState>,
     pub elapsed_time: Duration,
+    /// Cursor position in pixels, from the top left corner.
+    pub cursor_position: [f32; 2],
+    /// View matrix of the last drawn frame.
+    pub view_matrix: Mat4,
+    /// Projection matrix of the last drawn frame.
+    pub projection_matrix: Mat4,
 }
 
 impl FrameStepInfo {
@@ -27,6 +34,9 @@ impl Default for FrameStepInfo {
             keyboard_state: HashMap::new(),
             mouse_state: HashMap::new(),
             elapsed_time: Duration::from_millis(24),
+            cursor_position: [0.0, 0.0],
+            view_matrix: Mat4::identity(),
+            projection_matrix: Mat4::identity(),
         }
     }
 }
@@ -1,4 +1,8 @@
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use winit::event::VirtualKeyCode;
+
 use super::{
+    controllers::FrameStepInfo,
     node::{node_ref, MakeNode, Node, NodeRef},
     scene::Scene,
     Manager, Window,
@@ -48,12 +52,34 @@ impl GeoViewer {
     }
 
     pub fn run(&mut self) {
+        self.run_with_key_callback(|_, _| {});
+    }
+
+    /// Runs the viewer, forwarding the key presses to `on_key`. The number keys still toggle
+    /// the visibility of the nodes.
+    pub fn run_with_key_callback<F>(&mut self, mut on_key: F)
+    where
+        F: FnMut(VirtualKeyCode, &FrameStepInfo) + 'static,
+    {
+        self.run_with_scene_callback(move |vkeycode, window_state, _, _| {
+            on_key(vkeycode, window_state)
+        });
+    }
+
+    /// Runs the viewer like [`GeoViewer::run_with_key_callback`], but `on_key` can also change
+    /// the scene, e.g., to replace the node of an edited geometry. New nodes must be
+    /// allocated with the given allocator.
+    pub fn run_with_scene_callback<F>(&mut self, mut on_key: F)
+    where
+        F: FnMut(VirtualKeyCode, &FrameStepInfo, &mut Scene, &StandardMemoryAllocator) + 'static,
+    {
+        let memory_allocator = StandardMemoryAllocator::new_default(self.manager.device.clone());
         self.window
             .replace(Window::create(&mut self.manager, self.scene.clone()));
         let window = self.window.as_mut().unwrap();
         let scene = self.scene.clone();
 
-        window.on_key = Some(Box::new(move |vkeycode, _window| {
+        window.on_key = Some(Box::new(move |vkeycode, window_state| {
             let num_key = vkeycode as u32;
             if num_key <= 10 {
                 if let Some(node) = scene.borrow_mut().nodes.get(num_key as usize).cloned() {
@@ -62,6 +88,12 @@ impl GeoViewer {
                     node.properties_mut().set_visible(!is_visible);
                 }
             }
+            on_key(
+                vkeycode,
+                window_state,
+                &mut scene.borrow_mut(),
+                &memory_allocator,
+            );
         }));
         window.show();
     }
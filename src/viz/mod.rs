@@ -16,7 +16,7 @@ mod virtual_projection;
 pub use virtual_projection::{PerspectiveVirtualProjectionBuilder, VirtualProjection};
 
 mod offscreen_render;
-pub use offscreen_render::OffscreenRenderer;
+pub use offscreen_render::{render_flythrough, view_matrix_from_pose, OffscreenRenderer};
 
 pub mod controllers;
 
@@ -1,4 +1,5 @@
-use image::{ImageBuffer, Rgba, RgbaImage};
+use image::{ImageBuffer, Rgb, RgbImage, Rgba, RgbaImage};
+use nalgebra::Matrix4;
 use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
 use vulkano::{
     buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
@@ -19,10 +20,12 @@ use vulkano::{
     sync::GpuFuture,
 };
 
+use crate::{io::video::VideoEncoder, trajectory::Trajectory, transform::Transform};
+
 use super::{
     controllers::FrameStepInfo,
     node::{CommandBuffersContext, Node},
-    Manager,
+    Manager, VirtualProjection,
 };
 
 /// Renders nodes into images instead of a window.
@@ -135,6 +138,26 @@ impl OffscreenRenderer {
     ///
     /// ```
     pub fn render(&mut self, scene: Rc<RefCell<dyn Node>>) -> RenderImage {
+        self.render_with_camera(
+            scene,
+            &nalgebra_glm::Mat4::identity(),
+            &nalgebra_glm::Mat4::identity(),
+        )
+    }
+
+    /// Draws the scene into a image as seen by a camera.
+    ///
+    /// # Arguments
+    ///
+    /// * `scene`: Target scene
+    /// * `view_matrix`: World to view matrix, see [`view_matrix_from_pose`].
+    /// * `projection_matrix`: Projection matrix, e.g., from a [`super::VirtualProjection`].
+    pub fn render_with_camera(
+        &mut self,
+        scene: Rc<RefCell<dyn Node>>,
+        view_matrix: &nalgebra_glm::Mat4,
+        projection_matrix: &nalgebra_glm::Mat4,
+    ) -> RenderImage {
         let (width, height) = (
             self.viewport.dimensions[0] as usize,
             self.viewport.dimensions[1] as usize,
@@ -171,16 +194,15 @@ impl OffscreenRenderer {
             .set_viewport(0, [self.viewport.clone()]);
 
         scene.borrow().collect_command_buffers(
-            &mut CommandBuffersContext {
-                device: self.device.clone(),
-                queue: self.queue.clone(),
-                builder: &mut builder,
-                pipelines: &mut self.pipelines,
-                render_pass: self.render_pass.clone(),
-                view_normals_matrix: nalgebra_glm::Mat3::identity(),
-                view_matrix: nalgebra_glm::Mat4::identity(),
-                projection_matrix: nalgebra_glm::Mat4::identity(),
-            },
+            &mut CommandBuffersContext::new(
+                self.device.clone(),
+                self.queue.clone(),
+                &mut builder,
+                &mut self.pipelines,
+                self.render_pass.clone(),
+                *view_matrix,
+                *projection_matrix,
+            ),
             &FrameStepInfo::new(self.viewport.dimensions),
         );
 
@@ -209,6 +231,42 @@ impl OffscreenRenderer {
     }
 }
 
+/// View matrix of a camera pose in the computer vision convention (x right, y down,
+/// z forward), as used by trajectories, for the viewer's projection that looks down -z.
+pub fn view_matrix_from_pose(camera_to_world: &Transform) -> nalgebra_glm::Mat4 {
+    let flip_yz = nalgebra_glm::Mat4::from_diagonal(&nalgebra_glm::vec4(1.0, -1.0, -1.0, 1.0));
+    flip_yz * Matrix4::from(&camera_to_world.inverse())
+}
+
+/// Renders a flythrough of the scene along a camera path into a video.
+///
+/// # Arguments
+///
+/// * `renderer`: The offscreen renderer, its size must match the encoder's.
+/// * `scene`: The scene, e.g., the reconstructed model.
+/// * `camera_path`: One pose per video frame, see [`Trajectory::flythrough`].
+/// * `projection`: The camera projection.
+/// * `encoder`: The video encoder, e.g., a [`crate::io::video::Y4mWriter`].
+pub fn render_flythrough<E: VideoEncoder>(
+    renderer: &mut OffscreenRenderer,
+    scene: Rc<RefCell<dyn Node>>,
+    camera_path: &Trajectory,
+    projection: &VirtualProjection,
+    encoder: &mut E,
+) -> std::io::Result<()> {
+    let projection_matrix = projection.matrix();
+    for camera_to_world in camera_path.camera_to_world.iter() {
+        renderer
+            .render_with_camera(
+                scene.clone(),
+                &view_matrix_from_pose(camera_to_world),
+                &projection_matrix,
+            )
+            .encode_into(encoder)?;
+    }
+    encoder.finish()
+}
+
 impl RenderImage {
     /// Returns a copy of the buffer into a RGBA Image.
     pub fn to_image(&self) -> RgbaImage {
@@ -237,6 +295,24 @@ impl RenderImage {
         let image_buffer = self.image_buffer.read().unwrap();
         f(ImageBuffer::from_raw(self.width, self.height, &image_buffer[..]).unwrap());
     }
+
+    /// Encodes the image as the next frame of a video, dropping the alpha channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder`: The video encoder, e.g., a [`crate::io::video::Y4mWriter`].
+    pub fn encode_into<E: VideoEncoder>(&self, encoder: &mut E) -> std::io::Result<()> {
+        let image_buffer = self.image_buffer.read().unwrap();
+        let frame = RgbImage::from_fn(self.width, self.height, |x, y| {
+            let offset = ((y * self.width + x) * 4) as usize;
+            Rgb::<u8>([
+                image_buffer[offset],
+                image_buffer[offset + 1],
+                image_buffer[offset + 2],
+            ])
+        });
+        encoder.encode(&frame)
+    }
 }
 
 #[cfg(test)]
@@ -272,6 +272,7 @@ impl Window {
                         event: WindowEvent::CursorMoved { position, .. },
                         ..
                     } => {
+                        window_state.cursor_position = [position.x as f32, position.y as f32];
                         camera_control.cursor_moved(
                             position.x,
                             position.y,
@@ -351,13 +352,15 @@ impl Window {
                             recreate_swapchain = true;
                         };
 
+                        window_state.view_matrix = camera_control.camera.matrix();
+                        window_state.projection_matrix = camera_control.projection_matrix();
                         let command_buffer = self.get_command_buffers(
                             framebuffers[image_index as usize].clone(),
                             &mut viewport,
                             &mut pipelines,
                             render_pass.clone(),
-                            &camera_control.camera.matrix(),
-                            &camera_control.projection_matrix(),
+                            &window_state.view_matrix,
+                            &window_state.projection_matrix,
                             &window_state,
                         );
 
@@ -0,0 +1,152 @@
+//! Voxel hash grid for fixed-radius neighbor search.
+
+use std::collections::HashMap;
+
+use itertools::iproduct;
+use nalgebra::Vector3;
+use ndarray::ArrayView1;
+
+/// Points hashed by the voxel containing them. Finding the neighbors within a radius close
+/// to the voxel size only visits the surrounding voxels, which is faster than a k-d tree
+/// for the fixed-radius lookups of fusion and downsampling, and points can be inserted
+/// at any time on unbounded maps.
+pub struct VoxelHashGrid {
+    voxel_size: f32,
+    points: Vec<Vector3<f32>>,
+    cells: HashMap<[i32; 3], Vec<usize>>,
+}
+
+impl VoxelHashGrid {
+    /// Creates an empty grid.
+    ///
+    /// # Arguments
+    ///
+    /// * voxel_size - Size of the voxels, about the radius of the queries.
+    pub fn new(voxel_size: f32) -> Self {
+        Self {
+            voxel_size,
+            points: Vec::new(),
+            cells: HashMap::new(),
+        }
+    }
+
+    /// Creates a grid of points, indexed by their position.
+    pub fn from_points(points: &ArrayView1<Vector3<f32>>, voxel_size: f32) -> Self {
+        Self::from_point_iter(points.iter().copied(), voxel_size)
+    }
+
+    /// Same as [`VoxelHashGrid::from_points`].
+    pub fn from_point_iter<I>(point_iter: I, voxel_size: f32) -> Self
+    where
+        I: IntoIterator<Item = Vector3<f32>>,
+    {
+        let mut grid = Self::new(voxel_size);
+        for point in point_iter {
+            grid.insert(point);
+        }
+        grid
+    }
+
+    pub fn voxel_size(&self) -> f32 {
+        self.voxel_size
+    }
+
+    /// Number of points.
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// The quantized coordinates of the voxel containing a point.
+    pub fn key(&self, point: &Vector3<f32>) -> [i32; 3] {
+        (point / self.voxel_size)
+            .map(|coord| coord.floor() as i32)
+            .into()
+    }
+
+    /// The indices of the points in a voxel.
+    pub fn voxel(&self, key: &[i32; 3]) -> &[usize] {
+        self.cells.get(key).map_or(&[], Vec::as_slice)
+    }
+
+    /// Inserts a point.
+    ///
+    /// # Returns
+    ///
+    /// The index of the point, following the previously inserted ones.
+    pub fn insert(&mut self, point: Vector3<f32>) -> usize {
+        let index = self.points.len();
+        self.cells.entry(self.key(&point)).or_default().push(index);
+        self.points.push(point);
+        index
+    }
+
+    /// Finds all the points within a radius of a query point, visiting the voxels that
+    /// the sphere of the query overlaps.
+    ///
+    /// # Returns
+    ///
+    /// Tuples with the index and squared distance of the neighbors, in no particular order.
+    pub fn neighbors_within(&self, query: &Vector3<f32>, radius: f32) -> Vec<(usize, f32)> {
+        let mut found = Vec::new();
+        if radius < 0.0 {
+            return found;
+        }
+        let sqr_radius = radius * radius;
+        let min = self.key(&query.add_scalar(-radius));
+        let max = self.key(&query.add_scalar(radius));
+        for (x, y, z) in iproduct!(min[0]..=max[0], min[1]..=max[1], min[2]..=max[2]) {
+            found.extend(self.voxel(&[x, y, z]).iter().filter_map(|index| {
+                let distance = (self.points[*index] - query).norm_squared();
+                (distance <= sqr_radius).then_some((*index, distance))
+            }));
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use ndarray::Array1;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::VoxelHashGrid;
+
+    #[test]
+    fn should_find_neighbors_within_radius() {
+        let mut rng = SmallRng::seed_from_u64(6);
+        let points = Array1::from_shape_fn(3000, |_| {
+            Vector3::new(
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-1.0..1.0),
+            )
+        });
+        let mut grid = VoxelHashGrid::from_points(&points.view(), 0.5);
+        assert_eq!(grid.insert(Vector3::new(100.0, 0.0, 0.0)), 3000);
+        assert_eq!(grid.voxel(&[200, 0, 0]), &[3000]);
+        assert!(grid.voxel(&[-200, 0, 0]).is_empty());
+
+        for (query, radius) in points
+            .iter()
+            .step_by(300)
+            .flat_map(|query| [(query, 0.3), (query, 1.2)])
+        {
+            let mut expected = points
+                .iter()
+                .enumerate()
+                .map(|(index, point)| (index, (point - query).norm_squared()))
+                .filter(|(_, sqr_distance)| *sqr_distance <= radius * radius)
+                .collect::<Vec<_>>();
+            expected.sort_by_key(|(index, _)| *index);
+            let mut found = grid.neighbors_within(query, radius);
+            found.sort_by_key(|(index, _)| *index);
+            assert_eq!(found, expected);
+        }
+        assert!(grid.neighbors_within(&points[0], -1.0).is_empty());
+    }
+}
@@ -28,7 +28,7 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         },
         &target_pcl,
     );
-    let result = icp.align(&source_pcl);
+    let result = icp.align(&source_pcl).transform;
 
     let mut viewer = GeoViewer::new();
     viewer.add(&target_pcl);
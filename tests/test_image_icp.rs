@@ -46,7 +46,7 @@ fn main() {
     });
 
     let icp = MultiscaleAlign::new(params, &target_pcl).unwrap();
-    let result = icp.align(&source_pcl);
+    let result = icp.align(&source_pcl).transform;
 
     let gt_transform = dataset
         .trajectory()